@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+// Let the file system testers take the image apart, a damaged directory must give an error, never a panic
+fuzz_target!(|data: &[u8]| {
+    for ext in [None,Some("do"),Some("po"),Some("d13"),Some("img")] {
+        if let Ok(mut disk) = a2kit::create_fs_from_bytestream(&data.to_vec(),ext) {
+            let _ = disk.catalog_to_stdout("/");
+            let _ = disk.tree(true,None);
+        }
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use a2kit::img::DiskImage;
+
+// Any input must give an image or an error, never a panic
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut img) = <a2kit::img::woz1::Woz1 as DiskImage>::from_bytes(data) {
+        for track in 0..img.track_count() {
+            let _ = img.get_track_solution(track);
+        }
+        let _ = img.to_bytes();
+    }
+});
@@ -195,6 +195,7 @@ fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
             }),
             document_symbol_provider: Some(lsp::OneOf::Left(true)),
             rename_provider: Some(lsp::OneOf::Left(true)),
+            code_action_provider: Some(lsp::CodeActionProviderCapability::Simple(true)),
             semantic_tokens_provider: match suppress_tokens {
                 true => None,
                 false => Some(lsp::SemanticTokensServerCapabilities::SemanticTokensOptions(lsp::SemanticTokensOptions {
@@ -50,6 +50,54 @@ fn renumber_or_move(connection: &Connection, req_id: RequestId, params: &lsp::Ex
     resp
 }
 
+/// Insert a numbered line after the cursor, the response is the new line number
+fn insert_line(connection: &Connection, req_id: RequestId, params: &lsp::ExecuteCommandParams) -> lsp_server::Response {
+    let mut resp = def_response(req_id.clone());
+    if params.arguments.len()==2 {
+        let doc_r = serde_json::from_value::<lsp::TextDocumentItem>(params.arguments[0].clone());
+        let pos_r = serde_json::from_value::<lsp::Position>(params.arguments[1].clone());
+        if let (Ok(doc),Ok(pos)) = (doc_r,pos_r) {
+            let mut renumberer = applesoft::renumber::Renumberer::new();
+            resp = match renumberer.insert_line_edit(&doc.text, pos) {
+                Ok((num,edit)) => {
+                    match send_edit_req(connection, &doc, vec![edit]) {
+                        Ok(()) => {},
+                        Err(s) => return lsp_server::Response::new_err(req_id.clone(),PARSE_ERROR,s)
+                    };
+                    lsp_server::Response::new_ok(req_id,num)
+                },
+                Err(s) => lsp_server::Response::new_err(req_id.clone(),PARSE_ERROR,s)
+            };
+        }
+    }
+    resp
+}
+
+/// Offer to renumber the selected lines, if the selection spans more than one line
+fn code_action_response(chkpts: HashMap<String,Arc<&applesoft::checkpoint::CheckpointManager>>, req: lsp_server::Request, resp: &mut lsp_server::Response) {
+    if let Ok(params) = serde_json::from_value::<lsp::CodeActionParams>(req.params) {
+        let uri = a2kit::lang::normalize_client_uri(params.text_document.uri);
+        let mut actions: Vec<lsp::CodeActionOrCommand> = Vec::new();
+        if let Some(chkpt) = chkpts.get(&uri.to_string()) {
+            let doc = chkpt.get_doc();
+            let mut renumberer = applesoft::renumber::Renumberer::new();
+            if params.range.end.line > params.range.start.line {
+                if let Ok(edits) = renumberer.selection_edits(&doc.text, params.range) {
+                    let mut changes = HashMap::new();
+                    changes.insert(doc.uri.clone(),edits);
+                    actions.push(lsp::CodeActionOrCommand::CodeAction(lsp::CodeAction {
+                        title: "Renumber selected lines".to_string(),
+                        kind: Some(lsp::CodeActionKind::REFACTOR_REWRITE),
+                        edit: Some(lsp::WorkspaceEdit::new(changes)),
+                        ..lsp::CodeAction::default()
+                    }));
+                }
+            }
+        }
+        *resp = lsp_server::Response::new_ok(req.id,actions);
+    }
+}
+
 /// returns true if there was a shutdown request
 pub fn handle_request(
     connection: &Connection,
@@ -71,6 +119,7 @@ pub fn handle_request(
         lsp::request::HoverRequest::METHOD => Checkpoint::hover_response(chkpts, &mut tools.hover_provider, req.clone(), &mut resp),
         lsp::request::Completion::METHOD => Checkpoint::completion_response(chkpts, &mut tools.completion_provider, req.clone(), &mut resp),
         lsp::request::SemanticTokensFullRequest::METHOD => Checkpoint::sem_tok_response(chkpts, &mut tools.highlighter, req.clone(), &mut resp),
+        lsp::request::CodeActionRequest::METHOD => code_action_response(chkpts, req.clone(), &mut resp),
 
         lsp::request::Shutdown::METHOD => {
             logger(&connection,"shutdown request");
@@ -144,6 +193,9 @@ pub fn handle_request(
                     "applesoft.move" => {
                         resp = renumber_or_move(&connection,req.id,&params,true);
                     },
+                    "applesoft.insertLine" => {
+                        resp = insert_line(&connection,req.id,&params);
+                    },
                     "applesoft.disk.mount" => {
                         if params.arguments.len()==1 {
                             let maybe_img_path = serde_json::from_value::<String>(params.arguments[0].clone());
@@ -138,7 +138,8 @@ fn parse_configuration(resp: lsp_server::Response) -> Result<merlin::settings::S
     Err(Box::new(ServerError::Parsing))
 }
 
-fn launch_analysis_thread(analyzer: Arc<Mutex<Analyzer>>, doc: a2kit::lang::Document, ws_scan: WorkspaceScanMethod, chks: &HashMap<String,CheckpointManager>) -> std::thread::JoinHandle<Option<AnalysisResult>> {
+/// If `assemble` has settings, the full assembler runs after the analysis, unless the document is an include.
+fn launch_analysis_thread(analyzer: Arc<Mutex<Analyzer>>, doc: a2kit::lang::Document, ws_scan: WorkspaceScanMethod, chks: &HashMap<String,CheckpointManager>, assemble: Option<merlin::settings::Settings>) -> std::thread::JoinHandle<Option<AnalysisResult>> {
     let checkpoints = match ws_scan {
         WorkspaceScanMethod::FullUpdate => {
             let mut ans = Vec::new();
@@ -169,7 +170,18 @@ fn launch_analysis_thread(analyzer: Arc<Mutex<Analyzer>>, doc: a2kit::lang::Docu
                     Ok(()) => Some(AnalysisResult {
                         uri: doc.uri.clone(),
                         version: doc.version,
-                        diagnostics: analyzer.get_diags(&doc),
+                        diagnostics: {
+                            let mut diags = analyzer.get_diags(&doc);
+                            if let Some(config) = assemble {
+                                if analyzer.get_workspace().get_masters(&doc.uri).is_empty() {
+                                    let mut assembler = merlin::assembly::Assembler::new();
+                                    assembler.set_config(config);
+                                    assembler.use_shared_symbols(Arc::new(analyzer.get_symbols()));
+                                    diags.extend(assembler.diagnose(&doc.text, None));
+                                }
+                            }
+                            diags
+                        },
                         folding: analyzer.get_folds(&doc),
                         symbols: analyzer.get_symbols(),
                         workspace: analyzer.get_workspace().clone(),
@@ -208,12 +220,14 @@ struct Tools {
     analyzer: Arc<Mutex<Analyzer>>,
     hover_provider: merlin::hovers::HoverProvider,
     completion_provider: merlin::completions::CompletionProvider,
+    inlay_hinter: merlin::inlay_hints::InlayHintProvider,
     highlighter: merlin::semantic_tokens::SemanticTokensProvider,
     tokenizer: merlin::tokenizer::Tokenizer,
     formatter: merlin::formatter::Formatter,
     assembler: merlin::assembly::Assembler,
     disassembler: merlin::disassembly::Disassembler,
-    disk: DiskServer
+    disk: DiskServer,
+    disk_symbols: merlin::imports::DiskSymbols
 }
 
 impl Tools {
@@ -226,12 +240,14 @@ impl Tools {
             analyzer: Arc::new(Mutex::new(Analyzer::new())),
             hover_provider: merlin::hovers::HoverProvider::new(),
             completion_provider: merlin::completions::CompletionProvider::new(),
+            inlay_hinter: merlin::inlay_hints::InlayHintProvider::new(),
             highlighter: merlin::semantic_tokens::SemanticTokensProvider::new(),
             tokenizer: merlin::tokenizer::Tokenizer::new(),
             formatter: merlin::formatter::Formatter::new(),
             assembler: merlin::assembly::Assembler::new(),
             disassembler: merlin::disassembly::Disassembler::new(),
-            disk: DiskServer::new()
+            disk: DiskServer::new(),
+            disk_symbols: merlin::imports::DiskSymbols::new()
         }
     }
 }
@@ -280,6 +296,7 @@ fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
                 more_trigger_character: Some(vec![";".to_string()])
             }),
             folding_range_provider: Some(lsp::FoldingRangeProviderCapability::Simple(true)),
+            inlay_hint_provider: Some(lsp::OneOf::Left(true)),
             semantic_tokens_provider: match suppress_tokens {
                 true => None,
                 false => Some(lsp::SemanticTokensServerCapabilities::SemanticTokensOptions(lsp::SemanticTokensOptions {
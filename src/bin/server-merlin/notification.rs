@@ -37,7 +37,8 @@ pub fn handle_notification(
                         text: params.text_document.text
                     },
                     crate::WorkspaceScanMethod::FullUpdate,
-                    &tools.doc_chkpts
+                    &tools.doc_chkpts,
+                    None
                 );
                 tools.thread_handles.push_back(handle);
             }
@@ -54,7 +55,11 @@ pub fn handle_notification(
                             text
                         },
                         crate::WorkspaceScanMethod::FullUpdate,
-                        &tools.doc_chkpts
+                        &tools.doc_chkpts,
+                        match tools.config.diagnostics.assemble_on_save {
+                            true => Some(tools.config.clone()),
+                            false => None
+                        }
                     );
                     tools.thread_handles.push_back(handle);
                 }
@@ -86,7 +91,8 @@ pub fn handle_notification(
                                 text: change.text
                             },
                             crate::WorkspaceScanMethod::UseCheckpoints,
-                            &tools.doc_chkpts
+                            &tools.doc_chkpts,
+                            None
                         );
                         tools.thread_handles.push_back(handle);
                     }
@@ -71,6 +71,17 @@ pub fn handle_request(
             }
         },
 
+        lsp::request::InlayHintRequest::METHOD => {
+            if let Ok(params) = serde_json::from_value::<lsp::InlayHintParams>(req.params) {
+                let normalized_uri = normalize_client_uri(params.text_document.uri);
+                if let Some(chk) = tools.doc_chkpts.get(normalized_uri.as_str()) {
+                    tools.inlay_hinter.use_shared_symbols(chk.shared_symbols());
+                    let hints = tools.inlay_hinter.get(&chk.get_doc().text, &params.range);
+                    resp = lsp_server::Response::new_ok(req.id,hints);
+                }
+            }
+        },
+
         lsp::request::OnTypeFormatting::METHOD => {
             if let Ok(params) = serde_json::from_value::<lsp::DocumentOnTypeFormattingParams>(req.params) {
                 let normalized_uri = normalize_client_uri(params.text_document_position.text_document.uri);
@@ -110,7 +121,8 @@ pub fn handle_request(
                                         Arc::clone(&tools.analyzer),
                                         chk.get_doc(),
                                         crate::WorkspaceScanMethod::FullUpdate,
-                                        &tools.doc_chkpts
+                                        &tools.doc_chkpts,
+                                        None
                                     );
                                     tools.thread_handles.push_back(handle);
                                 }
@@ -128,7 +140,8 @@ pub fn handle_request(
                                         Arc::clone(&tools.analyzer),
                                         chk.get_doc(),
                                         crate::WorkspaceScanMethod::FullUpdate,
-                                        &tools.doc_chkpts
+                                        &tools.doc_chkpts,
+                                        None
                                     );
                                     tools.thread_handles.push_back(handle);
                                 }
@@ -146,7 +159,8 @@ pub fn handle_request(
                                         Arc::clone(&tools.analyzer),
                                         chk.get_doc(),
                                         crate::WorkspaceScanMethod::UseCheckpoints,
-                                        &tools.doc_chkpts
+                                        &tools.doc_chkpts,
+                                        None
                                     );
                                     tools.thread_handles.push_back(handle);
                                 }
@@ -352,6 +366,35 @@ pub fn handle_request(
                             };
                         }
                     },
+                    "merlin6502.disk.symbols" => {
+                        if params.arguments.len()==1 {
+                            resp = match serde_json::from_value::<Vec<String>>(params.arguments[0].clone()) {
+                                Ok(paths) => {
+                                    // an empty list forgets all the imported symbols
+                                    if paths.is_empty() {
+                                        tools.disk_symbols = merlin::imports::DiskSymbols::new();
+                                    }
+                                    let mut err = None;
+                                    for path in paths {
+                                        match tools.disk.get(&path) {
+                                            Ok(sfimg) => {
+                                                tools.disk_symbols.add_file(&sfimg.data, &path);
+                                            },
+                                            Err(e) => err = Some(format!("{}: {}",path,e))
+                                        }
+                                    }
+                                    let shared = Arc::new(tools.disk_symbols.clone());
+                                    tools.hover_provider.use_disk_symbols(Arc::clone(&shared));
+                                    tools.completion_provider.use_disk_symbols(shared);
+                                    match err {
+                                        Some(mess) => Response::new_err(req.id,PARSE_ERROR,mess),
+                                        None => Response::new_ok(req.id,tools.disk_symbols.len())
+                                    }
+                                },
+                                _ => Response::new_err(req.id,PARSE_ERROR,"bad arguments while importing symbols".to_string())
+                            };
+                        }
+                    },
                     _ => {
                         logger(&connection,&format!("unhandled command {}",params.command));
                     }
@@ -16,6 +16,7 @@ pub fn handle_response(connection: &lsp_server::Connection, resp: lsp_server::Re
                     tools.config = config.clone();
                     tools.hover_provider.set_config(config.clone());
                     tools.completion_provider.set_config(config.clone());
+                    tools.inlay_hinter.set_config(config.clone());
                     tools.tokenizer.set_config(&config);
                     tools.formatter.set_config(&config);
                     tools.assembler.set_config(config.clone());
@@ -41,7 +42,8 @@ pub fn handle_response(connection: &lsp_server::Connection, resp: lsp_server::Re
                             Arc::new(Mutex::new(loc_analyzer)),
                             doc,
                             crate::WorkspaceScanMethod::None,
-                            &tools.doc_chkpts
+                            &tools.doc_chkpts,
+                            None
                         );
                         tools.thread_handles.push_back(handle);
                     }
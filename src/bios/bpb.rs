@@ -47,12 +47,14 @@ pub struct BPBFoundation {
     /// Value should also be put in FAT[0] in the low 8 bits.
     /// typical values are 0xf0 (removable) and 0xf8 (fixed).
     /// based on 86BOX :
-    /// 0xf9 = 1200K
+    /// 0xf0 = 1440K or 2880K
+    /// 0xf9 = 720K or 1200K
     /// 0xfb = 640K
     /// 0xfc = 180K
     /// 0xfd = 360K
     /// 0xfe = 160K
     /// 0xff = 320K
+    /// MSX adds 0xf8 = 360K and 0xfa = 320K, single sided with 80 tracks.
     pub media: u8,
     /// count of sectors occupied by one FAT, should be 0 for FAT32
     pub fat_size_16: [u8;2],
@@ -206,7 +208,7 @@ impl BPBFoundation {
         (entries*32 + bytes - 1) / bytes
     }
     pub fn root_dir_entries(&self) -> u64 {
-        u16::from_be_bytes(self.root_ent_cnt) as u64
+        u16::from_le_bytes(self.root_ent_cnt) as u64
     }
     pub fn sec_per_clus(&self) -> u8 {
         // Early DOS had a bug that erroneously set this to 2 for 160K and 180K disks.
@@ -317,6 +319,31 @@ impl BootSector {
             _ => Err(Box::new(super::Error::UnsupportedDiskKind))
         }
     }
+    /// Create a boot sector from the FAT ID, i.e., the media byte in the low 8 bits of FAT[0].
+    /// This is a quirk mode for disks with no BPB, as produced by MSX-DOS and other Z80 machines,
+    /// whose disk drivers derive the geometry from this byte.  The `capacity` of the image in bytes
+    /// is used to disambiguate IDs that are shared by more than one format.
+    pub fn create_from_fat_id(media: u8,capacity: usize) -> Result<Self,DYNERR> {
+        let foundation = match (media,capacity) {
+            (0xf0,c) if c >= 2880*1024 => D35_2880,
+            (0xf0,_) => D35_1440,
+            (0xf8,_) => MSX_SS_80_9,
+            (0xf9,c) if c >= 1200*1024 => DSHD_525,
+            (0xf9,_) => D35_720,
+            (0xfa,_) => MSX_SS_80_8,
+            (0xfb,_) => DSQD_525,
+            (0xfc,_) => SSDD_525_9,
+            (0xfd,_) => DSDD_525_9,
+            (0xfe,_) => SSDD_525_8,
+            (0xff,_) => DSDD_525_8,
+            _ => return Err(Box::new(super::Error::UnsupportedDiskKind))
+        };
+        if foundation.tot_sec() * foundation.sec_size() > capacity as u64 {
+            debug!("FAT ID {} implies more sectors than the image has",media);
+            return Err(Box::new(super::Error::IncompatibleDiskKind));
+        }
+        Ok(Self::create1216(foundation))
+    }
     fn create1216(bpb: BPBFoundation) -> Self {
         let tail = BPBTail::new();
         let sec_size = bpb.sec_size() as usize;
@@ -384,6 +411,38 @@ impl BootSector {
             None
         }
     }
+    /// Set the label in the BPB tail, returns false if there is no tail
+    pub fn set_label(&mut self,label: [u8;11]) -> bool {
+        if self.tail.boot_sig==0x29 {
+            self.tail.vol_lab = label;
+        }
+        self.tail.boot_sig==0x29
+    }
+    /// Set the volume serial number in the BPB tail, returns false if there is no tail
+    pub fn set_vol_id(&mut self,id: [u8;4]) -> bool {
+        if self.tail.boot_sig==0x29 {
+            self.tail.vol_id = id;
+        }
+        self.tail.boot_sig==0x29
+    }
+    /// Set the count of root directory entries, which moves the data region.  Returns false, changing nothing,
+    /// if the entries would not fill whole sectors, or would change the FAT type.  Only for FAT12 and FAT16.
+    pub fn set_root_dir_entries(&mut self,entries: u16) -> bool {
+        let old = self.foundation.root_ent_cnt;
+        let typ = self.fat_type();
+        let overhead = self.res_secs() as u64 + self.num_fats() * self.fat_secs();
+        let sec_size = self.sec_size();
+        if typ==32 || entries==0 || sec_size==0 || !(entries as u64 * 32).is_multiple_of(sec_size)
+            || overhead + entries as u64 * 32 / sec_size >= self.tot_sec() {
+            return false;
+        }
+        self.foundation.root_ent_cnt = u16::to_le_bytes(entries);
+        if self.fat_type()!=typ {
+            self.foundation.root_ent_cnt = old;
+            return false;
+        }
+        true
+    }
     pub fn sec_size(&self) -> u64 {
         self.foundation.sec_size()
     }
@@ -671,4 +730,36 @@ const D35_2880: BPBFoundation = BPBFoundation {
     num_heads: [2,0],
     hidd_sec: [0,0,0,0],
     tot_sec_32: [0,0,0,0]
-};
\ No newline at end of file
+};
+
+/// MSX single sided 80 track disk with 9 sectors per track, FAT ID 0xf8
+const MSX_SS_80_9: BPBFoundation = BPBFoundation {
+    bytes_per_sec: [0,2],
+    sec_per_clus: 2,
+    reserved_sectors: [1,0],
+    num_fats: 2,
+    root_ent_cnt: u16::to_le_bytes(0x70),
+    tot_sec_16: u16::to_le_bytes(720),
+    media: 0xf8,
+    fat_size_16: [2,0],
+    sec_per_trk: [9,0],
+    num_heads: [1,0],
+    hidd_sec: [0,0,0,0],
+    tot_sec_32: [0,0,0,0]
+};
+
+/// MSX single sided 80 track disk with 8 sectors per track, FAT ID 0xfa
+const MSX_SS_80_8: BPBFoundation = BPBFoundation {
+    bytes_per_sec: [0,2],
+    sec_per_clus: 2,
+    reserved_sectors: [1,0],
+    num_fats: 2,
+    root_ent_cnt: u16::to_le_bytes(0x70),
+    tot_sec_16: u16::to_le_bytes(640),
+    media: 0xfa,
+    fat_size_16: [1,0],
+    sec_per_trk: [8,0],
+    num_heads: [1,0],
+    hidd_sec: [0,0,0,0],
+    tot_sec_32: [0,0,0,0]
+};
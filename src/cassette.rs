@@ -0,0 +1,309 @@
+//! # Cassette audio
+//!
+//! Converts programs and binary data to and from Apple II cassette audio in WAV format.
+//! The signal follows the Monitor's `WRITE` and `READ` routines:
+//! * leader tone of 770 Hz, the Monitor writes about 10 seconds
+//! * sync bit made of a 2500 Hz half cycle and a 2000 Hz half cycle
+//! * data bits, most significant first, a 0 is one cycle at 2000 Hz, a 1 is one cycle at 1000 Hz
+//! * checksum byte, which is the XOR of $FF and every data byte
+//!
+//! BASIC programs are saved as two records.  The first is the length, followed by the lock flag
+//! in the case of Applesoft.  The second is the program, plus one more byte, because `SAVE`
+//! writes through the end pointer inclusively.  Binary data is saved as a single record,
+//! as with the Monitor's `W` command, so the load address is not on the tape.
+//!
+//! Example:
+//! ```rs
+//! let wav = a2kit::cassette::Encoder::new().to_wav(ItemType::ApplesoftTokens,&tokens)?;
+//! let tokens = a2kit::cassette::from_wav(ItemType::ApplesoftTokens,&wav)?;
+//! ```
+
+use log::{error,debug};
+use crate::commands::ItemType;
+use crate::DYNERR;
+
+/// half cycle durations in microseconds
+const LEADER_HALF: f64 = 650.0;
+const SYNC_HALVES: [f64;2] = [200.0,250.0];
+const ZERO_HALF: f64 = 250.0;
+const ONE_HALF: f64 = 500.0;
+/// full cycle thresholds in microseconds
+const BIT_THRESHOLD: f64 = 750.0;
+const LEADER_THRESHOLD: f64 = 1150.0;
+const GAP_THRESHOLD: f64 = 1800.0;
+/// half cycle threshold separating the sync from the leader
+const SYNC_THRESHOLD: f64 = 400.0;
+/// leader half cycles that must be seen before a sync is accepted
+const MIN_LEADER: usize = 64;
+
+#[derive(thiserror::Error,Debug)]
+pub enum Error {
+    #[error("WAV format not supported")]
+    UnsupportedWav,
+    #[error("no record found")]
+    NoRecord,
+    #[error("checksum mismatch")]
+    Checksum,
+    #[error("record has an unexpected length")]
+    BadLength
+}
+
+/// Settings for producing the audio
+pub struct Encoder {
+    /// samples per second
+    pub sample_rate: u32,
+    /// duration of the leader tone before each record in seconds
+    pub leader: f64,
+    /// peak amplitude of the square wave
+    pub amplitude: i16
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self {
+            sample_rate: 44100,
+            leader: 10.0,
+            amplitude: 16384
+        }
+    }
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Append a half cycle of `micros` microseconds.  The running time `t` keeps rounding errors from accumulating.
+    fn half_cycle(&self, samples: &mut Vec<i16>, t: &mut f64, micros: f64) {
+        *t += micros * self.sample_rate as f64 / 1e6;
+        let level = match samples.len() {
+            0 => self.amplitude,
+            n if samples[n-1] > 0 => -self.amplitude,
+            _ => self.amplitude
+        };
+        while (samples.len() as f64) < t.round() {
+            samples.push(level);
+        }
+    }
+    /// Append the audio for one record
+    pub fn record(&self, samples: &mut Vec<i16>, dat: &[u8]) {
+        let mut t = samples.len() as f64;
+        let leader_halves = (self.leader * 1e6 / LEADER_HALF) as usize;
+        for _ in 0..leader_halves.max(MIN_LEADER * 2) {
+            self.half_cycle(samples, &mut t, LEADER_HALF);
+        }
+        for half in SYNC_HALVES {
+            self.half_cycle(samples, &mut t, half);
+        }
+        let checksum = dat.iter().fold(0xff,|acc,b| acc ^ b);
+        for val in dat.iter().chain(std::iter::once(&checksum)) {
+            for bit in (0..8).rev() {
+                let half = match (val >> bit) & 1 {
+                    0 => ZERO_HALF,
+                    _ => ONE_HALF
+                };
+                self.half_cycle(samples, &mut t, half);
+                self.half_cycle(samples, &mut t, half);
+            }
+        }
+        // a short tail so the last cycle is terminated, followed by silence
+        self.half_cycle(samples, &mut t, ZERO_HALF);
+        let silence = samples.len() + self.sample_rate as usize / 10;
+        samples.resize(silence, 0);
+    }
+    /// Produce the audio for `dat`, which is tokenized BASIC or binary data, as a WAV file
+    pub fn to_wav(&self, typ: ItemType, dat: &[u8]) -> Result<Vec<u8>,DYNERR> {
+        let len = match u16::try_from(dat.len()) {
+            Ok(l) => l.to_le_bytes(),
+            Err(_) => {
+                error!("data is too large for a cassette record");
+                return Err(Box::new(Error::BadLength));
+            }
+        };
+        let mut with_end = dat.to_vec();
+        with_end.push(0);
+        let records = match typ {
+            ItemType::ApplesoftTokens => vec![vec![len[0],len[1],0],with_end],
+            ItemType::IntegerTokens => vec![len.to_vec(),with_end],
+            ItemType::Binary => vec![dat.to_vec()],
+            _ => {
+                error!("cassette supports atok, itok, and bin");
+                return Err(Box::new(crate::commands::CommandError::UnsupportedItemType));
+            }
+        };
+        let mut samples = Vec::new();
+        for record in records {
+            self.record(&mut samples, &record);
+        }
+        Ok(wav_bytes(self.sample_rate, &samples))
+    }
+}
+
+/// Wrap mono 16 bit samples in a WAV file
+fn wav_bytes(sample_rate: u32, samples: &[i16]) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let mut ans = Vec::new();
+    ans.extend_from_slice(b"RIFF");
+    ans.extend_from_slice(&u32::to_le_bytes(36 + data_len));
+    ans.extend_from_slice(b"WAVEfmt ");
+    ans.extend_from_slice(&u32::to_le_bytes(16));
+    ans.extend_from_slice(&u16::to_le_bytes(1)); // PCM
+    ans.extend_from_slice(&u16::to_le_bytes(1)); // mono
+    ans.extend_from_slice(&u32::to_le_bytes(sample_rate));
+    ans.extend_from_slice(&u32::to_le_bytes(sample_rate * 2));
+    ans.extend_from_slice(&u16::to_le_bytes(2));
+    ans.extend_from_slice(&u16::to_le_bytes(16));
+    ans.extend_from_slice(b"data");
+    ans.extend_from_slice(&u32::to_le_bytes(data_len));
+    for s in samples {
+        ans.extend_from_slice(&s.to_le_bytes());
+    }
+    ans
+}
+
+/// Get the sample rate and the first channel from a PCM WAV file, 8 or 16 bit samples are accepted
+fn wav_samples(wav: &[u8]) -> Result<(u32,Vec<i16>),DYNERR> {
+    if wav.len() < 12 || &wav[0..4] != b"RIFF" || &wav[8..12] != b"WAVE" {
+        error!("not a WAV file");
+        return Err(Box::new(Error::UnsupportedWav));
+    }
+    let mut fmt: Option<(u16,u16,u32,u16)> = None;
+    let mut ptr = 12;
+    while ptr + 8 <= wav.len() {
+        let id = &wav[ptr..ptr+4];
+        let len = u32::from_le_bytes([wav[ptr+4],wav[ptr+5],wav[ptr+6],wav[ptr+7]]) as usize;
+        let body = &wav[ptr+8..usize::min(ptr+8+len,wav.len())];
+        if id == b"fmt " && body.len() >= 16 {
+            fmt = Some((
+                u16::from_le_bytes([body[0],body[1]]),
+                u16::from_le_bytes([body[2],body[3]]),
+                u32::from_le_bytes([body[4],body[5],body[6],body[7]]),
+                u16::from_le_bytes([body[14],body[15]])
+            ));
+        } else if id == b"data" {
+            let (code,channels,rate,bits) = match fmt {
+                Some(f) => f,
+                None => {
+                    error!("WAV data precedes the format");
+                    return Err(Box::new(Error::UnsupportedWav));
+                }
+            };
+            if code != 1 || channels == 0 || (bits != 8 && bits != 16) {
+                error!("WAV must be 8 or 16 bit PCM, found format {} with {} bits",code,bits);
+                return Err(Box::new(Error::UnsupportedWav));
+            }
+            let stride = channels as usize * bits as usize / 8;
+            let samples = body.chunks_exact(stride).map(|frame| match bits {
+                8 => (frame[0] as i16 - 128) << 8,
+                _ => i16::from_le_bytes([frame[0],frame[1]])
+            }).collect();
+            return Ok((rate,samples));
+        }
+        // chunks are padded to even length
+        ptr += 8 + len + (len & 1);
+    }
+    error!("WAV file has no data");
+    Err(Box::new(Error::UnsupportedWav))
+}
+
+/// Durations in microseconds between zero crossings, with some hysteresis to reject noise
+fn half_cycles(sample_rate: u32, samples: &[i16]) -> Vec<f64> {
+    let peak = samples.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0);
+    let threshold = (peak / 8) as i32;
+    let mut ans = Vec::new();
+    let mut sign = 0;
+    let mut last = 0;
+    for (i,s) in samples.iter().enumerate() {
+        let new_sign = match *s as i32 {
+            x if x > threshold => 1,
+            x if x < -threshold => -1,
+            _ => sign
+        };
+        if new_sign != sign {
+            if sign != 0 {
+                ans.push((i - last) as f64 * 1e6 / sample_rate as f64);
+            }
+            sign = new_sign;
+            last = i;
+        }
+    }
+    ans
+}
+
+/// Decode every record in the WAV file, checksums are verified and removed
+pub fn decode_records(wav: &[u8]) -> Result<Vec<Vec<u8>>,DYNERR> {
+    let (rate,samples) = wav_samples(wav)?;
+    let halves = half_cycles(rate, &samples);
+    let mut ans = Vec::new();
+    let mut i = 0;
+    let mut leader = 0;
+    while i + 1 < halves.len() {
+        // look for the sync following a leader
+        if halves[i] > LEADER_THRESHOLD / 2.0 && halves[i] < GAP_THRESHOLD / 2.0 {
+            leader += 1;
+            i += 1;
+            continue;
+        }
+        if leader < MIN_LEADER || halves[i] >= SYNC_THRESHOLD {
+            leader = 0;
+            i += 1;
+            continue;
+        }
+        leader = 0;
+        i += 2;
+        let mut bytes = Vec::new();
+        let mut val: u8 = 0;
+        let mut bits = 0;
+        while i + 1 < halves.len() {
+            let cycle = halves[i] + halves[i+1];
+            if cycle > LEADER_THRESHOLD {
+                break;
+            }
+            val = (val << 1) | (cycle > BIT_THRESHOLD) as u8;
+            bits += 1;
+            if bits == 8 {
+                bytes.push(val);
+                bits = 0;
+            }
+            i += 2;
+        }
+        debug!("record with {} bytes and {} extra bits",bytes.len(),bits);
+        match bytes.pop() {
+            Some(checksum) => {
+                if bytes.iter().fold(0xff,|acc,b| acc ^ b) != checksum {
+                    error!("checksum mismatch in record {}",ans.len());
+                    return Err(Box::new(Error::Checksum));
+                }
+                ans.push(bytes);
+            },
+            None => {
+                error!("empty record");
+                return Err(Box::new(Error::NoRecord));
+            }
+        }
+    }
+    Ok(ans)
+}
+
+/// Decode tokenized BASIC or binary data from a WAV file
+pub fn from_wav(typ: ItemType, wav: &[u8]) -> Result<Vec<u8>,DYNERR> {
+    let records = decode_records(wav)?;
+    let (hdr_len,prog) = match (typ,records.as_slice()) {
+        (ItemType::Binary,[dat,..]) => return Ok(dat.to_vec()),
+        (ItemType::ApplesoftTokens,[hdr,prog,..]) if hdr.len() == 3 => (hdr,prog),
+        (ItemType::IntegerTokens,[hdr,prog,..]) if hdr.len() == 2 => (hdr,prog),
+        (ItemType::Binary|ItemType::ApplesoftTokens|ItemType::IntegerTokens,_) => {
+            error!("expected records were not found, there were {}",records.len());
+            return Err(Box::new(Error::NoRecord));
+        },
+        _ => {
+            error!("cassette supports atok, itok, and bin");
+            return Err(Box::new(crate::commands::CommandError::UnsupportedItemType));
+        }
+    };
+    let len = u16::from_le_bytes([hdr_len[0],hdr_len[1]]) as usize;
+    if prog.len() < len {
+        error!("program record has {} bytes, header says {}",prog.len(),len);
+        return Err(Box::new(Error::BadLength));
+    }
+    Ok(prog[..len].to_vec())
+}
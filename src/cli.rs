@@ -3,6 +3,14 @@ use clap::{arg, value_parser, crate_version, Arg, ArgAction, ArgGroup, Command,
 const RNG_HELP: &str = "some types support ranges using `..` and `,,` separators,
 e.g., `1..4,,7..10` would mean 1,2,3,7,8,9";
 const IN_HELP: &str = "if disk image is piped, omit `--dimg` option";
+const WORKSPACE_HELP: &str = "with `--workspace` paths take the form VOL:NAME, e.g. `APPLE1:SYSTEM.EDITOR`";
+const CHAIN_HELP: &str = "a chain is a list of `track,sector` pairs (DOS 3.x) or block numbers separated by spaces,
+e.g., `--chain \"17,15 17,14\"`, as might be found with `inspect`";
+const ADDR_HELP: &str = "with `-t sec` the sector is the ID in the address field unless `--addressing` says otherwise,
+`dos`, `prodos`, and `cpm` use that system's logical order, e.g., `-f 17,0,15 --addressing dos` is the first catalog sector";
+const BOOT_HELP: &str = "`-t boot` is the reserved system tracks of a CP/M disk as one blob, `--file` is not needed";
+const BATCH_HELP: &str = "a file that fails is reported and the rest continue, the status manifest lists each file as ok, skipped, or failed
+with `--resume` files that were ok or skipped in the manifest are not tried again";
 
 pub fn build_cli() -> Command {
     let long_help = "a2kit is always invoked with exactly one of several subcommands.
@@ -20,7 +28,8 @@ Language file check:   `a2kit get -f myprog.bas | a2kit verify -t atxt`
 Tokenize to file:      `a2kit get -f prog.bas | a2kit tokenize -a 2049 -t atxt > prog.atok
 Tokenize to image:     `a2kit get -f prog.bas | a2kit tokenize -a 2049 -t atxt \\
                            | a2kit put -f prog -t atok -d myimg.dsk`
-Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokenize -t atok";
+Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokenize -t atok
+Image in a zip file:   `a2kit catalog -d collection.zip:disk1.dsk`";
     let img_types = [
         "d13", "do", "po", "woz1", "woz2", "imd", "img", "2mg", "nib", "td0",
     ];
@@ -42,6 +51,10 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
         "5.25in-kay4",
         "5.25in-osb-sd",
         "5.25in-osb-dd",
+        "kaypro2",
+        "kaypro4",
+        "osborne1",
+        "osborne1-dd",
         "3.5in",
         "3.5in-ss",
         "3.5in-ds",
@@ -66,7 +79,10 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
         "track",
         "raw_track",
         "meta",
+        "macbinary",
+        "boot",
     ];
+    let put_types = [get_put_types.as_slice(),&["atxt","itxt","mtxt"]].concat();
 
     let pack_unpack_types = [
         "auto",
@@ -76,9 +92,12 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
         "rec",
         "atok",
         "itok",
-        "mtok"
+        "mtok",
+        "macbinary"
     ];
 
+    let output_arg = Arg::new("output").long("output").short('o').help("write to this file rather than stdout, avoids translation by the shell")
+        .value_name("PATH").value_hint(ValueHint::FilePath).required(false);
     let indent_arg = Arg::new("indent").long("indent").help("JSON indentation, omit to minify")
         .value_name("SPACES")
         .value_parser(value_parser!(u16).range(0..16))
@@ -97,8 +116,36 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
     let mut main_cmd = Command::new("a2kit")
         .about("Retro languages and disk images with emphasis on Apple II.")
         .after_long_help(long_help)
-        .version(crate_version!());
+        .version(crate_version!())
+        .arg(Arg::new("deterministic").long("deterministic").help("fix timestamps and clock based IDs so repeated runs give identical images")
+            .action(ArgAction::SetTrue).global(true))
+        .arg(Arg::new("yes-i-know").long("yes-i-know").help("allow disk image paths that are block devices, which are written in place")
+            .action(ArgAction::SetTrue).global(true))
+        .arg(Arg::new("fix-bitmap").long("fix-bitmap").help("rebuild the ProDOS volume bitmap or DOS 3.x VTOC bitmap from the directory tree before saving")
+            .action(ArgAction::SetTrue).global(true))
+        .arg(Arg::new("repair-padding").long("repair-padding").help("save ProDOS ordered images as whole blocks, dropping trailing bytes or padding a short block")
+            .action(ArgAction::SetTrue).global(true))
+        .arg(Arg::new("quirks").long("quirks").help("JSON profile that switches file system behaviors, e.g., to match an emulator")
+            .value_name("PATH").value_hint(ValueHint::FilePath).global(true))
+        .arg(Arg::new("provenance").long("provenance").help("record each save of an image in a sidecar JSON file")
+            .action(ArgAction::SetTrue).global(true))
+        .arg(Arg::new("cache-stats").long("cache-stats").help("print sector cache hits and misses of WOZ and NIB images to stderr after reading commands such as catalog")
+            .action(ArgAction::SetTrue).global(true))
+        .arg(Arg::new("lenient").long("lenient").help("skip damaged CP/M directory entries with a warning rather than rejecting the disk")
+            .action(ArgAction::SetTrue).global(true))
+        .arg(Arg::new("save-as").long("save-as").help("save a modified image here rather than over the source, converting to the image type of the extension")
+            .value_name("PATH").value_hint(ValueHint::FilePath).global(true))
+        .arg(Arg::new("fill-bad").long("fill-bad").help("read sectors the image marks bad as this hex pattern, rather than as recorded")
+            .value_name("HEX").global(true))
+        .arg(Arg::new("fail-bad").long("fail-bad").help("fail when reading sectors the image marks bad, rather than reading them as recorded")
+            .action(ArgAction::SetTrue).conflicts_with("fill-bad").global(true))
+        .arg(Arg::new("codepage").long("codepage").help("code page of FAT file names and labels")
+            .value_name("CP").value_parser(["437","850"]).default_value("437").global(true));
 
+    let addressing_arg = Arg::new("addressing").long("addressing").help("how sector numbers are interpreted with `-t sec`")
+        .value_name("ORDER").value_parser(["physical","dos","prodos","cpm"]).default_value("physical");
+    let workspace_arg = Arg::new("workspace").long("workspace").help("JSON file mapping Pascal volume names to disk images")
+        .value_name("PATH").value_hint(ValueHint::FilePath).required(false).conflicts_with("dimg");
     main_cmd = main_cmd.subcommand(
         Command::new("get")
             .arg(Arg::new("file").long("file").short('f').help("path, key, or address, maybe inside disk image")
@@ -113,28 +160,58 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
                 .value_name("LENGTH").required(false)
             )
             .arg(Arg::new("trunc").long("trunc").help("truncate raw at EOF if possible").action(ArgAction::SetTrue))
+            .arg(Arg::new("chain").long("chain").help("concatenate an explicit list of sectors or blocks")
+                .value_name("CHAIN").required(false).conflicts_with_all(["file","type","workspace"]))
+            .arg(workspace_arg.clone())
+            .arg(addressing_arg.clone())
+            .arg(output_arg.clone())
             .about("read from stdin, local, or disk image, write to stdout")
-            .after_help(RNG_HELP.to_string() + "\n\n" + IN_HELP)
+            .after_help(RNG_HELP.to_string() + "\n\n" + IN_HELP + "\n\n" + WORKSPACE_HELP + "\n\n" + CHAIN_HELP + "\n\n" + ADDR_HELP + "\n\n" + BOOT_HELP)
     );
+    let status_arg = Arg::new("status").long("status").help("write the outcome of each file as JSON")
+        .value_name("PATH").value_hint(ValueHint::FilePath).required(false);
+    let resume_arg = Arg::new("resume").long("resume").help("retry only the files that failed in a status manifest")
+        .value_name("PATH").value_hint(ValueHint::FilePath).required(false);
+    let if_exists_arg = Arg::new("if-exists").long("if-exists").help("what to do if a file is already there")
+        .value_name("POLICY").value_parser(["overwrite","skip","rename","error"]).default_value("error");
     main_cmd = main_cmd.subcommand(
         Command::new("put")
             .arg(Arg::new("file").long("file").short('f').help("path, key, or address, maybe inside disk image")
                 .value_name("PATH").value_hint(ValueHint::FilePath).required(false)
             )
             .arg(Arg::new("type").long("type").short('t').help("type of the item")
-                .value_name("TYPE").required(false).value_parser(get_put_types)
+                .value_name("TYPE").required(false).value_parser(put_types)
             )
             .arg(dimg_arg_opt.clone())
             .arg(Arg::new("addr").long("addr").short('a').help("load-address if applicable").value_name("ADDRESS").required(false))
-            .about("read from stdin, write to local or disk image")
-            .after_help(RNG_HELP)
+            .arg(if_exists_arg.clone())
+            .arg(Arg::new("input").long("input").short('i').help("host file to read, otherwise stdin").value_name("PATH")
+                .value_hint(ValueHint::FilePath).required(false))
+            .arg(Arg::new("as-dir").long("as-dir").help("treat `--input` as a glob and put every match in this directory").value_name("DIR")
+                .required(false).requires_all(["input","type","dimg"]).conflicts_with("file"))
+            .arg(Arg::new("preserve-times").long("preserve-times").help("give the file the modified time of the input file")
+                .action(ArgAction::SetTrue).requires("input"))
+            .arg(Arg::new("time").long("time").help("give the file this time, e.g. 1986-09-15T12:00:00-05:00").value_name("RFC3339")
+                .conflicts_with("preserve-times"))
+            .arg(Arg::new("keep-case").long("keep-case").help("keep lower case letters in the file name (ProDOS)")
+                .action(ArgAction::SetTrue))
+            .arg(addressing_arg)
+            .about("read from stdin or a host file, write to local or disk image")
+            .after_help(RNG_HELP.to_string() + "\n\n" + ADDR_HELP + "\n\n" + BOOT_HELP + "
+source types `atxt`, `itxt`, and `mtxt` are tokenized or encoded before they are put
+with `--as-dir` the glob is expanded by a2kit, e.g. `put -i \"src/*.bas\" --as-dir /BASIC/ -t atxt -d disk.po`,
+names are formed from the host names and made valid for the file system")
     );
     main_cmd = main_cmd.subcommand(
         Command::new("mget")
             .arg(dimg_arg_req.clone())
             .arg(indent_arg.clone())
+            .arg(status_arg.clone())
+            .arg(resume_arg.clone())
             .about("read list of paths from stdin, get files from disk image, write file images to stdout")
-            .after_help("this can take `a2kit glob` as a piped input")
+            .after_help("this can take `a2kit glob` as a piped input
+if the image is a zip archive with several images, each is searched, with results keyed by member
+".to_string() + BATCH_HELP)
     );
     main_cmd = main_cmd.subcommand(
         Command::new("mput")
@@ -142,8 +219,20 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
             .arg(Arg::new("file").long("file").short('f').help("override target paths")
                 .value_name("PATH").value_hint(ValueHint::FilePath).required(false)
             )
+            .arg(Arg::new("first").long("first").help("file names to put first, in this order")
+                .value_name("NAMES").value_delimiter(',').required(false)
+            )
+            .arg(Arg::new("at-block").long("at-block").help("start the named file at this block, or the next free block after it")
+                .value_name("NAME=BLOCK").action(ArgAction::Append).required(false)
+            )
+            .arg(if_exists_arg)
+            .arg(status_arg)
+            .arg(resume_arg)
             .about("read list of file images from stdin, restore files to a disk image")
-            .after_help("for CP/M the user number can be overridden using `-f <num>:`")
+            .after_help("for CP/M the user number can be overridden using `-f <num>:`
+files are put in the order of the list, unless `--first` is used, e.g., `--first prodos,basic.system`
+`--at-block` is only supported for ProDOS
+".to_string() + BATCH_HELP)
     );
     main_cmd = main_cmd.subcommand(
         Command::new("pack")
@@ -207,7 +296,107 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
                     .value_parser(wrap_types)
                     .required(false),
             )
+            .arg(
+                arg!(--tracks <COUNT> "track count for DOS 3.3 in a DO image, 35 to 50, or 80")
+                    .value_parser(value_parser!(u8).range(35..=80))
+                    .required(false),
+            )
+            .arg(
+                arg!(--template <NAME> "bootable DOS 3.3 disk with a starting program")
+                    .value_parser(["applesoft-boot","merlin-hello"])
+                    .required(false),
+            )
+            .arg(
+                arg!(--system <PATH> "CP/M system for the reserved tracks, tracks 0-2 in DOS order, or a whole DO image")
+                    .value_hint(ValueHint::FilePath)
+                    .required(false),
+            )
+            .arg(
+                arg!(--"bit-timing" <TICKS> "optimal bit timing recorded in a WOZ2 image, in units of 125 ns")
+                    .value_parser(value_parser!(u8).range(8..=40))
+                    .required(false),
+            )
+            .arg(
+                arg!(--"track-bits" <BITS> "bits in each track of a 5.25 inch WOZ image, e.g., 50000 or 51200")
+                    .value_parser(value_parser!(u32).range(1..))
+                    .required(false),
+            )
             .about("write a blank disk image to the given path")
+            .after_help("A DOS 3.x disk that is not bootable leaves tracks 1 and 2 free for data.
+A bootable Apple II CP/M disk needs `--system`, the reserved tracks are copied from it.
+The WOZ defaults are 32 (4 us) bit timing for 5.25 inch disks and 16 (2 us) for 3.5 inch disks.")
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("build")
+            .arg(
+                Arg::new("manifest").help("path to the build manifest").value_name("PATH")
+                    .value_hint(ValueHint::FilePath)
+                    .required(true)
+            )
+            .about("build a disk image from a manifest")
+            .after_help("The manifest is JSON, see the `commands::build` module documentation.
+The image is created from scratch, the files are put, and the post steps are run."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("serve")
+            .arg(dimg_arg_req.clone())
+            .arg(
+                arg!(--drive2 <PATH> "disk image for drive 2")
+                    .value_hint(ValueHint::FilePath)
+                    .required(false),
+            )
+            .arg(
+                arg!(--tcp <ADDRESS> "address to listen on")
+                    .required(false)
+                    .default_value("0.0.0.0:6502"),
+            )
+            .arg(
+                arg!(--serial <DEVICE> "serial device to use instead of TCP")
+                    .value_hint(ValueHint::FilePath)
+                    .required(false),
+            )
+            .about("serve disk images as virtual drives to an Apple II")
+            .after_help("The protocol is that of ADTPro's virtual drive client.
+Blocks are addressed in ProDOS order.  Writes are saved immediately.
+A serial device has to be configured beforehand, e.g. with `stty`."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("serve-http")
+            .arg(
+                arg!(--root <PATH> "directory containing disk images")
+                    .value_hint(ValueHint::DirPath)
+                    .required(true),
+            )
+            .arg(
+                arg!(--addr <ADDRESS> "address to listen on")
+                    .required(false)
+                    .default_value("127.0.0.1:8080"),
+            )
+            .about("serve the disk images in a directory over HTTP")
+            .after_help("Routes are /images, /stat, /catalog, and /file, see the `commands::http` module documentation.
+Uploads are saved to the image immediately."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("rpc")
+            .about("answer JSON-RPC requests from stdin, one per line, keeping images in memory")
+            .after_help("Methods include catalog, tree, stat, get, put, mkdsk, and exit, see the `commands::rpc` module documentation."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("watch")
+            .arg(
+                Arg::new("manifest").help("path to the build manifest").value_name("PATH")
+                    .value_hint(ValueHint::FilePath)
+                    .required(true)
+            )
+            .arg(
+                arg!(-i --interval <MS> "milliseconds between checks for changes")
+                    .value_parser(value_parser!(u64).range(10..))
+                    .required(false)
+                    .default_value("500"),
+            )
+            .about("build a disk image from a manifest, and rebuild whenever a source changes")
+            .after_help("The manifest is JSON, see the `commands::build` module documentation.
+Only files that changed are put again.  Press Ctrl-C to stop."),
     );
     main_cmd = main_cmd.subcommand(
         Command::new("mkdir")
@@ -223,6 +412,39 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
             .visible_alias("era")
             .about("delete a file or directory inside a disk image"),
     );
+    main_cmd = main_cmd.subcommand(
+        Command::new("append")
+            .arg(arg!(-f --file <PATH> "path inside disk image to append to").required(true))
+            .arg(dimg_arg_req.clone())
+            .arg(Arg::new("type").long("type").short('t').help("type of the data, `txt` is encoded for the file system")
+                .value_name("TYPE").value_parser(["raw","txt"]).default_value("raw"))
+            .arg(Arg::new("input").long("input").short('i').help("host file to read, otherwise stdin").value_name("PATH")
+                .value_hint(ValueHint::FilePath).required(false))
+            .about("add data to the end of a file inside a disk image")
+            .after_help("the file is extended in place, ProDOS, DOS 3.x, and FAT are supported
+for DOS 3.x text ends at the first null, and binary or BASIC files are extended past the length in the header"),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("patch")
+            .arg(arg!(-f --file <PATH> "path inside disk image to patch").required(true))
+            .arg(dimg_arg_req.clone())
+            .arg(Arg::new("offset").long("offset").short('o').help("byte offset within the file, not counting a DOS 3.x header, `$` or `0x` for hex")
+                .value_name("OFFSET").required(true))
+            .arg(Arg::new("input").long("input").short('i').help("host file to read, otherwise stdin").value_name("PATH")
+                .value_hint(ValueHint::FilePath).required(false))
+            .about("overwrite bytes at an offset within a file inside a disk image")
+            .after_help("only the blocks that hold the new bytes are rewritten, like `dd seek=... conv=notrunc`,
+the file grows if the bytes run past the end, ProDOS, DOS 3.x, and FAT are supported"),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("truncate")
+            .arg(arg!(-f --file <PATH> "path inside disk image to truncate").required(true))
+            .arg(dimg_arg_req.clone())
+            .arg(Arg::new("len").long("len").short('l').help("new length in bytes, not counting a DOS 3.x header")
+                .value_name("BYTES").value_parser(value_parser!(usize)).required(true))
+            .about("cut a file inside a disk image to a given length")
+            .after_help("blocks past the new end are freed, ProDOS, DOS 3.x, and FAT are supported"),
+    );
     main_cmd = main_cmd.subcommand(
         Command::new("protect")
             .arg(arg!(-f --file <PATH> "path inside disk image to protect").required(true))
@@ -253,25 +475,54 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
     );
     main_cmd = main_cmd.subcommand(
         Command::new("rename")
-            .arg(arg!(-f --file <PATH> "path inside disk image to rename").required(true))
-            .arg(arg!(-n --name <NAME> "new name").required(true))
+            .arg(arg!(-f --file <PATH> "path inside disk image to rename, CP/M and FAT allow wildcards").required(true))
+            .arg(arg!(-n --name <NAME> "new name, with wildcards if the path has them").required(true))
             .arg(dimg_arg_req.clone())
             .about("rename a file or directory inside a disk image"),
     );
     main_cmd = main_cmd.subcommand(
         Command::new("retype")
-            .arg(arg!(-f --file <PATH> "path inside disk image to retype").required(true))
+            .arg(arg!(-f --file <PATH> "path inside disk image to retype, may be a glob pattern").required(true))
             .arg(arg!(-t --type <TYPE> "file system type, code or mnemonic").required(true))
             .arg(arg!(-a --aux <AUX> "file system auxiliary metadata").required(true))
-            .arg(dimg_arg_req)
-            .about("change file type inside a disk image"),
+            .arg(dimg_arg_req.clone())
+            .arg(arg!(--"dry-run" "list the files that would be retyped without changing anything").action(ArgAction::SetTrue))
+            .about("change file type inside a disk image")
+            .after_help("If PATH has `*`, `?`, or `[`, every matching file is retyped.
+AUX is decimal, or hex with a `0x` or `$` prefix."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("relabel")
+            .arg(arg!(-l --label <LABEL> "new volume name, or volume number for DOS 3.x").required(false))
+            .arg(arg!(--serial <HEX> "new volume serial number, e.g. `1234-ABCD` (FAT only)").required(false))
+            .group(ArgGroup::new("what").args(["label","serial"]).multiple(true).required(true))
+            .arg(dimg_arg_req.clone())
+            .about("change the volume label or serial number of a disk image")
+            .after_help("ProDOS and Pascal rename the volume, DOS 3.x changes the VTOC volume number,
+FAT changes the boot sector and root directory labels, and CP/M 3 changes or creates the label."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("reclaim-dos")
+            .arg(dimg_arg_req.clone())
+            .about("free the DOS tracks of a DOS 3.x disk image")
+            .after_help("Tracks 1 and 2 are marked free, provided no file uses them. The disk will no longer boot."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("fsck")
+            .arg(dimg_arg_req.clone())
+            .arg(Arg::new("repair").long("repair").help("rebuild the bitmap from the directory tree and save the image")
+                .action(ArgAction::SetTrue))
+            .about("compare the free space bitmap of a ProDOS or DOS 3.x disk image with its directory tree")
+            .after_help("Each block or sector whose bitmap state disagrees with the directory tree is listed. \
+With `--repair` the differences are listed before and after the repair. \
+DOS 3.x tracks 1 and 2 are only checked where files use them, since DOS may be reserving them."),
     );
     main_cmd = main_cmd.subcommand(
         Command::new("verify")
             .arg(
                 arg!(-t --type <TYPE> "type of the file")
                     .required(true)
-                    .value_parser(["atxt", "itxt", "mtxt"]),
+                    .value_parser(["atxt", "itxt", "mtxt", "bin"]),
             )
             .arg(
                 arg!(-s --sexpr "write S-expressions to stderr").action(ArgAction::SetTrue)
@@ -285,7 +536,41 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
                 arg!(-w --workspace <PATH> "workspace directory")
                     .required(false)
             )
-            .about("read from stdin and perform language analysis"),
+            .arg(
+                Arg::new("proc").short('p').long("proc").help("processor target for `-t bin`").value_name("NAME")
+                    .value_parser(["6502","65c02","65802","65816"])
+                    .default_value("6502")
+            )
+            .arg(
+                Arg::new("org").short('o').long("org").help("load address for `-t bin`").value_name("ADDRESS")
+            )
+            .arg(
+                Arg::new("entry").short('e').long("entry").help("comma separated entry points for `-t bin`, defaults to the load address").value_name("ADDRESSES")
+            )
+            .arg(
+                Arg::new("undocumented").long("undocumented").help("accept undocumented 6502 operations with `-t bin`").action(ArgAction::SetTrue)
+            )
+            .about("read from stdin and perform language analysis, or check the paths through a binary"),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("lint")
+            .arg(
+                arg!(-t --type <TYPE> "type of the sources")
+                    .value_parser(["atxt", "mtxt"])
+                    .default_value("mtxt"),
+            )
+            .arg(arg!(-w --workspace <PATH> "workspace directory").required(true))
+            .arg(arg!(-c --config <JSON> "modify diagnostic configuration").required(false))
+            .arg(Arg::new("severity").long("severity").help("least severe diagnostic to report").value_name("LEVEL")
+                .value_parser(["error","warning","info"]).default_value("info"))
+            .arg(Arg::new("format").long("format").help("output format").value_name("FORMAT")
+                .value_parser(["text","json","sarif"]).default_value("text"))
+            .arg(arg!(--"deny-warnings" "exit with an error if there are any warnings").action(ArgAction::SetTrue))
+            .arg(indent_arg.clone())
+            .about("analyze every Merlin or Applesoft source in a workspace")
+            .after_help("Merlin includes are analyzed in the context of their masters, as in the language server.
+Applesoft sources are `*.bas` or `*.abas`.
+Text goes to stderr, JSON and SARIF go to stdout.  Exit status is an error if there are any errors."),
     );
     main_cmd = main_cmd.subcommand(
         Command::new("minify")
@@ -296,7 +581,7 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
             )
             .arg(
                 arg!(--level <LEVEL> "set minification level")
-                    .value_parser(["0", "1", "2", "3"])
+                    .value_parser(["0", "1", "2", "3", "4"])
                     .default_value("1"),
             )
             .arg(arg!(--flags <VAL> "set minification flags").default_value("1"))
@@ -306,7 +591,8 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
                     .multiple(false)
                     .args(["level", "flags"]),
             )
-            .about("reduce program size"),
+            .about("reduce program size")
+            .after_help("level 4 renames variables and combines lines, the savings are reported to stderr"),
     );
     main_cmd = main_cmd.subcommand(
         Command::new("renumber")
@@ -320,13 +606,35 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
             .arg(arg!(-f --first <NUM> "first number").required(true))
             .arg(arg!(-s --step <NUM> "step between numbers").required(true))
             .arg(arg!(-r --reorder "allow reordering of lines").action(ArgAction::SetTrue))
-            .about("renumber BASIC program lines"),
+            .about("renumber BASIC program lines")
+            .after_help("BASIC labels such as `@loop:` stay attached to the same lines"),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("convert-lang")
+            .arg(
+                arg!(-t --type <TYPE> "type of the source, the other BASIC is the target")
+                    .required(true)
+                    .value_parser(["atxt","itxt"]),
+            )
+            .about("translate Applesoft to Integer BASIC or the reverse")
+            .after_help("Reads from stdin, writes to stdout.  Untranslatable statements are kept as is and reported to stderr."),
     );
     main_cmd = main_cmd.subcommand(
         Command::new("catalog")
             .arg(arg!(-f --file <PATH> "path of directory inside disk image").required(false))
             .arg(arg!(--generic "use generic output format").action(ArgAction::SetTrue))
+            .arg(Arg::new("plain").long("plain").help("tab separated name, type, blocks, and modified time, the same for every file system")
+                .action(ArgAction::SetTrue).conflicts_with("generic"))
+            .arg(Arg::new("sort").long("sort").help("order of the rows, native listings that put several files on a line cannot be sorted")
+                .value_name("KEY").value_parser(["name","date","address","size"]))
+            .arg(Arg::new("filter").long("filter").help("keep rows that match, e.g. type=BIN")
+                .value_name("KEY=VAL"))
+            .arg(Arg::new("since").long("since").help("keep files modified on or after this date, e.g. 1986-09-15")
+                .value_name("DATE"))
+            .arg(Arg::new("limit").long("limit").help("write at most this many rows")
+                .value_name("N").value_parser(value_parser!(usize)))
             .arg(dimg_arg_opt.clone())
+            .arg(output_arg.clone())
             .visible_alias("cat")
             .visible_alias("dir")
             .visible_alias("ls")
@@ -338,23 +646,210 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
             .arg(dimg_arg_opt.clone())
             .arg(Arg::new("meta").long("meta").help("include metadata").action(ArgAction::SetTrue))
             .arg(indent_arg.clone())
+            .arg(Arg::new("depth").long("depth").help("levels to descend, 1 is the root directory only").value_name("N")
+                .value_parser(value_parser!(u64).range(1..)))
+            .arg(Arg::new("du").long("du").help("add up files, bytes, and blocks within each directory").action(ArgAction::SetTrue))
+            .arg(Arg::new("paths").long("paths").help("list paths one per line rather than JSON").action(ArgAction::SetTrue)
+                .conflicts_with_all(["meta","indent"]))
+            .arg(output_arg.clone())
             .about("write directory tree as a JSON string to stdout")
-            .after_help(IN_HELP),
+            .after_help(IN_HELP.to_string() + "\n\nWith `--paths --du` each line starts with the block count, as in `du -a`."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("inspect")
+            .arg(dimg_arg_opt.clone())
+            .arg(Arg::new("file").short('f').long("file").help("path of the file within disk image").value_name("PATH")
+                .required(true))
+            .arg(indent_arg.clone())
+            .about("write the block structure of a file as a JSON string to stdout")
+            .after_help("ProDOS gives the storage type, key block, and the tree of index blocks,
+DOS 3.x gives the track/sector lists, FAT gives the cluster chain.
+A data pointer of 0 is a hole in a sparse file.\n\n".to_string() + IN_HELP),
     );
     main_cmd = main_cmd.subcommand(
         Command::new("stat")
             .arg(dimg_arg_opt.clone())
+            .arg(Arg::new("level").long("level").help("how much of the volume structures to show in `raw`").value_name("LEVEL")
+                .value_parser(["basic","deep"]).default_value("basic"))
             .arg(indent_arg.clone())
             .about("write FS statistics as a JSON string to stdout")
-            .after_help(IN_HELP),
+            .after_help("with `--level deep`, `raw` has the parsed volume structures with numbers decoded, such as the ProDOS
+volume header, DOS 3.x VTOC, FAT BPB, or CP/M DPB, and derived values including the image type
+
+".to_string() + IN_HELP),
+    );
+    #[cfg(feature = "browse")]
+    {
+        main_cmd = main_cmd.subcommand(
+            Command::new("browse")
+                .arg(dimg_arg_req.clone())
+                .arg(Arg::new("host").long("host").help("host directory to start in").value_name("DIR")
+                    .value_hint(ValueHint::DirPath).default_value("."))
+                .about("browse a disk image and a host directory side by side in the terminal")
+                .after_help("Tab switches panes, c copies, d deletes, r renames, u undoes, v views, q quits.
+Changes to the image are saved immediately."),
+        );
+    }
+    main_cmd = main_cmd.subcommand(
+        Command::new("fsdiff")
+            .arg(Arg::new("a").help("original disk image").value_name("IMAGE_A")
+                .value_hint(ValueHint::FilePath).required(true))
+            .arg(Arg::new("b").help("changed disk image").value_name("IMAGE_B")
+                .value_hint(ValueHint::FilePath).required(true))
+            .arg(Arg::new("unified").short('u').long("unified").help("show unified diffs of changed files").action(ArgAction::SetTrue))
+            .arg(Arg::new("context").short('U').long("context").help("lines of context in unified diffs").value_name("LINES")
+                .value_parser(value_parser!(usize)).default_value("3").requires("unified"))
+            .arg(Arg::new("raw").long("raw").help("compare bytes as stored, without detokenizing or converting text").action(ArgAction::SetTrue))
+            .arg(Arg::new("ignore-space").short('w').long("ignore-space").help("ignore trailing whitespace on lines").action(ArgAction::SetTrue))
+            .arg(Arg::new("meta").long("meta").help("also compare file type, auxiliary type, and access").action(ArgAction::SetTrue))
+            .about("compare the files in two disk images, write a report to stdout")
+            .after_help("Each file that differs is listed as added, removed, changed, or meta (only metadata changed).
+BASIC programs are compared as listings, binary files are diffed as hex dumps."),
+    );
+    let range_arg = Arg::new("range").long("range").short('r').help("tracks and sectors, e.g. `t0s0..t34s15` or `t3..t5`")
+        .value_name("RANGE").required(false);
+    let blocks_arg = Arg::new("blocks").long("blocks").short('b').help("file system blocks, e.g. `0..280`")
+        .value_name("BLOCKS").required(false);
+    main_cmd = main_cmd.subcommand(
+        Command::new("dump")
+            .arg(dimg_arg_req.clone())
+            .arg(range_arg.clone())
+            .arg(blocks_arg.clone())
+            .group(ArgGroup::new("span").args(["range","blocks"]).required(true))
+            .arg(Arg::new("out").long("out").short('o').help("binary file to write, otherwise stdout").value_name("PATH")
+                .value_hint(ValueHint::FilePath).required(false))
+            .arg(Arg::new("hex").long("hex").help("write a hex dump to stdout even if it is not the console").action(ArgAction::SetTrue)
+                .conflicts_with("out"))
+            .about("write a range of sectors or blocks to a file or stdout")
+            .after_help("Tracks are numbered from 0 across both sides, sectors are by ID, both ends are included.
+Blocks work as with `get -t block`, the end is excluded."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("restore")
+            .arg(dimg_arg_req.clone())
+            .arg(range_arg.clone())
+            .arg(blocks_arg.clone())
+            .group(ArgGroup::new("span").args(["range","blocks"]).required(true))
+            .arg(Arg::new("input").long("input").short('i').help("binary file to read, otherwise stdin").value_name("PATH")
+                .value_hint(ValueHint::FilePath).required(false))
+            .about("write data produced by `dump` back to a range of sectors or blocks")
+            .after_help("The data must fill the range exactly."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("wipe")
+            .arg(dimg_arg_req.clone())
+            .arg(Arg::new("free").long("free").help("overwrite blocks the file system marks free").action(ArgAction::SetTrue))
+            .arg(Arg::new("deleted").long("deleted").help("erase deleted directory entries").action(ArgAction::SetTrue))
+            .arg(range_arg)
+            .arg(blocks_arg)
+            .group(ArgGroup::new("what").args(["free","deleted","range","blocks"]).multiple(true).required(true))
+            .arg(Arg::new("pattern").long("pattern").short('p').help("hex bytes to fill with, repeated as needed").value_name("HEX")
+                .default_value("00"))
+            .about("overwrite free space, deleted entries, or chosen sectors, e.g. before distributing an image")
+            .after_help("Ranges work as with `dump`.  Free space and deleted entries need a supported file system (DOS 3.x, ProDOS).
+The count of what was overwritten is written to stderr."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("banner")
+            .arg(dimg_arg_req.clone())
+            .arg(Arg::new("message").long("message").short('m').help("text of the banner, encoded for the file system").value_name("TEXT")
+                .required(true))
+            .about("write a text banner into free sectors near the directory")
+            .after_help("DOS 3.x uses free sectors on the catalog track, ProDOS uses the lowest free blocks.
+Only sectors or blocks the allocation map marks free are written, and they stay free.
+Where the banner went is written to stderr."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("carve")
+            .arg(dimg_arg_req.clone())
+            .arg(Arg::new("out").long("out").short('o').help("directory where carved files are written").value_name("DIR")
+                .value_hint(ValueHint::DirPath).required(false))
+            .arg(Arg::new("min-confidence").long("min-confidence").help("omit files with lower confidence").value_name("FRACTION")
+                .value_parser(value_parser!(f64)).default_value("0"))
+            .arg(Arg::new("raw").long("raw").help("write Applesoft as tokens rather than listings").action(ArgAction::SetTrue))
+            .arg(indent_arg.clone())
+            .about("scan a damaged disk image for probable files, write a JSON confidence report to stdout")
+            .after_help("The directory is ignored.  DOS track/sector lists and ProDOS index blocks are followed,
+then the remaining sectors are searched for Applesoft programs and runs of text."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("migrate")
+            .arg(dimg_arg_req.clone())
+            .arg(Arg::new("out").long("out").short('o').help("ProDOS disk image to create").value_name("PATH")
+                .value_hint(ValueHint::FilePath).required(true))
+            .arg(
+                arg!(-t --type <TYPE> "type of disk image to create, defaults to the type of the source")
+                    .required(false)
+                    .value_parser(img_types),
+            )
+            .arg(Arg::new("volume").long("volume").short('v').help("ProDOS volume name").value_name("NAME")
+                .default_value("MIGRATED"))
+            .arg(indent_arg.clone())
+            .about("copy every file on a DOS 3.3 disk to a new ProDOS disk, write a JSON report to stdout")
+            .after_help("Types are mapped as the DOS-ProDOS conversion utility did, text has the high bits cleared.
+S, R, and the second A and B types become user types F1 through F4.  Names are made valid for ProDOS."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("verify-convert")
+            .arg(dimg_arg_req.clone())
+            .arg(
+                arg!(-t --type <TYPE> "type of disk image to convert to")
+                    .required(true)
+                    .value_parser(img_types),
+            )
+            .arg(
+                arg!(-o --output <PATH> "where to save the converted image")
+                    .value_hint(ValueHint::FilePath)
+                    .required(false),
+            )
+            .arg(indent_arg.clone())
+            .about("convert a disk image and back, write a JSON comparison report to stdout")
+            .after_help("The converted image is only saved if both comparisons succeed.
+Offsets that are known to be ignorable, such as certain timestamps, are not compared."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("normalize")
+            .arg(Arg::new("dir").long("dir").help("directory of disk images").value_name("DIR")
+                .value_hint(ValueHint::DirPath).required(true))
+            .arg(Arg::new("out").long("out").short('o').help("where to write the normalized images, defaults to DIR").value_name("DIR")
+                .value_hint(ValueHint::DirPath).required(false))
+            .arg(
+                arg!(-t --type <TYPE> "type of disk image to convert to")
+                    .required(true)
+                    .value_parser(img_types),
+            )
+            .arg(indent_arg.clone())
+            .about("convert every image in a directory to one type with uniform names, write a JSON report to stdout")
+            .after_help("Names are lower case with unusual characters replaced by `_`.
+Images whose destination exists are skipped, conversions that are not lossless are not saved."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("provenance")
+            .arg(dimg_arg_req.clone())
+            .arg(indent_arg.clone())
+            .about("write the provenance sidecar of an image to stdout and verify it")
+            .after_help("The sidecar is written when an image is saved with `--provenance`.
+Verification fails if the chain of hashes is broken, or the image was changed outside the record."),
     );
     main_cmd = main_cmd.subcommand(
         Command::new("geometry")
             .arg(dimg_arg_opt.clone())
             .arg(indent_arg.clone())
+            .arg(Arg::new("table").long("table").help("write a table for reading rather than JSON").action(ArgAction::SetTrue)
+                .conflicts_with("indent"))
             .about("write disk geometry as a JSON string to stdout")
             .after_help(IN_HELP),
     );
+    main_cmd = main_cmd.subcommand(
+        Command::new("entropy")
+            .arg(dimg_arg_opt.clone())
+            .arg(indent_arg.clone())
+            .arg(Arg::new("table").long("table").help("write a table for reading rather than JSON").action(ArgAction::SetTrue)
+                .conflicts_with("indent"))
+            .about("write entropy and compressibility of each track as a JSON string to stdout")
+            .after_help("Tracks are classed as unformatted, blank, duplicate, or data.
+Entropy is in bits per byte, ratio is the compressed size over the original size."),
+    );
     main_cmd = main_cmd.subcommand(
         Command::new("tokenize")
             .arg(
@@ -366,8 +861,25 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
                     .required(true)
                     .value_parser(["atxt", "itxt", "mtxt"]),
             )
+            .arg(arg!(--dialect <PATH> "JSON table of extension commands (Applesoft only)").required(false))
             .visible_alias("tok")
-            .about("read from stdin, tokenize, write to stdout"),
+            .about("read from stdin, tokenize, write to stdout")
+            .after_help("BASIC labels such as `@loop:` are resolved to line numbers"),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("bundle")
+            .arg(
+                Arg::new("addr").short('a').long("addr").help("load address of the bundle").value_name("ADDRESS")
+                    .required(true),
+            )
+            .arg(Arg::new("asset").long("asset").help("binary file to load at an address, repeatable").value_name("ADDR:PATH")
+                .action(ArgAction::Append).required(false))
+            .arg(dimg_arg_opt.clone().requires("file"))
+            .arg(Arg::new("file").short('f').long("file").help("name of the bundle inside the disk image").value_name("PATH")
+                .required(false).requires("dimg"))
+            .about("read tokenized Applesoft from stdin, write a self-loading binary with the assets")
+            .after_help("The program is installed at 2049 and run when the bundle is `BRUN`.
+Addresses are decimal.  Without `--dimg` the bundle goes to stdout."),
     );
     main_cmd = main_cmd.subcommand(
         Command::new("detokenize")
@@ -376,8 +888,92 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
                     .required(true)
                     .value_parser(["atok", "itok", "mtok"]),
             )
+            .arg(arg!(--labels "emit labels for branch targets (BASIC only)").action(ArgAction::SetTrue))
+            .arg(arg!(--dialect <PATH> "JSON table of extension commands (Applesoft only)").required(false))
+            .arg(Arg::new("highlight").long("highlight").help("syntax highlight the listing").value_name("STYLE")
+                .value_parser(["ansi","html"]).required(false))
             .visible_alias("dtok")
-            .about("read from stdin, detokenize, write to stdout"),
+            .about("read from stdin, detokenize, write to stdout")
+            .after_help("Highlighting uses the same token classes as the language servers.
+HTML is a `pre` element with spans classed by token type, e.g. `a2kit-keyword`."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("format")
+            .arg(
+                Arg::new("type").short('t').long("type").help("type of the file").value_name("TYPE")
+                    .required(true)
+                    .value_parser(["mtxt"]),
+            )
+            .arg(Arg::new("columns").long("columns").help("widths of the label, operation, and operand columns")
+                .value_name("C1,C2,C3").default_value("9,6,11"))
+            .arg(Arg::new("style").long("style").help("how columns are separated").value_name("STYLE")
+                .value_parser(["variable","tabs","paste"]).default_value("variable"))
+            .arg(Arg::new("case").long("case").help("case of operations and pseudo-operations").value_name("CASE")
+                .value_parser(["upper","lower"]).required(false))
+            .about("read source from stdin, format, write to stdout")
+            .after_help("Column widths only apply to the variable style, the paste style is suitable for emulators.
+Labels, operands, and macro calls are never changed."),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("to-wav")
+            .arg(
+                Arg::new("type").short('t').long("type").help("type of the data").value_name("TYPE")
+                    .required(true)
+                    .value_parser(["atok", "itok", "bin"]),
+            )
+            .arg(
+                arg!(--leader <SECONDS> "duration of the leader tone")
+                    .value_parser(value_parser!(f64))
+                    .required(false)
+                    .default_value("10"),
+            )
+            .arg(
+                arg!(--rate <HZ> "sample rate")
+                    .value_parser(value_parser!(u32).range(8000..))
+                    .required(false)
+                    .default_value("44100"),
+            )
+            .about("read from stdin, encode as cassette audio, write WAV to stdout")
+            .after_help("BASIC programs are written as they would be by SAVE, binary data as by the Monitor's W command"),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("from-wav")
+            .arg(
+                Arg::new("type").short('t').long("type").help("type of the data").value_name("TYPE")
+                    .required(true)
+                    .value_parser(["atok", "itok", "bin"]),
+            )
+            .about("read WAV from stdin, decode cassette audio, write to stdout"),
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("analyze")
+            .arg(
+                Arg::new("type").short('t').long("type").help("type of the file").value_name("TYPE")
+                    .required(true)
+                    .value_parser(["atok"]),
+            )
+            .arg(
+                Arg::new("addr").short('a').long("addr").help("address of tokenized code").value_name("ADDRESS")
+                    .value_parser(value_parser!(u16))
+                    .required(false)
+                    .default_value("2049"),
+            )
+            .arg(
+                arg!(--himem <ADDRESS> "top of memory available to the program")
+                    .value_parser(value_parser!(u16))
+                    .required(false)
+                    .default_value("38400"),
+            )
+            .arg(
+                arg!(--top <COUNT> "number of largest lines to list")
+                    .value_parser(value_parser!(usize))
+                    .required(false)
+                    .default_value("5"),
+            )
+            .arg(indent_arg.clone())
+            .about("read tokens from stdin, write size statistics as a JSON string to stdout")
+            .after_help("Lines whose listing would not fit the 239 character input buffer are listed under `long_lines`.
+The variable table size is an estimate, string contents are not counted."),
     );
     main_cmd = main_cmd.subcommand(
         Command::new("asm")
@@ -392,10 +988,28 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
                     .required(false)
             )
             .arg(
-                Arg::new("literals").long("literals").help("assign values to disassembled hex labels").action(ArgAction::SetTrue)
+                Arg::new("literals").long("literals").help("assign values to disassembled hex labels (spot assembly)").action(ArgAction::SetTrue)
+            )
+            .arg(
+                Arg::new("link").long("link").help("link REL files instead of assembling").value_name("PATHS")
+                    .num_args(1..).conflicts_with("literals")
+            )
+            .arg(
+                Arg::new("org").short('o').long("org").help("starting address of linked code").value_name("ADDRESS")
+                    .requires("link").default_value("32768")
+            )
+            .arg(
+                Arg::new("sweet16").long("sweet16").help("accept SWEET16 operations").action(ArgAction::SetTrue)
+            )
+            .arg(
+                Arg::new("undocumented").long("undocumented").help("accept undocumented 6502 operations").action(ArgAction::SetTrue)
             )
             .about("read from stdin, assemble, write to stdout")
-            .after_help("At present this is limited, it will error out if program counter or symbol value cannot be determined.")
+            .after_help("Includes (PUT) are not supported yet.
+If the source begins with REL the output is a REL file, use `--link` to combine REL files into a program.
+With `--literals` the spot assembler is used, which will error out if program counter or symbol value cannot be determined.
+SWEET16 is assembled after `JSR $F689` up to `RTN`, registers are written R0 through R15, e.g., `SET R1,$0800`, `LD @R1`.
+Undocumented operations are SLO, RLA, SRE, RRA, SAX, LAX, DCP, ISC, ANC, ALR, ARR, SBX, LAS, they require the 6502 target.")
     );
     main_cmd = main_cmd.subcommand(
         Command::new("dasm")
@@ -414,7 +1028,44 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
                 Arg::new("org").short('o').long("org").help("starting address").value_name("ADDRESS")
                     .required(true)
             )
+            .arg(
+                Arg::new("syntax").long("syntax").help("assembler syntax of the output").value_name("NAME")
+                    .required(false)
+                    .value_parser(["merlin","scasm","ca65","acme"])
+                    .default_value("merlin")
+            )
+            .arg(
+                Arg::new("symbols").long("symbols").help("annotate well known addresses").value_name("SETS")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("sweet16").long("sweet16").help("decode SWEET16 after JSR $F689").action(ArgAction::SetTrue)
+            )
+            .arg(
+                Arg::new("undocumented").long("undocumented").help("decode undocumented 6502 operations").action(ArgAction::SetTrue)
+            )
             .about("read from stdin, disassemble, write to stdout")
+            .after_help("SETS is a comma separated list drawn from appleii, prodos, dos33, iigs.
+Undocumented operations are only decoded for the 6502 target.
+Syntaxes other than Merlin get SWEET16 and undocumented operations as bytes, with the Merlin form as a comment.")
+    );
+    main_cmd = main_cmd.subcommand(
+        Command::new("omf")
+            .arg(
+                Arg::new("seg").short('s').long("seg").help("segment to write to stdout").value_name("NUM")
+                    .value_parser(value_parser!(u16))
+                    .required(false)
+            )
+            .arg(
+                Arg::new("org").short('o').long("org").help("relocate the segment to this address").value_name("ADDRESS")
+                    .required(false)
+                    .requires("seg")
+            )
+            .arg(indent_arg.clone().conflicts_with("seg"))
+            .about("read a GS/OS load file from stdin, list its segments or write one to stdout")
+            .after_help("Without `--seg` the segments are listed as a JSON string.
+With `--org` the segment is placed at ADDRESS and the others follow it, so that every reference can be patched.
+ADDRESS is decimal, or hex with `$` or `0x` prefix.  The result can be piped to `dasm -p 65816`.")
     );
     main_cmd = main_cmd.subcommand(
         Command::new("glob")
@@ -423,17 +1074,44 @@ Detokenize from image: `a2kit get -f prog -t atok -d myimg.dsk | a2kit detokeniz
                 Arg::new("file").short('f').long("file").help("glob pattern to match against").value_name("PATTERN")
                     .required(true),
             )
+            .arg(Arg::new("case").long("case-sensitive").help("match letter case exactly").action(ArgAction::SetTrue))
+            .arg(Arg::new("fold").long("fold").help("accented letters match plain letters, spaces and underscores match periods").action(ArgAction::SetTrue))
             .arg(indent_arg.clone())
+            .arg(workspace_arg.clone())
             .about("write JSON list of matching paths to stdout")
-            .after_help("the pattern may need to be quoted depending on shell\n\n".to_string() + IN_HELP)
+            .after_help("the pattern may need to be quoted depending on shell, `*` and `?` never match `/`, use `**` to match across directories\n\n".to_string() + IN_HELP + "\n\n" + WORKSPACE_HELP)
+    );
+    // every command that opens an image can select a volume, unless it already has a volume argument
+    let vol_arg = Arg::new("hd-volume").long("volume").help("volume of a multi-volume hard disk image, counting from 1")
+        .value_name("N").value_parser(value_parser!(usize)).requires("dimg");
+    let names: Vec<String> = main_cmd.get_subcommands()
+        .filter(|sub| sub.get_arguments().any(|a| a.get_id()=="dimg") && !sub.get_arguments().any(|a| a.get_long()==Some("volume")))
+        .map(|sub| sub.get_name().to_string())
+        .collect();
+    for name in names {
+        main_cmd = main_cmd.mut_subcommand(name,|sub| sub.arg(vol_arg.clone()));
+    }
+    main_cmd = main_cmd.subcommand(
+        Command::new("volumes")
+            .arg(dimg_arg_req)
+            .arg(indent_arg.clone())
+            .about("list the volumes in a multi-volume hard disk image as a JSON string")
+            .after_help("Images of CFFA3000 or MicroDrive drives hold a ProDOS volume in each 32 MB partition.
+Other commands select one with `--volume`."),
     );
     main_cmd = main_cmd.subcommand(
         Command::new("completions")
             .arg(
                 Arg::new("shell").short('s').long("shell").help("shell target").value_name("NAME")
-                    .required(true)
+                    .required_unless_present("image")
                     .value_parser(["bash","elv","fish","ps1","zsh"])
             )
+            .arg(
+                Arg::new("dynamic").long("dynamic").help("complete paths inside disk images by calling a2kit (bash, fish, zsh)")
+                    .action(ArgAction::SetTrue)
+            )
+            .arg(Arg::new("image").long("image").value_name("PATH").hide(true))
+            .arg(Arg::new("prefix").long("prefix").value_name("PREFIX").hide(true).allow_hyphen_values(true))
             .about("write completions script to stdout for the specified shell")
     );
     return main_cmd;
@@ -0,0 +1,446 @@
+//! # Interactive browser
+//!
+//! Two panes, the host file system on the left and the disk image on the right.
+//! Files can be copied either way, and files in the image can be deleted or renamed.
+//! Any file can be viewed as hex, text, Applesoft, or Integer BASIC.
+//! The image is saved after every change, as with the JSON-RPC server.
+//! Changes can be undone, the image journals what each of the last few changes overwrote.
+//!
+//! Files copied into the image are text if they are printable ASCII, otherwise binary at $0800.
+//! Files copied to the host are unpacked according to their type in the image.
+//! Existing host files are never replaced.
+
+use std::path::{Path,PathBuf};
+use ratatui::crossterm::event::{self,Event,KeyCode,KeyEventKind};
+use ratatui::layout::{Constraint,Layout};
+use ratatui::style::{Modifier,Style};
+use ratatui::widgets::{Block,Borders,List,ListItem,ListState,Paragraph};
+use ratatui::Frame;
+use super::{ItemType,CommandError};
+use crate::fs::{DiskFS,UnpackedData};
+use crate::img::Snapshot;
+use crate::{STDRESULT,DYNERR};
+
+const RCH: &str = "unreachable was reached";
+const LIST_KEYS: &str = "Tab switch  Enter open  Bksp up  c copy  d delete  r rename  u undo  v view  q quit";
+/// how many changes can be undone
+const UNDO_LEVELS: usize = 16;
+const VIEW_KEYS: &str = "h hex  t text  a Applesoft  i Integer  arrows/PgUp/PgDn scroll  Esc close";
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Side {
+    Host,
+    Image
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum ViewAs {
+    Hex,
+    Text,
+    Applesoft,
+    Integer
+}
+
+/// Entry in one of the panes
+#[derive(Clone,PartialEq,Debug)]
+pub struct Item {
+    pub name: String,
+    pub is_dir: bool
+}
+
+enum Mode {
+    List,
+    View { title: String, side: Side, name: String, lines: Vec<String>, scroll: u16 },
+    Rename(String),
+    ConfirmDelete
+}
+
+fn is_printable(dat: &[u8]) -> bool {
+    dat.iter().all(|c| *c == b'\n' || *c == b'\r' || *c == b'\t' || (32..127).contains(c))
+}
+
+fn view_bytes(dat: &[u8],how: ViewAs) -> Result<Vec<String>,DYNERR> {
+    let txt = match how {
+        ViewAs::Hex => return Ok(crate::block_lines(0,dat)),
+        ViewAs::Text => String::from_utf8_lossy(dat).to_string(),
+        ViewAs::Applesoft => crate::lang::applesoft::tokenizer::Tokenizer::new().detokenize(dat)?,
+        ViewAs::Integer => crate::lang::integer::tokenizer::Tokenizer::new().detokenize(dat)?
+    };
+    Ok(txt.lines().map(|l| l.to_string()).collect())
+}
+
+pub struct Browser {
+    disk: Box<dyn DiskFS>,
+    img_path: String,
+    host_dir: PathBuf,
+    /// directory in the image, empty or ending with `/`
+    img_dir: String,
+    host_items: Vec<Item>,
+    img_items: Vec<Item>,
+    host_state: ListState,
+    img_state: ListState,
+    side: Side,
+    mode: Mode,
+    status: String,
+    done: bool,
+    undo: Vec<Snapshot>,
+    save_options: crate::SaveOptions
+}
+
+impl Browser {
+    pub fn new(img_path: &str,host_dir: &Path) -> Result<Self,DYNERR> {
+        Self::with_options(img_path,host_dir,&crate::LoadOptions::default(),crate::SaveOptions::default())
+    }
+    /// Browse the image loaded with `load`, with `opts` used whenever the image is saved,
+    /// the selected volume, if any, should be the same in both
+    pub fn with_options(img_path: &str,host_dir: &Path,load: &crate::LoadOptions,opts: crate::SaveOptions) -> Result<Self,DYNERR> {
+        let mut ans = Self {
+            disk: crate::create_fs_with(img_path,load)?,
+            img_path: img_path.to_string(),
+            host_dir: host_dir.canonicalize()?,
+            img_dir: String::new(),
+            host_items: Vec::new(),
+            img_items: Vec::new(),
+            host_state: ListState::default(),
+            img_state: ListState::default(),
+            side: Side::Host,
+            mode: Mode::List,
+            status: LIST_KEYS.to_string(),
+            done: false,
+            undo: Vec::new(),
+            save_options: opts
+        };
+        ans.refresh()?;
+        Ok(ans)
+    }
+    pub fn host_items(&self) -> &[Item] {
+        &self.host_items
+    }
+    pub fn image_items(&self) -> &[Item] {
+        &self.img_items
+    }
+    /// Read both directories again, keeping the selections where possible
+    pub fn refresh(&mut self) -> STDRESULT {
+        let mut items = Vec::new();
+        for entry in std::fs::read_dir(&self.host_dir)?.flatten() {
+            items.push(Item {
+                name: entry.file_name().to_string_lossy().to_string(),
+                is_dir: entry.path().is_dir()
+            });
+        }
+        items.sort_by(|a,b| b.is_dir.cmp(&a.is_dir).then(a.name.cmp(&b.name)));
+        if self.host_dir.parent().is_some() {
+            items.insert(0,Item { name: "..".to_string(), is_dir: true });
+        }
+        self.host_items = items;
+        let mut items = Vec::new();
+        for path in super::completions::path_candidates(&mut self.disk,&self.img_dir)? {
+            let name = path[self.img_dir.len()..].to_string();
+            items.push(Item { is_dir: name.ends_with('/'), name: name.trim_end_matches('/').to_string() });
+        }
+        items.sort_by(|a,b| b.is_dir.cmp(&a.is_dir).then(a.name.cmp(&b.name)));
+        if !self.img_dir.is_empty() {
+            items.insert(0,Item { name: "..".to_string(), is_dir: true });
+        }
+        self.img_items = items;
+        for (state,len) in [(&mut self.host_state,self.host_items.len()),(&mut self.img_state,self.img_items.len())] {
+            match (state.selected(),len) {
+                (_,0) => state.select(None),
+                (Some(i),_) if i < len => {},
+                _ => state.select(Some(0))
+            }
+        }
+        Ok(())
+    }
+    /// Enter a directory, `..` goes up
+    pub fn open_dir(&mut self,side: Side,name: &str) -> STDRESULT {
+        match (side,name) {
+            (Side::Host,"..") => if let Some(parent) = self.host_dir.parent() {
+                self.host_dir = parent.to_path_buf();
+            },
+            (Side::Host,_) => self.host_dir = self.host_dir.join(name),
+            (Side::Image,"..") => {
+                let trimmed = self.img_dir.trim_end_matches('/');
+                self.img_dir = match trimmed.rfind('/') {
+                    Some(i) => trimmed[0..i+1].to_string(),
+                    None => String::new()
+                };
+            },
+            (Side::Image,_) => self.img_dir = [&self.img_dir,name,"/"].concat()
+        }
+        match side {
+            Side::Host => self.host_state.select(Some(0)),
+            Side::Image => self.img_state.select(Some(0))
+        }
+        self.refresh()
+    }
+    /// Apply `f` to the image and save it, if `f` fails the image is rolled back
+    fn change(&mut self,f: impl FnOnce(&mut Box<dyn DiskFS>) -> STDRESULT) -> STDRESULT {
+        let before = self.disk.snapshot();
+        if let Err(e) = f(&mut self.disk) {
+            self.disk.rollback(&before)?;
+            return Err(e);
+        }
+        if self.undo.len() == UNDO_LEVELS {
+            self.undo.remove(0);
+        }
+        self.undo.push(before);
+        crate::save_img_with(&mut self.disk,&self.img_path,&self.save_options)?;
+        self.refresh()
+    }
+    /// Undo the last change to the image, returns false if there is nothing to undo
+    pub fn undo(&mut self) -> Result<bool,DYNERR> {
+        let snap = match self.undo.pop() {
+            Some(snap) => snap,
+            None => return Ok(false)
+        };
+        self.disk.rollback(&snap)?;
+        crate::save_img_with(&mut self.disk,&self.img_path,&self.save_options)?;
+        self.refresh()?;
+        Ok(true)
+    }
+    /// Copy a file from the host directory to the image directory
+    pub fn copy_to_image(&mut self,name: &str) -> STDRESULT {
+        let dat = std::fs::read(self.host_dir.join(name))?;
+        let path = [&self.img_dir,name].concat();
+        self.change(|disk| match is_printable(&dat) {
+            true => super::build::put_item(disk,&path,ItemType::Text,&dat,None),
+            false => super::build::put_item(disk,&path,ItemType::Binary,&dat,Some(0x800))
+        })
+    }
+    /// Copy a file from the image directory to the host directory
+    pub fn copy_to_host(&mut self,name: &str) -> STDRESULT {
+        let dest = self.host_dir.join(name);
+        if dest.exists() {
+            log::error!("{} already exists",dest.display());
+            return Err(Box::new(CommandError::InvalidCommand));
+        }
+        let fimg = self.disk.get(&[&self.img_dir,name].concat())?;
+        let dat = match fimg.unpack()? {
+            UnpackedData::Text(txt) => txt.into_bytes(),
+            UnpackedData::Binary(dat) => dat,
+            UnpackedData::Records(recs) => recs.to_json(Some(2)).into_bytes()
+        };
+        std::fs::write(dest,dat)?;
+        self.refresh()
+    }
+    pub fn delete(&mut self,name: &str) -> STDRESULT {
+        let path = [&self.img_dir,name].concat();
+        self.change(|disk| disk.delete(&path))
+    }
+    pub fn rename(&mut self,name: &str,new_name: &str) -> STDRESULT {
+        let path = [&self.img_dir,name].concat();
+        self.change(|disk| disk.rename(&path,new_name))
+    }
+    /// Lines to show for the file `name` on the given side
+    pub fn view(&mut self,side: Side,name: &str,how: ViewAs) -> Result<Vec<String>,DYNERR> {
+        if side == Side::Host {
+            return view_bytes(&std::fs::read(self.host_dir.join(name))?,how);
+        }
+        let fimg = self.disk.get(&[&self.img_dir,name].concat())?;
+        match how {
+            ViewAs::Hex => {
+                let dat = match fimg.unpack_bin() {
+                    Ok(dat) => dat,
+                    Err(_) => fimg.unpack_raw(true)?
+                };
+                view_bytes(&dat,how)
+            },
+            ViewAs::Text => Ok(fimg.unpack_txt()?.lines().map(|l| l.to_string()).collect()),
+            ViewAs::Applesoft | ViewAs::Integer => view_bytes(&fimg.unpack_tok()?,how)
+        }
+    }
+    fn selected(&self) -> Option<Item> {
+        match self.side {
+            Side::Host => self.host_state.selected().and_then(|i| self.host_items.get(i).cloned()),
+            Side::Image => self.img_state.selected().and_then(|i| self.img_items.get(i).cloned())
+        }
+    }
+    fn open_view(&mut self,side: Side,name: &str,how: ViewAs) -> STDRESULT {
+        let lines = self.view(side,name,how)?;
+        self.mode = Mode::View { title: format!("{} ({:?})",name,how), side, name: name.to_string(), lines, scroll: 0 };
+        self.status = VIEW_KEYS.to_string();
+        Ok(())
+    }
+    fn list_key(&mut self,code: KeyCode) -> STDRESULT {
+        let (state,len) = match self.side {
+            Side::Host => (&mut self.host_state,self.host_items.len()),
+            Side::Image => (&mut self.img_state,self.img_items.len())
+        };
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => self.done = true,
+            KeyCode::Tab | KeyCode::Left | KeyCode::Right => self.side = match self.side {
+                Side::Host => Side::Image,
+                Side::Image => Side::Host
+            },
+            KeyCode::Up => state.select(state.selected().map(|i| i.saturating_sub(1))),
+            KeyCode::Down => state.select(state.selected().map(|i| usize::min(i+1,len.saturating_sub(1)))),
+            KeyCode::Backspace => self.open_dir(self.side,"..")?,
+            KeyCode::Enter | KeyCode::Char('v') => match self.selected() {
+                Some(item) if item.is_dir => self.open_dir(self.side,&item.name)?,
+                Some(item) => self.open_view(self.side,&item.name,ViewAs::Hex)?,
+                None => {}
+            },
+            KeyCode::Char('c') => match (self.selected(),self.side) {
+                (Some(item),_) if item.is_dir => self.status = "only files can be copied".to_string(),
+                (Some(item),Side::Host) => {
+                    self.copy_to_image(&item.name)?;
+                    self.status = format!("copied {} to the image",item.name);
+                },
+                (Some(item),Side::Image) => {
+                    self.copy_to_host(&item.name)?;
+                    self.status = format!("copied {} to {}",item.name,self.host_dir.display());
+                },
+                (None,_) => {}
+            },
+            KeyCode::Char('u') => self.status = match self.undo()? {
+                true => "undid the last change".to_string(),
+                false => "nothing to undo".to_string()
+            },
+            KeyCode::Char('d') | KeyCode::Char('r') if self.side == Side::Host => {
+                self.status = "delete and rename are only available in the image".to_string();
+            },
+            KeyCode::Char('d') => if let Some(item) = self.selected() {
+                self.mode = Mode::ConfirmDelete;
+                self.status = format!("delete {}? (y/n)",item.name);
+            },
+            KeyCode::Char('r') => if let Some(item) = self.selected() {
+                self.mode = Mode::Rename(String::new());
+                self.status = format!("rename {} to: ",item.name);
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+    fn handle_key(&mut self,code: KeyCode) -> STDRESULT {
+        let item = self.selected();
+        match &mut self.mode {
+            Mode::List => self.list_key(code)?,
+            Mode::View { side, name, lines, scroll, .. } => match code {
+                KeyCode::Esc | KeyCode::Char('q') => {
+                    self.mode = Mode::List;
+                    self.status = LIST_KEYS.to_string();
+                },
+                KeyCode::Up => *scroll = scroll.saturating_sub(1),
+                KeyCode::Down => *scroll = u16::min(*scroll + 1,lines.len().saturating_sub(1) as u16),
+                KeyCode::PageUp => *scroll = scroll.saturating_sub(20),
+                KeyCode::PageDown => *scroll = u16::min(*scroll + 20,lines.len().saturating_sub(1) as u16),
+                KeyCode::Char(c) => {
+                    let how = match c {
+                        'h' => ViewAs::Hex,
+                        't' => ViewAs::Text,
+                        'a' => ViewAs::Applesoft,
+                        'i' => ViewAs::Integer,
+                        _ => return Ok(())
+                    };
+                    let (side,name) = (*side,name.clone());
+                    self.open_view(side,&name,how)?;
+                },
+                _ => {}
+            },
+            Mode::Rename(input) => match code {
+                KeyCode::Esc => {
+                    self.mode = Mode::List;
+                    self.status = LIST_KEYS.to_string();
+                },
+                KeyCode::Backspace => {
+                    input.pop();
+                },
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Enter => {
+                    let new_name = input.clone();
+                    self.mode = Mode::List;
+                    if let Some(item) = item {
+                        self.rename(&item.name,&new_name)?;
+                        self.status = format!("renamed {} to {}",item.name,new_name);
+                    }
+                },
+                _ => {}
+            },
+            Mode::ConfirmDelete => {
+                self.mode = Mode::List;
+                self.status = LIST_KEYS.to_string();
+                if let (KeyCode::Char('y'),Some(item)) = (code,item) {
+                    self.delete(&item.name)?;
+                    self.status = format!("deleted {}",item.name);
+                }
+            }
+        }
+        Ok(())
+    }
+    fn draw(&mut self,frame: &mut Frame) {
+        let [main,status] = Layout::vertical([Constraint::Min(3),Constraint::Length(1)]).areas(frame.area());
+        let status_txt = match &self.mode {
+            Mode::Rename(input) => [self.status.as_str(),input].concat(),
+            _ => self.status.clone()
+        };
+        frame.render_widget(Paragraph::new(status_txt),status);
+        if let Mode::View { title, lines, scroll, .. } = &self.mode {
+            let txt = lines.join("\n");
+            let para = Paragraph::new(txt).scroll((*scroll,0)).block(Block::default().borders(Borders::ALL).title(title.as_str()));
+            frame.render_widget(para,main);
+            return;
+        }
+        let [left,right] = Layout::horizontal([Constraint::Percentage(50),Constraint::Percentage(50)]).areas(main);
+        let panes = [
+            (left,Side::Host,self.host_dir.display().to_string(),&self.host_items,&mut self.host_state),
+            (right,Side::Image,[&self.img_path,":/",&self.img_dir].concat(),&self.img_items,&mut self.img_state)
+        ];
+        for (area,side,title,items,state) in panes {
+            let rows: Vec<ListItem> = items.iter().map(|x| match x.is_dir {
+                true => ListItem::new(x.name.clone() + "/"),
+                false => ListItem::new(x.name.clone())
+            }).collect();
+            let mut block = Block::default().borders(Borders::ALL).title(title);
+            let mut highlight = Style::default();
+            if side == self.side {
+                block = block.border_style(Style::default().add_modifier(Modifier::BOLD));
+                highlight = highlight.add_modifier(Modifier::REVERSED);
+            }
+            frame.render_stateful_widget(List::new(rows).block(block).highlight_style(highlight),area,state);
+        }
+    }
+    /// Run the browser until the user quits
+    pub fn run(&mut self) -> STDRESULT {
+        // log messages would corrupt the screen, errors are shown on the status line instead
+        let log_level = log::max_level();
+        log::set_max_level(log::LevelFilter::Off);
+        let mut terminal = ratatui::init();
+        let mut ans = Ok(());
+        while !self.done {
+            if let Err(e) = terminal.draw(|frame| self.draw(frame)) {
+                ans = Err(Box::new(e) as DYNERR);
+                break;
+            }
+            match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                    if let Err(e) = self.handle_key(key.code) {
+                        self.status = format!("error: {}",e);
+                        if self.refresh().is_err() {
+                            self.status += ", could not refresh";
+                        }
+                    }
+                },
+                Ok(_) => {},
+                Err(e) => {
+                    ans = Err(Box::new(e) as DYNERR);
+                    break;
+                }
+            }
+        }
+        ratatui::restore();
+        log::set_max_level(log_level);
+        ans
+    }
+}
+
+pub fn browse(cmd: &clap::ArgMatches,opts: &crate::SaveOptions) -> STDRESULT {
+    if !atty::is(atty::Stream::Stdout) || !atty::is(atty::Stream::Stdin) {
+        log::error!("browse needs an interactive terminal");
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    let img_path = cmd.get_one::<String>("dimg").expect(RCH);
+    let host_dir = cmd.get_one::<String>("host").expect(RCH);
+    let mut browser = Browser::with_options(img_path,Path::new(host_dir),&crate::LoadOptions::from_matches(cmd)?,opts.clone())?;
+    browser.run()
+}
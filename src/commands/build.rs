@@ -0,0 +1,418 @@
+//! # Disk builds
+//!
+//! A build manifest is a JSON file describing a disk image and the host files that go on it:
+//! ```json
+//! {
+//!     "image": { "path": "mydisk.woz", "type": "woz2", "os": "prodos", "kind": "5.25in", "volume": "mydisk" },
+//!     "files": [
+//!         { "source": "hello.bas", "path": "hello", "type": "atxt", "addr": 2049 },
+//!         { "source": "prog.S", "path": "prog", "type": "asm" },
+//!         { "source": "table.bin", "path": "table", "type": "bin", "addr": 768 }
+//!     ],
+//!     "post": [
+//!         { "rename": "hello", "name": "startup" },
+//!         { "lock": "startup" }
+//!     ]
+//! }
+//! ```
+//! Relative paths are relative to the manifest.  The file types are those of `put`, plus source types
+//! that are transformed first: `atxt` and `itxt` are tokenized, `mtxt` is encoded, and `asm` is assembled
+//! to a binary, with the load address taken from `addr`, or else the first `ORG`.
+//! Files are put in the order listed.  A file can be given an `at_block` key, in which case
+//! it starts at that block, or the next free block after it (ProDOS only).
+//! The optional `post` steps run in order after all the files are put.
+//! Only JSON is accepted, other formats can be converted to JSON beforehand.
+//!
+//! The `Builder` keeps the disk in memory so that only files that changed need to be put again.
+//! If there are post steps, any change causes a full build, since the steps may not be repeatable.
+
+use std::collections::HashMap;
+use std::path::{Path,PathBuf};
+use std::str::FromStr;
+use std::time::{Duration,SystemTime};
+use log::{error,info};
+use super::{ItemType,CommandError};
+use crate::fs::DiskFS;
+use crate::img::{DiskImageType,DiskKind};
+use crate::lang;
+use crate::lang::server::Analysis;
+use crate::{STDRESULT,DYNERR};
+
+/// Description of the disk image to build
+#[derive(Clone,Debug,PartialEq)]
+pub struct ImageSpec {
+    pub path: PathBuf,
+    pub typ: String,
+    pub os: String,
+    pub kind: String,
+    pub volume: Option<String>,
+    pub wrap: Option<String>,
+    pub bootable: bool
+}
+
+/// Description of one file to put on the disk image
+#[derive(Clone,Debug,PartialEq)]
+pub struct FileSpec {
+    pub source: PathBuf,
+    pub path: String,
+    pub typ: String,
+    pub addr: Option<usize>,
+    pub at_block: Option<usize>
+}
+
+/// Operation to perform after all files are put
+#[derive(Clone,Debug,PartialEq)]
+pub enum Step {
+    Lock(String),
+    /// (path,new name)
+    Rename(String,String)
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Manifest {
+    pub image: ImageSpec,
+    pub files: Vec<FileSpec>,
+    pub post: Vec<Step>
+}
+
+fn get_str(obj: &json::JsonValue, key: &str, ctx: &str) -> Result<String,DYNERR> {
+    match obj[key].as_str() {
+        Some(s) => Ok(s.to_string()),
+        None => {
+            error!("{} is missing string `{}`",ctx,key);
+            Err(Box::new(CommandError::KeyNotFound))
+        }
+    }
+}
+
+fn get_opt_str(obj: &json::JsonValue, key: &str) -> Option<String> {
+    obj[key].as_str().map(|s| s.to_string())
+}
+
+impl Manifest {
+    /// Parse the manifest, relative paths are joined to `base`
+    pub fn from_json(json_str: &str, base: &Path) -> Result<Self,DYNERR> {
+        let root = json::parse(json_str)?;
+        let img = &root["image"];
+        if !img.is_object() {
+            error!("manifest is missing the `image` object");
+            return Err(Box::new(CommandError::KeyNotFound));
+        }
+        let image = ImageSpec {
+            path: base.join(get_str(img,"path","image")?),
+            typ: get_str(img,"type","image")?,
+            os: get_str(img,"os","image")?,
+            kind: get_opt_str(img,"kind").unwrap_or("5.25in".to_string()),
+            volume: match &img["volume"] {
+                v if v.is_number() => Some(v.to_string()),
+                v => v.as_str().map(|s| s.to_string())
+            },
+            wrap: get_opt_str(img,"wrap"),
+            bootable: img["bootable"].as_bool().unwrap_or(false)
+        };
+        let mut files = Vec::new();
+        for f in root["files"].members() {
+            files.push(FileSpec {
+                source: base.join(get_str(f,"source","file")?),
+                path: get_str(f,"path","file")?,
+                typ: get_str(f,"type","file")?,
+                addr: f["addr"].as_usize(),
+                at_block: f["at_block"].as_usize()
+            });
+        }
+        let mut post = Vec::new();
+        for step in root["post"].members() {
+            if let Some(path) = step["lock"].as_str() {
+                post.push(Step::Lock(path.to_string()));
+            } else if let Some(path) = step["rename"].as_str() {
+                post.push(Step::Rename(path.to_string(),get_str(step,"name","rename step")?));
+            } else {
+                error!("post step should have `lock` or `rename`");
+                return Err(Box::new(CommandError::UnknownItemType));
+            }
+        }
+        Ok(Self { image, files, post })
+    }
+    pub fn from_file(path: &Path) -> Result<Self,DYNERR> {
+        let json_str = std::fs::read_to_string(path)?;
+        let base = match path.parent() {
+            Some(p) => p.to_path_buf(),
+            None => PathBuf::new()
+        };
+        Self::from_json(&json_str, &base)
+    }
+}
+
+/// Text of a source file, `name` is used for error messages
+fn text_from(dat: Vec<u8>, name: &str) -> Result<String,DYNERR> {
+    let mut program = String::from_utf8(dat)?;
+    if program.is_empty() {
+        error!("{} is empty",name);
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    if !program.ends_with('\n') {
+        program.push('\n');
+    }
+    Ok(program)
+}
+
+fn read_text(path: &Path) -> Result<String,DYNERR> {
+    text_from(std::fs::read(path)?, &path.display().to_string())
+}
+
+/// Assemble Merlin source, returns (load address,object code).  Sources are searched for in the directory of `path`.
+fn assemble(path: &Path, addr: Option<usize>) -> Result<(usize,Vec<u8>),DYNERR> {
+    let program = read_text(path)?;
+    let mut analyzer = lang::merlin::diagnostics::Analyzer::new();
+    let doc = lang::Document::from_string(program,0);
+    if let Some(dir) = std::fs::canonicalize(path)?.parent() {
+        if let Ok(uri) = lsp_types::Url::from_directory_path(dir) {
+            analyzer.init_workspace(vec![uri],vec![doc.clone()])?;
+        }
+    }
+    analyzer.analyze(&doc)?;
+    for diag in analyzer.get_diags(&doc) {
+        lang::eprint_diagnostic(&diag,&doc.text);
+    }
+    if analyzer.err_warn_info_counts()[0] > 0 {
+        error!("{} has errors",path.display());
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    let org = match (addr,doc.text.lines().find_map(org_operand)) {
+        (Some(a),_) => a,
+        (None,Some(a)) => a,
+        (None,None) => {
+            error!("{} needs `addr` or `ORG`",path.display());
+            return Err(Box::new(CommandError::InvalidCommand));
+        }
+    };
+    let mut asm = lang::merlin::assembly::Assembler::new();
+    asm.use_shared_symbols(std::sync::Arc::new(analyzer.get_symbols()));
+    Ok((org,asm.assemble(&doc.text, Some(org))?))
+}
+
+/// Value of a literal `ORG` operand in a line, if any
+fn org_operand(line: &str) -> Option<usize> {
+    let mut cols = line.split_whitespace();
+    if !line.starts_with(char::is_whitespace) {
+        cols.next();
+    }
+    match (cols.next(),cols.next()) {
+        (Some(op),Some(val)) if op.eq_ignore_ascii_case("ORG") => match val.strip_prefix('$') {
+            Some(hex) => usize::from_str_radix(hex,16).ok(),
+            None => usize::from_str(val).ok()
+        },
+        _ => None
+    }
+}
+
+/// Transform source data of type `typ` as needed, returns the type, data, and load address to put.
+/// The source types `atxt`, `itxt`, and `mtxt` are transformed, other types are passed through.
+/// Assembly is not handled here, since it depends on the source's location.
+pub fn pack_source(typ: &str, dat: Vec<u8>, addr: Option<usize>, name: &str) -> Result<(ItemType,Vec<u8>,Option<usize>),DYNERR> {
+    Ok(match typ {
+        "atxt" => {
+            let mut program = text_from(dat,name)?;
+            if lang::labels::has_labels(&program) {
+                program = lang::labels::resolve(&program)?.0;
+            }
+            lang::verify_str(tree_sitter_applesoft::language(),&program)?;
+            let addr = addr.unwrap_or(2049);
+            let mut tokenizer = lang::applesoft::tokenizer::Tokenizer::new();
+            (ItemType::ApplesoftTokens,tokenizer.tokenize_numbered(&program,addr as u16)?,None)
+        },
+        "itxt" => {
+            let mut program = text_from(dat,name)?;
+            if lang::labels::has_labels(&program) {
+                program = lang::labels::resolve(&program)?.0;
+            }
+            lang::verify_str(tree_sitter_integerbasic::language(),&program)?;
+            let mut tokenizer = lang::integer::tokenizer::Tokenizer::new();
+            (ItemType::IntegerTokens,tokenizer.tokenize_numbered(program)?,None)
+        },
+        "mtxt" => {
+            let program = text_from(dat,name)?;
+            lang::verify_str(tree_sitter_merlin6502::language(),&program)?;
+            let mut tokenizer = lang::merlin::tokenizer::Tokenizer::new();
+            (ItemType::MerlinTokens,tokenizer.tokenize(program)?,None)
+        },
+        s => (ItemType::from_str(s)?,dat,addr)
+    })
+}
+
+/// Put data on the disk, replacing any existing file
+pub fn put_item(disk: &mut Box<dyn DiskFS>, path: &str, typ: ItemType, dat: &[u8], addr: Option<usize>) -> STDRESULT {
+    let mut fimg = disk.new_fimg(None, true, path)?;
+    super::put::pack_primitive(&mut fimg, dat, addr, typ)?;
+    // the old file is restored if the new one cannot be written
+    disk.put_if_exists(&fimg,crate::fs::IfExists::Overwrite)?;
+    Ok(())
+}
+
+/// Transform the source file as needed and put it on the disk, replacing any existing file
+pub fn put_file(disk: &mut Box<dyn DiskFS>, spec: &FileSpec) -> STDRESULT {
+    let (typ,dat,addr) = match spec.typ.as_str() {
+        "asm" => {
+            let (org,object) = assemble(&spec.source, spec.addr)?;
+            (ItemType::Binary,object,Some(org))
+        },
+        s => pack_source(s, std::fs::read(&spec.source)?, spec.addr, &spec.source.display().to_string())?
+    };
+    if spec.at_block.is_none() {
+        return put_item(disk, &spec.path, typ, &dat, addr);
+    }
+    disk.allocate_from(spec.at_block)?;
+    let result = put_item(disk, &spec.path, typ, &dat, addr);
+    disk.allocate_from(None)?;
+    result
+}
+
+fn run_step(disk: &mut Box<dyn DiskFS>, step: &Step) -> STDRESULT {
+    match step {
+        Step::Lock(path) => disk.lock(path),
+        Step::Rename(path,name) => disk.rename(path,name)
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Builds a disk image from a manifest, and keeps it in memory for incremental updates
+pub struct Builder {
+    manifest: Manifest,
+    disk: Option<Box<dyn DiskFS>>,
+    stamps: HashMap<PathBuf,Option<SystemTime>>,
+    load_options: crate::LoadOptions,
+    save_options: crate::SaveOptions
+}
+
+impl Builder {
+    pub fn new(manifest: Manifest) -> Self {
+        Self {
+            manifest,
+            disk: None,
+            stamps: HashMap::new(),
+            load_options: crate::LoadOptions::default(),
+            save_options: crate::SaveOptions::default()
+        }
+    }
+    pub fn set_load_options(&mut self, opts: crate::LoadOptions) {
+        self.load_options = opts;
+    }
+    pub fn set_save_options(&mut self, opts: crate::SaveOptions) {
+        self.save_options = opts;
+    }
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+    fn save(&mut self) -> STDRESULT {
+        let path = self.manifest.image.path.to_string_lossy().to_string();
+        match self.disk.as_mut() {
+            Some(disk) => crate::save_img_with(disk, &path, &self.save_options),
+            None => Err(Box::new(CommandError::InvalidCommand))
+        }
+    }
+    /// Create the disk image from scratch, put every file, run the post steps, and save it
+    pub fn build(&mut self) -> STDRESULT {
+        let spec = &self.manifest.image;
+        let dest = spec.path.to_string_lossy().to_string();
+        let img_typ = DiskImageType::from_str(&spec.typ)?;
+        let kind = DiskKind::from_str(&spec.kind)?;
+        let buf = super::mkdsk::create(&dest,&img_typ,kind,&spec.os,spec.volume.as_ref(),spec.bootable,spec.wrap.as_ref(),&self.load_options)?;
+        let mut disk = crate::create_fs_from_bytestream_with(&buf, dest.split('.').next_back(), &self.load_options)?;
+        // stamp everything first, so that a failed build is not repeated until something changes
+        self.stamps.clear();
+        for file in &self.manifest.files {
+            self.stamps.insert(file.source.clone(), modified(&file.source));
+        }
+        for file in &self.manifest.files {
+            if let Err(e) = put_file(&mut disk, file) {
+                error!("could not put {}",file.source.display());
+                return Err(e);
+            }
+        }
+        for step in &self.manifest.post {
+            if let Err(e) = run_step(&mut disk, step) {
+                error!("post step {:?} failed",step);
+                return Err(e);
+            }
+        }
+        self.disk = Some(disk);
+        self.save()
+    }
+    /// Files whose sources changed since they were last put
+    pub fn changed(&self) -> Vec<FileSpec> {
+        self.manifest.files.iter().filter(|f| match self.stamps.get(&f.source) {
+            Some(t) => *t != modified(&f.source),
+            None => true
+        }).cloned().collect()
+    }
+    /// Put the files whose sources changed, and save the image if there were any.
+    /// If there is no image yet, or there are post steps, do a full build.  Returns the files that were put.
+    pub fn update(&mut self) -> Result<Vec<FileSpec>,DYNERR> {
+        let changed = self.changed();
+        if self.disk.is_none() || !self.manifest.post.is_empty() {
+            if !self.stamps.is_empty() && changed.is_empty() {
+                return Ok(changed);
+            }
+            self.build()?;
+            return Ok(self.manifest.files.clone());
+        }
+        if changed.is_empty() {
+            return Ok(changed);
+        }
+        for file in &changed {
+            self.stamps.insert(file.source.clone(), modified(&file.source));
+            if let Some(disk) = self.disk.as_mut() {
+                if let Err(e) = put_file(disk, file) {
+                    error!("could not put {}",file.source.display());
+                    return Err(e);
+                }
+            }
+        }
+        self.save()?;
+        Ok(changed)
+    }
+}
+
+/// Build the image described by the manifest at `path`, then keep it up to date, checking for changes every `interval`.
+/// A change to the manifest itself causes a full build.  Errors are reported and watching continues.  Never returns normally.
+pub fn watch(path: &Path, interval: Duration, load: &crate::LoadOptions, opts: &crate::SaveOptions) -> STDRESULT {
+    let mut builder = Builder::new(Manifest::from_file(path)?);
+    builder.set_load_options(load.clone());
+    builder.set_save_options(opts.clone());
+    let mut manifest_stamp = modified(path);
+    match builder.build() {
+        Ok(()) => eprintln!("built {}",builder.manifest().image.path.display()),
+        Err(e) => error!("build failed: {}",e)
+    }
+    loop {
+        std::thread::sleep(interval);
+        if modified(path) != manifest_stamp {
+            manifest_stamp = modified(path);
+            info!("manifest changed");
+            match Manifest::from_file(path) {
+                Ok(manifest) => {
+                    builder = Builder::new(manifest);
+                    builder.set_load_options(load.clone());
+                    builder.set_save_options(opts.clone());
+                    match builder.build() {
+                        Ok(()) => eprintln!("rebuilt {}",builder.manifest().image.path.display()),
+                        Err(e) => error!("build failed: {}",e)
+                    }
+                },
+                Err(e) => error!("manifest could not be read: {}",e)
+            }
+            continue;
+        }
+        match builder.update() {
+            Ok(files) => {
+                for f in files {
+                    eprintln!("updated {}",f.path);
+                }
+            },
+            Err(e) => error!("update failed: {}",e)
+        }
+    }
+}
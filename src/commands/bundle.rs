@@ -0,0 +1,72 @@
+//! # Bundle command
+//!
+//! Combines a tokenized Applesoft program from stdin with binary assets into one self-loading
+//! binary file, see `lang::applesoft::bundle`.  The result goes to stdout, or into a disk image
+//! with the load address set, so that `BRUN` installs and runs the program.
+
+use std::io::{Read,Write};
+use crate::lang::applesoft::bundle;
+use super::CommandError;
+use crate::STDRESULT;
+
+const RCH: &str = "unreachable was reached";
+
+/// Parse `ADDR:PATH`, the address is decimal
+fn parse_asset(spec: &str) -> Result<bundle::Asset,Box<dyn std::error::Error>> {
+    match spec.split_once(':') {
+        Some((addr,path)) => match addr.parse::<u16>() {
+            Ok(addr) => Ok(bundle::Asset { addr, dat: std::fs::read(path)? }),
+            Err(_) => {
+                log::error!("asset address {} should be decimal",addr);
+                Err(Box::new(CommandError::InvalidCommand))
+            }
+        },
+        None => {
+            log::error!("asset should be given as ADDR:PATH");
+            Err(Box::new(CommandError::InvalidCommand))
+        }
+    }
+}
+
+pub fn bundle(cmd: &clap::ArgMatches,opts: &crate::SaveOptions) -> STDRESULT {
+    if atty::is(atty::Stream::Stdin) {
+        log::error!("line entry is not supported for `bundle`, please pipe the tokenized program in");
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    let addr = match cmd.get_one::<String>("addr").expect(RCH).parse::<u16>() {
+        Ok(a) => a,
+        Err(_) => {
+            log::error!("bundle address should be decimal");
+            return Err(Box::new(CommandError::InvalidCommand));
+        }
+    };
+    let mut prog = Vec::new();
+    std::io::stdin().read_to_end(&mut prog)?;
+    if prog.is_empty() {
+        log::error!("bundle did not receive any data from previous node");
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    let mut assets = Vec::new();
+    if let Some(specs) = cmd.get_many::<String>("asset") {
+        for spec in specs {
+            assets.push(parse_asset(spec)?);
+        }
+    }
+    let dat = bundle::bundle(&prog,&assets,addr)?;
+    match (cmd.get_one::<String>("dimg"),cmd.get_one::<String>("file")) {
+        (Some(img_path),Some(path)) => {
+            let mut disk = crate::create_fs_with(img_path,&crate::LoadOptions::from_matches(cmd)?)?;
+            disk.bsave(path,&dat,Some(addr as usize),None)?;
+            eprintln!("bundle is {} bytes at {}",dat.len(),addr);
+            crate::save_img_with(&mut disk,img_path,opts)
+        },
+        (None,None) => {
+            std::io::stdout().write_all(&dat)?;
+            Ok(())
+        },
+        _ => {
+            log::error!("`--dimg` and `--file` go together");
+            Err(Box::new(CommandError::InvalidCommand))
+        }
+    }
+}
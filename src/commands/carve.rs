@@ -0,0 +1,86 @@
+//! # Carve files from a damaged image
+//!
+//! The scan is done by `fs::carve`, this module writes the report and, optionally, the files.
+//! Carved files are named by their order and content, e.g. `carved_003.bas`.  Applesoft is
+//! written as a listing unless `raw` is set, in which case the tokens are written.
+
+use std::path::Path;
+use super::CommandError;
+use crate::fs::carve::{Carved,Content};
+use crate::{STDRESULT,DYNERR};
+
+const RCH: &str = "unreachable was reached";
+
+fn file_name(idx: usize,carved: &Carved,raw: bool) -> String {
+    let ext = match (carved.content,raw) {
+        (Content::Applesoft,true) => "atok",
+        (Content::Applesoft,false) => "bas",
+        (Content::Text,_) => "txt",
+        (Content::Binary,_) | (Content::Data,_) => "bin"
+    };
+    format!("carved_{:03}.{}",idx,ext)
+}
+
+/// Write the carved files to `dir`, returning the names in the same order
+pub fn write_files(files: &[Carved],dir: &Path,raw: bool) -> Result<Vec<String>,DYNERR> {
+    let mut ans = Vec::new();
+    for (idx,carved) in files.iter().enumerate() {
+        let name = file_name(idx,carved,raw);
+        let dat = match (carved.content,raw) {
+            (Content::Applesoft,false) => crate::lang::applesoft::tokenizer::Tokenizer::new().detokenize(&carved.data)?.into_bytes(),
+            _ => carved.data.clone()
+        };
+        std::fs::write(dir.join(&name),dat)?;
+        ans.push(name);
+    }
+    Ok(ans)
+}
+
+/// Confidence report as a JSON string, `names` are the files that were written, if any
+pub fn to_json(files: &[Carved],names: Option<&[String]>,indent: Option<u16>) -> String {
+    let mut list = json::JsonValue::new_array();
+    for (idx,carved) in files.iter().enumerate() {
+        let mut obj = json::object! {
+            "evidence": carved.evidence.to_string(),
+            "location": carved.location.to_string(),
+            "content": carved.content.to_string(),
+            "bytes": carved.data.len(),
+            "confidence": (carved.confidence * 100.0).round() / 100.0
+        };
+        if let Some(addr) = carved.addr {
+            obj["addr"] = addr.into();
+        }
+        if let Some(names) = names {
+            obj["file"] = names[idx].as_str().into();
+        }
+        list.push(obj).expect(RCH);
+    }
+    let ans = json::object! {
+        "count": files.len(),
+        "files": list
+    };
+    match indent {
+        Some(spaces) => json::stringify_pretty(ans, spaces),
+        None => json::stringify(ans)
+    }
+}
+
+pub fn carve(cmd: &clap::ArgMatches) -> STDRESULT {
+    let mut img = crate::create_img_with(cmd.get_one::<String>("dimg").expect(RCH),&crate::LoadOptions::from_matches(cmd)?)?;
+    let min = *cmd.get_one::<f64>("min-confidence").expect(RCH);
+    let mut files = crate::fs::carve::carve(&mut img)?;
+    files.retain(|f| f.confidence >= min);
+    let names = match cmd.get_one::<String>("out") {
+        Some(out) => {
+            let dir = Path::new(out);
+            if !dir.is_dir() {
+                log::error!("{} is not a directory",dir.display());
+                return Err(Box::new(CommandError::InvalidCommand));
+            }
+            Some(write_files(&files,dir,cmd.get_flag("raw"))?)
+        },
+        None => None
+    };
+    println!("{}",to_json(&files,names.as_deref(),cmd.get_one::<u16>("indent").copied()));
+    Ok(())
+}
@@ -1,5 +1,74 @@
+//! # Shell completions
+//!
+//! Scripts are generated by `clap_complete`.  With `--dynamic`, the bash, fish, and zsh scripts are
+//! modified so that `-f` is completed by calling back into a2kit, which lists the paths inside the
+//! image given by `-d`.  The callback is the hidden form `a2kit completions --image <IMG> --prefix <PFX>`.
+
 use clap_complete::shells;
-use crate::STDRESULT;
+use super::CommandError;
+use crate::fs::DiskFS;
+use crate::{STDRESULT,DYNERR};
+
+const BASH_DYNAMIC: &str = r#"
+_a2kit_dynamic() {
+    local i img cur
+    cur="${COMP_WORDS[COMP_CWORD]}"
+    if [[ "${COMP_WORDS[COMP_CWORD-1]}" == "-f" || "${COMP_WORDS[COMP_CWORD-1]}" == "--file" ]]; then
+        for (( i=1; i<${#COMP_WORDS[@]}-1; i++ )); do
+            if [[ "${COMP_WORDS[i]}" == "-d" || "${COMP_WORDS[i]}" == "--dimg" ]]; then
+                img="${COMP_WORDS[i+1]/#\~/$HOME}"
+            fi
+        done
+        if [[ -n "$img" ]]; then
+            local IFS=$'\n'
+            COMPREPLY=( $(a2kit completions --image "$img" --prefix "$cur" 2>/dev/null) )
+            for i in "${COMPREPLY[@]}"; do
+                if [[ "$i" == */ ]]; then
+                    compopt -o nospace 2>/dev/null
+                fi
+            done
+            return 0
+        fi
+    fi
+    _a2kit "$@"
+}
+"#;
+
+const ZSH_DYNAMIC: &str = r#"
+_a2kit_image_paths() {
+    local i img
+    for (( i=1; i<${#words}; i++ )); do
+        if [[ ${words[i]} == -d || ${words[i]} == --dimg ]]; then
+            img=${words[i+1]}
+        fi
+    done
+    if [[ -z $img ]]; then
+        _files
+        return
+    fi
+    local -a paths
+    paths=( ${(f)"$(a2kit completions --image ${~img} --prefix "$PREFIX" 2>/dev/null)"} )
+    compadd -U -S '' -- ${(M)paths:#*/}
+    compadd -U -- ${paths:#*/}
+}
+"#;
+
+const FISH_DYNAMIC: &str = r#"
+function __fish_a2kit_image_paths
+    set -l tokens (commandline -opc)
+    set -l img
+    for i in (seq (math (count $tokens) - 1))
+        if contains -- $tokens[$i] -d --dimg
+            set img $tokens[(math $i + 1)]
+        end
+    end
+    if test -z "$img"
+        __fish_complete_path (commandline -ct)
+        return
+    end
+    a2kit completions --image $img --prefix (commandline -ct) 2>/dev/null
+end
+"#;
 
 // const ALIASES: [(&str,&[&str]);4] = [
 //     ("(catalog)", &["(ls)","(dir)","(cat)"]),
@@ -46,14 +115,121 @@ use crate::STDRESULT;
 //     return new_script;
 // }
 
+/// The `files` node of a directory tree from `DiskFS::tree`, with CP/M user areas flattened
+/// so that files outside user 0 are keyed as `USER:NAME`.
+pub fn tree_files(tree: &json::JsonValue) -> json::JsonValue {
+    if tree["file_system"] != "cpm" {
+        return tree["files"].clone();
+    }
+    let mut flat = json::JsonValue::new_object();
+    for (user,node) in tree["files"].entries() {
+        for (name,_) in node["files"].entries() {
+            match user {
+                "0" => flat[name] = json::JsonValue::new_object(),
+                _ => flat[[user,":",name].concat()] = json::JsonValue::new_object()
+            }
+        }
+    }
+    flat
+}
+
+/// Candidates for completing `prefix` as a path inside the disk image.
+/// Directories end with `/`.  ProDOS absolute paths start with the volume name, and
+/// CP/M files outside user 0 are written as `USER:NAME`.
+pub fn path_candidates(disk: &mut Box<dyn DiskFS>,prefix: &str) -> Result<Vec<String>,DYNERR> {
+    let tree = json::parse(&disk.tree(false,None)?)?;
+    let files = tree_files(&tree);
+    let (dir,partial) = match prefix.rfind('/') {
+        Some(i) => prefix.split_at(i+1),
+        None => ("",prefix)
+    };
+    let label = tree["label"]["name"].as_str().unwrap_or_default();
+    let mut need_label = tree["file_system"] == "prodos" && dir.starts_with('/');
+    let mut node = &files;
+    for comp in dir.split('/').filter(|c| !c.is_empty()) {
+        if need_label {
+            if !comp.eq_ignore_ascii_case(label) {
+                return Ok(Vec::new());
+            }
+            need_label = false;
+            continue;
+        }
+        match node.entries().find(|(k,v)| k.eq_ignore_ascii_case(comp) && v.has_key("files")) {
+            Some((_,v)) => node = &v["files"],
+            None => return Ok(Vec::new())
+        }
+    }
+    let mut ans = Vec::new();
+    if need_label {
+        if label.to_uppercase().starts_with(&partial.to_uppercase()) {
+            ans.push(["/",label,"/"].concat());
+        }
+        return Ok(ans);
+    }
+    for (key,val) in node.entries() {
+        if key.to_uppercase().starts_with(&partial.to_uppercase()) {
+            match val.has_key("files") {
+                true => ans.push([dir,key,"/"].concat()),
+                false => ans.push([dir,key].concat())
+            }
+        }
+    }
+    Ok(ans)
+}
+
+/// Modify a generated script so that `-f` is completed from the disk image
+fn make_dynamic(shell: &str,script: &str) -> Result<String,DYNERR> {
+    match shell {
+        "bash" => {
+            let (body,register) = match script.find("\nif [[ \"${BASH_VERSINFO[0]}\"") {
+                Some(i) => script.split_at(i),
+                None => (script,"")
+            };
+            Ok([body,BASH_DYNAMIC,&register.replace("complete -F _a2kit ","complete -F _a2kit_dynamic ")].concat())
+        },
+        "zsh" => {
+            let file_patt = regex::RegexBuilder::new(r"^('(?:-f\+|--file=)\[[^\]]*\]:[A-Z_]+:)[^']*'").multi_line(true).build()?;
+            let modified = file_patt.replace_all(script,"${1}_a2kit_image_paths'");
+            match modified.split_once('\n') {
+                Some((first,rest)) => Ok([first,"\n",ZSH_DYNAMIC,rest].concat()),
+                None => Ok(modified.to_string())
+            }
+        },
+        "fish" => {
+            let file_patt = regex::RegexBuilder::new(r"^(complete -c a2kit .* -s f -l file .*?-r)(?: -F)?$").multi_line(true).build()?;
+            let modified = file_patt.replace_all(script,"${1} -f -a '(__fish_a2kit_image_paths)'");
+            Ok([FISH_DYNAMIC,&modified].concat())
+        },
+        _ => {
+            log::error!("dynamic completions are not available for {}",shell);
+            Err(Box::new(CommandError::UnsupportedFormat))
+        }
+    }
+}
+
 pub fn generate(mut main_cmd: clap::Command,cmd: &clap::ArgMatches) -> STDRESULT {
-    match cmd.get_one::<String>("shell").unwrap().as_str() {
-        "bash" => clap_complete::generate(shells::Bash,&mut main_cmd,"a2kit",&mut std::io::stdout()),
-        "elv" => clap_complete::generate(shells::Elvish,&mut main_cmd,"a2kit",&mut std::io::stdout()),
-        "fish" => clap_complete::generate(shells::Fish,&mut main_cmd,"a2kit",&mut std::io::stdout()),
-        "ps1" => clap_complete::generate(shells::PowerShell,&mut main_cmd,"a2kit",&mut std::io::stdout()),
-        "zsh" => clap_complete::generate(shells::Zsh,&mut main_cmd,"a2kit",&mut std::io::stdout()),
+    if let Some(img_path) = cmd.get_one::<String>("image") {
+        let mut disk = crate::create_fs_from_file(img_path)?;
+        let prefix = cmd.get_one::<String>("prefix").map(|s| s.as_str()).unwrap_or_default();
+        for path in path_candidates(&mut disk,prefix)? {
+            println!("{}",path);
+        }
+        return Ok(());
+    }
+    let shell = cmd.get_one::<String>("shell").expect("unreachable was reached").as_str();
+    let mut buf: Vec<u8> = Vec::new();
+    match shell {
+        "bash" => clap_complete::generate(shells::Bash,&mut main_cmd,"a2kit",&mut buf),
+        "elv" => clap_complete::generate(shells::Elvish,&mut main_cmd,"a2kit",&mut buf),
+        "fish" => clap_complete::generate(shells::Fish,&mut main_cmd,"a2kit",&mut buf),
+        "ps1" => clap_complete::generate(shells::PowerShell,&mut main_cmd,"a2kit",&mut buf),
+        "zsh" => clap_complete::generate(shells::Zsh,&mut main_cmd,"a2kit",&mut buf),
         _ => panic!("unexpected shell")
     }
+    let script = String::from_utf8(buf)?;
+    match cmd.get_flag("dynamic") {
+        true => print!("{}",make_dynamic(shell,&script)?),
+        false => print!("{}",script)
+    }
     Ok(())
 }
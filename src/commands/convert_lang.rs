@@ -0,0 +1,49 @@
+//! # Convert between BASIC dialects
+//!
+//! Reads Applesoft or Integer BASIC source from stdin and writes the other dialect to stdout,
+//! see `lang::convert`.  Diagnostics are written to stderr in the same form as `verify`.
+//! The output is still written if there are errors, so the flagged lines can be fixed by hand.
+
+use std::io::Read;
+use std::str::FromStr;
+use colored::Colorize;
+use crate::lang;
+use crate::commands::{ItemType,CommandError};
+use crate::STDRESULT;
+
+const RCH: &str = "unreachable was reached";
+
+pub fn convert_lang(cmd: &clap::ArgMatches) -> STDRESULT {
+    if atty::is(atty::Stream::Stdin) {
+        log::error!("line entry is not supported for `convert-lang`, please pipe something in");
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    let mut program = String::new();
+    if let Err(e) = std::io::stdin().read_to_string(&mut program) {
+        log::error!("the program to convert could not be interpreted as a string");
+        return Err(Box::new(e));
+    }
+    if program.is_empty() {
+        log::error!("convert-lang did not receive any data from previous node");
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    let conversion = match ItemType::from_str(cmd.get_one::<String>("type").expect(RCH)) {
+        Ok(ItemType::ApplesoftText) => lang::convert::applesoft_to_integer(&program)?,
+        Ok(ItemType::IntegerText) => lang::convert::integer_to_applesoft(&program)?,
+        _ => return Err(Box::new(CommandError::UnsupportedItemType))
+    };
+    for diag in &conversion.diagnostics {
+        lang::eprint_diagnostic(diag,&program);
+    }
+    print!("{}",conversion.program);
+    let [err,warn,_info] = conversion.err_warn_info_counts();
+    if warn > 0 {
+        eprintln!("! {} {}",warn.to_string().bright_yellow(),"warnings".bright_yellow());
+    }
+    if err > 0 {
+        eprintln!("\u{2717} {} {}",err.to_string().red(),"errors".red());
+        return Err(Box::new(lang::Error::Syntax));
+    }
+    eprintln!("\u{2713} {}","Converted".green());
+    Ok(())
+}
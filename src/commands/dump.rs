@@ -0,0 +1,166 @@
+//! # Dump and restore ranges of an image
+//!
+//! A range of sectors is given as `t0s0..t34s15`, where `t` is the track index and `s` is the
+//! sector ID.  Both ends are included, and the sector can be omitted to take the whole track,
+//! e.g. `t3..t5` is every sector on tracks 3 through 5.  Sectors are visited in track order, and
+//! within a track in order of increasing ID, whatever their physical order.
+//!
+//! A range of blocks is given as for `get -t block`, e.g. `0..280`, and uses the file system's blocks.
+//!
+//! `restore` writes the data back in the same order, and demands that the size match exactly.
+
+use std::io::{Read,Write};
+use std::str::FromStr;
+use log::error;
+use super::CommandError;
+use crate::img::DiskImage;
+use crate::{STDRESULT,DYNERR};
+
+const RCH: &str = "unreachable was reached";
+const RANGE_MESS: &str = "sector range should be in the form `t0s0..t34s15`, sectors are optional";
+
+/// parse `tN` or `tNsM`
+fn parse_track_sector(s: &str) -> Result<(usize,Option<usize>),DYNERR> {
+    let bad = || -> DYNERR {
+        error!("{}",RANGE_MESS);
+        Box::new(CommandError::InvalidCommand)
+    };
+    let rest = s.trim().strip_prefix('t').ok_or_else(bad)?;
+    match rest.split_once('s') {
+        Some((t,sec)) => Ok((usize::from_str(t).map_err(|_| bad())?,Some(usize::from_str(sec).map_err(|_| bad())?))),
+        None => Ok((usize::from_str(rest).map_err(|_| bad())?,None))
+    }
+}
+
+/// Sector IDs on the track in increasing order
+fn sector_ids(img: &mut Box<dyn DiskImage>,track: usize) -> Result<Vec<usize>,DYNERR> {
+    match img.get_track_solution(track)? {
+        Some(sol) => {
+            let mut ans: Vec<usize> = sol.chss_map().iter().map(|x| x[2]).collect();
+            ans.sort();
+            ans.dedup();
+            Ok(ans)
+        },
+        None => {
+            error!("could not find the sectors on track {}",track);
+            Err(Box::new(crate::img::Error::SectorAccess))
+        }
+    }
+}
+
+/// Every `[cyl,head,sec]` in a range such as `t0s0..t34s15`, in the order they are dumped
+pub fn sectors_in_range(img: &mut Box<dyn DiskImage>,range: &str) -> Result<Vec<[usize;3]>,DYNERR> {
+    let (start,end) = match range.split_once("..") {
+        Some((a,b)) => (parse_track_sector(a)?,parse_track_sector(b)?),
+        None => (parse_track_sector(range)?,parse_track_sector(range)?)
+    };
+    if end.0 < start.0 || end.0 >= img.track_count() {
+        error!("track range {}..{} is not within 0..{}",start.0,end.0,img.track_count().saturating_sub(1));
+        return Err(Box::new(CommandError::OutOfRange));
+    }
+    let mut ans = Vec::new();
+    for track in start.0..=end.0 {
+        let [cyl,head] = img.track_2_ch(track);
+        for sec in sector_ids(img,track)? {
+            if (track==start.0 && start.1.is_some_and(|s| sec < s)) || (track==end.0 && end.1.is_some_and(|s| sec > s)) {
+                continue;
+            }
+            ans.push([cyl,head,sec]);
+        }
+    }
+    if ans.is_empty() {
+        error!("no sectors in range {}",range);
+        return Err(Box::new(CommandError::OutOfRange));
+    }
+    Ok(ans)
+}
+
+fn output(dat: &[u8],maybe_path: Option<&String>,hex: bool) -> STDRESULT {
+    match (maybe_path,hex || atty::is(atty::Stream::Stdout)) {
+        (Some(path),_) => std::fs::write(path,dat)?,
+        (None,true) => crate::display_block(0,&dat.to_vec()),
+        (None,false) => std::io::stdout().write_all(dat)?
+    }
+    Ok(())
+}
+
+pub fn dump(cmd: &clap::ArgMatches) -> STDRESULT {
+    let img_path = cmd.get_one::<String>("dimg").expect(RCH);
+    let mut dat = Vec::new();
+    if let Some(range) = cmd.get_one::<String>("range") {
+        let mut img = crate::create_img_with(img_path,&crate::LoadOptions::from_matches(cmd)?)?;
+        let chs_list = sectors_in_range(&mut img,range)?;
+        let mut tracks: Vec<usize> = chs_list.iter().map(|[cyl,head,_]| img.ch_2_track([*cyl,*head])).collect();
+        tracks.dedup();
+        img.decode_tracks(&tracks);
+        for [cyl,head,sec] in chs_list {
+            dat.append(&mut img.read_sector(cyl,head,sec)?);
+        }
+    }
+    if let Some(blocks) = cmd.get_one::<String>("blocks") {
+        let mut disk = crate::create_fs_with(img_path,&crate::LoadOptions::from_matches(cmd)?)?;
+        for b in super::parse_block_request(blocks)? {
+            dat.append(&mut disk.read_block(&b.to_string())?);
+        }
+    }
+    output(&dat,cmd.get_one::<String>("out"),cmd.get_flag("hex"))
+}
+
+pub fn restore(cmd: &clap::ArgMatches,opts: &crate::SaveOptions) -> STDRESULT {
+    let img_path = cmd.get_one::<String>("dimg").expect(RCH);
+    let dat = match cmd.get_one::<String>("input") {
+        Some(path) => std::fs::read(path)?,
+        None => {
+            if atty::is(atty::Stream::Stdin) {
+                error!("please pipe something in or use `--input`");
+                return Err(Box::new(CommandError::InvalidCommand));
+            }
+            let mut dat = Vec::new();
+            std::io::stdin().read_to_end(&mut dat)?;
+            dat
+        }
+    };
+    let size_mismatch = |expected: usize| -> STDRESULT {
+        error!("range holds {} bytes, but {} were given",expected,dat.len());
+        Err(Box::new(CommandError::InvalidCommand))
+    };
+    if let Some(range) = cmd.get_one::<String>("range") {
+        let mut img = crate::create_img_with(img_path,&crate::LoadOptions::from_matches(cmd)?)?;
+        let mut chss = Vec::new();
+        let mut expected = 0;
+        // gather the sizes first so nothing is written if the data does not fit
+        for [cyl,head,sec] in sectors_in_range(&mut img,range)? {
+            let size = img.read_sector(cyl,head,sec)?.len();
+            chss.push([cyl,head,sec,size]);
+            expected += size;
+        }
+        if expected != dat.len() {
+            return size_mismatch(expected);
+        }
+        let mut ptr = 0;
+        for [cyl,head,sec,size] in chss {
+            img.write_sector(cyl,head,sec,&dat[ptr..ptr+size])?;
+            ptr += size;
+        }
+        return crate::save_raw_img_with(&mut img,img_path,opts);
+    }
+    if let Some(blocks) = cmd.get_one::<String>("blocks") {
+        let mut disk = crate::create_fs_with(img_path,&crate::LoadOptions::from_matches(cmd)?)?;
+        let list = super::parse_block_request(blocks)?;
+        let mut sizes = Vec::new();
+        for b in &list {
+            sizes.push(disk.read_block(&b.to_string())?.len());
+        }
+        let expected: usize = sizes.iter().sum();
+        if expected != dat.len() {
+            return size_mismatch(expected);
+        }
+        let mut ptr = 0;
+        for (b,size) in list.iter().zip(sizes) {
+            disk.write_block(&b.to_string(),&dat[ptr..ptr+size])?;
+            ptr += size;
+        }
+        return crate::save_img_with(&mut disk,img_path,opts);
+    }
+    Ok(())
+}
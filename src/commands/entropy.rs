@@ -0,0 +1,188 @@
+//! # Track entropy report
+//!
+//! Every track is read sector by sector, and the data is scored by its Shannon entropy in bits
+//! per byte, and by how well it compresses with the LZSS-Huffman scheme used for TD0 images.
+//! Tracks are then classified as
+//! * `unformatted` - no sectors could be found or read
+//! * `blank` - every byte is the same, as after formatting
+//! * `duplicate` - same data as an earlier track, as happens when a capture does not step the head
+//! * `data` - anything else
+//!
+//! This is meant for triage of flux captures, e.g., to decide which disks merit more work.
+
+use colored::Colorize;
+use std::collections::HashMap;
+use crate::img::DiskImage;
+use crate::{STDRESULT,DYNERR};
+
+const RCH: &str = "unreachable was reached";
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Class {
+    Unformatted,
+    Blank,
+    Duplicate,
+    Data
+}
+
+impl std::fmt::Display for Class {
+    fn fmt(&self,f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unformatted => write!(f,"unformatted"),
+            Self::Blank => write!(f,"blank"),
+            Self::Duplicate => write!(f,"duplicate"),
+            Self::Data => write!(f,"data")
+        }
+    }
+}
+
+/// Statistics for one track
+pub struct TrackStats {
+    pub track: usize,
+    pub sectors: usize,
+    /// sectors that were found but could not be read
+    pub bad_sectors: usize,
+    pub bytes: usize,
+    /// Shannon entropy in bits per byte, 0 to 8
+    pub entropy: f64,
+    /// compressed size over original size, capped at 1
+    pub ratio: f64,
+    pub class: Class,
+    /// earliest track with the same data, if `class` is `Duplicate`
+    pub duplicate_of: Option<usize>
+}
+
+/// Shannon entropy of `dat` in bits per byte
+pub fn shannon(dat: &[u8]) -> f64 {
+    if dat.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize;256];
+    for b in dat {
+        counts[*b as usize] += 1;
+    }
+    let n = dat.len() as f64;
+    counts.iter().filter(|c| **c > 0).map(|c| {
+        let p = *c as f64 / n;
+        p * (1.0 / p).log2()
+    }).sum()
+}
+
+/// Compressed size over original size, capped at 1
+pub fn compress_ratio(dat: &[u8]) -> Result<f64,DYNERR> {
+    if dat.is_empty() {
+        return Ok(1.0);
+    }
+    let opt = retrocompressor::lzss_huff::Options {
+        header: false,
+        ..retrocompressor::lzss_huff::STD_OPTIONS
+    };
+    let compressed = retrocompressor::lzss_huff::compress_slice(dat,&opt)?;
+    Ok(f64::min(1.0,compressed.len() as f64 / dat.len() as f64))
+}
+
+/// Gather statistics for every track in the image
+pub fn analyze(img: &mut Box<dyn DiskImage>) -> Result<Vec<TrackStats>,DYNERR> {
+    let mut ans = Vec::new();
+    let mut seen: HashMap<Vec<u8>,usize> = HashMap::new();
+    img.decode_tracks(&(0..img.track_count()).collect::<Vec<usize>>());
+    for track in 0..img.track_count() {
+        // a track with no solution is left empty, unreadable sectors are counted
+        let chs_list = match img.get_track_solution(track) {
+            Ok(Some(_)) => super::dump::sectors_in_range(img,&format!("t{}",track)).unwrap_or_default(),
+            _ => Vec::new()
+        };
+        let mut dat = Vec::new();
+        let mut bad_sectors = 0;
+        for [cyl,head,sec] in &chs_list {
+            match img.read_sector(*cyl,*head,*sec) {
+                Ok(mut buf) => dat.append(&mut buf),
+                Err(_) => bad_sectors += 1
+            }
+        }
+        let mut stats = TrackStats {
+            track,
+            sectors: chs_list.len(),
+            bad_sectors,
+            bytes: dat.len(),
+            entropy: shannon(&dat),
+            ratio: compress_ratio(&dat)?,
+            class: Class::Data,
+            duplicate_of: None
+        };
+        if dat.is_empty() {
+            stats.class = Class::Unformatted;
+        } else if dat.iter().all(|b| *b==dat[0]) {
+            stats.class = Class::Blank;
+        } else if let Some(prev) = seen.get(&dat) {
+            stats.class = Class::Duplicate;
+            stats.duplicate_of = Some(*prev);
+        } else {
+            seen.insert(dat,track);
+        }
+        ans.push(stats);
+    }
+    Ok(ans)
+}
+
+fn round2(x: f64) -> f64 {
+    (x * 100.0).round() / 100.0
+}
+
+/// Report as a JSON string, with a count of tracks in each class
+pub fn to_json(tracks: &[TrackStats],indent: Option<u16>) -> String {
+    let mut list = json::JsonValue::new_array();
+    let mut summary = json::JsonValue::new_object();
+    for class in [Class::Unformatted,Class::Blank,Class::Duplicate,Class::Data] {
+        summary[class.to_string()] = tracks.iter().filter(|t| t.class==class).count().into();
+    }
+    for t in tracks {
+        let mut obj = json::object! {
+            "track": t.track,
+            "sectors": t.sectors,
+            "bad_sectors": t.bad_sectors,
+            "bytes": t.bytes,
+            "entropy": round2(t.entropy),
+            "ratio": round2(t.ratio),
+            "class": t.class.to_string()
+        };
+        if let Some(prev) = t.duplicate_of {
+            obj["duplicate_of"] = prev.into();
+        }
+        list.push(obj).expect(RCH);
+    }
+    let ans = json::object! {
+        "summary": summary,
+        "tracks": list
+    };
+    match indent {
+        Some(spaces) => json::stringify_pretty(ans, spaces),
+        None => json::stringify(ans)
+    }
+}
+
+/// Report as a table for reading, one line per track
+pub fn to_table(tracks: &[TrackStats]) -> String {
+    let mut ans = format!("{:>5} {:>4} {:>4} {:>6} {:>7} {:>5}  {}\n","TRACK","SECS","BAD","BYTES","ENTROPY","RATIO","CLASS");
+    for t in tracks {
+        let class = match (t.class,t.duplicate_of) {
+            (Class::Duplicate,Some(prev)) => format!("duplicate of {}",prev).yellow(),
+            (Class::Unformatted,_) => t.class.to_string().red(),
+            (Class::Blank,_) => t.class.to_string().dimmed(),
+            _ => t.class.to_string().normal()
+        };
+        ans += &format!("{:>5} {:>4} {:>4} {:>6} {:>7.2} {:>5.2}  {}\n",
+            t.track,t.sectors,t.bad_sectors,t.bytes,t.entropy,t.ratio,class);
+    }
+    ans
+}
+
+pub fn entropy(cmd: &clap::ArgMatches) -> STDRESULT {
+    let mut img = crate::create_img_with_or_stdin(cmd.get_one::<String>("dimg"),&crate::LoadOptions::from_matches(cmd)?)?;
+    let tracks = analyze(&mut img)?;
+    match cmd.get_flag("table") {
+        true => print!("{}",to_table(&tracks)),
+        false => println!("{}",to_json(&tracks,cmd.get_one::<u16>("indent").copied()))
+    }
+    Ok(())
+}
@@ -0,0 +1,184 @@
+//! # Compare two file systems
+//!
+//! Files are matched by path, and each pair is unpacked before comparing.
+//! BASIC programs are detokenized, text is converted to UTF8 with LF, records become JSON,
+//! and anything else is compared as binary data.  With `raw` the bytes as stored are compared instead.
+//!
+//! Unified diffs can be produced for changed files, binary data is diffed as hex dump lines.
+
+use std::collections::BTreeMap;
+use crate::commands::ItemType;
+use crate::fs::{DiskFS,FileImage,UnpackedData};
+use crate::{STDRESULT,DYNERR};
+
+const RCH: &str = "unreachable was reached";
+
+#[derive(Clone,Default)]
+pub struct Options {
+    /// compare bytes as stored, without detokenizing or converting text
+    pub raw: bool,
+    /// ignore trailing whitespace on lines, and blank lines at the end of text
+    pub ignore_space: bool,
+    /// also compare file type, auxiliary type, and access
+    pub meta: bool,
+    /// produce unified diffs with this many lines of context
+    pub context: Option<usize>
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Status {
+    Added,
+    Removed,
+    Changed,
+    /// content is the same, but type, auxiliary type, or access differs
+    MetaChanged
+}
+
+pub struct Entry {
+    pub path: String,
+    pub status: Status,
+    pub diff: Option<String>
+}
+
+enum Content {
+    Text(String),
+    Binary(Vec<u8>)
+}
+
+/// All file paths in the image, relative to the root, CP/M files are `USER:NAME` outside user 0
+pub fn file_paths(disk: &mut Box<dyn DiskFS>) -> Result<Vec<String>,DYNERR> {
+    let tree = json::parse(&disk.tree(false,None)?)?;
+    let mut ans = Vec::new();
+    let files = super::completions::tree_files(&tree);
+    let mut dirs = vec![(String::new(),&files)];
+    while let Some((dir,node)) = dirs.pop() {
+        for (key,val) in node.entries() {
+            match val.has_key("files") {
+                true => dirs.push(([&dir,key,"/"].concat(),&val["files"])),
+                false => ans.push([&dir,key].concat())
+            }
+        }
+    }
+    ans.sort();
+    Ok(ans)
+}
+
+/// Language of a tokenized BASIC program, if it is one
+fn language(fimg: &FileImage) -> Option<ItemType> {
+    let typ = *fimg.fs_type.first()?;
+    match (fimg.file_system.as_str(),typ) {
+        (crate::fs::dos3x::FS_NAME,t) if t & 0x7f == 1 => Some(ItemType::IntegerTokens),
+        (crate::fs::dos3x::FS_NAME,t) if t & 0x7f == 2 => Some(ItemType::ApplesoftTokens),
+        (crate::fs::prodos::FS_NAME,0xfa) => Some(ItemType::IntegerTokens),
+        (crate::fs::prodos::FS_NAME,0xfc) => Some(ItemType::ApplesoftTokens),
+        _ => None
+    }
+}
+
+fn content(fimg: &FileImage,opt: &Options) -> Result<Content,DYNERR> {
+    if opt.raw {
+        return Ok(Content::Binary(fimg.unpack_raw(true)?));
+    }
+    match language(fimg) {
+        Some(ItemType::ApplesoftTokens) => return Ok(Content::Text(
+            crate::lang::applesoft::tokenizer::Tokenizer::new().detokenize(&fimg.unpack_tok()?)?)),
+        Some(ItemType::IntegerTokens) => return Ok(Content::Text(
+            crate::lang::integer::tokenizer::Tokenizer::new().detokenize(&fimg.unpack_tok()?)?)),
+        _ => {}
+    }
+    Ok(match fimg.unpack() {
+        Ok(UnpackedData::Text(txt)) => Content::Text(txt),
+        Ok(UnpackedData::Binary(dat)) => Content::Binary(dat),
+        Ok(UnpackedData::Records(recs)) => Content::Text(recs.to_json(Some(2))),
+        Err(_) => Content::Binary(fimg.unpack_raw(true)?)
+    })
+}
+
+fn lines(content: &Content,opt: &Options) -> String {
+    let txt = match content {
+        Content::Text(txt) => txt.clone(),
+        Content::Binary(dat) => crate::block_lines(0,dat).join("\n") + "\n"
+    };
+    if !opt.ignore_space {
+        return txt;
+    }
+    let mut ans = String::new();
+    for line in txt.lines() {
+        ans += line.trim_end();
+        ans += "\n";
+    }
+    ans.trim_end_matches('\n').to_string() + "\n"
+}
+
+fn same(a: &Content,b: &Content,opt: &Options) -> bool {
+    match (a,b) {
+        (Content::Binary(x),Content::Binary(y)) => x == y,
+        (Content::Text(x),Content::Text(y)) if !opt.ignore_space => x == y,
+        _ => lines(a,opt) == lines(b,opt)
+    }
+}
+
+/// Compare the files in `a` with those in `b`, the result is sorted by path
+pub fn diff_fs(a: &mut Box<dyn DiskFS>,b: &mut Box<dyn DiskFS>,opt: &Options) -> Result<Vec<Entry>,DYNERR> {
+    let mut paths: BTreeMap<String,(bool,bool)> = BTreeMap::new();
+    for path in file_paths(a)? {
+        paths.entry(path).or_default().0 = true;
+    }
+    for path in file_paths(b)? {
+        paths.entry(path).or_default().1 = true;
+    }
+    let mut ans = Vec::new();
+    for (path,found) in paths {
+        let status = match found {
+            (true,false) => Status::Removed,
+            (false,true) => Status::Added,
+            _ => {
+                let (fa,fb) = (a.get(&path)?,b.get(&path)?);
+                let (ca,cb) = (content(&fa,opt)?,content(&fb,opt)?);
+                if !same(&ca,&cb,opt) {
+                    let diff = opt.context.map(|n| {
+                        let (la,lb) = (lines(&ca,opt),lines(&cb,opt));
+                        similar::TextDiff::from_lines(&la,&lb).unified_diff().context_radius(n)
+                            .header(&["a/",&path].concat(),&["b/",&path].concat()).to_string()
+                    });
+                    ans.push(Entry { path, status: Status::Changed, diff });
+                    continue;
+                }
+                if opt.meta && (fa.fs_type != fb.fs_type || fa.aux != fb.aux || fa.access != fb.access) {
+                    Status::MetaChanged
+                } else {
+                    continue;
+                }
+            }
+        };
+        ans.push(Entry { path, status, diff: None });
+    }
+    Ok(ans)
+}
+
+pub fn fsdiff(cmd: &clap::ArgMatches) -> STDRESULT {
+    let mut a = crate::create_fs_with(cmd.get_one::<String>("a").expect(RCH),&crate::LoadOptions::from_matches(cmd)?)?;
+    let mut b = crate::create_fs_with(cmd.get_one::<String>("b").expect(RCH),&crate::LoadOptions::from_matches(cmd)?)?;
+    let opt = Options {
+        raw: cmd.get_flag("raw"),
+        ignore_space: cmd.get_flag("ignore-space"),
+        meta: cmd.get_flag("meta"),
+        context: match cmd.get_flag("unified") {
+            true => Some(*cmd.get_one::<usize>("context").expect(RCH)),
+            false => None
+        }
+    };
+    for entry in diff_fs(&mut a,&mut b,&opt)? {
+        let label = match entry.status {
+            Status::Added => "added",
+            Status::Removed => "removed",
+            Status::Changed => "changed",
+            Status::MetaChanged => "meta"
+        };
+        println!("{:8}{}",label,entry.path);
+        if let Some(diff) = entry.diff {
+            print!("{}",diff);
+        }
+    }
+    Ok(())
+}
@@ -7,7 +7,15 @@ use super::{ItemType,CommandError};
 use crate::fs::{FileImage,UnpackedData};
 use crate::{DYNERR,STDRESULT};
 
-fn output_get(result: UnpackedData, load_addr: usize) -> STDRESULT {
+fn output_get(result: UnpackedData, load_addr: usize, maybe_out: Option<&String>) -> STDRESULT {
+    if maybe_out.is_some() {
+        let dat = match result {
+            UnpackedData::Text(txt) => txt.into_bytes(),
+            UnpackedData::Binary(dat) => dat,
+            UnpackedData::Records(recs) => (recs.to_json(Some(2)) + "\n").into_bytes()
+        };
+        return super::write_output(maybe_out,&dat);
+    }
     match (result,atty::is(atty::Stream::Stdout)) {
         (UnpackedData::Text(txt),_) => {
             print!("{}",txt);
@@ -24,7 +32,7 @@ fn output_get(result: UnpackedData, load_addr: usize) -> STDRESULT {
     Ok(())
 }
 
-fn unpack_primitive(fimg: &FileImage,typ: ItemType,rec_len: Option<usize>,trunc: bool,indent: Option<u16>) -> Result<UnpackedData,DYNERR> {
+pub(super) fn unpack_primitive(fimg: &FileImage,typ: ItemType,rec_len: Option<usize>,trunc: bool,indent: Option<u16>) -> Result<UnpackedData,DYNERR> {
     match typ {
         ItemType::Automatic => fimg.unpack(),
         ItemType::FileImage => Ok(UnpackedData::Text(fimg.to_json(indent))),
@@ -35,6 +43,7 @@ fn unpack_primitive(fimg: &FileImage,typ: ItemType,rec_len: Option<usize>,trunc:
         ItemType::Binary => Ok(UnpackedData::Binary(fimg.unpack_bin()?)),
         ItemType::Text => Ok(UnpackedData::Text(fimg.unpack_txt()?)),
         ItemType::Raw => Ok(UnpackedData::Binary(fimg.unpack_raw(trunc)?)),
+        ItemType::MacBinary => Ok(UnpackedData::Binary(crate::fs::macbinary::unpack(fimg)?)),
         _ => Err(Box::new(CommandError::UnsupportedItemType))
     }
 }
@@ -59,7 +68,7 @@ pub fn unpack(cmd: &clap::ArgMatches) -> STDRESULT {
     let json_str = String::from_utf8(dat)?;
     let fimg = FileImage::from_json(&json_str)?;
     let result = unpack_primitive(&fimg, typ, rec_len, trunc, cmd.get_one::<u16>("indent").copied())?;
-    output_get(result, fimg.get_load_address() as usize)
+    output_get(result, fimg.get_load_address() as usize, None)
 }
 
 pub fn get(cmd: &clap::ArgMatches) -> STDRESULT {
@@ -67,15 +76,28 @@ pub fn get(cmd: &clap::ArgMatches) -> STDRESULT {
     let maybe_src_path = cmd.get_one::<String>("file");
     let maybe_typ = cmd.get_one::<String>("type");
     let maybe_img = cmd.get_one::<String>("dimg");
+    let maybe_out = cmd.get_one::<String>("output");
     let pipe_or_img = !atty::is(atty::Stream::Stdin) || maybe_img.is_some();
     let trunc = cmd.get_flag("trunc");
     let rec_len = match cmd.get_one::<String>("len") {
         Some(s) => Some(usize::from_str(s)?),
         None => None
     };
+    if let Some(map_path) = cmd.get_one::<String>("workspace") {
+        return get_from_workspace(cmd,map_path);
+    }
+    if let Some(chain) = cmd.get_one::<String>("chain") {
+        return get_chain(maybe_img,&crate::LoadOptions::from_matches(cmd)?,chain,maybe_out);
+    }
 
     match (maybe_typ, pipe_or_img, maybe_src_path) {
 
+        // the reserved system tracks do not need a path
+        (Some(typ_str),true,_) if typ_str=="boot" => {
+            let mut disk = crate::create_fs_with_or_stdin(maybe_img,&crate::LoadOptions::from_matches(cmd)?)?;
+            return output_get(UnpackedData::Binary(disk.read_boot()?),0,maybe_out);
+        },
+
         // we are getting a specific item from a disk image
         (Some(typ_str),true,Some(src_path)) => {
             // For items that don't need a file system handle differently.
@@ -93,18 +115,18 @@ pub fn get(cmd: &clap::ArgMatches) -> STDRESULT {
                     return Err(Box::new(CommandError::InvalidCommand));
                 }
             }
-            let mut disk = crate::create_fs_from_file_or_stdin(maybe_img)?;
+            let mut disk = crate::create_fs_with_or_stdin(maybe_img,&crate::LoadOptions::from_matches(cmd)?)?;
             if typ == ItemType::Block {
                 let mut cum: Vec<u8> = Vec::new();
                 let blocks = super::parse_block_request(&src_path)?;
                 for b in blocks {
                     cum.append(&mut disk.read_block(&b.to_string())?);
                 }
-                return output_get(UnpackedData::Binary(cum),0);
+                return output_get(UnpackedData::Binary(cum),0,maybe_out);
             }
             let fimg = disk.get(&src_path)?;
             let result = unpack_primitive(&fimg, typ, rec_len, trunc, cmd.get_one::<u16>("indent").copied())?;
-            return output_get(result,fimg.get_load_address() as usize);
+            output_get(result,fimg.get_load_address() as usize,maybe_out)
         },
 
         // this pattern can be used for metadata only
@@ -122,11 +144,8 @@ pub fn get(cmd: &clap::ArgMatches) -> STDRESULT {
         // this pattern means we have a local file
         (None,false,Some(src_path)) => {
             match std::fs::read(&src_path) {
-                Ok(object) => {
-                    std::io::stdout().write_all(&object).expect("could not write stdout");
-                    return Ok(());
-                },
-                Err(e) => return Err(Box::new(e))
+                Ok(object) => super::write_output(maybe_out,&object),
+                Err(e) => Err(Box::new(e))
             }
         },
 
@@ -143,6 +162,87 @@ pub fn get(cmd: &clap::ArgMatches) -> STDRESULT {
     }
 }
 
+/// Read an explicit chain of sectors or blocks and concatenate them, for recovering a file whose
+/// directory entry is gone.  Items are separated by spaces, `T,S` is a DOS 3.x track and sector,
+/// anything else is passed to `read_block` as is.
+fn get_chain(maybe_img: Option<&String>,load: &crate::LoadOptions,chain: &str,maybe_out: Option<&String>) -> STDRESULT {
+    let mut disk = crate::create_fs_with_or_stdin(maybe_img,load)?;
+    let sectors = match chain.contains(',') {
+        true => disk.stat_deep()?["vtoc"]["sectors"].as_usize(),
+        false => None
+    };
+    let mut cum: Vec<u8> = Vec::new();
+    for item in chain.split_whitespace() {
+        let block = match (item.split_once(','),sectors) {
+            (Some(_),None) => {
+                log::error!("track and sector pairs are only for DOS 3.x, please use blocks");
+                return Err(Box::new(CommandError::InvalidCommand));
+            },
+            (Some((t,s)),Some(sectors)) => {
+                let (track,sector) = (usize::from_str(t)?,usize::from_str(s)?);
+                if sector >= sectors {
+                    log::error!("sector {} is out of range",sector);
+                    return Err(Box::new(CommandError::OutOfRange));
+                }
+                (track*sectors + sector).to_string()
+            },
+            (None,_) => item.to_string()
+        };
+        cum.append(&mut disk.read_block(&block)?);
+    }
+    if cum.is_empty() {
+        log::error!("chain is empty");
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    output_get(UnpackedData::Binary(cum),0,maybe_out)
+}
+
+/// Get a file by `VOL:NAME` from a Pascal workspace, see `fs::pascal::workspace`
+fn get_from_workspace(cmd: &clap::ArgMatches,map_path: &str) -> STDRESULT {
+    let (typ,src_path) = match (cmd.get_one::<String>("type"),cmd.get_one::<String>("file")) {
+        (Some(t),Some(f)) => (ItemType::from_str(t)?,f),
+        _ => {
+            log::error!("workspace requires `-f` and `-t`");
+            return Err(Box::new(CommandError::InvalidCommand));
+        }
+    };
+    let workspace = crate::fs::pascal::workspace::Workspace::from_file(map_path)?;
+    let fimg = workspace.get(src_path)?;
+    let rec_len = match cmd.get_one::<String>("len") {
+        Some(s) => Some(usize::from_str(s)?),
+        None => None
+    };
+    let result = unpack_primitive(&fimg, typ, rec_len, cmd.get_flag("trunc"), cmd.get_one::<u16>("indent").copied())?;
+    output_get(result,fimg.get_load_address() as usize,cmd.get_one::<String>("output"))
+}
+
+/// Get the same list of paths from every image in a zip archive, the result is keyed by member name.
+/// Paths missing from an image are skipped.
+fn mget_archive(cmd: &clap::ArgMatches,path_to_img: &str,members: &[String],json_list: &json::JsonValue) -> STDRESULT {
+    let mut ans = json::object! {};
+    for member in members {
+        let mut disk = crate::create_fs_from_archive_with(path_to_img,member,&crate::LoadOptions::from_matches(cmd)?)?;
+        let mut fimgs = json::array![];
+        for path in json_list.members() {
+            if !path.is_string() {
+                log::error!("element of input to mget was not a string");
+                return Err(Box::new(CommandError::InvalidCommand));
+            }
+            match disk.get(path.as_str().unwrap()) {
+                Ok(fimg) => fimgs.push(fimg.to_json_value())?,
+                Err(_) => log::warn!("{} not found in {}",path,member)
+            }
+        }
+        ans[member.as_str()] = fimgs;
+    }
+    if let Some(spaces) = cmd.get_one::<u16>("indent") {
+        println!("{}",json::stringify_pretty(ans,*spaces));
+    } else {
+        println!("{}",json::stringify(ans))
+    }
+    Ok(())
+}
+
 pub fn mget(cmd: &clap::ArgMatches) -> STDRESULT {
     if atty::is(atty::Stream::Stdin) {
         log::error!("line entry is not supported for `mget`, please pipe something in");
@@ -150,21 +250,41 @@ pub fn mget(cmd: &clap::ArgMatches) -> STDRESULT {
     }
     let path_to_img = cmd.get_one::<String>("dimg").unwrap();
     let json_list = super::get_json_list_from_stdin()?;
-    let mut disk = crate::create_fs_from_file(&path_to_img)?;
+    if let Some(members) = crate::img::archive::image_members(path_to_img)? {
+        if members.len() > 1 {
+            if cmd.contains_id("status") || cmd.contains_id("resume") {
+                log::warn!("`--status` and `--resume` are ignored for archives with several images");
+            }
+            return mget_archive(cmd,path_to_img,&members,&json_list);
+        }
+    }
+    let mut disk = crate::create_fs_with(path_to_img,&crate::LoadOptions::from_matches(cmd)?)?;
 
+    let mut status = super::BatchStatus::new(cmd.get_one::<String>("resume"))?;
     let mut ans = json::array![];
     for path in json_list.members() {
-        if !path.is_string() {
-            log::error!("element of input to mget was not a string");
-            return Err(Box::new(CommandError::InvalidCommand));
+        let path = match path.as_str() {
+            Some(p) => p,
+            None => {
+                status.failed(&path.to_string(),&(Box::new(CommandError::InvalidCommand) as DYNERR));
+                continue;
+            }
+        };
+        if status.done_before(path) {
+            continue;
+        }
+        match disk.get(path) {
+            Ok(fimg) => {
+                ans.push(fimg.to_json_value())?;
+                status.ok(path);
+            },
+            Err(e) => status.failed(path,&e)
         }
-        let fimg = disk.get(path.as_str().unwrap())?;
-        ans.push(json::parse(&fimg.to_json(None))?)?;
     }
     if let Some(spaces) = cmd.get_one::<u16>("indent") {
         println!("{}",json::stringify_pretty(ans,*spaces));
     } else {
         println!("{}",json::stringify(ans))
     }
-    return Ok(());
+    status.finish(cmd.get_one::<String>("status"))
 }
\ No newline at end of file
@@ -4,7 +4,6 @@
 //! This is invoked from the `get` module.
 
 use clap;
-use std::io::Write;
 use std::str::FromStr;
 use log::error;
 use super::{ItemType,CommandError};
@@ -13,29 +12,30 @@ use crate::STDRESULT;
 
 const RCH: &str = "unreachable was reached";
 
-fn output_get(dat: Vec<u8>,typ: ItemType,img: Box<dyn DiskImage>) {
-    if atty::is(atty::Stream::Stdout) {
-        match typ {
-            ItemType::Track => println!("{}",img.display_track(&dat)),
-            _ => crate::display_block(0,&dat)
-        };
-    } else {
-        std::io::stdout().write_all(&dat).expect("could not write stdout")
+fn output_get(dat: Vec<u8>,typ: ItemType,img: Box<dyn DiskImage>,maybe_out: Option<&String>) -> STDRESULT {
+    if maybe_out.is_some() || !atty::is(atty::Stream::Stdout) {
+        return super::write_output(maybe_out,&dat);
     }
+    match typ {
+        ItemType::Track => println!("{}",img.display_track(&dat)),
+        _ => crate::display_block(0,&dat)
+    };
+    Ok(())
 }
 
 pub fn get(cmd: &clap::ArgMatches) -> STDRESULT {
     // presence of arguments should already be resolved
     let src_path = cmd.get_one::<String>("file").expect(RCH);
     let typ = ItemType::from_str(&cmd.get_one::<String>("type").expect(RCH)).expect(RCH);
+    let addressing = cmd.get_one::<String>("addressing").expect(RCH);
     let maybe_img_path = cmd.get_one::<String>("dimg");
 
-    match crate::create_img_from_file_or_stdin(maybe_img_path) {
+    match crate::create_img_with_or_stdin(maybe_img_path,&crate::LoadOptions::from_matches(cmd)?) {
         Ok(mut img) => {
             let bytes = match typ {
                 ItemType::Sector => {
                     let mut cum: Vec<u8> = Vec::new();
-                    let sector_list = super::parse_sector_request(&src_path)?;
+                    let sector_list = super::parse_sector_request_addressed(src_path,&img.kind(),addressing)?;
                     for [cyl,head,sec] in sector_list {
                         cum.append(&mut img.read_sector(cyl,head,sec)?);
                     }
@@ -51,7 +51,7 @@ pub fn get(cmd: &clap::ArgMatches) -> STDRESULT {
                 },
                 _ => panic!("{}",RCH)
             };
-            return Ok(output_get(bytes,typ,img));
+            output_get(bytes,typ,img,cmd.get_one::<String>("output"))
         },
         Err(e) => return Err(e)
     }
@@ -62,13 +62,10 @@ pub fn get_meta(cmd: &clap::ArgMatches) -> STDRESULT {
     let maybe_selection = cmd.get_one::<String>("file");
     let maybe_img_path = cmd.get_one::<String>("dimg");
 
-    match crate::create_img_from_file_or_stdin(maybe_img_path) {
+    match crate::create_img_with_or_stdin(maybe_img_path,&crate::LoadOptions::from_matches(cmd)?) {
         Ok(img) => {
             match maybe_selection {
-                None => {
-                    println!("{}",img.get_metadata(Some(4)));
-                    Ok(())
-                }
+                None => super::write_output(cmd.get_one::<String>("output"),(img.get_metadata(Some(4)) + "\n").as_bytes()),
                 Some(selection) => {
                     if selection.chars().next()!=Some('/') {
                         error!("selection string should start with `/`");
@@ -88,8 +85,7 @@ pub fn get_meta(cmd: &clap::ArgMatches) -> STDRESULT {
                                     x => { obj = x }
                                 };
                             }
-                            println!("{}",json::stringify_pretty(obj, 4));
-                            Ok(())
+                            super::write_output(cmd.get_one::<String>("output"),(json::stringify_pretty(obj, 4) + "\n").as_bytes())
                         },
                         Err(e) => Err(Box::new(e))
                     }
@@ -0,0 +1,273 @@
+//! # HTTP server
+//!
+//! Exposes the disk images in a directory through a small REST interface:
+//!
+//! request | response
+//! --------|---------
+//! `GET /images` | JSON array of image paths relative to the root
+//! `GET /stat?image=IMG` | JSON file system statistics
+//! `GET /catalog?image=IMG` | JSON file system tree with metadata
+//! `GET /file?image=IMG&path=PATH&type=TYPE` | the file, unpacked as with `get`
+//! `PUT /file?image=IMG&path=PATH&type=TYPE&addr=ADDR` | put the request body, as with `put`, and save the image
+//!
+//! Download types are those of `get`, plus `atxt`, `itxt`, and `mtxt`, which detokenize.
+//! Upload types are those of `put`, plus `atxt`, `itxt`, and `mtxt`, which tokenize.
+//! Image paths cannot leave the root directory.  Requests are handled one at a time.
+
+use std::io::{BufRead,BufReader,Read,Write};
+use std::net::{TcpListener,TcpStream};
+use std::path::{Path,PathBuf};
+use std::str::FromStr;
+use log::{debug,info,error};
+use super::ItemType;
+use crate::fs::UnpackedData;
+use crate::lang;
+use crate::{STDRESULT,DYNERR};
+
+/// upper limit on request bodies
+const MAX_BODY: usize = 1 << 26;
+/// how deep to look for images below the root
+const MAX_DEPTH: usize = 4;
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: String,
+    pub body: Vec<u8>
+}
+
+impl Response {
+    fn json(body: String) -> Self {
+        Self { status: 200, content_type: "application/json".to_string(), body: body.into_bytes() }
+    }
+    fn text(body: String) -> Self {
+        Self { status: 200, content_type: "text/plain; charset=utf-8".to_string(), body: body.into_bytes() }
+    }
+    fn binary(body: Vec<u8>) -> Self {
+        Self { status: 200, content_type: "application/octet-stream".to_string(), body }
+    }
+    fn error(status: u16, msg: &str) -> Self {
+        Self { status, content_type: "text/plain; charset=utf-8".to_string(), body: msg.as_bytes().to_vec() }
+    }
+    fn reason(&self) -> &str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            _ => "Internal Server Error"
+        }
+    }
+}
+
+/// Decode `%XX` escapes, and `+` as space
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut ans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                match u8::from_str_radix(&String::from_utf8_lossy(&bytes[i+1..i+3]),16) {
+                    Ok(b) => {
+                        ans.push(b);
+                        i += 3;
+                        continue;
+                    },
+                    Err(_) => ans.push(b'%')
+                }
+            },
+            b'+' => ans.push(b' '),
+            b => ans.push(b)
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&ans).to_string()
+}
+
+/// Split a request target into the path and the query parameters
+fn parse_target(target: &str) -> (String,Vec<(String,String)>) {
+    let (path,query) = match target.split_once('?') {
+        Some((p,q)) => (p,q),
+        None => (target,"")
+    };
+    let params = query.split('&').filter(|kv| !kv.is_empty()).map(|kv| match kv.split_once('=') {
+        Some((k,v)) => (percent_decode(k),percent_decode(v)),
+        None => (percent_decode(kv),String::new())
+    }).collect();
+    (percent_decode(path),params)
+}
+
+fn param<'a>(params: &'a [(String,String)], key: &str) -> Option<&'a str> {
+    params.iter().find(|(k,_)| k == key).map(|(_,v)| v.as_str())
+}
+
+/// Serves the disk images below a root directory
+pub struct HttpServer {
+    root: PathBuf,
+    load_options: crate::LoadOptions,
+    save_options: crate::SaveOptions
+}
+
+impl HttpServer {
+    pub fn new(root: &Path) -> Result<Self,DYNERR> {
+        Ok(Self {
+            root: std::fs::canonicalize(root)?,
+            load_options: crate::LoadOptions::default(),
+            save_options: crate::SaveOptions::default()
+        })
+    }
+    pub fn set_load_options(&mut self, opts: crate::LoadOptions) {
+        self.load_options = opts;
+    }
+    pub fn set_save_options(&mut self, opts: crate::SaveOptions) {
+        self.save_options = opts;
+    }
+    /// Resolve an image path relative to the root, refusing anything outside the root
+    fn image_path(&self, rel: &str) -> Option<PathBuf> {
+        let candidate = self.root.join(rel);
+        match std::fs::canonicalize(candidate) {
+            Ok(p) if p.starts_with(&self.root) && p.is_file() => Some(p),
+            _ => None
+        }
+    }
+    fn list(&self, dir: &Path, depth: usize, ans: &mut Vec<String>) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(e) => e,
+            Err(_) => return
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() && depth < MAX_DEPTH {
+                self.list(&path, depth + 1, ans);
+            } else if let Some(ext) = path.extension() {
+                if crate::KNOWN_FILE_EXTENSIONS.split(',').any(|x| ext.eq_ignore_ascii_case(x)) {
+                    if let Ok(rel) = path.strip_prefix(&self.root) {
+                        ans.push(rel.to_string_lossy().replace('\\',"/"));
+                    }
+                }
+            }
+        }
+    }
+    /// List of image paths relative to the root
+    pub fn images(&self) -> Vec<String> {
+        let mut ans = Vec::new();
+        self.list(&self.root, 0, &mut ans);
+        ans.sort();
+        ans
+    }
+    fn get_file(disk: &mut Box<dyn crate::fs::DiskFS>, path: &str, typ: &str) -> Result<Response,DYNERR> {
+        let fimg = disk.get(path)?;
+        let ans = match typ {
+            "atxt" => UnpackedData::Text(lang::applesoft::tokenizer::Tokenizer::new().detokenize(&fimg.unpack_tok()?)?),
+            "itxt" => UnpackedData::Text(lang::integer::tokenizer::Tokenizer::new().detokenize(&fimg.unpack_tok()?)?),
+            "mtxt" => UnpackedData::Text(lang::merlin::tokenizer::Tokenizer::new().detokenize(&fimg.unpack_raw(true)?)?),
+            s => super::get::unpack_primitive(&fimg, ItemType::from_str(s)?, None, false, None)?
+        };
+        Ok(match ans {
+            UnpackedData::Text(txt) => Response::text(txt),
+            UnpackedData::Binary(dat) => Response::binary(dat),
+            UnpackedData::Records(recs) => Response::json(recs.to_json(None))
+        })
+    }
+    fn try_handle(&self, method: &str, target: &str, body: &[u8]) -> Result<Response,DYNERR> {
+        let (route,params) = parse_target(target);
+        if route == "/images" {
+            return match method {
+                "GET" => Ok(Response::json(json::stringify(self.images()))),
+                _ => Ok(Response::error(405,"use GET"))
+            };
+        }
+        if !["/stat","/catalog","/file"].contains(&route.as_str()) {
+            return Ok(Response::error(404,"unknown route"));
+        }
+        let img_path = match param(&params,"image").and_then(|rel| self.image_path(rel)) {
+            Some(p) => p,
+            None => return Ok(Response::error(404,"image not found"))
+        };
+        let img_str = img_path.to_string_lossy().to_string();
+        let mut disk = crate::create_fs_with(&img_str, &self.load_options)?;
+        match (method,route.as_str()) {
+            ("GET","/stat") => Ok(Response::json(disk.stat()?.to_json(None))),
+            ("GET","/catalog") => Ok(Response::json(disk.tree(true,None)?)),
+            (_,"/file") => {
+                let (path,typ) = match (param(&params,"path"),param(&params,"type")) {
+                    (Some(p),Some(t)) => (p,t),
+                    _ => return Ok(Response::error(400,"`path` and `type` are required"))
+                };
+                match method {
+                    "GET" => Self::get_file(&mut disk, path, typ),
+                    "PUT" => {
+                        let addr = match param(&params,"addr") {
+                            Some(a) => Some(usize::from_str(a)?),
+                            None => None
+                        };
+                        let (typ,dat,addr) = super::build::pack_source(typ, body.to_vec(), addr, path)?;
+                        super::build::put_item(&mut disk, path, typ, &dat, addr)?;
+                        crate::save_img_with(&mut disk, &img_str, &self.save_options)?;
+                        Ok(Response::json(json::stringify(json::object! { "image": param(&params,"image"), "path": path })))
+                    },
+                    _ => Ok(Response::error(405,"use GET or PUT"))
+                }
+            },
+            _ => Ok(Response::error(405,"use GET"))
+        }
+    }
+    /// Produce the response to a request, errors become status 400
+    pub fn handle(&self, method: &str, target: &str, body: &[u8]) -> Response {
+        match self.try_handle(method, target, body) {
+            Ok(resp) => resp,
+            Err(e) => Response::error(400,&e.to_string())
+        }
+    }
+    fn handle_stream(&self, stream: TcpStream) -> STDRESULT {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut words = line.split_whitespace();
+        let (method,target) = match (words.next(),words.next()) {
+            (Some(m),Some(t)) => (m.to_string(),t.to_string()),
+            _ => return Ok(())
+        };
+        let mut content_len = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+            if let Some((key,val)) = header.split_once(':') {
+                if key.trim().eq_ignore_ascii_case("content-length") {
+                    content_len = usize::from_str(val.trim())?;
+                }
+            }
+        }
+        debug!("{} {} with {} bytes",method,target,content_len);
+        let resp = match content_len > MAX_BODY {
+            true => Response::error(413,"request body is too large"),
+            false => {
+                let mut body = vec![0;content_len];
+                reader.read_exact(&mut body)?;
+                self.handle(&method, &target, &body)
+            }
+        };
+        info!("{} {} {}",method,target,resp.status);
+        let mut stream = stream;
+        write!(stream,"HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            resp.status,resp.reason(),resp.content_type,resp.body.len())?;
+        stream.write_all(&resp.body)?;
+        stream.flush()?;
+        Ok(())
+    }
+    /// Handle connections from the listener, one at a time.  Never returns normally.
+    pub fn serve(&self, listener: TcpListener) -> STDRESULT {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(s) => if let Err(e) = self.handle_stream(s) {
+                    error!("request failed: {}",e);
+                },
+                Err(e) => error!("connection failed: {}",e)
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+//! # Lint a Merlin or Applesoft project
+//!
+//! Runs the analyzer over every source in a folder.  Merlin sources are analyzed the same way the
+//! language server does when a file is opened, so each include is checked in the context of its master.
+//! Applesoft sources, `*.bas` or `*.abas`, are analyzed one at a time.
+//! The rule set is the analyzer configuration, i.e., the same JSON the language server accepts.
+//! Results can be written for people, or as JSON or SARIF for CI.
+
+use log::error;
+use lsp_types as lsp;
+use colored::Colorize;
+use crate::lang::server::{Analysis,path_in_workspace};
+use crate::lang::{merlin,applesoft,Document};
+use crate::{STDRESULT,DYNERR};
+
+const RCH: &str = "unreachable was reached";
+const MAX_DEPTH: usize = 10;
+const IGNORE_DIRS: [&str;3] = [
+    "build",
+    "node_modules",
+    "target"
+];
+
+/// Diagnostics for one file in the workspace
+struct FileReport {
+    path: String,
+    text: String,
+    diags: Vec<lsp::Diagnostic>
+}
+
+fn severity_rank(sev: Option<lsp::DiagnosticSeverity>) -> usize {
+    match sev {
+        Some(lsp::DiagnosticSeverity::ERROR) => 1,
+        Some(lsp::DiagnosticSeverity::WARNING) => 2,
+        Some(lsp::DiagnosticSeverity::INFORMATION) => 3,
+        _ => 4
+    }
+}
+
+fn severity_name(sev: Option<lsp::DiagnosticSeverity>) -> &'static str {
+    match severity_rank(sev) {
+        1 => "error",
+        2 => "warning",
+        3 => "information",
+        _ => "hint"
+    }
+}
+
+fn to_json(reports: &[FileReport],counts: [usize;3],indent: Option<u16>) -> String {
+    let mut files = json::JsonValue::new_array();
+    for rep in reports {
+        let mut diags = json::JsonValue::new_array();
+        for diag in &rep.diags {
+            diags.push(json::object! {
+                "severity": severity_name(diag.severity),
+                "line": diag.range.start.line,
+                "column": diag.range.start.character,
+                "end_line": diag.range.end.line,
+                "end_column": diag.range.end.character,
+                "message": diag.message.as_str()
+            }).expect(RCH);
+        }
+        files.push(json::object! {
+            "path": rep.path.as_str(),
+            "diagnostics": diags
+        }).expect(RCH);
+    }
+    let ans = json::object! {
+        "errors": counts[0],
+        "warnings": counts[1],
+        "information": counts[2],
+        "files": files
+    };
+    match indent {
+        Some(spaces) => json::stringify_pretty(ans, spaces),
+        None => json::stringify(ans)
+    }
+}
+
+/// SARIF 2.1.0 log with one run, lines and columns are 1 based as SARIF requires
+fn to_sarif(reports: &[FileReport],indent: Option<u16>) -> String {
+    let mut results = json::JsonValue::new_array();
+    for rep in reports {
+        for diag in &rep.diags {
+            let level = match severity_rank(diag.severity) {
+                1 => "error",
+                2 => "warning",
+                _ => "note"
+            };
+            results.push(json::object! {
+                "level": level,
+                "message": { "text": diag.message.as_str() },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": rep.path.as_str() },
+                        "region": {
+                            "startLine": diag.range.start.line + 1,
+                            "startColumn": diag.range.start.character + 1,
+                            "endLine": diag.range.end.line + 1,
+                            "endColumn": diag.range.end.character + 1
+                        }
+                    }
+                }]
+            }).expect(RCH);
+        }
+    }
+    let run = json::object! {
+        "tool": {
+            "driver": {
+                "name": "a2kit",
+                "version": env!("CARGO_PKG_VERSION"),
+                "informationUri": "https://github.com/dfgordon/a2kit"
+            }
+        },
+        "results": results
+    };
+    let ans = json::object! {
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [run]
+    };
+    match indent {
+        Some(spaces) => json::stringify_pretty(ans, spaces),
+        None => json::stringify(ans)
+    }
+}
+
+/// Recursively gather Applesoft sources, the order is sorted by path
+fn gather_applesoft(dir: &std::path::Path,depth: usize,docs: &mut Vec<Document>) -> STDRESULT {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            let ignore = path.file_name().is_some_and(|n| IGNORE_DIRS.iter().any(|d| n==*d));
+            if !ignore && depth < MAX_DEPTH {
+                gather_applesoft(&path,depth+1,docs)?;
+            }
+            continue;
+        }
+        let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        if ext.as_deref()==Some("bas") || ext.as_deref()==Some("abas") {
+            docs.push(Document::from_file_path(&path)?);
+        }
+    }
+    Ok(())
+}
+
+/// Analyze every document, keeping the diagnostics at least as severe as `max_rank`
+fn analyze_all(analyzer: &mut dyn Analysis,mut docs: Vec<Document>,ws_uri: &lsp::Url,max_rank: usize) -> Result<Vec<FileReport>,DYNERR> {
+    docs.sort_by(|a,b| a.uri.as_str().cmp(b.uri.as_str()));
+    let mut reports = Vec::new();
+    for doc in docs {
+        analyzer.analyze(&doc)?;
+        // hints are used to dim inactive code, they are never reported
+        let diags: Vec<lsp::Diagnostic> = analyzer.get_diags(&doc).into_iter()
+            .filter(|d| severity_rank(d.severity) <= max_rank)
+            .collect();
+        if !diags.is_empty() {
+            reports.push(FileReport {
+                path: path_in_workspace(&doc.uri,&vec![ws_uri.clone()]),
+                text: doc.text,
+                diags
+            });
+        }
+    }
+    Ok(reports)
+}
+
+pub fn lint(cmd: &clap::ArgMatches) -> STDRESULT {
+    let ws_path = cmd.get_one::<String>("workspace").expect(RCH);
+    let max_rank = match cmd.get_one::<String>("severity").expect(RCH).as_str() {
+        "error" => 1,
+        "warning" => 2,
+        _ => 3
+    };
+    let (ws_dir,ws_uri) = match std::fs::canonicalize(ws_path).map(|p| (lsp::Url::from_directory_path(&p),p)) {
+        Ok((Ok(uri),dir)) => (dir,uri),
+        _ => {
+            error!("workspace directory not found");
+            return Err(Box::new(crate::lang::Error::PathNotFound));
+        }
+    };
+    let config = cmd.get_one::<String>("config");
+    let reports = match cmd.get_one::<String>("type").expect(RCH).as_str() {
+        "atxt" => {
+            let mut analyzer = applesoft::diagnostics::Analyzer::new();
+            if let Some(json) = config {
+                analyzer.update_config(json)?;
+            }
+            let mut docs = Vec::new();
+            gather_applesoft(&ws_dir,0,&mut docs)?;
+            analyze_all(&mut analyzer,docs,&ws_uri,max_rank)?
+        },
+        _ => {
+            let mut analyzer = merlin::diagnostics::Analyzer::new();
+            if let Some(json) = config {
+                analyzer.update_config(json)?;
+            }
+            analyzer.init_workspace(vec![ws_uri.clone()],Vec::new())?;
+            let docs = analyzer.get_workspace().docs.clone();
+            analyze_all(&mut analyzer,docs,&ws_uri,max_rank)?
+        }
+    };
+    let mut counts = [0,0,0];
+    for diag in reports.iter().flat_map(|r| r.diags.iter()) {
+        counts[severity_rank(diag.severity)-1] += 1;
+    }
+    let indent = cmd.get_one::<u16>("indent").copied();
+    match cmd.get_one::<String>("format").expect(RCH).as_str() {
+        "json" => println!("{}",to_json(&reports,counts,indent)),
+        "sarif" => println!("{}",to_sarif(&reports,indent)),
+        _ => {
+            for rep in &reports {
+                eprintln!("{}",rep.path.bold());
+                for diag in &rep.diags {
+                    crate::lang::eprint_diagnostic(diag,&rep.text);
+                }
+            }
+            if counts[1] > 0 {
+                eprintln!("! {} {}",counts[1].to_string().bright_yellow(),"warnings".bright_yellow());
+            }
+            if counts[0] == 0 {
+                eprintln!("\u{2713} {}","Passing".green());
+            } else {
+                eprintln!("\u{2717} {} {}",counts[0].to_string().red(),"errors".red());
+            }
+        }
+    }
+    if counts[0] > 0 || (cmd.get_flag("deny-warnings") && counts[1] > 0) {
+        return Err(Box::new(crate::lang::Error::Syntax));
+    }
+    Ok(())
+}
@@ -0,0 +1,63 @@
+//! # Migrate a DOS 3.3 image to ProDOS
+//!
+//! The conversion is done by `fs::migrate`, this module creates the ProDOS image and writes the report.
+//! The new image has the same kind of disk as the old one, and is never written over an existing file.
+
+use log::error;
+use std::str::FromStr;
+use super::CommandError;
+use crate::fs::migrate::Migrated;
+use crate::img::DiskImageType;
+use crate::STDRESULT;
+
+const RCH: &str = "unreachable was reached";
+
+/// Report as a JSON string
+pub fn to_json(files: &[Migrated],indent: Option<u16>) -> String {
+    let mut list = json::JsonValue::new_array();
+    for m in files {
+        list.push(json::object! {
+            "dos_name": m.dos_name.as_str(),
+            "prodos_name": m.prodos_name.as_str(),
+            "dos_type": hex::encode_upper([m.dos_type]),
+            "prodos_type": hex::encode_upper([m.prodos_type]),
+            "aux": hex::encode_upper((m.aux as u16).to_be_bytes()),
+            "locked": m.locked
+        }).expect(RCH);
+    }
+    let ans = json::object! {
+        "count": files.len(),
+        "files": list
+    };
+    match indent {
+        Some(spaces) => json::stringify_pretty(ans, spaces),
+        None => json::stringify(ans)
+    }
+}
+
+pub fn migrate(cmd: &clap::ArgMatches,opts: &crate::SaveOptions) -> STDRESULT {
+    let src_path = cmd.get_one::<String>("dimg").expect(RCH);
+    let dest_path = cmd.get_one::<String>("out").expect(RCH);
+    let vol = cmd.get_one::<String>("volume").expect(RCH);
+    if std::path::Path::new(dest_path).exists() {
+        error!("cannot overwrite existing disk image");
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    let load_opts = crate::LoadOptions::from_matches(cmd)?;
+    let mut src = crate::create_fs_with(src_path,&load_opts)?;
+    if src.stat()?.fs_name != crate::fs::dos3x::FS_NAME {
+        error!("source should be a DOS 3.x disk");
+        return Err(Box::new(CommandError::UnsupportedFormat));
+    }
+    let kind = src.get_img().kind();
+    let img_typ = match cmd.get_one::<String>("type") {
+        Some(typ) => DiskImageType::from_str(typ)?,
+        None => src.get_img().what_am_i()
+    };
+    let buf = super::mkdsk::create(dest_path,&img_typ,kind,"prodos",Some(vol),false,None,&load_opts)?;
+    let mut dst = crate::create_fs_from_bytestream_with(&buf,dest_path.split('.').next_back(),&load_opts)?;
+    let files = crate::fs::migrate::migrate(&mut src,&mut dst)?;
+    crate::save_img_with(&mut dst,dest_path,opts)?;
+    println!("{}",to_json(&files,cmd.get_one::<u16>("indent").copied()));
+    Ok(())
+}
@@ -11,8 +11,10 @@ use crate::{STDRESULT,DYNERR};
 const RCH: &str = "unreachable was reached";
 const BOOT_MESS: &str = "omit boot flag; for this OS you will need to copy boot files after formatting";
 const BOOT_MESS_CPM: &str = "omit boot flag; for this OS you will need to copy reserved tracks after formatting";
+const BOOT_MESS_A2_CPM: &str = "an Apple II CP/M disk needs the `--system` option to be bootable";
 const BOOT_MESS_FAT: &str = "omit boot flag; for this OS copy reserved sectors and boot files after formatting";
 
+/// IBM kinds that every sector image can record, the 2880K disk is handled separately
 macro_rules! ibm_patterns {
     () => {
         DiskKind::D525(names::IBM_SSDD_8) |
@@ -23,8 +25,7 @@ macro_rules! ibm_patterns {
         DiskKind::D525(names::IBM_DSQD) |
         DiskKind::D525(names::IBM_DSHD) |
         DiskKind::D35(names::IBM_720) |
-        DiskKind::D35(names::IBM_1440) |
-        DiskKind::D35(names::IBM_2880)
+        DiskKind::D35(names::IBM_1440)
     };
 }
 
@@ -41,16 +42,23 @@ macro_rules! cpm_patterns {
     };
 }
 
-/// Create an image of a specific kind of disk.  If the pairing is not explicitly allowed
-/// return an error.  N.b. there is no file system selection whatever at this point.
-fn mkimage(img_typ: &DiskImageType,kind: &DiskKind,maybe_vol: Option<&String>,maybe_wrap: Option<&String>) -> Result<Box<dyn DiskImage>,DYNERR> {
-    let vol = match maybe_vol {
+/// Volume number for the address fields of a nibble image, 254 unless the volume is a number
+fn vol_number(maybe_vol: Option<&String>) -> u8 {
+    match maybe_vol {
         Some(vstr) => match u8::from_str_radix(vstr,10) {
             Ok(v) => v,
             _ => 254
         },
         _ => 254
-    };
+    }
+}
+
+/// Create an image of a specific kind of disk.  If the pairing is not explicitly allowed
+/// return an error.  N.b. there is no file system selection whatever at this point.
+/// If `deterministic` the times some images record are fixed, see `crate::now`.
+pub(crate) fn mkimage(img_typ: &DiskImageType,kind: &DiskKind,maybe_vol: Option<&String>,maybe_wrap: Option<&String>,deterministic: bool) -> Result<Box<dyn DiskImage>,DYNERR> {
+    let vol = vol_number(maybe_vol);
+    let now = crate::now(deterministic);
     match (img_typ,maybe_wrap) {
         (DiskImageType::DOT2MG,None) => {
             error!("selected image type requires the `--wrap` option");
@@ -76,17 +84,21 @@ fn mkimage(img_typ: &DiskImageType,kind: &DiskKind,maybe_vol: Option<&String>,ma
         (DiskImageType::PO,names::A2_400_KIND) => Ok(Box::new(img::dsk_po::PO::create(800))),
         (DiskImageType::PO,names::A2_800_KIND) => Ok(Box::new(img::dsk_po::PO::create(1600))),
         (DiskImageType::PO,names::A2_HD_MAX) => Ok(Box::new(img::dsk_po::PO::create(65535))),
-        (DiskImageType::DOT2MG,names::A2_DOS33_KIND) => img::dot2mg::Dot2mg::create(vol,*kind,maybe_wrap),
-        (DiskImageType::DOT2MG,names::A2_400_KIND) => img::dot2mg::Dot2mg::create(vol,*kind,maybe_wrap),
-        (DiskImageType::DOT2MG,names::A2_800_KIND) => img::dot2mg::Dot2mg::create(vol,*kind,maybe_wrap),
-        (DiskImageType::DOT2MG,names::A2_HD_MAX) => img::dot2mg::Dot2mg::create(vol,*kind,maybe_wrap),
+        (DiskImageType::DOT2MG,names::A2_DOS33_KIND) => img::dot2mg::Dot2mg::create_at(vol,*kind,maybe_wrap,now),
+        (DiskImageType::DOT2MG,names::A2_400_KIND) => img::dot2mg::Dot2mg::create_at(vol,*kind,maybe_wrap,now),
+        (DiskImageType::DOT2MG,names::A2_800_KIND) => img::dot2mg::Dot2mg::create_at(vol,*kind,maybe_wrap,now),
+        (DiskImageType::DOT2MG,names::A2_HD_MAX) => img::dot2mg::Dot2mg::create_at(vol,*kind,maybe_wrap,now),
         (DiskImageType::NIB,names::A2_DOS32_KIND) => Ok(Box::new(img::nib::Nib::create(vol,*kind))),
         (DiskImageType::NIB,names::A2_DOS33_KIND) => Ok(Box::new(img::nib::Nib::create(vol,*kind))),
-        (DiskImageType::IMD,cpm_patterns!()) => Ok(Box::new(img::imd::Imd::create(*kind))),
-        (DiskImageType::TD0,cpm_patterns!()) => Ok(Box::new(img::td0::Td0::create(*kind))),
-        (DiskImageType::IMD,ibm_patterns!()) => Ok(Box::new(img::imd::Imd::create(*kind))),
-        (DiskImageType::TD0,ibm_patterns!()) => Ok(Box::new(img::td0::Td0::create(*kind))),
-        (DiskImageType::IMG,ibm_patterns!()) => Ok(Box::new(img::dsk_img::Img::create(*kind))),
+        (DiskImageType::IMD,cpm_patterns!()) => Ok(Box::new(img::imd::Imd::create_at(*kind,now))),
+        (DiskImageType::TD0,cpm_patterns!()) => Ok(Box::new(img::td0::Td0::create_at(*kind,now))),
+        (DiskImageType::IMD | DiskImageType::TD0,DiskKind::D35(names::IBM_2880)) => {
+            error!("{} cannot record the 1 Mbps data rate of a 2880K disk",img_typ);
+            Err(Box::new(CommandError::UnsupportedItemType))
+        },
+        (DiskImageType::IMD,ibm_patterns!()) => Ok(Box::new(img::imd::Imd::create_at(*kind,now))),
+        (DiskImageType::TD0,ibm_patterns!()) => Ok(Box::new(img::td0::Td0::create_at(*kind,now))),
+        (DiskImageType::IMG,ibm_patterns!() | DiskKind::D35(names::IBM_2880)) => Ok(Box::new(img::dsk_img::Img::create(*kind))),
         _ => {
             error!("pairing of image type and disk kind is not supported");
             Err(Box::new(CommandError::UnsupportedItemType))
@@ -94,8 +106,8 @@ fn mkimage(img_typ: &DiskImageType,kind: &DiskKind,maybe_vol: Option<&String>,ma
     };
 }
 
-fn mkdos3x(vol: Option<&String>,boot: bool,img: Box<dyn DiskImage>) -> Result<Vec<u8>,DYNERR> {
-    if img.byte_capacity()!=35*13*256 && img.byte_capacity()!=35*16*256 {
+fn mkdos3x(vol: Option<&String>,boot: bool,img: Box<dyn DiskImage>,opts: &crate::LoadOptions) -> Result<Vec<u8>,DYNERR> {
+    if img.byte_capacity()!=35*13*256 && img.byte_capacity()!=img.track_count()*16*256 {
         error!("disk image capacity {} not consistent with DOS 3.x",img.byte_capacity());
         return Err(Box::new(CommandError::OutOfRange));
     }
@@ -122,12 +134,12 @@ fn mkdos3x(vol: Option<&String>,boot: bool,img: Box<dyn DiskImage>) -> Result<Ve
                 error!("we can only add the boot tracks if volume number is 254");
                 return Err(Box::new(CommandError::UnsupportedItemType));
             }
-            let mut disk = dos3x::Disk::from_img(img)?;
+            let mut disk = dos3x::Disk::from_img(img)?.with_quirks(opts.quirks.clone());
             match kind {
                 DiskKind::LogicalSectors(img::names::A2_DOS32) => disk.init32(v,boot)?,
                 DiskKind::D525(img::names::A2_DOS32) => disk.init32(v,boot)?,
-                DiskKind::LogicalSectors(img::names::A2_DOS33) => disk.init33(v,boot)?,
-                DiskKind::D525(img::names::A2_DOS33) => disk.init33(v,boot)?,
+                DiskKind::LogicalSectors(img::names::A2_DOS33) => disk.init33_tracks(v,boot)?,
+                DiskKind::D525(img::names::A2_DOS33) => disk.init33_tracks(v,boot)?,
                 _ => {
                     error!("disk incompatible with DOS 3.x");
                     return Err(Box::new(CommandError::UnsupportedFormat));
@@ -142,7 +154,7 @@ fn mkdos3x(vol: Option<&String>,boot: bool,img: Box<dyn DiskImage>) -> Result<Ve
     }
 }
 
-fn mkprodos(vol: Option<&String>,boot: bool,img: Box<dyn DiskImage>) -> Result<Vec<u8>,DYNERR> {
+fn mkprodos(vol: Option<&String>,boot: bool,img: Box<dyn DiskImage>,opts: &crate::LoadOptions) -> Result<Vec<u8>,DYNERR> {
     if boot {
         error!("{}",BOOT_MESS);
         return Err(Box::new(CommandError::UnsupportedItemType));
@@ -154,7 +166,7 @@ fn mkprodos(vol: Option<&String>,boot: bool,img: Box<dyn DiskImage>) -> Result<V
         _ => false
     };
     if let Some(vol_name) = vol {
-        let mut disk = prodos::Disk::from_img(img)?;
+        let mut disk = prodos::Disk::from_img(img)?.with_quirks(opts.quirks.clone()).with_deterministic(opts.deterministic);
         disk.format(vol_name,floppy,None)?;
         return Ok(disk.get_img().to_bytes());
     } else {
@@ -163,13 +175,13 @@ fn mkprodos(vol: Option<&String>,boot: bool,img: Box<dyn DiskImage>) -> Result<V
     }
 }
 
-fn mkpascal(vol: Option<&String>,boot: bool,img: Box<dyn DiskImage>) -> Result<Vec<u8>,DYNERR> {
+fn mkpascal(vol: Option<&String>,boot: bool,img: Box<dyn DiskImage>,opts: &crate::LoadOptions) -> Result<Vec<u8>,DYNERR> {
     if boot {
         error!("{}",BOOT_MESS);
         return Err(Box::new(CommandError::UnsupportedItemType));
     }
     if let Some(vol_name) = vol {
-        let mut disk = pascal::Disk::from_img(img)?;
+        let mut disk = pascal::Disk::from_img(img)?.with_deterministic(opts.deterministic);
         disk.format(vol_name,0xee,None)?;
         return Ok(disk.get_img().to_bytes());
     } else {
@@ -178,10 +190,44 @@ fn mkpascal(vol: Option<&String>,boot: bool,img: Box<dyn DiskImage>) -> Result<V
     }
 }
 
-fn mkcpm(vol: Option<&String>,boot: bool,kind: &DiskKind,img: Box<dyn DiskImage>,vers: u8) -> Result<Vec<u8>,DYNERR> {
-    if boot {
-        error!("{}",BOOT_MESS_CPM);
-        return Err(Box::new(CommandError::UnsupportedItemType));
+/// Copy the CP/M system into the reserved tracks of an Apple II disk.  The system is given as it
+/// appears at the start of a DO image, i.e., tracks 0 through 2 with sectors in DOS 3.3 order.
+/// An entire DO image is also accepted, in which case only the reserved tracks are taken.
+fn inject_a2_cpm_system(img: &mut Box<dyn DiskImage>,sys: &[u8]) -> STDRESULT {
+    let dpb = dpb::A2_525;
+    let capacity = dpb.reserved_track_capacity;
+    if sys.len() != capacity && sys.len() != 35*16*256 {
+        error!("CP/M system should be {} bytes or a whole DO image, got {}",capacity,sys.len());
+        return Err(Box::new(CommandError::OutOfRange));
+    }
+    for track in 0..dpb.off as usize {
+        for sector in 0..16 {
+            let offset = (track*16 + sector)*256;
+            img.write_block(crate::fs::Block::DO([track,sector]),&sys[offset..offset+256])?;
+        }
+    }
+    Ok(())
+}
+
+fn mkcpm(vol: Option<&String>,boot: bool,kind: &DiskKind,img: Box<dyn DiskImage>,vers: u8,system: Option<&[u8]>,opts: &crate::LoadOptions) -> Result<Vec<u8>,DYNERR> {
+    match (boot,system) {
+        (true,None) if *kind==names::A2_DOS33_KIND => {
+            error!("{}",BOOT_MESS_A2_CPM);
+            return Err(Box::new(CommandError::InvalidCommand));
+        },
+        (true,None) => {
+            error!("{}",BOOT_MESS_CPM);
+            return Err(Box::new(CommandError::UnsupportedItemType));
+        },
+        (_,Some(_)) if *kind!=names::A2_DOS33_KIND => {
+            error!("CP/M system can only be installed on an Apple II 5.25 inch disk");
+            return Err(Box::new(CommandError::UnsupportedItemType));
+        },
+        (false,Some(_)) => {
+            error!("the `--system` option requires the boot flag");
+            return Err(Box::new(CommandError::InvalidCommand));
+        },
+        _ => {}
     }
     if vers<3 && vol.is_some() {
         warn!("volume name inapplicable for CP/M version < 3");
@@ -189,24 +235,33 @@ fn mkcpm(vol: Option<&String>,boot: bool,kind: &DiskKind,img: Box<dyn DiskImage>
     let (vol_name,time,cpm_vers) = match vers {
         3 => match vol {
             // notice timestamps are always created
-            Some(nm) => (nm.as_str(),Some(chrono::Local::now().naive_local()),[3,1,0]),
-            None => ("",Some(chrono::Local::now().naive_local()),[3,1,0])
+            Some(nm) => (nm.as_str(),Some(crate::now(opts.deterministic)),[3,1,0]),
+            None => ("",Some(crate::now(opts.deterministic)),[3,1,0])
         },
         2 => ("",None,[2,2,3]),
         _ => panic!("unexpected CP/M version")
     };
-    let mut disk = cpm::Disk::from_img(img,dpb::DiskParameterBlock::create(&kind),cpm_vers)?;
+    let mut disk = cpm::Disk::from_img(img,dpb::DiskParameterBlock::create(kind),cpm_vers)?.with_deterministic(opts.deterministic);
     disk.format(vol_name,time)?;
+    if let Some(sys) = system {
+        inject_a2_cpm_system(disk.get_img(),sys)?;
+    }
     Ok(disk.get_img().to_bytes())
 }
 
-fn mkfat(vol: Option<&String>,boot: bool,img: Box<dyn DiskImage>) -> Result<Vec<u8>,DYNERR> {
+fn mkfat(vol: Option<&String>,boot: bool,img: Box<dyn DiskImage>,opts: &crate::LoadOptions) -> Result<Vec<u8>,DYNERR> {
     if boot {
         error!("{}",BOOT_MESS_FAT);
         return Err(Box::new(CommandError::UnsupportedItemType));
     }
-    let boot_sector = bpb::BootSector::create(&img.kind())?;
-    let mut disk = fat::Disk::from_img(img,Some(boot_sector))?;
+    let mut boot_sector = bpb::BootSector::create(&img.kind())?;
+    if let Some(entries) = opts.quirks.fat_root_entries {
+        if !boot_sector.set_root_dir_entries(entries) {
+            error!("{} root directory entries will not work for this disk",entries);
+            return Err(Box::new(CommandError::OutOfRange));
+        }
+    }
+    let mut disk = fat::Disk::from_img(img,Some(boot_sector))?.with_codepage(opts.codepage).with_deterministic(opts.deterministic);
     let vol_name = match vol {
         Some(nm) => nm.as_str(),
         None => ""
@@ -215,14 +270,15 @@ fn mkfat(vol: Option<&String>,boot: bool,img: Box<dyn DiskImage>) -> Result<Vec<
     Ok(disk.get_img().to_bytes())
 }
 
-pub fn mkdsk(cmd: &clap::ArgMatches) -> STDRESULT {
+pub fn mkdsk(cmd: &clap::ArgMatches,opts: &crate::SaveOptions) -> STDRESULT {
     let dest_path= cmd.get_one::<String>("dimg").expect(RCH);
     let which_fs = cmd.get_one::<String>("os").expect(RCH);
     if !["cpm2","cpm3","dos32","dos33","prodos","pascal","fat"].contains(&which_fs.as_str()) {
         return Err(Box::new(CommandError::UnknownItemType));
     }
-    // First make sure destination is OK
-    let dest_path_abstract = std::path::Path::new(dest_path);
+    // First make sure destination is OK, an image can also be added to a zip archive
+    let (file_path,maybe_member) = crate::img::archive::split_member(dest_path);
+    let dest_path_abstract = std::path::Path::new(file_path);
     if let Some(parent) = std::path::Path::parent(dest_path_abstract) {
         if parent.to_string_lossy().len()>0 {
             match std::path::Path::try_exists(parent) {
@@ -239,6 +295,14 @@ pub fn mkdsk(cmd: &clap::ArgMatches) -> STDRESULT {
         }
     }
     match std::path::Path::try_exists(dest_path_abstract) {
+        Ok(true) if maybe_member.is_some() => {
+            let names = crate::img::archive::zip_names(&std::fs::read(file_path)?)?;
+            if names.iter().any(|nm| nm.eq_ignore_ascii_case(maybe_member.expect(RCH))) {
+                error!("cannot overwrite existing disk image");
+                return Err(Box::new(CommandError::InvalidCommand));
+            }
+            info!("destination archive OK, preparing to write");
+        },
         Ok(true) => {
             error!("cannot overwrite existing disk image");
             return Err(Box::new(CommandError::InvalidCommand));
@@ -250,48 +314,146 @@ pub fn mkdsk(cmd: &clap::ArgMatches) -> STDRESULT {
         }
     }
     // Destination is OK, proceed
-    let maybe_vol = cmd.get_one::<String>("volume");
-    let mut kind = DiskKind::from_str(cmd.get_one::<String>("kind").expect(RCH)).unwrap();
+    let maybe_template = cmd.get_one::<String>("template");
+    if maybe_template.is_some() && which_fs != "dos33" {
+        error!("templates are DOS 3.3 disks, use `-o dos33`");
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    let template_vol = "254".to_string();
+    let maybe_vol = match (cmd.get_one::<String>("volume"),maybe_template) {
+        (None,Some(_)) => Some(&template_vol),
+        (v,_) => v
+    };
+    let kind = DiskKind::from_str(cmd.get_one::<String>("kind").expect(RCH)).unwrap();
     let img_typ = DiskImageType::from_str(cmd.get_one::<String>("type").expect(RCH)).unwrap();
     let maybe_wrap = cmd.get_one::<String>("wrap");
-    // Refine disk kind based on combined inputs
-    if kind==names::A2_DOS33_KIND && which_fs=="dos32" {
-        kind = names::A2_DOS32_KIND;
-    }
-    let boot = cmd.get_flag("bootable");
+    let boot = cmd.get_flag("bootable") || maybe_template.is_some();
+    let load_opts = crate::LoadOptions::from_matches(cmd)?;
     if boot {
         info!("bootable requested");
     }
-    match mkimage(&img_typ,&kind,maybe_vol,maybe_wrap) {
-        Ok(img) => {
-            if let Some(fext) = dest_path.split(".").last() {
-                if !img.file_extensions().contains(&fext.to_string().to_lowercase()) {
-                    error!("Extension was {}, should be {:?}",fext,img.file_extensions());
-                    return Err(Box::new(CommandError::InvalidCommand));
-                }
-            } else {
-                error!("Extension missing, should be {:?}",img.file_extensions());
+    let buf = match cmd.get_one::<u8>("tracks") {
+        Some(tracks) if *tracks != 35 => {
+            if which_fs != "dos33" || img_typ != DiskImageType::DO || kind != names::A2_DOS33_KIND || maybe_wrap.is_some() {
+                error!("track count can only be changed for a DOS 3.3 disk in a DO image");
                 return Err(Box::new(CommandError::InvalidCommand));
             }
-            let result = match which_fs.as_str() {
-                "cpm2" => mkcpm(maybe_vol,boot,&kind,img,2),
-                "cpm3" => mkcpm(maybe_vol,boot,&kind,img,3),
-                "dos32" => mkdos3x(maybe_vol,boot,img),
-                "dos33" => mkdos3x(maybe_vol,boot,img),
-                "prodos" => mkprodos(maybe_vol,boot,img),
-                "pascal" => mkpascal(maybe_vol,boot,img),
-                "fat" => mkfat(maybe_vol,boot,img),
-                _ => panic!("unreachable")
-            };
-            match result {
-                Ok(buf) => {
-                    eprintln!("writing {} bytes",buf.len());
-                    std::fs::write(&dest_path,&buf).expect("could not write data to disk");
-                    Ok(())
-                },
-                Err(e) => Err(e)
+            create_dos33(dest_path,*tracks,maybe_vol,boot,&load_opts)?
+        },
+        _ if cmd.get_one::<String>("system").is_some() => {
+            if !which_fs.starts_with("cpm") {
+                error!("the `--system` option is only for CP/M");
+                return Err(Box::new(CommandError::InvalidCommand));
             }
+            let sys = std::fs::read(cmd.get_one::<String>("system").expect(RCH))?;
+            create_cpm(dest_path,&img_typ,kind,which_fs,maybe_vol,boot,&sys,&load_opts)?
+        },
+        _ if cmd.get_one::<u8>("bit-timing").is_some() || cmd.get_one::<u32>("track-bits").is_some() => {
+            let timing = img::woz::TrackTiming {
+                bit_timing: cmd.get_one::<u8>("bit-timing").copied(),
+                track_bits: cmd.get_one::<u32>("track-bits").map(|b| *b as usize)
+            };
+            create_woz(dest_path,&img_typ,kind,which_fs,maybe_vol,boot,&timing,&load_opts)?
         },
-        Err(e) => Err(e)
+        _ => create(dest_path,&img_typ,kind,which_fs,maybe_vol,boot,maybe_wrap,&load_opts)?
+    };
+    let buf = match maybe_template {
+        Some(template) => {
+            let (_,ext) = dest_path.rsplit_once('.').unwrap_or(("",""));
+            let mut disk = crate::create_fs_from_bytestream_with(&buf,Some(ext),&load_opts)?;
+            super::templates::apply(&mut disk,template)?;
+            disk.get_img().to_bytes()
+        },
+        None => buf
+    };
+    eprintln!("writing {} bytes",buf.len());
+    crate::write_img_file(dest_path,&buf,opts)
+}
+
+/// Create a formatted disk image and return its bytes.  The extension of `dest_path` is checked against the image type.
+/// The file system behaves as `opts` calls for while formatting, the volume is not used.
+#[allow(clippy::too_many_arguments)]
+pub fn create(dest_path: &str,img_typ: &DiskImageType,mut kind: DiskKind,which_fs: &str,maybe_vol: Option<&String>,boot: bool,maybe_wrap: Option<&String>,opts: &crate::LoadOptions) -> Result<Vec<u8>,DYNERR> {
+    // Refine disk kind based on combined inputs
+    if kind==names::A2_DOS33_KIND && which_fs=="dos32" {
+        kind = names::A2_DOS32_KIND;
+    }
+    let img = mkimage(img_typ,&kind,maybe_vol,maybe_wrap,opts.deterministic)?;
+    check_extension(dest_path,img.as_ref())?;
+    format(which_fs,&kind,maybe_vol,boot,img,opts)
+}
+
+/// Create a formatted WOZ disk image with the given bit timing and track length and return its bytes.
+/// The extension of `dest_path` is checked against the image type, `opts` are as in `create`.
+#[allow(clippy::too_many_arguments)]
+pub fn create_woz(dest_path: &str,img_typ: &DiskImageType,mut kind: DiskKind,which_fs: &str,maybe_vol: Option<&String>,boot: bool,timing: &img::woz::TrackTiming,opts: &crate::LoadOptions) -> Result<Vec<u8>,DYNERR> {
+    if kind==names::A2_DOS33_KIND && which_fs=="dos32" {
+        kind = names::A2_DOS32_KIND;
     }
-}
\ No newline at end of file
+    let vol = vol_number(maybe_vol);
+    let img: Box<dyn DiskImage> = match (img_typ,kind) {
+        (DiskImageType::WOZ1,names::A2_DOS32_KIND | names::A2_DOS33_KIND) =>
+            Box::new(img::woz1::Woz1::create_with_timing(vol,kind,timing)?),
+        (DiskImageType::WOZ2,names::A2_DOS32_KIND | names::A2_DOS33_KIND | names::A2_400_KIND | names::A2_800_KIND) =>
+            Box::new(img::woz2::Woz2::create_with_timing(vol,kind,timing)?),
+        _ => {
+            error!("bit timing and track length are only for WOZ images of Apple disks");
+            return Err(Box::new(CommandError::InvalidCommand));
+        }
+    };
+    check_extension(dest_path,img.as_ref())?;
+    format(which_fs,&kind,maybe_vol,boot,img,opts)
+}
+
+fn format(which_fs: &str,kind: &DiskKind,maybe_vol: Option<&String>,boot: bool,img: Box<dyn DiskImage>,opts: &crate::LoadOptions) -> Result<Vec<u8>,DYNERR> {
+    match which_fs {
+        "cpm2" => mkcpm(maybe_vol,boot,kind,img,2,None,opts),
+        "cpm3" => mkcpm(maybe_vol,boot,kind,img,3,None,opts),
+        "dos32" => mkdos3x(maybe_vol,boot,img,opts),
+        "dos33" => mkdos3x(maybe_vol,boot,img,opts),
+        "prodos" => mkprodos(maybe_vol,boot,img,opts),
+        "pascal" => mkpascal(maybe_vol,boot,img,opts),
+        "fat" => mkfat(maybe_vol,boot,img,opts),
+        _ => Err(Box::new(CommandError::UnknownItemType))
+    }
+}
+
+fn check_extension(dest_path: &str,img: &dyn DiskImage) -> STDRESULT {
+    if let Some(fext) = dest_path.split(".").last() {
+        if !img.file_extensions().contains(&fext.to_string().to_lowercase()) {
+            error!("Extension was {}, should be {:?}",fext,img.file_extensions());
+            return Err(Box::new(CommandError::InvalidCommand));
+        }
+    } else {
+        error!("Extension missing, should be {:?}",img.file_extensions());
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    Ok(())
+}
+
+/// Create a formatted DOS 3.3 disk image with a nonstandard number of tracks and return its bytes.
+/// The tracks can number 35 to 50, or 80.  Only DO images are supported, `opts` are as in `create`.
+pub fn create_dos33(dest_path: &str,tracks: u8,maybe_vol: Option<&String>,boot: bool,opts: &crate::LoadOptions) -> Result<Vec<u8>,DYNERR> {
+    if boot && tracks > dos3x::types::MAX_TRACKS_STD {
+        error!("the DOS that would be installed cannot use more than {} tracks",dos3x::types::MAX_TRACKS_STD);
+        return Err(Box::new(CommandError::UnsupportedItemType));
+    }
+    let img: Box<dyn DiskImage> = Box::new(img::dsk_do::DO::create(tracks as u16,16));
+    check_extension(dest_path,img.as_ref())?;
+    mkdos3x(maybe_vol,boot,img,opts)
+}
+
+/// Create a CP/M disk image with the system in the reserved tracks and return its bytes.
+/// Currently this is only for Apple II 5.25 inch disks, `sys` is tracks 0 through 2 in DOS 3.3 order,
+/// see `inject_a2_cpm_system`.  The extension of `dest_path` is checked against the image type, `opts` are as in `create`.
+#[allow(clippy::too_many_arguments)]
+pub fn create_cpm(dest_path: &str,img_typ: &DiskImageType,kind: DiskKind,which_fs: &str,maybe_vol: Option<&String>,boot: bool,sys: &[u8],opts: &crate::LoadOptions) -> Result<Vec<u8>,DYNERR> {
+    let vers = match which_fs {
+        "cpm2" => 2,
+        "cpm3" => 3,
+        _ => return Err(Box::new(CommandError::UnknownItemType))
+    };
+    let img = mkimage(img_typ,&kind,maybe_vol,None,opts.deterministic)?;
+    check_extension(dest_path,img.as_ref())?;
+    mkcpm(maybe_vol,boot,&kind,img,vers,Some(sys),opts)
+}
@@ -3,17 +3,37 @@
 //! Contains modules that run the subcommands.
 
 pub mod mkdsk;
+pub mod build;
+pub mod serve;
+pub mod http;
+pub mod rpc;
+pub mod normalize;
 pub mod put;
 pub mod get;
 pub mod get_img;
 pub mod put_img;
 pub mod completions;
+#[cfg(feature = "browse")]
+pub mod browse;
+pub mod fsdiff;
+pub mod carve;
+pub mod dump;
+pub mod wipe;
+pub mod migrate;
+pub mod lint;
+pub mod entropy;
+pub mod bundle;
+pub mod templates;
+pub mod omf;
+pub mod verify_bin;
+pub mod convert_lang;
 
 use std::str::FromStr;
 use std::io::Read;
+use std::collections::HashMap;
 use log::{debug,error};
 
-use crate::DYNERR;
+use crate::{DYNERR,STDRESULT};
 
 #[derive(thiserror::Error,Debug)]
 pub enum CommandError {
@@ -32,7 +52,9 @@ pub enum CommandError {
     #[error("File not found")]
     FileNotFound,
     #[error("Key not found")]
-    KeyNotFound
+    KeyNotFound,
+    #[error("Some items in the batch failed")]
+    BatchFailures
 }
 
 /// Types of files that may be distinguished by the file system or a2kit.
@@ -58,6 +80,8 @@ pub enum ItemType {
     Sector,
     RawTrack,
     System,
+    Boot,
+    MacBinary,
     Metadata,
     Automatic
 }
@@ -84,6 +108,8 @@ impl FromStr for ItemType {
             "raw_track" => Ok(Self::RawTrack),
             "sec" => Ok(Self::Sector),
             "sys" => Ok(Self::System),
+            "boot" => Ok(Self::Boot),
+            "macbinary" => Ok(Self::MacBinary),
             "meta" => Ok(Self::Metadata),
             "auto" => Ok(Self::Automatic),
             _ => Err(CommandError::UnknownItemType)
@@ -160,6 +186,44 @@ fn parse_sector_request(farg: &str) -> Result<Vec<[usize;3]>,DYNERR> {
     Ok(ans)
 }
 
+/// Map a sector number given with `--addressing` to the physical sector ID that `DiskImage::read_sector` expects.
+/// The physical ID is the one in the sector's address field.
+/// * `dos` - DOS 3.3 logical order, on 13 sector disks this is the same as physical because DOS 3.2 skews the IDs on the track
+/// * `prodos` - ProDOS logical order, i.e., the order of the sectors in a PO image
+/// * `cpm` - Apple CP/M order of 256 byte sectors, numbered from 0
+fn map_sector(kind: &crate::img::DiskKind,addressing: &str,sec: usize) -> Result<usize,DYNERR> {
+    use crate::bios::skew;
+    use crate::img::names;
+    let range_err = |count: usize| -> DYNERR {
+        error!("{} sector must be less than {}",addressing,count);
+        Box::new(CommandError::OutOfRange)
+    };
+    match (addressing,*kind) {
+        ("physical",_) => Ok(sec),
+        ("dos",names::A2_DOS32_KIND) => Ok(sec),
+        ("dos",names::A2_DOS33_KIND) => skew::DOS_LSEC_TO_DOS_PSEC.get(sec).copied().ok_or_else(|| range_err(16)),
+        ("prodos",names::A2_DOS33_KIND) => match sec {
+            0..8 => Ok(2*sec),
+            8..16 => Ok(2*sec-15),
+            _ => Err(range_err(16))
+        },
+        ("cpm",names::A2_DOS33_KIND) => skew::CPM_LSEC_TO_DOS_PSEC.get(2*sec).copied().ok_or_else(|| range_err(16)),
+        _ => {
+            error!("{} addressing is not defined for {}",addressing,kind);
+            Err(Box::new(CommandError::UnsupportedFormat))
+        }
+    }
+}
+
+/// Parse a sector request and map the sectors to physical sector ID's, see `map_sector`.
+fn parse_sector_request_addressed(farg: &str,kind: &crate::img::DiskKind,addressing: &str) -> Result<Vec<[usize;3]>,DYNERR> {
+    let mut ans = parse_sector_request(farg)?;
+    for chs in ans.iter_mut() {
+        chs[2] = map_sector(kind,addressing,chs[2])?;
+    }
+    Ok(ans)
+}
+
 /// parse a sector request in the form `cyl,head` (ranges not allowed)
 fn parse_track_request(farg: &str) -> Result<[usize;2],DYNERR> {
     let fcopy = String::from(farg);
@@ -195,6 +259,25 @@ fn parse_block_request(farg: &str) -> Result<Vec<usize>,DYNERR> {
     Ok(ans)
 }
 
+/// The volume selected with `--volume`, for subcommands that open a multi-volume hard disk image
+pub fn volume_arg(cmd: &clap::ArgMatches) -> Option<usize> {
+    cmd.try_get_one::<usize>("hd-volume").ok().flatten().copied()
+}
+
+/// Write `dat` to the file at `maybe_path`, or to stdout if there is none.  Either way the bytes go out
+/// unchanged, but writing a file directly avoids shells that translate line endings in a pipe.
+pub fn write_output(maybe_path: Option<&String>,dat: &[u8]) -> STDRESULT {
+    match maybe_path {
+        Some(path) => std::fs::write(path,dat)?,
+        None => {
+            let mut stdout = std::io::stdout();
+            std::io::Write::write_all(&mut stdout,dat)?;
+            std::io::Write::flush(&mut stdout)?;
+        }
+    }
+    Ok(())
+}
+
 /// get a JSON object presumed to be a list and log any errors
 fn get_json_list_from_stdin() -> Result<json::JsonValue,DYNERR> {
     let mut raw_list = Vec::new();
@@ -213,6 +296,76 @@ fn get_json_list_from_stdin() -> Result<json::JsonValue,DYNERR> {
     Ok(json_list)
 }
 
+/// Outcome of each item in a batch such as `mget` or `mput`, keyed by path.
+/// The outcomes can be written as a JSON manifest, which a later run can use to retry only the failures.
+struct BatchStatus {
+    /// entries from an earlier run, keyed by path
+    prior: HashMap<String,json::JsonValue>,
+    items: json::JsonValue
+}
+
+impl BatchStatus {
+    /// Start a batch, optionally resuming from the manifest at `resume`
+    fn new(resume: Option<&String>) -> Result<Self,DYNERR> {
+        let mut prior = HashMap::new();
+        if let Some(manifest_path) = resume {
+            let manifest = json::parse(&std::fs::read_to_string(manifest_path)?)?;
+            if !manifest.is_array() {
+                error!("resume manifest should be a JSON list");
+                return Err(Box::new(CommandError::InvalidCommand));
+            }
+            for entry in manifest.members() {
+                if let Some(path) = entry["path"].as_str() {
+                    prior.insert(path.to_string(),entry.clone());
+                }
+            }
+        }
+        Ok(Self { prior, items: json::array![] })
+    }
+    /// If an earlier run succeeded with or skipped `path`, carry its entry forward and return true
+    fn done_before(&mut self,path: &str) -> bool {
+        match self.prior.get(path) {
+            Some(entry) if entry["status"] != "failed" => {
+                debug!("{} was done in an earlier run",path);
+                self.items.push(entry.clone()).expect("could not build JSON");
+                true
+            },
+            _ => false
+        }
+    }
+    fn record(&mut self,path: &str,status: &str,reason: Option<String>) {
+        let mut entry = json::object! { "path": path, "status": status };
+        if let Some(r) = reason {
+            entry["reason"] = r.into();
+        }
+        self.items.push(entry).expect("could not build JSON");
+    }
+    fn ok(&mut self,path: &str) {
+        self.record(path,"ok",None)
+    }
+    fn skipped(&mut self,path: &str,reason: &str) {
+        self.record(path,"skipped",Some(reason.to_string()))
+    }
+    fn failed(&mut self,path: &str,e: &DYNERR) {
+        error!("{}: {}",path,e);
+        self.record(path,"failed",Some(e.to_string()))
+    }
+    /// Write the manifest to `status_path` if given, report the counts to stderr,
+    /// and return an error if anything failed.
+    fn finish(self,status_path: Option<&String>) -> STDRESULT {
+        let count = |s: &str| self.items.members().filter(|e| e["status"] == s).count();
+        let (ok,skipped,failed) = (count("ok"),count("skipped"),count("failed"));
+        eprintln!("{} ok, {} skipped, {} failed",ok,skipped,failed);
+        if let Some(path) = status_path {
+            std::fs::write(path,json::stringify_pretty(self.items,2))?;
+        }
+        match failed {
+            0 => Ok(()),
+            _ => Err(Box::new(CommandError::BatchFailures))
+        }
+    }
+}
+
 #[test]
 fn test_parse_sec_req() {
     let single = "2,0,3";
@@ -0,0 +1,193 @@
+//! # Normalize a directory of images
+//!
+//! Every disk image in a directory is rewritten as one image type, with a uniform file name,
+//! as is convenient when preparing storage for hardware such as Floppy Emu.
+//! Names are lower case with runs of anything other than letters, digits, `-`, and `_` replaced by `_`,
+//! and the extension is the usual one for the image type.
+//!
+//! Each conversion is verified with `testing::verify_convert`, and only saved if it is lossless.
+//! If the destination already holds an image of the target type it is skipped, so an interrupted run can be repeated.
+//! If two images map to the same destination, or the destination is something else, that is a conflict,
+//! and the command exits with an error after the report is printed.  Originals are never removed.
+
+use std::collections::HashMap;
+use std::path::{Path,PathBuf};
+use std::str::FromStr;
+use log::{info,warn};
+use super::CommandError;
+use crate::img::DiskImageType;
+use crate::{STDRESULT,DYNERR};
+
+const RCH: &str = "unreachable was reached";
+
+/// What happened to one image
+#[derive(Clone,Debug,PartialEq)]
+pub enum Outcome {
+    Converted,
+    /// already of the target type, copied under the normalized name
+    Copied,
+    /// the destination already holds an image of the target type
+    Skipped,
+    /// the destination was written by another image, or is not an image of the target type
+    Conflict(String),
+    Failed(String)
+}
+
+pub struct Entry {
+    pub source: PathBuf,
+    pub dest: PathBuf,
+    pub outcome: Outcome
+}
+
+/// Lower case the stem, replacing runs of unusual characters with `_`
+pub fn normalized_stem(stem: &str) -> String {
+    let mut ans = String::new();
+    for c in stem.chars() {
+        match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+            true => ans.push(c.to_ascii_lowercase()),
+            false => if !ans.ends_with('_') {
+                ans.push('_')
+            }
+        }
+    }
+    ans.trim_matches('_').to_string()
+}
+
+fn image_paths(dir: &Path) -> Result<Vec<PathBuf>,DYNERR> {
+    let mut ans = Vec::new();
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        if let Some(ext) = path.extension() {
+            if path.is_file() && crate::KNOWN_FILE_EXTENSIONS.split(',').any(|x| ext.eq_ignore_ascii_case(x)) {
+                ans.push(path);
+            }
+        }
+    }
+    ans.sort();
+    Ok(ans)
+}
+
+/// The usual extension for the image type
+pub fn extension(typ: DiskImageType) -> &'static str {
+    match typ {
+        DiskImageType::D13 => "d13",
+        DiskImageType::DO => "do",
+        DiskImageType::PO => "po",
+        DiskImageType::IMG => "img",
+        DiskImageType::WOZ1 | DiskImageType::WOZ2 => "woz",
+        DiskImageType::IMD => "imd",
+        DiskImageType::DOT2MG => "2mg",
+        DiskImageType::NIB => "nib",
+        DiskImageType::TD0 => "td0"
+    }
+}
+
+fn normalize_one(src: &Path, dest: &Path, typ: DiskImageType) -> Result<Outcome,DYNERR> {
+    if dest.exists() {
+        let already = match crate::create_img_from_file(&dest.to_string_lossy()) {
+            Ok(img) => img.what_am_i() == typ,
+            Err(_) => false
+        };
+        return Ok(match already {
+            true => Outcome::Skipped,
+            false => Outcome::Conflict("destination exists and is not the target type".to_string())
+        });
+    }
+    let src_str = src.to_string_lossy().to_string();
+    let mut img = crate::create_img_from_file(&src_str)?;
+    let (dat,outcome) = match img.what_am_i() == typ {
+        true => (img.to_bytes(),Outcome::Copied),
+        false => {
+            let ans = crate::testing::verify_convert(&src_str, typ)?;
+            if !ans.is_lossless() {
+                return Ok(Outcome::Failed("conversion is not lossless".to_string()));
+            }
+            (ans.converted,Outcome::Converted)
+        }
+    };
+    std::fs::write(dest,&dat)?;
+    Ok(outcome)
+}
+
+/// Normalize the images in `dir`, writing them to `out_dir`
+pub fn normalize_dir(dir: &Path, out_dir: &Path, typ: DiskImageType) -> Result<Vec<Entry>,DYNERR> {
+    let mut ans = Vec::new();
+    // destinations written during this run, and the image that was written there
+    let mut written: HashMap<PathBuf,PathBuf> = HashMap::new();
+    for source in image_paths(dir)? {
+        let stem = source.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let dest = out_dir.join(normalized_stem(&stem) + "." + extension(typ));
+        let outcome = match written.get(&dest) {
+            Some(first) => Outcome::Conflict(format!("same destination as {}",first.display())),
+            None => match normalize_one(&source, &dest, typ) {
+                Ok(o) => o,
+                Err(e) => Outcome::Failed(e.to_string())
+            }
+        };
+        if [Outcome::Converted,Outcome::Copied].contains(&outcome) {
+            written.insert(dest.clone(),source.clone());
+        }
+        match &outcome {
+            Outcome::Failed(why) | Outcome::Conflict(why) => warn!("{}: {}",source.display(),why),
+            o => info!("{}: {:?}",source.display(),o)
+        }
+        ans.push(Entry { source, dest, outcome });
+    }
+    Ok(ans)
+}
+
+/// Summary report as a JSON string
+pub fn to_json(entries: &[Entry], indent: Option<u16>) -> String {
+    let count = |f: fn(&Outcome) -> bool| entries.iter().filter(|e| f(&e.outcome)).count();
+    let mut files = json::JsonValue::new_array();
+    for e in entries {
+        let mut obj = json::object! {
+            "source": e.source.to_string_lossy().to_string(),
+            "dest": e.dest.to_string_lossy().to_string(),
+            "status": match &e.outcome {
+                Outcome::Converted => "converted",
+                Outcome::Copied => "copied",
+                Outcome::Skipped => "skipped",
+                Outcome::Conflict(_) => "conflict",
+                Outcome::Failed(_) => "failed"
+            }
+        };
+        if let Outcome::Failed(why) | Outcome::Conflict(why) = &e.outcome {
+            obj["reason"] = why.as_str().into();
+        }
+        files.push(obj).expect(RCH);
+    }
+    let ans = json::object! {
+        "converted": count(|o| *o == Outcome::Converted),
+        "copied": count(|o| *o == Outcome::Copied),
+        "skipped": count(|o| *o == Outcome::Skipped),
+        "conflicts": count(|o| matches!(o,Outcome::Conflict(_))),
+        "failed": count(|o| matches!(o,Outcome::Failed(_))),
+        "files": files
+    };
+    match indent {
+        Some(spaces) => json::stringify_pretty(ans, spaces),
+        None => json::stringify(ans)
+    }
+}
+
+pub fn normalize(cmd: &clap::ArgMatches) -> STDRESULT {
+    let dir = Path::new(cmd.get_one::<String>("dir").expect(RCH));
+    let out_dir = match cmd.get_one::<String>("out") {
+        Some(out) => Path::new(out),
+        None => dir
+    };
+    if !out_dir.is_dir() {
+        log::error!("{} is not a directory",out_dir.display());
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    let typ = DiskImageType::from_str(cmd.get_one::<String>("type").expect(RCH))?;
+    let entries = normalize_dir(dir, out_dir, typ)?;
+    println!("{}",to_json(&entries, cmd.get_one::<u16>("indent").copied()));
+    let conflicts = entries.iter().filter(|e| matches!(e.outcome,Outcome::Conflict(_))).count();
+    if conflicts > 0 {
+        log::error!("{} images could not be written because of name conflicts",conflicts);
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    Ok(())
+}
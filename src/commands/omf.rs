@@ -0,0 +1,52 @@
+//! # OMF command
+//!
+//! Reads a GS/OS load file from stdin, see `crate::omf`.  The segments are listed as JSON,
+//! or one segment is written to stdout, either as it is in the file, or relocated to an address
+//! so that it can be piped to `dasm`.
+
+use std::io::{Read,Write};
+use crate::omf::LoadFile;
+use super::CommandError;
+use crate::STDRESULT;
+
+pub fn omf(cmd: &clap::ArgMatches) -> STDRESULT {
+    if atty::is(atty::Stream::Stdin) {
+        log::error!("line entry is not supported for `omf`, please pipe the load file in");
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    let mut dat = Vec::new();
+    std::io::stdin().read_to_end(&mut dat)?;
+    if dat.is_empty() {
+        log::error!("omf did not receive any data from previous node");
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    let load_file = LoadFile::from_bytes(&dat)?;
+    let segnum = match cmd.get_one::<u16>("seg") {
+        Some(s) => *s,
+        None => {
+            println!("{}",load_file.to_json(cmd.get_one::<u16>("indent").copied()));
+            return Ok(());
+        }
+    };
+    let seg = match load_file.segment(segnum) {
+        Some(s) => s,
+        None => {
+            log::error!("segment {} is not in the file",segnum);
+            return Err(Box::new(CommandError::OutOfRange));
+        }
+    };
+    let maybe_org = cmd.get_one::<String>("org").map(|org| match org.strip_prefix("0x").or(org.strip_prefix('$')) {
+        Some(hex) => u32::from_str_radix(hex,16),
+        None => org.parse::<u32>()
+    });
+    let out = match maybe_org {
+        Some(Ok(org)) if org < 0x1000000 => load_file.relocate(segnum,&load_file.layout(segnum,org)?)?,
+        Some(_) => {
+            log::error!("origin should be an address below $1000000");
+            return Err(Box::new(CommandError::OutOfRange));
+        },
+        None => seg.data.clone()
+    };
+    std::io::stdout().write_all(&out)?;
+    Ok(())
+}
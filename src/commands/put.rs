@@ -1,14 +1,16 @@
 use clap;
 use std::io::Read;
 use std::str::FromStr;
+use std::collections::HashMap;
 use super::{ItemType,CommandError};
-use crate::fs::FileImage;
-use crate::STDRESULT;
+use crate::fs::{DiskFS,FileImage,IfExists,names};
+use crate::{STDRESULT,DYNERR};
 
+const RCH: &str = "unreachable was reached";
 const RANGED_ACCESS: &str =
 "Writing to multiple blocks is only allowed if the buffers match exactly";
 
-fn pack_primitive(fimg: &mut FileImage, dat: &[u8], load_addr: Option<usize>, typ: ItemType) -> STDRESULT {
+pub(super) fn pack_primitive(fimg: &mut FileImage, dat: &[u8], load_addr: Option<usize>, typ: ItemType) -> STDRESULT {
     match typ {
         ItemType::Raw => fimg.pack_raw(&dat),
         ItemType::Binary => fimg.pack_bin(&dat,load_addr,None),
@@ -23,6 +25,7 @@ fn pack_primitive(fimg: &mut FileImage, dat: &[u8], load_addr: Option<usize>, ty
             let json_str = std::str::from_utf8(&dat)?;
             fimg.pack_rec_str(json_str)
         },
+        ItemType::MacBinary => crate::fs::macbinary::pack(fimg,dat),
         _ => return Err(Box::new(CommandError::UnsupportedItemType))
     }
 }
@@ -56,12 +59,14 @@ pub fn pack(cmd: &clap::ArgMatches) -> STDRESULT {
             return Err(Box::new(CommandError::InvalidCommand));
         }
     };
+    let opts = crate::LoadOptions::from_matches(cmd)?;
+    let now = Some(crate::now(opts.deterministic));
     let mut fimg = match which_fs.as_str() {
-        "cpm2" | "cpm3" => crate::fs::cpm::new_fimg(chunk_len, true, path)?,
+        "cpm2" | "cpm3" => crate::fs::cpm::new_fimg(chunk_len, now, path)?,
         "dos32" | "dos33" => crate::fs::dos3x::new_fimg(chunk_len, path)?,
-        "prodos" => crate::fs::prodos::new_fimg(chunk_len, true, path)?,
-        "pascal" => crate::fs::pascal::new_fimg(chunk_len, true, path)?,
-        "fat" => crate::fs::fat::new_fimg(chunk_len, true, path)?,
+        "prodos" => crate::fs::prodos::new_fimg(chunk_len, now, path)?,
+        "pascal" => crate::fs::pascal::new_fimg(chunk_len, now, path)?,
+        "fat" => crate::fs::fat::new_fimg(chunk_len, now, path, opts.codepage)?,
         _ => return Err(Box::new(CommandError::UnknownItemType))
     };
     pack_primitive(&mut fimg, &dat, load_addr, typ)?;
@@ -69,9 +74,106 @@ pub fn pack(cmd: &clap::ArgMatches) -> STDRESULT {
     Ok(())
 }
 
-pub fn put(cmd: &clap::ArgMatches) -> STDRESULT {
-    if atty::is(atty::Stream::Stdin) {
-        log::error!("cannot use `put` with console input, please pipe something in");
+/// Time to give a file that is put, from `--time` or from the modified time of the host file `input`
+fn file_time(cmd: &clap::ArgMatches,input: Option<&String>) -> Result<Option<chrono::NaiveDateTime>,DYNERR> {
+    if let Some(time) = cmd.get_one::<String>("time") {
+        return match chrono::DateTime::parse_from_rfc3339(time) {
+            Ok(t) => Ok(Some(t.naive_local())),
+            Err(e) => {
+                log::error!("time should be RFC 3339, e.g. 1986-09-15T12:00:00-05:00");
+                Err(Box::new(e))
+            }
+        };
+    }
+    match (cmd.get_flag("preserve-times"),input) {
+        (true,Some(path)) => {
+            let modified = std::fs::metadata(path)?.modified()?;
+            Ok(Some(chrono::DateTime::<chrono::Local>::from(modified).naive_local()))
+        },
+        _ => Ok(None)
+    }
+}
+
+/// Put `dat` at `dest_path`, source types such as `atxt` are transformed first, see `build::pack_source`.
+/// The host file `input` is only used for its time.  Returns the path written, or `None` if skipped.
+fn put_file(cmd: &clap::ArgMatches,disk: &mut Box<dyn DiskFS>,dest_path: &str,typ_str: &str,dat: Vec<u8>,
+        load_addr: Option<usize>,input: Option<&String>) -> Result<Option<String>,DYNERR> {
+    let policy = IfExists::from_str(cmd.get_one::<String>("if-exists").expect(RCH))?;
+    let time = match (file_time(cmd,input)?,typ_str) {
+        (None,"macbinary") => crate::fs::macbinary::modified_time(&dat),
+        (time,_) => time
+    };
+    let mut fimg = disk.new_fimg(None, true, dest_path)?;
+    if typ_str == "any" {
+        let json_str = std::str::from_utf8(&dat)?;
+        fimg = FileImage::from_json(json_str)?;
+        fimg.set_path(dest_path)?;
+    } else {
+        let (typ,dat,load_addr) = super::build::pack_source(typ_str,dat,load_addr,dest_path)?;
+        pack_primitive(&mut fimg, &dat, load_addr, typ)?;
+    }
+    if cmd.get_flag("keep-case") {
+        fimg.set_case();
+    }
+    let written = disk.put_if_exists(&fimg,policy)?;
+    if let (Some(path),Some(time)) = (&written,time) {
+        disk.set_time(path,time)?;
+    }
+    Ok(written)
+}
+
+/// Put every host file matching the glob `pattern` into the directory `dir` of the disk image.
+/// The pattern is expanded here, so it works the same on any platform.  A file that fails is
+/// reported and the rest continue.
+fn put_glob(cmd: &clap::ArgMatches,pattern: &str,dir: &str,opts: &crate::SaveOptions) -> STDRESULT {
+    let img_path = cmd.get_one::<String>("dimg").expect(RCH);
+    let typ_str = cmd.get_one::<String>("type").expect(RCH);
+    let load_addr: Option<usize> = match cmd.get_one::<String>("addr") {
+        Some(a) => Some(usize::from_str(a)?),
+        _ => None
+    };
+    let sources: Vec<std::path::PathBuf> = glob::glob(pattern)?.flatten().filter(|p| p.is_file()).collect();
+    if sources.is_empty() {
+        log::error!("no host files match {}",pattern);
+        return Err(Box::new(CommandError::FileNotFound));
+    }
+    let mut disk = crate::create_fs_with(img_path,&crate::LoadOptions::from_matches(cmd)?)?;
+    let fs_name = disk.stat()?.fs_name;
+    let mut status = super::BatchStatus::new(None)?;
+    let mut taken = Vec::new();
+    for src in sources {
+        let src_str = src.display().to_string();
+        let name = names::from_host(&fs_name,&src,&taken);
+        taken.push(name.clone());
+        let dest_path = match dir {
+            "" => name,
+            d if d.ends_with(['/',':']) => [d,&name].concat(),
+            d => [d,"/",&name].concat()
+        };
+        log::info!("{} -> {}",src_str,dest_path);
+        let snap = disk.snapshot();
+        let result = std::fs::read(&src).map_err(|e| Box::new(e) as DYNERR)
+            .and_then(|dat| put_file(cmd,&mut disk,&dest_path,typ_str,dat,load_addr,Some(&src_str)));
+        match result {
+            Ok(Some(_)) => status.ok(&src_str),
+            Ok(None) => status.skipped(&src_str,"already exists"),
+            Err(e) => {
+                disk.rollback(&snap)?;
+                status.failed(&src_str,&e);
+            }
+        }
+    }
+    crate::save_img_with(&mut disk,img_path,opts)?;
+    status.finish(None)
+}
+
+pub fn put(cmd: &clap::ArgMatches,opts: &crate::SaveOptions) -> STDRESULT {
+    let maybe_input = cmd.get_one::<String>("input");
+    if let (Some(pattern),Some(dir)) = (maybe_input,cmd.get_one::<String>("as-dir")) {
+        return put_glob(cmd,pattern,dir,opts);
+    }
+    if maybe_input.is_none() && atty::is(atty::Stream::Stdin) {
+        log::error!("cannot use `put` with console input, please pipe something in or use `--input`");
         return Err(Box::new(CommandError::InvalidCommand));
     }
     // if !atty::is(atty::Stream::Stdout) {
@@ -81,29 +183,43 @@ pub fn put(cmd: &clap::ArgMatches) -> STDRESULT {
     let maybe_dest_path = cmd.get_one::<String>("file");
     let maybe_typ = cmd.get_one::<String>("type");
     let maybe_img = cmd.get_one::<String>("dimg");
-    let mut dat = Vec::new();
-    std::io::stdin().read_to_end(&mut dat).expect("failed to read input stream");
+    let dat = match maybe_input {
+        Some(path) => std::fs::read(path)?,
+        None => {
+            let mut dat = Vec::new();
+            std::io::stdin().read_to_end(&mut dat).expect("failed to read input stream");
+            dat
+        }
+    };
     if dat.len()==0 {
         log::error!("put did not receive any data from previous node");
         return Err(Box::new(CommandError::InvalidCommand));
     }
 
     match (maybe_typ,maybe_img,maybe_dest_path) {
-        
+
+        // the reserved system tracks do not need a path
+        (Some(typ_str),Some(img_path),_) if typ_str=="boot" => {
+            let mut disk = crate::create_fs_with(img_path,&crate::LoadOptions::from_matches(cmd)?)?;
+            let count = disk.write_boot(&dat)?;
+            log::info!("wrote {} bytes to the system tracks",count);
+            crate::save_img_with(&mut disk,img_path,opts)
+        },
+
         // we are putting a specific item to a disk image
         (Some(typ_str),Some(img_path),Some(dest_path)) => {
             let typ = ItemType::from_str(typ_str)?;
             // For items that don't need a file system, handle differently
             match typ {
-                ItemType::Track | ItemType::RawTrack | ItemType::Sector => return super::put_img::put(cmd,&dat),
-                ItemType::Metadata => return super::put_img::put_meta(cmd,&dat),
+                ItemType::Track | ItemType::RawTrack | ItemType::Sector => return super::put_img::put(cmd,&dat,opts),
+                ItemType::Metadata => return super::put_img::put_meta(cmd,&dat,opts),
                 _ => {}
             }
             let load_addr: Option<usize> = match cmd.get_one::<String>("addr") {
                 Some(a) => Some(usize::from_str(a)?),
                 _ => None
             };
-            let mut disk = crate::create_fs_from_file(img_path)?;
+            let mut disk = crate::create_fs_with(img_path,&crate::LoadOptions::from_matches(cmd)?)?;
 
             // Handle block ranges
             if typ == ItemType::Block {
@@ -127,26 +243,18 @@ pub fn put(cmd: &clap::ArgMatches) -> STDRESULT {
                     log::error!("{}",RANGED_ACCESS);
                     return Err(Box::new(CommandError::InvalidCommand));
                 }
-                return crate::save_img(&mut disk, img_path);
+                return crate::save_img_with(&mut disk,img_path,opts);
             }
 
             // If not a block, handle a file
-            let mut fimg = disk.new_fimg(None, true, dest_path)?;
-            if typ == ItemType::FileImage {
-                let json_str = std::str::from_utf8(&dat)?;
-                fimg = FileImage::from_json(json_str)?;
-                fimg.set_path(dest_path)?;
-            } else {
-                pack_primitive(&mut fimg, &dat, load_addr, typ)?;
-            }
-            disk.put(&fimg)?;
-            crate::save_img(&mut disk,img_path)
+            put_file(cmd,&mut disk,dest_path,typ_str,dat,load_addr,maybe_input)?;
+            crate::save_img_with(&mut disk,img_path,opts)
         },
 
         // this pattern can be used for metadata only
         (Some(type_str),Some(_),None) => {
             match ItemType::from_str(type_str) {
-                Ok(ItemType::Metadata) => return super::put_img::put_meta(cmd,&dat),
+                Ok(ItemType::Metadata) => return super::put_img::put_meta(cmd,&dat,opts),
                 Ok(_) => {
                     log::error!("please narrow the item with `-f`");
                     Err(Box::new(CommandError::InvalidCommand))
@@ -174,7 +282,85 @@ pub fn put(cmd: &clap::ArgMatches) -> STDRESULT {
     }
 }
 
-pub fn mput(cmd: &clap::ArgMatches) -> STDRESULT {
+/// Read all of `--input` or stdin, `verb` is for the error message
+fn read_input(cmd: &clap::ArgMatches,verb: &str) -> Result<Vec<u8>,DYNERR> {
+    let maybe_input = cmd.get_one::<String>("input");
+    if maybe_input.is_none() && atty::is(atty::Stream::Stdin) {
+        log::error!("cannot use `{}` with console input, please pipe something in or use `--input`",verb);
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    match maybe_input {
+        Some(path) => Ok(std::fs::read(path)?),
+        None => {
+            let mut dat = Vec::new();
+            std::io::stdin().read_to_end(&mut dat).expect("failed to read input stream");
+            Ok(dat)
+        }
+    }
+}
+
+/// Append data from stdin or a host file to a file in a disk image.  Text is encoded as the file system
+/// would put it, but without any terminator, so it can follow existing text.
+pub fn append(cmd: &clap::ArgMatches,opts: &crate::SaveOptions) -> STDRESULT {
+    let img_path = cmd.get_one::<String>("dimg").expect(RCH);
+    let dest_path = cmd.get_one::<String>("file").expect(RCH);
+    let dat = read_input(cmd,"append")?;
+    let mut disk = crate::create_fs_with(img_path,&crate::LoadOptions::from_matches(cmd)?)?;
+    let dat = match cmd.get_one::<String>("type").expect(RCH).as_str() {
+        "txt" => {
+            let mut fimg = disk.new_fimg(None,false,dest_path)?;
+            fimg.pack_txt(std::str::from_utf8(&dat)?)?;
+            let mut encoded = fimg.sequence();
+            while encoded.last()==Some(&0) {
+                encoded.pop();
+            }
+            encoded
+        },
+        _ => dat
+    };
+    let len = disk.append(dest_path,&dat)?;
+    log::info!("{} is now {} bytes",dest_path,len);
+    crate::save_img_with(&mut disk,img_path,opts)
+}
+
+/// Write a text banner into free sectors or blocks of a disk image, see `DiskFS::banner`.
+pub fn banner(cmd: &clap::ArgMatches,opts: &crate::SaveOptions) -> STDRESULT {
+    let img_path = cmd.get_one::<String>("dimg").expect(RCH);
+    let msg = cmd.get_one::<String>("message").expect(RCH);
+    if msg.is_empty() {
+        log::error!("banner is empty");
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    let mut disk = crate::create_fs_with(img_path,&crate::LoadOptions::from_matches(cmd)?)?;
+    let mut fimg = disk.new_fimg(None,false,"BANNER")?;
+    fimg.pack_txt(msg)?;
+    let mut encoded = fimg.sequence();
+    while encoded.last()==Some(&0) {
+        encoded.pop();
+    }
+    for addr in disk.banner(&encoded)? {
+        eprintln!("wrote banner to {}",addr);
+    }
+    crate::save_img_with(&mut disk,img_path,opts)
+}
+
+/// Overwrite bytes within a file in a disk image, with data from stdin or a host file.
+pub fn patch(cmd: &clap::ArgMatches,opts: &crate::SaveOptions) -> STDRESULT {
+    let img_path = cmd.get_one::<String>("dimg").expect(RCH);
+    let dest_path = cmd.get_one::<String>("file").expect(RCH);
+    let offset_str = cmd.get_one::<String>("offset").expect(RCH);
+    let offset = match offset_str.strip_prefix("0x").or(offset_str.strip_prefix('$')) {
+        Some(hex) => usize::from_str_radix(hex,16)?,
+        None => usize::from_str(offset_str)?
+    };
+    let dat = read_input(cmd,"patch")?;
+    let mut disk = crate::create_fs_with(img_path,&crate::LoadOptions::from_matches(cmd)?)?;
+    let len = disk.patch(dest_path,offset,&dat)?;
+    log::info!("wrote {} bytes at {}, {} is {} bytes",dat.len(),offset,dest_path,len);
+    crate::save_img_with(&mut disk,img_path,opts)
+}
+
+pub fn mput(cmd: &clap::ArgMatches,opts: &crate::SaveOptions) -> STDRESULT {
     if atty::is(atty::Stream::Stdin) {
         log::error!("line entry is not supported for `mput`, please pipe something in");
         return Err(Box::new(CommandError::InvalidCommand));
@@ -182,32 +368,102 @@ pub fn mput(cmd: &clap::ArgMatches) -> STDRESULT {
     let maybe_dest_path = cmd.get_one::<String>("file");
     let path_to_img = cmd.get_one::<String>("dimg").unwrap();
     let json_list = super::get_json_list_from_stdin()?;
-    let mut disk = crate::create_fs_from_file(&path_to_img)?;
+    let policy = IfExists::from_str(cmd.get_one::<String>("if-exists").expect(RCH))?;
+    let mut disk = crate::create_fs_with(path_to_img,&crate::LoadOptions::from_matches(cmd)?)?;
+    let first: Vec<String> = match cmd.get_many::<String>("first") {
+        Some(names) => names.map(|s| s.to_uppercase()).collect(),
+        None => Vec::new()
+    };
+    let mut at_block = HashMap::new();
+    if let Some(pairs) = cmd.get_many::<String>("at-block") {
+        for pair in pairs {
+            match pair.split_once('=').map(|(n,b)| (n,usize::from_str(b))) {
+                Some((name,Ok(block))) => at_block.insert(name.to_uppercase(),block),
+                _ => {
+                    log::error!("expected NAME=BLOCK, got {}",pair);
+                    return Err(Box::new(CommandError::InvalidCommand));
+                }
+            };
+        }
+    }
 
+    let mut status = super::BatchStatus::new(cmd.get_one::<String>("resume"))?;
+    let mut renamed = Vec::new();
+    let mut fimgs = Vec::new();
     for fimg_value in json_list.members() {
-        let mut fimg = FileImage::from_json(&fimg_value.to_string())?;
-        if let Some(dest_path_primitive) = maybe_dest_path {
-            if ["prodos","fat"].contains(&fimg.file_system.as_str()) {
-                let fname = fimg.full_path.split("/").last().unwrap();
-                let dest_path = match dest_path_primitive.ends_with("/") {
-                    true => [dest_path_primitive,fname].concat(),
-                    false => [dest_path_primitive,"/",fname].concat()
-                };
-                log::debug!("{} overridden by {}",&fimg.full_path,dest_path);
-                fimg.set_path(&dest_path)?;
-            } else if fimg.file_system == "cpm" {
-                let fname = fimg.full_path.split(":").last().unwrap();
-                let dest_path = match dest_path_primitive.ends_with(":") {
-                    true => [dest_path_primitive,fname].concat(),
-                    false => return Err(Box::new(CommandError::UnknownFormat))
-                };
-                log::debug!("{} overridden by {}",&fimg.full_path,dest_path);
-                fimg.set_path(&dest_path)?;
-            } else {
-                log::warn!("ignoring destination path due to flat file system");
+        match FileImage::from_json(&fimg_value.to_string()) {
+            Ok(fimg) => fimgs.push(fimg),
+            Err(e) => status.failed(fimg_value["full_path"].as_str().unwrap_or("?"),&e)
+        }
+    }
+    // names from `--first` in the given order, then the rest in list order
+    let base_name = |fimg: &FileImage| fimg.full_path.rsplit(['/',':']).next().unwrap_or_default().to_uppercase();
+    fimgs.sort_by_key(|fimg| first.iter().position(|n| *n == base_name(fimg)).unwrap_or(first.len()));
+
+    for mut fimg in fimgs {
+        // status is keyed by the path in the input list, so that `--resume` can find it
+        let src_path = fimg.full_path.clone();
+        if status.done_before(&src_path) {
+            continue;
+        }
+        let block = at_block.get(&base_name(&fimg)).copied();
+        let snap = disk.snapshot();
+        match put_one(&mut disk,&mut fimg,maybe_dest_path,block,policy,&mut renamed) {
+            Ok(Some(_)) => status.ok(&src_path),
+            Ok(None) => status.skipped(&src_path,"already exists"),
+            Err(e) => {
+                disk.rollback(&snap)?;
+                status.failed(&src_path,&e);
             }
         }
-        disk.put(&fimg)?;
     }
-    return crate::save_img(&mut disk, path_to_img);
+    crate::save_img_with(&mut disk,path_to_img,opts)?;
+    status.finish(cmd.get_one::<String>("status"))
+}
+
+/// Put one file image for `mput`, overriding the path if `maybe_dest_path` is given,
+/// and starting at `block` if given.  A name that is not valid for the file system is replaced,
+/// avoiding any in `renamed`, which accumulates the replacements.  Returns the path written, or `None` if skipped.
+fn put_one(disk: &mut Box<dyn DiskFS>,fimg: &mut FileImage,maybe_dest_path: Option<&String>,block: Option<usize>,policy: IfExists,renamed: &mut Vec<String>) -> Result<Option<String>,DYNERR> {
+    if let Some(dest_path_primitive) = maybe_dest_path {
+        if ["prodos","fat"].contains(&fimg.file_system.as_str()) {
+            let fname = fimg.full_path.split("/").last().unwrap();
+            let dest_path = match dest_path_primitive.ends_with("/") {
+                true => [dest_path_primitive,fname].concat(),
+                false => [dest_path_primitive,"/",fname].concat()
+            };
+            log::debug!("{} overridden by {}",&fimg.full_path,dest_path);
+            fimg.set_path(&dest_path)?;
+        } else if fimg.file_system == "cpm" {
+            let fname = fimg.full_path.split(":").last().unwrap();
+            let dest_path = match dest_path_primitive.ends_with(":") {
+                true => [dest_path_primitive,fname].concat(),
+                false => return Err(Box::new(CommandError::UnknownFormat))
+            };
+            log::debug!("{} overridden by {}",&fimg.full_path,dest_path);
+            fimg.set_path(&dest_path)?;
+        } else {
+            log::warn!("ignoring destination path due to flat file system");
+        }
+    }
+    let split = match fimg.file_system.as_str() {
+        "cpm" => fimg.full_path.rfind(':'),
+        _ => fimg.full_path.rfind('/')
+    }.map(|i| i+1).unwrap_or(0);
+    let (dir,fname) = fimg.full_path.split_at(split);
+    if !names::is_valid(&fimg.file_system,fname) {
+        let new_name = names::valid_name(&fimg.file_system,fname,renamed);
+        log::warn!("{} is not a valid name, using {}",fname,new_name);
+        let new_path = [dir,&new_name].concat();
+        renamed.push(new_name);
+        fimg.set_path(&new_path)?;
+    }
+    if block.is_some() {
+        disk.allocate_from(block)?;
+    }
+    let result = disk.put_if_exists(fimg,policy);
+    if block.is_some() {
+        disk.allocate_from(None)?;
+    }
+    result
 }
\ No newline at end of file
@@ -13,18 +13,19 @@ const RCH: &str = "unreachable was reached";
 const RANGED_ACCESS: &str =
 "Writing to multiple sectors is only allowed if the buffers match exactly";
 
-pub fn put(cmd: &clap::ArgMatches,dat: &[u8]) -> STDRESULT {
+pub fn put(cmd: &clap::ArgMatches,dat: &[u8],opts: &crate::SaveOptions) -> STDRESULT {
     // presence of arguments should already be resolved
     let dest_path = cmd.get_one::<String>("file").expect(RCH);
     let typ = ItemType::from_str(&cmd.get_one::<String>("type").expect(RCH)).expect(RCH);
+    let addressing = cmd.get_one::<String>("addressing").expect(RCH);
     let img_path = cmd.get_one::<String>("dimg").expect(RCH);
 
-    match crate::create_img_from_file(&img_path) {
+    match crate::create_img_with(img_path,&crate::LoadOptions::from_matches(cmd)?) {
         Ok(mut img) => {
             match typ {
                 ItemType::Sector => {
                     let mut ptr = 0;
-                    let sec_list = super::parse_sector_request(&dest_path)?;
+                    let sec_list = super::parse_sector_request_addressed(dest_path,&img.kind(),addressing)?;
                     let mut chsl = Vec::new();
                     // Gather all the sector sizes, this must be done first so that
                     // we preserve angle-order during the write phase.
@@ -55,19 +56,19 @@ pub fn put(cmd: &clap::ArgMatches,dat: &[u8]) -> STDRESULT {
                 }
                 _ => panic!("{}",RCH)
             };
-            std::fs::write(img_path,img.to_bytes())?;
+            crate::save_raw_img_with(&mut img,img_path,opts)?;
             return Ok(());
         },
         Err(e) => return Err(e)
     }
 }
 
-pub fn put_meta(cmd: &clap::ArgMatches,dat: &[u8]) -> STDRESULT {
+pub fn put_meta(cmd: &clap::ArgMatches,dat: &[u8],opts: &crate::SaveOptions) -> STDRESULT {
     // presence of arguments should already be resolved
     let maybe_selection = cmd.get_one::<String>("file");
     let img_path = cmd.get_one::<String>("dimg").expect(RCH);
 
-    match crate::create_img_from_file(&img_path) {
+    match crate::create_img_with(img_path,&crate::LoadOptions::from_matches(cmd)?) {
         Ok(mut img) => {
             let json_string = String::from_utf8(dat.to_vec())?;
             let parsed = json::parse(&json_string)?;
@@ -104,7 +105,7 @@ pub fn put_meta(cmd: &clap::ArgMatches,dat: &[u8]) -> STDRESULT {
                     img.put_metadata(&curs.key_path(), leaf)?;
                 }
             }
-            std::fs::write(img_path,img.to_bytes())?;
+            crate::save_raw_img_with(&mut img,img_path,opts)?;
             Ok(())
         },
         Err(e) => return Err(e)
@@ -0,0 +1,286 @@
+//! # JSON-RPC interface
+//!
+//! Accepts JSON-RPC 2.0 requests, one per line, and answers each with one line.
+//! Disk images are kept in memory between calls, and reloaded only if the file changes.
+//! Operations that modify an image save it immediately.
+//!
+//! method | params | result
+//! -------|--------|-------
+//! `catalog` | `image`, `path`, `sort` | array of catalog lines, `sort` is `name`, `date`, or `address`
+//! `tree` | `image`, `meta` | file system tree
+//! `stat` | `image` | file system statistics
+//! `get` | `image`, `path`, `type` | `{"text": ...}`, `{"hex": ...}`, or records
+//! `put` | `image`, `path`, `type`, `text` or `hex`, `addr` | `null`
+//! `delete`, `lock`, `unlock`, `mkdir` | `image`, `path` | `null`
+//! `rename` | `image`, `path`, `name` | `null`
+//! `mkdsk` | `image`, `type`, `os`, `kind`, `volume`, `bootable`, `wrap` | `null`
+//! `snapshot` | `image` | `null`, the image state is kept in memory
+//! `rollback` | `image` | `null`, the image returns to the last snapshot and is saved
+//! `close` | `image` | `null`, the image is dropped from memory
+//! `exit` | | `null`, the server stops
+//!
+//! Types are those of `get` and `put`, and `put` also accepts `atxt`, `itxt`, and `mtxt`, which are tokenized.
+//! If an operation that modifies an image fails part way, the image is rolled back to where it was before the call.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::str::FromStr;
+use std::time::SystemTime;
+use json::JsonValue;
+use super::ItemType;
+use crate::fs::{CatalogOrder,DiskFS,UnpackedData};
+use crate::img::{DiskImageType,DiskKind,Snapshot};
+use crate::{STDRESULT,DYNERR};
+
+const RCH: &str = "unreachable was reached";
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const OPERATION_FAILED: i32 = -32000;
+
+struct Cached {
+    disk: Box<dyn DiskFS>,
+    stamp: Option<SystemTime>,
+    /// set by the `snapshot` method
+    snapshot: Option<Snapshot>
+}
+
+/// Error to send back to the client
+struct RpcError {
+    code: i32,
+    message: String
+}
+
+impl RpcError {
+    fn new(code: i32, message: &str) -> Self {
+        Self { code, message: message.to_string() }
+    }
+}
+
+impl From<DYNERR> for RpcError {
+    fn from(e: DYNERR) -> Self {
+        Self { code: OPERATION_FAILED, message: e.to_string() }
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(crate::img::archive::split_member(path).0).and_then(|m| m.modified()).ok()
+}
+
+fn str_param<'a>(params: &'a JsonValue, key: &str) -> Result<&'a str,RpcError> {
+    match params[key].as_str() {
+        Some(s) => Ok(s),
+        None => Err(RpcError::new(INVALID_PARAMS,&format!("missing string parameter `{}`",key)))
+    }
+}
+
+/// Handles requests, keeping disk images in memory
+#[derive(Default)]
+pub struct RpcServer {
+    cache: HashMap<String,Cached>,
+    done: bool,
+    load_options: crate::LoadOptions,
+    save_options: crate::SaveOptions
+}
+
+impl RpcServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn set_load_options(&mut self, opts: crate::LoadOptions) {
+        self.load_options = opts;
+    }
+    pub fn set_save_options(&mut self, opts: crate::SaveOptions) {
+        self.save_options = opts;
+    }
+    /// True once the `exit` method was called
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+    /// Get the image from the cache, loading it if it is not there or the file changed
+    fn disk(&mut self, path: &str) -> Result<&mut Box<dyn DiskFS>,RpcError> {
+        let stamp = modified(path);
+        let stale = match self.cache.get(path) {
+            Some(c) => c.stamp != stamp,
+            None => true
+        };
+        if stale {
+            let disk = crate::create_fs_with(path, &self.load_options)?;
+            self.cache.insert(path.to_string(), Cached { disk, stamp, snapshot: None });
+        }
+        Ok(&mut self.cache.get_mut(path).expect(RCH).disk)
+    }
+    /// Save the cached image and update its stamp
+    fn save(&mut self, path: &str) -> Result<(),RpcError> {
+        if let Some(c) = self.cache.get_mut(path) {
+            crate::save_img_with(&mut c.disk, path, &self.save_options)?;
+            c.stamp = modified(path);
+        }
+        Ok(())
+    }
+    /// Apply `f` to the image, rolling back if it fails
+    fn atomic(&mut self, image: &str, f: impl FnOnce(&mut Box<dyn DiskFS>) -> STDRESULT) -> Result<(),RpcError> {
+        let disk = self.disk(image)?;
+        let before = disk.snapshot();
+        if let Err(e) = f(disk) {
+            disk.rollback(&before)?;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+    fn modify(&mut self, params: &JsonValue, f: impl FnOnce(&mut Box<dyn DiskFS>,&str) -> STDRESULT) -> Result<JsonValue,RpcError> {
+        let image = str_param(params,"image")?;
+        let path = str_param(params,"path")?;
+        self.atomic(image, |disk| f(disk,path))?;
+        self.save(image)?;
+        Ok(JsonValue::Null)
+    }
+    fn rollback(&mut self, params: &JsonValue) -> Result<JsonValue,RpcError> {
+        let image = str_param(params,"image")?;
+        self.disk(image)?;
+        let c = self.cache.get_mut(image).expect(RCH);
+        match &c.snapshot {
+            Some(snap) => c.disk.rollback(snap)?,
+            None => return Err(RpcError::new(OPERATION_FAILED,"no snapshot was taken"))
+        }
+        self.save(image)?;
+        Ok(JsonValue::Null)
+    }
+    fn get(&mut self, params: &JsonValue) -> Result<JsonValue,RpcError> {
+        let path = str_param(params,"path")?;
+        let typ = ItemType::from_str(str_param(params,"type")?).map_err(|e| RpcError::new(INVALID_PARAMS,&e.to_string()))?;
+        let disk = self.disk(str_param(params,"image")?)?;
+        let fimg = disk.get(path)?;
+        Ok(match super::get::unpack_primitive(&fimg, typ, None, false, None)? {
+            UnpackedData::Text(txt) => json::object! { "text": txt },
+            UnpackedData::Binary(dat) => json::object! { "hex": hex::encode_upper(dat) },
+            UnpackedData::Records(recs) => json::parse(&recs.to_json(None)).map_err(|e| RpcError::new(OPERATION_FAILED,&e.to_string()))?
+        })
+    }
+    fn put(&mut self, params: &JsonValue) -> Result<JsonValue,RpcError> {
+        let image = str_param(params,"image")?;
+        let path = str_param(params,"path")?;
+        let typ = str_param(params,"type")?;
+        let dat = match (params["text"].as_str(),params["hex"].as_str()) {
+            (Some(txt),None) => txt.as_bytes().to_vec(),
+            (None,Some(h)) => hex::decode(h).map_err(|e| RpcError::new(INVALID_PARAMS,&e.to_string()))?,
+            _ => return Err(RpcError::new(INVALID_PARAMS,"one of `text` or `hex` is required"))
+        };
+        let (typ,dat,addr) = super::build::pack_source(typ, dat, params["addr"].as_usize(), path)?;
+        self.atomic(image, |disk| super::build::put_item(disk, path, typ, &dat, addr))?;
+        self.save(image)?;
+        Ok(JsonValue::Null)
+    }
+    fn mkdsk(&mut self, params: &JsonValue) -> Result<JsonValue,RpcError> {
+        let image = str_param(params,"image")?;
+        let img_typ = DiskImageType::from_str(str_param(params,"type")?).map_err(|e| RpcError::new(INVALID_PARAMS,&e.to_string()))?;
+        let kind = DiskKind::from_str(params["kind"].as_str().unwrap_or("5.25in")).map_err(|e| RpcError::new(INVALID_PARAMS,&e.to_string()))?;
+        let vol = match &params["volume"] {
+            v if v.is_number() => Some(v.to_string()),
+            v => v.as_str().map(|s| s.to_string())
+        };
+        let wrap = params["wrap"].as_str().map(|s| s.to_string());
+        let buf = super::mkdsk::create(image, &img_typ, kind, str_param(params,"os")?, vol.as_ref(),
+            params["bootable"].as_bool().unwrap_or(false), wrap.as_ref(), &self.load_options)?;
+        std::fs::write(image, &buf).map_err(|e| RpcError::new(OPERATION_FAILED,&e.to_string()))?;
+        self.cache.remove(image);
+        Ok(JsonValue::Null)
+    }
+    fn dispatch(&mut self, method: &str, params: &JsonValue) -> Result<JsonValue,RpcError> {
+        match method {
+            "catalog" => {
+                let path = params["path"].as_str().unwrap_or("/");
+                let order = match params["sort"].as_str() {
+                    Some(key) => CatalogOrder::from_str(key).map_err(|e| RpcError::new(INVALID_PARAMS,&e.to_string()))?,
+                    None => CatalogOrder::Directory
+                };
+                let lines = self.disk(str_param(params,"image")?)?.catalog_sorted(path,order)?;
+                Ok(JsonValue::from(lines))
+            },
+            "tree" => {
+                let meta = params["meta"].as_bool().unwrap_or(false);
+                let tree = self.disk(str_param(params,"image")?)?.tree(meta,None)?;
+                json::parse(&tree).map_err(|e| RpcError::new(OPERATION_FAILED,&e.to_string()))
+            },
+            "stat" => {
+                let stat = self.disk(str_param(params,"image")?)?.stat()?.to_json(None);
+                json::parse(&stat).map_err(|e| RpcError::new(OPERATION_FAILED,&e.to_string()))
+            },
+            "get" => self.get(params),
+            "put" => self.put(params),
+            "delete" => self.modify(params, |disk,path| disk.delete(path)),
+            "lock" => self.modify(params, |disk,path| disk.lock(path)),
+            "unlock" => self.modify(params, |disk,path| disk.unlock(path)),
+            "mkdir" => self.modify(params, |disk,path| disk.create(path)),
+            "rename" => {
+                let name = str_param(params,"name")?.to_string();
+                self.modify(params, |disk,path| disk.rename(path,&name))
+            },
+            "mkdsk" => self.mkdsk(params),
+            "snapshot" => {
+                let image = str_param(params,"image")?;
+                let snap = self.disk(image)?.snapshot();
+                self.cache.get_mut(image).expect(RCH).snapshot = Some(snap);
+                Ok(JsonValue::Null)
+            },
+            "rollback" => self.rollback(params),
+            "close" => {
+                self.cache.remove(str_param(params,"image")?);
+                Ok(JsonValue::Null)
+            },
+            "exit" => {
+                self.done = true;
+                Ok(JsonValue::Null)
+            },
+            _ => Err(RpcError::new(METHOD_NOT_FOUND,&format!("unknown method {}",method)))
+        }
+    }
+    /// Handle one request line and return the response line.
+    /// Notifications, i.e. requests without an id, get an empty response.
+    pub fn handle(&mut self, line: &str) -> String {
+        let req = match json::parse(line) {
+            Ok(r) => r,
+            Err(e) => return json::stringify(json::object! {
+                "jsonrpc": "2.0", "id": JsonValue::Null, "error": { "code": PARSE_ERROR, "message": e.to_string() }
+            })
+        };
+        let id = req["id"].clone();
+        let result = match req["method"].as_str() {
+            Some(method) => self.dispatch(method, &req["params"]),
+            None => Err(RpcError::new(INVALID_REQUEST,"missing method"))
+        };
+        if id.is_null() {
+            return String::new();
+        }
+        json::stringify(match result {
+            Ok(val) => json::object! { "jsonrpc": "2.0", "id": id, "result": val },
+            Err(e) => json::object! { "jsonrpc": "2.0", "id": id, "error": { "code": e.code, "message": e.message } }
+        })
+    }
+}
+
+/// Answer requests from stdin until it closes or `exit` is called
+pub fn run(load: &crate::LoadOptions, opts: &crate::SaveOptions) -> STDRESULT {
+    let mut server = RpcServer::new();
+    server.set_load_options(load.clone());
+    server.set_save_options(opts.clone());
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let resp = server.handle(&line);
+        if !resp.is_empty() {
+            println!("{}",resp);
+        }
+        if server.is_done() {
+            break;
+        }
+    }
+    if !server.is_done() {
+        log::info!("input closed");
+    }
+    Ok(())
+}
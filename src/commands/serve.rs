@@ -0,0 +1,178 @@
+//! # Block server
+//!
+//! Serves disk images to a real Apple II as virtual ProDOS drives, using the protocol of
+//! ADTPro's virtual drive client.  Every message starts with an envelope with a 1 byte checksum:
+//!
+//! * request: `$C5`, command, block (2 bytes), checksum
+//! * read reply: `$C5`, command, block (2 bytes), ProDOS date and time (4 bytes), checksum, data (512 bytes), checksum
+//! * write: the request is followed by data (512 bytes), checksum, the reply is `$C5`, command, block (2 bytes), checksum
+//!
+//! Commands are `$03` to read and `$02` to write drive 1, and `$05` to read and `$04` to write drive 2.
+//! Checksums are the XOR of the bytes they cover.  Requests with a bad checksum are not answered,
+//! which causes the client to retry.  Writes are saved to the host file immediately.
+//! The protocol has no error reply, so if a block cannot be read or written, or write data has a bad
+//! checksum, the connection is closed rather than leaving the client to take the request as done.
+//!
+//! The transport can be TCP, or a serial device opened as a file, in which case the port settings
+//! have to be made beforehand, e.g. with `stty`.
+
+use std::io::{Read,Write};
+use log::{debug,info,warn,error};
+use super::CommandError;
+use crate::fs::Block;
+use crate::img::DiskImage;
+use crate::STDRESULT;
+
+const ENVELOPE: u8 = 0xc5;
+const BLOCK_SIZE: usize = 512;
+
+struct Drive {
+    path: String,
+    img: Box<dyn DiskImage>
+}
+
+/// Virtual drives backed by disk images
+pub struct BlockServer {
+    drives: Vec<Drive>,
+    save_options: crate::SaveOptions
+}
+
+fn checksum(dat: &[u8]) -> u8 {
+    dat.iter().fold(0,|acc,b| acc ^ b)
+}
+
+impl BlockServer {
+    /// Create a server for one or two disk images, the first is drive 1
+    pub fn new(paths: &[String]) -> Result<Self,crate::DYNERR> {
+        Self::new_with(paths,&crate::LoadOptions::default())
+    }
+    /// Create a server as in `new`, loading the images with the given options
+    pub fn new_with(paths: &[String], opts: &crate::LoadOptions) -> Result<Self,crate::DYNERR> {
+        if paths.is_empty() || paths.len() > 2 {
+            error!("serve one or two disk images");
+            return Err(Box::new(CommandError::InvalidCommand));
+        }
+        let mut drives = Vec::new();
+        for path in paths {
+            drives.push(Drive { path: path.to_string(), img: crate::create_img_with(path,opts)? });
+        }
+        Ok(Self { drives, save_options: crate::SaveOptions::default() })
+    }
+    /// Options used each time a write is saved to the host file
+    pub fn set_save_options(&mut self, opts: crate::SaveOptions) {
+        self.save_options = opts;
+    }
+    /// Drive index and whether this is a write, or None if the command is unknown
+    fn decode_command(&self, cmd: u8) -> Option<(usize,bool)> {
+        let ans = match cmd {
+            0x02 => (0,true),
+            0x03 => (0,false),
+            0x04 => (1,true),
+            0x05 => (1,false),
+            _ => return None
+        };
+        match ans.0 < self.drives.len() {
+            true => Some(ans),
+            false => None
+        }
+    }
+    /// Handle one request, returns false if the stream has ended
+    pub fn respond<S: Read + Write>(&mut self, stream: &mut S) -> Result<bool,crate::DYNERR> {
+        let mut byte = [0;1];
+        // skip anything that is not the start of an envelope
+        loop {
+            if stream.read(&mut byte)? == 0 {
+                return Ok(false);
+            }
+            if byte[0] == ENVELOPE {
+                break;
+            }
+            debug!("skipping byte {:02X}",byte[0]);
+        }
+        let mut req = [ENVELOPE,0,0,0,0];
+        stream.read_exact(&mut req[1..])?;
+        if checksum(&req[0..4]) != req[4] {
+            warn!("bad request checksum");
+            return Ok(true);
+        }
+        let (drive,is_write) = match self.decode_command(req[1]) {
+            Some(x) => x,
+            None => {
+                warn!("unsupported command {:02X}",req[1]);
+                return Ok(true);
+            }
+        };
+        let block = u16::from_le_bytes([req[2],req[3]]) as usize;
+        match is_write {
+            true => self.write(stream, &req, drive, block)?,
+            false => self.read(stream, &req, drive, block)?
+        }
+        Ok(true)
+    }
+    fn read<S: Read + Write>(&mut self, stream: &mut S, req: &[u8], drive: usize, block: usize) -> STDRESULT {
+        debug!("read block {} of drive {}",block,drive + 1);
+        let dat = match self.drives[drive].img.read_block(Block::PO(block)) {
+            Ok(dat) => dat,
+            Err(e) => {
+                error!("could not read block {}: {}",block,e);
+                return Err(e);
+            }
+        };
+        let mut reply = req[0..4].to_vec();
+        reply.extend_from_slice(&crate::fs::prodos::pack::pack_time(None));
+        reply.push(checksum(&reply));
+        reply.extend_from_slice(&dat);
+        reply.push(checksum(&dat));
+        stream.write_all(&reply)?;
+        stream.flush()?;
+        Ok(())
+    }
+    fn write<S: Read + Write>(&mut self, stream: &mut S, req: &[u8], drive: usize, block: usize) -> STDRESULT {
+        debug!("write block {} of drive {}",block,drive + 1);
+        let mut dat = [0;BLOCK_SIZE + 1];
+        stream.read_exact(&mut dat)?;
+        if checksum(&dat[0..BLOCK_SIZE]) != dat[BLOCK_SIZE] {
+            error!("bad data checksum for block {}",block);
+            return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData,"bad data checksum")));
+        }
+        let d = &mut self.drives[drive];
+        if let Err(e) = d.img.write_block(Block::PO(block), &dat[0..BLOCK_SIZE]) {
+            error!("could not write block {}: {}",block,e);
+            return Err(e);
+        }
+        crate::save_raw_img_with(&mut d.img, &d.path, &self.save_options)?;
+        let mut reply = req[0..4].to_vec();
+        reply.push(checksum(&reply));
+        stream.write_all(&reply)?;
+        stream.flush()?;
+        Ok(())
+    }
+    /// Handle requests until the stream ends, or until a request fails, in which case the error is returned
+    /// and the caller should close the stream
+    pub fn run<S: Read + Write>(&mut self, stream: &mut S) -> STDRESULT {
+        while self.respond(stream)? {}
+        Ok(())
+    }
+}
+
+/// Accept TCP connections on `addr`, such as `0.0.0.0:6502`, one client at a time.  Never returns normally.
+pub fn serve_tcp(server: &mut BlockServer, addr: &str) -> STDRESULT {
+    let listener = std::net::TcpListener::bind(addr)?;
+    eprintln!("serving {} drive(s) on {}",server.drives.len(),listener.local_addr()?);
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        info!("connection from {}",stream.peer_addr()?);
+        if let Err(e) = server.run(&mut stream) {
+            error!("connection ended: {}",e);
+        }
+    }
+    Ok(())
+}
+
+/// Serve over a serial device that is already configured, such as `/dev/ttyUSB0`.
+/// A failed request ends serving, see `BlockServer::run`.
+pub fn serve_serial(server: &mut BlockServer, device: &str) -> STDRESULT {
+    let mut port = std::fs::OpenOptions::new().read(true).write(true).open(device)?;
+    eprintln!("serving {} drive(s) on {}",server.drives.len(),device);
+    server.run(&mut port)
+}
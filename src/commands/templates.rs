@@ -0,0 +1,79 @@
+//! # Disk templates
+//!
+//! A template is a bootable DOS 3.3 disk with a runnable starting point already on it.
+//! * `applesoft-boot` - the greeting program `HELLO` is a stub Applesoft program
+//! * `merlin-hello` - `T.HELLO` is Merlin source, `HELLO.OBJ` is its object code, and the greeting program runs it
+//!
+//! The programs are kept here as source, and are tokenized or assembled when the disk is made.
+
+use std::sync::Arc;
+use crate::lang::{applesoft,merlin};
+use crate::lang::server::Analysis;
+use crate::fs::DiskFS;
+use super::CommandError;
+use crate::DYNERR;
+
+pub const TEMPLATES: [&str;2] = ["applesoft-boot","merlin-hello"];
+
+const APPLESOFT_HELLO: &str = "10 HOME
+20 PRINT \"HELLO FROM A2KIT\"
+30 PRINT
+40 PRINT \"EDIT THIS PROGRAM, THEN SAVE HELLO\"
+";
+
+const MERLIN_HELLO: &str = "* PRINT A MESSAGE USING THE MONITOR
+COUT     EQU   $FDED
+         ORG   $0300
+START    LDX   #0
+LOOP     LDA   MSG,X
+         BEQ   DONE
+         JSR   COUT
+         INX
+         BNE   LOOP
+DONE     RTS
+MSG      ASC   \"HELLO FROM MERLIN\"
+         HEX   8D00
+";
+
+const MERLIN_GREETING: &str = "10 PRINT CHR$(4);\"BRUN HELLO.OBJ\"
+20 END
+";
+
+/// Tokenize the Applesoft source and save it as `name`
+fn save_applesoft(disk: &mut Box<dyn DiskFS>,name: &str,src: &str) -> Result<(),DYNERR> {
+    let mut tokenizer = applesoft::tokenizer::Tokenizer::new();
+    let tok = tokenizer.tokenize(src,2049)?;
+    disk.save(name,&tok,super::ItemType::ApplesoftTokens,None)?;
+    Ok(())
+}
+
+/// Assemble the Merlin source, returning the object code
+fn assemble(src: &str) -> Result<Vec<u8>,DYNERR> {
+    let mut analyzer = merlin::diagnostics::Analyzer::new();
+    let doc = crate::lang::Document::from_string(src.to_string(),0);
+    analyzer.analyze(&doc)?;
+    let symbols = Arc::new(analyzer.get_symbols());
+    let mut asm = merlin::assembly::Assembler::new();
+    asm.use_shared_symbols(symbols);
+    asm.assemble(&doc.text,None)
+}
+
+/// Put the template's files on a freshly made disk, the disk should be DOS 3.3 and bootable.
+pub fn apply(disk: &mut Box<dyn DiskFS>,template: &str) -> Result<(),DYNERR> {
+    match template {
+        "applesoft-boot" => save_applesoft(disk,"HELLO",APPLESOFT_HELLO),
+        "merlin-hello" => {
+            let mut tokenizer = merlin::tokenizer::Tokenizer::new();
+            let src = tokenizer.tokenize(MERLIN_HELLO.to_string())?;
+            let mut fimg = disk.new_fimg(None,true,"T.HELLO")?;
+            fimg.pack_raw(&src)?;
+            disk.put(&fimg)?;
+            disk.bsave("HELLO.OBJ",&assemble(MERLIN_HELLO)?,Some(0x300),None)?;
+            save_applesoft(disk,"HELLO",MERLIN_GREETING)
+        },
+        _ => {
+            log::error!("unknown template {}",template);
+            Err(Box::new(CommandError::UnknownItemType))
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! # Verify a binary from its entry points
+//!
+//! Reads a binary from stdin, places it at its load address, and follows every execution path
+//! from the entry points, see `lang::merlin::disassembly::paths`.  Any illegal opcode, or a path
+//! that wanders out of the binary, is an error.  This is meant as a smoke test in CI for
+//! assembled binaries that are going onto a disk.
+
+use std::io::Read;
+use std::str::FromStr;
+use colored::Colorize;
+use crate::lang::merlin::{self,ProcessorType};
+use crate::lang::merlin::disassembly::Disassembler;
+use super::CommandError;
+use crate::STDRESULT;
+
+const RCH: &str = "unreachable was reached";
+
+/// Parse decimal, or hex with `$` or `0x` prefix
+fn parse_address(s: &str) -> Result<usize,CommandError> {
+    let s = s.trim();
+    let maybe_addr = match s.strip_prefix("0x").or(s.strip_prefix('$')) {
+        Some(hex) => usize::from_str_radix(hex,16).ok(),
+        None => usize::from_str(s).ok()
+    };
+    match maybe_addr {
+        Some(addr) if addr < 0x10000 => Ok(addr),
+        _ => {
+            log::error!("address {} did not parse as a 16 bit unsigned integer",s);
+            Err(CommandError::OutOfRange)
+        }
+    }
+}
+
+pub fn verify_bin(cmd: &clap::ArgMatches) -> STDRESULT {
+    if atty::is(atty::Stream::Stdin) {
+        log::error!("line entry is not supported for `verify -t bin`, please pipe something in");
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    let proc = match cmd.get_one::<String>("proc").expect(RCH).as_str() {
+        "6502" => ProcessorType::_6502,
+        "65c02" => ProcessorType::_65c02,
+        "65802" => ProcessorType::_65802,
+        "65816" => ProcessorType::_65c816,
+        _ => panic!("{}",RCH)
+    };
+    let org = match cmd.get_one::<String>("org") {
+        Some(s) => parse_address(s)?,
+        None => {
+            log::error!("`verify -t bin` needs the load address, use `--org`");
+            return Err(Box::new(CommandError::InvalidCommand));
+        }
+    };
+    let entries = match cmd.get_one::<String>("entry") {
+        Some(list) => list.split(',').map(parse_address).collect::<Result<Vec<usize>,CommandError>>()?,
+        None => vec![org]
+    };
+    let mut img: Vec<u8> = vec![0;org];
+    std::io::stdin().read_to_end(&mut img)?;
+    if img.len()==org {
+        log::error!("verify did not receive any data from previous node");
+        return Err(Box::new(CommandError::InvalidCommand));
+    }
+    let mut config = merlin::settings::Settings::new();
+    config.extensions.undocumented = cmd.get_flag("undocumented");
+    let mut dasm = Disassembler::new();
+    dasm.set_config(config);
+    let report = dasm.trace_entry_points(&img,[org,img.len()],&entries,proc);
+    for fault in &report.faults {
+        eprintln!("{}",fault);
+    }
+    eprintln!("{} instructions reached from {} entry points",report.instructions,entries.len());
+    if report.faults.is_empty() {
+        eprintln!("\u{2713} {}","Passing".green());
+        Ok(())
+    } else {
+        eprintln!("\u{2717} {} {}",report.faults.len().to_string().red(),"errors".red());
+        Err(Box::new(crate::lang::Error::Syntax))
+    }
+}
@@ -0,0 +1,60 @@
+//! # Wipe free space, deleted entries, or chosen sectors
+//!
+//! Free space and deleted entries are handled by the file system, see `DiskFS::wipe_free` and
+//! `DiskFS::wipe_deleted`.  Sectors and blocks are given as for `dump`, and are overwritten
+//! whether or not they are in use, so this can break the file system.
+
+use log::error;
+use super::CommandError;
+use crate::img::DiskImage;
+use crate::{STDRESULT,DYNERR};
+
+const RCH: &str = "unreachable was reached";
+
+/// Fill every sector in `range` with `pattern`, returning the number of sectors
+fn wipe_sectors(img: &mut Box<dyn DiskImage>,range: &str,pattern: &[u8]) -> Result<usize,DYNERR> {
+    let list = super::dump::sectors_in_range(img,range)?;
+    for [cyl,head,sec] in &list {
+        let size = img.read_sector(*cyl,*head,*sec)?.len();
+        let dat: Vec<u8> = pattern.iter().cycle().take(size).copied().collect();
+        img.write_sector(*cyl,*head,*sec,&dat)?;
+    }
+    Ok(list.len())
+}
+
+pub fn wipe(cmd: &clap::ArgMatches,opts: &crate::SaveOptions) -> STDRESULT {
+    let img_path = cmd.get_one::<String>("dimg").expect(RCH);
+    let pattern = match hex::decode(cmd.get_one::<String>("pattern").expect(RCH)) {
+        Ok(pattern) if !pattern.is_empty() => pattern,
+        _ => {
+            error!("fill pattern should be a nonempty hex string");
+            return Err(Box::new(CommandError::InvalidCommand));
+        }
+    };
+    if cmd.get_flag("free") || cmd.get_flag("deleted") || cmd.contains_id("blocks") {
+        let mut disk = crate::create_fs_with(img_path,&crate::LoadOptions::from_matches(cmd)?)?;
+        if cmd.get_flag("deleted") {
+            eprintln!("erased {} deleted entries",disk.wipe_deleted()?);
+        }
+        if cmd.get_flag("free") {
+            eprintln!("filled {} free blocks",disk.wipe_free(&pattern)?);
+        }
+        if let Some(blocks) = cmd.get_one::<String>("blocks") {
+            let list = super::parse_block_request(blocks)?;
+            for b in &list {
+                let size = disk.read_block(&b.to_string())?.len();
+                let dat: Vec<u8> = pattern.iter().cycle().take(size).copied().collect();
+                disk.write_block(&b.to_string(),&dat)?;
+            }
+            eprintln!("filled {} blocks",list.len());
+        }
+        crate::save_img_with(&mut disk,img_path,opts)?;
+    }
+    // Sectors are done on a fresh image, so nothing the file system buffered can undo them
+    if let Some(range) = cmd.get_one::<String>("range") {
+        let mut img = crate::create_img_with(img_path,&crate::LoadOptions::from_matches(cmd)?)?;
+        eprintln!("filled {} sectors",wipe_sectors(&mut img,range,&pattern)?);
+        crate::save_raw_img_with(&mut img,img_path,opts)?;
+    }
+    Ok(())
+}
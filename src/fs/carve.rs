@@ -0,0 +1,372 @@
+//! # File carving
+//!
+//! Recover probable files from a damaged disk without relying on the directory.
+//! The image is scanned for structures that files leave behind, DOS 3.x track/sector lists
+//! and ProDOS index blocks, and what remains is scanned for tokenized Applesoft programs
+//! and runs of text.  Each carved file has a confidence between 0 and 1, which reflects
+//! how well the bytes fit what was assumed.  The structures are those of Apple II file systems,
+//! on other disks only Applesoft programs and text are found.
+
+use std::collections::HashSet;
+use std::fmt;
+use super::Block;
+use crate::img::{DiskImage,DiskImageType,names};
+use crate::DYNERR;
+
+const APPLESOFT_BASE: usize = 0x801;
+/// longest program to look for, in sectors or blocks
+const MAX_PROGRAM_UNITS: usize = 160;
+/// shortest text run worth reporting, in bytes
+const MIN_TEXT_RUN: usize = 64;
+
+/// What led to the file
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Evidence {
+    TrackSectorList,
+    IndexBlock,
+    MasterIndexBlock,
+    ApplesoftProgram,
+    TextRun
+}
+
+/// What the file appears to contain
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Content {
+    /// Applesoft tokens
+    Applesoft,
+    /// UTF8 text with LF line separators
+    Text,
+    /// binary data whose load address is known
+    Binary,
+    /// anything else
+    Data
+}
+
+impl fmt::Display for Evidence {
+    fn fmt(&self,f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TrackSectorList => write!(f,"track_sector_list"),
+            Self::IndexBlock => write!(f,"index_block"),
+            Self::MasterIndexBlock => write!(f,"master_index_block"),
+            Self::ApplesoftProgram => write!(f,"applesoft_program"),
+            Self::TextRun => write!(f,"text_run")
+        }
+    }
+}
+
+impl fmt::Display for Content {
+    fn fmt(&self,f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Applesoft => write!(f,"applesoft"),
+            Self::Text => write!(f,"text"),
+            Self::Binary => write!(f,"binary"),
+            Self::Data => write!(f,"data")
+        }
+    }
+}
+
+/// A probable file
+pub struct Carved {
+    pub evidence: Evidence,
+    /// where the evidence was found
+    pub location: Block,
+    pub content: Content,
+    /// load address, if the file system stored one
+    pub addr: Option<usize>,
+    /// tokens, text, or binary data, without any file system header
+    pub data: Vec<u8>,
+    pub confidence: f64
+}
+
+/// How the image is divided for scanning
+#[derive(Clone,Copy,PartialEq)]
+enum Geometry {
+    /// 13 sector 5.25 inch disk with this many tracks
+    D13(usize),
+    /// 16 sector 5.25 inch disk with this many tracks
+    DO(usize),
+    /// this many ProDOS blocks
+    PO(usize)
+}
+
+impl Geometry {
+    fn of(img: &mut Box<dyn DiskImage>) -> Self {
+        if img.what_am_i() == DiskImageType::D13 || img.kind() == names::A2_DOS32_KIND {
+            return Self::D13(img.track_count());
+        }
+        if img.read_block(Block::DO([0,0])).is_ok() {
+            return Self::DO(img.track_count());
+        }
+        Self::PO(img.byte_capacity() / 512)
+    }
+    /// Smallest units that can be claimed, in the order a scan should visit them.
+    /// DOS allocates sectors in a track from the top down, so the sectors are visited that way.
+    fn units(&self,dos_order: bool) -> Vec<Block> {
+        let mut ans = Vec::new();
+        match *self {
+            Self::D13(tracks) => for t in 0..tracks {
+                for s in (0..13).rev() {
+                    ans.push(Block::D13([t,s]));
+                }
+            },
+            Self::DO(tracks) if dos_order => for t in 0..tracks {
+                for s in (0..16).rev() {
+                    ans.push(Block::DO([t,s]));
+                }
+            },
+            Self::DO(tracks) => for b in 0..tracks*8 {
+                ans.push(Block::PO(b));
+            },
+            Self::PO(blocks) => for b in 0..blocks {
+                ans.push(Block::PO(b));
+            }
+        }
+        ans
+    }
+    /// DOS sectors that make up a ProDOS block on a 5.25 inch disk, otherwise the block itself
+    fn parts(&self,block: &Block) -> Vec<Block> {
+        match (self,block) {
+            (Self::DO(_),Block::PO(b)) => match crate::bios::skew::ts_from_prodos_block(*b,&names::A2_DOS33_KIND) {
+                Ok(ts) => ts.iter().map(|x| Block::DO(*x)).collect(),
+                Err(_) => vec![*block]
+            },
+            _ => vec![*block]
+        }
+    }
+}
+
+/// Blocks or sectors that already belong to a carved file
+struct Claims {
+    geometry: Geometry,
+    set: HashSet<Block>
+}
+
+impl Claims {
+    fn is_free(&self,block: &Block) -> bool {
+        self.geometry.parts(block).iter().all(|x| !self.set.contains(x))
+    }
+    fn claim(&mut self,blocks: &[Block]) {
+        for block in blocks {
+            for part in self.geometry.parts(block) {
+                self.set.insert(part);
+            }
+        }
+    }
+}
+
+/// Walk the line links of a tokenized Applesoft program that loads at $0801.
+/// Returns the program length, including the null link, and the number of lines, if the links are sound.
+pub fn applesoft_chain(dat: &[u8]) -> Option<(usize,usize)> {
+    let mut offset = 0;
+    let mut lines = 0;
+    let mut last_line: Option<u16> = None;
+    loop {
+        if offset + 2 > dat.len() {
+            return None;
+        }
+        let link = u16::from_le_bytes([dat[offset],dat[offset+1]]) as usize;
+        if link == 0 {
+            return match lines {
+                0 => None,
+                _ => Some((offset + 2,lines))
+            };
+        }
+        if link < APPLESOFT_BASE + offset + 5 || link - APPLESOFT_BASE > dat.len() {
+            return None;
+        }
+        let next = link - APPLESOFT_BASE;
+        let line_num = u16::from_le_bytes([dat[offset+2],dat[offset+3]]);
+        if line_num > 63999 || last_line.is_some_and(|x| x >= line_num) {
+            return None;
+        }
+        if dat[next-1] != 0 || dat[offset+4..next-1].contains(&0) {
+            return None;
+        }
+        last_line = Some(line_num);
+        lines += 1;
+        offset = next;
+    }
+}
+
+fn is_printable(c: u8) -> bool {
+    let c = c & 0x7f;
+    c == 0x0d || c == 0x0a || c == 0x09 || (0x20..0x7f).contains(&c)
+}
+
+/// Fraction of the nonzero bytes that are printable ASCII, ignoring the high bit
+pub fn text_fraction(dat: &[u8]) -> f64 {
+    let nonzero = dat.iter().filter(|c| **c != 0).count();
+    if nonzero == 0 {
+        return 0.0;
+    }
+    dat.iter().filter(|c| **c != 0 && is_printable(**c)).count() as f64 / nonzero as f64
+}
+
+/// Apple text with either polarity and CR separators as UTF8 with LF, nulls are dropped
+pub fn decode_text(dat: &[u8]) -> Vec<u8> {
+    dat.iter().filter(|c| **c != 0).map(|c| match c & 0x7f {
+        0x0d => 0x0a,
+        c if is_printable(c) => c,
+        _ => b'?'
+    }).collect()
+}
+
+fn trim_zeros(dat: &[u8]) -> Vec<u8> {
+    let end = dat.iter().rposition(|c| *c != 0).map(|i| i + 1).unwrap_or(0);
+    dat[0..end].to_vec()
+}
+
+/// Guess what file data contains, returning the content, load address, data without header, and confidence.
+/// If `dos_header` the data starts with the header DOS 3.x stores for BASIC and binary files.
+pub fn classify(dat: &[u8],dos_header: bool) -> (Content,Option<usize>,Vec<u8>,f64) {
+    if dos_header && dat.len() > 4 {
+        let len = u16::from_le_bytes([dat[0],dat[1]]) as usize;
+        if let Some((prog_len,lines)) = applesoft_chain(&dat[2..]) {
+            let conf = match prog_len == len {
+                true => 1.0,
+                false => 0.6
+            };
+            return (Content::Applesoft,Some(APPLESOFT_BASE),dat[2..2+prog_len].to_vec(),conf * f64::min(1.0,0.5 + 0.1 * lines as f64));
+        }
+        let addr = len;
+        let len = u16::from_le_bytes([dat[2],dat[3]]) as usize;
+        if len > 0 && 4 + len <= dat.len() && dat.len() - 4 - len < 256 {
+            return (Content::Binary,Some(addr),dat[4..4+len].to_vec(),0.6);
+        }
+    }
+    if !dos_header {
+        if let Some((prog_len,lines)) = applesoft_chain(dat) {
+            return (Content::Applesoft,Some(APPLESOFT_BASE),dat[0..prog_len].to_vec(),f64::min(1.0,0.4 + 0.1 * lines as f64));
+        }
+    }
+    let frac = text_fraction(dat);
+    if frac > 0.9 {
+        return (Content::Text,None,decode_text(dat),frac);
+    }
+    (Content::Data,None,trim_zeros(dat),0.2)
+}
+
+/// Concatenate readable units starting at `start` that are not claimed, up to `max` units
+fn free_run(img: &mut Box<dyn DiskImage>,units: &[Block],start: usize,max: usize,claims: &Claims) -> Vec<u8> {
+    let mut ans = Vec::new();
+    for unit in units.iter().skip(start).take(max) {
+        if !claims.is_free(unit) {
+            break;
+        }
+        match img.read_block(*unit) {
+            Ok(mut buf) => ans.append(&mut buf),
+            Err(_) => break
+        }
+    }
+    ans
+}
+
+/// Look for programs starting at the beginning of free units
+fn scan_applesoft(img: &mut Box<dyn DiskImage>,units: &[Block],claims: &mut Claims,ans: &mut Vec<Carved>) {
+    let mut i = 0;
+    while i < units.len() {
+        let first = match (claims.is_free(&units[i]),img.read_block(units[i])) {
+            (true,Ok(buf)) if buf.len() > 8 => buf,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let unit_len = first.len();
+        // quick check of the first link, with or without the DOS length header
+        let plausible = |off: usize| {
+            let link = u16::from_le_bytes([first[off],first[off+1]]) as usize;
+            link > APPLESOFT_BASE + 4 && link < 0xc000 && u16::from_le_bytes([first[off+2],first[off+3]]) < 64000
+        };
+        if !plausible(0) && !plausible(2) {
+            i += 1;
+            continue;
+        }
+        let dat = free_run(img,units,i,MAX_PROGRAM_UNITS,claims);
+        let found = [2,0].iter().find_map(|off| applesoft_chain(&dat[*off..]).filter(|(_,lines)| *lines > 1).map(|x| (*off,x)));
+        if let Some((off,(prog_len,lines))) = found {
+            let header = off == 2 && u16::from_le_bytes([dat[0],dat[1]]) as usize == prog_len;
+            let count = (off + prog_len).div_ceil(unit_len);
+            claims.claim(&units[i..i+count]);
+            ans.push(Carved {
+                evidence: Evidence::ApplesoftProgram,
+                location: units[i],
+                content: Content::Applesoft,
+                addr: Some(APPLESOFT_BASE),
+                data: dat[off..off+prog_len].to_vec(),
+                confidence: f64::min(0.9,0.3 + 0.05 * lines as f64) + if header { 0.1 } else { 0.0 }
+            });
+            i += count;
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Look for consecutive free units of text, the last one can be padded with nulls
+fn scan_text(img: &mut Box<dyn DiskImage>,units: &[Block],claims: &mut Claims,ans: &mut Vec<Carved>) {
+    let mut i = 0;
+    while i < units.len() {
+        let mut run = Vec::new();
+        let mut count = 0;
+        while i + count < units.len() && claims.is_free(&units[i+count]) {
+            let buf = match img.read_block(units[i+count]) {
+                Ok(buf) => buf,
+                Err(_) => break
+            };
+            let end = buf.iter().position(|c| *c == 0).unwrap_or(buf.len());
+            if end == 0 || text_fraction(&buf[0..end]) < 0.95 || buf[end..].iter().any(|c| *c != 0) {
+                break;
+            }
+            run.extend_from_slice(&buf[0..end]);
+            count += 1;
+            if end < buf.len() {
+                break;
+            }
+        }
+        let distinct: HashSet<u8> = run.iter().copied().collect();
+        if run.len() >= MIN_TEXT_RUN && distinct.len() > 8 {
+            claims.claim(&units[i..i+count]);
+            ans.push(Carved {
+                evidence: Evidence::TextRun,
+                location: units[i],
+                content: Content::Text,
+                addr: None,
+                data: decode_text(&run),
+                confidence: 0.2 + 0.6 * text_fraction(&run) * f64::min(1.0,run.len() as f64 / 1024.0)
+            });
+        }
+        i += usize::max(count,1);
+    }
+}
+
+/// Scan the image for probable files, structures first, then content.
+/// The result is in the order the evidence was found.
+pub fn carve(img: &mut Box<dyn DiskImage>) -> Result<Vec<Carved>,DYNERR> {
+    let geometry = Geometry::of(img);
+    let mut claims = Claims { geometry, set: HashSet::new() };
+    let mut ans = Vec::new();
+    let mut dos_found = false;
+    if let Geometry::D13(tracks) | Geometry::DO(tracks) = geometry {
+        for (file,used) in super::dos3x::carve::track_sector_lists(img,matches!(geometry,Geometry::D13(_)),tracks) {
+            claims.claim(&used);
+            ans.push(file);
+            dos_found = true;
+        }
+    }
+    let blocks = match geometry {
+        Geometry::D13(_) => 0,
+        Geometry::DO(tracks) => tracks * 8,
+        Geometry::PO(blocks) => blocks
+    };
+    for (file,used) in super::prodos::carve::index_blocks(img,blocks) {
+        if claims.is_free(&file.location) {
+            claims.claim(&used);
+            ans.push(file);
+        }
+    }
+    let units = geometry.units(dos_found || super::dos3x::Disk::test_img(img));
+    scan_applesoft(img,&units,&mut claims,&mut ans);
+    scan_text(img,&units,&mut claims,&mut ans);
+    Ok(ans)
+}
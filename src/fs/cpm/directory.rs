@@ -7,6 +7,10 @@
 //! particular, there is no separate file index or volume bitmap.
 //! 
 //! The term "extent" has shades of meaning, see the parent module notes. 
+//!
+//! This module is library-safe, see `crate::fs`.
+
+#![deny(clippy::panic,clippy::unwrap_used,clippy::expect_used)]
 
 use super::types::*;
 use super::pack::*;
@@ -21,7 +25,6 @@ use crate::{STDRESULT,DYNERR};
 use a2kit_macro::{DiskStructError,DiskStruct};
 use a2kit_macro_derive::DiskStruct;
 
-const RCH: &str = "unreachable was reached";
 const LABEL_EXISTS: u8 = 0x01;
 const CREATE: u8 = 0x10;
 const UPDATE: u8 = 0x20;
@@ -312,13 +315,9 @@ impl Extent {
     /// Inner value is the count of logical extents up to and including this extent, minus 1.
     /// If this is the last extent, only *used* logical extents are counted.
     pub fn set_data_ptr(&mut self,ptr: Ptr) {
-        match ptr {
-            Ptr::ExtentData(i) => {
-                self.idx_low = (i & 0b11111) as u8;
-                self.idx_high = ((i & 0b11111100000) >> 5) as u8;
-            },
-            _ => panic!("wrong pointer type")
-        }
+        let i = ptr.unwrap();
+        self.idx_low = (i & 0b11111) as u8;
+        self.idx_high = ((i & 0b11111100000) >> 5) as u8;
     }
     /// Returns the eof in bytes, *assuming* this is the last extent.
     /// Result may be modulo RECORD_SIZE depending on `self.last_bytes`,
@@ -363,11 +362,10 @@ impl Extent {
         let lx_per_x = dpb.exm as usize + 1;
         match dpb.ptr_size() {
             1 => self.block_list[lx*16/lx_per_x + slot] = iblock as u8,
-            2 => {
+            _ => {
                 self.block_list[2*(lx*8/lx_per_x + slot)] = u16::to_le_bytes(iblock)[0];
                 self.block_list[2*(lx*8/lx_per_x + slot)+1] = u16::to_le_bytes(iblock)[1];
-            },
-            _ => panic!("invalid block pointer size")
+            }
         }
     }
     /// Get block pointers, given the DPB (which implies the pointer size).
@@ -376,14 +374,13 @@ impl Extent {
     pub fn get_block_list(&self,dpb: &DiskParameterBlock) -> Vec<u16> {
         match dpb.ptr_size() {
             1 => self.block_list.iter().map(|x| *x as u16).collect::<Vec<u16>>(),
-            2 => {
+            _ => {
                 let mut ans: Vec<u16> = Vec::new();
                 for i in 0..8 {
                     ans.push(u16::from_le_bytes([self.block_list[i*2],self.block_list[i*2+1]]));
                 }
                 ans
-            },
-            _ => panic!("invalid block pointer size")
+            }
         }
     }
 }
@@ -422,9 +419,9 @@ impl DirectoryEntry for Password {
 
 impl Timestamp {
     fn create() -> Self {
-        let mut bytes = vec![0;32];
-        bytes[0] = TIMESTAMP;
-        Self::from_bytes(&bytes).expect(RCH)
+        let mut ans = Self::new();
+        ans.status = TIMESTAMP;
+        ans
     }
     /// Given the ptr to the entry containing logical extent 0 of a file, get the time stamps and save in the FileInfo struct.
     fn get(dir: &Directory,lab: &Label,lx0: &Ptr,info: &mut FileInfo) -> STDRESULT {
@@ -490,7 +487,7 @@ impl Timestamp {
                     return Err(Box::new(Error::BadFormat)) 
                 }
             };
-            dir.set_entry(&Ptr::ExtentEntry(expected_idx), &ts);
+            dir.set_entry(&Ptr::ExtentEntry(expected_idx), &ts)?;
         } else {
             error!("timestamp entry not in expected slot");
             return Err(Box::new(Error::BadFormat));
@@ -564,13 +561,25 @@ impl Directory {
     pub fn num_entries(&self) -> usize {
         self.entries.len()
     }
-    pub fn get_type(&self,ptr: &Ptr) -> ExtentType {
-        let (idx,xstat) = match ptr {
-            Ptr::ExtentEntry(i) => (*i,self.entries[*i][0]),
-            _ => panic!("wrong pointer type")
-        };
+    /// Index of the entry, error if the pointer is not an entry in this directory
+    fn entry_idx(&self,ptr: &Ptr) -> Result<usize,DYNERR> {
+        match ptr {
+            Ptr::ExtentEntry(idx) if *idx < self.entries.len() => Ok(*idx),
+            Ptr::ExtentEntry(idx) => {
+                error!("entry {} is beyond the end of the directory",idx);
+                Err(Box::new(Error::BadFormat))
+            },
+            _ => {
+                error!("wrong pointer type");
+                Err(Box::new(Error::BadFormat))
+            }
+        }
+    }
+    pub fn get_type(&self,ptr: &Ptr) -> Result<ExtentType,DYNERR> {
+        let idx = self.entry_idx(ptr)?;
+        let xstat = self.entries[idx][0];
         trace!("entry {} has extent type {}",idx,xstat);
-        match xstat {
+        Ok(match xstat {
             x if x<USER_END => ExtentType::File,
             x if x<USER_END*2 => ExtentType::Password,
             LABEL => ExtentType::Label,
@@ -580,39 +589,33 @@ impl Directory {
                 debug!("unknown extent type {}",x);
                 ExtentType::Unknown
             }
-        }
+        })
     }
-    pub fn get_raw_entry(&self,ptr: &Ptr) -> [u8;DIR_ENTRY_SIZE] {
-        match ptr {
-            Ptr::ExtentEntry(i) => self.entries[*i].clone(),
-            _ => panic!("wrong pointer type")
-        }
+    pub fn get_raw_entry(&self,ptr: &Ptr) -> Result<[u8;DIR_ENTRY_SIZE],DYNERR> {
+        Ok(self.entries[self.entry_idx(ptr)?])
     }
-    // pub fn set_raw_entry(&mut self,ptr: &Ptr,bytes: [u8;DIR_ENTRY_SIZE]) {
-    //     match ptr {
-    //         Ptr::ExtentEntry(i) => self.entries[*i] = bytes,
-    //         _ => panic!("wrong pointer type")
-    //     }
-    // }
+    /// Get the entry if it is of the requested type, a bad pointer also gives `None`
     pub fn get_entry<EntryType: DiskStruct + DirectoryEntry>(&self,ptr: &Ptr) -> Option<EntryType> {
         let rng = EntryType::stat_range();
-        match ptr {
-            Ptr::ExtentEntry(idx) => match self.entries[*idx][0] {
-                x if x>=rng[0] && x<rng[1] => Some(
-                    EntryType::from_bytes(&self.entries[*idx]).expect(RCH)
-                ),
-                _ => None
-            },
-            _ => panic!("wrong pointer type")
+        let idx = self.entry_idx(ptr).ok()?;
+        match self.entries[idx][0] {
+            x if x>=rng[0] && x<rng[1] => EntryType::from_bytes(&self.entries[idx]).ok(),
+            _ => None
         }
     }
-    pub fn set_entry<EntryType: DiskStruct>(&mut self,ptr: &Ptr,x: &EntryType) {
-        match ptr {
-            Ptr::ExtentEntry(idx) => {
-                self.entries[*idx] = x.to_bytes().try_into().expect("unexpected size")
-            },
-            _ => panic!("wrong pointer type")
-        }
+    pub fn set_entry<EntryType: DiskStruct>(&mut self,ptr: &Ptr,x: &EntryType) -> STDRESULT {
+        let idx = self.entry_idx(ptr)?;
+        self.entries[idx].copy_from_slice(&x.to_bytes());
+        Ok(())
+    }
+    fn push_entry<EntryType: DiskStruct>(&mut self,x: &EntryType) {
+        let mut bytes = [0;DIR_ENTRY_SIZE];
+        bytes.copy_from_slice(&x.to_bytes());
+        self.entries.push(bytes);
+    }
+    /// Pointer to the label extent, if there is one
+    pub fn find_label_ptr(&self) -> Option<Ptr> {
+        (0..self.num_entries()).map(Ptr::ExtentEntry).find(|ptr| self.get_entry::<Label>(ptr).is_some())
     }
     pub fn find_label(&self) -> Option<Label> {
         for i in 0..self.num_entries() {
@@ -627,18 +630,18 @@ impl Directory {
     pub fn add_timestamps(&self) -> Result<Directory,DYNERR> {
         let mut ans = Directory::new();
         let mut timestamp = Timestamp::create();
-        let empty_entry: [u8;32] = [vec![DELETED],vec![0;31]].concat().try_into().expect(RCH);
+        let mut empty_entry = [0;DIR_ENTRY_SIZE];
+        empty_entry[0] = DELETED;
         for i in 0..self.num_entries() {
             if ans.entries.len()%4 == 3 {
-                ans.entries.push(timestamp.to_bytes().try_into().expect(RCH));
+                ans.push_entry(&timestamp);
                 timestamp = Timestamp::create();
             }
             if self.entries[i][0]==TIMESTAMP {
                 error!("directory already has timestamps");
                 return Err(Box::new(Error::BadFormat));
             }
-            if self.entries[i][0]==LABEL {
-                let lab = self.get_entry::<Label>(&Ptr::ExtentEntry(i)).unwrap();
+            if let Some(lab) = self.get_entry::<Label>(&Ptr::ExtentEntry(i)) {
                 match ans.entries.len()%4 + 1 {
                     1 => {
                         timestamp.create_access1 = lab.create_time;
@@ -659,7 +662,7 @@ impl Directory {
                 };
             }
             if self.entries[i][0]!=DELETED {
-                let bytes = self.get_raw_entry(&Ptr::ExtentEntry(i));
+                let bytes = self.get_raw_entry(&Ptr::ExtentEntry(i))?;
                 ans.entries.push(bytes);
             }
         }
@@ -668,7 +671,7 @@ impl Directory {
         }
         for _i in ans.num_entries()..self.num_entries() {
             if ans.entries.len()%4 == 3 {
-                ans.entries.push(timestamp.to_bytes().try_into().expect(RCH));
+                ans.push_entry(&timestamp);
                 timestamp = Timestamp::create();
             } else {
                 ans.entries.push(empty_entry);
@@ -683,25 +686,34 @@ impl Directory {
                 label.timestamp_access(false);
                 label.timestamp_creation(false);
                 label.timestamp_update(false);
-                self.set_entry::<Label>(&Ptr::ExtentEntry(i),&label);
+                let _ = self.set_entry::<Label>(&Ptr::ExtentEntry(i),&label);
             }
             if let Some(mut timestamp) = self.get_entry::<Timestamp>(&Ptr::ExtentEntry(i)) {
                 timestamp.status = DELETED;
-                self.set_entry::<Timestamp>(&Ptr::ExtentEntry(i),&timestamp);
+                let _ = self.set_entry::<Timestamp>(&Ptr::ExtentEntry(i),&timestamp);
             }
         }
     }
     /// Build an alphabetized map of user prefixed file names to file info.
     /// This is designed to work whether the disk is CP/M 1, 2, or 3.
     /// The `cpm_vers` sets the maximum version that is accepted.
-    pub fn build_files(&self,dpb: &DiskParameterBlock,cpm_vers: [u8;3]) -> Result<BTreeMap<String,FileInfo>,DYNERR> {
+    /// If `lenient`, damaged entries are skipped with a warning, rather than rejecting the whole directory,
+    /// unless they outnumber the good ones, so that other file systems are not mistaken for CP/M.
+    pub fn build_files(&self,dpb: &DiskParameterBlock,cpm_vers: [u8;3],lenient: bool) -> Result<BTreeMap<String,FileInfo>,DYNERR> {
         let mut bad_names = 0;
-        let mut ans = BTreeMap::new();
+        let mut good = 0;
+        let mut skipped = 0;
+        let mut ans: BTreeMap<String,FileInfo> = BTreeMap::new();
         let maybe_lab = self.find_label();
         // first pass collects everything except passwords
         for i in 0..self.num_entries() {
-            let xtype = self.get_type(&Ptr::ExtentEntry(i));
+            let xtype = self.get_type(&Ptr::ExtentEntry(i))?;
             if xtype==ExtentType::Unknown {
+                if lenient {
+                    warn!("skipping entry {} with unknown extent type",i);
+                    skipped += 1;
+                    continue;
+                }
                 debug!("unknown extent type in entry {}",i);
                 return Err(Box::new(Error::BadFormat));
             }
@@ -716,47 +728,65 @@ impl Directory {
                 let (name,typ) = fx.get_split_string();
                 let flags = fx.get_flags();
                 if flags[4]>0x7f || flags[5]>0x7f || flags[6]>0x7f || flags[7]>0x7f {
+                    if lenient {
+                        warn!("skipping entry {} with high bits in file name",i);
+                        skipped += 1;
+                        continue;
+                    }
                     debug!("unexpected high bits in file name");
                     return Err(Box::new(Error::BadFormat));
                 }
                 if !is_name_valid(&fx.get_string()) {
+                    if lenient {
+                        warn!("skipping entry {} with bad file name {}",i,fx.get_string_escaped());
+                        skipped += 1;
+                        continue;
+                    }
                     bad_names += 1;
                 }
                 if bad_names > 2 {
                     debug!("after {} bad file names rejecting disk",bad_names);
                     return Err(Box::new(Error::BadFormat));
                 }
+                if lenient {
+                    if fx.get_block_list(dpb).iter().any(|b| *b as usize >= dpb.user_blocks()) {
+                        warn!("skipping entry {} with block pointer out of range",i);
+                        skipped += 1;
+                        continue;
+                    }
+                    if let Some(prev) = ans.get(&key) {
+                        if prev.entries.contains_key(&fx.get_data_ptr()) {
+                            warn!("skipping entry {} that repeats an extent of {}",i,fx.get_string_escaped());
+                            skipped += 1;
+                            continue;
+                        }
+                    }
+                }
+                good += 1;
                 trace!("found file {}:{}",fx.user,fx.get_string_escaped());
 
-                let finfo = match ans.get_mut(&key) {
-                    Some(f) => f,
-                    None => {
-                        let v = FileInfo {
-                            user: fx.user,
-                            name,
-                            typ,
-                            read_only: flags[8] > 0,
-                            system: flags[9] > 0,
-                            archived: flags[10] > 0,
-                            f1: flags[0] > 0,
-                            f2: flags[1] > 0,
-                            f3: flags[2] > 0,
-                            f4: flags[3] > 0,
-                            encrypted_password: [0;8],
-                            read_pass: false,
-                            write_pass: false,
-                            del_pass: false,
-                            decoder: 0,
-                            update_time: None,
-                            create_time: None,
-                            access_time: None,
-                            blocks_allocated: 0,
-                            entries: BTreeMap::new()
-                        };
-                        ans.insert(key.clone(),v);
-                        ans.get_mut(&key).unwrap()
-                    }
-                };
+                let finfo = ans.entry(key).or_insert_with(|| FileInfo {
+                    user: fx.user,
+                    name,
+                    typ,
+                    read_only: flags[8] > 0,
+                    system: flags[9] > 0,
+                    archived: flags[10] > 0,
+                    f1: flags[0] > 0,
+                    f2: flags[1] > 0,
+                    f3: flags[2] > 0,
+                    f4: flags[3] > 0,
+                    encrypted_password: [0;8],
+                    read_pass: false,
+                    write_pass: false,
+                    del_pass: false,
+                    decoder: 0,
+                    update_time: None,
+                    create_time: None,
+                    access_time: None,
+                    blocks_allocated: 0,
+                    entries: BTreeMap::new()
+                });
                 finfo.entries.insert(fx.get_data_ptr(),Ptr::ExtentEntry(i));
                 for b in fx.get_block_list(dpb) {
                     finfo.blocks_allocated += match b>0 { true => 1, false => 0};
@@ -764,7 +794,10 @@ impl Directory {
                 if fx.get_data_ptr() <= Ptr::ExtentData(dpb.exm as usize) {
                     if let Some(lab) = &maybe_lab {
                         if lab.is_timestamped() {
-                            Timestamp::get(&self,lab, &Ptr::ExtentEntry(i), finfo)?;
+                            match Timestamp::get(self,lab, &Ptr::ExtentEntry(i), finfo) {
+                                Err(e) if lenient => warn!("skipping timestamp of entry {}: {}",i,e),
+                                result => result?
+                            }
                         }
                     }
                 }
@@ -788,6 +821,13 @@ impl Directory {
                 }
             }
         }
+        if skipped > good {
+            debug!("{} damaged entries outnumber {} good ones, rejecting disk",skipped,good);
+            return Err(Box::new(Error::BadFormat));
+        }
+        if skipped > 0 {
+            warn!("skipped {} damaged directory entries",skipped);
+        }
         Ok(ans)
     }
     /// Collect users, this assumes we have established a valid CP/M directory
@@ -804,12 +844,13 @@ impl Directory {
         ans
     }
     /// Sort the files based on the order of appearance in the directory.
-    /// Panics if there is a file with an empty entry list.
+    /// A file with an empty entry list is left out.
     pub fn sort_on_entry_index(&self,files: &BTreeMap<String,FileInfo>) -> BTreeMap<usize,FileInfo> {
         let mut ans = BTreeMap::new();
         for f in files.values() {
-            let min_idx = f.entries.values().min().unwrap().unwrap();
-            ans.insert(min_idx,f.clone());
+            if let Some(min_idx) = f.entries.values().min() {
+                ans.insert(min_idx.unwrap(),f.clone());
+            }
         }
         ans
     }
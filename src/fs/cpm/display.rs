@@ -5,6 +5,7 @@
 
 use log::{trace,debug,error};
 use colored::Colorize;
+use std::fmt::Write;
 use std::collections::BTreeSet;
 use regex::Regex;
 use super::directory;
@@ -303,7 +304,7 @@ impl DirOptions {
 /// Intrinsic v2 table: cols=4, sizes=false.
 /// Intrinsic v3 table: cols=5, sizes=false.
 /// If SIZE and not FULL: cols=3, sizes=true.
-fn dir_table(dir: &directory::Directory,finfo: &directory::FileInfo,stats: &mut UserStats,cols: usize,sizes: bool) {
+fn dir_table(out: &mut String,dir: &directory::Directory,finfo: &directory::FileInfo,stats: &mut UserStats,cols: usize,sizes: bool) -> std::fmt::Result {
     let bytes = match finfo.entries.last_key_value() {
         Some((_k,v))=> {
             let fx = dir.get_entry::<directory::Extent>(v).unwrap();
@@ -314,11 +315,11 @@ fn dir_table(dir: &directory::Directory,finfo: &directory::FileInfo,stats: &mut
     let kbytes = match bytes { b if b>0 => 1+(b-1)/1024, _ => 0 };
     let records = match bytes { b if b>0 => 1+(b-1)/128, _ => 0 };
     if stats.file_count%cols==0 {
-        print!("A: ");
+        write!(out,"A: ")?;
     } else {
-        print!(" : ");
+        write!(out," : ")?;
     }
-    print!("{:8} {:3}",
+    write!(out,"{:8} {:3}",
         match (finfo.read_only,finfo.system) {
             (true,true) => finfo.name.red().dimmed(),
             (true,false) => finfo.name.red(),
@@ -331,21 +332,22 @@ fn dir_table(dir: &directory::Directory,finfo: &directory::FileInfo,stats: &mut
             (false,true) => finfo.typ.dimmed(),
             (false,false) => finfo.typ.normal()
         },
-    );
+    )?;
     if sizes==true {
-        print!(" {:4}k",kbytes);
+        write!(out," {:4}k",kbytes)?;
     }
     if stats.file_count%cols==cols-1 {
-        println!();
+        writeln!(out)?;
     }
     stats.file_count += 1;
     stats.total_kbytes += kbytes;
     stats.total_recs += records;
     stats.total_blocks += finfo.blocks_allocated;
+    Ok(())
 }
 
 /// Display first 40 columns of a long directory entry
-fn dir_first40(dir: &directory::Directory,finfo: &directory::FileInfo,stats: &mut UserStats,show_att: bool) {
+fn dir_first40(out: &mut String,dir: &directory::Directory,finfo: &directory::FileInfo,stats: &mut UserStats,show_att: bool) -> std::fmt::Result {
     let mut attr = String::new();
     attr += match finfo.system { true => "Sys ", false => "Dir " };
     attr += match finfo.read_only { true => "RO  ", false => "RW  " };
@@ -362,21 +364,22 @@ fn dir_first40(dir: &directory::Directory,finfo: &directory::FileInfo,stats: &mu
     };
     let kbytes = match bytes { b if b>0 => 1+(b-1)/1024, _ => 0 };
     let records = match bytes { b if b>0 => 1+(b-1)/128, _ => 0 };
-    print!("{:8} {:3} {:5}k {:6} {}",
+    write!(out,"{:8} {:3} {:5}k {:6} {}",
         finfo.name,
         finfo.typ,
         kbytes,
         records,
         attr
-    );
+    )?;
     stats.file_count += 1;
     stats.total_kbytes += kbytes;
     stats.total_recs += records;
     stats.total_blocks += finfo.blocks_allocated;
+    Ok(())
 }
 
 /// Display last 40 columns of a long directory entry
-fn dir_last40(finfo: &directory::FileInfo) {
+fn dir_last40(out: &mut String,finfo: &directory::FileInfo) -> std::fmt::Result {
     let mut access_create_data = String::new();
     access_create_data += &match finfo.access_time {
         Some([0,0,0,0]) => "".to_string(),
@@ -392,7 +395,7 @@ fn dir_last40(finfo: &directory::FileInfo) {
     prot += match finfo.read_pass { true => "R", false => "" };
     prot += match finfo.write_pass { true => "W", false => "" };
     prot += match finfo.del_pass { true => "D", false => "" };
-    print!("{:6} {:14}  {:14}",
+    write!(out,"{:6} {:14}  {:14}",
         match prot.len() {
             0 => "None",
             _ => &prot
@@ -403,17 +406,18 @@ fn dir_last40(finfo: &directory::FileInfo) {
             None => "".to_string()
         },
         access_create_data
-    );
+    )?;
+    Ok(())
 }
 
 /// SHOW [LABEL] as in CP/M v3
-pub fn show_label(lab: &directory::Label) {
+pub fn show_label(out: &mut String,lab: &directory::Label) -> std::fmt::Result {
     let (base,typ) = lab.get_split_string();
     let access_create = match lab.is_timestamped_access() { true => "Access", false => "Create" };
-    println!("{:12}  {:7}  {:6}  {:6}","Directory","Passwds","Stamp","Stamp");
-    println!("{:12}  {:7}  {:6}  {:6}  {:14}  {:14}","Label","Reqd",access_create,"Update","Label Created","Label Updated");
-    println!("------------  -------  ------  ------  --------------  --------------");
-    println!("{:8}.{:3}  {:7}  {:6}  {:6}  {:14}  {:14}",
+    writeln!(out,"{:12}  {:7}  {:6}  {:6}","Directory","Passwds","Stamp","Stamp")?;
+    writeln!(out,"{:12}  {:7}  {:6}  {:6}  {:14}  {:14}","Label","Reqd",access_create,"Update","Label Created","Label Updated")?;
+    writeln!(out,"------------  -------  ------  ------  --------------  --------------")?;
+    writeln!(out,"{:8}.{:3}  {:7}  {:6}  {:6}  {:14}  {:14}",
         base,
         typ,
         match lab.is_protected() { true => "on", false => "off" },
@@ -421,7 +425,8 @@ pub fn show_label(lab: &directory::Label) {
         match lab.is_timestamped_update() { true => "on", false => "off" },
         unpack_date(lab.get_create_time()).format("%m/%d/%y %H:%M"),
         unpack_date(lab.get_update_time()).format("%m/%d/%y %H:%M")
-    );
+    )?;
+    Ok(())
 }
 
 fn is_displayed(user: u8,finfo: &directory::FileInfo,opt: &DirOptions) -> bool {
@@ -439,14 +444,15 @@ fn is_displayed(user: u8,finfo: &directory::FileInfo,opt: &DirOptions) -> bool {
 /// Display CP/M directory in style determined by options.
 /// This will behave like CP/M v3, including how it will list
 /// the files on a CP/M v2 disk.
-pub fn dir(dir: &directory::Directory,dpb: &DiskParameterBlock,opt: &str) -> STDRESULT {
+pub fn dir(dir: &directory::Directory,dpb: &DiskParameterBlock,opt: &str,lenient: bool) -> Result<String,DYNERR> {
+    let mut out = String::new();
     let maybe_lab = dir.find_label();
     let access_create = match &maybe_lab {
         Some(lab) => {
-            println!();
-            println!("Label for drive A:");
-            println!();
-            show_label(&lab);
+            writeln!(out)?;
+            writeln!(out,"Label for drive A:")?;
+            writeln!(out)?;
+            show_label(&mut out,lab)?;
             match lab.is_timestamped_access() { true => "Access", false => "Create" }
         },
         None => {
@@ -457,7 +463,7 @@ pub fn dir(dir: &directory::Directory,dpb: &DiskParameterBlock,opt: &str) -> STD
         Some(lab) => (lab.is_protected(),lab.is_timestamped()),
         None => (false,false)
     };
-    if let Ok(sorted) = dir.build_files(dpb,[3,1,0]) {
+    if let Ok(sorted) = dir.build_files(dpb,[3,1,0],lenient) {
         // `build_files` sorts on the name automatically, so we have to "re-sort" in order
         // to get the "unsorted" list.
         let unsorted = dir.sort_on_entry_index(&sorted);
@@ -478,30 +484,28 @@ pub fn dir(dir: &directory::Directory,dpb: &DiskParameterBlock,opt: &str) -> STD
                 file_count: 0
             };
             if !options.full && !options.size {
-                println!();
+                let cols = match maybe_lab {
+                    Some(_) => crate::fs::render::fit_columns(5,15,0),
+                    None => crate::fs::render::fit_columns(4,15,0)
+                };
+                writeln!(out)?;
                 for v in unsorted.values() {
                     if is_displayed(*user, v, &options) {
-                        match maybe_lab {
-                            Some(_) => dir_table(dir,v,&mut user_stats,5,false),
-                            None => dir_table(dir,v,&mut user_stats,4,false)
-                        };
+                        dir_table(&mut out,dir,v,&mut user_stats,cols,false)?;
                     }
                 }
-                if user_stats.file_count % 4 > 0 && maybe_lab.is_none() {
-                    println!();
-                }
-                if user_stats.file_count % 5 > 0 && maybe_lab.is_some() {
-                    println!();
+                if !user_stats.file_count.is_multiple_of(cols) {
+                    writeln!(out)?;
                 }
                 if user_stats.file_count==0 {
-                    println!("No File");
+                    writeln!(out,"No File")?;
                 }
-                println!();
+                writeln!(out)?;
                 continue;
             }
-            println!();
-            println!("Directory for Drive A: User {}",user);
-            println!();
+            writeln!(out)?;
+            writeln!(out,"Directory for Drive A: User {}",user)?;
+            writeln!(out)?;
             let files: Vec<&directory::FileInfo> = match options.nosort {
                 true => unsorted.values().collect(),
                 false => sorted.values().collect()
@@ -509,13 +513,14 @@ pub fn dir(dir: &directory::Directory,dpb: &DiskParameterBlock,opt: &str) -> STD
             match (options.full,options.size,protected || timestamped) {
                 (false,true,_) => {
                     // asked for size, but not full, 3 files per row
+                    let cols = crate::fs::render::fit_columns(3,21,0);
                     for v in files {
                         if is_displayed(*user,v,&options) {
-                            dir_table(dir, v, &mut user_stats,3,true);
+                            dir_table(&mut out,dir, v, &mut user_stats,cols,true)?;
                         }
                     }
-                    if user_stats.file_count % 3 > 0 {
-                        println!();
+                    if !user_stats.file_count.is_multiple_of(cols) {
+                        writeln!(out)?;
                     }
                 }
                 (_,_,true) => {
@@ -523,14 +528,14 @@ pub fn dir(dir: &directory::Directory,dpb: &DiskParameterBlock,opt: &str) -> STD
                     for v in files {
                         if is_displayed(*user, v, &options) {
                             if user_stats.file_count==0 {
-                                println!("{} {}",first40_heading,last40_heading);
-                                println!("{} {}",first40_sep,last40_sep);
-                                println!();
+                                writeln!(out,"{} {}",first40_heading,last40_heading)?;
+                                writeln!(out,"{} {}",first40_sep,last40_sep)?;
+                                writeln!(out)?;
                             }
-                            dir_first40(dir,v,&mut user_stats,options.att);
-                            print!(" ");
-                            dir_last40(v);
-                            println!();
+                            dir_first40(&mut out,dir,v,&mut user_stats,options.att)?;
+                            write!(out," ")?;
+                            dir_last40(&mut out,v)?;
+                            writeln!(out)?;
                         }
                     }
                 },
@@ -539,20 +544,20 @@ pub fn dir(dir: &directory::Directory,dpb: &DiskParameterBlock,opt: &str) -> STD
                     for v in files.iter() {
                         if is_displayed(*user, v, &options) {
                             if user_stats.file_count==0 {
-                                println!("{} {}",first40_heading,first40_heading);
-                                println!("{} {}",first40_sep,first40_sep);
-                                println!();
+                                writeln!(out,"{} {}",first40_heading,first40_heading)?;
+                                writeln!(out,"{} {}",first40_sep,first40_sep)?;
+                                writeln!(out)?;
                             }
-                            dir_first40(dir,v,&mut user_stats,options.att);
+                            dir_first40(&mut out,dir,v,&mut user_stats,options.att)?;
                             if user_stats.file_count%2==1 {
-                                print!(" ");
+                                write!(out," ")?;
                             } else {
-                                println!();
+                                writeln!(out)?;
                             }
                         }
                     }
                     if user_stats.file_count % 2 > 0 {
-                        println!();
+                        writeln!(out)?;
                     }
                 }
             }
@@ -563,29 +568,29 @@ pub fn dir(dir: &directory::Directory,dpb: &DiskParameterBlock,opt: &str) -> STD
             let mut used_entries = 0;
             for i in 0..dir.num_entries() {
                 used_entries += match dir.get_type(&types::Ptr::ExtentEntry(i)) {
-                    types::ExtentType::Deleted => 0,
+                    Ok(types::ExtentType::Deleted) => 0,
                     _ => 1
                 };
             }
             // Display the cumulative stats
             if user_stats.file_count==0 {
-                println!("No File");
-                println!();
+                writeln!(out,"No File")?;
+                writeln!(out)?;
             } else {
-                println!();
-                println!("Total Bytes     = {:6}k  Total Records = {:7}  Files Found = {:4}",
-                    user_stats.total_kbytes,user_stats.total_recs,user_stats.file_count);
-                println!("Total {}k Blocks = {:6}   Occupied/Tot Entries For Drive A: {:4}/{:4}",
-                    dpb.block_size()/1024,user_stats.total_blocks,used_entries,dir.num_entries());
-                println!();
+                writeln!(out)?;
+                writeln!(out,"Total Bytes     = {:6}k  Total Records = {:7}  Files Found = {:4}",
+                    user_stats.total_kbytes,user_stats.total_recs,user_stats.file_count)?;
+                writeln!(out,"Total {}k Blocks = {:6}   Occupied/Tot Entries For Drive A: {:4}/{:4}",
+                    dpb.block_size()/1024,user_stats.total_blocks,used_entries,dir.num_entries())?;
+                writeln!(out)?;
             }
         }
     }
-    Ok(())
+    Ok(out)
 }
 
 /// Output CP/M directory "tree" as a JSON string, users are treated as directories.
-pub fn tree(dir: &directory::Directory,dpb: &DiskParameterBlock,include_meta: bool,indent: Option<u16>) -> Result<String,DYNERR> {
+pub fn tree(dir: &directory::Directory,dpb: &DiskParameterBlock,include_meta: bool,indent: Option<u16>,lenient: bool) -> Result<String,DYNERR> {
     const TIME_FMT: &str = "%Y/%m/%d %H:%M";
     let mut tree = json::JsonValue::new_object();
     tree["file_system"] = json::JsonValue::String(super::FS_NAME.to_string());
@@ -606,7 +611,7 @@ pub fn tree(dir: &directory::Directory,dpb: &DiskParameterBlock,include_meta: bo
             tree["label"]["time_modified"] = json::JsonValue::String(unpack_date(lab.get_update_time()).format(TIME_FMT).to_string());
         }
     }
-    if let Ok(sorted) = dir.build_files(dpb,[3,1,0]) {
+    if let Ok(sorted) = dir.build_files(dpb,[3,1,0],lenient) {
         let files: Vec<&directory::FileInfo> = sorted.values().collect();
         for finfo in files {
             let key = match finfo.typ.len() {
@@ -98,16 +98,23 @@ fn get_directory(img: &mut Box<dyn img::DiskImage>,dpb: &DiskParameterBlock) ->
         }
     }
     let buf_size = dpb.dir_entries() * DIR_ENTRY_SIZE;
-    Some(Directory::from_bytes(&buf[0..buf_size]).expect(RCH))
+    match buf.get(0..buf_size) {
+        Some(dir_buf) => Directory::from_bytes(dir_buf).ok(),
+        None => {
+            debug!("CP/M directory blocks are smaller than the directory");
+            None
+        }
+    }
 }
 
-pub fn new_fimg(chunk_len: usize,set_time: bool,xname: &str) -> Result<FileImage,DYNERR> {
+/// New file image, stamped with `time` if it is not `None`
+pub fn new_fimg(chunk_len: usize,time: Option<chrono::NaiveDateTime>,xname: &str) -> Result<FileImage,DYNERR> {
     if !is_xname_valid(xname) {
         return Err(Box::new(Error::BadFormat))
     }
-    let created = match set_time {
-        true => pack::pack_date(None).to_vec(),
-        false => vec![]
+    let created = match time {
+        Some(t) => pack::pack_date(Some(t)).to_vec(),
+        None => vec![]
     };
     let (access,fs_type) = std_access_and_typ(xname)?;
     Ok(FileImage {
@@ -138,7 +145,9 @@ pub struct Disk
 {
     cpm_vers: [u8;3],
     dpb: DiskParameterBlock,
-    img: Box<dyn img::DiskImage>
+    img: Box<dyn img::DiskImage>,
+    lenient: bool,
+    deterministic: bool
 }
 
 impl Disk
@@ -152,15 +161,32 @@ impl Disk
         Ok(Self {
             cpm_vers,
             dpb,
-            img
+            img,
+            lenient: false,
+            deterministic: false
         })
     }
+    /// Skip damaged directory entries, with a warning, rather than rejecting the whole disk,
+    /// see `Directory::build_files`.
+    pub fn with_lenient(mut self,lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+    /// Fix the times that are stored, see `crate::now`
+    pub fn with_deterministic(mut self,deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+    fn now(&self) -> Option<chrono::NaiveDateTime> {
+        Some(crate::now(self.deterministic))
+    }
     /// Test an image for the CP/M file system.
     /// Will not accept images with directory structures corresponding to CP/M versions higher than `cpm_vers`.
-    pub fn test_img(img: &mut Box<dyn img::DiskImage>,dpb: &DiskParameterBlock,cpm_vers: [u8;3]) -> bool {
+    /// If `lenient`, a directory with a few damaged entries is accepted.
+    pub fn test_img(img: &mut Box<dyn img::DiskImage>,dpb: &DiskParameterBlock,cpm_vers: [u8;3],lenient: bool) -> bool {
         // test the volume directory header
         if let Some(directory) = get_directory(img,dpb) {
-            if let Err(_e) = directory.build_files(dpb,cpm_vers) {
+            if let Err(_e) = directory.build_files(dpb,cpm_vers,lenient) {
                 debug!("Unable to build CP/M file directory");
                 return false;
             }
@@ -169,8 +195,14 @@ impl Disk
         debug!("CP/M directory was not readable");
         return false;
     }
-    fn get_directory(&mut self) -> Directory {
-        return get_directory(&mut self.img,&self.dpb).expect("directory broken");
+    fn get_directory(&mut self) -> Result<Directory,DYNERR> {
+        match get_directory(&mut self.img,&self.dpb) {
+            Some(dir) => Ok(dir),
+            None => {
+                error!("CP/M directory could not be read");
+                Err(Box::new(Error::ReadError))
+            }
+        }
     }
     fn save_directory(&mut self,dir: &Directory) -> STDRESULT {
         let buf = dir.to_bytes();
@@ -209,10 +241,7 @@ impl Disk
         return self.dpb.user_blocks() as u16 - used as u16;
     }
     fn is_extent_free(&self,ptr: Ptr,dir: &Directory) -> bool {
-        match dir.get_type(&ptr) {
-            ExtentType::Deleted | ExtentType::Unknown => true,
-            _ => false
-        }
+        matches!(dir.get_type(&ptr),Ok(ExtentType::Deleted | ExtentType::Unknown))
     }
     /// extents available in the directory, each can reference up to (EXM+1)*16K of data
     fn num_free_extents(&self,dir: &Directory) -> usize {
@@ -220,7 +249,7 @@ impl Disk
         for i in 0..dir.num_entries() {
             trace!("check entry {}",i);
             match dir.get_type(&Ptr::ExtentEntry(i)) {
-                ExtentType::Deleted | ExtentType::Unknown => {
+                Ok(ExtentType::Deleted | ExtentType::Unknown) => {
                     ans += 1
                 },
                 _ => { debug!("entry {} is used",i); }
@@ -261,6 +290,23 @@ impl Disk
         };
         self.img.write_block(Block::CPM((iblock,self.dpb.bsh,self.dpb.off)), &data[offset..offset+actual_len])
     }
+    /// Addresses that cover the reserved tracks in order.  The DPB does not describe the physical
+    /// sectors, so each address spans the largest power of two records that divides the track,
+    /// which keeps the image's deblocking aligned.  The reserved tracks are assumed to hold `spt` records.
+    fn boot_chunks(&self) -> Vec<Block> {
+        let spt = self.dpb.spt as usize;
+        let mut shift = 0;
+        while shift < 7 && spt.is_multiple_of(2 << shift) {
+            shift += 1;
+        }
+        let mut ans = Vec::new();
+        for track in 0..self.dpb.off {
+            for chunk in 0..spt >> shift {
+                ans.push(Block::CPM((chunk,shift,track)));
+            }
+        }
+        ans
+    }
     fn get_available_block(&mut self,dir: &Directory) -> Option<u16> {
         for block in 0..self.dpb.user_blocks() {
             if self.is_block_free(block,&dir) {
@@ -304,9 +350,9 @@ impl Disk
                 lab.timestamp_creation(true);
                 lab.timestamp_update(true);
             }
-            let mut dir = self.get_directory();
+            let mut dir = self.get_directory()?;
             if vol_name.len() > 0 || time.is_some() {
-                dir.set_entry::<Label>(&Ptr::ExtentEntry(0), &lab);
+                dir.set_entry::<Label>(&Ptr::ExtentEntry(0), &lab)?;
             }
            let final_dir = match time.is_some() {
                 true => dir.add_timestamps()?,
@@ -319,11 +365,11 @@ impl Disk
     /// Read any file into a file image. Use `FileImage::sequence` to make the result sequential.
     fn read_file(&mut self,xname: &str) -> Result<FileImage,DYNERR> {
         trace!("attempt to read {}",xname);
-        let dir = self.get_directory();
-        let files = dir.build_files(&self.dpb,self.cpm_vers)?;
+        let dir = self.get_directory()?;
+        let files = dir.build_files(&self.dpb,self.cpm_vers,self.lenient)?;
         if let Some(finfo) = get_file(xname,&files) {
             let pointers: Vec<&Ptr> = finfo.entries.values().collect();
-            let mut ans = new_fimg(self.dpb.block_size(),false,xname)?;
+            let mut ans = new_fimg(self.dpb.block_size(),None,xname)?;
             let mut buf = vec![0;self.dpb.block_size()];
             let mut block_count = 0;
             let mut prev_lx_count = 0;
@@ -354,7 +400,8 @@ impl Disk
                     }
                     let lx_lower_bound = (curr_lx_count - 1) & (usize::MAX ^ self.dpb.exm as usize);
                     if lx_lower_bound < prev_lx_count {
-                        panic!("unreachable: extents were not sorted");
+                        error!("overlapping extent index");
+                        return Err(Box::new(Error::BadFormat));
                     }
                     block_count += (lx_lower_bound - prev_lx_count) * LOGICAL_EXTENT_SIZE / self.dpb.block_size();
                     // Get the data
@@ -403,7 +450,7 @@ impl Disk
         return (Ptr::ExtentEntry(entry_idx),Some(fx));
     }
     /// Update extent data and save to directory buffer
-    fn close_extent(&self,entry_ptr: &Ptr,fx: &mut Extent,dir: &mut Directory,lx_count: usize,is_last: bool,fimg: &FileImage) {
+    fn close_extent(&self,entry_ptr: &Ptr,fx: &mut Extent,dir: &mut Directory,lx_count: usize,is_last: bool,fimg: &FileImage) -> STDRESULT {
         trace!("close extent with index {}",lx_count-1);
         trace!("block pointers {:?}",fx.block_list);
         fx.set_data_ptr(Ptr::ExtentData(lx_count-1));
@@ -413,7 +460,7 @@ impl Disk
             remainder = self.dpb.extent_capacity();
         }
         fx.set_eof(remainder,self.cpm_vers);
-        dir.set_entry(entry_ptr,fx);
+        dir.set_entry(entry_ptr,fx)
     }
     /// Write any file from a file image.  Use `FileImage::desequence` to convert sequential data.
     fn write_file(&mut self,xname: &str,fimg: &FileImage) -> Result<usize,DYNERR> {
@@ -422,8 +469,8 @@ impl Disk
             error!("invalid CP/M filename");
             return Err(Box::new(Error::BadFormat));
         }
-        let mut dir = self.get_directory();
-        let files = dir.build_files(&self.dpb,self.cpm_vers)?;
+        let mut dir = self.get_directory()?;
+        let files = dir.build_files(&self.dpb,self.cpm_vers,self.lenient)?;
         if get_file(xname,&files).is_some() {
             error!("overwriting is not allowed");
             return Err(Box::new(Error::FileExists));
@@ -486,7 +533,7 @@ impl Disk
                         }
                         if let Some(fx) = maybe_fx.as_mut() {
                             fx.set_block_ptr(loc_slot, lx, iblock, &self.dpb);
-                            dir.set_entry(&entry_ptr, fx);
+                            dir.set_entry(&entry_ptr, fx)?;
                             self.write_block(&fimg.chunks[&glob_slot], iblock as usize, 0)?;
                         }
                     }
@@ -500,7 +547,7 @@ impl Disk
             // update totals and save the extent to the directory buffer
             lx_count_tot += lx_used_in_x;
             if let Some(fx) = maybe_fx.as_mut() {
-                self.close_extent(&entry_ptr, fx, &mut dir, lx_count_tot, x+1 == extents, &fimg);
+                self.close_extent(&entry_ptr, fx, &mut dir, lx_count_tot, x+1 == extents, fimg)?;
                 maybe_fx = None;
                 x_created_count += 1;
             }
@@ -508,12 +555,12 @@ impl Disk
         // if the file is still empty create an empty extent
         if x_created_count==0 {
             (entry_ptr,maybe_fx) = self.open_extent(&name,user,fimg,&dir,&mut maybe_entry1);
-            self.close_extent(&entry_ptr,&mut maybe_fx.unwrap(),&mut dir,1,true,&fimg);
+            self.close_extent(&entry_ptr,&mut maybe_fx.unwrap(),&mut dir,1,true,fimg)?;
         }
         // update the timestamp if applicable
         if let (Some(lab),Some(lx0)) = (dir.find_label(),maybe_entry1) {
             debug!("creating timestamp for entry {}",lx0.unwrap());
-            Timestamp::maybe_set_create(&mut dir, &lab, &lx0, None)?;
+            Timestamp::maybe_set_create(&mut dir, &lab, &lx0, self.now())?;
         }
         // save the directory changes
         self.save_directory(&dir)?;
@@ -528,8 +575,8 @@ impl Disk
             error!("invalid CP/M filename");
             return Err(Box::new(Error::BadFormat));
         }
-        let mut dir = self.get_directory();
-        let files = dir.build_files(&self.dpb,self.cpm_vers)?;
+        let mut dir = self.get_directory()?;
+        let files = dir.build_files(&self.dpb,self.cpm_vers,self.lenient)?;
         if let Some(finfo) = get_file(old_xname,&files) {
             // Rename
             if let Some(new_xname) = maybe_new_xname {
@@ -549,7 +596,7 @@ impl Disk
                             let (base,typ) = string_to_file_name(&new_name);
                             fx.user = new_user;
                             fx.set_name(base,typ);
-                            dir.set_entry(&entry,&fx);
+                            dir.set_entry(entry,&fx)?;
                         }
                     }
                 } else {
@@ -569,7 +616,7 @@ impl Disk
                         };
                     }
                     fx.set_flags(new_flags[0..8].try_into().expect(RCH),new_flags[8..11].try_into().expect(RCH));
-                    dir.set_entry(&entry,&fx);
+                    dir.set_entry(entry,&fx)?;
                 }
             }
             self.save_directory(&dir)?;
@@ -582,13 +629,17 @@ impl Disk
 
 impl super::DiskFS for Disk {
     fn new_fimg(&self, chunk_len: Option<usize>,set_time: bool,path: &str) -> Result<FileImage,DYNERR> {
+        let time = match set_time {
+            true => self.now(),
+            false => None
+        };
         match chunk_len {
-            Some(l) => new_fimg(l,set_time,path),
-            None => new_fimg(self.dpb.block_size(),set_time,path)
+            Some(l) => new_fimg(l,time,path),
+            None => new_fimg(self.dpb.block_size(),time,path)
         }
     }
     fn stat(&mut self) -> Result<super::Stat,DYNERR> {
-        let dir = &self.get_directory();
+        let dir = &self.get_directory()?;
         Ok(super::Stat {
             fs_name: FS_NAME.to_string(),
             label: match dir.find_label() {
@@ -610,19 +661,22 @@ impl super::DiskFS for Disk {
             raw: self.dpb.to_json(Some(1))
         })
     }
-    fn catalog_to_stdout(&mut self, opt: &str) -> STDRESULT {
-        let dir = self.get_directory();
+    fn catalog_to_string(&mut self, opt: &str) -> Result<String,DYNERR> {
+        let dir = self.get_directory()?;
         match opt {
-            "/" => display::dir(&dir,&self.dpb,""),
-            _ => display::dir(&dir,&self.dpb,opt)
+            "/" => display::dir(&dir,&self.dpb,"",self.lenient),
+            _ => display::dir(&dir,&self.dpb,opt,self.lenient)
         }
     }
     fn catalog_to_vec(&mut self, path: &str) -> Result<Vec<String>,DYNERR> {
+        Ok(self.catalog_items(path)?.into_iter().map(|x| x.row).collect())
+    }
+    fn catalog_items(&mut self, path: &str) -> Result<Vec<super::CatalogItem>,DYNERR> {
         if path!="/" && path!="" {
             return Err(Box::new(Error::FileNotFound));
         }
-        let dir = self.get_directory();
-        match dir.build_files(&self.dpb, [3,0,0]) {
+        let dir = self.get_directory()?;
+        match dir.build_files(&self.dpb, [3,0,0], self.lenient) {
             Ok(files) => {
                 let mut ans = Vec::new();
                 let mut multi_user = false;
@@ -631,30 +685,36 @@ impl super::DiskFS for Disk {
                         multi_user = true;
                     }
                 }
-                for (name,info) in files {
-                    match multi_user {
-                        true => ans.push(super::universal_row(&info.typ,info.blocks_allocated,&name)),
-                        false => ans.push(super::universal_row(&info.typ, info.blocks_allocated, &info.name))
-                    }
+                for (key,info) in files {
+                    let name = match multi_user {
+                        true => key,
+                        false => info.name.clone()
+                    };
+                    // first block of the first logical extent
+                    let addr = match info.entries.values().next().and_then(|ptr| dir.get_entry::<Extent>(ptr)) {
+                        Some(fx) => fx.get_block_list(&self.dpb).first().copied().unwrap_or(0) as usize,
+                        None => 0
+                    };
+                    ans.push(super::CatalogItem {
+                        row: super::universal_row(&info.typ,info.blocks_allocated,&name),
+                        name,
+                        typ: info.typ.clone(),
+                        blocks: info.blocks_allocated,
+                        modified: info.update_time.filter(|t| *t!=[0;4]).map(unpack_date),
+                        addr
+                    });
                 }
                 Ok(ans)
             },
             Err(e) => Err(e)
         }
     }
-    fn glob(&mut self,pattern: &str,case_sensitive: bool) -> Result<Vec<String>,DYNERR> {
+    fn glob_with(&mut self,pattern: &str,opt: super::GlobOptions) -> Result<Vec<String>,DYNERR> {
         let mut ans = Vec::new();
-        let glob = match case_sensitive {
-            true => globset::Glob::new(pattern)?.compile_matcher(),
-            false => globset::Glob::new(&pattern.to_uppercase())?.compile_matcher()
-        };
-        let dir = self.get_directory();
-        let files = dir.build_files(&self.dpb, self.cpm_vers)?;
+        let glob = super::NameMatcher::new(pattern,opt)?;
+        let dir = self.get_directory()?;
+        let files = dir.build_files(&self.dpb, self.cpm_vers, self.lenient)?;
         for (name,_info) in files {
-            let name = match case_sensitive {
-                true => name.clone(),
-                false => name.to_uppercase()
-            };
             if glob.is_match(&name) {
                 ans.push(name);
             }
@@ -662,16 +722,47 @@ impl super::DiskFS for Disk {
         Ok(ans)
     }
     fn tree(&mut self,include_meta: bool,indent: Option<u16>) -> Result<String,DYNERR> {
-        let dir = self.get_directory();
-        display::tree(&dir,&self.dpb,include_meta,indent)
+        let dir = self.get_directory()?;
+        display::tree(&dir,&self.dpb,include_meta,indent,self.lenient)
     }
     fn create(&mut self,_path: &str) -> STDRESULT {
         error!("CP/M implementation does not support operation");
         return Err(Box::new(Error::Select));
     }
+    fn stat_deep(&mut self) -> Result<json::JsonValue,DYNERR> {
+        let dir = self.get_directory()?;
+        let dpb = &self.dpb;
+        let mut ans = json::JsonValue::new_object();
+        ans["dpb"] = json::object! {
+            "spt": dpb.spt,
+            "bsh": dpb.bsh,
+            "blm": dpb.blm,
+            "exm": dpb.exm,
+            "dsm": dpb.dsm,
+            "drm": dpb.drm,
+            "al0": dpb.al0,
+            "al1": dpb.al1,
+            "cks": dpb.cks,
+            "off": dpb.off,
+            "psh": dpb.psh,
+            "phm": dpb.phm
+        };
+        ans["derived"] = json::object! {
+            "cpm_version": self.cpm_vers.iter().map(|v| v.to_string()).collect::<Vec<String>>().join("."),
+            "block_size": dpb.block_size(),
+            "user_blocks": dpb.user_blocks(),
+            "reserved_blocks": dpb.reserved_blocks(),
+            "dir_entries": dpb.dir_entries(),
+            "dir_blocks": dpb.dir_blocks(),
+            "extent_capacity": dpb.extent_capacity(),
+            "free_blocks": self.num_free_blocks(&dir),
+            "image": super::image_json(self.img.as_ref())
+        };
+        Ok(ans)
+    }
     fn delete(&mut self,xname: &str) -> STDRESULT {
-        let mut dir = self.get_directory();
-        let files = dir.build_files(&self.dpb,self.cpm_vers)?;
+        let mut dir = self.get_directory()?;
+        let files = dir.build_files(&self.dpb,self.cpm_vers,self.lenient)?;
         if let Some(finfo) = get_file(xname,&files) {
             let pointers: Vec<&Ptr> = finfo.entries.values().collect();
             for ptr in &pointers {
@@ -681,7 +772,7 @@ impl super::DiskFS for Disk {
                         return Err(Box::new(Error::FileReadOnly));
                     }
                     fx.user = DELETED;
-                    dir.set_entry(ptr,&fx);
+                    dir.set_entry(ptr,&fx)?;
                 }
             }
             self.save_directory(&dir)?;
@@ -695,7 +786,7 @@ impl super::DiskFS for Disk {
             error!("password is invalid");
             return Err(Box::new(Error::BadFormat));
         }
-        let mut dir = self.get_directory();
+        let mut dir = self.get_directory()?;
         if !read && !write && !delete {
             error!("no flag specified, perhaps use unprotect");
             return Err(Box::new(Error::BadFormat));
@@ -704,7 +795,7 @@ impl super::DiskFS for Disk {
             error!("no label on this disk, cannot protect");
             return Err(Box::new(Error::BadFormat));
         }
-        let files = dir.build_files(&self.dpb,self.cpm_vers)?;
+        let files = dir.build_files(&self.dpb,self.cpm_vers,self.lenient)?;
         if let Some(_) = get_file(xname,&files) {
             let (user,name_string) = split_user_filename(xname)?;
             let (name,typ) = string_to_file_name(&name_string);
@@ -713,7 +804,7 @@ impl super::DiskFS for Disk {
             for i in 0..dir.num_entries() {
                 if let Some(old_px) = dir.get_entry::<Password>(&Ptr::ExtentEntry(i)) {
                     if old_px.user==user+16 && old_px.name==name && old_px.typ==typ {
-                        dir.set_entry::<Password>(&Ptr::ExtentEntry(i), &new_px);
+                        dir.set_entry::<Password>(&Ptr::ExtentEntry(i), &new_px)?;
                         return self.save_directory(&dir);
                     }
                 }
@@ -724,11 +815,11 @@ impl super::DiskFS for Disk {
                 if let Some(mut lab) = dir.get_entry::<Label>(&Ptr::ExtentEntry(i)) {
                     // TODO: is this protecting the label, or enabling file protection?
                     lab.protect(true);
-                    dir.set_entry::<Label>(&Ptr::ExtentEntry(i), &lab);
+                    dir.set_entry::<Label>(&Ptr::ExtentEntry(i), &lab)?;
                     completion += 1;
                 }
-                if dir.get_type(&Ptr::ExtentEntry(i))==ExtentType::Deleted {
-                    dir.set_entry::<Password>(&Ptr::ExtentEntry(i), &new_px);
+                if matches!(dir.get_type(&Ptr::ExtentEntry(i)),Ok(ExtentType::Deleted)) {
+                    dir.set_entry::<Password>(&Ptr::ExtentEntry(i), &new_px)?;
                     completion += 1;
                 }
                 if completion==2 {
@@ -741,7 +832,7 @@ impl super::DiskFS for Disk {
     }
     fn unprotect(&mut self,xname: &str) -> STDRESULT {
         let mut found = false;
-        let mut dir = self.get_directory();
+        let mut dir = self.get_directory()?;
         debug!("removing password for {}",xname);
         let (user,name_string) = split_user_filename(xname)?;
         let (name,typ) = string_to_file_name(&name_string);
@@ -749,7 +840,7 @@ impl super::DiskFS for Disk {
             if let Some(mut px) = dir.get_entry::<Password>(&Ptr::ExtentEntry(i)) {
                 if px.user==user+16 && px.name==name && px.typ==typ {
                     px.user = DELETED;
-                    dir.set_entry::<Password>(&Ptr::ExtentEntry(i), &px);
+                    dir.set_entry::<Password>(&Ptr::ExtentEntry(i), &px)?;
                     found = true;
                 }
             }
@@ -770,6 +861,27 @@ impl super::DiskFS for Disk {
     fn rename(&mut self,old_xname: &str,new_xname: &str) -> STDRESULT {
         return self.modify(old_xname,Some(new_xname),[0;11]);
     }
+    fn rename_wildcard(&mut self,pattern: &str,new_pattern: &str) -> Result<usize,DYNERR> {
+        if new_pattern.contains(':') {
+            error!("wildcard rename stays within the user");
+            return Err(Box::new(Error::BadFormat));
+        }
+        let pattern = match pattern.contains(':') {
+            true => pattern.to_string(),
+            false => ["0:",pattern].concat()
+        };
+        let matches = self.glob(&pattern,false)?;
+        if matches.is_empty() {
+            error!("no file matches {}",pattern);
+            return Err(Box::new(Error::FileNotFound));
+        }
+        let existing = self.glob("*",false)?;
+        let renames = super::wildcard_renames(&matches,new_pattern,&existing)?;
+        for (old,new) in &renames {
+            self.rename(old,new)?;
+        }
+        Ok(renames.len())
+    }
     fn retype(&mut self,xname: &str,new_type: &str,_sub_type: &str) -> STDRESULT {
         // CP/M v2 or higher uses bit 7 of typ[1] for system file (hidden file)
         if new_type=="sys" {
@@ -802,6 +914,53 @@ impl super::DiskFS for Disk {
             Err(e) => Err(Box::new(e))
         }
     }
+    fn read_boot(&mut self) -> Result<Vec<u8>,DYNERR> {
+        let mut ans = Vec::new();
+        for addr in self.boot_chunks() {
+            ans.append(&mut self.img.read_block(addr)?);
+        }
+        Ok(ans)
+    }
+    fn write_boot(&mut self,dat: &[u8]) -> Result<usize,DYNERR> {
+        let mut buf = self.read_boot()?;
+        if dat.len() > buf.len() {
+            error!("system image has {} bytes, but the reserved tracks hold {}",dat.len(),buf.len());
+            return Err(Box::new(Error::BadFormat));
+        }
+        buf[0..dat.len()].copy_from_slice(dat);
+        let mut ptr = 0;
+        for addr in self.boot_chunks() {
+            let len = match addr {
+                Block::CPM((_,shift,_)) => RECORD_SIZE << shift,
+                _ => panic!("unexpected block type")
+            };
+            self.img.write_block(addr,&buf[ptr..ptr+len])?;
+            ptr += len;
+        }
+        Ok(dat.len())
+    }
+    fn set_time(&mut self,xname: &str,time: chrono::NaiveDateTime) -> STDRESULT {
+        let mut dir = self.get_directory()?;
+        let files = dir.build_files(&self.dpb,self.cpm_vers,self.lenient)?;
+        let lx0 = match get_file(xname,&files).and_then(|finfo| finfo.entries.values().next()) {
+            Some(ptr) => *ptr,
+            None => return Err(Box::new(Error::FileNotFound))
+        };
+        match dir.find_label() {
+            Some(lab) if lab.is_timestamped_creation() || lab.is_timestamped_update() => {
+                Timestamp::maybe_set_create(&mut dir, &lab, &lx0, Some(time))?;
+                self.save_directory(&dir)
+            },
+            _ => {
+                warn!("timestamps are not enabled on this disk");
+                Ok(())
+            }
+        }
+    }
+    fn exists(&mut self,xname: &str) -> Result<bool,DYNERR> {
+        let files = self.get_directory()?.build_files(&self.dpb,self.cpm_vers,self.lenient)?;
+        Ok(get_file(xname,&files).is_some())
+    }
     fn get(&mut self,xname: &str) -> Result<FileImage,DYNERR> {
         self.read_file(xname)
     }
@@ -853,6 +1012,37 @@ impl super::DiskFS for Disk {
             }
         }
     }
+    fn relabel(&mut self,label: &str) -> STDRESULT {
+        if self.cpm_vers[0] < 3 {
+            error!("labels require CP/M 3");
+            return Err(Box::new(Error::BadFormat));
+        }
+        if !is_name_valid(label) {
+            error!("CP/M volume name invalid");
+            return Err(Box::new(Error::BadFormat));
+        }
+        let mut dir = self.get_directory()?;
+        let now = self.now();
+        let (ptr,mut lab) = match dir.find_label_ptr() {
+            Some(ptr) => (ptr,dir.get_entry::<Label>(&ptr).expect(RCH)),
+            None => match self.get_available_extent(&dir) {
+                Some(idx) => {
+                    let mut lab = Label::create();
+                    lab.set_timestamp_for_label(now,None);
+                    (Ptr::ExtentEntry(idx),lab)
+                },
+                None => return Err(Box::new(Error::DirectoryFull))
+            }
+        };
+        let (name,typ) = string_to_file_name(label);
+        lab.set(name,typ);
+        lab.set_timestamp_for_label(None,now);
+        dir.set_entry(&ptr,&lab)?;
+        self.save_directory(&dir)
+    }
+    fn all_blocks(&mut self) -> Result<Vec<Block>,DYNERR> {
+        Ok((0..self.dpb.user_blocks()).map(|b| Block::CPM((b,self.dpb.bsh,self.dpb.off))).collect())
+    }
     fn get_img(&mut self) -> &mut Box<dyn img::DiskImage> {
         &mut self.img
     }
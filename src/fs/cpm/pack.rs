@@ -4,23 +4,29 @@
 //! Functions to help pack or unpack dates, filenames, and passwords.
 //! N.b. CP/M passwords are stored with a trivial encryption algorithm
 //! and should not be considered secure.
+//!
+//! This module is library-safe, see `crate::fs`.
 
-use chrono::{Timelike,Duration};
+#![deny(clippy::panic,clippy::unwrap_used,clippy::expect_used)]
+
+use chrono::{Timelike,Duration,NaiveDate,NaiveTime};
 use std::str::FromStr;
 use a2kit_macro::DiskStruct;
 use super::types;
 use super::{Packer,Error};
 use super::super::{Packing,FileImage,UnpackedData};
 use crate::{STDRESULT,DYNERR};
-const RCH: &str = "unreachable was reached";
+/// CP/M day 1 is 1 Jan 1978
+fn ref_date() -> NaiveDate {
+    NaiveDate::from_ymd_opt(1978, 1, 1).unwrap_or_default()
+}
 
 pub fn pack_date(time: Option<chrono::NaiveDateTime>) -> [u8;4] {
     let now = match time {
         Some(t) => t,
-        _ => chrono::Local::now().naive_local()
+        _ => crate::now(false)
     };
-    let ref_date = chrono::NaiveDate::from_ymd_opt(1978, 1, 1).unwrap()
-        .and_hms_opt(0,0,0).unwrap();
+    let ref_date = ref_date().and_time(NaiveTime::MIN);
     let days = match now.signed_duration_since(ref_date).num_days() {
         d if d>u16::MAX as i64 => {
             log::warn!("timestamp is pegged at {} days after reference date",u16::MAX);
@@ -39,11 +45,16 @@ pub fn pack_date(time: Option<chrono::NaiveDateTime>) -> [u8;4] {
 
 // TODO: return an option
 pub fn unpack_date(cpm_date: [u8;4]) -> chrono::NaiveDateTime {
-    let ref_date = chrono::NaiveDate::from_ymd_opt(1978, 1, 1).unwrap();
-    let now = ref_date + Duration::days(u16::from_le_bytes([cpm_date[0],cpm_date[1]]) as i64 - 1);
+    let now = ref_date() + Duration::days(u16::from_le_bytes([cpm_date[0],cpm_date[1]]) as i64 - 1);
     let hours = (cpm_date[2] & 0x0f) + 10*(cpm_date[2] >> 4);
     let minutes = (cpm_date[3] & 0x0f) + 10*(cpm_date[3] >> 4);
-    return now.and_hms_opt(hours.into(), minutes.into(), 0).unwrap();
+    match now.and_hms_opt(hours.into(), minutes.into(), 0) {
+        Some(t) => t,
+        None => {
+            log::warn!("invalid time of day in timestamp");
+            now.and_time(NaiveTime::MIN)
+        }
+    }
 }
 
 /// Take string such as `2:USER2.TXT` and return (2,"USER2.TXT")
@@ -110,9 +121,9 @@ pub fn file_name_to_string(name: [u8;8],typ: [u8;3]) -> String {
     let base: Vec<u8> = name.iter().map(|x| x & 0x7f).collect();
     let ext: Vec<u8> = typ.iter().map(|x| x & 0x7f).collect();
     [
-        &String::from_utf8(base).expect(RCH).trim_end(),
+        String::from_utf8_lossy(&base).trim_end(),
         ".",
-        &String::from_utf8(ext).expect(RCH).trim_end(),
+        String::from_utf8_lossy(&ext).trim_end(),
     ].concat()
 }
 
@@ -121,8 +132,8 @@ pub fn file_name_to_split_string(name: [u8;8],typ: [u8;3]) -> (String,String) {
     // in CP/M high bits are explicitly not part of the name
     let base: Vec<u8> = name.iter().map(|x| x & 0x7f).collect();
     let ext: Vec<u8> = typ.iter().map(|x| x & 0x7f).collect();
-    (   String::from_utf8(base).expect(RCH).trim_end().to_string(),
-        String::from_utf8(ext).expect(RCH).trim_end().to_string()
+    (   String::from_utf8_lossy(&base).trim_end().to_string(),
+        String::from_utf8_lossy(&ext).trim_end().to_string()
     )
 }
 
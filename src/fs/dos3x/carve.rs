@@ -0,0 +1,148 @@
+//! ### Carving DOS 3.x files
+//!
+//! Any sector that parses as a track/sector list, with every pair on the disk, is taken to
+//! belong to a file.  Lists with a zero sector offset start a file, the rest are reached by
+//! following the links.  Deleted files keep their lists, so they are found as well.
+
+use std::collections::HashMap;
+use a2kit_macro::DiskStruct;
+use super::directory::TrackSectorList;
+use super::types::MAX_TSLIST_REPS;
+use crate::fs::Block;
+use crate::fs::carve::{Carved,Evidence,classify};
+use crate::img::DiskImage;
+
+const PAIRS: usize = 122;
+
+struct List {
+    base: usize,
+    next: [usize;2],
+    /// `None` is a hole in a sparse file
+    pairs: Vec<Option<[usize;2]>>
+}
+
+fn addr(d13: bool,ts: [usize;2]) -> Block {
+    match d13 {
+        true => Block::D13(ts),
+        false => Block::DO(ts)
+    }
+}
+
+/// Interpret the sector as a track/sector list, if it is plausible
+fn parse(buf: &[u8],tracks: usize,sectors: usize) -> Option<List> {
+    let tsl = TrackSectorList::from_bytes(buf).ok()?;
+    if tsl.pad1 != 0 || tsl.pad2 != [0,0] || tsl.pad3 != [0;5] {
+        return None;
+    }
+    let base = u16::from_le_bytes(tsl.sector_base) as usize;
+    if !base.is_multiple_of(PAIRS) {
+        return None;
+    }
+    let next = [tsl.next_track as usize,tsl.next_sector as usize];
+    if next != [0,0] && (next[0] == 0 || next[0] >= tracks || next[1] >= sectors) {
+        return None;
+    }
+    let mut pairs = Vec::new();
+    for p in 0..PAIRS {
+        let ts = [tsl.pairs[p*2] as usize,tsl.pairs[p*2+1] as usize];
+        match ts {
+            [0,0] => pairs.push(None),
+            [t,s] if t > 0 && t < tracks && s < sectors => pairs.push(Some(ts)),
+            _ => return None
+        }
+    }
+    while pairs.last() == Some(&None) {
+        pairs.pop();
+    }
+    let mut distinct: Vec<[usize;2]> = pairs.iter().flatten().copied().collect();
+    let count = distinct.len();
+    distinct.sort();
+    distinct.dedup();
+    if count == 0 || distinct.len() < count {
+        return None;
+    }
+    Some(List { base, next, pairs })
+}
+
+/// Carve files from the track/sector lists on a 13 or 16 sector disk.
+/// Each file comes with the sectors it uses, including the lists.
+pub fn track_sector_lists(img: &mut Box<dyn DiskImage>,d13: bool,tracks: usize) -> Vec<(Carved,Vec<Block>)> {
+    let sectors = match d13 {
+        true => 13,
+        false => 16
+    };
+    let mut lists: HashMap<[usize;2],List> = HashMap::new();
+    for t in 1..tracks {
+        for s in 0..sectors {
+            if let Ok(buf) = img.read_block(addr(d13,[t,s])) {
+                if let Some(list) = parse(&buf,tracks,sectors) {
+                    lists.insert([t,s],list);
+                }
+            }
+        }
+    }
+    let linked: Vec<[usize;2]> = lists.values().map(|l| l.next).collect();
+    let mut heads: Vec<[usize;2]> = lists.iter().filter(|(ts,l)| l.base == 0 && !linked.contains(ts)).map(|(ts,_)| *ts).collect();
+    heads.sort();
+    let mut ans = Vec::new();
+    for head in heads {
+        let mut used = Vec::new();
+        let mut pairs = Vec::new();
+        let mut sound = true;
+        let mut curr = head;
+        for _try in 0..MAX_TSLIST_REPS {
+            let list = match lists.get(&curr) {
+                Some(l) if l.base == pairs.len() && !used.contains(&addr(d13,curr)) => l,
+                _ => {
+                    sound = false;
+                    break;
+                }
+            };
+            used.push(addr(d13,curr));
+            pairs.extend_from_slice(&list.pairs);
+            if list.next == [0,0] {
+                break;
+            }
+            // pairs of a list that is not the last are all present
+            pairs.resize(list.base + PAIRS,None);
+            curr = list.next;
+        }
+        let mut dat = Vec::new();
+        let mut unreadable = 0;
+        for ts in &pairs {
+            match ts {
+                Some(ts) => match img.read_block(addr(d13,*ts)) {
+                    Ok(mut buf) => {
+                        used.push(addr(d13,*ts));
+                        dat.append(&mut buf);
+                    },
+                    Err(_) => {
+                        unreadable += 1;
+                        dat.append(&mut vec![0;256]);
+                    }
+                },
+                None => dat.append(&mut vec![0;256])
+            }
+        }
+        let (content,addr,data,conf) = classify(&dat,true);
+        if data.is_empty() {
+            continue;
+        }
+        let mut confidence = 0.5 + 0.5 * conf;
+        if !sound {
+            confidence *= 0.7;
+        }
+        if unreadable > 0 {
+            confidence *= 1.0 - unreadable as f64 / pairs.len() as f64;
+        }
+        ans.push((Carved {
+            evidence: Evidence::TrackSectorList,
+            location: used[0],
+            content,
+            addr,
+            data,
+            confidence
+        },used));
+    }
+    ans
+}
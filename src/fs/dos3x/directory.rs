@@ -48,7 +48,7 @@ pub struct VTOC {
     pub tracks: u8,
     pub sectors: u8,
     pub bytes: [u8;2],
-    pub bitmap: [u8;140]
+    pub bitmap: [u8;200]
 }
 
 impl VTOC {
@@ -5,17 +5,25 @@
 //! wide latitude is allowed for track counts, while sector counts
 //! are restricted to 13, 16, or 32.
 //! 
+//! Besides the standard 35 tracks, DOS 3.3 volumes with up to 50 tracks are handled, as are the
+//! 80 track volumes of patched DOS versions for high capacity drives.  The VTOC bitmap has room
+//! for 50 tracks at 4 bytes per track.  When there are more than 50 tracks the bitmap is packed
+//! at 2 bytes per track, which is enough for 16 sectors.
+//!
 //! The module will try to emulate the order in which DOS would access sectors
 
 pub mod types;
 mod boot;
 mod directory;
 mod pack;
+pub mod carve;
+pub mod variant;
 
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::fmt::Write;
 use a2kit_macro::DiskStruct;
+use num_traits::FromPrimitive;
 
 use types::*;
 use pack::*;
@@ -57,7 +65,8 @@ pub struct Disk
 {
     // VTOC works for any DOS 3.x
     maybe_vtoc: Option<VTOC>,
-    img: Box<dyn img::DiskImage>
+    img: Box<dyn img::DiskImage>,
+    quirks: super::quirks::Quirks
 }
 
 impl Disk
@@ -67,9 +76,23 @@ impl Disk
     pub fn from_img(img: Box<dyn img::DiskImage>) -> Result<Self,DYNERR> {
         Ok(Self {
             maybe_vtoc: None,
-            img
+            img,
+            quirks: super::quirks::Quirks::DEFAULT
         })
     }
+    /// Use the given quirks profile rather than the default
+    pub fn with_quirks(mut self,quirks: super::quirks::Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+    /// Track counts that can hold a volume with the given number of sectors per track
+    fn tracks_allowed(tracks: usize,sectors: usize) -> bool {
+        match sectors {
+            13 => tracks==35,
+            16 => (35..=MAX_TRACKS_STD as usize).contains(&tracks) || tracks==MAX_TRACKS_PACKED as usize,
+            _ => false
+        }
+    }
     fn test_img_13(img: &mut Box<dyn img::DiskImage>) -> bool {
         if let Ok(dat) = img.read_block(Block::D13([17,0])) {
             let vtoc = match VTOC::from_bytes(&dat) {
@@ -85,7 +108,7 @@ impl Disk
                 log::debug!("D13: Volume {} out of range",vtoc.vol);
                 return false;
             }
-            if vtoc.track1 != VTOC_TRACK || vtoc.sector1 != slen-1 {
+            if vtoc.track1 == 0 || vtoc.track1 as usize >= tlen || vtoc.sector1 >= slen {
                 log::debug!("D13: VTOC wrong track1 {}, sector1 {}",vtoc.track1,vtoc.sector1);
                 return false;
             }
@@ -104,7 +127,7 @@ impl Disk
                 Ok(res) => res,
                 Err(_) => return false
             };
-            let (tlen,slen) = (35,16);
+            let (tlen,slen) = (img.track_count(),16);
             if vtoc.version<3 {
                 log::debug!("VTOC wrong version {}",vtoc.version);
                 return false;
@@ -113,7 +136,7 @@ impl Disk
                 log::debug!("Volume {} out of range",vtoc.vol);
                 return false;
             }
-            if vtoc.track1 != VTOC_TRACK || vtoc.sector1 != slen-1 {
+            if vtoc.track1 == 0 || vtoc.track1 as usize >= tlen || vtoc.sector1 >= slen {
                 log::debug!("VTOC wrong track1 {}, sector1 {}",vtoc.track1,vtoc.sector1);
                 return false;
             }
@@ -129,15 +152,17 @@ impl Disk
     /// Test an image to see if it already contains DOS 3.x.
     pub fn test_img(img: &mut Box<dyn img::DiskImage>) -> bool {
         let tlen = img.track_count();
-        if tlen!=35 {
+        if !Self::tracks_allowed(tlen,13) && !Self::tracks_allowed(tlen,16) {
             log::debug!("track count is unexpected");
             return false;
         }
         let old_kind = img.kind();
-        img.change_kind(img::names::A2_DOS32_KIND);
-        log::debug!("change to 13 sectors");
-        if Self::test_img_13(img) {
-            return true;
+        if Self::tracks_allowed(tlen,13) {
+            img.change_kind(img::names::A2_DOS32_KIND);
+            log::debug!("change to 13 sectors");
+            if Self::test_img_13(img) {
+                return true;
+            }
         }
         log::debug!("change to 16 sectors");
         img.change_kind(img::names::A2_DOS33_KIND);
@@ -169,7 +194,8 @@ impl Disk
         if let Some(vtoc) = self.maybe_vtoc.as_mut() {
             return Ok(vtoc);
         }
-        panic!("VTOC buffer failed to open");
+        log::error!("VTOC buffer failed to open");
+        Err(Box::new(Error::IOError))
     }
     /// Get the buffered VTOC immutably, will open buffer if necessary.
     fn get_vtoc_ref(&mut self) -> Result<&VTOC,DYNERR> {
@@ -177,7 +203,8 @@ impl Disk
         if let Some(vtoc) = self.maybe_vtoc.as_ref() {
             return Ok(vtoc);
         }
-        panic!("VTOC buffer failed to open");
+        log::error!("VTOC buffer failed to open");
+        Err(Box::new(Error::IOError))
     }
     /// Gets constant fields of the VTOC as copies, will open buffer if necessary.
     fn get_vtoc_constants(&mut self) -> Result<VolumeConstants,DYNERR> {
@@ -185,7 +212,8 @@ impl Disk
         if let Some(vtoc) = self.maybe_vtoc.as_ref() {
             return Ok(vtoc.get_constants());
         }
-        panic!("VTOC buffer failed to open");
+        log::error!("VTOC buffer failed to open");
+        Err(Box::new(Error::IOError))
     }
     /// Buffer needs to be written back when an external caller
     /// asks for the underlying image.
@@ -212,18 +240,26 @@ impl Disk
         }
         Ok(())
     }
+    /// Bytes per track in the VTOC bitmap, only the leading bytes are significant.
+    fn track_map_len(vtoc: &VTOC) -> usize {
+        match vtoc.tracks {
+            t if t > MAX_TRACKS_STD => 2,
+            _ => 4
+        }
+    }
     fn get_track_map(vtoc: &VTOC,track: u8) -> u32 {
         let bm = &vtoc.bitmap;
-        let i = (track*4) as usize;
-        u32::from_be_bytes([bm[i],bm[i+1],bm[i+2],bm[i+3]])
+        let n = Self::track_map_len(vtoc);
+        let i = track as usize * n;
+        let mut slice = [0;4];
+        slice[0..n].copy_from_slice(&bm[i..i+n]);
+        u32::from_be_bytes(slice)
     }
     fn save_track_map(vtoc: &mut VTOC,track: u8,map: u32) {
-        let i = (track*4) as usize;
+        let n = Self::track_map_len(vtoc);
+        let i = track as usize * n;
         let slice: [u8;4] = u32::to_be_bytes(map);
-        vtoc.bitmap[i] = slice[0];
-        vtoc.bitmap[i+1] = slice[1];
-        vtoc.bitmap[i+2] = slice[2];
-        vtoc.bitmap[i+3] = slice[3];
+        vtoc.bitmap[i..i+n].copy_from_slice(&slice[0..n]);
     }
     fn update_last_track(&mut self,track: u8) -> STDRESULT {
         let vtoc = self.get_vtoc_mut()?;
@@ -270,16 +306,16 @@ impl Disk
             x if x<=bytes_per_sector => x,
             _ => bytes_per_sector
         };
-        let buf = match ts {
+        match ts {
             // TODO: getting VTOC sector from buffer forces trailing bytes to read as 0;
             // High level callers can always read the physical sector from the image to avoid this.
-            [VTOC_TRACK,0] => img::quantize_block(&vtoc.to_bytes(), 256),
-            _ => self.img.read_block(self.addr(ts))?
-        };
-        for i in 0..actual_len as usize {
-            data[offset + i] = buf[i];
+            [VTOC_TRACK,0] => {
+                let buf = img::quantize_block(&vtoc.to_bytes(), 256);
+                data[offset..offset+actual_len as usize].copy_from_slice(&buf[0..actual_len as usize]);
+                Ok(())
+            },
+            _ => self.img.read_block_into(self.addr(ts),&mut data[offset..offset+actual_len as usize])
         }
-        Ok(())
     }
     /// Zap and allocate the sector in one step.
     /// If it is the VTOC panic; we should only be zapping VTOC.
@@ -308,7 +344,7 @@ impl Disk
     /// Create any DOS 3.x volume
     pub fn init(&mut self,vol:u8,bootable:bool,last_track_written:u8,tracks:u8,sectors:u8) -> STDRESULT {
         assert!(vol>0 && vol<255);
-        assert!(tracks>VTOC_TRACK && tracks<=50);
+        assert!(tracks>VTOC_TRACK && (tracks<=MAX_TRACKS_STD || (tracks<=MAX_TRACKS_PACKED && sectors<=16)));
         assert!(sectors==13 || sectors==16 || sectors==32);
         assert!(last_track_written>0 && last_track_written<tracks);
         
@@ -334,28 +370,22 @@ impl Disk
         vtoc.sectors = sectors;
         vtoc.tracks = tracks;
         // Mark as free except track 0
-        let all_free: [u8;4] = match sectors {
-            13 => u32::to_be_bytes(0xfff80000),
-            16 => u32::to_be_bytes(0xffff0000),
-            32 => u32::to_be_bytes(0xffffffff),
+        let all_free: u32 = match sectors {
+            13 => 0xfff80000,
+            16 => 0xffff0000,
+            32 => 0xffffffff,
             _ => panic!("unexpected sector count")
         };
-        for track in 1..tracks as usize {
-            vtoc.bitmap[track*4+0] = all_free[0];
-            vtoc.bitmap[track*4+1] = all_free[1];
-            vtoc.bitmap[track*4+2] = all_free[2];
-            vtoc.bitmap[track*4+3] = all_free[3];
+        for track in 1..tracks {
+            Self::save_track_map(&mut vtoc,track,all_free);
         }
         // If bootable mark DOS tracks as entirely used
         if bootable {
-            for i in 1*4..3*4 {
-                vtoc.bitmap[i] = 0;
-            }
+            Self::save_track_map(&mut vtoc,1,0);
+            Self::save_track_map(&mut vtoc,2,0);
         }
         // Mark track VTOC_TRACK as entirely used (VTOC and directory)
-        for i in VTOC_TRACK*4..(VTOC_TRACK+1)*4 {
-            vtoc.bitmap[i as usize] = 0;
-        }
+        Self::save_track_map(&mut vtoc,VTOC_TRACK,0);
         // zap in the VTOC
         self.zap_sector(&vtoc.to_bytes(),[VTOC_TRACK,0],0,256)?;
         // Write the directory sectors
@@ -382,6 +412,96 @@ impl Disk
         }
         Ok(())
     }
+    /// Every sector used by a file, with the file's name, including the track/sector lists
+    fn file_sectors(&mut self) -> Result<Vec<(String,[u8;2])>,DYNERR> {
+        let vconst = self.get_vtoc_constants()?;
+        let mut buf: Vec<u8> = vec![0;256];
+        let mut ans = Vec::new();
+        let mut dir_ts = [vconst.track1,vconst.sector1];
+        for _try in 0..types::MAX_DIRECTORY_REPS {
+            Self::verify_ts(&vconst,dir_ts[0], dir_ts[1])?;
+            self.read_sector(&mut buf, dir_ts, 0)?;
+            let dir = DirectorySector::from_bytes(&buf)?;
+            for entry in dir.entries.as_ref() {
+                if entry.tsl_track==0 || entry.tsl_track==255 {
+                    continue;
+                }
+                let name = file_name_to_string(entry.name);
+                let mut tslist_ts = [entry.tsl_track,entry.tsl_sector];
+                for _try2 in 0..types::MAX_TSLIST_REPS {
+                    Self::verify_ts(&vconst,tslist_ts[0],tslist_ts[1])?;
+                    ans.push((name.clone(),tslist_ts));
+                    self.read_sector(&mut buf, tslist_ts, 0)?;
+                    let tslist = TrackSectorList::from_bytes(&buf)?;
+                    for p in 0..vconst.max_pairs as usize {
+                        if tslist.pairs[p*2]>0 && tslist.pairs[p*2]<255 {
+                            ans.push((name.clone(),[tslist.pairs[p*2],tslist.pairs[p*2+1]]));
+                        }
+                    }
+                    tslist_ts = [tslist.next_track,tslist.next_sector];
+                    if tslist_ts[0]==0 {
+                        break;
+                    }
+                }
+            }
+            dir_ts = [dir.next_track,dir.next_sector];
+            if dir_ts == [0,0] {
+                return Ok(ans);
+            }
+        }
+        log::error!("number of directory sectors is not plausible, aborting");
+        Err(Box::new(Error::EndOfData))
+    }
+    /// Every catalog sector, following the chain from the VTOC
+    fn catalog_sectors(&mut self) -> Result<Vec<[u8;2]>,DYNERR> {
+        let vconst = self.get_vtoc_constants()?;
+        let mut buf: Vec<u8> = vec![0;256];
+        let mut ans = Vec::new();
+        let mut dir_ts = [vconst.track1,vconst.sector1];
+        for _try in 0..types::MAX_DIRECTORY_REPS {
+            Self::verify_ts(&vconst,dir_ts[0], dir_ts[1])?;
+            ans.push(dir_ts);
+            self.read_sector(&mut buf, dir_ts, 0)?;
+            let dir = DirectorySector::from_bytes(&buf)?;
+            dir_ts = [dir.next_track,dir.next_sector];
+            if dir_ts == [0,0] {
+                return Ok(ans);
+            }
+        }
+        log::error!("number of directory sectors is not plausible, aborting");
+        Err(Box::new(Error::EndOfData))
+    }
+    /// Whether each sector should be marked used, judging from the catalog and track/sector lists, indexed by track and sector.
+    /// Track 0, the VTOC, and the catalog are always used.  Whether tracks 1 and 2 are reserved for DOS cannot be
+    /// told from the catalog, so their sectors are `None`, meaning leave alone, unless a file uses them.
+    /// It is an error if a sector is claimed twice.
+    fn sectors_in_use(&mut self) -> Result<Vec<Vec<Option<bool>>>,DYNERR> {
+        let vconst = self.get_vtoc_constants()?;
+        let mut used = vec![vec![Some(false);vconst.sectors as usize];vconst.tracks as usize];
+        for track in [1,2] {
+            if let Some(secs) = used.get_mut(track) {
+                secs.fill(None);
+            }
+        }
+        if let Some(secs) = used.get_mut(0) {
+            secs.fill(Some(true));
+        }
+        let mut claims = vec![("VTOC".to_string(),[VTOC_TRACK,0])];
+        for ts in self.catalog_sectors()? {
+            claims.push(("the catalog".to_string(),ts));
+        }
+        claims.append(&mut self.file_sectors()?);
+        for (name,ts) in claims {
+            Self::verify_ts(&vconst,ts[0],ts[1])?;
+            let sec = &mut used[ts[0] as usize][ts[1] as usize];
+            if ts[0]>0 && *sec==Some(true) {
+                log::error!("track {} sector {} is claimed more than once, last by {}",ts[0],ts[1],name);
+                return Err(Box::new(Error::IOError));
+            }
+            *sec = Some(true);
+        }
+        Ok(used)
+    }
     /// Create a standard DOS 3.2 volume (116K)
     pub fn init32(&mut self,vol:u8,bootable:bool) -> STDRESULT {
         self.init(vol,bootable,17,35,13)
@@ -390,6 +510,26 @@ impl Disk
     pub fn init33(&mut self,vol:u8,bootable:bool) -> STDRESULT {
         self.init(vol,bootable,17,35,16)
     }
+    /// Create a DOS 3.3 volume with as many tracks as the image has, this can be 35 to 50, or 80
+    pub fn init33_tracks(&mut self,vol:u8,bootable:bool) -> STDRESULT {
+        let tracks = self.img.track_count();
+        if !Self::tracks_allowed(tracks,16) {
+            log::error!("DOS 3.3 cannot use {} tracks",tracks);
+            return Err(Box::new(Error::Range));
+        }
+        self.init(vol,bootable,17,tracks as u8,16)
+    }
+    /// Identify the version of DOS on tracks 0 through 2, see the `variant` module.
+    pub fn variant(&mut self) -> Result<variant::Variant,DYNERR> {
+        let vconst = self.get_vtoc_constants()?;
+        let mut dat = Vec::new();
+        for track in 0..3 {
+            for sector in 0..vconst.sectors {
+                dat.append(&mut self.img.read_block(self.addr([track,sector]))?);
+            }
+        }
+        Ok(variant::detect(&dat,vconst.sectors))
+    }
     fn num_free_sectors(&mut self) -> Result<usize,DYNERR> {
         let vtoc = self.get_vtoc_ref()?;
         let mut ans: usize = 0;
@@ -406,6 +546,8 @@ impl Disk
         // Search algorithm outlined in DOS manual seems inconsistent with actual results from emulators.
         // This algorithm is a guess at how DOS is doing it, based on emulator outputs.
         // Fortunately we don't have to emulate this exactly for the disk to work.
+        let quirks = self.quirks.clone();
+        let prefer_jump = prefer_jump && quirks.dos3x_jump_for_new_file;
         let vtoc = self.get_vtoc_ref()?;
         let tvtoc: u8 = vtoc.track1;
         let tstart = match vtoc.last_track {
@@ -431,8 +573,12 @@ impl Disk
             ].concat();
         }
         // search
+        let search_sectors: Vec<u8> = match quirks.dos3x_sector_order {
+            super::quirks::SectorOrder::Descending => (0..vtoc.sectors).rev().collect(),
+            super::quirks::SectorOrder::Ascending => (0..vtoc.sectors).collect()
+        };
         for track in search_tracks {
-            for sector in (0..vtoc.sectors).rev() {
+            for sector in search_sectors.iter().copied() {
                 if Self::is_sector_free(vtoc,track,sector) {
                     return Ok([track,sector]);
                 }
@@ -606,6 +752,222 @@ impl Disk
         
         return Ok(data_sectors + tslist_sectors);
     }
+    /// Scan the directory sectors to find the named file, returning (directory sector ptr,directory sector,entry index)
+    fn find_entry(&mut self,name: &str) -> Result<([u8;2],DirectorySector,usize),DYNERR> {
+        let vconst = self.get_vtoc_constants()?;
+        let mut buf: Vec<u8> = vec![0;256];
+        let fname = string_to_file_name(name);
+        let mut dir_ts = [vconst.track1,vconst.sector1];
+        for _try in 0..types::MAX_DIRECTORY_REPS {
+            Self::verify_ts(&vconst,dir_ts[0], dir_ts[1])?;
+            self.read_sector(&mut buf, dir_ts, 0)?;
+            let dir = DirectorySector::from_bytes(&buf)?;
+            for e in 0..7 {
+                let entry = &dir.entries[e];
+                if fname==entry.name && entry.tsl_track>0 && entry.tsl_track<255 {
+                    return Ok((dir_ts,dir,e));
+                }
+            }
+            dir_ts = [dir.next_track,dir.next_sector];
+            if dir_ts == [0,0] {
+                return Err(Box::new(Error::FileNotFound));
+            }
+        }
+        log::error!("number of directory sectors is not plausible, aborting");
+        Err(Box::new(Error::EndOfData))
+    }
+    /// Get the data sector holding sector `idx` of the file whose track/sector list starts at `tslist_ts`,
+    /// `None` if it is a hole or past the end.  If `grow` is true, holes are filled and the track/sector list
+    /// is extended, and the number of sectors allocated is added to `added`.
+    fn file_sector(&mut self,tslist_ts: [u8;2],idx: usize,grow: bool,added: &mut usize) -> Result<Option<[u8;2]>,DYNERR> {
+        let max_pairs = self.get_vtoc_constants()?.max_pairs as usize;
+        if idx/max_pairs >= types::MAX_TSLIST_REPS {
+            return Err(Box::new(Error::Range));
+        }
+        let mut buf: Vec<u8> = vec![0;256];
+        let mut ts = tslist_ts;
+        self.read_sector(&mut buf,ts,0)?;
+        let mut tslist = TrackSectorList::from_bytes(&buf)?;
+        for count in 0..idx/max_pairs {
+            if tslist.next_track==0 {
+                if !grow {
+                    return Ok(None);
+                }
+                let next_ts = self.get_next_free_sector(false)?;
+                let mut next = TrackSectorList::new();
+                next.sector_base = u16::to_le_bytes(((count+1)*max_pairs) as u16);
+                self.write_sector(&next.to_bytes(),next_ts,0)?;
+                self.update_last_track(next_ts[0])?;
+                tslist.next_track = next_ts[0];
+                tslist.next_sector = next_ts[1];
+                self.write_sector(&tslist.to_bytes(),ts,0)?;
+                *added += 1;
+            }
+            ts = [tslist.next_track,tslist.next_sector];
+            self.read_sector(&mut buf,ts,0)?;
+            tslist = TrackSectorList::from_bytes(&buf)?;
+        }
+        let p = idx % max_pairs;
+        if tslist.pairs[p*2]>0 {
+            return Ok(Some([tslist.pairs[p*2],tslist.pairs[p*2+1]]));
+        }
+        if !grow {
+            return Ok(None);
+        }
+        let data_ts = self.get_next_free_sector(false)?;
+        self.write_sector(&vec![0;256],data_ts,0)?;
+        self.update_last_track(data_ts[0])?;
+        tslist.pairs[p*2] = data_ts[0];
+        tslist.pairs[p*2+1] = data_ts[1];
+        self.write_sector(&tslist.to_bytes(),ts,0)?;
+        *added += 1;
+        Ok(Some(data_ts))
+    }
+    /// Find where the data ends, returning (header length,end of data) in bytes from the start of the file.
+    /// Text ends at the first null, binary and BASIC files end where the header says.
+    fn data_end(&mut self,tslist_ts: [u8;2],ftype: u8) -> Result<(usize,usize),DYNERR> {
+        let mut buf: Vec<u8> = vec![0;256];
+        let mut added = 0;
+        let hdr = match FileType::from_u8(ftype & 0x7f) {
+            Some(FileType::Text) => 0,
+            Some(FileType::Binary) => 4,
+            Some(FileType::Applesoft) | Some(FileType::Integer) => 2,
+            None => {
+                log::error!("file type {} cannot be resized",ftype & 0x7f);
+                return Err(Box::new(Error::FileTypeMismatch));
+            }
+        };
+        let mut idx = 0;
+        while let Some(ts) = self.file_sector(tslist_ts,idx,false,&mut added)? {
+            self.read_sector(&mut buf,ts,0)?;
+            if hdr>0 {
+                return Ok((hdr,hdr + u16::from_le_bytes([buf[hdr-2],buf[hdr-1]]) as usize));
+            }
+            if let Some(i) = buf.iter().position(|b| *b==0) {
+                return Ok((0,idx*256 + i));
+            }
+            idx += 1;
+        }
+        Ok((hdr,usize::max(hdr,idx*256)))
+    }
+    /// Write `dat` to the file starting at byte `offset`, extending it as needed.
+    /// The number of sectors allocated is added to `added`.
+    fn write_file_at(&mut self,tslist_ts: [u8;2],offset: usize,dat: &[u8],added: &mut usize) -> STDRESULT {
+        let mut buf: Vec<u8> = vec![0;256];
+        let mut ptr = offset;
+        while ptr < offset + dat.len() {
+            let beg = ptr % 256;
+            let n = usize::min(256 - beg,offset + dat.len() - ptr);
+            let ts = self.file_sector(tslist_ts,ptr/256,true,added)?.expect("unreachable");
+            self.read_sector(&mut buf,ts,0)?;
+            buf[beg..beg+n].copy_from_slice(&dat[ptr-offset..ptr-offset+n]);
+            self.write_sector(&buf,ts,0)?;
+            ptr += n;
+        }
+        Ok(())
+    }
+    /// Write `dat` into the file at byte `offset` of the data, not counting any header, or at the end if `None`.
+    /// The length is updated if the file grows.  Returns the new length.  If a sector cannot be allocated the disk is restored.
+    fn write_into(&mut self,name: &str,maybe_offset: Option<usize>,dat: &[u8]) -> Result<usize,DYNERR> {
+        let (dir_ts,dir,e,hdr,end) = self.find_resizable_file(name)?;
+        let beg = match maybe_offset {
+            Some(offset) => hdr + offset,
+            None => end
+        };
+        if beg > end {
+            log::error!("offset {} is past the end of the file ({} bytes)",beg-hdr,end-hdr);
+            return Err(Box::new(Error::Range));
+        }
+        let new_end = usize::max(end,beg + dat.len());
+        if hdr>0 && new_end - hdr > u16::MAX as usize {
+            log::error!("file would exceed the maximum length");
+            return Err(Box::new(Error::Range));
+        }
+        let tslist_ts = [dir.entries[e].tsl_track,dir.entries[e].tsl_sector];
+        let snap = super::DiskFS::snapshot(self);
+        let mut added = 0;
+        let result = match self.write_file_at(tslist_ts,beg,dat,&mut added) {
+            Ok(()) => self.finish_resize(dir_ts,dir,e,hdr,new_end,added as isize),
+            Err(e) => Err(e)
+        };
+        if result.is_err() {
+            super::DiskFS::rollback(self,&snap)?;
+        }
+        result
+    }
+    /// Free the sectors beyond `len` bytes, including track/sector list sectors, and zero the rest
+    /// of the last sector that is kept.  Returns the number of sectors freed.
+    fn cut_file(&mut self,tslist_ts: [u8;2],len: usize) -> Result<usize,DYNERR> {
+        let max_pairs = self.get_vtoc_constants()?.max_pairs as usize;
+        let keep = usize::max(1,len.div_ceil(256));
+        let mut buf: Vec<u8> = vec![0;256];
+        let mut freed = 0;
+        let mut base = 0;
+        let mut ts = tslist_ts;
+        for _try in 0..types::MAX_TSLIST_REPS {
+            self.read_sector(&mut buf,ts,0)?;
+            let mut tslist = TrackSectorList::from_bytes(&buf)?;
+            let next = [tslist.next_track,tslist.next_sector];
+            for p in 0..max_pairs {
+                if base+p >= keep && tslist.pairs[p*2]>0 && tslist.pairs[p*2]<255 {
+                    self.deallocate_sector(tslist.pairs[p*2],tslist.pairs[p*2+1])?;
+                    tslist.pairs[p*2] = 0;
+                    tslist.pairs[p*2+1] = 0;
+                    freed += 1;
+                }
+            }
+            if base >= keep {
+                self.deallocate_sector(ts[0],ts[1])?;
+                freed += 1;
+            } else {
+                if base + max_pairs >= keep {
+                    tslist.next_track = 0;
+                    tslist.next_sector = 0;
+                }
+                self.write_sector(&tslist.to_bytes(),ts,0)?;
+            }
+            if next==[0,0] {
+                let mut added = 0;
+                if len/256 < keep {
+                    if let Some(data_ts) = self.file_sector(tslist_ts,len/256,false,&mut added)? {
+                        self.read_sector(&mut buf,data_ts,0)?;
+                        buf[len%256..].fill(0);
+                        self.write_sector(&buf,data_ts,0)?;
+                    }
+                }
+                return Ok(freed);
+            }
+            ts = next;
+            base += max_pairs;
+        }
+        log::error!("number of track-sector list sectors is not plausible, aborting");
+        Err(Box::new(Error::EndOfData))
+    }
+    /// After the data has been changed, update the length in the header, if any, and add `delta` to the sector count in the entry.
+    /// Returns the new length, not counting the header.
+    fn finish_resize(&mut self,dir_ts: [u8;2],mut dir: DirectorySector,e: usize,hdr: usize,end: usize,delta: isize) -> Result<usize,DYNERR> {
+        let tslist_ts = [dir.entries[e].tsl_track,dir.entries[e].tsl_sector];
+        let mut more = 0;
+        if hdr>0 {
+            let len_bytes = u16::to_le_bytes((end-hdr) as u16);
+            self.write_file_at(tslist_ts,hdr-2,&len_bytes,&mut more)?;
+        }
+        let sectors = u16::from_le_bytes(dir.entries[e].sectors) as isize + delta + more as isize;
+        dir.entries[e].sectors = u16::to_le_bytes(sectors.max(0) as u16);
+        self.write_sector(&dir.to_bytes(),dir_ts,0)?;
+        Ok(end-hdr)
+    }
+    /// Find a file that can be resized, returning (directory sector ptr,directory sector,entry index,header length,end of data)
+    fn find_resizable_file(&mut self,name: &str) -> Result<([u8;2],DirectorySector,usize,usize,usize),DYNERR> {
+        let (dir_ts,dir,e) = self.find_entry(name)?;
+        if dir.entries[e].file_type > 127 {
+            log::error!("{} is locked",name);
+            return Err(Box::new(Error::WriteProtected));
+        }
+        let tslist_ts = [dir.entries[e].tsl_track,dir.entries[e].tsl_sector];
+        let (hdr,end) = self.data_end(tslist_ts,dir.entries[e].file_type)?;
+        Ok((dir_ts,dir,e,hdr,end))
+    }
     /// Verify that the new name does not already exist
     fn ok_to_rename(&mut self,new_name: &str) -> STDRESULT {
         if !is_name_valid(&new_name) {
@@ -662,36 +1024,14 @@ impl Disk
         log::error!("number of directory sectors is not plausible, aborting");
         Err(Box::new(Error::EndOfData))
     }
-}
-
-impl super::DiskFS for Disk {
-    fn new_fimg(&self, chunk_len: Option<usize>,_set_time: bool,path: &str) -> Result<super::FileImage,DYNERR> {
-        match chunk_len {
-            Some(l) => new_fimg(l,path),
-            None => new_fimg(256,path)
-        }
-    }
-    fn stat(&mut self) -> Result<super::Stat,DYNERR> {
-        let vtoc = &self.get_vtoc_constants()?;
-        Ok(super::Stat {
-            fs_name: FS_NAME.to_string(),
-            label: vtoc.vol.to_string(),
-            users: Vec::new(),
-            block_size: 256,
-            block_beg: 0,
-            block_end: vtoc.sectors as usize * vtoc.tracks as usize,
-            free_blocks: self.num_free_sectors()?,
-            raw: "".to_string()
-        })
-    }
-    fn catalog_to_stdout(&mut self, _path: &str) -> STDRESULT {
+    /// Native catalog in parts, see `DiskFS::catalog_native`
+    fn native_catalog(&mut self, path: &str) -> Result<super::NativeCatalog,DYNERR> {
         let vconst = self.get_vtoc_constants()?;
         let typ_map: HashMap<u8,&str> = HashMap::from([(0," T"),(1," I"),(2," A"),(4," B"),(128,"*T"),(129,"*I"),(130,"*A"),(132,"*B")]);
         let mut ts = [vconst.track1,vconst.sector1];
         let mut buf = vec![0;256];
-        println!();
-        println!("DISK VOLUME {}",vconst.vol);
-        println!();
+        let header = format!("\nDISK VOLUME {}\n\n",vconst.vol);
+        let mut rows = Vec::new();
         for _try in 0..types::MAX_DIRECTORY_REPS {
             Self::verify_ts(&vconst,ts[0], ts[1])?;
             self.read_sector(&mut buf, ts, 0)?;
@@ -701,22 +1041,53 @@ impl super::DiskFS for Disk {
                     let name = file_name_to_string(entry.name);
                     let sectors = u16::from_le_bytes(entry.sectors);
                     if let Some(typ) = typ_map.get(&entry.file_type) {
-                        println!("{} {:03} {}",typ,sectors,name);
+                        rows.push(format!("{} {:03} {}",typ,sectors,name));
                     } else {
-                        println!("?? {:03} {}",sectors,name);
+                        rows.push(format!("?? {:03} {}",sectors,name));
                     }
                 }
             }
             ts = [dir.next_track,dir.next_sector];
             if ts == [0,0] {
-                println!();
-                return Ok(());
+                let items = super::DiskFS::catalog_items(self,path)?;
+                return super::NativeCatalog::pair(header,items,rows,"\n".to_string());
             }
         }
         log::error!("the disk image directory seems to be damaged");
         return Err(Box::new(Error::IOError));
     }
+}
+
+impl super::DiskFS for Disk {
+    fn new_fimg(&self, chunk_len: Option<usize>,_set_time: bool,path: &str) -> Result<super::FileImage,DYNERR> {
+        match chunk_len {
+            Some(l) => new_fimg(l,path),
+            None => new_fimg(256,path)
+        }
+    }
+    fn stat(&mut self) -> Result<super::Stat,DYNERR> {
+        let vtoc = &self.get_vtoc_constants()?;
+        Ok(super::Stat {
+            fs_name: FS_NAME.to_string(),
+            label: vtoc.vol.to_string(),
+            users: Vec::new(),
+            block_size: 256,
+            block_beg: 0,
+            block_end: vtoc.sectors as usize * vtoc.tracks as usize,
+            free_blocks: self.num_free_sectors()?,
+            raw: json::stringify(json::object! { "variant": self.variant()?.to_string() })
+        })
+    }
+    fn catalog_to_string(&mut self, path: &str) -> Result<String,DYNERR> {
+        Ok(self.native_catalog(path)?.render(&super::CatalogFilter::default(),super::CatalogOrder::Directory))
+    }
+    fn catalog_native(&mut self, path: &str) -> Result<Option<super::NativeCatalog>,DYNERR> {
+        Ok(Some(self.native_catalog(path)?))
+    }
     fn catalog_to_vec(&mut self, path: &str) -> Result<Vec<String>,DYNERR> {
+        Ok(self.catalog_items(path)?.into_iter().map(|x| x.row).collect())
+    }
+    fn catalog_items(&mut self, path: &str) -> Result<Vec<super::CatalogItem>,DYNERR> {
         if path!="/" && path!="" {
             return Err(Box::new(Error::VolumeMismatch));
         }
@@ -738,7 +1109,14 @@ impl super::DiskFS for Disk {
                         Some(s) => s,
                         None => type_as_hex.as_str()
                     };
-                    ans.push(super::universal_row(typ,sectors as usize,&name));
+                    ans.push(super::CatalogItem {
+                        row: super::universal_row(typ,sectors as usize,&name),
+                        name,
+                        typ: typ.to_string(),
+                        blocks: sectors as usize,
+                        modified: None,
+                        addr: entry.tsl_track as usize * vconst.sectors as usize + entry.tsl_sector as usize
+                    });
                 }
             }
             ts = [dir.next_track,dir.next_sector];
@@ -749,12 +1127,9 @@ impl super::DiskFS for Disk {
         log::error!("the disk image directory seems to be damaged");
         return Err(Box::new(Error::IOError));
     }
-    fn glob(&mut self,pattern: &str,case_sensitive: bool) -> Result<Vec<String>,DYNERR> {
+    fn glob_with(&mut self,pattern: &str,opt: super::GlobOptions) -> Result<Vec<String>,DYNERR> {
         let mut ans = Vec::new();
-        let glob = match case_sensitive {
-            true => globset::Glob::new(pattern)?.compile_matcher(),
-            false => globset::Glob::new(&pattern.to_uppercase())?.compile_matcher()
-        };
+        let glob = super::NameMatcher::new(pattern,opt)?;
         let vconst = self.get_vtoc_constants()?;
         let mut ts = [vconst.track1,vconst.sector1];
         let mut buf = vec![0;256];
@@ -764,10 +1139,7 @@ impl super::DiskFS for Disk {
             let dir = DirectorySector::from_bytes(&buf)?;
             for entry in dir.entries.as_ref() {
                 if entry.tsl_track>0 && entry.tsl_track<255 {
-                    let name = match case_sensitive {
-                        true => file_name_to_string(entry.name),
-                        false => file_name_to_string(entry.name).to_uppercase()
-                    };
+                    let name = file_name_to_string(entry.name);
                     if glob.is_match(&name) {
                         ans.push(name);
                     }
@@ -901,6 +1273,9 @@ impl super::DiskFS for Disk {
     fn retype(&mut self,name: &str,new_type: &str,_sub_type: &str) -> STDRESULT {
         return self.modify(name, None,None, Some(new_type));
     }
+    fn exists(&mut self,name: &str) -> Result<bool,DYNERR> {
+        Ok(self.get_tslist_sector(name)?.is_some())
+    }
     fn get(&mut self,name: &str) -> Result<super::FileImage,DYNERR> {
         return self.read_file(name);
     }
@@ -976,6 +1351,240 @@ impl super::DiskFS for Disk {
             }
         }
     }
+    fn all_blocks(&mut self) -> Result<Vec<Block>,DYNERR> {
+        let vconst = self.get_vtoc_constants()?;
+        let mut ans = Vec::new();
+        for track in 0..vconst.tracks {
+            for sector in 0..vconst.sectors {
+                ans.push(self.addr([track,sector]));
+            }
+        }
+        Ok(ans)
+    }
+    fn stat_deep(&mut self) -> Result<json::JsonValue,DYNERR> {
+        let vtoc = self.get_vtoc_ref()?;
+        let mut ans = json::JsonValue::new_object();
+        ans["vtoc"] = json::object! {
+            "catalog_track": vtoc.track1,
+            "catalog_sector": vtoc.sector1,
+            "version": vtoc.version,
+            "volume": vtoc.vol,
+            "max_pairs": vtoc.max_pairs,
+            "last_track": vtoc.last_track,
+            "last_direction": vtoc.last_direction as i8,
+            "tracks": vtoc.tracks,
+            "sectors": vtoc.sectors,
+            "bytes_per_sector": u16::from_le_bytes(vtoc.bytes)
+        };
+        let total = vtoc.tracks as usize * vtoc.sectors as usize;
+        ans["derived"] = json::object! {
+            "variant": self.variant()?.to_string(),
+            "total_sectors": total,
+            "free_sectors": self.num_free_sectors()?,
+            "image": super::image_json(self.img.as_ref())
+        };
+        Ok(ans)
+    }
+    fn inspect(&mut self,name: &str,indent: Option<u16>) -> Result<String,DYNERR> {
+        let vconst = self.get_vtoc_constants()?;
+        let (mut next_tslist,ftype) = match self.get_tslist_sector(name)? {
+            Some((ts,typ)) => (ts,typ),
+            None => return Err(Box::new(Error::FileNotFound))
+        };
+        let mut lists = json::JsonValue::new_array();
+        let mut buf = vec![0;256];
+        for _try in 0..types::MAX_TSLIST_REPS {
+            Self::verify_ts(&vconst,next_tslist[0],next_tslist[1])?;
+            self.read_sector(&mut buf,next_tslist,0)?;
+            let tslist = TrackSectorList::from_bytes(&buf)?;
+            let mut pairs: Vec<[u8;2]> = (0..vconst.max_pairs as usize).map(|p| [tslist.pairs[p*2],tslist.pairs[p*2+1]]).collect();
+            while pairs.last().is_some_and(|ts| ts[0]==0) {
+                pairs.pop();
+            }
+            let mut node = json::object! { "track": next_tslist[0], "sector": next_tslist[1] };
+            node["data"] = json::JsonValue::new_array();
+            for ts in pairs {
+                node["data"].push(json::array![ts[0],ts[1]])?;
+            }
+            lists.push(node)?;
+            if tslist.next_track==0 {
+                let mut ans = json::JsonValue::new_object();
+                ans["file_system"] = json::JsonValue::String(FS_NAME.to_string());
+                ans["path"] = json::JsonValue::String(name.to_string());
+                ans["type"] = json::JsonValue::String(hex::encode_upper([ftype]));
+                ans["ts_lists"] = lists;
+                return match indent {
+                    Some(spaces) => Ok(json::stringify_pretty(ans,spaces)),
+                    None => Ok(json::stringify(ans))
+                };
+            }
+            next_tslist = [tslist.next_track,tslist.next_sector];
+        }
+        log::error!("number of track-sector list sectors is not plausible, aborting");
+        Err(Box::new(Error::EndOfData))
+    }
+    fn relabel(&mut self,label: &str) -> STDRESULT {
+        // Only the VTOC changes, address fields of track images keep the volume they were formatted with.
+        match u8::from_str(label) {
+            Ok(vol) if vol>0 && vol<255 => {
+                self.get_vtoc_mut()?.vol = vol;
+                Ok(())
+            },
+            _ => {
+                log::error!("DOS volume number should be from 1 to 254");
+                Err(Box::new(Error::Range))
+            }
+        }
+    }
+    fn wipe_free(&mut self,pattern: &[u8]) -> Result<usize,DYNERR> {
+        let vconst = self.get_vtoc_constants()?;
+        let bytes_per_sector = u16::from_le_bytes(vconst.bytes);
+        let dat: Vec<u8> = pattern.iter().cycle().take(bytes_per_sector as usize).copied().collect();
+        let mut count = 0;
+        for track in 0..vconst.tracks {
+            for sector in 0..vconst.sectors {
+                if Self::is_sector_free(self.get_vtoc_ref()?,track,sector) {
+                    self.zap_sector(&dat,[track,sector],0,bytes_per_sector)?;
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+    fn banner(&mut self,msg: &[u8]) -> Result<Vec<Block>,DYNERR> {
+        // only the catalog track is used, taking sectors in the order the catalog would
+        let vconst = self.get_vtoc_constants()?;
+        let bytes_per_sector = u16::from_le_bytes(vconst.bytes);
+        let needed = msg.len().div_ceil(bytes_per_sector as usize);
+        let mut free = Vec::new();
+        for sector in (0..vconst.sectors).rev() {
+            if Self::is_sector_free(self.get_vtoc_ref()?,vconst.track1,sector) {
+                free.push([vconst.track1,sector]);
+            }
+        }
+        if free.len() < needed {
+            log::error!("banner needs {} sectors, but the catalog track has {} free",needed,free.len());
+            return Err(Box::new(Error::DiskFull));
+        }
+        let mut padded = msg.to_vec();
+        padded.resize(needed*bytes_per_sector as usize,0);
+        let mut ans = Vec::new();
+        for (i,ts) in free.into_iter().take(needed).enumerate() {
+            self.zap_sector(&padded,ts,i*bytes_per_sector as usize,bytes_per_sector)?;
+            ans.push(self.addr(ts));
+        }
+        Ok(ans)
+    }
+    fn reclaim_dos(&mut self) -> Result<usize,DYNERR> {
+        // DOS occupies tracks 0 through 2, but track 0 cannot be given to files,
+        // since a track/sector pair on track 0 means there is no sector.
+        for (name,ts) in self.file_sectors()? {
+            if ts[0]==1 || ts[0]==2 {
+                log::error!("track {} sector {} is used by {}",ts[0],ts[1],name);
+                return Err(Box::new(crate::commands::CommandError::InvalidCommand));
+            }
+        }
+        let vconst = self.get_vtoc_constants()?;
+        let mut count = 0;
+        for track in 1..3 {
+            for sector in 0..vconst.sectors {
+                if !Self::is_sector_free(self.get_vtoc_ref()?,track,sector) {
+                    self.deallocate_sector(track,sector)?;
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+    fn check_bitmap(&mut self) -> Result<Vec<(Block,bool)>,DYNERR> {
+        let used = self.sectors_in_use()?;
+        let vtoc = self.get_vtoc_ref()?;
+        let mut diffs = Vec::new();
+        for (track,secs) in used.iter().enumerate() {
+            for (sector,maybe_used) in secs.iter().enumerate() {
+                if let Some(is_used) = maybe_used {
+                    if Self::is_sector_free(vtoc,track as u8,sector as u8) == *is_used {
+                        diffs.push(([track as u8,sector as u8],*is_used));
+                    }
+                }
+            }
+        }
+        Ok(diffs.into_iter().map(|(ts,is_used)| (self.addr(ts),is_used)).collect())
+    }
+    fn repair_bitmap(&mut self) -> Result<usize,DYNERR> {
+        let used = self.sectors_in_use()?;
+        let mut changed = 0;
+        for (track,secs) in used.into_iter().enumerate() {
+            for (sector,maybe_used) in secs.into_iter().enumerate() {
+                let ts = [track as u8,sector as u8];
+                match maybe_used {
+                    Some(true) if Self::is_sector_free(self.get_vtoc_ref()?,ts[0],ts[1]) => self.allocate_sector(ts[0],ts[1])?,
+                    Some(false) if !Self::is_sector_free(self.get_vtoc_ref()?,ts[0],ts[1]) => self.deallocate_sector(ts[0],ts[1])?,
+                    _ => continue
+                }
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+    fn wipe_deleted(&mut self) -> Result<usize,DYNERR> {
+        let vconst = self.get_vtoc_constants()?;
+        let mut buf: Vec<u8> = vec![0;256];
+        let mut dir_ts = [vconst.track1,vconst.sector1];
+        let mut count = 0;
+        for _try in 0..types::MAX_DIRECTORY_REPS {
+            Self::verify_ts(&vconst,dir_ts[0], dir_ts[1])?;
+            self.read_sector(&mut buf, dir_ts, 0)?;
+            let mut dir = DirectorySector::from_bytes(&buf)?;
+            let mut changed = false;
+            for entry in dir.entries.as_mut() {
+                // keep the deleted marker, since a never used entry ends the catalog
+                if entry.tsl_track==255 && (entry.tsl_sector>0 || entry.file_type>0 || entry.name!=[0;30] || entry.sectors!=[0;2]) {
+                    *entry = DirectoryEntry::new();
+                    entry.tsl_track = 255;
+                    changed = true;
+                    count += 1;
+                }
+            }
+            if changed {
+                self.write_sector(&dir.to_bytes(),dir_ts,0)?;
+            }
+            dir_ts = [dir.next_track,dir.next_sector];
+            if dir_ts == [0,0] {
+                return Ok(count);
+            }
+        }
+        log::error!("number of directory sectors is not plausible, aborting");
+        Err(Box::new(Error::EndOfData))
+    }
+    fn append(&mut self,name: &str,dat: &[u8]) -> Result<usize,DYNERR> {
+        self.write_into(name,None,dat)
+    }
+    fn patch(&mut self,name: &str,offset: usize,dat: &[u8]) -> Result<usize,DYNERR> {
+        self.write_into(name,Some(offset),dat)
+    }
+    fn truncate(&mut self,name: &str,len: usize) -> Result<usize,DYNERR> {
+        let (dir_ts,dir,e,hdr,end) = self.find_resizable_file(name)?;
+        if hdr + len > end {
+            log::error!("cannot truncate {} bytes to {}",end-hdr,len);
+            return Err(Box::new(Error::Range));
+        }
+        let tslist_ts = [dir.entries[e].tsl_track,dir.entries[e].tsl_sector];
+        let snap = super::DiskFS::snapshot(self);
+        let result = match self.cut_file(tslist_ts,hdr + len) {
+            Ok(freed) => self.finish_resize(dir_ts,dir,e,hdr,hdr + len,-(freed as isize)),
+            Err(e) => Err(e)
+        };
+        if result.is_err() {
+            super::DiskFS::rollback(self,&snap)?;
+        }
+        result
+    }
+    fn rollback(&mut self,snap: &img::Snapshot) -> STDRESULT {
+        self.img.rollback(snap)?;
+        self.maybe_vtoc = None;
+        Ok(())
+    }
     fn get_img(&mut self) -> &mut Box<dyn img::DiskImage> {
         self.writeback_vtoc_buffer().expect("could not write back VTOC buffer");
         &mut self.img
@@ -7,6 +7,10 @@ use crate::fs::TextConversion;
 
 pub const VTOC_TRACK: u8 = 17;
 pub const MAX_DIRECTORY_REPS: usize = 100;
+/// most tracks the VTOC bitmap can hold at 4 bytes per track
+pub const MAX_TRACKS_STD: u8 = 50;
+/// most tracks allowed when the VTOC bitmap is packed at 2 bytes per track
+pub const MAX_TRACKS_PACKED: u8 = 80;
 pub const MAX_TSLIST_REPS: usize = 1000;
 
 /// Enumerates DOS errors.  The `Display` trait will print equivalent DOS message such as `FILE NOT FOUND`.  Following DOS errors are omitted:
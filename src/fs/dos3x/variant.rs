@@ -0,0 +1,80 @@
+//! ### DOS variants
+//!
+//! Many patched versions of DOS 3.3 were distributed, most of them to speed up disk access.
+//! The VTOC, catalog, and track/sector lists are the same as in standard DOS, but some
+//! variants start the catalog elsewhere, e.g., to give more of the catalog track to files.
+//! The catalog is always found by following the VTOC, so these disks need no special
+//! handling beyond recognizing them.
+//!
+//! The variant is identified by searching tracks 0 through 2 for the name the variant
+//! displays when it boots.  If there is no name, the tracks are compared with standard DOS 3.3.
+//! A 13 sector disk with DOS tracks is taken to be standard DOS 3.2.
+
+use super::boot;
+
+/// Number of bytes that may differ from the standard DOS image.  INIT patches a few, e.g., the greeting
+/// program name, and the releases of DOS 3.3 differ from one another by a few hundred.
+const MAX_PATCHED_BYTES: usize = 1024;
+
+/// Names as they appear in the DOS image, matched in either negative or positive ASCII.
+const SIGNATURES: [(Variant,&str);3] = [
+    (Variant::ProntoDos,"PRONTO-DOS"),
+    (Variant::DavidDos,"DAVID-DOS"),
+    (Variant::DiversiDos,"DIVERSI-DOS")
+];
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Variant {
+    /// DOS tracks are blank, the disk will not boot
+    None,
+    Standard,
+    ProntoDos,
+    DavidDos,
+    DiversiDos,
+    /// DOS tracks hold something that is not recognized
+    Unknown
+}
+
+impl std::fmt::Display for Variant {
+    fn fmt(&self,f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f,"none"),
+            Self::Standard => write!(f,"standard"),
+            Self::ProntoDos => write!(f,"ProntoDOS"),
+            Self::DavidDos => write!(f,"DavidDOS"),
+            Self::DiversiDos => write!(f,"DiversiDOS"),
+            Self::Unknown => write!(f,"unknown")
+        }
+    }
+}
+
+fn contains_name(dat: &[u8],name: &str) -> bool {
+    let neg: Vec<u8> = name.bytes().map(|b| b | 0x80).collect();
+    dat.windows(neg.len()).any(|w| w==neg.as_slice() || w==name.as_bytes())
+}
+
+/// Identify the DOS variant from tracks 0 through 2, given in DOS order, `sectors` is the number of sectors per track.
+pub fn detect(dos_tracks: &[u8],sectors: u8) -> Variant {
+    if dos_tracks.iter().all(|b| *b==0) {
+        return Variant::None;
+    }
+    for (variant,name) in SIGNATURES {
+        if contains_name(dos_tracks,name) {
+            return variant;
+        }
+    }
+    // the patched versions are all based on DOS 3.3
+    if sectors == 13 {
+        return Variant::Standard;
+    }
+    let reference: &[u8] = &boot::DOS33_TRACKS;
+    if reference.len() != dos_tracks.len() {
+        return Variant::Unknown;
+    }
+    let diffs = reference.iter().zip(dos_tracks).filter(|(a,b)| a!=b).count();
+    log::debug!("DOS tracks differ from standard in {} bytes",diffs);
+    match diffs {
+        d if d <= MAX_PATCHED_BYTES => Variant::Standard,
+        _ => Variant::Unknown
+    }
+}
@@ -0,0 +1,93 @@
+//! ### FAT Code Page Module
+//!
+//! Short names and labels are stored in the OEM code page of the machine that wrote them.
+//! The lower half is ASCII, the upper half depends on the code page, e.g., CP437 for
+//! US machines, or CP850 for much of western Europe.  Nothing on the disk tells us which one
+//! was used, so the code page is given to the FAT `Disk` when it is created, see `Disk::with_codepage`.
+
+use super::types::Error;
+use crate::DYNERR;
+
+/// Code pages that can be used to decode and encode names
+pub const SUPPORTED: [u16;2] = [437,850];
+pub const DEFAULT_CODEPAGE: u16 = 437;
+
+/// A name that starts with 0xe5 is stored starting with this, since 0xe5 marks a free entry
+pub const E5_STANDIN: u8 = 0x05;
+
+const CP437: [char;128] = [
+    'Ç','ü','é','â','ä','à','å','ç','ê','ë','è','ï','î','ì','Ä','Å',
+    'É','æ','Æ','ô','ö','ò','û','ù','ÿ','Ö','Ü','¢','£','¥','₧','ƒ',
+    'á','í','ó','ú','ñ','Ñ','ª','º','¿','⌐','¬','½','¼','¡','«','»',
+    '░','▒','▓','│','┤','╡','╢','╖','╕','╣','║','╗','╝','╜','╛','┐',
+    '└','┴','┬','├','─','┼','╞','╟','╚','╔','╩','╦','╠','═','╬','╧',
+    '╨','╤','╥','╙','╘','╒','╓','╫','╪','┘','┌','█','▄','▌','▐','▀',
+    'α','ß','Γ','π','Σ','σ','µ','τ','Φ','Θ','Ω','δ','∞','φ','ε','∩',
+    '≡','±','≥','≤','⌠','⌡','÷','≈','°','∙','·','√','ⁿ','²','■','\u{a0}'
+];
+
+const CP850: [char;128] = [
+    'Ç','ü','é','â','ä','à','å','ç','ê','ë','è','ï','î','ì','Ä','Å',
+    'É','æ','Æ','ô','ö','ò','û','ù','ÿ','Ö','Ü','ø','£','Ø','×','ƒ',
+    'á','í','ó','ú','ñ','Ñ','ª','º','¿','®','¬','½','¼','¡','«','»',
+    '░','▒','▓','│','┤','Á','Â','À','©','╣','║','╗','╝','¢','¥','┐',
+    '└','┴','┬','├','─','┼','ã','Ã','╚','╔','╩','╦','╠','═','╬','¤',
+    'ð','Ð','Ê','Ë','È','ı','Í','Î','Ï','┘','┌','█','▄','¦','Ì','▀',
+    'Ó','ß','Ô','Ò','õ','Õ','µ','þ','Þ','Ú','Û','Ù','ý','Ý','¯','´',
+    '\u{ad}','±','‗','¾','¶','§','÷','¸','°','¨','·','¹','³','²','■','\u{a0}'
+];
+
+/// A code page used to decode and encode names
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct CodePage {
+    id: u16,
+    upper_half: &'static [char;128]
+}
+
+impl Default for CodePage {
+    fn default() -> Self {
+        Self { id: DEFAULT_CODEPAGE, upper_half: &CP437 }
+    }
+}
+
+impl CodePage {
+    /// Code page with the given number, error if it is not supported
+    pub fn new(id: u16) -> Result<Self,DYNERR> {
+        match id {
+            437 => Ok(Self { id, upper_half: &CP437 }),
+            850 => Ok(Self { id, upper_half: &CP850 }),
+            _ => {
+                log::error!("code page {} is not supported, try one of {:?}",id,SUPPORTED);
+                Err(Box::new(Error::InvalidSwitch))
+            }
+        }
+    }
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+    /// Decode name bytes, the lower half passes through.
+    pub fn decode(&self,bytes: &[u8]) -> String {
+        bytes.iter().map(|b| match *b {
+            b if b < 0x80 => b as char,
+            b => self.upper_half[b as usize - 0x80]
+        }).collect()
+    }
+    /// Encode one character, `None` if it is not in the code page.
+    pub fn encode_char(&self,c: char) -> Option<u8> {
+        if c.is_ascii() {
+            return Some(c as u8);
+        }
+        self.upper_half.iter().position(|x| *x==c).map(|i| i as u8 + 0x80)
+    }
+    /// Raise the case of each character that has an upper case form in the code page.
+    /// Characters whose upper case form is missing from the code page are kept as is.
+    pub fn to_upper(&self,s: &str) -> String {
+        s.chars().map(|c| {
+            let mut up = c.to_uppercase();
+            match (up.next(),up.next()) {
+                (Some(u),None) if self.encode_char(u).is_some() => u,
+                _ => c
+            }
+        }).collect()
+    }
+}
@@ -2,11 +2,16 @@
 //! 
 //! This module encapsulates the FAT directory.  The FAT itself is implemented in
 //! `crate::bios::fat`.  The BPB is in `crate::bios::bpb`.
+//!
+//! This module is library-safe, see `crate::fs`.
+
+#![deny(clippy::panic,clippy::unwrap_used,clippy::expect_used)]
 
 use std::collections::BTreeMap;
 use chrono::{NaiveDate,NaiveTime};
-use log::{debug,warn,trace};
+use log::{debug,warn,trace,error};
 use super::types::*;
+use super::codepage::CodePage;
 use crate::fs::FileImage;
 use crate::{STDRESULT,DYNERR};
 
@@ -167,15 +172,15 @@ impl FileInfo {
 impl Entry {
     /// Create an entry with given name and timestamp (time==None means use current time).
     /// Not to be used to create a label entry.
-    pub fn create(name: &str, time: Option<chrono::NaiveDateTime>) -> Self {
+    pub fn create(name: &str, time: Option<chrono::NaiveDateTime>, cp: CodePage) -> Self {
         let now = match time {
             Some(t) => t,
-            None => chrono::Local::now().naive_local()
+            None => crate::now(false)
         };
         let tenths = super::pack::pack_tenths(Some(now));
         let time = super::pack::pack_time(Some(now));
         let date = super::pack::pack_date(Some(now));
-        let (base,ext) = super::pack::string_to_file_name(name);
+        let (base,ext) = super::pack::string_to_file_name(name,cp);
         Self {
             name: base,
             ext,
@@ -193,15 +198,15 @@ impl Entry {
         }
     }
     /// Create a label entry with given name and timestamp (time==None means use current time).
-    pub fn create_label(name: &str, time: Option<chrono::NaiveDateTime>) -> Self {
+    pub fn create_label(name: &str, time: Option<chrono::NaiveDateTime>, cp: CodePage) -> Self {
         let now = match time {
             Some(t) => t,
-            None => chrono::Local::now().naive_local()
+            None => crate::now(false)
         };
         let tenths = super::pack::pack_tenths(Some(now));
         let time = super::pack::pack_time(Some(now));
         let date = super::pack::pack_date(Some(now));
-        let (base,ext) = super::pack::string_to_label_name(name);
+        let (base,ext) = super::pack::string_to_label_entry(name,cp);
         Self {
             name: base,
             ext,
@@ -232,35 +237,64 @@ impl Entry {
     /// Create a subdirectory at `new_cluster`, in directory at `parent_cluster` (0 if root even for FAT32).
     /// Return the (parent entry, directory buffer), where the buffer includes the dot and dotdot entries.
     /// The clusters are expected to be written by the caller.
-    pub fn create_subdir(name: &str,parent_cluster: usize,new_cluster: usize,block_size: usize,time: Option<chrono::NaiveDateTime>) -> (Self,Vec<u8>) {
-        let mut dot = Entry::create(".",time);
-        let mut dotdot = Entry::create("..",time);
+    pub fn create_subdir(name: &str,parent_cluster: usize,new_cluster: usize,block_size: usize,time: Option<chrono::NaiveDateTime>,cp: CodePage) -> Result<(Self,Vec<u8>),DYNERR> {
+        let mut dot = Entry::create(".",time,cp);
+        let mut dotdot = Entry::create("..",time,cp);
         dot.attr = DIRECTORY;
         dot.set_cluster(new_cluster);
         dotdot.attr = DIRECTORY;
         dotdot.set_cluster(parent_cluster);
         let mut dir = Directory::new();
         dir.expand(block_size/DIR_ENTRY_SIZE);
-        dir.set_entry(&Ptr::Entry(0),&dot);
-        dir.set_entry(&Ptr::Entry(1),&dotdot);
-        dot.rename(name);
-        (dot,dir.to_bytes())
+        dir.set_entry(&Ptr::Entry(0),&dot)?;
+        dir.set_entry(&Ptr::Entry(1),&dotdot)?;
+        dot.rename(name,cp);
+        Ok((dot,dir.to_bytes()))
     }
-    pub fn name(&self,label: bool) -> String {
-        let prim = super::pack::file_name_to_string(self.name, self.ext);
+    pub fn name(&self,label: bool,cp: CodePage) -> String {
+        let prim = super::pack::file_name_to_string(self.name, self.ext, cp);
         match label {
             true => prim.replace(".",""),
             false => prim
         }
     }
-    pub fn rename(&mut self,new_name: &str) {
-        let (name,ext) = super::pack::string_to_file_name(new_name);
+    pub fn rename(&mut self,new_name: &str,cp: CodePage) {
+        let (name,ext) = super::pack::string_to_file_name(new_name,cp);
+        self.name = name;
+        self.ext = ext;
+    }
+    /// Change the name of a label entry
+    pub fn relabel(&mut self,new_label: &str,cp: CodePage) {
+        let (name,ext) = super::pack::string_to_label_entry(new_label,cp);
         self.name = name;
         self.ext = ext;
     }
     pub fn eof(&self) -> usize {
         u32::from_le_bytes(self.file_size) as usize
     }
+    pub fn set_eof(&mut self,bytes: usize) {
+        self.file_size = u32::to_le_bytes(bytes as u32);
+    }
+    /// set the creation time, `None` means the current time
+    pub fn set_create_time(&mut self,time: Option<chrono::NaiveDateTime>) {
+        let now = match time {
+            Some(t) => t,
+            None => crate::now(false)
+        };
+        self.creation_tenth = super::pack::pack_tenths(Some(now));
+        self.creation_time = super::pack::pack_time(Some(now));
+        self.creation_date = super::pack::pack_date(Some(now));
+    }
+    /// set the write time and access date, `None` means the current time
+    pub fn set_write_time(&mut self,time: Option<chrono::NaiveDateTime>) {
+        let now = match time {
+            Some(t) => t,
+            None => crate::now(false)
+        };
+        self.write_time = super::pack::pack_time(Some(now));
+        self.write_date = super::pack::pack_date(Some(now));
+        self.access_date = self.write_date;
+    }
     /// access date is lost with this version of file image
     pub fn metadata_to_fimg(&self,fimg: &mut FileImage) {
         fimg.set_eof(self.eof());
@@ -321,9 +355,17 @@ impl Entry {
         let ans = vec![13,14,15,16,17,18,19,22,23,24,25];
         ans.iter().map(|x| x + offset).collect()
     }
-    // pub fn cluster1(&self) -> usize {
-    //     u32::from_le_bytes([self.cluster1_low[0],self.cluster1_low[1],self.cluster1_high[0],self.cluster1_high[1]]) as usize
-    // }
+    /// high bits are only used for FAT32
+    pub fn cluster1(&self,fat_typ: usize) -> usize {
+        match fat_typ {
+            32 => u32::from_le_bytes([self.cluster1_low[0],self.cluster1_low[1],self.cluster1_high[0],self.cluster1_high[1]]) as usize,
+            _ => u16::from_le_bytes(self.cluster1_low) as usize
+        }
+    }
+    pub fn write_time(&self) -> Option<chrono::NaiveDateTime> {
+        let date = super::pack::unpack_date(self.write_date)?;
+        Some(date.and_time(super::pack::unpack_time(self.write_time,0).unwrap_or_default()))
+    }
 }
 
 impl DiskStruct for Directory {
@@ -375,54 +417,58 @@ impl Directory {
             self.entries.push([0;32]);
         }
     }
-    pub fn get_type(&self,ptr: &Ptr) -> EntryType {
-        let (idx,nm0,attr) = match ptr {
-            Ptr::Entry(i) => (*i,self.entries[*i][0],self.entries[*i][11]),
-            _ => panic!("wrong pointer type")
-        };
+    /// Index of the entry, error if the pointer is not an entry in this directory
+    fn entry_idx(&self,ptr: &Ptr) -> Result<usize,DYNERR> {
+        match ptr {
+            Ptr::Entry(idx) if *idx < self.entries.len() => Ok(*idx),
+            Ptr::Entry(idx) => {
+                error!("entry {} is beyond the end of the directory",idx);
+                Err(Box::new(Error::General))
+            },
+            _ => {
+                error!("wrong pointer type");
+                Err(Box::new(Error::General))
+            }
+        }
+    }
+    pub fn get_type(&self,ptr: &Ptr) -> Result<EntryType,DYNERR> {
+        let idx = self.entry_idx(ptr)?;
+        let (nm0,attr) = (self.entries[idx][0],self.entries[idx][11]);
         trace!("entry {} has name[0] {} and attr {}",idx,nm0,attr);
-        match (nm0,attr) {
+        Ok(match (nm0,attr) {
             (0xe5,_) => EntryType::Free,
             (0x00,_) => EntryType::FreeAndNoMore,
             (_,a) if a & LONG_NAME >= LONG_NAME => EntryType::LongName,
             (_,a) if a & VOLUME_ID > 0 => EntryType::VolumeLabel,
             (_,a) if a & DIRECTORY > 0 => EntryType::Directory,
             _ => EntryType::File
-        }
+        })
     }
-    pub fn get_raw_entry(&self,ptr: &Ptr) -> [u8;DIR_ENTRY_SIZE] {
-        match ptr {
-            Ptr::Entry(i) => self.entries[*i].clone(),
-            _ => panic!("wrong pointer type")
-        }
+    pub fn get_raw_entry(&self,ptr: &Ptr) -> Result<[u8;DIR_ENTRY_SIZE],DYNERR> {
+        Ok(self.entries[self.entry_idx(ptr)?])
     }
-    pub fn get_entry(&self,ptr: &Ptr) -> Entry {
-        match ptr {
-            Ptr::Entry(idx) => Entry::from_bytes(&self.entries[*idx]).expect("unexpected size"),
-            _ => panic!("wrong pointer type")
-        }
+    pub fn get_entry(&self,ptr: &Ptr) -> Result<Entry,DYNERR> {
+        Ok(Entry::from_bytes(&self.entries[self.entry_idx(ptr)?])?)
     }
-    pub fn set_entry(&mut self,ptr: &Ptr,entry: &Entry) {
-        match ptr {
-            Ptr::Entry(idx) => {
-                self.entries[*idx] = entry.to_bytes().try_into().expect("unexpected size")
-            },
-            _ => panic!("wrong pointer type")
-        }
+    pub fn set_entry(&mut self,ptr: &Ptr,entry: &Entry) -> STDRESULT {
+        let idx = self.entry_idx(ptr)?;
+        self.entries[idx].copy_from_slice(&entry.to_bytes());
+        Ok(())
+    }
+    /// Pointer to the disk label entry, if there is one
+    pub fn find_label_ptr(&self) -> Option<Ptr> {
+        (0..self.num_entries()).map(Ptr::Entry).find(|ptr| matches!(self.get_type(ptr),Ok(EntryType::VolumeLabel)))
     }
     /// If this is the root directory there may be a disk label entry
     pub fn find_label(&self) -> Option<Entry> {
-        for i in 0..self.num_entries() {
-            let ptr = Ptr::Entry(i);
-            if self.get_type(&ptr)==EntryType::VolumeLabel {
-                return Some(self.get_entry(&ptr));
-            }
+        match self.find_label_ptr() {
+            Some(ptr) => self.get_entry(&ptr).ok(),
+            None => None
         }
-        None
     }
-    fn add_file(&self,ans: &mut BTreeMap<String,FileInfo>,fat_typ: usize,entry_idx: usize) -> Result<bool,DYNERR> {
-        let entry = self.get_entry(&Ptr::Entry(entry_idx));    
-        let (name,typ) = super::pack::file_name_to_split_string(entry.name, entry.ext);
+    fn add_file(&self,ans: &mut BTreeMap<String,FileInfo>,fat_typ: usize,entry_idx: usize,cp: CodePage) -> Result<bool,DYNERR> {
+        let entry = self.get_entry(&Ptr::Entry(entry_idx))?;
+        let (name,typ) = super::pack::file_name_to_split_string(entry.name, entry.ext, cp);
         let key = [name.clone(),".".to_string(),typ.clone()].concat();
         trace!("entry in use: {}",key);
         if ans.contains_key(&key) {
@@ -457,15 +503,15 @@ impl Directory {
             cluster1: Some(Ptr::Cluster(cluster1))
         };
         ans.insert(key.clone(),finfo);
-        Ok(super::pack::is_name_valid(&key))
+        Ok(super::pack::is_name_valid(&key,cp))
     }
-    /// Build an alphabetized map of file names to file info.
-    pub fn build_files(&self,fat_typ: usize) -> Result<BTreeMap<String,FileInfo>,DYNERR> {
+    /// Build an alphabetized map of file names to file info, decoding names with the given code page.
+    pub fn build_files(&self,fat_typ: usize,cp: CodePage) -> Result<BTreeMap<String,FileInfo>,DYNERR> {
         let mut bad_names = 0;
         let mut ans = BTreeMap::new();
         // first pass collects everything except passwords
         for i in 0..self.num_entries() {
-            let etyp = self.get_type(&Ptr::Entry(i));
+            let etyp = self.get_type(&Ptr::Entry(i))?;
             if etyp==EntryType::Free {
                 continue;
             }
@@ -476,7 +522,7 @@ impl Directory {
                 debug!("after {} bad file names rejecting disk",bad_names);
                 return Err(Box::new(Error::Syntax));
             }
-            if !self.add_file(&mut ans,fat_typ,i)? {
+            if !self.add_file(&mut ans,fat_typ,i,cp)? {
                 bad_names += 1;
             }
         }
@@ -496,7 +542,7 @@ impl Directory {
 /// Search for a file in the map produced by `Directory::build_files`.
 /// This will try with the given case, and then with upper case.
 /// This will also handle empty extensions reliably.
-pub fn get_file<'a>(name: &str,files: &'a BTreeMap<String,FileInfo>) -> Option<&'a FileInfo> {
+pub fn get_file<'a>(name: &str,files: &'a BTreeMap<String,FileInfo>,cp: CodePage) -> Option<&'a FileInfo> {
     let mut trimmed = name.trim_end().to_string();
     if !name.contains(".") {
         trimmed += ".";
@@ -505,7 +551,7 @@ pub fn get_file<'a>(name: &str,files: &'a BTreeMap<String,FileInfo>) -> Option<&
     if let Some(finfo) = files.get(&trimmed) {
         return Some(finfo);
     }
-    if let Some(finfo) = files.get(&trimmed.to_uppercase()) {
+    if let Some(finfo) = files.get(&cp.to_upper(&trimmed)) {
         return Some(finfo);
     }
     return None;
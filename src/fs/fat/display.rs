@@ -4,45 +4,49 @@
 
 use log::{debug,error};
 use colored::Colorize;
+use std::fmt::Write;
+use std::collections::BTreeMap;
 use super::directory;
+use super::codepage::CodePage;
 use super::types::Error;
-use crate::{DYNERR,STDRESULT};
+use crate::DYNERR;
 
 /// Extend a filename string by padding with
 /// spaces or expanding asterisk wildcard.
-fn extend_fragment(short: &str,len: usize) -> Result<String,DYNERR> {
+fn extend_fragment(short: &str,len: usize) -> Result<Vec<char>,DYNERR> {
     debug!("extending {}",short);
-    let mut ans = String::new();
+    let chars: Vec<char> = short.chars().collect();
+    let mut ans = Vec::new();
     let mut curs: usize = 0;
-    if short.len() > 0 {
+    if !chars.is_empty() {
         loop {
-            match &short[curs..curs+1] {
-                "*" => {
-                    if curs+1!=short.len() {
+            match chars[curs] {
+                '*' => {
+                    if curs+1!=chars.len() {
                         error!("wildcard in illegal position");
                         return Err(Box::new(Error::Syntax));
                     }
                     for _i in curs..len {
-                        ans += "?";
+                        ans.push('?');
                     }
                     return Ok(ans);
                 }
-                c => ans += c
+                c => ans.push(c)
             }
             curs += 1;
-            if curs >= short.len() || curs >= len {
+            if curs >= chars.len() || curs >= len {
                 break;
             }
         }
     }
     for _i in curs..len {
-        ans += " ";
+        ans.push(' ');
     }
     Ok(ans)
 }
 
 /// Test for a match to a CP/M wildcard pattern
-fn match_wildcard_pattern(patt_raw: &str,base_raw: &str,typ_raw: &str) -> Result<bool,DYNERR> {
+fn match_wildcard_pattern(patt_raw: &str,base_raw: &str,typ_raw: &str,cp: CodePage) -> Result<bool,DYNERR> {
     if patt_raw.len()==0 {
         return Ok(true);
     }
@@ -50,28 +54,28 @@ fn match_wildcard_pattern(patt_raw: &str,base_raw: &str,typ_raw: &str) -> Result
         return Ok(false);
     }
     let mut dot_iter = patt_raw.split('.');
-    let patt_base = extend_fragment(dot_iter.next().unwrap(),8)?.to_uppercase();
+    let patt_base = extend_fragment(&cp.to_upper(dot_iter.next().unwrap()),8)?;
     let patt_typ = match dot_iter.next() {
-        Some(s) => extend_fragment(s,3)?.to_uppercase(),
-        None => "   ".to_string()
+        Some(s) => extend_fragment(&cp.to_upper(s),3)?,
+        None => vec![' ';3]
     };
-    let base = extend_fragment(base_raw,8)?.to_uppercase();
-    let typ = extend_fragment(typ_raw,3)?.to_uppercase();
+    let base = extend_fragment(&cp.to_upper(base_raw),8)?;
+    let typ = extend_fragment(&cp.to_upper(typ_raw),3)?;
     for i in 0..8 {
-        if &patt_base[i..i+1]!="?" && patt_base[i..i+1]!=base[i..i+1] {
+        if patt_base[i]!='?' && patt_base[i]!=base[i] {
             return Ok(false);
         }
     }
     for i in 0..3 {
-        if &patt_typ[i..i+1]!="?" && patt_typ[i..i+1]!=typ[i..i+1] {
+        if patt_typ[i]!='?' && patt_typ[i]!=typ[i] {
             return Ok(false);
         }
     }
     Ok(true)
 }
 
-fn format_name(finfo: &directory::FileInfo) {
-    print!("{:8} {:3}",
+fn format_name(out: &mut String,finfo: &directory::FileInfo) -> std::fmt::Result {
+    write!(out,"{:8} {:3}",
         match (finfo.directory,finfo.system,finfo.read_only,finfo.hidden) {
             (true,_,_,_) => finfo.name.blue().bold(),
             (_,true,_,_) => finfo.name.bold(),
@@ -88,91 +92,121 @@ fn format_name(finfo: &directory::FileInfo) {
             (_,_,false,true) => finfo.typ.dimmed(),
             _ => finfo.typ.normal()
         },
-    );
+    )?;
+    Ok(())
 }
 
 /// Display basic directory table cell.
-fn dir_table(finfo: &directory::FileInfo,count: &mut usize,cols: usize) {
+fn dir_table(out: &mut String,finfo: &directory::FileInfo,count: &mut usize,cols: usize) -> std::fmt::Result {
     if *count%cols>0 {
-        print!("    ");
+        write!(out,"    ")?;
     }
-    format_name(finfo);
+    format_name(out,finfo)?;
     if *count%cols==cols-1 {
-        println!();
+        writeln!(out)?;
     }
     *count += 1;
+    Ok(())
 }
 
 /// Display one line of directory listing
-fn dir_line(finfo: &directory::FileInfo,count: &mut usize) {
-    format_name(finfo);
+fn dir_line(out: &mut String,finfo: &directory::FileInfo,count: &mut usize) -> std::fmt::Result {
+    format_name(out,finfo)?;
     if finfo.directory {
-        print!(" <DIR>     ");
+        write!(out," <DIR>     ")?;
     } else {
-        print!(" {:8}  ",finfo.eof);
+        write!(out," {:8}  ",finfo.eof)?;
     }
     if let Some(t) = finfo.write_date {
-        print!("{}   ",t.format("%m-%d-%y").to_string());
+        write!(out,"{}   ",t.format("%m-%d-%y"))?;
         if let Some(t) = finfo.write_time {
-            print!("{}",t.format("%H:%M").to_string());
+            write!(out,"{}",t.format("%H:%M"))?;
         }
     }
-    println!();
+    writeln!(out)?;
     *count += 1;
+    Ok(())
 }
 
-fn is_displayed(finfo: &directory::FileInfo,pattern: &str) -> bool {
+fn is_displayed(finfo: &directory::FileInfo,pattern: &str,cp: CodePage) -> bool {
     let mut ans = true;
-    let pattern_match = match_wildcard_pattern(pattern, &finfo.name, &finfo.typ).expect("bad wildcard pattern");
+    let pattern_match = match_wildcard_pattern(pattern, &finfo.name, &finfo.typ, cp).expect("bad wildcard pattern");
     ans &= pattern_match;
     ans &= !finfo.volume_id;
     ans
 }
 
-/// Display FAT directory, either in normal or `wide` mode.
-/// This will behave like MS-DOS 3.3, except for color highlights.
-pub fn dir(path: &str,vol_lab: &str, dir: &directory::Directory,pattern: &str,wide: bool,free: u64,fat_typ: usize) -> STDRESULT {
+fn dir_header(out: &mut String,path: &str,vol_lab: &str) -> std::fmt::Result {
     if vol_lab!="NO NAME" {
-        println!();
-        println!(" Volume in drive A is {}",vol_lab.blue().bold());
+        writeln!(out)?;
+        writeln!(out," Volume in drive A is {}",vol_lab.blue().bold())?;
     } else {
-        println!();
-        println!(" Volume in drive A has no label")
+        writeln!(out)?;
+        writeln!(out," Volume in drive A has no label")?
     }
     let displ_path = if !path.starts_with("/") {
         "/".to_string() + path
     } else {
         path.to_string()
     }.replace("/","\\").to_uppercase();
-    println!(" Directory of A:{}",&displ_path);
-    if let Ok(sorted) = dir.build_files(fat_typ) {
+    writeln!(out," Directory of A:{}",&displ_path)
+}
+
+/// Display FAT directory, either in normal or `wide` mode.
+/// This will behave like MS-DOS 3.3, except for color highlights.
+#[allow(clippy::too_many_arguments)]
+pub fn dir(path: &str,vol_lab: &str, dir: &directory::Directory,pattern: &str,wide: bool,free: u64,fat_typ: usize,cp: CodePage) -> Result<String,DYNERR> {
+    let mut out = String::new();
+    dir_header(&mut out,path,vol_lab)?;
+    if let Ok(sorted) = dir.build_files(fat_typ,cp) {
         // `build_files` sorts on the name automatically, so we have to "re-sort" in order
         // to get the "unsorted" list.
         let unsorted = dir.sort_on_entry_index(&sorted);
         let mut count = 0;
         if wide {
-            println!();
+            let cols = crate::fs::render::fit_columns(5,12,4);
+            writeln!(out)?;
             for v in unsorted.values() {
-                if is_displayed(v, pattern) {
-                    dir_table(v,&mut count,5);
+                if is_displayed(v, pattern, cp) {
+                    dir_table(&mut out,v,&mut count,cols)?;
                 }
             }
-            if count % 5 > 0 {
-                println!();
+            if count % cols > 0 {
+                writeln!(out)?;
             }
         } else {
-            println!();
+            writeln!(out)?;
             for v in unsorted.values() {
-                if is_displayed(v, pattern) {
-                    dir_line(v,&mut count);
+                if is_displayed(v, pattern, cp) {
+                    dir_line(&mut out,v,&mut count)?;
                 }
             }
         }
         if count==0 {
-            println!("No File");
+            writeln!(out,"No File")?;
         }
-        println!("{:9} File(s)   {} bytes free",count,free);
-        println!();
+        writeln!(out,"{:9} File(s)   {} bytes free",count,free)?;
+        writeln!(out)?;
     }
-    Ok(())
-}
\ No newline at end of file
+    Ok(out)
+}
+
+/// Display FAT directory in normal mode, taken apart into a header, a footer, and a map from
+/// entry index to the line for that entry.  The footer counts every file in the directory.
+pub fn dir_parts(path: &str,vol_lab: &str,dir: &directory::Directory,free: u64,fat_typ: usize,cp: CodePage) -> Result<(String,BTreeMap<usize,String>,String),DYNERR> {
+    let mut header = String::new();
+    dir_header(&mut header,path,vol_lab)?;
+    writeln!(header)?;
+    let sorted = dir.build_files(fat_typ,cp)?;
+    let mut rows = BTreeMap::new();
+    let mut count = 0;
+    for (idx,v) in dir.sort_on_entry_index(&sorted) {
+        if !v.volume_id {
+            let mut line = String::new();
+            dir_line(&mut line,&v,&mut count)?;
+            rows.insert(idx,line.trim_end_matches('\n').to_string());
+        }
+    }
+    let footer = format!("{:9} File(s)   {} bytes free\n\n",count,free);
+    Ok((header,rows,footer))
+}
@@ -8,7 +8,8 @@
 //! Assumes empty partition table.
 
 mod directory;
-mod pack;
+pub(crate) mod pack;
+pub mod codepage;
 mod types;
 mod display;
 
@@ -26,17 +27,18 @@ use crate::{DYNERR,STDRESULT};
 
 pub const FS_NAME: &str = "fat";
 
-pub fn new_fimg(chunk_len: usize,set_time: bool,path: &str) -> Result<super::FileImage,DYNERR> {
-    if !pack::is_path_valid(path) {
+/// New file image, stamped with `time` if it is not `None`, the path is checked using code page `cp`
+pub fn new_fimg(chunk_len: usize,time: Option<chrono::NaiveDateTime>,path: &str,cp: codepage::CodePage) -> Result<super::FileImage,DYNERR> {
+    if !pack::is_path_valid(path,cp) {
         return Err(Box::new(Error::Syntax))
     }
-    let created = match set_time {
-        true => [
-            vec![pack::pack_tenths(None)],
-            pack::pack_time(None).to_vec(),
-            pack::pack_date(None).to_vec()
+    let created = match time {
+        Some(t) => [
+            vec![pack::pack_tenths(Some(t))],
+            pack::pack_time(Some(t)).to_vec(),
+            pack::pack_date(Some(t)).to_vec()
         ].concat(),
-        false => vec![0;5]
+        None => vec![0;5]
     };
     Ok(super::FileImage {
         fimg_version: super::FileImage::fimg_version(),
@@ -57,6 +59,7 @@ pub fn new_fimg(chunk_len: usize,set_time: bool,path: &str) -> Result<super::Fil
 }
 
 pub struct Packer {
+    codepage: codepage::CodePage
 }
 
 /// The primary interface for disk operations.
@@ -66,7 +69,9 @@ pub struct Disk {
     maybe_fat: Option<Vec<u8>>,
     /// only valid during glob search
     curr_path: Vec<String>,
-    typ: usize
+    typ: usize,
+    codepage: codepage::CodePage,
+    deterministic: bool
 }
 
 impl Disk {
@@ -102,7 +107,9 @@ impl Disk {
             boot_sector,
             maybe_fat: None,
             curr_path: Vec::new(),
-            typ
+            typ,
+            codepage: codepage::CodePage::default(),
+            deterministic: false
         })
     }
     /// Create an MS-DOS 1.0 file system using the given image as storage.
@@ -115,9 +122,41 @@ impl Disk {
             boot_sector,
             maybe_fat: None,
             curr_path: Vec::new(),
-            typ
+            typ,
+            codepage: codepage::CodePage::default(),
+            deterministic: false
         })
     }
+    /// Create a FAT file system on a disk with no BPB, taking the geometry from the FAT ID.
+    /// This is the quirk mode for MSX-DOS and other Z80 machines, see `BootSector::create_from_fat_id`.
+    /// The DiskFS takes ownership of the image.
+    pub fn from_img_fat_id(mut img: Box<dyn img::DiskImage>) -> Result<Self,DYNERR> {
+        let media = img.read_sector(0,0,2)?[0];
+        let boot_sector = bpb::BootSector::create_from_fat_id(media,img.byte_capacity())?;
+        let typ = boot_sector.fat_type();
+        Ok(Self {
+            img,
+            boot_sector,
+            maybe_fat: None,
+            curr_path: Vec::new(),
+            typ,
+            codepage: codepage::CodePage::default(),
+            deterministic: false
+        })
+    }
+    /// Decode and encode file names and labels using the given code page
+    pub fn with_codepage(mut self,cp: codepage::CodePage) -> Self {
+        self.codepage = cp;
+        self
+    }
+    /// Fix the times that are stored, and the volume serial number of a new disk, see `crate::now`
+    pub fn with_deterministic(mut self,deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+    fn now(&self) -> Option<chrono::NaiveDateTime> {
+        Some(crate::now(self.deterministic))
+    }
     /// Test an image for the FAT file system.
     pub fn test_img(img: &mut Box<dyn img::DiskImage>) -> bool {
         // test the boot sector to see if this is FAT
@@ -169,30 +208,72 @@ impl Disk {
                 debug!("wrong media type {} != {}",buf[0],boot.media_byte());
                 ans = false;
             }
-            let beg = fat::FIRST_DATA_CLUSTER as usize;
-            let end = beg + boot.cluster_count_usable() as usize;
-            for n in beg..end {
-                if fat::is_damaged(n, 12, &buf) || fat::is_free(n, 12, &buf) || fat::is_last(n, 12, &buf) {
-                    continue;
-                }
-                let val = fat::get_cluster(n, 12, &buf) as usize;
-                trace!("cluster link {} -> {}",n,val);
-                if val < beg || val >= end {
-                    debug!("wrong cluster link {} -> {}",n,val);
-                    ans = false;
-                    break;
-                }
-            }
+            ans &= Self::test_fat12_links(&buf,&boot);
         } else {
             debug!("could not guess FAT parameters");
             ans = false;
         }
         ans
     }
+    /// Test an image for a FAT12 file system with no BPB, where the geometry is implied by the FAT ID,
+    /// as on disks from MSX-DOS and other Z80 machines.  This should be tried after `test_img_dos1x`.
+    pub fn test_img_fat_id(img: &mut Box<dyn img::DiskImage>) -> bool {
+        let first = match img.read_sector(0,0,2) {
+            Ok(b) if b.len() >= 3 => b,
+            _ => {
+                debug!("first FAT sector was not readable");
+                return false;
+            }
+        };
+        if first[1]!=0xff || first[2]!=0xff {
+            debug!("FAT ID not followed by 0xffff");
+            return false;
+        }
+        let boot = match bpb::BootSector::create_from_fat_id(first[0],img.byte_capacity()) {
+            Ok(b) => b,
+            Err(_) => {
+                debug!("FAT ID {} is not in the table",first[0]);
+                return false;
+            }
+        };
+        let mut buf = Vec::new();
+        let sec1 = boot.res_secs() as u64;
+        for isec in sec1..sec1+boot.fat_secs() {
+            match img.read_sector(0, 0, 1 + isec as usize) {
+                Ok(mut b) => buf.append(&mut b),
+                Err(_) => {
+                    debug!("could not read FAT");
+                    return false;
+                }
+            }
+        }
+        Self::test_fat12_links(&buf,&boot)
+    }
+    /// Check that every link in a FAT12 points to a cluster that exists
+    fn test_fat12_links(buf: &[u8],boot: &bpb::BootSector) -> bool {
+        let buf = buf.to_vec();
+        let beg = fat::FIRST_DATA_CLUSTER as usize;
+        let end = beg + boot.cluster_count_usable() as usize;
+        for n in beg..end {
+            if fat::is_damaged(n, 12, &buf) || fat::is_free(n, 12, &buf) || fat::is_last(n, 12, &buf) {
+                continue;
+            }
+            let val = fat::get_cluster(n, 12, &buf) as usize;
+            trace!("cluster link {} -> {}",n,val);
+            if val < beg || val >= end {
+                debug!("wrong cluster link {} -> {}",n,val);
+                return false;
+            }
+        }
+        true
+    }
     fn get_chs(&self,ptr: &Ptr) -> Result<[usize;3],DYNERR> {
         let lsec = match ptr {
             Ptr::LogicalSector(s) => *s,
-            _ => panic!("wrong pointer type")
+            _ => {
+                error!("wrong pointer type");
+                return Err(Box::new(Error::General));
+            }
         };
         let psec = lsec % self.boot_sector.secs_per_track() as usize;
         let trk = lsec / self.boot_sector.secs_per_track() as usize;
@@ -246,7 +327,8 @@ impl Disk {
         if let Some(buf) = self.maybe_fat.as_mut() {
             return Ok((self.typ,buf));
         }
-        panic!("FAT buffer failed to open");
+        error!("FAT buffer failed to open");
+        Err(Box::new(Error::BadFAT))
     }
     /// Buffer needs to be written back when an external caller
     /// asks, directly or indirectly, for the underlying image.
@@ -353,10 +435,11 @@ impl Disk {
     /// Format a disk with the FAT file system, by this point the boot sector is presumed to be buffered,
     /// and must at least contain a valid BPB foundation.  If there is a BPB tail it is overwritten.
     pub fn format(&mut self, vol_name: &str, time: Option<chrono::NaiveDateTime>) -> STDRESULT {
-        if !pack::is_label_valid(vol_name) && vol_name.len()>0 {
+        if !pack::is_label_valid(vol_name,self.codepage) && !vol_name.is_empty() {
             error!("FAT volume name invalid");
             return Err(Box::new(Error::Syntax));
         }
+        let time = time.or(self.now());
         let block_size = self.boot_sector.block_size() as usize;
         let sec_size = self.boot_sector.sec_size() as usize;
         let media = self.boot_sector.media_byte() as u32;
@@ -377,14 +460,14 @@ impl Disk {
         // Create a BPB tail
         let boot_label: [u8;11] = match vol_name.len()>0 {
             true => {
-                let (nm,x) = pack::string_to_label_name(vol_name);
+                let (nm,x) = pack::string_to_label_name(vol_name,self.codepage);
                 [nm.to_vec(),x.to_vec()].concat().try_into().expect("label mismatch")
             }
             false => *b"NO NAME    "
         };
         // Using nanos gives us about 30 bits of resolution.
         // This avoids issues with the FAT datestamp after the year 2107.
-        let id = u32::to_le_bytes(chrono::Local::now().naive_local().and_utc().timestamp_subsec_nanos());
+        let id = u32::to_le_bytes(crate::now(self.deterministic).and_utc().timestamp_subsec_nanos());
         self.boot_sector.create_tail(0x00, id, boot_label);
         // write the boot sector (perhaps rewriting).
         // may be written again if FAT32.
@@ -408,7 +491,7 @@ impl Disk {
                 dir.expand(block_size/directory::DIR_ENTRY_SIZE);
                 self.write_block(&vec![0;block_size], 0, root_cluster,0)?;
                 if vol_name.len()>0 {
-                    let mut label = Entry::create_label(vol_name,time);
+                    let mut label = Entry::create_label(vol_name,time,self.codepage);
                     label.set_attr(directory::VOLUME_ID | directory::ARCHIVE);
                     let mut loc = EntryLocation {
                         cluster1: Some(Ptr::Cluster(root_cluster)),
@@ -424,7 +507,7 @@ impl Disk {
                 if vol_name.len()>0 {
                     let mut dir = Directory::new();
                     dir.expand(self.boot_sector.root_dir_entries() as usize);
-                    let mut label = Entry::create_label(vol_name,time);
+                    let mut label = Entry::create_label(vol_name,time,self.codepage);
                     label.set_attr(directory::VOLUME_ID | directory::ARCHIVE);
                     let mut loc = EntryLocation {
                         cluster1: None,
@@ -443,7 +526,10 @@ impl Disk {
     fn next_cluster(&mut self,curr: &Ptr) -> Result<Option<Ptr>,DYNERR> {
         let n = *match curr {
             Ptr::Cluster(n) => n,
-            _ => panic!("wrong pointer type")
+            _ => {
+                error!("wrong pointer type");
+                return Err(Box::new(Error::General));
+            }
         };
         if !self.clus_in_rng(n) {
             error!("cluster {} out of range",n);
@@ -545,14 +631,15 @@ impl Disk {
     /// as the label obtained from the root directory, if it exists.
     fn get_label(&self) -> Option<String> {
         match self.boot_sector.label() {
-            Some(lab) => Some(pack::label_to_string(lab)),
+            Some(lab) => Some(pack::label_to_string(lab,self.codepage)),
             None => None
         }
     }
     fn is_root(&self,cluster1: &Option<Ptr>) -> bool {
         match (self.typ,cluster1) {
             (32,Some(c)) if c.unwrap() as u64 == self.boot_sector.root_dir_cluster1() => true,
-            (32,None) => panic!("FAT32 directory with no first cluster"),
+            // FAT32 directories always have a first cluster, `get_directory` will reject this
+            (32,None) => false,
             (_,Some(_)) => false,
             (_,None) => true
         }
@@ -578,7 +665,7 @@ impl Disk {
             }
         };
         let vol_name = match root.find_label() {
-            Some(entry) => entry.name(true),
+            Some(entry) => entry.name(true,self.codepage),
             None => {
                 match self.get_label() {
                     Some(lab) => lab,
@@ -598,7 +685,8 @@ impl Disk {
             },
             None => {
                 if self.typ==32 {
-                    panic!("attempt to get FAT32 root without cluster 1");
+                    error!("attempt to get FAT32 directory without cluster 1");
+                    return Err(Box::new(Error::FirstClusterInvalid));
                 }
                 Ok(self.get_root_dir()?.1)
             }
@@ -633,14 +721,14 @@ impl Disk {
     fn writeback_directory_entry(&mut self,loc: &mut EntryLocation,entry: &Entry) -> STDRESULT {
         let entries_per_cluster = self.boot_sector.block_size() as usize / directory::DIR_ENTRY_SIZE;
         let entries_per_sector = self.boot_sector.sec_size() as usize / directory::DIR_ENTRY_SIZE;
-        loc.dir.set_entry(&loc.entry, entry);
+        loc.dir.set_entry(&loc.entry, entry)?;
         match loc.cluster1 {
             Some(cluster1) => {
                 let mut data: Vec<u8> = Vec::new();
                 let cluster = cluster1.unwrap() + loc.entry.unwrap() / entries_per_cluster;
                 let entry_beg = (cluster - cluster1.unwrap()) * entries_per_cluster;
                 for i in entry_beg..entry_beg+entries_per_cluster {
-                    data.append(&mut loc.dir.get_raw_entry(&Ptr::Entry(i)).to_vec());
+                    data.append(&mut loc.dir.get_raw_entry(&Ptr::Entry(i))?.to_vec());
                 }
                 self.zap_block(&data, cluster, 0)
             },
@@ -651,7 +739,7 @@ impl Disk {
                 let lsec = sec_beg as usize + loc.entry.unwrap() / entries_per_sector;
                 let entry_beg = (lsec - sec_beg as usize) * entries_per_sector;
                 for i in entry_beg..entry_beg+entries_per_cluster {
-                    data.append(&mut loc.dir.get_raw_entry(&Ptr::Entry(i)).to_vec());
+                    data.append(&mut loc.dir.get_raw_entry(&Ptr::Entry(i))?.to_vec());
                 }
                 let [cyl,head,sec] = self.get_chs(&Ptr::LogicalSector(lsec))?;
                 self.img.write_sector(cyl, head, sec, &data)
@@ -663,7 +751,7 @@ impl Disk {
     fn get_available_entry(&mut self, dir: &mut Directory, maybe_cluster1: &Option<Ptr>) -> Result<Ptr,DYNERR> {
         let num = dir.num_entries();
         for i in 0..num {
-            match dir.get_type(&Ptr::Entry(i)) {
+            match dir.get_type(&Ptr::Entry(i))? {
                 EntryType::Free | EntryType::FreeAndNoMore => return Ok(Ptr::Entry(i)),
                 _ => continue
             }
@@ -684,14 +772,14 @@ impl Disk {
         if normalized.len()==0 {
             normalized = "/".to_string();
         }
-        if &normalized[0..1]!="/" {
+        if !normalized.starts_with('/') {
             normalized.insert(0,'/');
         }
-        if normalized.len()>63 {
-            error!("MS-DOS path too long {}",normalized.len());
+        if normalized.chars().count()>63 {
+            error!("MS-DOS path too long {}",normalized.chars().count());
             return Err(Box::new(Error::Syntax));
         }
-        let path_nodes: Vec<String> = normalized.split("/").map(|s| s.to_string().to_uppercase()).collect();
+        let path_nodes: Vec<String> = normalized.split("/").map(|s| self.codepage.to_upper(s)).collect();
 
         // check empty nodes
         for i in 1..path_nodes.len() {
@@ -739,7 +827,7 @@ impl Disk {
             return Ok((parent_info,root_info));
         }
         // walk the tree
-        let mut files = root.build_files(self.typ)?;
+        let mut files = root.build_files(self.typ,self.codepage)?;
         parent_info = Some(root_info);
         for level in 0..n {
             debug!("searching level {}: {}",level,parent_info.clone().unwrap().name);
@@ -750,7 +838,7 @@ impl Disk {
             if wildcard_terminus {
                 return Ok((parent_info,FileInfo::create_wildcard(&subdir)));
             }
-            let curr = match directory::get_file(&subdir, &files) {
+            let curr = match directory::get_file(&subdir, &files, self.codepage) {
                 Some(finfo) => finfo.clone(),
                 None => return Err(Box::new(Error::FileNotFound))
             };
@@ -758,17 +846,17 @@ impl Disk {
                 return Ok((parent_info,curr));
             }
             let new_dir = self.get_directory(&curr.cluster1)?;
-            files = new_dir.build_files(self.typ)?;
+            files = new_dir.build_files(self.typ,self.codepage)?;
             parent_info = Some(curr);
         }
         return Err(Box::new(Error::FileNotFound));
     }
     /// Read any file into a file image
     fn read_file(&mut self,parent: &FileInfo,finfo: &FileInfo) -> Result<super::FileImage,DYNERR> {
-        let mut fimg = new_fimg(self.boot_sector.block_size() as usize,false,"temp")?;
+        let mut fimg = new_fimg(self.boot_sector.block_size() as usize,None,"temp",self.codepage)?;
         // TODO: eliminate redundancy, by this time the directory has already been read at least once
         let dir = self.get_directory(&parent.cluster1)?;
-        let entry = dir.get_entry(&Ptr::Entry(finfo.idx));
+        let entry = dir.get_entry(&Ptr::Entry(finfo.idx))?;
         let all_data = self.get_cluster_chain_data(&finfo.cluster1.unwrap())?;
         fimg.desequence(&all_data);
         entry.metadata_to_fimg(&mut fimg); // must come after desequence or eof is spoiled
@@ -777,15 +865,15 @@ impl Disk {
     /// If the new name does not already exist, return an EntryLocation with entry pointer set to existing file.
     /// If the new name does exist, return an error.
     fn ok_to_rename(&mut self,old_path: &str,new_name: &str) -> Result<EntryLocation,DYNERR> {
-        if !pack::is_name_valid(new_name) {
+        if !pack::is_name_valid(new_name,self.codepage) {
             error!("invalid MS-DOS name {}",new_name);
             return Err(Box::new(Error::Syntax));
         }
         if let Ok((maybe_parent,file_info)) = self.goto_path(old_path) {
             if let Some(parent) = maybe_parent {
                 let search_dir = self.get_directory(&parent.cluster1)?;
-                let files = search_dir.build_files(self.typ)?;
-                return match directory::get_file(new_name, &files) {
+                let files = search_dir.build_files(self.typ,self.codepage)?;
+                return match directory::get_file(new_name, &files, self.codepage) {
                     Some(_) => Err(Box::new(Error::DuplicateFile)),
                     None => Ok(EntryLocation { cluster1: parent.cluster1, entry: Ptr::Entry(file_info.idx), dir: search_dir })
                 };
@@ -800,15 +888,15 @@ impl Disk {
     /// if the directory needs to grow.
     fn prepare_to_write(&mut self,path: &str) -> Result<(String,EntryLocation),DYNERR> {
         let [parent_path,new_name] = self.split_path(path)?;
-        if !pack::is_name_valid(&new_name) {
+        if !pack::is_name_valid(&new_name,self.codepage) {
             error!("invalid MS-DOS name {}",&new_name);
             return Err(Box::new(Error::Syntax));
         }
         debug!("write {} to {}",new_name,parent_path);
         if let Ok((_maybe_grandparent,parent)) = self.goto_path(&parent_path) {
             let mut search_dir = self.get_directory(&parent.cluster1)?;
-            let files = search_dir.build_files(self.typ)?;
-            return match directory::get_file(&new_name, &files) {
+            let files = search_dir.build_files(self.typ,self.codepage)?;
+            return match directory::get_file(&new_name, &files, self.codepage) {
                 Some(_) => Err(Box::new(Error::DuplicateFile)),
                 None => match self.get_available_entry(&mut search_dir, &parent.cluster1) {
                     Ok(ptr) => Ok((new_name,EntryLocation { cluster1: parent.cluster1, entry: ptr, dir: search_dir})),
@@ -822,7 +910,7 @@ impl Disk {
     /// The entry must already exist, but cluster1 pointer will be set herein.
     /// Also writes back changes to the directory (but FAT remains in buffer).
     fn write_file(&mut self,loc: &mut EntryLocation,fimg: &super::FileImage) -> Result<usize,DYNERR> {
-        let mut entry = loc.dir.get_entry(&loc.entry);
+        let mut entry = loc.dir.get_entry(&loc.entry)?;
         if self.num_free_blocks()? < fimg.end() {
             return Err(Box::new(Error::DiskFull));
         }
@@ -836,7 +924,8 @@ impl Disk {
                     }
                     prev = curr;
                 } else {
-                    panic!("unexpectedly ran out of disk space");
+                    error!("unexpectedly ran out of disk space");
+                    return Err(Box::new(Error::DiskFull));
                 }
             } else {
                 error!("FAT file image had a hole which is not allowed");
@@ -847,9 +936,142 @@ impl Disk {
         self.writeback_directory_entry(loc,&entry)?;
         return Ok(entry.eof());
     }
+    /// Find a file that can be written, returning its location and entry
+    fn find_writable_file(&mut self,path: &str) -> Result<(EntryLocation,Entry),DYNERR> {
+        let (maybe_parent,finfo) = self.goto_path(path)?;
+        if !finfo.wildcard.is_empty() || finfo.directory {
+            error!("{} is not a file",path);
+            return Err(Box::new(Error::Syntax));
+        }
+        let parent = match maybe_parent {
+            Some(p) => p,
+            None => {
+                error!("file with no parent directory {}",path);
+                return Err(Box::new(Error::FileNotFound));
+            }
+        };
+        let dir = self.get_directory(&parent.cluster1)?;
+        let loc = EntryLocation { cluster1: parent.cluster1, entry: Ptr::Entry(finfo.idx), dir };
+        let entry = loc.dir.get_entry(&loc.entry)?;
+        if entry.get_attr(directory::READ_ONLY) {
+            error!("{} is read only",path);
+            return Err(Box::new(Error::WriteProtect));
+        }
+        Ok((loc,entry))
+    }
+    /// List the clusters in the chain starting at `cluster1`, which may be 0 for an empty file
+    fn cluster_chain(&mut self,cluster1: usize) -> Result<Vec<usize>,DYNERR> {
+        let mut ans = Vec::new();
+        if cluster1==0 {
+            return Ok(ans);
+        }
+        let mut curr = Ptr::Cluster(cluster1);
+        for _i in 0..self.boot_sector.cluster_count_usable() {
+            ans.push(curr.unwrap());
+            curr = match self.next_cluster(&curr)? {
+                None => return Ok(ans),
+                Some(next) => next
+            };
+        }
+        Err(Box::new(Error::BadFAT))
+    }
+    /// Write `dat` into the file at byte `offset`, or at the end if `None`, extending the cluster chain as needed.
+    /// The size is updated if the file grows.  Returns the new size.
+    fn write_into(&mut self,path: &str,maybe_offset: Option<usize>,dat: &[u8]) -> Result<usize,DYNERR> {
+        let (mut loc,mut entry) = self.find_writable_file(path)?;
+        let eof = entry.eof();
+        let offset = maybe_offset.unwrap_or(eof);
+        if offset > eof {
+            error!("offset {} is past the end of the file ({} bytes)",offset,eof);
+            return Err(Box::new(Error::InvalidSwitch));
+        }
+        if offset + dat.len() > u32::MAX as usize {
+            error!("file would exceed the maximum length");
+            return Err(Box::new(Error::DiskFull));
+        }
+        let block_size = self.boot_sector.block_size() as usize;
+        let chain = self.cluster_chain(entry.cluster1(self.typ))?;
+        let needed = (offset + dat.len()).div_ceil(block_size).saturating_sub(chain.len());
+        if needed > self.num_free_blocks()? {
+            return Err(Box::new(Error::DiskFull));
+        }
+        let snap = super::DiskFS::snapshot(self);
+        let result = match self.write_chain(&mut entry,chain,offset,dat) {
+            Ok(()) => self.finish_resize(&mut loc,&mut entry,usize::max(eof,offset + dat.len())),
+            Err(e) => Err(e)
+        };
+        if result.is_err() {
+            super::DiskFS::rollback(self,&snap)?;
+        }
+        result
+    }
+    /// Write `dat` at byte `offset` of the file whose clusters are `chain`, adding clusters as needed.
+    /// If the file had no clusters the first one is set in `entry`.
+    fn write_chain(&mut self,entry: &mut Entry,mut chain: Vec<usize>,offset: usize,dat: &[u8]) -> STDRESULT {
+        let block_size = self.boot_sector.block_size() as usize;
+        let mut buf: Vec<u8> = vec![0;block_size];
+        let mut ptr = offset;
+        while ptr < offset + dat.len() {
+            let beg = ptr % block_size;
+            let n = usize::min(block_size - beg,offset + dat.len() - ptr);
+            let idx = ptr / block_size;
+            if idx < chain.len() {
+                self.read_block(&mut buf,chain[idx],0)?;
+                buf[beg..beg+n].copy_from_slice(&dat[ptr-offset..ptr-offset+n]);
+                self.zap_block(&buf,chain[idx],0)?;
+            } else {
+                let curr = match self.get_available_block()? {
+                    Some(c) => c,
+                    None => {
+                        error!("unexpectedly ran out of disk space");
+                        return Err(Box::new(Error::DiskFull));
+                    }
+                };
+                buf.fill(0);
+                buf[beg..beg+n].copy_from_slice(&dat[ptr-offset..ptr-offset+n]);
+                self.write_block(&buf,*chain.last().unwrap_or(&0),curr,0)?;
+                if chain.is_empty() {
+                    entry.set_cluster(curr);
+                }
+                chain.push(curr);
+            }
+            ptr += n;
+        }
+        Ok(())
+    }
+    /// Free the clusters of `chain` beyond `len` bytes and zero the rest of the last cluster that is kept.
+    /// If no clusters are kept the first cluster in `entry` is cleared.
+    fn cut_chain(&mut self,entry: &mut Entry,chain: &[usize],len: usize) -> STDRESULT {
+        let block_size = self.boot_sector.block_size() as usize;
+        let keep = len.div_ceil(block_size);
+        if keep < chain.len() {
+            if keep==0 {
+                entry.set_cluster(0);
+            } else {
+                let (typ,buf) = self.get_fat_buffer()?;
+                fat::mark_last(chain[keep-1],typ,buf);
+            }
+            self.deallocate_cluster_chain_data(&Ptr::Cluster(chain[keep]))?;
+        }
+        if !len.is_multiple_of(block_size) {
+            let mut buf: Vec<u8> = vec![0;block_size];
+            self.read_block(&mut buf,chain[keep-1],0)?;
+            buf[len % block_size..].fill(0);
+            self.zap_block(&buf,chain[keep-1],0)?;
+        }
+        Ok(())
+    }
+    /// Update size, time, and archive bit, and write back the entry
+    fn finish_resize(&mut self,loc: &mut EntryLocation,entry: &mut Entry,len: usize) -> Result<usize,DYNERR> {
+        entry.set_eof(len);
+        entry.set_write_time(self.now());
+        entry.set_attr(directory::ARCHIVE);
+        self.writeback_directory_entry(loc,entry)?;
+        Ok(len)
+    }
     /// modify a file entry, optionally change attributes, rename; attempt to rename read-only file will fail.
     fn modify(&mut self,loc: &mut EntryLocation,maybe_set: Option<u8>,maybe_clear: Option<u8>,maybe_new_name: Option<&str>) -> STDRESULT {  
-        let mut entry = loc.dir.get_entry(&loc.entry);
+        let mut entry = loc.dir.get_entry(&loc.entry)?;
         if entry.get_attr(directory::READ_ONLY) && maybe_new_name.is_some() {
             return Err(Box::new(Error::WriteProtect));
         }
@@ -860,8 +1082,8 @@ impl Disk {
             entry.clear_attr(mask);
         }
         if let Some(new_name) = maybe_new_name {
-            if pack::is_name_valid(new_name) {
-                entry.rename(new_name);
+            if pack::is_name_valid(new_name,self.codepage) {
+                entry.rename(new_name,self.codepage);
             } else {
                 return Err(Box::new(Error::Syntax));
             }
@@ -870,14 +1092,10 @@ impl Disk {
         self.writeback_directory_entry(loc,&entry)
     }
     /// Output FAT directory as a vector of paths that match a glob, calls itself recursively
-    fn glob_node(&mut self,pattern: &str,dir: &directory::Directory,case_sensitive: bool) -> Result<Vec<String>,DYNERR> {
+    fn glob_node(&mut self,glob: &super::NameMatcher,dir: &directory::Directory) -> Result<Vec<String>,DYNERR> {
         // this blindly searches everywhere, we could be more efficient by truncating based on the pattern
         let mut files = Vec::new();
-        let glob = match case_sensitive {
-            true => globset::GlobBuilder::new(&pattern).literal_separator(true).build()?.compile_matcher(),
-            false => globset::GlobBuilder::new(&pattern.to_uppercase()).literal_separator(true).build()?.compile_matcher()
-        };
-        if let Ok(sorted) = dir.build_files(self.typ) {
+        if let Ok(sorted) = dir.build_files(self.typ,self.codepage) {
             for finfo in sorted.values() {
                 if finfo.volume_id {
                     continue;
@@ -889,10 +1107,7 @@ impl Disk {
                     0 => finfo.name.clone(),
                     _ => [finfo.name.clone(),".".to_string(),finfo.typ.clone()].concat()
                 };
-                let test = match case_sensitive {
-                    true => [self.curr_path.concat(),key.clone()].concat(),
-                    false => [self.curr_path.concat(),key.clone()].concat().to_uppercase(),
-                };
+                let test = [self.curr_path.concat(),key.clone()].concat();
                 if !finfo.directory && glob.is_match(&test) {
                     let mut full_path = self.curr_path.concat();
                     full_path += &key;
@@ -903,7 +1118,7 @@ impl Disk {
                         trace!("descend into directory {}",key);
                         let subdir = self.get_directory(&Some(ptr))?;
                         self.curr_path.push(key + "/");
-                        files.append(&mut self.glob_node(pattern,&subdir,case_sensitive)?);
+                        files.append(&mut self.glob_node(glob,&subdir)?);
                     }
                 }
             }
@@ -916,7 +1131,7 @@ impl Disk {
         const DATE_FMT: &str = "%Y/%m/%d";
         const TIME_FMT: &str = "%H:%M";
         let mut files = json::JsonValue::new_object();
-        if let Ok(sorted) = dir.build_files(self.typ) {
+        if let Ok(sorted) = dir.build_files(self.typ,self.codepage) {
             for finfo in sorted.values() {
                 if finfo.volume_id {
                     continue;
@@ -988,13 +1203,69 @@ impl Disk {
         }
         Ok(files)
     }
+    /// Catalog items for a directory, each paired with its entry index
+    fn dir_items(&self,dir: &directory::Directory) -> Result<Vec<(usize,super::CatalogItem)>,DYNERR> {
+        let mut ans = Vec::new();
+        for i in 0..dir.num_entries() {
+            let entry_type = dir.get_type(&Ptr::Entry(i))?;
+            if entry_type==EntryType::FreeAndNoMore {
+                break;
+            }
+            if entry_type==EntryType::VolumeLabel || entry_type==EntryType::Free {
+                continue;
+            }
+            let entry = dir.get_entry(&Ptr::Entry(i))?;
+            let name_and_ext = entry.name(false,self.codepage);
+            let mut split = name_and_ext.split(".").collect::<Vec<&str>>();
+            if split.len()<2 {
+                split.push("");
+            }
+            let (typ,name) = match (entry_type,split[1].len()) {
+                (EntryType::Directory,0) => ("DIR",split[0].to_string()),
+                (EntryType::Directory,_) => ("DIR",name_and_ext),
+                _ => (split[1],split[0].to_string())
+            };
+            let blocks = 1 + (entry.eof() as i64 - 1) / self.boot_sector.block_size() as i64;
+            ans.push((i,super::CatalogItem {
+                row: super::universal_row(typ,blocks as usize,&name),
+                name,
+                typ: typ.to_string(),
+                blocks: blocks as usize,
+                modified: entry.write_time(),
+                addr: entry.cluster1(self.typ)
+            }));
+        }
+        Ok(ans)
+    }
+    /// Native catalog in parts, see `DiskFS::catalog_native`, rows are matched to items by entry index
+    fn native_catalog(&mut self,path: &str) -> Result<super::NativeCatalog,DYNERR> {
+        let (_,dir_info) = self.goto_path(path)?;
+        if !dir_info.directory {
+            return Err(Box::new(Error::FileNotFound));
+        }
+        let dir = self.get_directory(&dir_info.cluster1)?;
+        let (vol_lab,_) = self.get_root_dir()?;
+        let free = self.num_free_blocks()? as u64 * self.boot_sector.sec_size() * self.boot_sector.secs_per_clus() as u64;
+        let (header,mut lines,footer) = display::dir_parts(path,&vol_lab,&dir,free,self.typ,self.codepage)?;
+        let mut rows = Vec::new();
+        for (idx,item) in self.dir_items(&dir)? {
+            if let Some(line) = lines.remove(&idx) {
+                rows.push((item,line));
+            }
+        }
+        Ok(super::NativeCatalog { header, rows, footer })
+    }
 }
 
 impl super::DiskFS for Disk {
     fn new_fimg(&self, chunk_len: Option<usize>,set_time: bool,path: &str) -> Result<super::FileImage,DYNERR> {
+        let time = match set_time {
+            true => self.now(),
+            false => None
+        };
         match chunk_len {
-            Some(l) => new_fimg(l,set_time,path),
-            None => new_fimg(self.boot_sector.block_size() as usize,set_time,path)
+            Some(l) => new_fimg(l,time,path,self.codepage),
+            None => new_fimg(self.boot_sector.block_size() as usize,time,path,self.codepage)
         }
     }
     fn stat(&mut self) -> Result<super::Stat,DYNERR> {
@@ -1010,7 +1281,7 @@ impl super::DiskFS for Disk {
             raw: self.boot_sector.to_json(None)
         })
     }
-    fn catalog_to_stdout(&mut self, path_and_options: &str) -> STDRESULT {
+    fn catalog_to_string(&mut self, path_and_options: &str) -> Result<String,DYNERR> {
         let items: Vec<&str> = path_and_options.split_whitespace().collect();
         let (path,opt) = match items.len() {
             1 => (items[0],""),
@@ -1036,55 +1307,33 @@ impl super::DiskFS for Disk {
         let (vol_lab,_) = self.get_root_dir()?;
         let free = self.num_free_blocks()? as u64 * self.boot_sector.sec_size() * self.boot_sector.secs_per_clus() as u64;
         match opt.to_lowercase().as_str() {
-            "" => display::dir(&path,&vol_lab,&dir,&pattern,false,free,self.typ),
-            "/w" => display::dir(&path,&vol_lab,&dir,&pattern,true,free,self.typ),
+            "" => display::dir(path,&vol_lab,&dir,&pattern,false,free,self.typ,self.codepage),
+            "/w" => display::dir(path,&vol_lab,&dir,&pattern,true,free,self.typ,self.codepage),
             _ => Err(Box::new(Error::InvalidSwitch))
         }
     }
+    fn catalog_native(&mut self, path: &str) -> Result<Option<super::NativeCatalog>,DYNERR> {
+        Ok(Some(self.native_catalog(path)?))
+    }
     fn catalog_to_vec(&mut self, path: &str) -> Result<Vec<String>,DYNERR> {
-        match self.goto_path(path) {
-            Ok((_,dir_info)) => {
-                if dir_info.directory {
-                    let dir = self.get_directory(&dir_info.cluster1)?;
-                    let mut ans = Vec::new();
-                    for i in 0..dir.num_entries() {
-                        let entry_type = dir.get_type(&Ptr::Entry(i));
-                        if entry_type==EntryType::FreeAndNoMore {
-                            break;
-                        }
-                        if entry_type==EntryType::VolumeLabel || entry_type==EntryType::Free {
-                            continue;
-                        }
-                        let entry = dir.get_entry(&Ptr::Entry(i));
-                        let name_and_ext = entry.name(false);
-                        let mut split = name_and_ext.split(".").collect::<Vec<&str>>();
-                        if split.len()<2 {
-                            split.push("");
-                        }
-                        let (typ,name) = match (entry_type,split[1].len()) {
-                            (EntryType::Directory,0) => ("DIR",split[0].to_string()),
-                            (EntryType::Directory,_) => ("DIR",name_and_ext),
-                            _ => (split[1],split[0].to_string())
-                        };
-                        let blocks = 1 + (entry.eof() as i64 - 1) / self.boot_sector.block_size() as i64;
-                        ans.push(super::universal_row(typ,blocks as usize,&name));
-                    }
-                    return Ok(ans);
-                } else {
-                    return Err(Box::new(Error::FileNotFound));
-                }
-            },
-            Err(e) => Err(e)
+        Ok(self.catalog_items(path)?.into_iter().map(|x| x.row).collect())
+    }
+    fn catalog_items(&mut self, path: &str) -> Result<Vec<super::CatalogItem>,DYNERR> {
+        let (_,dir_info) = self.goto_path(path)?;
+        if !dir_info.directory {
+            return Err(Box::new(Error::FileNotFound));
         }
+        let dir = self.get_directory(&dir_info.cluster1)?;
+        Ok(self.dir_items(&dir)?.into_iter().map(|(_,item)| item).collect())
     }
-    fn glob(&mut self,pattern: &str,case_sensitive: bool) -> Result<Vec<String>,DYNERR> {
+    fn glob_with(&mut self,pattern: &str,opt: super::GlobOptions) -> Result<Vec<String>,DYNERR> {
         let (_,dir) = self.get_root_dir()?;
         self.curr_path = vec!["/".to_string()];
-        if pattern.starts_with("/") {
-            self.glob_node(pattern, &dir,case_sensitive)
-        } else {
-            self.glob_node(&["/",pattern].concat(), &dir, case_sensitive)
-        }
+        let glob = match pattern.starts_with("/") {
+            true => super::NameMatcher::new(pattern,opt)?,
+            false => super::NameMatcher::new(&["/",pattern].concat(),opt)?
+        };
+        self.glob_node(&glob, &dir)
     }
     fn tree(&mut self,include_meta: bool,indent: Option<u16>) -> Result<String,DYNERR> {
         let (vol,dir) = self.get_root_dir()?;
@@ -1106,7 +1355,7 @@ impl super::DiskFS for Disk {
                 Some(c) => c.unwrap(),
                 None => 0 // this holds even for FAT32
             };
-            let (entry, dir_data) = Entry::create_subdir(&name,parent_cluster,new_cluster,self.boot_sector.block_size() as usize,None);
+            let (entry, dir_data) = Entry::create_subdir(&name,parent_cluster,new_cluster,self.boot_sector.block_size() as usize,self.now(),self.codepage)?;
             self.write_block(&dir_data, 0, new_cluster, 0)?;
             self.writeback_directory_entry(&mut loc,&entry)    
         } else {
@@ -1127,7 +1376,7 @@ impl super::DiskFS for Disk {
                 return Err(Box::new(Error::WriteProtect));
             }
             let dir = self.get_directory(&finfo.cluster1)?;
-            let files = dir.build_files(self.typ)?;
+            let files = dir.build_files(self.typ,self.codepage)?;
             if files.len() > 2 {
                 error!("cannot delete directory with {} files",files.len()-2);
                 return Err(Box::new(Error::DirectoryNotEmpty));
@@ -1137,7 +1386,7 @@ impl super::DiskFS for Disk {
             Some(parent) => {
                 let dir = self.get_directory(&parent.cluster1)?;
                 let entry_ptr = Ptr::Entry(finfo.idx);
-                let mut entry = dir.get_entry(&entry_ptr);
+                let mut entry = dir.get_entry(&entry_ptr)?;
                 entry.erase(false);
                 self.writeback_directory_entry(&mut EntryLocation {
                     cluster1: parent.cluster1,
@@ -1150,7 +1399,10 @@ impl super::DiskFS for Disk {
                 }
                 Ok(())
             },
-            None => panic!("file with no parent directory {}",path)
+            None => {
+                error!("file with no parent directory {}",path);
+                Err(Box::new(Error::FileNotFound))
+            }
         }
     }
     fn protect(&mut self,_path: &str,_password: &str,_read: bool,_write: bool,_delete: bool) -> STDRESULT {
@@ -1216,6 +1468,24 @@ impl super::DiskFS for Disk {
             }
         }
     }
+    fn rename_wildcard(&mut self,pattern: &str,new_pattern: &str) -> Result<usize,DYNERR> {
+        if new_pattern.contains('/') {
+            error!("wildcard rename stays within the directory");
+            return Err(Box::new(Error::Syntax));
+        }
+        let matches = self.glob(pattern,false)?;
+        if matches.is_empty() {
+            error!("no file matches {}",pattern);
+            return Err(Box::new(Error::FileNotFound));
+        }
+        let existing = self.glob("**",false)?;
+        let renames = super::wildcard_renames(&matches,new_pattern,&existing)?;
+        for (old,new) in &renames {
+            let name = new.rsplit('/').next().unwrap_or(new);
+            self.rename(old,name)?;
+        }
+        Ok(renames.len())
+    }
     fn retype(&mut self,path: &str,new_type: &str,_sub_type: &str) -> STDRESULT {
         let (maybe_parent,finfo) = self.goto_path(path)?;
         if finfo.directory {
@@ -1275,6 +1545,33 @@ impl super::DiskFS for Disk {
             Err(e) => Err(Box::new(e))
         }
     }
+    fn set_time(&mut self,path: &str,time: chrono::NaiveDateTime) -> STDRESULT {
+        let (maybe_parent,finfo) = self.goto_path(path)?;
+        match maybe_parent {
+            Some(parent) => {
+                let dir = self.get_directory(&parent.cluster1)?;
+                let mut loc = EntryLocation {
+                    cluster1: parent.cluster1,
+                    entry: Ptr::Entry(finfo.idx),
+                    dir
+                };
+                let mut entry = loc.dir.get_entry(&loc.entry)?;
+                entry.set_create_time(Some(time));
+                entry.set_write_time(Some(time));
+                self.writeback_directory_entry(&mut loc,&entry)
+            },
+            None => {
+                error!("cannot set the time of root");
+                Err(Box::new(Error::General))
+            }
+        }
+    }
+    fn exists(&mut self,path: &str) -> Result<bool,DYNERR> {
+        match self.goto_path(path) {
+            Ok((Some(_),finfo)) => Ok(!finfo.directory && finfo.wildcard.is_empty()),
+            _ => Ok(false)
+        }
+    }
     fn get(&mut self,path: &str) -> Result<super::FileImage,DYNERR> {
         let (maybe_parent,finfo) = self.goto_path(path)?;
         if let Some(parent) = maybe_parent {
@@ -1297,10 +1594,10 @@ impl super::DiskFS for Disk {
         match self.prepare_to_write(&fimg.full_path) {
             Ok((name,mut loc)) => {
                 // create the entry
-                let mut entry = Entry::create(&name,None);
+                let mut entry = Entry::create(&name,self.now(),self.codepage);
                 entry.fimg_to_metadata(fimg,true)?;
                 debug!("create entry {:?}",entry.to_bytes());
-                loc.dir.set_entry(&loc.entry, &entry);
+                loc.dir.set_entry(&loc.entry, &entry)?;
                 // write blocks
                 match self.write_file(&mut loc,fimg) {
                     Ok(len) => Ok(len),
@@ -1319,7 +1616,7 @@ impl super::DiskFS for Disk {
             _ => Some(Ptr::Cluster(ref_con as usize))
         };
         let dir = self.get_directory(&cluster1).expect("disk error");
-        let files = dir.build_files(self.typ).expect("could not build files");
+        let files = dir.build_files(self.typ,self.codepage).expect("could not build files");
         for finfo in files.values() {
             // recursion into subdirectory
             if finfo.directory && finfo.name!="." && finfo.name!=".." {
@@ -1398,6 +1695,148 @@ impl super::DiskFS for Disk {
             }
         }
     }
+    fn stat_deep(&mut self) -> Result<json::JsonValue,DYNERR> {
+        let boot = &self.boot_sector;
+        let mut ans = json::JsonValue::new_object();
+        ans["bpb"] = json::object! {
+            "bytes_per_sec": boot.sec_size(),
+            "sec_per_clus": boot.secs_per_clus(),
+            "reserved_sectors": boot.res_secs(),
+            "num_fats": boot.num_fats(),
+            "root_ent_cnt": boot.root_dir_entries(),
+            "tot_sec": boot.tot_sec(),
+            "media": boot.media_byte(),
+            "fat_size": boot.fat_secs(),
+            "sec_per_trk": boot.secs_per_track(),
+            "num_heads": boot.heads()
+        };
+        ans["derived"] = json::object! {
+            "fat_type": boot.fat_type(),
+            "cluster_size": boot.block_size(),
+            "clusters": boot.cluster_count_usable(),
+            "root_dir_secs": boot.root_dir_secs(),
+            "first_data_sec": boot.first_data_sec(),
+            "free_clusters": self.num_free_blocks()?,
+            "image": super::image_json(self.img.as_ref())
+        };
+        Ok(ans)
+    }
+    fn inspect(&mut self,path: &str,indent: Option<u16>) -> Result<String,DYNERR> {
+        let (maybe_parent,finfo) = self.goto_path(path)?;
+        if maybe_parent.is_none() || !finfo.wildcard.is_empty() {
+            error!("inspect needs the path of a file or subdirectory");
+            return Err(Box::new(Error::ReadFault));
+        }
+        let mut chain = Vec::new();
+        if let Some(mut curr) = finfo.cluster1.filter(|c| c.unwrap() > 0) {
+            if !self.clus_in_rng(curr.unwrap()) {
+                error!("invalid first cluster {}",curr.unwrap());
+                return Err(Box::new(Error::FirstClusterInvalid));
+            }
+            let max_clusters = self.boot_sector.cluster_count_usable() as usize;
+            loop {
+                chain.push(curr.unwrap());
+                if chain.len() > max_clusters {
+                    error!("cluster chain is longer than the volume");
+                    return Err(Box::new(Error::BadFAT));
+                }
+                curr = match self.next_cluster(&curr)? {
+                    None => break,
+                    Some(next) => next
+                };
+            }
+        }
+        let mut ans = json::JsonValue::new_object();
+        ans["file_system"] = json::JsonValue::String(FS_NAME.to_string());
+        ans["path"] = json::JsonValue::String(path.to_string());
+        ans["directory"] = finfo.directory.into();
+        ans["eof"] = finfo.eof.into();
+        ans["cluster_size"] = self.boot_sector.block_size().into();
+        ans["clusters"] = chain.into();
+        if let Some(spaces) = indent {
+            Ok(json::stringify_pretty(ans,spaces))
+        } else {
+            Ok(json::stringify(ans))
+        }
+    }
+    fn append(&mut self,path: &str,dat: &[u8]) -> Result<usize,DYNERR> {
+        self.write_into(path,None,dat)
+    }
+    fn patch(&mut self,path: &str,offset: usize,dat: &[u8]) -> Result<usize,DYNERR> {
+        self.write_into(path,Some(offset),dat)
+    }
+    fn truncate(&mut self,path: &str,len: usize) -> Result<usize,DYNERR> {
+        let (mut loc,mut entry) = self.find_writable_file(path)?;
+        if len > entry.eof() {
+            error!("cannot truncate {} bytes to {}",entry.eof(),len);
+            return Err(Box::new(Error::InvalidSwitch));
+        }
+        let block_size = self.boot_sector.block_size() as usize;
+        let chain = self.cluster_chain(entry.cluster1(self.typ))?;
+        let keep = len.div_ceil(block_size);
+        if keep > chain.len() {
+            error!("cluster chain is shorter than the file size");
+            return Err(Box::new(Error::BadFAT));
+        }
+        let snap = super::DiskFS::snapshot(self);
+        let result = match self.cut_chain(&mut entry,&chain,len) {
+            Ok(()) => self.finish_resize(&mut loc,&mut entry,len),
+            Err(e) => Err(e)
+        };
+        if result.is_err() {
+            super::DiskFS::rollback(self,&snap)?;
+        }
+        result
+    }
+    fn relabel(&mut self,label: &str) -> STDRESULT {
+        if !pack::is_label_valid(label,self.codepage) {
+            error!("FAT volume name invalid");
+            return Err(Box::new(Error::Syntax));
+        }
+        // the boot sector copy is only there if the BPB has a tail
+        let (base,ext) = pack::string_to_label_name(label,self.codepage);
+        if self.boot_sector.set_label([base.to_vec(),ext.to_vec()].concat().try_into().expect("label mismatch")) {
+            self.img.write_sector(0,0,1,&self.boot_sector.to_bytes())?;
+        }
+        let (_,mut dir) = self.get_root_dir()?;
+        let cluster1 = match self.typ {
+            32 => Some(Ptr::Cluster(self.boot_sector.root_dir_cluster1() as usize)),
+            _ => None
+        };
+        let (ptr,mut entry) = match dir.find_label_ptr() {
+            Some(ptr) => (ptr,dir.get_entry(&ptr)?),
+            None => {
+                let ptr = self.get_available_entry(&mut dir,&cluster1)?;
+                (ptr,Entry::create_label(label,self.now(),self.codepage))
+            }
+        };
+        entry.relabel(label,self.codepage);
+        entry.set_attr(directory::VOLUME_ID | directory::ARCHIVE);
+        let mut loc = EntryLocation { cluster1, entry: ptr, dir };
+        self.writeback_directory_entry(&mut loc,&entry)
+    }
+    fn set_serial(&mut self,serial: &str) -> STDRESULT {
+        let id = match u32::from_str_radix(&serial.replace('-',""),16) {
+            Ok(id) if serial.replace('-',"").len()==8 => id,
+            _ => {
+                error!("serial number should be 8 hex digits, e.g. 1234-ABCD");
+                return Err(Box::new(Error::Syntax));
+            }
+        };
+        if !self.boot_sector.set_vol_id(u32::to_le_bytes(id)) {
+            error!("boot sector has no place for a serial number");
+            return Err(Box::new(Error::IncorrectDOS));
+        }
+        self.img.write_sector(0,0,1,&self.boot_sector.to_bytes())
+    }
+    fn all_blocks(&mut self) -> Result<Vec<Block>,DYNERR> {
+        Ok((0..self.boot_sector.tot_sec()).map(|s| Block::FAT((s,1))).collect())
+    }
+    fn rollback(&mut self,snap: &img::Snapshot) -> STDRESULT {
+        self.img.rollback(snap)?;
+        self.maybe_fat = None;
+        Ok(())
+    }
     fn get_img(&mut self) -> &mut Box<dyn img::DiskImage> {
         self.writeback_fat_buffer().expect("could not write back FAT buffer");
         &mut self.img
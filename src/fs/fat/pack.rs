@@ -8,22 +8,20 @@ use a2kit_macro::DiskStruct;
 use super::Packer;
 use super::super::{FileImage,Packing,UnpackedData};
 use super::types::{SequentialText,Error};
+use super::codepage::{self,CodePage};
 use crate::{STDRESULT,DYNERR};
 
 /// Characters forbidden from file names
 pub const INVALID_CHARS: &str = "\"*+,./:;<=>?[\\]|";
 pub const DOT: ([u8;8],[u8;3]) = ([b'.',32,32,32,32,32,32,32],[32,32,32]);
 pub const DOTDOT: ([u8;8],[u8;3]) = ([b'.',b'.',32,32,32,32,32,32],[32,32,32]);
-// TODO: how do we support old style extended character sets?
-// For Kanji, if first name byte is 0x05 replace with extended character 0xe5.
-//const KANJI: u8 = 0x05;
 
 /// pack the date into the FAT format, if the year is not between 1980
 /// and 2107 it will be pegged to the nearest representable date.
 pub fn pack_date(time: Option<chrono::NaiveDateTime>) -> [u8;2] {
     let now = match time {
         Some(t) => t,
-        _ => chrono::Local::now().naive_local()
+        _ => crate::now(false)
     };
     let year = match now.year() {
         y if y < 1980 => {
@@ -44,7 +42,7 @@ pub fn pack_date(time: Option<chrono::NaiveDateTime>) -> [u8;2] {
 pub fn pack_time(time: Option<chrono::NaiveDateTime>) -> [u8;2] {
     let now = match time {
         Some(t) => t,
-        _ => chrono::Local::now().naive_local()
+        _ => crate::now(false)
     };
 
     let ans16 = (now.second() as u16) / 2 + ((now.minute() as u16) << 5) + ((now.hour() as u16) << 11);
@@ -54,7 +52,7 @@ pub fn pack_time(time: Option<chrono::NaiveDateTime>) -> [u8;2] {
 pub fn pack_tenths(time: Option<chrono::NaiveDateTime>) -> u8 {
     let now = match time {
         Some(t) => t,
-        _ => chrono::Local::now().naive_local()
+        _ => crate::now(false)
     };
     (now.and_utc().timestamp_subsec_millis() / 100) as u8 + 10*(now.second() % 2) as u8
 }
@@ -79,8 +77,9 @@ pub fn unpack_time(fat_time: [u8;2],tenths: u8) -> Option<chrono::NaiveTime> {
 }
 
 /// Accepts lower case, case is raised by string_to_file_name.
+/// Characters outside ASCII are accepted if they are in the given code page.
 /// "." and ".." are not accepted here.
-pub fn is_name_valid(s: &str) -> bool {
+pub fn is_name_valid(s: &str, cp: CodePage) -> bool {
     let it: Vec<&str> = s.split('.').collect();
     if it.len()>2 {
         return false;
@@ -90,18 +89,17 @@ pub fn is_name_valid(s: &str) -> bool {
         1 => "",
         _ => it[1]
     };
-    // TODO: handle extended chars like KANJI
     for char in [base,ext].concat().chars() {
-        if !char.is_ascii() || INVALID_CHARS.contains(char) || char.is_ascii_control() {
+        if cp.encode_char(char).is_none() || INVALID_CHARS.contains(char) || char.is_ascii_control() {
             log::debug!("bad file name character `{}` (codepoint {})",char,char as u32);
             return false;
         }
     }
-    if base.len()<1 || base.len()>8 {
-        log::info!("base name length {} out of range",base.len());
+    if base.chars().count()<1 || base.chars().count()>8 {
+        log::info!("base name length {} out of range",base.chars().count());
         return false;
     }
-    if ext.len()>3 {
+    if ext.chars().count()>3 {
         log::info!("extension name too long, max 3");
         return false;
     }
@@ -111,7 +109,7 @@ pub fn is_name_valid(s: &str) -> bool {
 /// Test the string for validity as a FAT file path.
 /// Directory paths ending with `/` are rejected.
 /// Checks each path segment, and length of overall path.
-pub fn is_path_valid(path: &str) -> bool {
+pub fn is_path_valid(path: &str, cp: CodePage) -> bool {
     // if path.len() > 128 {
     //     log::error!("FAT path is too long");
     //     return false;
@@ -121,7 +119,7 @@ pub fn is_path_valid(path: &str) -> bool {
         iter.next();
     }
     while let Some(segment) = iter.next() {
-        if !is_name_valid(segment) {
+        if !is_name_valid(segment,cp) {
             return false;
         }
     }
@@ -129,14 +127,13 @@ pub fn is_path_valid(path: &str) -> bool {
 }
 
 /// Same as is_name_valid except dot is not needed or allowed
-pub fn is_label_valid(s: &str) -> bool {
-    if s.len()<1 || s.len()>11 {
-        log::info!("label length {} out of range",s.len());
+pub fn is_label_valid(s: &str, cp: CodePage) -> bool {
+    if s.chars().count()<1 || s.chars().count()>11 {
+        log::info!("label length {} out of range",s.chars().count());
         return false;
     }
-    // TODO: handle extended chars like KANJI
     for char in s.chars() {
-        if !char.is_ascii() || INVALID_CHARS.contains(char) || char.is_ascii_control() {
+        if cp.encode_char(char).is_none() || INVALID_CHARS.contains(char) || char.is_ascii_control() {
             log::debug!("bad file name character `{}` (codepoint {})",char,char as u32);
             return false;
         }
@@ -144,119 +141,97 @@ pub fn is_label_valid(s: &str) -> bool {
     true
 }
 
-/// Convert label bytes to an ASCII string.
-/// Will not panic, will escape the string if necessary.
-pub fn label_to_string(label: [u8;11]) -> String {
-    match String::from_utf8(label.to_vec()) {
-        Ok(l) => l.trim_end().to_string(),
-        _ => {
-            log::warn!("escaping invalid filename");
-            crate::escaped_ascii_from_bytes(&label.to_vec(), true, false).trim_end().to_string()
-        }
+/// Convert label bytes to a string using the given code page.
+pub fn label_to_string(label: [u8;11], cp: CodePage) -> String {
+    cp.decode(&label).trim_end().to_string()
+}
+
+/// Decode the base name, a first byte that is the stand-in for 0xe5 is decoded as 0xe5.
+fn decode_base_name(mut name: [u8;8], cp: CodePage) -> String {
+    if name[0]==codepage::E5_STANDIN {
+        name[0] = 0xe5;
     }
+    cp.decode(&name).trim_end().to_string()
 }
 
-/// Convert filename bytes to an ASCII string.
+/// Convert filename bytes to a string using the given code page.
 /// Dot and DotDot are specially handled.
-/// Will not panic, will escape the string if necessary.
-pub fn file_name_to_string(name: [u8;8], typ: [u8;3]) -> String {
+pub fn file_name_to_string(name: [u8;8], typ: [u8;3], cp: CodePage) -> String {
     match (name,typ) {
         DOT => ".".to_string(),
         DOTDOT => "..".to_string(),
-        _ => match (String::from_utf8(name.to_vec()),String::from_utf8(typ.to_vec())) {
-            (Ok(b),Ok(x)) => [b.trim_end(),".",x.trim_end()].concat(),
-            _ => {
-                log::warn!("escaping invalid filename");
-                [
-                    crate::escaped_ascii_from_bytes(&name.to_vec(), true, false).trim_end(),
-                    ".",
-                    crate::escaped_ascii_from_bytes(&typ.to_vec(), true, false).trim_end()
-                ].concat()
-            }
-        }
+        _ => [decode_base_name(name,cp).as_str(),".",cp.decode(&typ).trim_end()].concat()
     }
 }
 
-/// Put the filename bytes as a split ASCII string (name,type).
+/// Put the filename bytes as a split string (name,type) using the given code page.
 /// Dot and DotDot are specially handled.
-/// Will not panic, will escape the string if necessary.
-pub fn file_name_to_split_string(name: [u8;8],typ: [u8;3]) -> (String,String) {
+pub fn file_name_to_split_string(name: [u8;8],typ: [u8;3],cp: CodePage) -> (String,String) {
     match (name,typ) {
         DOT => (".".to_string(),"".to_string()),
         DOTDOT => ("..".to_string(),"".to_string()),
-        _ => match (String::from_utf8(name.to_vec()),String::from_utf8(typ.to_vec())) {
-            (Ok(b),Ok(x)) => (b.trim_end().to_string(),x.trim_end().to_string()),
-            _ => {
-                log::warn!("escaping invalid filename");
-                (
-                    crate::escaped_ascii_from_bytes(&name.to_vec(), true, false).trim_end().to_string(),
-                    crate::escaped_ascii_from_bytes(&typ.to_vec(), true, false).trim_end().to_string()
-                )
-            }
-        }
+        _ => (decode_base_name(name,cp),cp.decode(&typ).trim_end().to_string())
     }
 }
 
-/// Convert string to name and type bytes for directory.
+/// Encode a name fragment using the given code page, padding with spaces.
+/// Characters that are not in the code page become `_`.
+fn encode_fragment<const N: usize>(s: &str, cp: CodePage) -> [u8;N] {
+    let mut ans = [0x20;N];
+    for (i,c) in s.chars().take(N).enumerate() {
+        ans[i] = cp.encode_char(c).unwrap_or(b'_');
+    }
+    ans
+}
+
+/// Convert string to name and type bytes for directory, using the given code page.
 /// Dot and DotDot are specially handled.
+/// A base name starting with 0xe5 is stored starting with the stand-in, since 0xe5 marks a free entry.
 /// Assumes string contains a valid filename.
-pub fn string_to_file_name(s: &str) -> ([u8;8],[u8;3]) {
+pub fn string_to_file_name(s: &str, cp: CodePage) -> ([u8;8],[u8;3]) {
     if s=="." {
         return DOT;
     }
     if s==".." {
         return DOTDOT;
     }
-    let mut ans: ([u8;8],[u8;3]) = ([0;8],[0;3]);
-    let upper = s.to_uppercase();
+    let upper = cp.to_upper(s);
     let it: Vec<&str> = upper.split('.').collect();
-    let base = it[0].as_bytes().to_vec();
     let ext = match it.len() {
-        1 => Vec::new(),
-        _ => it[1].as_bytes().to_vec()
+        1 => "",
+        _ => it[1]
     };
-    for i in 0..8 {
-        if i<base.len() {
-            ans.0[i] = base[i];
-        } else {
-            ans.0[i] = 0x20;
-        }
+    let mut name = encode_fragment::<8>(it[0],cp);
+    if name[0]==0xe5 {
+        name[0] = codepage::E5_STANDIN;
     }
-    for i in 0..3 {
-        if i<ext.len() {
-            ans.1[i] = ext[i];
-        } else {
-            ans.1[i] = 0x20;
-        }
-    }
-    return ans;
+    (name,encode_fragment::<3>(ext,cp))
 }
 
-/// Convert label string to name and type bytes for directory.
+/// Convert label string to name and type bytes for directory, using the given code page.
 /// Assumes string contains a valid label name.
-pub fn string_to_label_name(s: &str) -> ([u8;8],[u8;3]) {
-    let mut ans: ([u8;8],[u8;3]) = ([0;8],[0;3]);
-    let upper = s.to_uppercase().as_bytes().to_vec();
-    for i in 0..8 {
-        if i<upper.len() {
-            ans.0[i] = upper[i];
-        } else {
-            ans.0[i] = 0x20;
-        }
-    }
-    for i in 8..11 {
-        if i<upper.len() {
-            ans.1[i-8] = upper[i];
-        } else {
-            ans.1[i-8] = 0x20;
-        }
+pub fn string_to_label_name(s: &str, cp: CodePage) -> ([u8;8],[u8;3]) {
+    let upper = cp.to_upper(s);
+    let base: String = upper.chars().take(8).collect();
+    let ext: String = upper.chars().skip(8).collect();
+    (encode_fragment::<8>(&base,cp),encode_fragment::<3>(&ext,cp))
+}
+
+/// Same as `string_to_label_name`, except a label starting with 0xe5 is stored starting with the stand-in,
+/// as is needed for the label's directory entry, but not for the copy in the boot sector.
+pub fn string_to_label_entry(s: &str, cp: CodePage) -> ([u8;8],[u8;3]) {
+    let (mut name,ext) = string_to_label_name(s,cp);
+    if name[0]==0xe5 {
+        name[0] = codepage::E5_STANDIN;
     }
-    return ans;
+    (name,ext)
 }
 
 impl Packer {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            codepage: CodePage::default()
+        }
     }
     fn verify(fimg: &FileImage) -> STDRESULT {
         if &fimg.file_system != super::FS_NAME {
@@ -269,7 +244,7 @@ impl Packer {
 impl Packing for Packer {
 
     fn set_path(&self, fimg: &mut FileImage, path: &str) -> STDRESULT {
-        if is_path_valid(path) {
+        if is_path_valid(path,self.codepage) {
             fimg.full_path = path.to_string();
             Ok(())
         } else {
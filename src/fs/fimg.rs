@@ -1,13 +1,17 @@
+// library-safe, see `crate::fs` for the policy
+#![deny(clippy::panic,clippy::unwrap_used,clippy::expect_used)]
+
 
 use std::str::FromStr;
-use std::collections::{BTreeMap,HashMap};
+use std::collections::HashMap;
 use super::{FileImage,Packing,UnpackedData,Records,Error};
-use super::{cpm,dos3x,fat,pascal,prodos};
+use super::{cpm,dos3x,fat,pascal,prodos,rdos};
 use crate::commands::ItemType;
 use crate::{STDRESULT,DYNERR};
 
 const A2_DOS: &str = "a2 dos";
 const A2_PASCAL: &str = "a2 pascal";
+const A2_RDOS: &str = "a2 rdos";
 const PRODOS: &str = "prodos";
 const CPM: &str = "cpm";
 const FAT: &str = "fat";
@@ -16,20 +20,31 @@ impl FileImage {
     pub fn fimg_version() -> String {
         "2.1.0".to_string()
     }
-    /// the string slices must be in the form X.Y.Z or else we panic
-    pub fn version_tuple(vers: &str) -> (usize,usize,usize) {
-        let v: Vec<usize> = vers.split(".").map(|s| usize::from_str(s).expect("bad version format")).collect();
-        (v[0],v[1],v[2])
+    /// the string slices must be in the form X.Y.Z or else we get an error
+    pub fn version_tuple(vers: &str) -> Result<(usize,usize,usize),Error> {
+        let v = vers.split(".").map(usize::from_str).collect::<Result<Vec<usize>,_>>();
+        match v.as_deref() {
+            Ok([x,y,z]) => Ok((*x,*y,*z)),
+            _ => {
+                log::error!("bad version format {}",vers);
+                Err(Error::FileImageFormat)
+            }
+        }
     }
     pub fn ordered_indices(&self) -> Vec<usize> {
-        let copy = self.chunks.clone();
-        let mut idx_list = copy.into_keys().collect::<Vec<usize>>();
+        let mut idx_list = self.chunks.keys().copied().collect::<Vec<usize>>();
         idx_list.sort_unstable();
         return idx_list;
     }
+    /// Borrow the chunks in order, pairing each with its chunk number
+    pub fn ordered_chunks(&self) -> Vec<(usize,&[u8])> {
+        let mut ans = self.chunks.iter().map(|(idx,v)| (*idx,v.as_slice())).collect::<Vec<(usize,&[u8])>>();
+        ans.sort_unstable_by_key(|(idx,_)| *idx);
+        ans
+    }
     /// Find the logical number of chunks (assuming indexing from 0..end)
     pub fn end(&self) -> usize {
-        match self.ordered_indices().pop() {
+        match self.chunks.keys().max() {
             Some(idx) => idx+1,
             None => 0
         }
@@ -59,20 +74,21 @@ impl FileImage {
     }
     /// pack the data sequentially, all structure is lost
     pub fn sequence(&self) -> Vec<u8> {
-        let mut ans: Vec<u8> = Vec::new();
-        for chunk in self.ordered_indices() {
-            match self.chunks.get(&chunk) {
-                Some(v) => ans.append(&mut v.clone()),
-                _ => panic!("unreachable")
-            };
+        let mut ans: Vec<u8> = Vec::with_capacity(self.chunks.values().map(|v| v.len()).sum());
+        for (_,v) in self.ordered_chunks() {
+            ans.extend_from_slice(v);
         }
         return ans;
     }
     /// pack the data sequentially, all structure is lost
     pub fn sequence_limited(&self,max_len: usize) -> Vec<u8> {
-        let mut ans = self.sequence();
-        if max_len < ans.len() {
-            ans = ans[0..max_len].to_vec();
+        let mut ans: Vec<u8> = Vec::new();
+        for (_,v) in self.ordered_chunks() {
+            if ans.len() + v.len() >= max_len {
+                ans.extend_from_slice(&v[0..max_len-ans.len()]);
+                break;
+            }
+            ans.extend_from_slice(v);
         }
         return ans;
     }
@@ -157,12 +173,16 @@ impl FileImage {
     pub fn from_json(json_str: &str) -> Result<FileImage,DYNERR> {
         let parsed = json::parse(json_str)?;
         let fimg_version = FileImage::parse_str("fimg_version",&parsed)?;
-        let vers_tup = Self::version_tuple(&fimg_version);
+        let vers_tup = Self::version_tuple(&fimg_version)?;
         if vers_tup < (2,0,0) {
             log::error!("file image v2 or higher is required");
             return Err(Box::new(Error::FileFormat));
         }
         let fs = Self::parse_str("file_system",&parsed)?;
+        if ![A2_DOS,A2_PASCAL,A2_RDOS,PRODOS,CPM,FAT].contains(&fs.as_str()) {
+            log::error!("illegal file system in file image: {}",fs);
+            return Err(Box::new(Error::FileImageFormat));
+        }
         let chunk_len = Self::parse_usize("chunk_len", &parsed)?;
         let fs_type = Self::parse_hex_to_vec("fs_type",&parsed)?;
         let aux = Self::parse_hex_to_vec("aux",&parsed)?;
@@ -218,110 +238,122 @@ impl FileImage {
     }
     /// Put chunks into the JSON string representation
     pub fn to_json(&self,indent: Option<u16>) -> String {
-        let mut json_map = json::JsonValue::new_object();
-        let mut sorted : BTreeMap<usize,Vec<u8>> = BTreeMap::new();
-        for (c,v) in &self.chunks {
-            sorted.insert(*c,v.clone());
+        match indent {
+            Some(spaces) => json::stringify_pretty(self.to_json_value(), spaces),
+            None => json::stringify(self.to_json_value())
         }
-        for (c,v) in &sorted {
+    }
+    /// Put chunks into a JSON value, for collecting many file images without going through strings
+    pub fn to_json_value(&self) -> json::JsonValue {
+        let mut json_map = json::JsonValue::new_object();
+        for (c,v) in self.ordered_chunks() {
             json_map[c.to_string()] = json::JsonValue::String(hex::encode_upper(v));
         }
-        let ans = json::object! {
+        json::object! {
             fimg_version: self.fimg_version.clone(),
             file_system: self.file_system.clone(),
             chunk_len: self.chunk_len,
-            eof: hex::encode_upper(self.eof.clone()),
-            fs_type: hex::encode_upper(self.fs_type.clone()),
-            aux: hex::encode_upper(self.aux.clone()),
-            access: hex::encode_upper(self.access.clone()),
-            accessed: hex::encode_upper(self.accessed.clone()),
-            created: hex::encode_upper(self.created.clone()),
-            modified: hex::encode_upper(self.modified.clone()),
-            version: hex::encode_upper(self.version.clone()),
-            min_version: hex::encode_upper(self.min_version.clone()),
+            eof: hex::encode_upper(&self.eof),
+            fs_type: hex::encode_upper(&self.fs_type),
+            aux: hex::encode_upper(&self.aux),
+            access: hex::encode_upper(&self.access),
+            accessed: hex::encode_upper(&self.accessed),
+            created: hex::encode_upper(&self.created),
+            modified: hex::encode_upper(&self.modified),
+            version: hex::encode_upper(&self.version),
+            min_version: hex::encode_upper(&self.min_version),
             full_path: self.full_path.clone(),
             chunks: json_map
-        };
-        if let Some(spaces) = indent {
-            return json::stringify_pretty(ans, spaces);
-        } else {
-            return json::stringify(ans);
         }
     }
-    fn packer(&self) -> Box<dyn Packing> {
+    fn packer(&self) -> Result<Box<dyn Packing>,Error> {
         match self.file_system.as_str() {
-            A2_DOS => Box::new(dos3x::Packer::new()),
-            A2_PASCAL => Box::new(pascal::Packer::new()), 
-            PRODOS => Box::new(prodos::Packer::new()), 
-            CPM => Box::new(cpm::Packer::new()),
-            FAT => Box::new(fat::Packer::new()),
-            _ => panic!("illegal file system in file image")
+            A2_DOS => Ok(Box::new(dos3x::Packer::new())),
+            A2_PASCAL => Ok(Box::new(pascal::Packer::new())),
+            A2_RDOS => Ok(Box::new(rdos::Packer::new())),
+            PRODOS => Ok(Box::new(prodos::Packer::new())),
+            CPM => Ok(Box::new(cpm::Packer::new())),
+            FAT => Ok(Box::new(fat::Packer::new())),
+            _ => {
+                log::error!("illegal file system in file image: {}",self.file_system);
+                Err(Error::FileSystemMismatch)
+            }
         }
     }
     pub fn set_path(&mut self, path: &str) -> STDRESULT {
-        self.packer().set_path(self,path)
+        self.packer()?.set_path(self,path)
+    }
+    /// Keep the case of the letters in the file name when it is put.
+    /// File systems that do not keep the case ignore this, as does a file image with an unknown file system.
+    pub fn set_case(&mut self) {
+        if let Ok(packer) = self.packer() {
+            packer.set_case(self)
+        }
     }
-    /// Get load address for this file image, if applicable.
+    /// Get load address for this file image, if applicable, 0 if the file system is unknown.
     pub fn get_load_address(&self) -> u16 {
-        self.packer().get_load_address(self)
+        match self.packer() {
+            Ok(packer) => packer.get_load_address(self),
+            Err(_) => 0
+        }
     }
     /// automatically select an unpacking strategy based on the file image metadata
     pub fn unpack(&self) -> Result<UnpackedData,DYNERR> {
-        self.packer().unpack(self)
+        self.packer()?.unpack(self)
     }
     /// Pack raw byte stream into file image.
     /// Headers used by the file system are *not* automatically inserted.
     /// If the file system has explicit typing, the type is set to text.
     pub fn pack_raw(&mut self, dat: &[u8]) -> STDRESULT {
-        self.packer().pack_raw(self,dat)
+        self.packer()?.pack_raw(self,dat)
     }
     /// Get the raw bytestream, including any header used by the file system.
     /// The byte stream will extend to end of block unless `trunc==true`.
     /// Setting `trunc==true` only works if the EOF is stored in the directory.
     pub fn unpack_raw(&self,trunc: bool) -> Result<Vec<u8>,DYNERR> {
-        self.packer().unpack_raw(self,trunc)
+        self.packer()?.unpack_raw(self,trunc)
     }
     /// Pack bytes into file image, if file system uses a header it is added.
     /// The load address will be checked for validity, if not used by FS it must be None.
     pub fn pack_bin(&mut self,dat: &[u8],load_addr: Option<usize>,trailing: Option<&[u8]>) -> STDRESULT {
-        self.packer().pack_bin(self,dat,load_addr,trailing)
+        self.packer()?.pack_bin(self,dat,load_addr,trailing)
     }
     /// get bytes from file image, if file system uses a header it is stripped
     pub fn unpack_bin(&self) -> Result<Vec<u8>,DYNERR> {
-        self.packer().unpack_bin(self)
+        self.packer()?.unpack_bin(self)
     }
     /// Convert UTF8 with either LF or CRLF to the file system's text format.  This returns an error
     /// if the conversion would result in any loss of data.
     pub fn pack_txt(&mut self, txt: &str) -> STDRESULT {
-        self.packer().pack_txt(self,txt)
+        self.packer()?.pack_txt(self,txt)
     }
     /// Convert the file system's text format to UTF8 with LF.  This always succeeds because the underlying
     /// text converters will replace unknown characters with ASCII NULL.
     pub fn unpack_txt(&self) -> Result<String,DYNERR> {
-        self.packer().unpack_txt(self)
+        self.packer()?.unpack_txt(self)
     }
     /// pack language tokens into file image, if file system uses a header it is added
     pub fn pack_tok(&mut self,tok: &[u8],lang: ItemType,trailing: Option<&[u8]>) -> STDRESULT {
-        self.packer().pack_tok(self,tok,lang,trailing)
+        self.packer()?.pack_tok(self,tok,lang,trailing)
     }
     /// get language tokens from file image, if file system uses a header it is stripped
     pub fn unpack_tok(&self) -> Result<Vec<u8>,DYNERR> {
-        self.packer().unpack_tok(self)
+        self.packer()?.unpack_tok(self)
     }
     /// pack JSON representation of random access text into a file image
     pub fn pack_rec_str(&mut self, json: &str) -> STDRESULT {
-        self.packer().pack_rec_str(self, json)
+        self.packer()?.pack_rec_str(self, json)
     }
     /// get JSON representation of random access text
     pub fn unpack_rec_str(&self,rec_len: Option<usize>,indent: Option<u16>) -> Result<String,DYNERR> {
-        self.packer().unpack_rec_str(self, rec_len, indent)
+        self.packer()?.unpack_rec_str(self, rec_len, indent)
     }
     /// pack random access text records into a file image
     pub fn pack_rec(&mut self, recs: &Records) -> STDRESULT {
-        self.packer().pack_rec(self,recs)
+        self.packer()?.pack_rec(self,recs)
     }
     /// get random access text records
     pub fn unpack_rec(&self,rec_len: Option<usize>) -> Result<Records,DYNERR> {
-        self.packer().unpack_rec(self,rec_len)
+        self.packer()?.unpack_rec(self,rec_len)
     }
 }
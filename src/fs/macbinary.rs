@@ -0,0 +1,152 @@
+//! ## MacBinary
+//!
+//! MacBinary II stores a Macintosh file as a single stream, so it can live on a host that knows nothing of
+//! Finder information or forks.  There is a 128 byte header, followed by the data fork and the resource fork,
+//! each padded to a multiple of 128 bytes.
+//!
+//! ProDOS files are written the way the Macintosh does it, with creator `pdos` and file type `p` followed by
+//! the ProDOS type and the auxiliary type (big endian), so that the file can be put back without losing either.
+//! File images do not have a resource fork yet, so it is always written empty, and a MacBinary file that
+//! has one cannot be put.  Likewise a ProDOS file with a resource fork (storage type 5) cannot be gotten.
+
+use super::{FileImage,Error};
+use super::prodos::pack::{pack_time,unpack_time};
+use crate::{STDRESULT,DYNERR};
+
+const HEADER_LEN: usize = 128;
+const NAME_MAX: usize = 63;
+const PRODOS_CREATOR: &[u8;4] = b"pdos";
+/// version written to the header, and the minimum version needed to read it
+const VERSION: u8 = 129;
+
+/// offset of the fields in the header, multi-byte fields are big endian
+mod hdr {
+    pub const NAME_LEN: usize = 1;
+    pub const NAME: usize = 2;
+    pub const TYPE: usize = 65;
+    pub const CREATOR: usize = 69;
+    pub const DATA_LEN: usize = 83;
+    pub const RSRC_LEN: usize = 87;
+    pub const CREATED: usize = 91;
+    pub const MODIFIED: usize = 95;
+    pub const VERSION: usize = 122;
+    pub const MIN_VERSION: usize = 123;
+    pub const CRC: usize = 124;
+}
+
+/// CRC-16/XMODEM, which MacBinary II computes over the first 124 bytes of the header
+fn crc16(buf: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for b in buf {
+        crc ^= (*b as u16) << 8;
+        for _bit in 0..8 {
+            crc = (crc << 1) ^ match crc & 0x8000 { 0 => 0, _ => 0x1021 };
+        }
+    }
+    crc
+}
+
+/// Seconds since 1904, which is the Macintosh epoch, 0 if there is no time
+fn mac_time(time: Option<chrono::NaiveDateTime>) -> u32 {
+    let epoch = chrono::NaiveDate::from_ymd_opt(1904,1,1).unwrap().and_hms_opt(0,0,0).unwrap();
+    match time {
+        Some(t) => u32::try_from((t - epoch).num_seconds()).unwrap_or(0),
+        None => 0
+    }
+}
+
+fn from_mac_time(secs: u32) -> Option<chrono::NaiveDateTime> {
+    let epoch = chrono::NaiveDate::from_ymd_opt(1904,1,1).unwrap().and_hms_opt(0,0,0).unwrap();
+    match secs {
+        0 => None,
+        s => epoch.checked_add_signed(chrono::Duration::seconds(s as i64))
+    }
+}
+
+fn pad(dat: &mut Vec<u8>) {
+    dat.resize(dat.len().div_ceil(HEADER_LEN) * HEADER_LEN,0);
+}
+
+fn be32(dat: &[u8],ptr: usize) -> u32 {
+    u32::from_be_bytes([dat[ptr],dat[ptr+1],dat[ptr+2],dat[ptr+3]])
+}
+
+/// The modified time in the header of the MacBinary stream `dat`, if there is one.
+/// A file image does not carry times into ProDOS, so `put` gives this to the file explicitly.
+pub fn modified_time(dat: &[u8]) -> Option<chrono::NaiveDateTime> {
+    match dat.len() >= HEADER_LEN {
+        true => from_mac_time(be32(dat,hdr::MODIFIED)),
+        false => None
+    }
+}
+
+/// Write the file image as a MacBinary II stream, only ProDOS file images are supported.
+pub fn unpack(fimg: &FileImage) -> Result<Vec<u8>,DYNERR> {
+    if fimg.file_system != "prodos" {
+        log::error!("MacBinary is only supported for ProDOS files");
+        return Err(Box::new(Error::FileSystemMismatch));
+    }
+    let data_fork = fimg.unpack_raw(true)?;
+    let name = fimg.full_path.rsplit('/').next().unwrap_or("").to_uppercase();
+    let name = &name.as_bytes()[0..usize::min(name.len(),NAME_MAX)];
+    let typ = fimg.fs_type.first().copied().unwrap_or(0);
+    let [aux_lo,aux_hi] = u16::to_le_bytes(fimg.get_aux() as u16);
+    let mut ans = vec![0;HEADER_LEN];
+    ans[hdr::NAME_LEN] = name.len() as u8;
+    ans[hdr::NAME..hdr::NAME+name.len()].copy_from_slice(name);
+    ans[hdr::TYPE..hdr::TYPE+4].copy_from_slice(&[b'p',typ,aux_hi,aux_lo]);
+    ans[hdr::CREATOR..hdr::CREATOR+4].copy_from_slice(PRODOS_CREATOR);
+    ans[hdr::DATA_LEN..hdr::DATA_LEN+4].copy_from_slice(&u32::to_be_bytes(data_fork.len() as u32));
+    let times = [(hdr::CREATED,&fimg.created),(hdr::MODIFIED,&fimg.modified)];
+    for (ptr,time) in times {
+        if let Ok(packed) = <[u8;4]>::try_from(time.as_slice()) {
+            ans[ptr..ptr+4].copy_from_slice(&u32::to_be_bytes(mac_time(unpack_time(packed))));
+        }
+    }
+    ans[hdr::VERSION] = VERSION;
+    ans[hdr::MIN_VERSION] = VERSION;
+    let crc = crc16(&ans[0..hdr::CRC]);
+    ans[hdr::CRC..hdr::CRC+2].copy_from_slice(&u16::to_be_bytes(crc));
+    ans.extend_from_slice(&data_fork);
+    pad(&mut ans);
+    Ok(ans)
+}
+
+/// Put the data fork of a MacBinary stream into the file image, it is an error if there is a resource fork.  If the target is ProDOS and
+/// the Finder information came from ProDOS, the file type, auxiliary type, and times are restored,
+/// otherwise this is the same as packing the data fork as raw.
+pub fn pack(fimg: &mut FileImage,dat: &[u8]) -> STDRESULT {
+    if dat.len() < HEADER_LEN || dat[0] != 0 || dat[hdr::NAME_LEN] as usize > NAME_MAX || dat[hdr::NAME_LEN] == 0 {
+        log::error!("MacBinary header is not valid");
+        return Err(Box::new(Error::FileFormat));
+    }
+    let crc = u16::from_be_bytes([dat[hdr::CRC],dat[hdr::CRC+1]]);
+    if dat[hdr::VERSION] >= VERSION && crc != crc16(&dat[0..hdr::CRC]) {
+        log::error!("MacBinary header has a bad CRC");
+        return Err(Box::new(Error::FileFormat));
+    }
+    let data_len = be32(dat,hdr::DATA_LEN) as usize;
+    if HEADER_LEN + data_len > dat.len() {
+        log::error!("MacBinary data fork runs past the end of the stream");
+        return Err(Box::new(Error::FileFormat));
+    }
+    if be32(dat,hdr::RSRC_LEN) > 0 {
+        log::error!("MacBinary file has a resource fork, files with forks are not supported");
+        return Err(Box::new(Error::FileFormat));
+    }
+    fimg.pack_raw(&dat[HEADER_LEN..HEADER_LEN+data_len])?;
+    let typ = &dat[hdr::TYPE..hdr::TYPE+4];
+    if fimg.file_system == "prodos" && typ[0] == b'p' && &dat[hdr::CREATOR..hdr::CREATOR+4] == PRODOS_CREATOR {
+        fimg.fs_type = vec![typ[1]];
+        fimg.aux = vec![typ[3],typ[2]];
+        let times = [(hdr::CREATED,&mut fimg.created),(hdr::MODIFIED,&mut fimg.modified)];
+        for (ptr,time) in times {
+            if let Some(t) = from_mac_time(be32(dat,ptr)) {
+                *time = pack_time(Some(t)).to_vec();
+            }
+        }
+    } else {
+        log::warn!("file type {} is not from ProDOS, packing as raw",String::from_utf8_lossy(typ));
+    }
+    Ok(())
+}
@@ -0,0 +1,112 @@
+//! # Migrate DOS 3.3 files to ProDOS
+//!
+//! This does what the DOS-ProDOS conversion utility did, copying every file from a DOS 3.x volume
+//! to the root directory of a ProDOS volume.  Types are mapped as follows:
+//! * Applesoft becomes `BAS` with the load address as the auxiliary type
+//! * Integer BASIC becomes `INT`
+//! * Binary becomes `BIN` with the load address as the auxiliary type, the DOS header is removed
+//! * Text becomes `TXT`, with the high bits cleared, random access files keep their holes, the
+//!   record length is unknown so the auxiliary type is 0
+//! * S, R, and the second A and B types have no counterpart, they are copied as stored with the
+//!   user defined types `$F1` through `$F4`
+//!
+//! Names are made valid for ProDOS, see `prodos_name`, and locked files stay locked.
+
+use super::{DiskFS,FileImage};
+use crate::commands::ItemType;
+use crate::DYNERR;
+
+const PRODOS_BLOCK: usize = 512;
+const DOS_SECTOR: usize = 256;
+
+/// Record of one file that was migrated
+pub struct Migrated {
+    pub dos_name: String,
+    pub prodos_name: String,
+    /// DOS type without the lock bit
+    pub dos_type: u8,
+    pub prodos_type: u8,
+    pub aux: usize,
+    pub locked: bool
+}
+
+/// Form a valid ProDOS name from a DOS name, not equal to any in `taken`.
+/// Characters ProDOS does not allow become periods, an `A` is put in front if the
+/// name does not start with a letter, and the name is cut to 15 characters.
+/// If the name is taken, the end is replaced by a number.
+pub fn prodos_name(dos_name: &str,taken: &[String]) -> String {
+    super::names::valid_name(super::prodos::FS_NAME,dos_name,taken)
+}
+
+/// Re-code DOS text, which may be a random access file with holes, for ProDOS.
+/// The ProDOS file image must have been started with `pack_raw`.
+fn copy_text(src: &FileImage,dst: &mut FileImage) {
+    let mut eof = 0;
+    for (idx,chunk) in &src.chunks {
+        let block = dst.chunks.entry(idx * DOS_SECTOR / PRODOS_BLOCK).or_insert(vec![0;PRODOS_BLOCK]);
+        let offset = (idx * DOS_SECTOR) % PRODOS_BLOCK;
+        for (i,byte) in chunk.iter().enumerate() {
+            block[offset + i] = byte & 0x7f;
+            if *byte > 0 {
+                eof = usize::max(eof,idx * DOS_SECTOR + i + 1);
+            }
+        }
+    }
+    // ProDOS always allocates the first block
+    dst.chunks.entry(0).or_insert(vec![0;PRODOS_BLOCK]);
+    dst.set_eof(eof);
+}
+
+/// Copy every file from the DOS volume `src` to the root of the ProDOS volume `dst`
+pub fn migrate(src: &mut Box<dyn DiskFS>,dst: &mut Box<dyn DiskFS>) -> Result<Vec<Migrated>,DYNERR> {
+    let mut ans: Vec<Migrated> = Vec::new();
+    for item in src.catalog_items("")? {
+        let taken: Vec<String> = ans.iter().map(|m| m.prodos_name.clone()).collect();
+        let name = prodos_name(&item.name,&taken);
+        let fimg = src.get(&item.name)?;
+        let dos_type = fimg.fs_type[0] & 0x7f;
+        log::debug!("migrate {} to {}",item.name,name);
+        match dos_type {
+            0x00 => {
+                let mut out = dst.new_fimg(None,true,&name)?;
+                out.pack_raw(&[])?;
+                copy_text(&fimg,&mut out);
+                dst.put(&out)?;
+            },
+            0x01 => {
+                dst.save(&name,&fimg.unpack_tok()?,ItemType::IntegerTokens,None)?;
+            },
+            0x02 => {
+                dst.save(&name,&fimg.unpack_tok()?,ItemType::ApplesoftTokens,None)?;
+            },
+            0x04 => {
+                dst.bsave(&name,&fimg.unpack_bin()?,Some(fimg.get_load_address() as usize),None)?;
+            },
+            _ => {
+                let mut out = dst.new_fimg(None,true,&name)?;
+                out.pack_raw(&fimg.sequence())?;
+                out.fs_type = vec![match dos_type {
+                    0x08 => 0xf1,
+                    0x10 => 0xf2,
+                    0x20 => 0xf3,
+                    _ => 0xf4
+                }];
+                dst.put(&out)?;
+            }
+        }
+        let locked = fimg.fs_type[0] & 0x80 > 0;
+        if locked {
+            dst.lock(&name)?;
+        }
+        let out = dst.get(&name)?;
+        ans.push(Migrated {
+            dos_name: item.name,
+            prodos_name: name,
+            dos_type,
+            prodos_type: out.fs_type[0],
+            aux: out.get_aux(),
+            locked
+        });
+    }
+    Ok(ans)
+}
@@ -18,12 +18,27 @@
 //! Sector skews are not handled here.  Transformation of a `Block` to a physical disk address is
 //! handled within the `img` module.  Transformations that go between a file system and a disk,
 //! such as sector skews, are kept in the `bios` module.
+//!
+//! Whatever is on the disk, or in a file image, should produce an error rather than a panic.
+//! Modules that are held to this are marked library-safe, and deny `panic!`, `unwrap`, and `expect`
+//! via clippy.  So far these are `fimg`, `cpm::directory`, `cpm::pack`, and `fat::directory`,
+//! the rest can still panic on malformed input.  A panic is still allowed anywhere for a caller
+//! breaking the contract of a function, such as asking to create an unsupported kind of disk,
+//! in which case the function says so.
 
 pub mod dos3x;
 pub mod prodos;
 pub mod pascal;
 pub mod cpm;
 pub mod fat;
+pub mod rdos;
+pub mod carve;
+pub mod migrate;
+pub mod macbinary;
+pub mod names;
+pub mod quirks;
+pub mod render;
+pub mod tree;
 mod fimg;
 mod recs;
 
@@ -41,7 +56,9 @@ pub enum Error {
     #[error("file image format is wrong")]
     FileImageFormat,
     #[error("high level file format is wrong")]
-    FileFormat
+    FileFormat,
+    #[error("file already exists")]
+    FileExists
 }
 
 pub enum UnpackedData {
@@ -57,7 +74,7 @@ pub enum UnpackedData {
 /// Disk images can also decide whether to immediately return an error given certain block types; e.g., a PO image might refuse
 /// to locate a DO block type.  However, do not get confused, e.g., a DO image should usually be prepared to process a
 /// PO block, since there are many ProDOS DSK images that are DOS ordered.
-#[derive(PartialEq,Eq,Clone,Copy,Hash)]
+#[derive(PartialEq,Eq,Clone,Copy,Hash,Debug)]
 pub enum Block {
     /// value is [track,sector]
     D13([usize;2]),
@@ -147,6 +164,327 @@ fn universal_row(typ: &str, blocks: usize, name: &str) -> String {
     format!("{:4} {:5}  {}",typ,blocks,name)
 }
 
+/// Substitute a file name into a CP/M or MS-DOS style wildcard, as when renaming with wildcards.
+/// The base name and extension are handled separately: `?` keeps the character in the same position,
+/// `*` keeps the rest of the field, and anything else is taken literally.
+pub fn wildcard_substitute(name: &str,pattern: &str) -> String {
+    let split = |s: &str| -> (String,String) {
+        match s.rsplit_once('.') {
+            Some((base,ext)) => (base.to_string(),ext.to_string()),
+            None => (s.to_string(),String::new())
+        }
+    };
+    let field = |old: &str,pat: &str| -> String {
+        let old: Vec<char> = old.chars().collect();
+        let mut ans = String::new();
+        for (i,c) in pat.chars().enumerate() {
+            match c {
+                '*' => {
+                    ans += &old.iter().skip(i).collect::<String>();
+                    break;
+                },
+                '?' => if let Some(keep) = old.get(i) {
+                    ans.push(*keep);
+                },
+                _ => ans.push(c)
+            }
+        }
+        ans
+    };
+    let (base,ext) = split(name);
+    let (pat_base,pat_ext) = split(pattern);
+    let new_base = field(&base,&pat_base);
+    let new_ext = field(&ext,&pat_ext);
+    match new_ext.len() {
+        0 => new_base,
+        _ => [new_base,".".to_string(),new_ext].concat()
+    }
+}
+
+/// What to do when a file is put where one already exists
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum IfExists {
+    /// delete the old file, then write the new one, the old file is kept if the write fails
+    Overwrite,
+    /// leave the old file alone and do not write
+    Skip,
+    /// write the new file under a numbered name, see `numbered_name`
+    Rename,
+    Error
+}
+
+impl std::str::FromStr for IfExists {
+    type Err = crate::commands::CommandError;
+    fn from_str(s: &str) -> Result<Self,Self::Err> {
+        match s {
+            "overwrite" => Ok(Self::Overwrite),
+            "skip" => Ok(Self::Skip),
+            "rename" => Ok(Self::Rename),
+            "error" => Ok(Self::Error),
+            _ => Err(crate::commands::CommandError::InvalidCommand)
+        }
+    }
+}
+
+/// Put `num` at the end of the base name in `path`, e.g. `/DIR/HELLO.S` becomes `/DIR/HELLO1.S`.
+/// The last `trim` characters of the base name are dropped first, for file systems with short names.
+pub fn numbered_name(path: &str,num: usize,trim: usize) -> String {
+    let split = path.rfind(['/',':']).map(|i| i+1).unwrap_or(0);
+    let name = &path[split..];
+    let (base,ext) = match name.rfind('.') {
+        Some(i) if i>0 => (&name[0..i],&name[i..]),
+        _ => (name,"")
+    };
+    let keep = base.chars().count().saturating_sub(trim);
+    let base: String = base.chars().take(keep).collect();
+    [&path[0..split],&base,&num.to_string(),ext].concat()
+}
+
+/// Pair each path in `matches` with its new path, found by substituting the final component into `new_pattern`.
+/// Components are separated by `/` or `:`, and names are compared without regard to case.
+/// Files that would keep their name are left out.  If two files would get the same name, or a new name
+/// belongs to any file in `existing`, an error is returned.
+pub fn wildcard_renames(matches: &[String],new_pattern: &str,existing: &[String]) -> Result<Vec<(String,String)>,DYNERR> {
+    let mut ans: Vec<(String,String)> = Vec::new();
+    let mut taken: Vec<String> = existing.iter().map(|x| x.to_uppercase()).collect();
+    for old in matches {
+        let split = old.rfind(['/',':']).map(|i| i+1).unwrap_or(0);
+        let new = [&old[0..split],&wildcard_substitute(&old[split..],new_pattern)].concat();
+        if new.to_uppercase() == old.to_uppercase() {
+            continue;
+        }
+        if taken.contains(&new.to_uppercase()) {
+            log::error!("cannot rename {} to {}, the name is taken",old,new);
+            return Err(Box::new(Error::FileExists));
+        }
+        taken.push(new.to_uppercase());
+        ans.push((old.to_string(),new));
+    }
+    Ok(ans)
+}
+
+/// One row of a directory listing along with the keys it can be sorted on
+pub struct CatalogItem {
+    /// row in the format of `DiskFS::catalog_to_vec`
+    pub row: String,
+    /// name as it appears in the row
+    pub name: String,
+    /// last modification, if the file system records it
+    pub modified: Option<chrono::NaiveDateTime>,
+    /// first allocation unit of the file, in the file system's own units
+    pub addr: usize,
+    /// type or extension as it appears in the row
+    pub typ: String,
+    /// size as it appears in the row, in the file system's own units
+    pub blocks: usize
+}
+
+/// Order of the rows in a directory listing
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum CatalogOrder {
+    /// order of the entries in the directory
+    Directory,
+    Name,
+    /// oldest first, undated files go last
+    Date,
+    Address,
+    /// largest first
+    Size
+}
+
+impl std::str::FromStr for CatalogOrder {
+    type Err = crate::commands::CommandError;
+    fn from_str(s: &str) -> Result<Self,Self::Err> {
+        match s {
+            "name" => Ok(Self::Name),
+            "date" => Ok(Self::Date),
+            "address" => Ok(Self::Address),
+            "size" => Ok(Self::Size),
+            _ => Err(crate::commands::CommandError::InvalidCommand)
+        }
+    }
+}
+
+/// Sort directory listing items, the sort is stable so ties are left in directory order
+pub fn sort_catalog(items: &mut [CatalogItem],order: CatalogOrder) {
+    match order {
+        CatalogOrder::Directory => {},
+        CatalogOrder::Name => items.sort_by_key(|x| x.name.to_uppercase()),
+        CatalogOrder::Date => items.sort_by(|a,b| match (a.modified,b.modified) {
+            (Some(x),Some(y)) => x.cmp(&y),
+            (x,y) => y.is_some().cmp(&x.is_some())
+        }),
+        CatalogOrder::Address => items.sort_by_key(|x| x.addr),
+        CatalogOrder::Size => items.sort_by_key(|x| std::cmp::Reverse(x.blocks))
+    }
+}
+
+/// Criteria for narrowing a directory listing, fields that are `None` match everything
+#[derive(Clone,Default,Debug)]
+pub struct CatalogFilter {
+    /// type or extension, case insensitive
+    pub typ: Option<String>,
+    /// keep files modified on or after this time, undated files are dropped
+    pub since: Option<chrono::NaiveDateTime>,
+    /// keep at most this many rows, applied after sorting
+    pub limit: Option<usize>
+}
+
+impl CatalogFilter {
+    /// Set a criterion from `key=value`, the only key so far is `type`
+    pub fn set(&mut self,key_value: &str) -> STDRESULT {
+        match key_value.split_once('=') {
+            Some(("type",val)) => {
+                self.typ = Some(val.to_uppercase());
+                Ok(())
+            },
+            _ => {
+                log::error!("filter should be `type=<TYPE>`");
+                Err(Box::new(crate::commands::CommandError::InvalidCommand))
+            }
+        }
+    }
+    /// Set `since` from `YYYY-MM-DD` or an RFC 3339 time
+    pub fn set_since(&mut self,date: &str) -> STDRESULT {
+        if let Ok(d) = chrono::NaiveDate::parse_from_str(date,"%Y-%m-%d") {
+            self.since = d.and_hms_opt(0,0,0);
+            return Ok(());
+        }
+        match chrono::DateTime::parse_from_rfc3339(date) {
+            Ok(t) => {
+                self.since = Some(t.naive_local());
+                Ok(())
+            },
+            Err(e) => {
+                log::error!("date should be YYYY-MM-DD or RFC 3339");
+                Err(Box::new(e))
+            }
+        }
+    }
+    /// True if every criterion matches everything
+    pub fn is_empty(&self) -> bool {
+        self.typ.is_none() && self.since.is_none() && self.limit.is_none()
+    }
+    fn is_match(&self,item: &CatalogItem) -> bool {
+        let typ_ok = self.typ.as_ref().is_none_or(|t| item.typ.to_uppercase()==*t);
+        let since_ok = match (self.since,item.modified) {
+            (None,_) => true,
+            (Some(t),Some(m)) => m >= t,
+            (Some(_),None) => false
+        };
+        typ_ok && since_ok
+    }
+}
+
+/// Directory listing in the file system's native style, taken apart so the rows can be
+/// sorted and filtered.  Each row is paired with the item for the same file.
+pub struct NativeCatalog {
+    pub header: String,
+    pub rows: Vec<(CatalogItem,String)>,
+    pub footer: String
+}
+
+impl NativeCatalog {
+    /// Pair native rows with the items from `DiskFS::catalog_items`, both must be in directory order
+    pub fn pair(header: String,items: Vec<CatalogItem>,rows: Vec<String>,footer: String) -> Result<Self,DYNERR> {
+        if items.len()!=rows.len() {
+            log::error!("native listing has {} rows, but there are {} items",rows.len(),items.len());
+            return Err(Box::new(Error::FileSystemMismatch));
+        }
+        Ok(Self { header, rows: items.into_iter().zip(rows).collect(), footer })
+    }
+    /// Put the listing back together, keeping only rows that pass the filter, in the given order
+    pub fn render(self,filter: &CatalogFilter,order: CatalogOrder) -> String {
+        // carry the native row in the item while filtering
+        let items = self.rows.into_iter().map(|(mut item,row)| { item.row = row; item }).collect();
+        let mut ans = self.header;
+        for item in filter_catalog(items,filter,order) {
+            ans += &item.row;
+            ans += "\n";
+        }
+        ans + &self.footer
+    }
+}
+
+/// Filter, sort, and truncate directory listing items
+pub fn filter_catalog(items: Vec<CatalogItem>,filter: &CatalogFilter,order: CatalogOrder) -> Vec<CatalogItem> {
+    let mut ans: Vec<CatalogItem> = items.into_iter().filter(|x| filter.is_match(x)).collect();
+    sort_catalog(&mut ans,order);
+    if let Some(n) = filter.limit {
+        ans.truncate(n);
+    }
+    ans
+}
+
+/// How names are compared with a glob pattern
+#[derive(Clone,Copy,Default,Debug)]
+pub struct GlobOptions {
+    pub case_sensitive: bool,
+    /// accented letters match the plain letter, and spaces and underscores match periods
+    pub fold: bool
+}
+
+/// How the directory tree is shaped by `DiskFS::tree_with`
+#[derive(Clone,Copy,Default,Debug)]
+pub struct TreeOptions {
+    pub meta: bool,
+    pub indent: Option<u16>,
+    /// levels of the tree to keep, 1 is the root directory only
+    pub depth: Option<usize>,
+    /// add up the files, bytes, and blocks within each directory
+    pub du: bool,
+    /// list the paths one per line rather than writing JSON
+    pub paths: bool
+}
+
+/// Glob pattern as every file system matches it.  Wildcards never match `/`, so that patterns
+/// behave the same whether or not the file system has directories.
+pub struct NameMatcher {
+    matcher: globset::GlobMatcher,
+    opt: GlobOptions
+}
+
+/// Map Latin-1 accented letters to the plain letter
+fn fold_accent(c: char) -> char {
+    match c {
+        'À'..='Å' => 'A', 'à'..='å' => 'a',
+        'Ç' => 'C', 'ç' => 'c',
+        'È'..='Ë' => 'E', 'è'..='ë' => 'e',
+        'Ì'..='Ï' => 'I', 'ì'..='ï' => 'i',
+        'Ñ' => 'N', 'ñ' => 'n',
+        'Ò'..='Ö' | 'Ø' => 'O', 'ò'..='ö' | 'ø' => 'o',
+        'Ù'..='Ü' => 'U', 'ù'..='ü' => 'u',
+        'Ý' => 'Y', 'ý' | 'ÿ' => 'y',
+        _ => c
+    }
+}
+
+impl NameMatcher {
+    pub fn new(pattern: &str,opt: GlobOptions) -> Result<Self,DYNERR> {
+        let mut ans = Self { matcher: globset::Glob::new("*")?.compile_matcher(), opt };
+        let pattern = ans.key(pattern);
+        ans.matcher = globset::GlobBuilder::new(&pattern).literal_separator(true).build()?.compile_matcher();
+        Ok(ans)
+    }
+    /// Form of a name or pattern that is actually compared
+    fn key(&self,s: &str) -> String {
+        let mut ans = match self.opt.fold {
+            true => s.chars().map(|c| match c {
+                ' ' | '_' => '.',
+                c => fold_accent(c)
+            }).collect(),
+            false => s.to_string()
+        };
+        if !self.opt.case_sensitive {
+            ans = ans.to_uppercase();
+        }
+        ans
+    }
+    pub fn is_match(&self,name: &str) -> bool {
+        self.matcher.is_match(self.key(name))
+    }
+}
+
 pub trait TextConversion {
     fn new(line_terminator: Vec<u8>) -> Self;
     /// Typical implementations will return Some(Vec) only if
@@ -185,6 +523,7 @@ pub trait TextConversion {
 /// Each `DiskFS` trait object provides its own routine for creating an empty file image.
 /// Buffer sizes should be set as appropriate for that FS.
 /// Unused metadata can be represented by an empty vector.
+#[derive(Clone)]
 pub struct FileImage {
     /// Version of the file image format, such as "2.0.0"
     pub fimg_version: String,
@@ -257,6 +596,10 @@ pub trait Packing {
     fn pack_rec(&self, fimg: &mut FileImage, recs: &Records) -> STDRESULT;
     /// turn the file image into random access text records
     fn unpack_rec(&self,fimg: &FileImage,rec_len: Option<usize>) -> Result<Records,DYNERR>;
+    /// Keep the case of the letters in the file name when it is put, for file systems that allow it (default method)
+    fn set_case(&self,_fimg: &mut FileImage) {
+        log::warn!("this file system does not keep the case of file names");
+    }
 }
 
 /// This is an abstraction used in handling random access text files.
@@ -293,18 +636,75 @@ pub trait DiskFS {
     fn new_fimg(&self, chunk_len: Option<usize>, set_time: bool, path: &str) -> Result<FileImage,DYNERR>;
     /// Stat the file system
     fn stat(&mut self) -> Result<Stat,DYNERR>;
-    /// Directory listing to standard output in the file system's native style
-    fn catalog_to_stdout(&mut self, path: &str) -> STDRESULT;
+    /// Directory listing in the file system's native style.
+    /// The default lists the generic rows of `catalog_to_vec`, one per line (default method).
+    fn catalog_to_string(&mut self, path: &str) -> Result<String,DYNERR> {
+        Ok(self.catalog_to_vec(path)?.iter().map(|row| row.to_string() + "\n").collect())
+    }
+    /// Directory listing to standard output in the file system's native style (default method)
+    fn catalog_to_stdout(&mut self, path: &str) -> STDRESULT {
+        print!("{}",self.catalog_to_string(path)?);
+        Ok(())
+    }
+    /// Directory listing in the native style taken apart into rows, or `None` if the native style
+    /// does not list one file per row (default method)
+    fn catalog_native(&mut self, _path: &str) -> Result<Option<NativeCatalog>,DYNERR> {
+        Ok(None)
+    }
+    /// Directory listing as in `catalog_to_string`, keeping only rows that pass the filter, in the given order.
+    /// If the file system has no `catalog_native` this fails unless there is nothing to do (default method).
+    fn catalog_to_string_with(&mut self, path: &str, filter: &CatalogFilter, order: CatalogOrder) -> Result<String,DYNERR> {
+        if filter.is_empty() && order==CatalogOrder::Directory {
+            return self.catalog_to_string(path);
+        }
+        match self.catalog_native(path)? {
+            Some(native) => Ok(native.render(filter,order)),
+            None => {
+                log::error!("native listing of this file system cannot be sorted or filtered, try `--generic` or `--plain`");
+                Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+            }
+        }
+    }
     /// Get directory listing as a Vec<String>.
     /// The rows are in an easily parsed fixed column format that is the same for all file systems.
     /// Columns 0..4 are the type/extension, 5..10 are the block count, 12.. is the basename.
     /// For flat file systems, the path must be "" or "/", or else an error is returned.
     /// For any file system, if the path resolves to a file, an error is returned.
     fn catalog_to_vec(&mut self, path: &str) -> Result<Vec<String>,DYNERR>;
-    /// Return vector of paths based on the glob pattern
-    fn glob(&mut self,pattern: &str,case_sensitive: bool) -> Result<Vec<String>,DYNERR>;
+    /// Get directory listing as a vector of items that can be sorted.
+    /// The default parses the rows of `catalog_to_vec`, so there is no date and the address is 0 (default method).
+    fn catalog_items(&mut self, path: &str) -> Result<Vec<CatalogItem>,DYNERR> {
+        Ok(self.catalog_to_vec(path)?.into_iter().map(|row| {
+            let typ = row.get(0..4).unwrap_or_default().trim().to_string();
+            let blocks = row.get(5..10).unwrap_or_default().trim().parse::<usize>().unwrap_or(0);
+            let name = row.get(12..).unwrap_or_default().to_string();
+            CatalogItem { row, name, modified: None, addr: 0, typ, blocks }
+        }).collect())
+    }
+    /// Get directory listing as in `catalog_to_vec`, with the rows in the given order (default method)
+    fn catalog_sorted(&mut self, path: &str, order: CatalogOrder) -> Result<Vec<String>,DYNERR> {
+        let mut items = self.catalog_items(path)?;
+        sort_catalog(&mut items,order);
+        Ok(items.into_iter().map(|x| x.row).collect())
+    }
+    /// Get directory listing as in `catalog_to_vec`, keeping only rows that pass the filter (default method)
+    fn catalog_filtered(&mut self, path: &str, filter: &CatalogFilter, order: CatalogOrder) -> Result<Vec<String>,DYNERR> {
+        let items = self.catalog_items(path)?;
+        Ok(filter_catalog(items,filter,order).into_iter().map(|x| x.row).collect())
+    }
+    /// Return vector of paths based on the glob pattern (default method)
+    fn glob(&mut self,pattern: &str,case_sensitive: bool) -> Result<Vec<String>,DYNERR> {
+        self.glob_with(pattern,GlobOptions { case_sensitive, fold: false })
+    }
+    /// Return vector of paths based on the glob pattern, the paths are as stored, whatever the options
+    fn glob_with(&mut self,pattern: &str,opt: GlobOptions) -> Result<Vec<String>,DYNERR>;
     /// Get the file system tree as a JSON string
     fn tree(&mut self,include_meta: bool,indent: Option<u16>) -> Result<String,DYNERR>;
+    /// Get the file system tree shaped by the options, see `tree` module (default method)
+    fn tree_with(&mut self,opt: TreeOptions) -> Result<String,DYNERR> {
+        let raw = self.tree(opt.meta || opt.du,None)?;
+        tree::shape(&raw,&opt)
+    }
     /// Create a new directory
     fn create(&mut self,path: &str) -> STDRESULT;
     /// Delete a file or directory
@@ -322,6 +722,18 @@ pub trait DiskFS {
     fn unlock(&mut self,path: &str) -> STDRESULT;
     /// Change the type and subtype of a file, strings may contain numbers as appropriate.
     fn retype(&mut self,path: &str,new_type: &str,sub_type: &str) -> STDRESULT;
+    /// Set the creation and modification time of the file at `path`, for file systems that keep times.
+    /// If the file system does not keep times a warning is logged and nothing changes (default method).
+    fn set_time(&mut self,_path: &str,_time: chrono::NaiveDateTime) -> STDRESULT {
+        log::warn!("this file system does not keep file times");
+        Ok(())
+    }
+    /// Is there a file, as opposed to a directory, at `path`.
+    /// If the file system does not support this an error is returned (default method).
+    fn exists(&mut self,path: &str) -> Result<bool,DYNERR> {
+        log::error!("checking for {} is not supported for this file system",path);
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
     /// Get file image from the `path` within this disk image.
     fn get(&mut self,path: &str) -> Result<FileImage,DYNERR>;
     /// Write file image to this disk image at the path stored in `fimg`.
@@ -331,6 +743,19 @@ pub trait DiskFS {
     /// Put a native file system allocation unit
     /// N.b. this simply zaps the block and can break the file system.
     fn write_block(&mut self, num: &str, dat: &[u8]) -> Result<usize,DYNERR>;
+    /// Get the reserved tracks that hold the operating system as a single blob.
+    /// If the file system does not reserve tracks this way an error is returned (default method).
+    fn read_boot(&mut self) -> Result<Vec<u8>,DYNERR> {
+        log::error!("reserved system tracks are not supported for this file system");
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
+    /// Put a blob to the reserved tracks that hold the operating system, and return the number of bytes written.
+    /// If `dat` is shorter than the reserved area, trailing bytes are unaffected.
+    /// If the file system does not reserve tracks this way an error is returned (default method).
+    fn write_boot(&mut self,dat: &[u8]) -> Result<usize,DYNERR> {
+        log::error!("reserved system tracks are not supported for this file system ({} bytes)",dat.len());
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
     /// Standardize for comparison with other sources of disk images.
     /// Returns a map from blocks to offsets within the block that are to be zeroed or ignored.
     /// Typically it is important to call this before deletions happen.
@@ -338,14 +763,181 @@ pub trait DiskFS {
     fn standardize(&mut self,ref_con: u16) -> HashMap<Block,Vec<usize>>;
     /// Compare this disk with a reference disk for testing purposes.  Panics if comparison fails.
     fn compare(&mut self,path: &std::path::Path,ignore: &HashMap<Block,Vec<usize>>);
+    /// List every block the file system covers, addressed the way the disk image expects.
+    /// This allows disks to be compared regardless of the image type.
+    fn all_blocks(&mut self) -> Result<Vec<Block>,DYNERR>;
     /// Mutably borrow the underlying disk image
     fn get_img(&mut self) -> &mut Box<dyn img::DiskImage>;
 
+    /// Start searching for free blocks at `block` during subsequent writes, `None` restores the usual search.
+    /// This controls where files are placed, for loaders that depend on it.  If the file system does not
+    /// support placement an error is returned (default method).
+    fn allocate_from(&mut self,block: Option<usize>) -> STDRESULT {
+        log::error!("file placement is not supported for this file system (want {:?})",block);
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
+    /// Rebuild the record of free blocks from the directory tree, returning how many blocks changed state.
+    /// If any block is claimed twice, or lies outside the volume, nothing is changed and an error is returned.
+    /// If the file system does not support this an error is returned (default method).
+    fn repair_bitmap(&mut self) -> Result<usize,DYNERR> {
+        // not an error log, since saving with `SaveOptions::fix_bitmap` only warns about this
+        log::debug!("bitmap repair is not supported for this file system");
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
+    /// Compare the record of free blocks with the directory tree without changing anything, returning each block
+    /// whose state disagrees, paired with `true` if the tree uses it.  This is what `repair_bitmap` would change.
+    /// If the file system does not support this an error is returned (default method).
+    fn check_bitmap(&mut self) -> Result<Vec<(Block,bool)>,DYNERR> {
+        log::error!("bitmap checking is not supported for this file system");
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
+    /// Overwrite every block the file system marks free with `pattern`, repeated as needed,
+    /// returning the number of blocks overwritten.  Blocks are not allocated by this.
+    /// If the file system does not support this an error is returned (default method).
+    fn wipe_free(&mut self,_pattern: &[u8]) -> Result<usize,DYNERR> {
+        log::error!("wiping free space is not supported for this file system");
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
+    /// Erase what remains of deleted files in the directory, so they cannot be recovered by name,
+    /// returning the number of entries erased.  Entries stay marked deleted as far as the file system is concerned.
+    /// If the file system does not support this an error is returned (default method).
+    fn wipe_deleted(&mut self) -> Result<usize,DYNERR> {
+        log::error!("wiping deleted entries is not supported for this file system");
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
+    /// Write `msg` as a banner into sectors or blocks that the allocation map marks free, as close to
+    /// the directory as the file system allows, and return where it went.  The last one is padded with nulls.
+    /// The sectors or blocks stay free, so a later write can cover the banner, but live data is never touched.
+    /// If there is not enough room nothing is written and an error is returned.
+    /// If the file system does not support this an error is returned (default method).
+    fn banner(&mut self,msg: &[u8]) -> Result<Vec<Block>,DYNERR> {
+        log::error!("banners are not supported for this file system ({} bytes)",msg.len());
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
+    /// Mark the tracks that hold the operating system free, after checking that no file uses them,
+    /// returning the number of sectors or blocks that were freed.  The disk will no longer boot.
+    /// If the file system does not support this an error is returned (default method).
+    fn reclaim_dos(&mut self) -> Result<usize,DYNERR> {
+        log::error!("reclaiming the DOS tracks is not supported for this file system");
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
+    /// Change the volume label, as reported by `stat`.  What the label is depends on the file system,
+    /// e.g. for DOS 3.x it is the volume number.  CP/M 3 and FAT create the label if there is none.
+    /// If the file system does not support this an error is returned (default method).
+    fn relabel(&mut self,label: &str) -> STDRESULT {
+        log::error!("relabeling is not supported for this file system (want {})",label);
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
+    /// Change the volume serial number, given in hex, e.g. `1234-ABCD` for FAT.
+    /// If the file system does not support this an error is returned (default method).
+    fn set_serial(&mut self,serial: &str) -> STDRESULT {
+        log::error!("volume serial numbers are not supported for this file system (want {})",serial);
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
+    /// Parsed volume structures with derived values, for `stat --level deep`, e.g., the ProDOS volume header,
+    /// the DOS 3.x VTOC, the FAT BPB, or the CP/M DPB, with numbers decoded rather than in hex.
+    /// If the file system has nothing more to show this is `raw` from `stat` (default method).
+    fn stat_deep(&mut self) -> Result<json::JsonValue,DYNERR> {
+        Ok(json::parse(&self.stat()?.raw).unwrap_or(json::JsonValue::Null))
+    }
+    /// Describe how the file at `path` is laid out on the disk as a JSON string, e.g., the ProDOS key block
+    /// and index blocks, the DOS 3.x track/sector lists, or the FAT cluster chain.
+    /// If the file system does not support this an error is returned (default method).
+    fn inspect(&mut self,path: &str,_indent: Option<u16>) -> Result<String,DYNERR> {
+        log::error!("inspecting file structure is not supported for this file system (path {})",path);
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
+    /// Append `dat` to the end of the file at `path` in place, extending the index or block chain,
+    /// and return the new length.  For DOS 3.x the length is the one DOS uses: text ends at the first null,
+    /// binary and BASIC files end where the length in the header says, and that length is updated.
+    /// If the file system does not support this an error is returned (default method).
+    fn append(&mut self,_path: &str,_dat: &[u8]) -> Result<usize,DYNERR> {
+        log::error!("appending is not supported for this file system");
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
+    /// Write `dat` into the file at `path` starting at byte `offset`, reading and writing only the blocks
+    /// that hold those bytes, and return the new length.  Offsets and lengths are measured as in `append`.
+    /// The file grows if the data runs past the end, but `offset` cannot be past the end.
+    /// If the file system does not support this an error is returned (default method).
+    fn patch(&mut self,_path: &str,_offset: usize,_dat: &[u8]) -> Result<usize,DYNERR> {
+        log::error!("patching is not supported for this file system");
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
+    /// Cut the file at `path` to `len` bytes in place, freeing blocks that are no longer needed,
+    /// and return the new length, which is measured as in `append`.  It is an error if `len` is
+    /// more than the current length.  If the file system does not support this an error is returned (default method).
+    fn truncate(&mut self,_path: &str,_len: usize) -> Result<usize,DYNERR> {
+        log::error!("truncating is not supported for this file system");
+        Err(Box::new(crate::commands::CommandError::UnsupportedFormat))
+    }
+    /// Rename every file matching the wildcard `pattern`, forming the new names from `new_pattern` as in
+    /// `wildcard_substitute`, and return the number of files renamed.  New names are checked before anything
+    /// is renamed.  File systems without wildcard renaming take both names literally (default method).
+    fn rename_wildcard(&mut self,pattern: &str,new_pattern: &str) -> Result<usize,DYNERR> {
+        self.rename(pattern,new_pattern)?;
+        Ok(1)
+    }
+    /// Save the state of the disk in memory, see `DiskImage::snapshot` (default method)
+    fn snapshot(&mut self) -> img::Snapshot {
+        self.get_img().snapshot()
+    }
+    /// Return to the state saved by `snapshot`.  File systems that buffer
+    /// directory or allocation data must discard it when overriding this (default method).
+    fn rollback(&mut self,snap: &img::Snapshot) -> STDRESULT {
+        self.get_img().rollback(snap)
+    }
     /// Convenience function to set path and put (default method)
     fn put_at(&mut self,path: &str,fimg: &mut FileImage) -> Result<usize,DYNERR> {
         fimg.set_path(path)?;
         self.put(fimg)
     }
+    /// Put `fimg`, doing as `policy` says if the path is taken.  Returns the path that was written,
+    /// or `None` if the file was skipped (default method).
+    fn put_if_exists(&mut self,fimg: &FileImage,policy: IfExists) -> Result<Option<String>,DYNERR> {
+        let path = fimg.full_path.clone();
+        if !self.exists(&path)? {
+            self.put(fimg)?;
+            return Ok(Some(path));
+        }
+        match policy {
+            IfExists::Error => {
+                log::error!("{} already exists",path);
+                Err(Box::new(Error::FileExists))
+            },
+            IfExists::Skip => {
+                log::warn!("skipping {}, it already exists",path);
+                Ok(None)
+            },
+            IfExists::Overwrite => {
+                let snap = self.snapshot();
+                if let Err(e) = self.delete(&path).and_then(|_| self.put(fimg).map(|_| ())) {
+                    log::error!("could not overwrite {}, it is unchanged",path);
+                    self.rollback(&snap)?;
+                    return Err(e);
+                }
+                Ok(Some(path))
+            },
+            IfExists::Rename => {
+                let mut renamed = fimg.clone();
+                for num in 1..1000 {
+                    // shorten the name until the number fits
+                    for trim in 0..path.len() {
+                        let candidate = numbered_name(&path,num,trim);
+                        if renamed.set_path(&candidate).is_ok() {
+                            break;
+                        }
+                    }
+                    if renamed.full_path != path && !self.exists(&renamed.full_path)? {
+                        log::warn!("{} already exists, writing {}",path,renamed.full_path);
+                        self.put(&renamed)?;
+                        return Ok(Some(renamed.full_path));
+                    }
+                }
+                log::error!("could not find a free name for {}",path);
+                Err(Box::new(Error::FileExists))
+            }
+        }
+    }
     /// Convenience function to get (load_addr,binary_data) (default method)
     fn bload(&mut self,path: &str) -> Result<(usize,Vec<u8>),DYNERR> {
         let fimg = self.get(path)?;
@@ -390,6 +982,14 @@ pub trait DiskFS {
     }    
 }
 
+/// Describe the disk image under a file system, which determines the sector order, for `stat_deep`
+fn image_json(img: &dyn img::DiskImage) -> json::JsonValue {
+    json::object! {
+        "type": img.what_am_i().to_string(),
+        "kind": img.kind().to_string()
+    }
+}
+
 impl Stat {
     pub fn to_json(&self,indent: Option<u16>) -> String {
         let mut ans = json::JsonValue::new_object();
@@ -0,0 +1,106 @@
+//! ## File name conversion
+//!
+//! Form names that are valid for a given file system from arbitrary names, such as host file names
+//! or names from another file system.  This is what `put --as-dir` and `mput` use, and front-ends should
+//! use it too, so that the same file gets the same name no matter how it was imported.
+//!
+//! * letters are made upper case
+//! * characters the file system does not allow are replaced, by `.` or, for CP/M and FAT, by `_`,
+//!   spaces are allowed in FAT names but are replaced anyway, since DOS cannot handle them
+//! * ProDOS names that do not start with a letter get an `A` in front
+//! * names are cut to the maximum length, for CP/M and FAT the base and extension are cut separately
+//! * if a name is taken, the end is replaced by a number, keeping within the maximum length
+
+use std::path::Path;
+
+/// How names are formed for one file system
+struct Rules {
+    /// maximum length, for CP/M and FAT this is the base name
+    max: usize,
+    /// maximum extension length, if the file system has a separate extension
+    ext_max: Option<usize>,
+    /// replaces characters that are not allowed
+    sub: char,
+    allowed: fn(char) -> bool,
+    letter_first: bool
+}
+
+fn printable(c: char) -> bool {
+    c.is_ascii() && !c.is_ascii_control()
+}
+
+fn rules(fs_name: &str) -> Option<Rules> {
+    match fs_name {
+        super::prodos::FS_NAME => Some(Rules { max: 15, ext_max: None, sub: '.', letter_first: true,
+            allowed: |c| c.is_ascii_alphanumeric() || c == '.' }),
+        super::dos3x::FS_NAME => Some(Rules { max: 30, ext_max: None, sub: '.', letter_first: false,
+            allowed: |c| printable(c) && c != ',' }),
+        super::rdos::FS_NAME => Some(Rules { max: 24, ext_max: None, sub: '.', letter_first: false,
+            allowed: |c| printable(c) && c != ',' }),
+        super::pascal::FS_NAME => Some(Rules { max: 15, ext_max: None, sub: '.', letter_first: false,
+            allowed: |c| printable(c) && !super::pascal::types::INVALID_CHARS.contains(c) }),
+        super::cpm::FS_NAME => Some(Rules { max: 8, ext_max: Some(3), sub: '_', letter_first: false,
+            allowed: |c| printable(c) && !super::cpm::types::INVALID_CHARS.contains(c) }),
+        super::fat::FS_NAME => Some(Rules { max: 8, ext_max: Some(3), sub: '_', letter_first: false,
+            allowed: |c| printable(c) && c != ' ' && !super::fat::pack::INVALID_CHARS.contains(c) }),
+        _ => None
+    }
+}
+
+fn clean(s: &str,rules: &Rules,max: usize) -> String {
+    s.trim().to_uppercase().chars()
+        .map(|c| if (rules.allowed)(c) { c } else { rules.sub })
+        .take(max)
+        .collect()
+}
+
+/// Test whether `name` is valid for the file system `fs_name`, ignoring case, and without logging anything.
+/// This is for a single name, not a path.  If the file system is unknown the answer is `true`.
+pub fn is_valid(fs_name: &str,name: &str) -> bool {
+    name.eq_ignore_ascii_case(&valid_name(fs_name,name,&[]))
+}
+
+/// Form a name that is valid for the file system `fs_name`, and not equal to any in `taken`,
+/// ignoring case.  The name should not include a directory.  If the file system is unknown
+/// the name is returned unchanged.
+pub fn valid_name(fs_name: &str,name: &str,taken: &[String]) -> String {
+    let rules = match rules(fs_name) {
+        Some(r) => r,
+        None => return name.to_string()
+    };
+    let (base,ext) = match (rules.ext_max,name.trim().rsplit_once('.')) {
+        (Some(ext_max),Some((b,e))) if !b.is_empty() => (clean(b,&rules,usize::MAX),clean(e,&rules,ext_max)),
+        _ => (clean(name,&rules,usize::MAX),String::new())
+    };
+    let mut base = match base.is_empty() {
+        true => "UNTITLED".to_string(),
+        false => base
+    };
+    if rules.letter_first && !base.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        base.insert(0,'A');
+    }
+    base.truncate(rules.max);
+    let join = |b: &str| match ext.is_empty() {
+        true => b.to_string(),
+        false => [b,".",&ext].concat()
+    };
+    let is_taken = |s: &str| taken.iter().any(|t| t.eq_ignore_ascii_case(s));
+    let mut ans = join(&base);
+    let mut count = 1;
+    while is_taken(&ans) {
+        let suffix = count.to_string();
+        ans = join(&[&base[0..usize::min(base.len(),rules.max-suffix.len())],&suffix].concat());
+        count += 1;
+    }
+    ans
+}
+
+/// Form a name for the host file `host` that is valid for the file system `fs_name`, and not equal to any in `taken`.
+/// The extension is dropped, except for CP/M and FAT, where it becomes the 3 character extension.
+pub fn from_host(fs_name: &str,host: &Path,taken: &[String]) -> String {
+    let name = match rules(fs_name).and_then(|r| r.ext_max) {
+        Some(_) => host.file_name(),
+        None => host.file_stem()
+    };
+    valid_name(fs_name,&name.unwrap_or_default().to_string_lossy(),taken)
+}
@@ -4,6 +4,7 @@
 //! Tested only with UCSD Pascal version 1.2.
 
 pub mod types;
+pub mod workspace;
 mod boot;
 mod directory;
 mod pack;
@@ -52,13 +53,14 @@ fn get_directory(img: &mut Box<dyn img::DiskImage>) -> Result<Directory,DYNERR>
     return Ok(ans);
 }
 
-pub fn new_fimg(chunk_len: usize,set_time: bool,name: &str) -> Result<super::FileImage,DYNERR> {
+/// New file image, stamped with `time` if it is not `None`
+pub fn new_fimg(chunk_len: usize,time: Option<chrono::NaiveDateTime>,name: &str) -> Result<super::FileImage,DYNERR> {
     if !is_name_valid(name, false) {
         return Err(Box::new(Error::BadFormat))
     }
-    let modified = match set_time {
-        true => pack::pack_date(None).to_vec(),
-        false => vec![0;2]
+    let modified = match time {
+        Some(t) => pack::pack_date(Some(t)).to_vec(),
+        None => vec![0;2]
     };
     Ok(super::FileImage {
         fimg_version: super::FileImage::fimg_version(),
@@ -84,7 +86,8 @@ pub struct Packer {
 /// The primary interface for disk operations.
 pub struct Disk
 {
-    img: Box<dyn img::DiskImage>
+    img: Box<dyn img::DiskImage>,
+    deterministic: bool
 }
 
 impl Disk
@@ -93,9 +96,18 @@ impl Disk
     /// The DiskFS takes ownership of the image.
     pub fn from_img(img: Box<dyn img::DiskImage>) -> Result<Self,DYNERR> {
         Ok(Self {
-            img
+            img,
+            deterministic: false
         })
     }
+    /// Fix the dates that are stored, see `crate::now`
+    pub fn with_deterministic(mut self,deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+    fn now(&self) -> Option<chrono::NaiveDateTime> {
+        Some(crate::now(self.deterministic))
+    }
     /// Test an image for the Pascal file system.
     pub fn test_img(img: &mut Box<dyn img::DiskImage>) -> bool {
         // test the volume directory header
@@ -287,7 +299,7 @@ impl Disk
         dir.header.total_blocks = u16::to_le_bytes(num_blocks as u16);
         dir.header.num_files = u16::to_le_bytes(0);
         dir.header.last_access_date = u16::to_le_bytes(0);
-        dir.header.last_set_date = pack_date(time);
+        dir.header.last_set_date = pack_date(time.or(self.now()));
         dir.header.pad = [0,0,0,0];
         // only need to write the first block, in fact, only first 22 bytes have data
         self.write_block(&dir.to_bytes(),VOL_HEADER_BLOCK,0)?;
@@ -332,7 +344,7 @@ impl Disk
         }
         if let (Some(idx),dir) = self.get_file_entry(name)? {
             let entry = &dir.entries[idx];
-            let mut ans = new_fimg(BLOCK_SIZE,false,name)?;
+            let mut ans = new_fimg(BLOCK_SIZE,None,name)?;
             let mut buf = vec![0;BLOCK_SIZE];
             let mut count: usize = 0;
             let beg = u16::from_le_bytes(entry.begin_block);
@@ -381,9 +393,12 @@ impl Disk
                         dir.entries[i].name_len = name.len() as u8;
                         dir.entries[i].name = string_to_file_name(name);
                         dir.entries[i].bytes_remaining = u16::to_le_bytes((BLOCK_SIZE*data_blocks - eof_usize) as u16);
-                        dir.entries[i].mod_date = pack_date(None); // None means use system clock
+                        dir.entries[i].mod_date = match fimg.modified.len()==2 && fimg.modified!=[0,0] {
+                            true => [fimg.modified[0],fimg.modified[1]],
+                            false => pack_date(self.now())
+                        };
                         dir.header.num_files = u16::to_le_bytes(u16::from_le_bytes(dir.header.num_files)+1);
-                        dir.header.last_access_date = pack_date(None);
+                        dir.header.last_access_date = pack_date(self.now());
                         self.save_directory(&dir)?;
                         for b in 0..data_blocks {
                             if fimg.chunks.contains_key(&b) {
@@ -447,13 +462,48 @@ impl Disk
             return Err(Box::new(Error::NoFile));
         }
     }
+    /// Native catalog in parts, see `DiskFS::catalog_native`
+    fn native_catalog(&mut self, path: &str) -> Result<super::NativeCatalog,DYNERR> {
+        let typ_map: HashMap<u8,&str> = HashMap::from(TYPE_MAP_DISP);
+        let dir = self.get_directory()?;
+        let total = dir.total_blocks();
+        let header = format!("\n{}:\n",vol_name_to_string(dir.header.name,dir.header.name_len));
+        let expected_count = u16::from_le_bytes(dir.header.num_files);
+        let mut rows = Vec::new();
+        for entry in dir.entries {
+            let beg = u16::from_le_bytes(entry.begin_block);
+            let end = u16::from_le_bytes(entry.end_block);
+            if beg!=0 && end>beg && (end as usize)<total {
+                let name = file_name_to_string(entry.name,entry.name_len);
+                let blocks = end - beg;
+                let date = match try_unpack_date(entry.mod_date) {
+                    Some(d) => d.format("%d-%b-%y").to_string(),
+                    None => "<NO DATE>".to_string()
+                };
+                let typ = match typ_map.get(&entry.file_type[0]) {
+                    Some(s) => s,
+                    None => "????"
+                };
+                rows.push(format!("{:15} {:4} {:9}  {:4}",name,blocks,date,typ));
+            }
+        }
+        let (free,largest) = self.num_free_blocks()?;
+        let used = total-free as usize;
+        let footer = format!("\n{}/{} files<listed/in-dir>, {} blocks used, {} unused, {} in largest\n\n",rows.len(),expected_count,used,free,largest);
+        let items = super::DiskFS::catalog_items(self,path)?;
+        super::NativeCatalog::pair(header,items,rows,footer)
+    }
 }
 
 impl super::DiskFS for Disk {
     fn new_fimg(&self, chunk_len: Option<usize>,set_time: bool,path: &str) -> Result<super::FileImage,DYNERR> {
+        let time = match set_time {
+            true => self.now(),
+            false => None
+        };
         match chunk_len {
-            Some(l) => new_fimg(l,set_time,path),
-            None => new_fimg(BLOCK_SIZE,set_time,path)
+            Some(l) => new_fimg(l,time,path),
+            None => new_fimg(BLOCK_SIZE,time,path)
         }
     }
     fn stat(&mut self) -> Result<super::Stat,DYNERR> {
@@ -470,40 +520,16 @@ impl super::DiskFS for Disk {
             raw: "".to_string()
         })
     }
-    fn catalog_to_stdout(&mut self, _path: &str) -> STDRESULT {
-        let typ_map: HashMap<u8,&str> = HashMap::from(TYPE_MAP_DISP);
-        let dir = self.get_directory()?;
-        let total = dir.total_blocks();
-        println!();
-        println!("{}:",vol_name_to_string(dir.header.name,dir.header.name_len));
-        let expected_count = u16::from_le_bytes(dir.header.num_files);
-        let mut file_count = 0;
-        for entry in dir.entries {
-            let beg = u16::from_le_bytes(entry.begin_block);
-            let end = u16::from_le_bytes(entry.end_block);
-            if beg!=0 && end>beg && (end as usize)<total {
-                let name = file_name_to_string(entry.name,entry.name_len);
-                let blocks = end - beg;
-                let mut date = "<NO DATE>".to_string();
-                if entry.mod_date!=[0,0] {
-                    date = unpack_date(entry.mod_date).format("%d-%b-%y").to_string();
-                }
-                let typ = match typ_map.get(&entry.file_type[0]) {
-                    Some(s) => s,
-                    None => "????"
-                };
-                println!("{:15} {:4} {:9}  {:4}",name,blocks,date,typ);
-                file_count += 1;
-            }
-        }
-        println!();
-        let (free,largest) = self.num_free_blocks()?;
-        let used = total-free as usize;
-        println!("{}/{} files<listed/in-dir>, {} blocks used, {} unused, {} in largest",file_count,expected_count,used,free,largest);
-        println!();
-        Ok(())
+    fn catalog_to_string(&mut self, path: &str) -> Result<String,DYNERR> {
+        Ok(self.native_catalog(path)?.render(&super::CatalogFilter::default(),super::CatalogOrder::Directory))
+    }
+    fn catalog_native(&mut self, path: &str) -> Result<Option<super::NativeCatalog>,DYNERR> {
+        Ok(Some(self.native_catalog(path)?))
     }
     fn catalog_to_vec(&mut self, path: &str) -> Result<Vec<String>,DYNERR> {
+        Ok(self.catalog_items(path)?.into_iter().map(|x| x.row).collect())
+    }
+    fn catalog_items(&mut self, path: &str) -> Result<Vec<super::CatalogItem>,DYNERR> {
         if path!="/" && path!="" {
             return Err(Box::new(Error::NoFile));
         }
@@ -522,27 +548,28 @@ impl super::DiskFS for Disk {
                     Some(s) => s,
                     None => type_as_hex.as_str()
                 };
-                ans.push(super::universal_row(typ,blocks as usize,&name));
+                ans.push(super::CatalogItem {
+                    row: super::universal_row(typ,blocks as usize,&name),
+                    name,
+                    typ: typ.to_string(),
+                    blocks: blocks as usize,
+                    modified: try_unpack_date(entry.mod_date),
+                    addr: beg as usize
+                });
             }
         }
         Ok(ans)
     }
-    fn glob(&mut self,pattern: &str,case_sensitive: bool) -> Result<Vec<String>,DYNERR> {
+    fn glob_with(&mut self,pattern: &str,opt: super::GlobOptions) -> Result<Vec<String>,DYNERR> {
         let mut ans = Vec::new();
-        let glob = match case_sensitive {
-            true => globset::Glob::new(pattern)?.compile_matcher(),
-            false => globset::Glob::new(&pattern.to_uppercase())?.compile_matcher()
-        };
+        let glob = super::NameMatcher::new(pattern,opt)?;
         let dir = self.get_directory()?;
         let total = dir.total_blocks();
         for entry in dir.entries {
             let beg = u16::from_le_bytes(entry.begin_block);
             let end = u16::from_le_bytes(entry.end_block);
             if beg!=0 && end>beg && (end as usize)<total {
-                let name = match case_sensitive {
-                    true => file_name_to_string(entry.name, entry.name_len),
-                    false => file_name_to_string(entry.name, entry.name_len).to_uppercase()
-                };
+                let name = file_name_to_string(entry.name, entry.name_len);
                 if glob.is_match(&name) {
                     ans.push(name);
                 }
@@ -559,8 +586,9 @@ impl super::DiskFS for Disk {
         tree["files"] = json::JsonValue::new_object();
         tree["label"] = json::JsonValue::new_object();
         tree["label"]["name"] = json::JsonValue::String(vol_name_to_string(dir.header.name, dir.header.name_len));
-        tree["label"]["time_created"] = json::JsonValue::String(unpack_date(dir.header.last_set_date).format(TIME_FMT).to_string());
-        tree["label"]["time_modified"] = json::JsonValue::String(unpack_date(dir.header.last_set_date).format(TIME_FMT).to_string());
+        let date = unpack_date(dir.header.last_set_date);
+        tree["label"]["time_created"] = json::JsonValue::String(date.format(TIME_FMT).to_string());
+        tree["label"]["time_modified"] = json::JsonValue::String(date.format(TIME_FMT).to_string());
         for entry in dir.entries {
             let beg = u16::from_le_bytes(entry.begin_block);
             let end = u16::from_le_bytes(entry.end_block);
@@ -575,8 +603,8 @@ impl super::DiskFS for Disk {
                     let meta = &mut tree["files"][&key]["meta"];
                     meta["type"] = json::JsonValue::String(hex::encode_upper(entry.file_type.to_vec()));
                     meta["eof"] = json::JsonValue::Number(bytes.into());
-                    if entry.mod_date!=[0,0] {
-                        meta["time_modified"] = json::JsonValue::String(unpack_date(entry.mod_date).format(TIME_FMT).to_string());
+                    if let Some(date) = try_unpack_date(entry.mod_date) {
+                        meta["time_modified"] = json::JsonValue::String(date.format(TIME_FMT).to_string());
                     }
                     meta["blocks"] = json::JsonValue::Number(blocks.into());
                 }
@@ -592,6 +620,27 @@ impl super::DiskFS for Disk {
         log::error!("pascal implementation does not support operation");
         Err(Box::new(Error::DevErr))
     }
+    fn stat_deep(&mut self) -> Result<json::JsonValue,DYNERR> {
+        let dir = self.get_directory()?;
+        let hdr = &dir.header;
+        let mut ans = json::JsonValue::new_object();
+        ans["volume_header"] = json::object! {
+            "name": vol_name_to_string(hdr.name,hdr.name_len),
+            "begin_block": u16::from_le_bytes(hdr.begin_block),
+            "end_block": u16::from_le_bytes(hdr.end_block),
+            "total_blocks": u16::from_le_bytes(hdr.total_blocks),
+            "num_files": u16::from_le_bytes(hdr.num_files),
+            "last_set_date": try_unpack_date(hdr.last_set_date).map(|t| t.format("%Y/%m/%d").to_string())
+        };
+        let (free,largest) = self.num_free_blocks()?;
+        ans["derived"] = json::object! {
+            "directory_blocks": u16::from_le_bytes(hdr.end_block).saturating_sub(u16::from_le_bytes(hdr.begin_block)),
+            "free_blocks": free,
+            "largest_free": largest,
+            "image": super::image_json(self.img.as_ref())
+        };
+        Ok(ans)
+    }
     fn delete(&mut self,name: &str) -> STDRESULT {
         if let (Some(idx),mut dir) = self.get_file_entry(name)? {
             for i in idx..dir.entries.len() {
@@ -633,6 +682,17 @@ impl super::DiskFS for Disk {
     fn retype(&mut self,name: &str,new_type: &str,_sub_type: &str) -> STDRESULT {
         self.modify(name, None, Some(new_type))
     }
+    fn set_time(&mut self,name: &str,time: chrono::NaiveDateTime) -> STDRESULT {
+        if let (Some(idx),mut dir) = self.get_file_entry(name)? {
+            dir.entries[idx].mod_date = pack_date(Some(time));
+            self.save_directory(&dir)
+        } else {
+            Err(Box::new(Error::NoFile))
+        }
+    }
+    fn exists(&mut self,name: &str) -> Result<bool,DYNERR> {
+        Ok(self.get_file_entry(name)?.0.is_some())
+    }
     fn get(&mut self,name: &str) -> Result<super::FileImage,DYNERR> {
         self.read_file(name)
     }
@@ -727,6 +787,20 @@ impl super::DiskFS for Disk {
             }
         }
     }
+    fn relabel(&mut self,label: &str) -> STDRESULT {
+        if !is_name_valid(label, true) {
+            log::error!("invalid pascal volume name");
+            return Err(Box::new(Error::BadTitle));
+        }
+        let mut dir = self.get_directory()?;
+        dir.header.name_len = label.len() as u8;
+        dir.header.name = string_to_vol_name(label);
+        self.save_directory(&dir)
+    }
+    fn all_blocks(&mut self) -> Result<Vec<Block>,DYNERR> {
+        let dir = self.get_directory()?;
+        Ok((0..dir.total_blocks()).map(Block::PO).collect())
+    }
     fn get_img(&mut self) -> &mut Box<dyn img::DiskImage> {
         &mut self.img
     }
@@ -10,20 +10,31 @@ use crate::{STDRESULT,DYNERR};
 pub fn pack_date(time: Option<chrono::NaiveDateTime>) -> [u8;2] {
     let now = match time {
         Some(t) => t,
-        _ => chrono::Local::now().naive_local()
+        _ => crate::now(false)
     };
     let (_is_common_era,year) = now.year_ce();
     let packed_date = (now.month() + (now.day() << 4) + ((year%100) << 9)) as u16;
     return u16::to_le_bytes(packed_date);
 }
 
-pub fn unpack_date(pascal_date: [u8;2]) -> chrono::NaiveDateTime {
+/// Unpack a Pascal date, the zero date, or a date with an impossible month or day, gives `None`.
+/// Only two digits of the year are kept, so we assume dates before 1979 are in the 21st century,
+/// as is done for ProDOS.
+pub fn try_unpack_date(pascal_date: [u8;2]) -> Option<chrono::NaiveDateTime> {
     let date = u16::from_le_bytes(pascal_date);
-    let year = 1900 + (date >> 9); // choose to stay in the 20th century (Y2K bug)
+    let yearmod100 = date >> 9;
+    let year = match yearmod100 < 79 {
+        true => 2000 + yearmod100,
+        false => 1900 + yearmod100
+    };
     let month = date & 15;
     let day = (date >> 4) & 31;
-    return chrono::NaiveDate::from_ymd_opt(year as i32,month as u32,day as u32).unwrap()
-        .and_hms_opt(0, 0, 0).unwrap();
+    chrono::NaiveDate::from_ymd_opt(year as i32,month as u32,day as u32)?.and_hms_opt(0,0,0)
+}
+
+/// Unpack a Pascal date as in `try_unpack_date`, dates that cannot be unpacked give the default time
+pub fn unpack_date(pascal_date: [u8;2]) -> chrono::NaiveDateTime {
+    try_unpack_date(pascal_date).unwrap_or_default()
 }
 
 /// This will accept lower case; case will be automatically converted as appropriate
@@ -50,21 +61,15 @@ pub fn is_name_valid(s: &str,is_vol: bool) -> bool {
 }
 
 pub fn file_name_to_string(fname: [u8;15],len: u8) -> String {
-    // UTF8 failure will cause panic
-    let copy = fname[0..len as usize].to_vec();
-    if let Ok(result) = String::from_utf8(copy) {
-        return result.trim_end().to_string();
-    }
-    panic!("encountered a bad file name");
+    // a damaged directory can have any length or bytes, keep what we can
+    let copy = &fname[0..usize::min(len as usize,15)];
+    String::from_utf8_lossy(copy).trim_end().to_string()
 }
 
 pub fn vol_name_to_string(fname: [u8;7],len: u8) -> String {
-    // UTF8 failure will cause panic
-    let copy = fname[0..len as usize].to_vec();
-    if let Ok(result) = String::from_utf8(copy) {
-        return result.trim_end().to_string();
-    }
-    panic!("encountered a bad file name");
+    // a damaged directory can have any length or bytes, keep what we can
+    let copy = &fname[0..usize::min(len as usize,7)];
+    String::from_utf8_lossy(copy).trim_end().to_string()
 }
 
 pub fn string_to_file_name(s: &str) -> [u8;15] {
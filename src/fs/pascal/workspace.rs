@@ -0,0 +1,117 @@
+//! ### Pascal workspace
+//!
+//! A workspace maps Pascal volume names to disk images, so that a path such as `APPLE1:SYSTEM.EDITOR`
+//! resolves within a multi-volume set, e.g., the disks of a two drive system.
+//! The volume map is a JSON object whose keys are volume names and whose values are image paths,
+//! relative image paths are taken relative to the map file:
+//! ```json
+//! {
+//!     "APPLE1": "apple1.do",
+//!     "APPLE2": "apple2.do"
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path,PathBuf};
+use super::types::Error;
+use crate::fs::{DiskFS,FileImage,GlobOptions,NameMatcher};
+use crate::DYNERR;
+
+pub struct Workspace {
+    /// upper case volume name mapped to image path
+    volumes: BTreeMap<String,PathBuf>
+}
+
+/// Split `VOL:NAME` into its parts, the volume part is `None` if there is no colon.
+pub fn split_path(path: &str) -> (Option<String>,String) {
+    match path.split_once(':') {
+        Some((vol,name)) => (Some(vol.to_uppercase()),name.to_string()),
+        None => (None,path.to_string())
+    }
+}
+
+impl Workspace {
+    /// Create a workspace from the JSON volume map, relative image paths are joined to `base`.
+    pub fn from_json(json_str: &str,base: &Path) -> Result<Self,DYNERR> {
+        let parsed = json::parse(json_str)?;
+        if !parsed.is_object() {
+            log::error!("volume map should be a JSON object");
+            return Err(Box::new(Error::BadFormat));
+        }
+        let mut volumes = BTreeMap::new();
+        for (vol,val) in parsed.entries() {
+            let img_path = match val.as_str() {
+                Some(s) => base.join(s),
+                None => {
+                    log::error!("image path for volume {} should be a string",vol);
+                    return Err(Box::new(Error::BadFormat));
+                }
+            };
+            if !super::pack::is_name_valid(vol,true) {
+                log::error!("{} is not a valid volume name",vol);
+                return Err(Box::new(Error::BadTitle));
+            }
+            volumes.insert(vol.to_uppercase(),img_path);
+        }
+        Ok(Self { volumes })
+    }
+    /// Create a workspace from a JSON volume map file.
+    pub fn from_file(map_path: &str) -> Result<Self,DYNERR> {
+        let json_str = std::fs::read_to_string(map_path)?;
+        let base = match Path::new(map_path).parent() {
+            Some(p) => p.to_path_buf(),
+            None => PathBuf::new()
+        };
+        Self::from_json(&json_str,&base)
+    }
+    /// Volume names in the workspace, in sorted order
+    pub fn volumes(&self) -> Vec<String> {
+        self.volumes.keys().cloned().collect()
+    }
+    /// Open the file system on the image that holds volume `vol`.
+    /// It is only a warning if the volume on the image has another name.
+    pub fn open(&self,vol: &str) -> Result<Box<dyn DiskFS>,DYNERR> {
+        let img_path = match self.volumes.get(&vol.to_uppercase()) {
+            Some(p) => p,
+            None => {
+                log::error!("volume {} is not in the workspace",vol);
+                return Err(Box::new(Error::NoDev));
+            }
+        };
+        let mut disk = crate::create_fs_from_file(&img_path.to_string_lossy())?;
+        let label = disk.stat()?.label;
+        if !label.eq_ignore_ascii_case(vol) {
+            log::warn!("{} holds volume {}, expected {}",img_path.to_string_lossy(),label,vol);
+        }
+        Ok(disk)
+    }
+    /// Get the file at `VOL:NAME`, the volume is required.
+    pub fn get(&self,path: &str) -> Result<FileImage,DYNERR> {
+        match split_path(path) {
+            (Some(vol),name) => self.open(&vol)?.get(&name),
+            (None,_) => {
+                log::error!("path should start with a volume, e.g. VOL:NAME");
+                Err(Box::new(Error::BadTitle))
+            }
+        }
+    }
+    /// Match `VOL:PATTERN` against every volume the volume glob matches, or `PATTERN` against every volume.
+    /// The results are in the form `VOL:NAME`.
+    pub fn glob(&self,pattern: &str,case_sensitive: bool) -> Result<Vec<String>,DYNERR> {
+        self.glob_with(pattern,GlobOptions { case_sensitive, fold: false })
+    }
+    /// Same as `glob`, with volume names matched using the same options as file names
+    pub fn glob_with(&self,pattern: &str,opt: GlobOptions) -> Result<Vec<String>,DYNERR> {
+        let (vol_pattern,name_pattern) = split_path(pattern);
+        let vol_glob = NameMatcher::new(&vol_pattern.unwrap_or("*".to_string()),opt)?;
+        let mut ans = Vec::new();
+        for vol in self.volumes.keys() {
+            if vol_glob.is_match(vol) {
+                for name in self.open(vol)?.glob_with(&name_pattern,opt)? {
+                    ans.push([vol,":",&name].concat());
+                }
+            }
+        }
+        Ok(ans)
+    }
+}
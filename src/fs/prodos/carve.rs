@@ -0,0 +1,126 @@
+//! ### Carving ProDOS files
+//!
+//! Any block that parses as an index block, with at least two distinct pointers on the volume,
+//! is taken to belong to a sapling file.  An index block whose pointers all lead to index blocks
+//! is the master index of a tree file.  Seedling files leave no structure behind, they can only be
+//! found by their content.
+
+use std::collections::BTreeMap;
+use super::types::BLOCK_SIZE;
+use crate::fs::Block;
+use crate::fs::carve::{Carved,Evidence,classify};
+use crate::img::DiskImage;
+
+/// Pointers of the index block, with trailing nulls removed, if it is plausible
+fn parse(buf: &[u8],iblock: usize,total: usize) -> Option<Vec<usize>> {
+    if buf.len() < BLOCK_SIZE {
+        return None;
+    }
+    let mut ptrs: Vec<usize> = (0..256).map(|i| u16::from_le_bytes([buf[i],buf[256+i]]) as usize).collect();
+    while ptrs.last() == Some(&0) {
+        ptrs.pop();
+    }
+    let mut distinct: Vec<usize> = ptrs.iter().filter(|p| **p != 0).copied().collect();
+    let count = distinct.len();
+    distinct.sort();
+    distinct.dedup();
+    // block 2 is always the volume directory key block
+    if count < 2 || distinct.len() < count || distinct.iter().any(|p| *p < 3 || *p >= total || *p == iblock) {
+        return None;
+    }
+    // the links of an empty directory block look like pointers 0 and 2, files rarely start with a hole
+    if ptrs.iter().enumerate().all(|(i,p)| *p == 0 || i == 0 || i == 2) {
+        return None;
+    }
+    Some(ptrs)
+}
+
+fn read_data(img: &mut Box<dyn DiskImage>,ptrs: &[usize],dat: &mut Vec<u8>,used: &mut Vec<Block>) -> usize {
+    let mut unreadable = 0;
+    for ptr in ptrs {
+        match (*ptr,img.read_block(Block::PO(*ptr))) {
+            (0,_) => dat.append(&mut vec![0;BLOCK_SIZE]),
+            (_,Ok(mut buf)) => {
+                used.push(Block::PO(*ptr));
+                dat.append(&mut buf);
+            },
+            (_,Err(_)) => {
+                unreadable += 1;
+                dat.append(&mut vec![0;BLOCK_SIZE]);
+            }
+        }
+    }
+    unreadable
+}
+
+/// Carve files from the index blocks on a volume with `total` blocks.
+/// Each file comes with the blocks it uses, including the index blocks.
+pub fn index_blocks(img: &mut Box<dyn DiskImage>,total: usize) -> Vec<(Carved,Vec<Block>)> {
+    let mut index: BTreeMap<usize,Vec<usize>> = BTreeMap::new();
+    for iblock in 2..total {
+        if let Ok(buf) = img.read_block(Block::PO(iblock)) {
+            if let Some(ptrs) = parse(&buf,iblock,total) {
+                index.insert(iblock,ptrs);
+            }
+        }
+    }
+    let masters: Vec<usize> = index.iter()
+        .filter(|(_,ptrs)| ptrs.iter().all(|p| *p == 0 || index.contains_key(p)))
+        .map(|(b,_)| *b).collect();
+    let mut consumed: Vec<usize> = Vec::new();
+    for master in &masters {
+        consumed.extend(index[master].iter().filter(|p| **p != 0));
+    }
+    let mut ans = Vec::new();
+    for (iblock,ptrs) in &index {
+        if consumed.contains(iblock) && !masters.contains(iblock) {
+            continue;
+        }
+        let mut used = vec![Block::PO(*iblock)];
+        let mut dat = Vec::new();
+        let mut unreadable = 0;
+        let mut count = 0;
+        let evidence = match masters.contains(iblock) {
+            true => {
+                for (i,sub) in ptrs.iter().enumerate() {
+                    match index.get(sub) {
+                        Some(sub_ptrs) => {
+                            used.push(Block::PO(*sub));
+                            // every index block but the last is full
+                            let mut sub_ptrs = sub_ptrs.clone();
+                            if i + 1 < ptrs.len() {
+                                sub_ptrs.resize(256,0);
+                            }
+                            unreadable += read_data(img,&sub_ptrs,&mut dat,&mut used);
+                            count += sub_ptrs.len();
+                        },
+                        None => {
+                            dat.append(&mut vec![0;256*BLOCK_SIZE]);
+                            count += 256;
+                        }
+                    }
+                }
+                Evidence::MasterIndexBlock
+            },
+            false => {
+                unreadable += read_data(img,ptrs,&mut dat,&mut used);
+                count += ptrs.len();
+                Evidence::IndexBlock
+            }
+        };
+        let (content,addr,data,conf) = classify(&dat,false);
+        let mut confidence = 0.4 + 0.5 * conf;
+        if unreadable > 0 {
+            confidence *= 1.0 - unreadable as f64 / count as f64;
+        }
+        ans.push((Carved {
+            evidence,
+            location: Block::PO(*iblock),
+            content,
+            addr,
+            data,
+            confidence
+        },used));
+    }
+    ans
+}
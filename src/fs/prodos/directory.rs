@@ -165,6 +165,27 @@ impl VolDirHeader {
     pub fn total_blocks(&self) -> u16 {
         u16::from_le_bytes(self.total_blocks)
     }
+    /// Fields as a JSON object, with numbers decoded
+    pub fn to_json(self) -> json::JsonValue {
+        json::object! {
+            "name": self.name(),
+            "created": unpack_time(self.create_time).map(|t| t.format("%Y/%m/%d %H:%M").to_string()),
+            "version": self.vers,
+            "min_version": self.min_vers,
+            "access": self.access,
+            "entry_length": self.entry_len,
+            "entries_per_block": self.entries_per_block,
+            "file_count": u16::from_le_bytes(self.file_count),
+            "bitmap_pointer": u16::from_le_bytes(self.bitmap_ptr),
+            "total_blocks": self.total_blocks()
+        }
+    }
+    /// Panics if `name` is invalid
+    pub fn rename(&mut self,name: &str) {
+        let (nibs,fname) = string_to_file_name(&StorageType::VolDirHeader, name);
+        self.stor_len_nibs = nibs;
+        self.name = fname;
+    }
 }
 
 impl SubDirHeader {
@@ -227,6 +248,13 @@ impl Entry {
         let new_val = u16::from_le_bytes(self.blocks_used) as i32 + delta;
         self.blocks_used = u16::to_le_bytes(new_val as u16);
     }
+    /// set the modification time, `None` means the current time
+    pub fn set_create_time(&mut self,time: Option<chrono::NaiveDateTime>) {
+        self.create_time = pack_time(time);
+    }
+    pub fn set_mod_time(&mut self,time: Option<chrono::NaiveDateTime>) {
+        self.last_mod = pack_time(time);
+    }
     pub fn metadata_to_fimg(&self,fimg: &mut FileImage) {
         fimg.set_eof(self.eof());
         fimg.access = vec![self.access];
@@ -295,12 +323,24 @@ impl Entry {
     pub fn set_all_access(&mut self,what: u8) {
         self.access = what;
     }
-    /// Panics if `name` is invalid
+    /// Panics if `name` is invalid.
+    /// If the entry has case flags they are recomputed from `name`.
     pub fn rename(&mut self,name: &str) {
         let stor = self.storage_type();
         let (nibs,fname) = string_to_file_name(&stor, name);
         self.stor_len_nibs = nibs;
         self.name = fname;
+        if self.case_flags() & 0x8000 > 0 {
+            [self.vers,self.min_vers] = u16::to_le_bytes(case_flags(name));
+        }
+    }
+    /// GS/OS case flags, which replace the version bytes in ProDOS 2.x
+    pub fn case_flags(&self) -> u16 {
+        u16::from_le_bytes([self.vers,self.min_vers])
+    }
+    /// Name with the case flags applied, use `name` for lookups
+    pub fn display_name(&self) -> String {
+        apply_case_flags(&self.name(),self.case_flags())
     }
     pub fn standardize(&mut self,offset: usize) -> Vec<usize> {
         // relative to the entry start
@@ -338,14 +378,22 @@ impl Entry {
         meta["blocks"] = json::JsonValue::Number(u16::from_le_bytes(self.blocks_used).into());
         meta
     }
-    pub fn universal_row(&self) -> String  {
+    pub fn mod_time(&self) -> Option<chrono::NaiveDateTime> {
+        unpack_time(self.last_mod)
+    }
+    /// file type as displayed in the catalog, or `$` followed by the hex code
+    pub fn type_name(&self) -> String {
         let typ_map: HashMap<u8,&str> = HashMap::from(TYPE_MAP_DISP);
-        let type_as_hex = "$".to_string()+ &hex::encode_upper(vec![self.file_type]);
-        super::super::universal_row(
-            match typ_map.get(&self.file_type) { Some(s) => *s, _ => &type_as_hex },
-            u16::from_le_bytes(self.blocks_used) as usize,
-            &self.name()
-        )
+        match typ_map.get(&self.file_type) {
+            Some(s) => s.to_string(),
+            None => "$".to_string() + &hex::encode_upper(vec![self.file_type])
+        }
+    }
+    pub fn blocks(&self) -> usize {
+        u16::from_le_bytes(self.blocks_used) as usize
+    }
+    pub fn universal_row(&self) -> String  {
+        super::super::universal_row(&self.type_name(),self.blocks(),&self.display_name())
     }
 }
 
@@ -372,7 +420,7 @@ impl fmt::Display for Entry {
         let type_as_hex = "$".to_string()+ &hex::encode_upper(vec![self.file_type]);
         write!(f,"{}{:15} {:4} {:6} {:16} {:16} {:7} {:7}",
             write_protect,
-            match self.file_type { 0x0f => self.name().blue().bold(), _ => self.name().normal() },
+            match self.file_type { 0x0f => self.display_name().blue().bold(), _ => self.display_name().normal() },
             match typ_map.get(&self.file_type) { Some(s) => *s, _ => &type_as_hex },
             u16::from_le_bytes(self.blocks_used),
             mod_time,
@@ -642,6 +690,17 @@ impl DiskStruct for EntryBlock {
     }
 }
 
+/// Storage types we do not know about (such as extended files) are treated as inactive
+fn storage_type_from_nibs(stor_len_nibs: u8) -> StorageType {
+    match StorageType::from_u8((stor_len_nibs & 0xf0) >> 4) {
+        Some(t) => t,
+        None => {
+            log::warn!("unknown storage type {}, treating as inactive",stor_len_nibs >> 4);
+            StorageType::Inactive
+        }
+    }
+}
+
 impl HasName for Entry {
     fn fname(&self) -> (u8,[u8;15]) {
         return (self.stor_len_nibs,self.name);
@@ -650,10 +709,7 @@ impl HasName for Entry {
         return file_name_to_string(self.stor_len_nibs, self.name);
     }
     fn storage_type(&self) -> StorageType {
-        match StorageType::from_u8((self.stor_len_nibs & 0xf0) >> 4) {
-            Some(t) => t,
-            _ => panic!("encountered unknown storage type")
-        }
+        storage_type_from_nibs(self.stor_len_nibs)
     }
 }
 
@@ -665,10 +721,7 @@ impl HasName for VolDirHeader {
         return file_name_to_string(self.stor_len_nibs, self.name);
     }
     fn storage_type(&self) -> StorageType {
-        match StorageType::from_u8((self.stor_len_nibs & 0xf0) >> 4) {
-            Some(t) => t,
-            _ => panic!("encountered unknown storage type")
-        }
+        storage_type_from_nibs(self.stor_len_nibs)
     }
 }
 
@@ -680,10 +733,7 @@ impl HasName for SubDirHeader {
         return file_name_to_string(self.stor_len_nibs, self.name);
     }
     fn storage_type(&self) -> StorageType {
-        match StorageType::from_u8((self.stor_len_nibs & 0xf0) >> 4) {
-            Some(t) => t,
-            _ => panic!("encountered unknown storage type")
-        }
+        storage_type_from_nibs(self.stor_len_nibs)
     }
 }
 
@@ -7,7 +7,8 @@
 mod boot;
 pub mod types;
 mod directory;
-mod pack;
+pub(crate) mod pack;
+pub mod carve;
 
 use std::collections::HashMap;
 use a2kit_macro::DiskStruct;
@@ -31,7 +32,11 @@ pub struct Disk {
     maybe_bitmap: Option<Vec<u8>>,
     bitmap_blocks: Vec<usize>,
     /// only valid during glob
-    curr_path: Vec<String>
+    curr_path: Vec<String>,
+    /// where the search for free blocks starts
+    alloc_floor: usize,
+    quirks: super::quirks::Quirks,
+    deterministic: bool
 }
 
 /// put a u16 into an index block in the prescribed fashion
@@ -41,13 +46,14 @@ fn pack_index_ptr(buf: &mut [u8],ptr: u16,idx: usize) {
     buf[idx+256] = bytes[1];
 }
 
-pub fn new_fimg(chunk_len: usize,set_time: bool,path: &str) -> Result<super::FileImage,DYNERR> {
+/// New file image, stamped with `time` if it is not `None`
+pub fn new_fimg(chunk_len: usize,time: Option<chrono::NaiveDateTime>,path: &str) -> Result<super::FileImage,DYNERR> {
     if !is_path_valid(path) {
         return Err(Box::new(Error::Syntax));
     }
-    let created = match set_time {
-        true => pack::pack_time(None).to_vec(),
-        false => vec![0;4]
+    let created = match time {
+        Some(t) => pack::pack_time(Some(t)).to_vec(),
+        None => vec![0;4]
     };
     Ok(super::FileImage {
         fimg_version: super::FileImage::fimg_version(),
@@ -82,9 +88,25 @@ impl Disk {
             // bitmap buffer is designed to work transparently
             maybe_bitmap: None,
             bitmap_blocks: Vec::new(),
-            curr_path: Vec::new()
+            curr_path: Vec::new(),
+            alloc_floor: 0,
+            quirks: super::quirks::Quirks::DEFAULT,
+            deterministic: false
         })
     }
+    /// Use the given quirks profile rather than the default
+    pub fn with_quirks(mut self,quirks: super::quirks::Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+    /// Fix the times that are stored, see `crate::now`
+    pub fn with_deterministic(mut self,deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+    fn now(&self) -> Option<chrono::NaiveDateTime> {
+        Some(crate::now(self.deterministic))
+    }
     /// Test an image for the ProDOS file system.
     pub fn test_img(img: &mut Box<dyn img::DiskImage>) -> bool {
         // test the volume directory header to see if this is ProDOS
@@ -147,7 +169,8 @@ impl Disk {
         if let Some(buf) = self.maybe_bitmap.as_mut() {
             return Ok(buf);
         }
-        panic!("bitmap buffer failed to open");
+        error!("bitmap buffer failed to open");
+        Err(Box::new(Error::IOError))
     }
     /// Buffer needs to be written back when an external caller
     /// asks, directly or indirectly, for the underlying image.
@@ -210,15 +233,7 @@ impl Disk {
             }
             return Ok(());
         }
-        match self.img.read_block(Block::PO(iblock)) {
-            Ok(buf) => {
-                for i in 0..actual_len as usize {
-                    data[offset + i] = buf[i];
-                }
-                Ok(())
-            }
-            Err(e) => Err(e)
-        }
+        self.img.read_block_into(Block::PO(iblock),&mut data[offset..offset+actual_len as usize])
     }
     /// Write and allocate the block in one step.
     /// If it is a bitmap block panic; we should only be zapping bitmap blocks.
@@ -244,7 +259,9 @@ impl Disk {
         self.img.write_block(Block::PO(iblock), &data[offset..offset+actual_len])
     }
     fn get_available_block(&mut self) -> Result<Option<u16>,DYNERR> {
-        for block in 0..self.total_blocks {
+        // start at the floor, but wrap around rather than fail
+        let floor = usize::min(self.alloc_floor,self.total_blocks);
+        for block in (floor..self.total_blocks).chain(0..floor) {
             if self.is_block_free(block)? {
                 return Ok(Some(block as u16));
             }
@@ -262,7 +279,7 @@ impl Disk {
         let mut volume_dir = KeyBlock::<VolDirHeader>::new();
         let bitmap_blocks = 1 + self.total_blocks / 4096;
         volume_dir.set_links(Some(0), Some(VOL_KEY_BLOCK+1));
-        volume_dir.header.format(self.total_blocks as u16,vol_name,time);
+        volume_dir.header.format(self.total_blocks as u16,vol_name,time.or(self.now()));
         let first = u16::from_le_bytes(volume_dir.header.bitmap_ptr) as usize;
 
         // zap in the volume key block
@@ -567,8 +584,10 @@ impl Disk {
             }
         }
         // ProDOS evidently swaps the index block halves upon deletion (why?)
-        let swapped = [index_block[256..512].to_vec(),index_block[0..256].to_vec()].concat();
-        self.write_block(&swapped,index_ptr as usize,0)?;
+        if self.quirks.prodos_swap_index_halves {
+            let swapped = [index_block[256..512].to_vec(),index_block[0..256].to_vec()].concat();
+            self.write_block(&swapped,index_ptr as usize,0)?;
+        }
         self.deallocate_block(index_ptr as usize)?;
         Ok(())
     }
@@ -593,18 +612,23 @@ impl Disk {
                     }
                 }
                 // ProDOS evidently swaps the master index block halves upon deletion (why?)
-                let swapped = [master_block[256..512].to_vec(),master_block[0..256].to_vec()].concat();
-                self.write_block(&swapped,master_ptr as usize,0)?;
+                if self.quirks.prodos_swap_index_halves {
+                    let swapped = [master_block[256..512].to_vec(),master_block[0..256].to_vec()].concat();
+                    self.write_block(&swapped,master_ptr as usize,0)?;
+                }
                 self.deallocate_block(master_ptr as usize)?;
             }
-            _ => panic!("cannot read file of this type")
+            _ => {
+                error!("cannot read file of this type");
+                return Err(Box::new(Error::FileTypeMismatch));
+            }
         }
         Ok(())
     }
     /// Read any file into the sparse file format.  Use `FileImage.sequence()` to flatten the result
     /// when it is expected to be sequential.
     fn read_file(&mut self,entry: &Entry) -> Result<super::FileImage,DYNERR> {
-        let mut fimg = new_fimg(512,false,"temp")?;
+        let mut fimg = new_fimg(512,None,"temp")?;
         entry.metadata_to_fimg(&mut fimg);
         let mut buf: Vec<u8> = vec![0;512];
         let master_ptr = entry.get_ptr();
@@ -634,7 +658,14 @@ impl Disk {
                 }
                 return Ok(fimg);
             }
-            _ => panic!("cannot read file of this type")
+            StorageType::Extended => {
+                error!("{} has a resource fork, files with forks are not supported",entry.name());
+                Err(Box::new(Error::FileTypeMismatch))
+            }
+            _ => {
+                error!("cannot read file of this type");
+                Err(Box::new(Error::FileTypeMismatch))
+            }
         }
     }
     /// Verify that the new name does not already exist
@@ -759,7 +790,10 @@ impl Disk {
                         (Some(_v),_,_) => 2 // index and data blocks
                     }
                 }
-                _ => panic!("unexpected storage type during write")
+                _ => {
+                    error!("unexpected storage type during write");
+                    return Err(Box::new(Error::FileTypeMismatch));
+                }
             };
             if blocks_needed > self.num_free_blocks()? {
                 return Err(Box::new(Error::DiskFull));
@@ -837,7 +871,10 @@ impl Disk {
                     self.write_block(&master_buf,master_ptr as usize,0)?;
                     index_count += 1;
                 },
-                _ => panic!("unexpected storage type during write")
+                _ => {
+                    error!("unexpected storage type during write");
+                    return Err(Box::new(Error::FileTypeMismatch));
+                }
             }
         }
         // update the entry, do last to capture all the changes
@@ -849,6 +886,172 @@ impl Disk {
         self.write_entry(&loc,&entry)?;
         return Ok(eof);
     }
+    /// Allocate a block and fill it with zeros
+    fn new_zero_block(&mut self,entry: &mut Entry) -> Result<u16,DYNERR> {
+        match self.get_available_block()? {
+            Some(ptr) => {
+                self.write_block(&vec![0;512],ptr as usize,0)?;
+                entry.delta_blocks(1);
+                Ok(ptr)
+            },
+            None => Err(Box::new(Error::DiskFull))
+        }
+    }
+    /// Get pointer `slot` of the index block at `index_ptr`.  If it is 0 and `grow` is true,
+    /// allocate a zeroed block for it and update the index block.
+    fn index_slot(&mut self,entry: &mut Entry,index_ptr: u16,slot: usize,grow: bool) -> Result<u16,DYNERR> {
+        let mut buf: Vec<u8> = vec![0;512];
+        self.read_block(&mut buf,index_ptr as usize,0)?;
+        let ptr = u16::from_le_bytes([buf[slot],buf[slot+256]]);
+        if ptr>0 || !grow {
+            return Ok(ptr);
+        }
+        let new_ptr = self.new_zero_block(entry)?;
+        pack_index_ptr(&mut buf,new_ptr,slot);
+        self.write_block(&buf,index_ptr as usize,0)?;
+        Ok(new_ptr)
+    }
+    /// Get the data block holding block `idx` of the file, 0 if it is a hole or past the end.
+    /// If `grow` is true, holes are filled and the index is extended, changing the storage type as needed.
+    /// The entry is updated but not written.
+    fn file_block(&mut self,entry: &mut Entry,idx: usize,grow: bool) -> Result<u16,DYNERR> {
+        if idx >= 128*256 {
+            return Err(Box::new(Error::Range));
+        }
+        if grow && entry.storage_type()==StorageType::Seedling && idx>0 {
+            let index_ptr = self.new_zero_block(entry)?;
+            let mut buf: Vec<u8> = vec![0;512];
+            pack_index_ptr(&mut buf,entry.get_ptr(),0);
+            self.write_block(&buf,index_ptr as usize,0)?;
+            entry.change_storage_type(StorageType::Sapling);
+            entry.set_ptr(index_ptr);
+        }
+        if grow && entry.storage_type()==StorageType::Sapling && idx>255 {
+            let master_ptr = self.new_zero_block(entry)?;
+            let mut buf: Vec<u8> = vec![0;512];
+            pack_index_ptr(&mut buf,entry.get_ptr(),0);
+            self.write_block(&buf,master_ptr as usize,0)?;
+            entry.change_storage_type(StorageType::Tree);
+            entry.set_ptr(master_ptr);
+        }
+        let key_ptr = entry.get_ptr();
+        match entry.storage_type() {
+            StorageType::Seedling if idx==0 => Ok(key_ptr),
+            StorageType::Sapling if idx<256 => self.index_slot(entry,key_ptr,idx,grow),
+            StorageType::Tree => match self.index_slot(entry,key_ptr,idx/256,grow)? {
+                0 => Ok(0),
+                index_ptr => self.index_slot(entry,index_ptr,idx%256,grow)
+            },
+            _ => Ok(0)
+        }
+    }
+    /// Free the data blocks in slots `first` and beyond of the index block at `index_ptr`
+    fn cut_index_block(&mut self,entry: &mut Entry,index_ptr: u16,first: usize) -> STDRESULT {
+        let mut buf: Vec<u8> = vec![0;512];
+        self.read_block(&mut buf,index_ptr as usize,0)?;
+        for slot in first..256 {
+            let ptr = u16::from_le_bytes([buf[slot],buf[slot+256]]);
+            if ptr>0 {
+                self.deallocate_block(ptr as usize)?;
+                pack_index_ptr(&mut buf,0,slot);
+                entry.delta_blocks(-1);
+            }
+        }
+        self.write_block(&buf,index_ptr as usize,0)
+    }
+    /// Find a file that can be written, returning its location and entry
+    fn find_writable_file(&mut self,path: &str) -> Result<(EntryLocation,Entry),DYNERR> {
+        let loc = self.find_file(path)?;
+        let entry = self.read_entry(&loc)?;
+        if !entry.get_access(Access::Write) {
+            error!("{} is write protected",path);
+            return Err(Box::new(Error::WriteProtected));
+        }
+        match entry.storage_type() {
+            StorageType::Seedling | StorageType::Sapling | StorageType::Tree => Ok((loc,entry)),
+            _ => {
+                error!("{} is not a standard file",path);
+                Err(Box::new(Error::FileTypeMismatch))
+            }
+        }
+    }
+    /// Write `dat` to the file starting at byte `offset`, extending it as needed.
+    /// The EOF is not changed.
+    fn write_file_at(&mut self,entry: &mut Entry,offset: usize,dat: &[u8]) -> STDRESULT {
+        let mut buf: Vec<u8> = vec![0;512];
+        let mut ptr = offset;
+        while ptr < offset + dat.len() {
+            let beg = ptr % 512;
+            let n = usize::min(512 - beg,offset + dat.len() - ptr);
+            let iblock = self.file_block(entry,ptr/512,true)?;
+            self.read_block(&mut buf,iblock as usize,0)?;
+            buf[beg..beg+n].copy_from_slice(&dat[ptr-offset..ptr-offset+n]);
+            self.write_block(&buf,iblock as usize,0)?;
+            ptr += n;
+        }
+        Ok(())
+    }
+    /// Write `dat` into the file at byte `offset`, or at the end if `None`, and update the EOF if the file grows.
+    /// Returns the new EOF.  If a block cannot be allocated the disk is restored.
+    fn write_into(&mut self,path: &str,maybe_offset: Option<usize>,dat: &[u8]) -> Result<usize,DYNERR> {
+        let (loc,mut entry) = self.find_writable_file(path)?;
+        let eof = entry.eof();
+        let offset = maybe_offset.unwrap_or(eof);
+        if offset > eof {
+            error!("offset {} is past the end of the file ({} bytes)",offset,eof);
+            return Err(Box::new(Error::Range));
+        }
+        if offset + dat.len() > 0xffffff {
+            error!("file would exceed the maximum length");
+            return Err(Box::new(Error::Range));
+        }
+        let snap = super::DiskFS::snapshot(self);
+        if let Err(e) = self.write_file_at(&mut entry,offset,dat) {
+            super::DiskFS::rollback(self,&snap)?;
+            return Err(e);
+        }
+        entry.set_eof(usize::max(eof,offset + dat.len()));
+        entry.set_mod_time(self.now());
+        self.write_entry(&loc,&entry)?;
+        Ok(entry.eof())
+    }
+    /// Free the blocks beyond `len` bytes and zero the rest of the last block that is kept.
+    /// The key block and storage type are kept, as ProDOS does.  The EOF is not changed.
+    fn cut_file(&mut self,entry: &mut Entry,len: usize) -> STDRESULT {
+        let keep = usize::max(1,len.div_ceil(512));
+        let key_ptr = entry.get_ptr();
+        match entry.storage_type() {
+            StorageType::Sapling if keep<256 => self.cut_index_block(entry,key_ptr,keep)?,
+            StorageType::Tree => {
+                let mut buf: Vec<u8> = vec![0;512];
+                self.read_block(&mut buf,key_ptr as usize,0)?;
+                for slot in 0..128 {
+                    let index_ptr = u16::from_le_bytes([buf[slot],buf[slot+256]]);
+                    if index_ptr==0 || (slot+1)*256 <= keep {
+                        continue;
+                    }
+                    self.cut_index_block(entry,index_ptr,keep.saturating_sub(slot*256))?;
+                    if slot*256 >= keep {
+                        self.deallocate_block(index_ptr as usize)?;
+                        pack_index_ptr(&mut buf,0,slot);
+                        entry.delta_blocks(-1);
+                    }
+                }
+                self.write_block(&buf,key_ptr as usize,0)?;
+            },
+            _ => {}
+        }
+        if len/512 < keep {
+            let iblock = self.file_block(entry,len/512,false)?;
+            if iblock>0 {
+                let mut buf: Vec<u8> = vec![0;512];
+                self.read_block(&mut buf,iblock as usize,0)?;
+                buf[len%512..].fill(0);
+                self.write_block(&buf,iblock as usize,0)?;
+            }
+        }
+        Ok(())
+    }
     /// modify a file entry, optionally lock, unlock, rename, retype; attempt to change already locked file will fail.
     fn modify(&mut self,loc: &EntryLocation,maybe_lock: Option<bool>,maybe_new_name: Option<&str>,
         maybe_new_type: Option<&str>,maybe_new_aux: Option<u16>) -> STDRESULT {  
@@ -884,13 +1087,9 @@ impl Disk {
         self.write_entry(loc, &entry)?;
         return Ok(());
     }
-    fn glob_node(&mut self,pattern: &str,dir_block: u16,case_sensitive: bool) -> Result<Vec<String>,DYNERR> {
+    fn glob_node(&mut self,glob: &super::NameMatcher,dir_block: u16) -> Result<Vec<String>,DYNERR> {
         // this blindly searches everywhere, we could be more efficient by truncating based on the pattern
         let mut files = Vec::new();
-        let glob = match case_sensitive {
-            true => globset::GlobBuilder::new(&pattern).literal_separator(true).build()?.compile_matcher(),
-            false => globset::GlobBuilder::new(&pattern.to_uppercase()).literal_separator(true).build()?.compile_matcher()
-        };
         let mut curr = dir_block;
         while curr>0 {
             let dir = self.get_directory(curr as usize)?;
@@ -898,10 +1097,7 @@ impl Disk {
                 let entry = dir.get_entry(&loc);
                 if entry.is_active() {
                     let key = entry.name();
-                    let test = match case_sensitive {
-                        true => [self.curr_path.concat(),key.clone()].concat(),
-                        false => [self.curr_path.concat(),key.clone()].concat().to_uppercase(),
-                    };
+                    let test = [self.curr_path.concat(),key.clone()].concat();
                     if entry.storage_type()!=StorageType::SubDirEntry && glob.is_match(&test) {
                         let mut full_path = self.curr_path.concat();
                         full_path += &key;
@@ -910,7 +1106,7 @@ impl Disk {
                     if entry.storage_type()==StorageType::SubDirEntry {
                         trace!("descend into directory {}",key);
                         self.curr_path.push(key + "/");
-                        files.append(&mut self.glob_node(pattern,entry.get_ptr(),case_sensitive)?);
+                        files.append(&mut self.glob_node(glob,entry.get_ptr())?);
                     }
                 }
             }
@@ -943,61 +1139,206 @@ impl Disk {
         }
         Ok(files)
     }
-}
-
-impl super::DiskFS for Disk {
-    fn new_fimg(&self, chunk_len: Option<usize>,set_time: bool,path: &str) -> Result<super::FileImage,DYNERR> {
-        match chunk_len {
-            Some(l) => new_fimg(l,set_time,path),
-            None => new_fimg(BLOCK_SIZE,set_time,path)
+    /// Mark a block as used, checking that it is on the volume and not claimed twice
+    fn mark_used(&self,iblock: u16,used: &mut [bool]) -> STDRESULT {
+        match used.get_mut(iblock as usize) {
+            Some(true) => {
+                error!("block {} is claimed more than once",iblock);
+                Err(Box::new(Error::IOError))
+            },
+            Some(u) => {
+                *u = true;
+                Ok(())
+            },
+            None => {
+                error!("block {} is beyond the end of the volume",iblock);
+                Err(Box::new(Error::Range))
+            }
         }
     }
-    fn stat(&mut self) -> Result<super::Stat,DYNERR> {
-        let vheader = self.get_vol_header()?;
-        Ok(super::Stat {
-            fs_name: FS_NAME.to_string(),
-            label: vheader.name(),
-            users: Vec::new(),
-            block_size: BLOCK_SIZE,
-            block_beg: 0,
-            block_end: self.total_blocks,
-            free_blocks: self.num_free_blocks()? as usize,
-            raw: "".to_string()
-        })
+    /// Whether each block should be marked used, judging from the directory tree.
+    /// It is an error if a block is claimed twice.
+    fn blocks_in_use(&mut self) -> Result<Vec<bool>,DYNERR> {
+        let mut used = vec![false;self.total_blocks];
+        used[0] = true;
+        used[1] = true;
+        let bptr = u16::from_le_bytes(self.get_vol_header()?.bitmap_ptr) as usize;
+        for iblock in bptr..bptr + self.total_blocks.div_ceil(4096) {
+            self.mark_used(iblock as u16,&mut used)?;
+        }
+        self.mark_used_node(VOL_KEY_BLOCK,&mut used)?;
+        Ok(used)
+    }
+    /// Mark the index block and the data blocks it references
+    fn mark_index_block(&mut self,index_ptr: u16,used: &mut [bool]) -> STDRESULT {
+        let mut buf: Vec<u8> = vec![0;512];
+        self.mark_used(index_ptr,used)?;
+        self.read_block(&mut buf,index_ptr as usize,0)?;
+        for idx in 0..256 {
+            let ptr = u16::from_le_bytes([buf[idx],buf[idx+256]]);
+            if ptr>0 {
+                self.mark_used(ptr,used)?;
+            }
+        }
+        Ok(())
+    }
+    /// Mark every block belonging to the directory starting at `dir_block`, calls itself recursively
+    fn mark_used_node(&mut self,dir_block: u16,used: &mut [bool]) -> STDRESULT {
+        let mut buf: Vec<u8> = vec![0;512];
+        let mut curr = dir_block;
+        while curr>0 {
+            self.mark_used(curr,used)?;
+            let dir = self.get_directory(curr as usize)?;
+            for loc in dir.entry_locations(curr) {
+                let entry = dir.get_entry(&loc);
+                if !entry.is_active() {
+                    continue;
+                }
+                let ptr = entry.get_ptr();
+                match entry.storage_type() {
+                    StorageType::Seedling => self.mark_used(ptr,used)?,
+                    StorageType::Sapling => self.mark_index_block(ptr,used)?,
+                    StorageType::Tree => {
+                        self.mark_used(ptr,used)?;
+                        self.read_block(&mut buf,ptr as usize,0)?;
+                        let master_block = buf.clone();
+                        for idx in 0..256 {
+                            let index_ptr = u16::from_le_bytes([master_block[idx],master_block[idx+256]]);
+                            if index_ptr>0 {
+                                self.mark_index_block(index_ptr,used)?;
+                            }
+                        }
+                    },
+                    StorageType::SubDirEntry => self.mark_used_node(ptr,used)?,
+                    _ => {
+                        error!("cannot account for blocks of {}",entry.name());
+                        return Err(Box::new(Error::FileTypeMismatch));
+                    }
+                }
+            }
+            curr = dir.next();
+        }
+        Ok(())
+    }
+    /// JSON node for the index block at `index_ptr`, `depth` is 1 for a sapling index or 2 for a master index.
+    /// A pointer of 0 is a hole, or null in a master index, pointers after the last one in use are dropped.
+    fn inspect_index(&mut self,index_ptr: u16,depth: usize) -> Result<json::JsonValue,DYNERR> {
+        let mut buf: Vec<u8> = vec![0;512];
+        self.read_block(&mut buf,index_ptr as usize,0)?;
+        let mut ptrs: Vec<u16> = (0..256).map(|idx| u16::from_le_bytes([buf[idx],buf[idx+256]])).collect();
+        while ptrs.last()==Some(&0) {
+            ptrs.pop();
+        }
+        let mut ans = json::object! { "block": index_ptr };
+        if depth > 1 {
+            ans["index"] = json::JsonValue::new_array();
+            for ptr in ptrs {
+                ans["index"].push(match ptr {
+                    0 => json::JsonValue::Null,
+                    p => self.inspect_index(p,depth-1)?
+                })?;
+            }
+        } else {
+            ans["data"] = ptrs.into();
+        }
+        Ok(ans)
     }
-    fn catalog_to_stdout(&mut self, path: &str) -> STDRESULT {
+    /// Erase inactive entries in this directory and its subdirectories, returning the count
+    fn wipe_deleted_node(&mut self,dir_block: u16) -> Result<usize,DYNERR> {
+        let mut count = 0;
+        let mut curr = dir_block;
+        while curr>0 {
+            let mut dir = self.get_directory(curr as usize)?;
+            let mut changed = false;
+            for loc in dir.entry_locations(curr) {
+                let entry = dir.get_entry(&loc);
+                if entry.is_active() {
+                    if entry.storage_type()==StorageType::SubDirEntry {
+                        count += self.wipe_deleted_node(entry.get_ptr())?;
+                    }
+                } else if entry.to_bytes().iter().any(|x| *x>0) {
+                    dir.set_entry(&loc,Entry::new());
+                    changed = true;
+                    count += 1;
+                }
+            }
+            if changed {
+                self.write_block(&dir.to_bytes(),curr as usize,0)?;
+            }
+            curr = dir.next();
+        }
+        Ok(count)
+    }
+    /// Native catalog in parts, see `DiskFS::catalog_native`
+    fn native_catalog(&mut self, path: &str) -> Result<super::NativeCatalog,DYNERR> {
+        let mut header = String::new();
         let b = self.find_dir_key_block(path)?;
         let mut dir = self.get_directory(b as usize)?;
-        println!();
+        writeln!(header)?;
         if b==2 {
-            println!("{}{}","/".bright_blue().bold(),dir.name().bright_blue().bold());
+            writeln!(header,"{}{}","/".bright_blue().bold(),dir.name().bright_blue().bold())?;
         } else {
-            println!("{}",dir.name().bright_blue().bold());
+            writeln!(header,"{}",dir.name().bright_blue().bold())?;
         }
-        println!();
-        println!(" {:15} {:4} {:6} {:16} {:16} {:7} {:7}",
+        writeln!(header)?;
+        writeln!(header," {:15} {:4} {:6} {:16} {:16} {:7} {:7}",
             "NAME".bold(),"TYPE".bold(),"BLOCKS".bold(),
-            "MODIFIED".bold(),"CREATED".bold(),"ENDFILE".bold(),"SUBTYPE".bold());
-        println!();
+            "MODIFIED".bold(),"CREATED".bold(),"ENDFILE".bold(),"SUBTYPE".bold())?;
+        writeln!(header)?;
+        let mut rows = Vec::new();
         let mut curr = b;
         while curr>0 {
             dir = self.get_directory(curr as usize)?;
             for loc in dir.entry_locations(curr) {
                 let entry = dir.get_entry(&loc);
                 if entry.is_active() {
-                    println!("{}",entry);
+                    rows.push(entry.to_string());
                 }
             }
             curr = dir.next();
         }
-        println!();
         let free = self.num_free_blocks()? as usize;
         let used = self.total_blocks-free;
-        println!("BLOCKS FREE: {}  BLOCKS USED: {}  TOTAL BLOCKS: {}",free,used,self.total_blocks);
-        println!();
-        Ok(())
+        let footer = format!("\nBLOCKS FREE: {}  BLOCKS USED: {}  TOTAL BLOCKS: {}\n\n",free,used,self.total_blocks);
+        let items = super::DiskFS::catalog_items(self,path)?;
+        super::NativeCatalog::pair(header,items,rows,footer)
+    }
+}
+
+impl super::DiskFS for Disk {
+    fn new_fimg(&self, chunk_len: Option<usize>,set_time: bool,path: &str) -> Result<super::FileImage,DYNERR> {
+        let time = match set_time {
+            true => self.now(),
+            false => None
+        };
+        match chunk_len {
+            Some(l) => new_fimg(l,time,path),
+            None => new_fimg(BLOCK_SIZE,time,path)
+        }
+    }
+    fn stat(&mut self) -> Result<super::Stat,DYNERR> {
+        let vheader = self.get_vol_header()?;
+        Ok(super::Stat {
+            fs_name: FS_NAME.to_string(),
+            label: vheader.name(),
+            users: Vec::new(),
+            block_size: BLOCK_SIZE,
+            block_beg: 0,
+            block_end: self.total_blocks,
+            free_blocks: self.num_free_blocks()? as usize,
+            raw: "".to_string()
+        })
+    }
+    fn catalog_to_string(&mut self, path: &str) -> Result<String,DYNERR> {
+        Ok(self.native_catalog(path)?.render(&super::CatalogFilter::default(),super::CatalogOrder::Directory))
+    }
+    fn catalog_native(&mut self, path: &str) -> Result<Option<super::NativeCatalog>,DYNERR> {
+        Ok(Some(self.native_catalog(path)?))
     }
     fn catalog_to_vec(&mut self, path: &str) -> Result<Vec<String>,DYNERR> {
+        Ok(self.catalog_items(path)?.into_iter().map(|x| x.row).collect())
+    }
+    fn catalog_items(&mut self, path: &str) -> Result<Vec<super::CatalogItem>,DYNERR> {
         let mut ans = Vec::new();
         let mut curr = self.find_dir_key_block(path)?;
         while curr>0 {
@@ -1005,23 +1346,30 @@ impl super::DiskFS for Disk {
             for loc in dir.entry_locations(curr) {
                 let entry = dir.get_entry(&loc);
                 if entry.is_active() {
-                    ans.push(entry.universal_row());
+                    ans.push(super::CatalogItem {
+                        row: entry.universal_row(),
+                        name: entry.display_name(),
+                        typ: entry.type_name(),
+                        blocks: entry.blocks(),
+                        modified: entry.mod_time(),
+                        addr: entry.get_ptr() as usize
+                    });
                 }
             }
             curr = dir.next();
         }
         Ok(ans)
     }
-    fn glob(&mut self,pattern: &str,case_sensitive: bool) -> Result<Vec<String>,DYNERR> {
+    fn glob_with(&mut self,pattern: &str,opt: super::GlobOptions) -> Result<Vec<String>,DYNERR> {
         let vhdr = self.get_vol_header()?;
         let dir_block = self.find_dir_key_block("/")?;
         let vol_path = ["/",&vhdr.name(),"/"].concat();
         self.curr_path = vec![vol_path.clone()];
-        if pattern.starts_with("/") {
-            self.glob_node(pattern, dir_block, case_sensitive)
-        } else {
-            self.glob_node(&(vol_path + pattern), dir_block, case_sensitive)
-        }
+        let glob = match pattern.starts_with("/") {
+            true => super::NameMatcher::new(pattern,opt)?,
+            false => super::NameMatcher::new(&(vol_path + pattern),opt)?
+        };
+        self.glob_node(&glob, dir_block)
     }
     fn tree(&mut self,include_meta: bool,indent: Option<u16>) -> Result<String,DYNERR> {
         let vhdr = self.get_vol_header()?;
@@ -1045,13 +1393,13 @@ impl super::DiskFS for Disk {
                 dir.inc_file_count();
                 self.write_block(&dir.to_bytes(),key_block as usize,0)?;
                 // write the entry into the parent directory (may not be key block)
-                let mut entry = Entry::create_subdir(&name,new_block,key_block,None);
+                let mut entry = Entry::create_subdir(&name,new_block,key_block,self.now());
                 entry.delta_blocks(1);
                 entry.set_eof(512);
                 self.write_entry(&loc,&entry)?;
                 // write the new directory's key block
                 let mut subdir = KeyBlock::<SubDirHeader>::new();
-                subdir.header.create(&name,loc.block,loc.idx as u8,None);
+                subdir.header.create(&name,loc.block,loc.idx as u8,self.now());
                 self.write_block(&subdir.to_bytes(),new_block as usize,0)?;
                 Ok(())
             },
@@ -1151,8 +1499,28 @@ impl super::DiskFS for Disk {
             Err(e) => Err(Box::new(e))
         }
     }
-    fn get(&mut self,path: &str) -> Result<super::FileImage,DYNERR> {
+    fn set_time(&mut self,path: &str,time: chrono::NaiveDateTime) -> STDRESULT {
+        let loc = self.find_file(path)?;
+        let mut entry = self.read_entry(&loc)?;
+        entry.set_create_time(Some(time));
+        entry.set_mod_time(Some(time));
+        self.write_entry(&loc,&entry)
+    }
+    fn exists(&mut self,path: &str) -> Result<bool,DYNERR> {
+        if path.ends_with('/') {
+            return Ok(false);
+        }
         match self.find_file(path) {
+            Ok(_) => Ok(true),
+            Err(e) => match e.downcast_ref::<Error>() {
+                Some(Error::PathNotFound) => Ok(false),
+                _ => Err(e)
+            }
+        }
+    }
+    fn get(&mut self,path: &str) -> Result<super::FileImage,DYNERR> {
+        // an extended file is found so that the reason it cannot be read is given
+        match self.search_volume(&vec![StorageType::Seedling,StorageType::Sapling,StorageType::Tree,StorageType::Extended],path) {
             Ok(loc) => {
                 let entry = self.read_entry(&loc)?;
                 let mut fimg = self.read_file(&entry)?;
@@ -1178,7 +1546,7 @@ impl super::DiskFS for Disk {
                 dir.inc_file_count();
                 self.write_block(&dir.to_bytes(),dir_key_block as usize,0)?;
                 // create the entry
-                match Entry::create_file(&name,fimg,new_key_block,dir_key_block,None) {
+                match Entry::create_file(&name,fimg,new_key_block,dir_key_block,self.now()) {
                     Ok(entry) => self.write_entry(&loc,&entry)?,
                     Err(e) => return Err(e)
                 }
@@ -1261,6 +1629,191 @@ impl super::DiskFS for Disk {
             }
         }
     }
+    fn all_blocks(&mut self) -> Result<Vec<Block>,DYNERR> {
+        let vhdr = self.get_vol_header()?;
+        Ok((0..vhdr.total_blocks() as usize).map(Block::PO).collect())
+    }
+    fn allocate_from(&mut self,block: Option<usize>) -> STDRESULT {
+        match block {
+            Some(b) if b >= self.total_blocks => {
+                error!("block {} is beyond the end of the volume",b);
+                Err(Box::new(Error::Range))
+            },
+            Some(b) => {
+                self.alloc_floor = b;
+                Ok(())
+            },
+            None => {
+                self.alloc_floor = 0;
+                Ok(())
+            }
+        }
+    }
+    fn check_bitmap(&mut self) -> Result<Vec<(Block,bool)>,DYNERR> {
+        let mut diffs = Vec::new();
+        for (iblock,is_used) in self.blocks_in_use()?.into_iter().enumerate() {
+            if self.is_block_free(iblock)? == is_used {
+                diffs.push((Block::PO(iblock),is_used));
+            }
+        }
+        Ok(diffs)
+    }
+    fn repair_bitmap(&mut self) -> Result<usize,DYNERR> {
+        let used = self.blocks_in_use()?;
+        let mut changed = 0;
+        for (iblock,is_used) in used.into_iter().enumerate() {
+            if self.is_block_free(iblock)? == is_used {
+                changed += 1;
+                match is_used {
+                    true => self.allocate_block(iblock)?,
+                    false => self.deallocate_block(iblock)?
+                }
+            }
+        }
+        Ok(changed)
+    }
+    fn relabel(&mut self,label: &str) -> STDRESULT {
+        if !is_name_valid(label) {
+            error!("invalid ProDOS volume name {}",label);
+            return Err(Box::new(Error::Syntax));
+        }
+        let mut buf: Vec<u8> = vec![0;512];
+        self.read_block(&mut buf,VOL_KEY_BLOCK as usize,0)?;
+        let mut volume_dir = KeyBlock::<VolDirHeader>::from_bytes(&buf)?;
+        volume_dir.header.rename(label);
+        self.write_block(&volume_dir.to_bytes(),VOL_KEY_BLOCK as usize,0)
+    }
+    fn wipe_free(&mut self,pattern: &[u8]) -> Result<usize,DYNERR> {
+        let dat: Vec<u8> = pattern.iter().cycle().take(BLOCK_SIZE).copied().collect();
+        let mut count = 0;
+        for iblock in 0..self.total_blocks {
+            if self.is_block_free(iblock)? {
+                self.zap_block(&dat,iblock,0)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+    fn banner(&mut self,msg: &[u8]) -> Result<Vec<Block>,DYNERR> {
+        // the volume directory cannot grow, so take the lowest free blocks, which follow it and the bitmap
+        let needed = msg.len().div_ceil(BLOCK_SIZE);
+        let mut free = Vec::new();
+        for iblock in 0..self.total_blocks {
+            if free.len()==needed {
+                break;
+            }
+            if self.is_block_free(iblock)? {
+                free.push(iblock);
+            }
+        }
+        if free.len() < needed {
+            log::error!("banner needs {} blocks, but only {} are free",needed,free.len());
+            return Err(Box::new(Error::DiskFull));
+        }
+        let mut padded = msg.to_vec();
+        padded.resize(needed*BLOCK_SIZE,0);
+        for (i,iblock) in free.iter().enumerate() {
+            self.zap_block(&padded,*iblock,i*BLOCK_SIZE)?;
+        }
+        Ok(free.into_iter().map(Block::PO).collect())
+    }
+    fn stat_deep(&mut self) -> Result<json::JsonValue,DYNERR> {
+        let vheader = self.get_vol_header()?;
+        let mut ans = json::JsonValue::new_object();
+        ans["volume_header"] = vheader.to_json();
+        ans["derived"] = json::object! {
+            "bitmap_blocks": (vheader.total_blocks() as usize).div_ceil(4096),
+            "free_blocks": self.num_free_blocks()?,
+            "image": super::image_json(self.img.as_ref())
+        };
+        Ok(ans)
+    }
+    fn inspect(&mut self,path: &str,indent: Option<u16>) -> Result<String,DYNERR> {
+        let loc = match self.find_file(path) {
+            Ok(loc) => loc,
+            Err(_) => self.search_volume(&vec![StorageType::SubDirEntry],path)?
+        };
+        let entry = self.read_entry(&loc)?;
+        let ptr = entry.get_ptr();
+        let mut ans = json::JsonValue::new_object();
+        ans["file_system"] = json::JsonValue::String(FS_NAME.to_string());
+        ans["path"] = json::JsonValue::String(path.to_string());
+        ans["key_block"] = ptr.into();
+        ans["blocks"] = entry.blocks().into();
+        ans["eof"] = entry.eof().into();
+        match entry.storage_type() {
+            StorageType::Seedling => {
+                ans["storage_type"] = "seedling".into();
+                ans["data"] = vec![ptr].into();
+            },
+            StorageType::Sapling => {
+                ans["storage_type"] = "sapling".into();
+                ans["index"] = self.inspect_index(ptr,1)?;
+            },
+            StorageType::Tree => {
+                ans["storage_type"] = "tree".into();
+                ans["index"] = self.inspect_index(ptr,2)?;
+            },
+            StorageType::SubDirEntry => {
+                ans["storage_type"] = "directory".into();
+                let mut chain = Vec::new();
+                let mut curr = ptr;
+                while curr>0 {
+                    if chain.contains(&curr) {
+                        error!("directory blocks form a loop");
+                        return Err(Box::new(Error::IOError));
+                    }
+                    chain.push(curr);
+                    curr = self.get_directory(curr as usize)?.next();
+                }
+                ans["directory"] = chain.into();
+            },
+            _ => {
+                error!("cannot inspect storage type of {}",entry.name());
+                return Err(Box::new(Error::FileTypeMismatch));
+            }
+        }
+        if let Some(spaces) = indent {
+            Ok(json::stringify_pretty(ans,spaces))
+        } else {
+            Ok(json::stringify(ans))
+        }
+    }
+    fn wipe_deleted(&mut self) -> Result<usize,DYNERR> {
+        self.wipe_deleted_node(VOL_KEY_BLOCK)
+    }
+    fn append(&mut self,path: &str,dat: &[u8]) -> Result<usize,DYNERR> {
+        self.write_into(path,None,dat)
+    }
+    fn patch(&mut self,path: &str,offset: usize,dat: &[u8]) -> Result<usize,DYNERR> {
+        self.write_into(path,Some(offset),dat)
+    }
+    fn truncate(&mut self,path: &str,len: usize) -> Result<usize,DYNERR> {
+        let (loc,mut entry) = self.find_writable_file(path)?;
+        if len > entry.eof() {
+            error!("cannot truncate {} bytes to {}",entry.eof(),len);
+            return Err(Box::new(Error::Range));
+        }
+        let snap = super::DiskFS::snapshot(self);
+        let result = match self.cut_file(&mut entry,len) {
+            Ok(()) => {
+                entry.set_eof(len);
+                entry.set_mod_time(self.now());
+                self.write_entry(&loc,&entry)
+            },
+            Err(e) => Err(e)
+        };
+        if let Err(e) = result {
+            super::DiskFS::rollback(self,&snap)?;
+            return Err(e);
+        }
+        Ok(len)
+    }
+    fn rollback(&mut self,snap: &img::Snapshot) -> STDRESULT {
+        self.img.rollback(snap)?;
+        self.maybe_bitmap = None;
+        Ok(())
+    }
     fn get_img(&mut self) -> &mut Box<dyn img::DiskImage> {
         self.writeback_bitmap_buffer().expect("could not write back bitmap buffer");
         &mut self.img
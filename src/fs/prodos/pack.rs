@@ -8,12 +8,25 @@ use super::super::{Packing,TextConversion,FileImage,UnpackedData,Records};
 use crate::commands::ItemType;
 use crate::{STDRESULT,DYNERR};
 
+/// Pack the time into the ProDOS format.  Per ProDOS technical note 28, the 7 bit year field
+/// holds 40..99 for 1940..1999 and 0..39 for 2000..2039.  Years outside this range are pegged
+/// to the nearest representable year.
 pub fn pack_time(time: Option<chrono::NaiveDateTime>) -> [u8;4] {
     let now = match time {
         Some(t) => t,
-        _ => chrono::Local::now().naive_local()
+        _ => crate::now(false)
+    };
+    let year = match now.year() {
+        y if y < 1940 => {
+            log::warn!("ProDOS year {} is pegged to 1940",y);
+            1940
+        },
+        y if y > 2039 => {
+            log::warn!("ProDOS year {} is pegged to 2039",y);
+            2039
+        },
+        y => y as u32
     };
-    let (_is_common_era,year) = now.year_ce();
     let packed_date = (now.day() + (now.month() << 5) + (year%100 << 9)) as u16;
     let packed_time = (now.minute() + (now.hour() << 8)) as u16;
     let bytes_date = u16::to_le_bytes(packed_date);
@@ -21,16 +34,17 @@ pub fn pack_time(time: Option<chrono::NaiveDateTime>) -> [u8;4] {
     return [bytes_date[0],bytes_date[1],bytes_time[0],bytes_time[1]];
 }
 
+/// Unpack the ProDOS time, `None` if there is no time stamp or it is invalid.
+/// The year field is decoded according to ProDOS technical note 28, i.e., 40..99 is 1940..1999
+/// and 0..39 is 2000..2039.  In addition, 100..127 is 2000..2027, which is how GS/OS
+/// and some utilities extended the year.
 pub fn unpack_time(prodos_date_time: [u8;4]) -> Option<chrono::NaiveDateTime> {
     let date = u16::from_le_bytes([prodos_date_time[0],prodos_date_time[1]]);
     let time = u16::from_le_bytes([prodos_date_time[2],prodos_date_time[3]]);
-    let yearmod100 = date >> 9;
-    // Suppose the earliest date stamp we can find originates from the year before
-    // SOS was released, i.e., 1979.  Use this to help decide the century.
-    // This scheme will work until 2079.
-    let year = match yearmod100 < 79 {
-        true => 2000 + yearmod100,
-        false => 1900 + yearmod100
+    let year7 = date >> 9;
+    let year = match year7 {
+        0..40 => 2000 + year7,
+        _ => 1900 + year7
     };
     let month = (date >> 5) & 15;
     let day = date & 31;
@@ -87,6 +101,33 @@ pub fn file_name_to_string(nibs: u8, fname: [u8;15]) -> String {
     crate::escaped_ascii_from_bytes(&fname[0..name_len as usize].to_vec(), true, false)
 }
 
+/// GS/OS case flags for a name, stored little endian in the version and min version bytes.
+/// Bit 15 enables the flags, bit 14-i is set if character i is lower case.
+/// Names with no lower case letters give 0, so that older ProDOS versions see nothing new.
+pub fn case_flags(name: &str) -> u16 {
+    if !name.chars().any(|c| c.is_ascii_lowercase()) {
+        return 0;
+    }
+    let mut ans = 0x8000;
+    for (i,c) in name.chars().take(15).enumerate() {
+        if c.is_ascii_lowercase() {
+            ans |= 0x4000 >> i;
+        }
+    }
+    ans
+}
+
+/// Apply GS/OS case flags to a name, if the flags are not enabled the name is returned as is.
+pub fn apply_case_flags(name: &str,flags: u16) -> String {
+    if flags & 0x8000 == 0 {
+        return name.to_string();
+    }
+    name.chars().enumerate().map(|(i,c)| match i < 15 && flags & (0x4000 >> i) > 0 {
+        true => c.to_ascii_lowercase(),
+        false => c
+    }).collect()
+}
+
 /// Convert storage type and String to (stor_len_nibs,fname).
 /// Panics if the string is not a valid ProDOS name.
 pub fn string_to_file_name(stype: &StorageType, s: &str) -> (u8,[u8;15]) {
@@ -126,6 +167,12 @@ impl Packing for Packer {
         }
     }
 
+    fn set_case(&self,fimg: &mut FileImage) {
+        let name = fimg.full_path.rsplit('/').next().unwrap_or("");
+        let flags = u16::to_le_bytes(case_flags(name));
+        fimg.version = vec![flags[0]];
+        fimg.min_version = vec![flags[1]];
+    }
     fn get_load_address(&self,fimg: &FileImage) -> u16 {
         fimg.get_aux() as u16
     }
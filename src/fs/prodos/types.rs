@@ -153,6 +153,8 @@ pub enum StorageType {
     Sapling = 0x02,
     Tree = 0x03,
     Pascal = 0x04,
+    /// GS/OS file with a data fork and a resource fork
+    Extended = 0x05,
     SubDirEntry = 0x0d,
     SubDirHeader = 0x0e,
     VolDirHeader = 0x0f
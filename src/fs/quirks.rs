@@ -0,0 +1,102 @@
+//! ## File System Quirks
+//!
+//! Switches for behaviors that differ between the original operating systems, their emulators,
+//! and the tools that produced existing images.  Each switch names something a2kit used to do
+//! unconditionally, and the default profile reproduces that, so nothing changes unless a profile
+//! is loaded.  A profile is a JSON object with a section for each file system, e.g.,
+//! ```json
+//! {
+//!     "dos3x": { "sector_order": "ascending", "jump_for_new_file": false },
+//!     "prodos": { "swap_index_halves": false },
+//!     "fat": { "root_entries": 64 }
+//! }
+//! ```
+//! Sections and switches can be left out, unknown ones are an error.
+//! A profile is given to a file system when it is created, see `LoadOptions::quirks`.
+
+use crate::DYNERR;
+
+#[derive(thiserror::Error,Debug)]
+pub enum Error {
+    #[error("quirks profile is not a JSON object")]
+    NotAnObject,
+    #[error("unknown quirk")]
+    UnknownQuirk,
+    #[error("quirk has the wrong kind of value")]
+    BadValue
+}
+
+/// Order in which DOS 3.x searches the sectors of a track for a free one
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum SectorOrder {
+    /// highest sector first, as DOS 3.3 does
+    Descending,
+    /// lowest sector first
+    Ascending
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Quirks {
+    /// DOS 3.x: order in which free sectors are taken within a track
+    pub dos3x_sector_order: SectorOrder,
+    /// DOS 3.x: start a new file on the track after the last one allocated, rather than the same track
+    pub dos3x_jump_for_new_file: bool,
+    /// ProDOS: swap the halves of index blocks when a file is deleted, as ProDOS does
+    pub prodos_swap_index_halves: bool,
+    /// FAT: count of root directory entries for new disks, `None` means the standard count for the disk kind
+    pub fat_root_entries: Option<u16>
+}
+
+impl Quirks {
+    pub const DEFAULT: Self = Self {
+        dos3x_sector_order: SectorOrder::Descending,
+        dos3x_jump_for_new_file: true,
+        prodos_swap_index_halves: true,
+        fat_root_entries: None
+    };
+    /// Parse a profile, starting from the defaults.  Unknown sections or switches are an error.
+    pub fn from_json(json_str: &str) -> Result<Self,DYNERR> {
+        let parsed = json::parse(json_str)?;
+        if !parsed.is_object() {
+            log::error!("quirks profile should be a JSON object");
+            return Err(Box::new(Error::NotAnObject));
+        }
+        let mut ans = Self::DEFAULT;
+        for (fs,section) in parsed.entries() {
+            if !section.is_object() {
+                log::error!("quirks section `{}` should be a JSON object",fs);
+                return Err(Box::new(Error::NotAnObject));
+            }
+            for (key,val) in section.entries() {
+                let bad_value = || -> DYNERR {
+                    log::error!("unexpected value {} for quirk `{}.{}`",val,fs,key);
+                    Box::new(Error::BadValue)
+                };
+                match (fs,key) {
+                    ("dos3x","sector_order") => ans.dos3x_sector_order = match val.as_str() {
+                        Some("descending") => SectorOrder::Descending,
+                        Some("ascending") => SectorOrder::Ascending,
+                        _ => return Err(bad_value())
+                    },
+                    ("dos3x","jump_for_new_file") => ans.dos3x_jump_for_new_file = val.as_bool().ok_or_else(bad_value)?,
+                    ("prodos","swap_index_halves") => ans.prodos_swap_index_halves = val.as_bool().ok_or_else(bad_value)?,
+                    ("fat","root_entries") => ans.fat_root_entries = match val.is_null() {
+                        true => None,
+                        false => Some(val.as_u16().ok_or_else(bad_value)?)
+                    },
+                    _ => {
+                        log::error!("unknown quirk `{}.{}`",fs,key);
+                        return Err(Box::new(Error::UnknownQuirk));
+                    }
+                }
+            }
+        }
+        Ok(ans)
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
@@ -0,0 +1,43 @@
+//! ### RDOS catalog structures
+//!
+//! The catalog is a flat list of fixed length entries, with the DiskStruct trait.
+
+use a2kit_macro::{DiskStructError,DiskStruct};
+use a2kit_macro_derive::DiskStruct;
+
+#[derive(DiskStruct,Copy,Clone)]
+pub struct Entry {
+    /// negative ASCII padded with spaces, first byte is 0x80 if deleted, 0 if past the end
+    pub name: [u8;24],
+    pub file_type: u8,
+    pub sectors: u8,
+    pub load_addr: [u8;2],
+    pub length: [u8;2],
+    /// absolute sector index, i.e., track times sectors per track plus sector
+    pub start: [u8;2]
+}
+
+impl Entry {
+    pub fn is_end(&self) -> bool {
+        self.name[0] == 0
+    }
+    pub fn is_deleted(&self) -> bool {
+        self.name[0] == 0x80
+    }
+    pub fn name(&self) -> String {
+        let ascii: Vec<u8> = self.name.iter().map(|c| c & 0x7f).collect();
+        String::from_utf8_lossy(&ascii).trim_end().to_string()
+    }
+    pub fn type_name(&self) -> String {
+        match self.file_type {
+            c if c > 0xa0 && c < 0xff => char::from(c & 0x7f).to_string(),
+            c => "$".to_string() + &hex::encode_upper([c])
+        }
+    }
+    pub fn start(&self) -> usize {
+        u16::from_le_bytes(self.start) as usize
+    }
+    pub fn length(&self) -> usize {
+        u16::from_le_bytes(self.length) as usize
+    }
+}
@@ -0,0 +1,331 @@
+//! ## RDOS file system module
+//!
+//! RDOS is the operating system SSI put on its game disks.  Files are contiguous, and the catalog
+//! is a flat list on track 1, sectors 0 through 10, where each entry gives the name, type, sector
+//! count, load address, length, and starting sector.  The first entry describes RDOS itself,
+//! and its name, e.g. `RDOS 2.1 COPYRIGHT 1981`, is taken as the volume label.
+//!
+//! There are two variants:
+//! * 13 sector disks, with sectors in DOS 3.2 order
+//! * 16 sector disks, with sectors in ProDOS order
+//!
+//! This module can read files, but cannot change the disk.
+
+pub mod types;
+mod directory;
+mod pack;
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use a2kit_macro::DiskStruct;
+use types::*;
+use pack::*;
+use directory::*;
+use super::Block;
+use crate::img;
+use crate::{STDRESULT,DYNERR};
+
+pub const FS_NAME: &str = "a2 rdos";
+
+pub fn new_fimg(chunk_len: usize,name: &str) -> Result<super::FileImage,DYNERR> {
+    if !is_name_valid(name) {
+        return Err(Box::new(Error::SyntaxError));
+    }
+    Ok(super::FileImage {
+        fimg_version: super::FileImage::fimg_version(),
+        file_system: String::from(FS_NAME),
+        fs_type: vec![0],
+        aux: vec![0;2],
+        eof: vec![0;4],
+        accessed: vec![],
+        created: vec![],
+        modified: vec![],
+        access: vec![],
+        version: vec![],
+        min_version: vec![],
+        chunk_len,
+        full_path: name.to_string(),
+        chunks: HashMap::new()
+    })
+}
+
+#[derive(Default)]
+pub struct Packer {
+}
+
+/// The primary interface for disk operations.
+pub struct Disk
+{
+    /// sectors per track, 13 or 16
+    sectors: usize,
+    img: Box<dyn img::DiskImage>
+}
+
+/// Read absolute sector `idx` from the image, `sectors` is the number of sectors per track.
+fn read_sector(img: &mut Box<dyn img::DiskImage>,sectors: usize,idx: usize) -> Result<Vec<u8>,DYNERR> {
+    let [track,sector] = [idx / sectors, idx % sectors];
+    match sectors {
+        13 => img.read_block(Block::D13([track,sector])),
+        _ => {
+            let buf = img.read_block(Block::PO(track*8 + sector/2))?;
+            let offset = (sector % 2) * SECTOR_SIZE;
+            Ok(buf[offset..offset+SECTOR_SIZE].to_vec())
+        }
+    }
+}
+
+/// Get the catalog entries that are in use, in order
+fn get_entries(img: &mut Box<dyn img::DiskImage>,sectors: usize) -> Result<Vec<Entry>,DYNERR> {
+    let mut ans = Vec::new();
+    for sec in 0..CATALOG_SECTORS {
+        let buf = read_sector(img,sectors,CATALOG_TRACK*sectors + sec)?;
+        for chunk in buf.chunks(ENTRY_SIZE) {
+            let entry = Entry::from_bytes(chunk)?;
+            if entry.is_end() {
+                return Ok(ans);
+            }
+            if !entry.is_deleted() {
+                ans.push(entry);
+            }
+        }
+    }
+    Ok(ans)
+}
+
+impl Disk
+{
+    /// Create a disk file system using the given image as storage.
+    /// The DiskFS takes ownership of the image.
+    pub fn from_img(img: Box<dyn img::DiskImage>) -> Result<Self,DYNERR> {
+        let sectors = match img.kind() {
+            img::names::A2_DOS32_KIND => 13,
+            _ => 16
+        };
+        Ok(Self {
+            sectors,
+            img
+        })
+    }
+    fn test_entries(img: &mut Box<dyn img::DiskImage>,sectors: usize) -> bool {
+        let entries = match get_entries(img,sectors) {
+            Ok(e) => e,
+            Err(_) => return false
+        };
+        if entries.is_empty() || entries[0].name[0..5] != SIGNATURE {
+            log::debug!("RDOS signature not found");
+            return false;
+        }
+        let total = img.track_count() * sectors;
+        for entry in &entries {
+            if entry.start() + entry.sectors as usize > total {
+                log::debug!("RDOS entry {} out of bounds",entry.name());
+                return false;
+            }
+            if entry.name.iter().any(|c| *c < 0xa0) {
+                log::debug!("RDOS entry has bad name");
+                return false;
+            }
+        }
+        true
+    }
+    /// Test an image for the RDOS file system.
+    pub fn test_img(img: &mut Box<dyn img::DiskImage>) -> bool {
+        if img.track_count() != 35 {
+            log::debug!("track count is unexpected");
+            return false;
+        }
+        let old_kind = img.kind();
+        img.change_kind(img::names::A2_DOS32_KIND);
+        if Self::test_entries(img,13) {
+            return true;
+        }
+        img.change_kind(img::names::A2_DOS33_KIND);
+        if Self::test_entries(img,16) {
+            return true;
+        }
+        img.change_kind(old_kind);
+        false
+    }
+    fn get_entries(&mut self) -> Result<Vec<Entry>,DYNERR> {
+        get_entries(&mut self.img,self.sectors)
+    }
+    fn total_sectors(&self) -> usize {
+        self.img.track_count() * self.sectors
+    }
+    fn get_file_entry(&mut self,name: &str) -> Result<Option<Entry>,DYNERR> {
+        Ok(self.get_entries()?.into_iter().find(|e| e.name().eq_ignore_ascii_case(name)))
+    }
+    fn read_file(&mut self,name: &str) -> Result<super::FileImage,DYNERR> {
+        match self.get_file_entry(name)? {
+            Some(entry) => {
+                let mut ans = new_fimg(SECTOR_SIZE,&entry.name())?;
+                for i in 0..entry.sectors as usize {
+                    ans.chunks.insert(i,read_sector(&mut self.img,self.sectors,entry.start() + i)?);
+                }
+                ans.fs_type = vec![entry.file_type];
+                ans.aux = entry.load_addr.to_vec();
+                ans.eof = u32::to_le_bytes(entry.length() as u32).to_vec();
+                Ok(ans)
+            },
+            None => Err(Box::new(Error::FileNotFound))
+        }
+    }
+    fn read_only(&self) -> STDRESULT {
+        log::error!("RDOS disks can only be read");
+        Err(Box::new(Error::WriteProtected))
+    }
+    /// Native catalog in parts, see `DiskFS::catalog_native`
+    fn native_catalog(&mut self,path: &str) -> Result<super::NativeCatalog,DYNERR> {
+        let entries = self.get_entries()?;
+        let header = format!("\n{}\n\n",entries[0].name());
+        let rows = entries[1..].iter().map(|entry| format!(" {} {:03} {}",entry.type_name(),entry.sectors,entry.name())).collect();
+        let items = super::DiskFS::catalog_items(self,path)?;
+        super::NativeCatalog::pair(header,items,rows,"\n".to_string())
+    }
+}
+
+impl super::DiskFS for Disk {
+    fn new_fimg(&self,chunk_len: Option<usize>,_set_time: bool,path: &str) -> Result<super::FileImage,DYNERR> {
+        match chunk_len {
+            Some(l) => new_fimg(l,path),
+            None => new_fimg(SECTOR_SIZE,path)
+        }
+    }
+    fn stat(&mut self) -> Result<super::Stat,DYNERR> {
+        let entries = self.get_entries()?;
+        let used: usize = entries.iter().map(|e| e.sectors as usize).sum();
+        let total = self.total_sectors();
+        Ok(super::Stat {
+            fs_name: FS_NAME.to_string(),
+            label: entries[0].name(),
+            users: Vec::new(),
+            block_size: SECTOR_SIZE,
+            block_beg: 0,
+            block_end: total,
+            free_blocks: total.saturating_sub(used),
+            raw: "".to_string()
+        })
+    }
+    fn catalog_to_string(&mut self,path: &str) -> Result<String,DYNERR> {
+        Ok(self.native_catalog(path)?.render(&super::CatalogFilter::default(),super::CatalogOrder::Directory))
+    }
+    fn catalog_native(&mut self,path: &str) -> Result<Option<super::NativeCatalog>,DYNERR> {
+        Ok(Some(self.native_catalog(path)?))
+    }
+    fn catalog_to_vec(&mut self,path: &str) -> Result<Vec<String>,DYNERR> {
+        Ok(self.catalog_items(path)?.into_iter().map(|x| x.row).collect())
+    }
+    fn catalog_items(&mut self,path: &str) -> Result<Vec<super::CatalogItem>,DYNERR> {
+        if path!="/" && !path.is_empty() {
+            return Err(Box::new(Error::FileNotFound));
+        }
+        let mut ans = Vec::new();
+        for entry in self.get_entries()?.iter().skip(1) {
+            let typ = entry.type_name();
+            ans.push(super::CatalogItem {
+                row: super::universal_row(&typ,entry.sectors as usize,&entry.name()),
+                name: entry.name(),
+                typ,
+                blocks: entry.sectors as usize,
+                modified: None,
+                addr: entry.start()
+            });
+        }
+        Ok(ans)
+    }
+    fn glob_with(&mut self,pattern: &str,opt: super::GlobOptions) -> Result<Vec<String>,DYNERR> {
+        let glob = super::NameMatcher::new(pattern,opt)?;
+        Ok(self.get_entries()?.iter().skip(1).map(|e| e.name()).filter(|n| glob.is_match(n)).collect())
+    }
+    fn tree(&mut self,include_meta: bool,indent: Option<u16>) -> Result<String,DYNERR> {
+        let entries = self.get_entries()?;
+        let mut tree = json::JsonValue::new_object();
+        tree["file_system"] = json::JsonValue::String(FS_NAME.to_string());
+        tree["files"] = json::JsonValue::new_object();
+        tree["label"] = json::JsonValue::new_object();
+        tree["label"]["name"] = json::JsonValue::String(entries[0].name());
+        for entry in &entries[1..] {
+            let key = entry.name();
+            tree["files"][&key] = json::JsonValue::new_object();
+            if include_meta {
+                tree["files"][&key]["meta"] = json::JsonValue::new_object();
+                let meta = &mut tree["files"][&key]["meta"];
+                meta["type"] = json::JsonValue::String(hex::encode_upper([entry.file_type]));
+                meta["aux"] = json::JsonValue::String(hex::encode_upper(entry.load_addr));
+                meta["eof"] = json::JsonValue::Number(entry.length().into());
+                meta["blocks"] = json::JsonValue::Number(entry.sectors.into());
+            }
+        }
+        if let Some(spaces) = indent {
+            Ok(json::stringify_pretty(tree,spaces))
+        } else {
+            Ok(json::stringify(tree))
+        }
+    }
+    fn create(&mut self,_path: &str) -> STDRESULT {
+        self.read_only()
+    }
+    fn delete(&mut self,_path: &str) -> STDRESULT {
+        self.read_only()
+    }
+    fn rename(&mut self,_path: &str,_name: &str) -> STDRESULT {
+        self.read_only()
+    }
+    fn protect(&mut self,_path: &str,_password: &str,_read: bool,_write: bool,_delete: bool) -> STDRESULT {
+        self.read_only()
+    }
+    fn unprotect(&mut self,_path: &str) -> STDRESULT {
+        self.read_only()
+    }
+    fn lock(&mut self,_path: &str) -> STDRESULT {
+        self.read_only()
+    }
+    fn unlock(&mut self,_path: &str) -> STDRESULT {
+        self.read_only()
+    }
+    fn retype(&mut self,_path: &str,_new_type: &str,_sub_type: &str) -> STDRESULT {
+        self.read_only()
+    }
+    fn exists(&mut self,path: &str) -> Result<bool,DYNERR> {
+        Ok(self.get_file_entry(path)?.is_some())
+    }
+    fn get(&mut self,path: &str) -> Result<super::FileImage,DYNERR> {
+        self.read_file(path)
+    }
+    fn put(&mut self,_fimg: &super::FileImage) -> Result<usize,DYNERR> {
+        self.read_only()?;
+        Ok(0)
+    }
+    fn read_block(&mut self,num: &str) -> Result<Vec<u8>,DYNERR> {
+        match usize::from_str(num) {
+            Ok(sector) if sector < self.total_sectors() => read_sector(&mut self.img,self.sectors,sector),
+            Ok(_) => Err(Box::new(Error::Range)),
+            Err(e) => Err(Box::new(e))
+        }
+    }
+    fn write_block(&mut self,_num: &str,_dat: &[u8]) -> Result<usize,DYNERR> {
+        self.read_only()?;
+        Ok(0)
+    }
+    fn standardize(&mut self,_ref_con: u16) -> HashMap<Block,Vec<usize>> {
+        HashMap::new()
+    }
+    fn compare(&mut self,path: &std::path::Path,_ignore: &HashMap<Block,Vec<usize>>) {
+        let mut emulator_disk = crate::create_fs_from_file(path.to_str().unwrap()).expect("read error");
+        for sector in 0..self.total_sectors() {
+            let actual = read_sector(&mut self.img,self.sectors,sector).expect("bad sector access");
+            let expected = emulator_disk.read_block(&sector.to_string()).expect("bad sector access");
+            assert_eq!(actual,expected," at sector {}",sector);
+        }
+    }
+    fn all_blocks(&mut self) -> Result<Vec<Block>,DYNERR> {
+        let tracks = self.img.track_count();
+        Ok(match self.sectors {
+            13 => (0..tracks*13).map(|i| Block::D13([i/13,i%13])).collect(),
+            _ => (0..tracks*8).map(Block::PO).collect()
+        })
+    }
+    fn get_img(&mut self) -> &mut Box<dyn img::DiskImage> {
+        &mut self.img
+    }
+}
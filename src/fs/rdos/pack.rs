@@ -0,0 +1,124 @@
+use super::types::*;
+use super::Packer;
+use super::super::{Packing,FileImage,UnpackedData,Records,TextConversion};
+use crate::fs::dos3x::types::TextConverter;
+use crate::commands::ItemType;
+use crate::{STDRESULT,DYNERR};
+
+/// Names are negative ASCII, and may have spaces, but not commas
+pub fn is_name_valid(s: &str) -> bool {
+    if s.is_empty() || s.len() > 24 {
+        log::info!("name should have 1 to 24 characters");
+        return false;
+    }
+    if s.chars().any(|c| !c.is_ascii() || c.is_ascii_control() || c==',') {
+        log::info!("bad file name character");
+        return false;
+    }
+    true
+}
+
+impl Packer {
+    pub fn new() -> Self {
+        Self {}
+    }
+    fn verify(fimg: &FileImage) -> STDRESULT {
+        if fimg.file_system != super::FS_NAME {
+            return Err(Box::new(Error::FileTypeMismatch));
+        }
+        Ok(())
+    }
+    fn read_only() -> STDRESULT {
+        log::error!("RDOS file images cannot be packed");
+        Err(Box::new(Error::WriteProtected))
+    }
+    /// data up to the length given in the catalog
+    fn truncated(fimg: &FileImage) -> Vec<u8> {
+        let mut ans = fimg.sequence();
+        ans.truncate(fimg.get_eof());
+        ans
+    }
+}
+
+impl Packing for Packer {
+    fn set_path(&self,fimg: &mut FileImage,name: &str) -> STDRESULT {
+        if is_name_valid(name) {
+            fimg.full_path = name.to_string();
+            Ok(())
+        } else {
+            Err(Box::new(Error::SyntaxError))
+        }
+    }
+    fn get_load_address(&self,fimg: &FileImage) -> u16 {
+        match fimg.aux.len() {
+            2 => u16::from_le_bytes([fimg.aux[0],fimg.aux[1]]),
+            _ => 0
+        }
+    }
+    fn unpack(&self,fimg: &FileImage) -> Result<UnpackedData,DYNERR> {
+        Self::verify(fimg)?;
+        match fimg.fs_type[0] {
+            t if t == FileType::Text as u8 => {
+                let maybe = self.unpack_txt(fimg)?;
+                if super::super::null_fraction(&maybe) < 0.01 {
+                    Ok(UnpackedData::Text(maybe))
+                } else {
+                    Ok(UnpackedData::Binary(self.unpack_raw(fimg,true)?))
+                }
+            },
+            t if t == FileType::Applesoft as u8 => Ok(UnpackedData::Binary(self.unpack_tok(fimg)?)),
+            _ => Ok(UnpackedData::Binary(self.unpack_bin(fimg)?))
+        }
+    }
+    fn pack_raw(&self,_fimg: &mut FileImage,_dat: &[u8]) -> STDRESULT {
+        Self::read_only()
+    }
+    fn unpack_raw(&self,fimg: &FileImage,trunc: bool) -> Result<Vec<u8>,DYNERR> {
+        Self::verify(fimg)?;
+        match trunc {
+            true => Ok(Self::truncated(fimg)),
+            false => Ok(fimg.sequence())
+        }
+    }
+    fn pack_bin(&self,_fimg: &mut FileImage,_dat: &[u8],_load_addr: Option<usize>,_trailing: Option<&[u8]>) -> STDRESULT {
+        Self::read_only()
+    }
+    fn unpack_bin(&self,fimg: &FileImage) -> Result<Vec<u8>,DYNERR> {
+        Self::verify(fimg)?;
+        Ok(Self::truncated(fimg))
+    }
+    fn pack_txt(&self,_fimg: &mut FileImage,_txt: &str) -> STDRESULT {
+        Self::read_only()
+    }
+    fn unpack_txt(&self,fimg: &FileImage) -> Result<String,DYNERR> {
+        Self::verify(fimg)?;
+        let dat = Self::truncated(fimg);
+        let end = dat.iter().position(|b| *b==0).unwrap_or(dat.len());
+        let encoder = TextConverter::new(vec![0x8d]);
+        match encoder.to_utf8(&dat[0..end]) {
+            Some(txt) => Ok(txt),
+            None => Err(Box::new(Error::FileTypeMismatch))
+        }
+    }
+    fn pack_tok(&self,_fimg: &mut FileImage,_tok: &[u8],_lang: ItemType,_trailing: Option<&[u8]>) -> STDRESULT {
+        Self::read_only()
+    }
+    fn unpack_tok(&self,fimg: &FileImage) -> Result<Vec<u8>,DYNERR> {
+        Self::verify(fimg)?;
+        Ok(Self::truncated(fimg))
+    }
+    fn pack_rec_str(&self,_fimg: &mut FileImage,_json: &str) -> STDRESULT {
+        Self::read_only()
+    }
+    fn unpack_rec_str(&self,_fimg: &FileImage,_rec_len: Option<usize>,_indent: Option<u16>) -> Result<String,DYNERR> {
+        log::error!("RDOS does not have random access text files");
+        Err(Box::new(Error::FileTypeMismatch))
+    }
+    fn pack_rec(&self,_fimg: &mut FileImage,_recs: &Records) -> STDRESULT {
+        Self::read_only()
+    }
+    fn unpack_rec(&self,_fimg: &FileImage,_rec_len: Option<usize>) -> Result<Records,DYNERR> {
+        log::error!("RDOS does not have random access text files");
+        Err(Box::new(Error::FileTypeMismatch))
+    }
+}
@@ -0,0 +1,29 @@
+pub const SECTOR_SIZE: usize = 256;
+pub const CATALOG_TRACK: usize = 1;
+pub const CATALOG_SECTORS: usize = 11;
+pub const ENTRY_SIZE: usize = 32;
+/// The first catalog entry describes RDOS itself, and its name starts with this, in negative ASCII.
+pub const SIGNATURE: [u8;5] = [0xd2,0xc4,0xcf,0xd3,0xa0];
+
+/// Enumerates RDOS errors.  The `Display` trait will print the long message.
+#[derive(thiserror::Error,Debug)]
+pub enum Error {
+    #[error("FILE NOT FOUND")]
+    FileNotFound,
+    #[error("FILE TYPE MISMATCH")]
+    FileTypeMismatch,
+    #[error("RANGE ERROR")]
+    Range,
+    #[error("SYNTAX ERROR")]
+    SyntaxError,
+    #[error("WRITE PROTECTED")]
+    WriteProtected
+}
+
+/// Enumerates the file types, the code is the negative ASCII letter shown in the catalog.
+pub enum FileType {
+    Applesoft = 0xc1,
+    Binary = 0xc2,
+    SaveGame = 0xd3,
+    Text = 0xd4
+}
@@ -0,0 +1,53 @@
+//! ## Catalog rendering
+//!
+//! Pieces shared by the native catalogs of the file systems.  Colors go through the `colored` crate,
+//! which leaves them out if `NO_COLOR` is set or stdout is not a terminal, unless `CLICOLOR_FORCE` is set.
+//! The wide formats fit as many columns as the terminal allows, up to the number the original system shows.
+//! The plain format is the same for every file system, for use in scripts.
+
+#[cfg(feature = "browse")]
+use std::io::IsTerminal;
+use super::CatalogItem;
+
+/// Width assumed when the terminal width cannot be found, as on the original systems
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// Width of the terminal, `COLUMNS` takes precedence.  If stdout is not a terminal the default is used.
+/// Without the `browse` feature the terminal is not queried, so only `COLUMNS` is used.
+pub fn terminal_width() -> usize {
+    if let Some(cols) = std::env::var("COLUMNS").ok().and_then(|s| s.parse::<usize>().ok()) {
+        if cols > 0 {
+            return cols;
+        }
+    }
+    #[cfg(feature = "browse")]
+    if std::io::stdout().is_terminal() {
+        if let Ok((cols,_)) = ratatui::crossterm::terminal::size() {
+            if cols > 0 {
+                return cols as usize;
+            }
+        }
+    }
+    DEFAULT_WIDTH
+}
+
+/// Number of cells `cell_width` wide, separated by `gap` spaces, that fit in the terminal,
+/// but no more than `native`, and at least 1.
+pub fn fit_columns(native: usize,cell_width: usize,gap: usize) -> usize {
+    let fit = (terminal_width() + gap) / (cell_width + gap);
+    fit.clamp(1,native.max(1))
+}
+
+/// Catalog with tab separated columns NAME, TYPE, BLOCKS, and MODIFIED, headed by the column names.
+/// Blocks are in the file system's own units, and a missing time is `-`.
+pub fn plain(items: &[CatalogItem]) -> String {
+    let mut ans = String::from("NAME\tTYPE\tBLOCKS\tMODIFIED\n");
+    for item in items {
+        let modified = match item.modified {
+            Some(t) => t.format("%Y-%m-%dT%H:%M").to_string(),
+            None => "-".to_string()
+        };
+        ans += &format!("{}\t{}\t{}\t{}\n",item.name,item.typ,item.blocks,modified);
+    }
+    ans
+}
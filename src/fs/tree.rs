@@ -0,0 +1,119 @@
+//! ## Directory tree shaping
+//!
+//! Every file system produces the same JSON tree, see `DiskFS::tree`.  Here the tree can be cut
+//! to a depth, have sizes added up within each directory, or be flattened into a list of paths.
+//! This works on the JSON, so it is the same for every file system.
+
+use json::JsonValue;
+use super::TreeOptions;
+use crate::DYNERR;
+
+/// Totals for everything within a directory
+#[derive(Default,Clone,Copy)]
+struct Usage {
+    files: usize,
+    eof: usize,
+    blocks: usize
+}
+
+impl Usage {
+    fn add(&mut self,other: Usage) {
+        self.files += other.files;
+        self.eof += other.eof;
+        self.blocks += other.blocks;
+    }
+    fn to_json(self) -> JsonValue {
+        json::object! {
+            "files": self.files,
+            "eof": self.eof,
+            "blocks": self.blocks
+        }
+    }
+}
+
+/// Add a `du` object to every directory and return the totals for `files`.
+/// A directory counts its own blocks, but only files count toward `files` and `eof`.
+fn roll_up(files: &mut JsonValue) -> Usage {
+    let mut total = Usage::default();
+    for (_,node) in files.entries_mut() {
+        let own_blocks = node["meta"]["blocks"].as_usize().unwrap_or(0);
+        if node.has_key("files") {
+            let mut du = roll_up(&mut node["files"]);
+            du.blocks += own_blocks;
+            node["du"] = du.to_json();
+            total.add(du);
+        } else {
+            total.add(Usage { files: 1, eof: node["meta"]["eof"].as_usize().unwrap_or(0), blocks: own_blocks });
+        }
+    }
+    total
+}
+
+/// Keep `depth` levels of `files`, deeper directories are emptied and marked `pruned`
+fn prune(files: &mut JsonValue,depth: usize) {
+    for (_,node) in files.entries_mut() {
+        if node.has_key("files") {
+            if depth > 1 {
+                prune(&mut node["files"],depth-1);
+            } else if !node["files"].is_empty() {
+                node["files"] = JsonValue::new_object();
+                node["pruned"] = JsonValue::Boolean(true);
+            }
+        }
+    }
+}
+
+fn strip_meta(files: &mut JsonValue) {
+    for (_,node) in files.entries_mut() {
+        node.remove("meta");
+        if node.has_key("files") {
+            strip_meta(&mut node["files"]);
+        }
+    }
+}
+
+/// One path per line, directories end with `/`.  With `du` each line starts with the block count and a tab.
+fn paths(files: &JsonValue,prefix: &str,du: bool,ans: &mut Vec<String>) {
+    for (name,node) in files.entries() {
+        let path = [prefix,name].concat();
+        let line = match node.has_key("files") {
+            true => path.clone() + "/",
+            false => path.clone()
+        };
+        ans.push(match (du,node.has_key("du")) {
+            (true,true) => format!("{}\t{}",node["du"]["blocks"],line),
+            (true,false) => format!("{}\t{}",node["meta"]["blocks"].as_usize().unwrap_or(0),line),
+            _ => line
+        });
+        if node.has_key("files") {
+            paths(&node["files"],&(path + "/"),du,ans);
+        }
+    }
+}
+
+/// Shape the tree produced by `DiskFS::tree`, which must include the metadata if `opt.du` is set
+pub fn shape(tree: &str,opt: &TreeOptions) -> Result<String,DYNERR> {
+    let mut tree = json::parse(tree)?;
+    if opt.du {
+        let total = roll_up(&mut tree["files"]);
+        tree["du"] = total.to_json();
+    }
+    if let Some(depth) = opt.depth {
+        prune(&mut tree["files"],depth);
+    }
+    if opt.paths {
+        let mut ans = Vec::new();
+        paths(&tree["files"],"/",opt.du,&mut ans);
+        if opt.du {
+            ans.push(format!("{}\t/",tree["du"]["blocks"]));
+        }
+        return Ok(ans.join("\n"));
+    }
+    if !opt.meta {
+        strip_meta(&mut tree["files"]);
+    }
+    match opt.indent {
+        Some(spaces) => Ok(json::stringify_pretty(tree,spaces)),
+        None => Ok(json::stringify(tree))
+    }
+}
@@ -0,0 +1,224 @@
+//! ## Compressed wrappers
+//!
+//! Disk images are often distributed as `.gz` files, or inside `.zip` archives.
+//! This module unwraps such files when they are read, and wraps them again when they are saved,
+//! so that the rest of the library only sees the disk image.
+//!
+//! * gzip: the image is the one member, its type is taken from the file name with `.gz` removed
+//! * zip: the image is the member selected by name, or if no name is given, the only member
+//!   with a known image extension.  Other members are kept as they are when the archive is saved.
+//!
+//! A member can be selected on the command line by appending it to the archive path,
+//! as in `collection.zip:disk1.dsk`.
+//!
+//! The compression itself is done by the `flate2` and `zip` crates.
+
+use std::io::{Cursor,Read,Write};
+use chrono::{Datelike,Timelike};
+use log::{debug,error};
+use crate::{STDRESULT,DYNERR};
+
+#[derive(thiserror::Error,Debug)]
+pub enum Error {
+    #[error("decompressed data is too large")]
+    TooLarge,
+    #[error("member not found in archive")]
+    MemberNotFound,
+    #[error("archive holds more than one image")]
+    Ambiguous
+}
+
+/// Kinds of wrapper, determined by the file extension
+#[derive(PartialEq,Eq,Clone,Copy,Debug)]
+pub enum Wrapper {
+    Gzip,
+    Zip
+}
+
+/// The wrapper implied by the extension of `path`, if any
+pub fn wrapper(path: &str) -> Option<Wrapper> {
+    match path.rsplit('.').next() {
+        Some(ext) if ext.eq_ignore_ascii_case("gz") => Some(Wrapper::Gzip),
+        Some(ext) if ext.eq_ignore_ascii_case("zip") => Some(Wrapper::Zip),
+        _ => None
+    }
+}
+
+/// Split `archive.zip:member` into the archive path and the member, other paths have no member
+pub fn split_member(path: &str) -> (&str,Option<&str>) {
+    match path.to_ascii_lowercase().find(".zip:") {
+        Some(i) if i + 5 < path.len() => (&path[0..i+4],Some(&path[i+5..])),
+        _ => (path,None)
+    }
+}
+
+/// If `path` is a zip archive with no member selected, list the images it holds
+pub fn image_members(path: &str) -> Result<Option<Vec<String>>,DYNERR> {
+    match (wrapper(path),split_member(path).1) {
+        (Some(Wrapper::Zip),None) => Ok(Some(image_names(&zip_names(&std::fs::read(path)?)?))),
+        _ => Ok(None)
+    }
+}
+
+fn is_image_name(name: &str) -> bool {
+    match name.rsplit('.').next() {
+        Some(ext) => crate::KNOWN_FILE_EXTENSIONS.split(',').any(|x| ext.eq_ignore_ascii_case(x)),
+        None => false
+    }
+}
+
+/// Names of the members with a known disk image extension
+pub fn image_names(names: &[String]) -> Vec<String> {
+    names.iter().filter(|nm| is_image_name(nm)).map(|nm| nm.to_string()).collect()
+}
+
+/// Names of the members of a zip archive, in the order they are stored
+pub fn zip_names(dat: &[u8]) -> Result<Vec<String>,DYNERR> {
+    let archive = zip::ZipArchive::new(Cursor::new(dat))?;
+    Ok(archive.file_names().map(|nm| nm.to_string()).collect())
+}
+
+/// Name of the member matching `member`, ignoring case, or if `member` is None, the only image
+fn resolve(names: &[String], member: Option<&str>) -> Result<String,Error> {
+    match member {
+        Some(nm) => match names.iter().find(|e| *e == nm || e.eq_ignore_ascii_case(nm)) {
+            Some(e) => Ok(e.to_string()),
+            None => {
+                error!("{} is not in the archive",nm);
+                Err(Error::MemberNotFound)
+            }
+        },
+        None => {
+            let images = image_names(names);
+            match images.len() {
+                0 => {
+                    error!("no disk images in the archive");
+                    Err(Error::MemberNotFound)
+                },
+                1 => Ok(images[0].to_string()),
+                _ => {
+                    error!("select one of: {}",images.join(", "));
+                    Err(Error::Ambiguous)
+                }
+            }
+        }
+    }
+}
+
+/// Read at most `limit` bytes from a decoder
+fn read_limited(rdr: impl Read, limit: usize) -> Result<Vec<u8>,DYNERR> {
+    let mut ans = Vec::new();
+    rdr.take(limit as u64 + 1).read_to_end(&mut ans)?;
+    if ans.len() > limit {
+        return Err(Box::new(Error::TooLarge));
+    }
+    Ok(ans)
+}
+
+/// Decompress a gzip file, only the first member is used
+pub fn gunzip(dat: &[u8], limit: usize) -> Result<Vec<u8>,DYNERR> {
+    read_limited(flate2::read::GzDecoder::new(dat),limit)
+}
+
+/// Compress to a gzip file, the modification time is left out so the output is reproducible
+pub fn gzip(dat: &[u8], name: Option<&str>) -> Result<Vec<u8>,DYNERR> {
+    let mut builder = flate2::GzBuilder::new().mtime(0);
+    if let Some(nm) = name {
+        builder = builder.filename(nm);
+    }
+    let mut enc = builder.write(Vec::new(),flate2::Compression::default());
+    enc.write_all(dat)?;
+    Ok(enc.finish()?)
+}
+
+/// Decompress the member of a zip archive with the given name
+pub fn zip_get(dat: &[u8], name: &str, limit: usize) -> Result<Vec<u8>,DYNERR> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(dat))?;
+    let file = archive.by_name(name)?;
+    if file.size() > limit as u64 {
+        return Err(Box::new(Error::TooLarge));
+    }
+    read_limited(file,limit)
+}
+
+/// Compress `dat` into the member of the zip archive `existing` with the given name,
+/// replacing it if it exists.  Other members are copied without recompressing them.
+/// The member's time is fixed if `deterministic`, see `crate::now`.
+pub fn zip_put(existing: Option<&[u8]>, name: &str, dat: &[u8], deterministic: bool) -> Result<Vec<u8>,DYNERR> {
+    let now = crate::now(deterministic);
+    let time = zip::DateTime::from_date_and_time(u16::max(1980,now.year() as u16),now.month() as u8,now.day() as u8,
+        now.hour() as u8,now.minute() as u8,now.second() as u8).unwrap_or_default();
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .last_modified_time(time);
+    let mut wtr = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let mut replaced = false;
+    if let Some(existing) = existing {
+        let mut archive = zip::ZipArchive::new(Cursor::new(existing))?;
+        for i in 0..archive.len() {
+            let file = archive.by_index_raw(i)?;
+            if file.name() == name {
+                drop(file);
+                wtr.start_file(name,options)?;
+                wtr.write_all(dat)?;
+                replaced = true;
+            } else {
+                wtr.raw_copy_file(file)?;
+            }
+        }
+    }
+    if !replaced {
+        wtr.start_file(name,options)?;
+        wtr.write_all(dat)?;
+    }
+    Ok(wtr.finish()?.into_inner())
+}
+
+/// Unwrap the contents of the file at `path`, `dat` is the file as read.
+/// Returns the image data and the name the image goes by, whose extension identifies its type.
+/// Files that are not wrapped are returned as they are.
+pub fn read(path: &str, dat: Vec<u8>, member: Option<&str>, limit: usize) -> Result<(Vec<u8>,String),DYNERR> {
+    let (path,inner) = split_member(path);
+    let member = member.or(inner);
+    match wrapper(path) {
+        Some(Wrapper::Gzip) => {
+            debug!("decompressing gzip file {}",path);
+            Ok((gunzip(&dat, limit)?,path[0..path.len()-3].to_string()))
+        },
+        Some(Wrapper::Zip) => {
+            let name = resolve(&zip_names(&dat)?,member)?;
+            debug!("decompressing {} from {}",name,path);
+            Ok((zip_get(&dat,&name,limit)?,name))
+        },
+        None => Ok((dat,path.to_string()))
+    }
+}
+
+/// Write image data to the file at `path`, wrapping it as the extension calls for.
+/// If the file is a zip archive, only the selected member is replaced.
+/// The deterministic and device options are taken from `opts`, see `crate::SaveOptions`.
+pub fn write(path: &str, dat: &[u8], member: Option<&str>, opts: &crate::SaveOptions) -> STDRESULT {
+    let (path,inner) = split_member(path);
+    let member = member.or(inner);
+    match wrapper(path) {
+        Some(Wrapper::Gzip) => {
+            let file_name = std::path::Path::new(&path[0..path.len()-3]).file_name().map(|s| s.to_string_lossy().to_string());
+            std::fs::write(path, gzip(dat, file_name.as_deref())?)?;
+        },
+        Some(Wrapper::Zip) => {
+            let existing = std::fs::read(path).ok();
+            let names = match &existing {
+                Some(existing) => zip_names(existing)?,
+                None => Vec::new()
+            };
+            let name = match (member,resolve(&names,member)) {
+                (_,Ok(name)) => name,
+                (Some(nm),Err(Error::MemberNotFound)) => nm.to_string(),
+                (_,Err(e)) => return Err(Box::new(e))
+            };
+            std::fs::write(path, zip_put(existing.as_deref(),&name,dat,opts.deterministic)?)?;
+        },
+        None => crate::write_file(path, dat, opts.allow_devices)?
+    }
+    Ok(())
+}
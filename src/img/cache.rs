@@ -0,0 +1,130 @@
+//! ## Sector Cache
+//!
+//! Images that store track bits have to decode a whole track to find one sector.  Commands such as
+//! `catalog`, `tree`, and anything that globs read the directory sectors over and over, so the image
+//! keeps the decoded sectors here, throwing out the least recently used when the cache is full.
+//! Whatever changes the track bits has to invalidate the track.
+
+use std::collections::{BTreeMap,HashMap,HashSet};
+use super::DiskKind;
+
+/// Enough to hold every sector of an 800K disk
+pub const DEFAULT_CAPACITY: usize = 1600;
+
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+    pub invalidations: usize
+}
+
+/// Least recently used cache of decoded sectors, keyed by `[track,sector]`.
+/// Each sector is kept with the bit pointer just past it, so a hit leaves the head where a read would.
+/// Entries are only valid for the disk kind they were decoded with.
+pub struct SectorCache {
+    capacity: usize,
+    kind: Option<DiskKind>,
+    clock: u64,
+    sectors: HashMap<[usize;2],(u64,Vec<u8>,usize)>,
+    /// keys of `sectors` by the time they were last used
+    lru: BTreeMap<u64,[usize;2]>,
+    /// tracks that were decoded as a whole
+    tracks: HashSet<usize>,
+    /// sectors whose ID appears more than once on their track, these are never cached
+    volatile: HashSet<[usize;2]>,
+    stats: CacheStats
+}
+
+impl Default for SectorCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl SectorCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            kind: None,
+            clock: 0,
+            sectors: HashMap::new(),
+            lru: BTreeMap::new(),
+            tracks: HashSet::new(),
+            volatile: HashSet::new(),
+            stats: CacheStats::default()
+        }
+    }
+    /// If the kind changed, everything decoded so far is dropped
+    fn check_kind(&mut self,kind: DiskKind) {
+        if self.kind != Some(kind) {
+            self.sectors.clear();
+            self.lru.clear();
+            self.tracks.clear();
+            self.volatile.clear();
+            self.kind = Some(kind);
+        }
+    }
+    /// Get a sector and the bit pointer past it, counting a hit or miss
+    pub fn get(&mut self,kind: DiskKind,track: usize,sector: usize) -> Option<(Vec<u8>,usize)> {
+        self.check_kind(kind);
+        self.clock += 1;
+        match self.sectors.get_mut(&[track,sector]) {
+            Some((used,dat,bit_ptr)) => {
+                self.lru.remove(used);
+                *used = self.clock;
+                self.lru.insert(self.clock,[track,sector]);
+                self.stats.hits += 1;
+                Some((dat.clone(),*bit_ptr))
+            },
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+    /// Was the track decoded as a whole since it last changed, this does not count as a hit or miss
+    pub fn has_track(&mut self,kind: DiskKind,track: usize) -> bool {
+        self.check_kind(kind);
+        self.tracks.contains(&track)
+    }
+    /// Record that the track was decoded as a whole, whether or not any of its sectors could be kept
+    pub fn mark_track(&mut self,kind: DiskKind,track: usize) {
+        self.check_kind(kind);
+        self.tracks.insert(track);
+    }
+    /// Record that the sector ID appears more than once on the track, so it must always be read from the bits
+    pub fn mark_volatile(&mut self,kind: DiskKind,track: usize,sector: usize) {
+        self.check_kind(kind);
+        self.volatile.insert([track,sector]);
+    }
+    pub fn insert(&mut self,kind: DiskKind,track: usize,sector: usize,dat: Vec<u8>,bit_ptr: usize) {
+        self.check_kind(kind);
+        if self.capacity == 0 || self.volatile.contains(&[track,sector]) {
+            return;
+        }
+        if let Some((used,_,_)) = self.sectors.get(&[track,sector]) {
+            self.lru.remove(used);
+        } else if self.sectors.len() >= self.capacity {
+            if let Some((_,key)) = self.lru.pop_first() {
+                self.sectors.remove(&key);
+                self.stats.evictions += 1;
+            }
+        }
+        self.clock += 1;
+        self.sectors.insert([track,sector],(self.clock,dat,bit_ptr));
+        self.lru.insert(self.clock,[track,sector]);
+    }
+    /// Forget the sectors of a track, this must be called whenever the track bits change
+    pub fn invalidate_track(&mut self,track: usize) {
+        let before = self.sectors.len();
+        self.sectors.retain(|[t,_],_| *t!=track);
+        self.lru.retain(|_,[t,_]| *t!=track);
+        self.tracks.remove(&track);
+        self.volatile.retain(|[t,_]| *t!=track);
+        self.stats.invalidations += before - self.sectors.len();
+    }
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
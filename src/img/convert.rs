@@ -0,0 +1,77 @@
+//! ## Image Conversion
+//!
+//! Conversion copies the blocks the file system covers into a blank image of the new type.
+//! Anything outside the file system, such as CP/M reserved tracks, is not copied.
+//! Tag bytes of Apple 3.5 inch sectors are copied if the new type can hold them,
+//! and so is the status of sectors marked bad or deleted.  Bad sectors are read as recorded.
+//! To check that a conversion is lossless see `testing::verify_convert`.
+
+use crate::fs::DiskFS;
+use super::{DiskImage,DiskImageType,names};
+use crate::{STDRESULT,DYNERR};
+
+/// Copy the blocks covered by the file system into a new image of type `typ`, keeping the disk kind.
+/// NIB to WOZ2 is the exception, every track is copied with its sync bytes rebuilt.
+pub fn convert(disk: &mut Box<dyn DiskFS>, typ: DiskImageType) -> Result<Box<dyn DiskImage>,DYNERR> {
+    convert_with(disk,typ,false)
+}
+
+/// Same as `convert`, except that if `deterministic`, times the new image records are fixed, see `crate::now`.
+pub fn convert_with(disk: &mut Box<dyn DiskFS>, typ: DiskImageType, deterministic: bool) -> Result<Box<dyn DiskImage>,DYNERR> {
+    if typ==DiskImageType::WOZ2 && disk.get_img().what_am_i()==DiskImageType::NIB {
+        let mut nib = super::nib::Nib::from_bytes(&disk.get_img().to_bytes())?;
+        return Ok(Box::new(super::woz2::Woz2::from_nib(&mut nib)?));
+    }
+    let kind = disk.get_img().kind();
+    let wrap = match (typ,kind) {
+        (DiskImageType::DOT2MG,names::A2_DOS33_KIND) => Some("do".to_string()),
+        (DiskImageType::DOT2MG,_) => Some("po".to_string()),
+        _ => None
+    };
+    let mut ans = crate::commands::mkdsk::mkimage(&typ, &kind, None, wrap.as_ref(), deterministic)?;
+    for block in disk.all_blocks()? {
+        let dat = disk.get_img().read_block(block)?;
+        if let Err(e) = ans.write_block(block, &dat) {
+            log::error!("{} image cannot hold this file system",typ);
+            return Err(e);
+        }
+    }
+    if kind==names::A2_400_KIND || kind==names::A2_800_KIND {
+        copy_tags(disk.get_img(), &mut ans)?;
+    }
+    copy_sector_status(disk.get_img(), &mut ans)?;
+    Ok(ans)
+}
+
+/// Copy the status of every sector that is marked bad or deleted, warning if `dest` cannot hold it.
+pub fn copy_sector_status(src: &mut Box<dyn DiskImage>, dest: &mut Box<dyn DiskImage>) -> STDRESULT {
+    for flagged in src.error_map() {
+        if dest.set_sector_status(flagged.cyl, flagged.head, flagged.sec, flagged.status).is_err() {
+            log::warn!("{} image does not keep sector status, e.g. cyl {} head {} sector {} is {}",
+                dest.what_am_i(),flagged.cyl,flagged.head,flagged.sec,flagged.status);
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Copy the tag bytes of every Apple 3.5 inch sector that has any, warning if `dest` cannot hold them.
+pub fn copy_tags(src: &mut Box<dyn DiskImage>, dest: &mut Box<dyn DiskImage>) -> STDRESULT {
+    for track in 0..src.track_count() {
+        let [cyl,head] = src.track_2_ch(track);
+        for sec in 0..super::disk35::ZONED_SECS_PER_TRACK[usize::min(cyl/16,4)] {
+            let tags = match src.read_tags(cyl, head, sec) {
+                Ok(tags) => tags,
+                Err(_) => break
+            };
+            if tags.iter().all(|x| *x==0) {
+                continue;
+            }
+            if dest.write_tags(cyl, head, sec, &tags).is_err() {
+                log::warn!("{} image does not keep sector tag bytes",dest.what_am_i());
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
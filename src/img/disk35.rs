@@ -7,7 +7,6 @@
 //! Acknowledgment: some of this module is adapted from CiderPress.
 
 // TODO: eliminate some of the overlap with disk525
-// TODO: what are these tag bytes for?
 
 use super::NibbleError;
 use log::{debug,trace,warn};
@@ -18,6 +17,7 @@ const CHUNK62: usize = 175;
 /// There are 5 zones on the disk.  Zones are characterized by number of sectors per track.
 /// The number of cylinders per zone is fixed at 16 (32 tracks per zone).
 /// Sectors are always 524 bytes, consisting of 12 "tag bytes" followed by 512 data bytes.
+/// The tags were used by Lisa and early Macintosh file systems, we keep whatever is there.
 pub const ZONED_SECS_PER_TRACK: [usize;5] = [12,11,10,9,8];
 /// number of blocks occuring prior to start of zone (1 side, zone indexes array); last element marks the end of disk.
 pub const ZONE_BOUNDS_1: [usize;6] = [0,192,368,528,672,800];
@@ -684,19 +684,52 @@ fn decode_62(byte: u8,inv: [u8;256]) -> u8 {
 /// Track is padded to `buf_len` with 0x00 for WOZ, 0xFF for NIB.
 pub fn format(vol: u8,track: u8,buf_len: usize,adr_fmt: SectorAddressFormat, dat_fmt: SectorDataFormat,sync_bits: usize) ->
     (Vec<u8>,Box<dyn super::TrackBits>) {
-    let (sectors,data_nibs) = match dat_fmt.nib {
+    format_gaps(vol,track,buf_len,adr_fmt,dat_fmt,sync_bits,[40,20,0])
+}
+
+/// Like `format`, but the sync gaps are sized so that the track has exactly `track_bits` bits.
+/// The gap after each sector is cut from 20 sync bytes as far as `MIN_SYNC_GAP` if need be,
+/// the leading gap takes up the rest.  Bits that do not make a whole sync byte are zeros at the end,
+/// which only lengthen the last sync byte.  Returns `None` if the track cannot be made that length.
+pub fn format_to_length(vol: u8,track: u8,buf_len: usize,adr_fmt: SectorAddressFormat, dat_fmt: SectorDataFormat,sync_bits: usize,track_bits: usize) ->
+    Option<(Vec<u8>,Box<dyn super::TrackBits>)> {
+    let sectors = sectors_and_nibs(dat_fmt).0;
+    let gap_bits = track_bits.checked_sub(sectors*field_bits(dat_fmt,sync_bits))?;
+    let syncs = gap_bits / sync_bits;
+    let trail = usize::min(20,syncs.checked_sub(MIN_LEAD_GAP)? / sectors);
+    if trail < MIN_SYNC_GAP || track_bits > buf_len*8 {
+        return None;
+    }
+    Some(format_gaps(vol,track,buf_len,adr_fmt,dat_fmt,sync_bits,[syncs - sectors*trail,trail,gap_bits % sync_bits]))
+}
+
+fn sectors_and_nibs(dat_fmt: SectorDataFormat) -> (usize,usize) {
+    match dat_fmt.nib {
         NibbleType::Enc53 => (13,411),
         NibbleType::Enc62 => (16,343),
         _ => panic!("only 5-3 or 6-2 nibbles allowed")
-    };
-    let bit_count = 40*sync_bits + sectors*((3+8+3)*8 + 10*sync_bits + (3+data_nibs+3)*8 + 20*sync_bits);
+    }
+}
+
+/// bits in the address and data fields of one sector, including the gap between them
+fn field_bits(dat_fmt: SectorDataFormat,sync_bits: usize) -> usize {
+    let data_nibs = sectors_and_nibs(dat_fmt).1;
+    (3+8+3)*8 + 10*sync_bits + (3+data_nibs+3)*8
+}
+
+/// Format with `gaps` = [leading gap, gap after each sector, extra bits], the gaps are in sync bytes,
+/// the extra bits are zeros at the end of the track
+fn format_gaps(vol: u8,track: u8,buf_len: usize,adr_fmt: SectorAddressFormat, dat_fmt: SectorDataFormat,sync_bits: usize,gaps: [usize;3]) ->
+    (Vec<u8>,Box<dyn super::TrackBits>) {
+    let sectors = sectors_and_nibs(dat_fmt).0;
+    let bit_count = gaps[0]*sync_bits + sectors*(field_bits(dat_fmt,sync_bits) + gaps[1]*sync_bits) + gaps[2];
     let mut bits: Vec<u8> = match sync_bits {
         b if b>8 => vec![0;buf_len], // WOZ
         _ => vec![0xff;buf_len] // NIB
     };
     let mut ans = TrackBits::create(track as usize,bit_count,adr_fmt,dat_fmt);
     ans.sync_bits = sync_bits;
-    ans.write_sync_gap(&mut bits,40);
+    ans.write_sync_gap(&mut bits,gaps[0]);
     for sector in 0..sectors {
         // address field
         ans.write(&mut bits,&adr_fmt.prolog,24);
@@ -723,7 +756,7 @@ pub fn format(vol: u8,track: u8,buf_len: usize,adr_fmt: SectorAddressFormat, dat
             }
         }
         //sync gap
-        ans.write_sync_gap(&mut bits,20);
+        ans.write_sync_gap(&mut bits,gaps[1]);
     }
     let mut obj: Box<dyn super::TrackBits> = Box::new(ans);
     obj.reset();
@@ -767,3 +800,92 @@ pub fn create_std13_track(vol: u8,track: u8,buf_len: usize) -> (Vec<u8>,Box<dyn
     return create_track(vol,track,buf_len,SectorAddressFormat::create_std13(),SectorDataFormat::create_std13());
 }
 
+
+/// Sync gaps are not shortened below this many sync bytes when fitting a track
+const MIN_SYNC_GAP: usize = 5;
+/// The leading gap is not shortened below this many sync bytes when formatting to a length
+const MIN_LEAD_GAP: usize = 16;
+
+/// Rebuild a WOZ track from NIB track bytes, which do not record how wide the sync bytes were.
+/// The track is rotated to begin at the longest run of 0xFF, so that no field straddles the end.
+/// Outside of address and data fields, runs of 0xFF become 9 or 10 bit sync bytes, depending on the
+/// nibble encoding.  The longest gaps are then shortened until the track fits in `max_bits`.
+/// The returned tuple has (track buffer, TrackBits object), with the buffer padded to `buf_len` with 0x00.
+pub fn reconstruct_sync(nibs: &[u8],track: u8,buf_len: usize,max_bits: usize,adr_fmt: SectorAddressFormat,dat_fmt: SectorDataFormat) ->
+    (Vec<u8>,Box<dyn super::TrackBits>) {
+    let data_nibs = match dat_fmt.nib {
+        NibbleType::Enc53 => 411,
+        _ => 343
+    };
+    let n = nibs.len();
+    // find the start of the longest gap
+    let mut start = 0;
+    let mut longest = 0;
+    for i in 0..n {
+        if nibs[i] != 0xff || nibs[(i+n-1)%n] == 0xff {
+            continue;
+        }
+        let len = (0..n).take_while(|k| nibs[(i+k)%n] == 0xff).count();
+        if len > longest {
+            longest = len;
+            start = i;
+        }
+    }
+    let rotated = [&nibs[start..],&nibs[..start]].concat();
+    // collect the gaps as (index,length), skipping over fields
+    let mut gaps: Vec<(usize,usize)> = Vec::new();
+    let mut i = 0;
+    while i < n {
+        if rotated[i..].starts_with(&adr_fmt.prolog) {
+            i += 3 + 8 + 3;
+        } else if rotated[i..].starts_with(&dat_fmt.prolog) {
+            i += 3 + data_nibs + 3;
+        } else if rotated[i] == 0xff && (rotated.get(i+1) == Some(&0xff) || (i > 0 && rotated[i-1] == 0xff)) {
+            match gaps.last_mut() {
+                Some((beg,len)) if *beg + *len == i => *len += 1,
+                _ => gaps.push((i,1))
+            }
+            i += 1;
+        } else {
+            i += 1;
+        }
+    }
+    let mut sync_bytes: usize = gaps.iter().map(|g| g.1).sum();
+    let data_bytes = n - sync_bytes;
+    let sync_bits = match dat_fmt.nib {
+        NibbleType::Enc53 => 9,
+        _ => 10
+    };
+    let mut keep: Vec<usize> = gaps.iter().map(|g| g.1).collect();
+    while data_bytes*8 + sync_bytes*sync_bits > max_bits {
+        // the first of the longest gaps
+        let idx = (0..keep.len()).rev().max_by_key(|k| keep[*k]);
+        let idx = match idx {
+            Some(k) if keep[k] > MIN_SYNC_GAP => k,
+            _ => {
+                warn!("NIB track {} is too long even with minimal sync gaps",track);
+                break;
+            }
+        };
+        keep[idx] -= 1;
+        sync_bytes -= 1;
+    }
+    let bit_count = data_bytes*8 + sync_bytes*sync_bits;
+    let mut bits = vec![0;usize::max(buf_len,bit_count.div_ceil(8))];
+    let mut ans = TrackBits::create(track as usize,bit_count,adr_fmt,dat_fmt);
+    let mut i = 0;
+    let mut g = 0;
+    while i < n {
+        if g < gaps.len() && gaps[g].0 == i {
+            ans.write_sync_gap(&mut bits,keep[g]);
+            i += gaps[g].1;
+            g += 1;
+        } else {
+            ans.write(&mut bits,&rotated[i..i+1],8);
+            i += 1;
+        }
+    }
+    let mut obj: Box<dyn super::TrackBits> = Box::new(ans);
+    obj.reset();
+    (bits,obj)
+}
@@ -3,7 +3,6 @@
 //! This format consists of a header followed by data in either DSK or NIB format.
 //! At the end of the data there can be a comment and creator information.
 
-use chrono;
 use std::str::FromStr;
 use log::{warn,debug,info,error};
 use a2kit_macro::{DiskStructError,DiskStruct};
@@ -62,7 +61,10 @@ pub struct Dot2mg {
 
 impl Dot2mg {
     pub fn create(vol: u8,kind: img::DiskKind,maybe_wrap: Option<&String>) -> Result<Box<dyn img::DiskImage>,DYNERR> {
-        let now = chrono::Local::now().naive_local();
+        Self::create_at(vol,kind,maybe_wrap,crate::now(false))
+    }
+    /// Same as `create`, with `now` as the time in the creator info.
+    pub fn create_at(vol: u8,kind: img::DiskKind,maybe_wrap: Option<&String>,now: chrono::NaiveDateTime) -> Result<Box<dyn img::DiskImage>,DYNERR> {
         let creator_info = "a2kit v".to_string() + env!("CARGO_PKG_VERSION") + " " + &now.format("%d-%m-%Y %H:%M:%S").to_string();
         let wrap = match maybe_wrap {
             None => None,
@@ -283,6 +285,9 @@ impl img::DiskImage for Dot2mg {
         ans.append(&mut self.creator_info.as_bytes().to_vec());
         return ans;
     }
+    fn journal(&mut self) -> Option<&mut img::journal::Journal> {
+        self.raw_img.journal()
+    }
     fn get_track_buf(&mut self,cyl: usize,head: usize) -> Result<Vec<u8>,DYNERR> {
         self.raw_img.get_track_buf(cyl, head)
     }
@@ -21,7 +21,8 @@ pub fn file_extensions() -> Vec<String> {
 /// Wrapper for D13 data
 pub struct D13 {
     tracks: u16,
-    data: Vec<u8>
+    data: Vec<u8>,
+    journal: img::journal::Journal
 }
 
 impl D13 {
@@ -32,7 +33,8 @@ impl D13 {
         }
         Self {
             tracks,
-            data
+            data,
+            journal: img::journal::Journal::default()
         }
     }
 }
@@ -52,18 +54,20 @@ impl img::DiskImage for D13 {
         match addr {
             Block::D13([t,s]) => {
                 let offset = t*TRACK_SIZE + s*SECTOR_SIZE;
-                Ok(self.data[offset..offset+SECTOR_SIZE].to_vec())
+                Ok(self.data[super::flat_range(self.data.len(),offset,SECTOR_SIZE)?].to_vec())
             },
             _ => Err(Box::new(img::Error::ImageTypeMismatch))
         }
     }
     fn write_block(&mut self, addr: Block, dat: &[u8]) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Block(addr));
         trace!("write {}",addr);
         match addr {
             Block::D13([t,s]) => {
                 let offset = t*TRACK_SIZE + s*SECTOR_SIZE;
                 let padded = super::quantize_block(dat, SECTOR_SIZE);
-                self.data[offset..offset+SECTOR_SIZE].copy_from_slice(&padded);
+                let range = super::flat_range(self.data.len(),offset,SECTOR_SIZE)?;
+                self.data[range].copy_from_slice(&padded);
                 Ok(())
             },
             _ => Err(Box::new(img::Error::ImageTypeMismatch))
@@ -75,16 +79,18 @@ impl img::DiskImage for D13 {
             return Err(Box::new(img::Error::SectorAccess));
         }
         let offset = cyl*TRACK_SIZE + sec*SECTOR_SIZE;
-        Ok(self.data[offset..offset+SECTOR_SIZE].to_vec())
+        Ok(self.data[super::flat_range(self.data.len(),offset,SECTOR_SIZE)?].to_vec())
     }
     fn write_sector(&mut self,cyl: usize,head: usize,sec: usize,dat: &[u8]) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Sector([cyl,head,sec]));
         if cyl>=self.track_count() || head>0 || sec>12 {
             error!("exceeded bounds: maxima are cyl {}, head {}, sector {}",self.track_count()-1,0,12);
             return Err(Box::new(img::Error::SectorAccess));
         }
         let offset = cyl*TRACK_SIZE + sec*SECTOR_SIZE;
         let padded = super::quantize_block(dat, SECTOR_SIZE);
-        self.data[offset..offset+SECTOR_SIZE].copy_from_slice(&padded);
+        let range = super::flat_range(self.data.len(),offset,SECTOR_SIZE)?;
+        self.data[range].copy_from_slice(&padded);
         Ok(())
     }
     fn from_bytes(data: &[u8]) -> Result<Self,DiskStructError> {
@@ -94,7 +100,8 @@ impl img::DiskImage for D13 {
         }
         Ok(Self {
             tracks: (data.len()/TRACK_SIZE) as u16,
-            data: data.to_vec()
+            data: data.to_vec(),
+            journal: img::journal::Journal::default()
         })
     }
     fn what_am_i(&self) -> img::DiskImageType {
@@ -112,6 +119,9 @@ impl img::DiskImage for D13 {
     fn to_bytes(&mut self) -> Vec<u8> {
         return self.data.clone();
     }
+    fn journal(&mut self) -> Option<&mut img::journal::Journal> {
+        Some(&mut self.journal)
+    }
     fn get_track_buf(&mut self,_cyl: usize,_head: usize) -> Result<Vec<u8>,DYNERR> {
         error!("D13 images have no track bits");
         return Err(Box::new(img::Error::ImageTypeMismatch));
@@ -131,6 +141,8 @@ impl img::DiskImage for D13 {
             head: h,
             flux_code: img::FluxCode::GCR,
             nib_code: img::NibbleCode::N53,
+            data_rate: Some(img::DataRate::R250Kbps),
+            bit_timing: None,
             chss_map
         }));
     }
@@ -26,7 +26,8 @@ pub struct DO {
     kind: img::DiskKind,
     tracks: u16,
     sectors: u16,
-    data: Vec<u8>
+    data: Vec<u8>,
+    journal: img::journal::Journal
 }
 
 impl DO {
@@ -38,12 +39,13 @@ impl DO {
         Self {
             kind: match (tracks,sectors) {
                 (35,13) => panic!("DO refusing to create a D13"),
-                (35,16) => img::names::A2_DOS33_KIND,
+                (_,16) => img::names::A2_DOS33_KIND,
                 _ => img::DiskKind::Unknown
             },
             tracks,
             sectors,
-            data
+            data,
+            journal: img::journal::Journal::default()
         }
     }
 }
@@ -65,7 +67,7 @@ impl img::DiskImage for DO {
             Block::DO([t,s]) => {
                 let mut ans: Vec<u8> = Vec::new();
                 let offset = t*self.sectors as usize*SECTOR_SIZE + s*SECTOR_SIZE;
-                ans.append(&mut self.data[offset..offset+SECTOR_SIZE].to_vec());
+                ans.append(&mut self.data[super::flat_range(self.data.len(),offset,SECTOR_SIZE)?].to_vec());
                 Ok(ans) 
             },
             Block::PO(block) => {
@@ -73,7 +75,7 @@ impl img::DiskImage for DO {
                 let ts_list = skew::ts_from_prodos_block(block,&self.kind)?;
                 for [t,s] in ts_list {
                     let offset = t*self.sectors as usize*SECTOR_SIZE + s*SECTOR_SIZE;
-                    ans.append(&mut self.data[offset..offset+SECTOR_SIZE].to_vec());    
+                    ans.append(&mut self.data[super::flat_range(self.data.len(),offset,SECTOR_SIZE)?].to_vec());    
                 }
                 Ok(ans) 
             },
@@ -85,21 +87,46 @@ impl img::DiskImage for DO {
                     let track = ts[0];
                     let dsec = skew::CPM_LSEC_TO_DOS_LSEC[ts[1]-1];
                     let offset = track*self.sectors as usize*SECTOR_SIZE + dsec*SECTOR_SIZE + skew::CPM_LSEC_TO_DOS_OFFSET[ts[1]-1];
-                    ans.append(&mut self.data[offset..offset+CPM_RECORD].to_vec());
+                    ans.append(&mut self.data[super::flat_range(self.data.len(),offset,CPM_RECORD)?].to_vec());
                 }
                 Ok(ans)
             },
             Block::FAT((_s1,_secs)) => Err(Box::new(super::Error::ImageTypeMismatch))
         }
     }
+    fn read_block_into(&mut self,addr: Block,buf: &mut [u8]) -> STDRESULT {
+        let ts_list = match addr {
+            Block::DO([t,s]) => vec![[t,s]],
+            Block::PO(block) => skew::ts_from_prodos_block(block,&self.kind)?,
+            _ => {
+                let dat = self.read_block(addr)?;
+                let n = usize::min(buf.len(),dat.len());
+                buf[0..n].copy_from_slice(&dat[0..n]);
+                return Ok(());
+            }
+        };
+        trace!("read {}",addr);
+        for (i,[t,s]) in ts_list.into_iter().enumerate() {
+            let beg = i*SECTOR_SIZE;
+            if beg >= buf.len() {
+                break;
+            }
+            let n = usize::min(buf.len()-beg,SECTOR_SIZE);
+            let offset = t*self.sectors as usize*SECTOR_SIZE + s*SECTOR_SIZE;
+            buf[beg..beg+n].copy_from_slice(&self.data[super::flat_range(self.data.len(),offset,n)?]);
+        }
+        Ok(())
+    }
     fn write_block(&mut self, addr: Block, dat: &[u8]) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Block(addr));
         trace!("write {}",addr);
         match addr {
             Block::D13(_) => Err(Box::new(img::Error::ImageTypeMismatch)),
             Block::DO([t,s]) => {
                 let padded = super::quantize_block(dat, SECTOR_SIZE);
                 let offset = t*self.sectors as usize*SECTOR_SIZE + s*SECTOR_SIZE;
-                self.data[offset..offset+SECTOR_SIZE].copy_from_slice(&padded);
+                let range = super::flat_range(self.data.len(),offset,SECTOR_SIZE)?;
+                self.data[range].copy_from_slice(&padded);
                 Ok(())
             },
             Block::PO(block) => {
@@ -108,7 +135,8 @@ impl img::DiskImage for DO {
                 let mut src_offset = 0;
                 for [t,s] in ts_list {
                     let offset = t*self.sectors as usize*SECTOR_SIZE + s*SECTOR_SIZE;
-                    self.data[offset..offset+SECTOR_SIZE].copy_from_slice(&padded[src_offset..src_offset+SECTOR_SIZE]);
+                    let range = super::flat_range(self.data.len(),offset,SECTOR_SIZE)?;
+                    self.data[range].copy_from_slice(&padded[src_offset..src_offset+SECTOR_SIZE]);
                     src_offset += SECTOR_SIZE;
                 }
                 Ok(())
@@ -122,7 +150,8 @@ impl img::DiskImage for DO {
                     let track = ts[0];
                     let dsec = skew::CPM_LSEC_TO_DOS_LSEC[ts[1]-1];
                     let offset = track*self.sectors as usize*SECTOR_SIZE + dsec*SECTOR_SIZE + skew::CPM_LSEC_TO_DOS_OFFSET[ts[1]-1];
-                    self.data[offset..offset+CPM_RECORD].copy_from_slice(&padded[src_offset..src_offset+CPM_RECORD]);
+                    let range = super::flat_range(self.data.len(),offset,CPM_RECORD)?;
+                    self.data[range].copy_from_slice(&padded[src_offset..src_offset+CPM_RECORD]);
                     src_offset += CPM_RECORD;
                 }
                 Ok(())
@@ -136,16 +165,18 @@ impl img::DiskImage for DO {
             return Err(Box::new(img::Error::SectorAccess));
         }
         let offset = (cyl*self.sectors as usize + skew::DOS_PSEC_TO_DOS_LSEC[sec])*SECTOR_SIZE;
-        Ok(self.data[offset..offset+SECTOR_SIZE].to_vec())
+        Ok(self.data[super::flat_range(self.data.len(),offset,SECTOR_SIZE)?].to_vec())
     }
     fn write_sector(&mut self,cyl: usize,head: usize,sec: usize,dat: &[u8]) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Sector([cyl,head,sec]));
         if cyl>=self.track_count() || head>0 || sec>=self.sectors as usize {
             error!("exceeded bounds: maxima are cyl {}, head {}, sector {}",self.track_count()-1,0,self.sectors-1);
             return Err(Box::new(img::Error::SectorAccess));
         }
         let offset = (cyl*self.sectors as usize + skew::DOS_PSEC_TO_DOS_LSEC[sec])*SECTOR_SIZE;
         let padded = super::quantize_block(dat, SECTOR_SIZE);
-        self.data[offset..offset+SECTOR_SIZE].copy_from_slice(&padded);
+        let range = super::flat_range(self.data.len(),offset,SECTOR_SIZE)?;
+        self.data[range].copy_from_slice(&padded);
         Ok(())
     }
     fn from_bytes(data: &[u8]) -> Result<Self,DiskStructError> {
@@ -165,7 +196,8 @@ impl img::DiskImage for DO {
             },
             tracks,
             sectors: 16,
-            data: data.to_vec()
+            data: data.to_vec(),
+            journal: img::journal::Journal::default()
         })
     }
     fn what_am_i(&self) -> img::DiskImageType {
@@ -183,6 +215,9 @@ impl img::DiskImage for DO {
     fn to_bytes(&mut self) -> Vec<u8> {
         return self.data.clone();
     }
+    fn journal(&mut self) -> Option<&mut img::journal::Journal> {
+        Some(&mut self.journal)
+    }
     fn get_track_buf(&mut self,_cyl: usize,_head: usize) -> Result<Vec<u8>,DYNERR> {
         error!("DO images have no track bits");
         return Err(Box::new(img::Error::ImageTypeMismatch));
@@ -202,6 +237,8 @@ impl img::DiskImage for DO {
             head: h,
             flux_code: img::FluxCode::GCR,
             nib_code: img::NibbleCode::N62,
+            data_rate: Some(img::DataRate::R250Kbps),
+            bit_timing: None,
             chss_map
         }));
     }
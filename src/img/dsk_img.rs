@@ -23,7 +23,8 @@ pub struct Img {
     cylinders: usize,
     heads: usize,
     sectors: usize,
-    data: Vec<u8>
+    data: Vec<u8>,
+    journal: img::journal::Journal
 }
 
 impl Img {
@@ -47,7 +48,8 @@ impl Img {
             cylinders,
             heads,
             sectors,
-            data: vec![0;img_size]
+            data: vec![0;img_size],
+            journal: img::journal::Journal::default()
         }
     }
 }
@@ -84,6 +86,7 @@ impl img::DiskImage for Img {
         }
     }
     fn write_block(&mut self, addr: Block, dat: &[u8]) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Block(addr));
         trace!("write {}",addr);
         match addr {
             Block::FAT((_sec1,_secs)) => {
@@ -112,9 +115,10 @@ impl img::DiskImage for Img {
             return Err(Box::new(img::Error::SectorAccess));
         }
         let offset = (track*self.sectors as usize + sec - 1)*self.sec_size;
-        Ok(self.data[offset..offset+self.sec_size].to_vec())
+        Ok(self.data[super::flat_range(self.data.len(),offset,self.sec_size)?].to_vec())
     }
     fn write_sector(&mut self,cyl: usize,head: usize,sec: usize,dat: &[u8]) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Sector([cyl,head,sec]));
         let track = self.ch_2_track([cyl, head]);
         trace!("writing {}/{}/{}",cyl,head,sec);
         if track>=self.track_count() || sec<1 || sec>self.sectors as usize {
@@ -123,7 +127,8 @@ impl img::DiskImage for Img {
         }
         let offset = (track*self.sectors as usize + sec - 1)*self.sec_size;
         let padded = super::quantize_block(dat, self.sec_size);
-        self.data[offset..offset+self.sec_size].copy_from_slice(&padded);
+        let range = super::flat_range(self.data.len(),offset,self.sec_size)?;
+        self.data[range].copy_from_slice(&padded);
         Ok(())
     }
     fn from_bytes(data: &[u8]) -> Result<Self,DiskStructError> {
@@ -162,7 +167,8 @@ impl img::DiskImage for Img {
             cylinders,
             heads,
             sectors,
-            data: data.to_vec()
+            data: data.to_vec(),
+            journal: img::journal::Journal::default()
         })
     }
     fn what_am_i(&self) -> img::DiskImageType {
@@ -180,6 +186,9 @@ impl img::DiskImage for Img {
     fn to_bytes(&mut self) -> Vec<u8> {
         return self.data.clone();
     }
+    fn journal(&mut self) -> Option<&mut img::journal::Journal> {
+        Some(&mut self.journal)
+    }
     fn get_track_buf(&mut self,_cyl: usize,_head: usize) -> Result<Vec<u8>,DYNERR> {
         error!("IMG images have no track bits");
         return Err(Box::new(img::Error::ImageTypeMismatch));
@@ -194,17 +203,19 @@ impl img::DiskImage for Img {
         for i in 0..self.sectors {
             chss_map.push([cylinder,head,i,self.sec_size]);
         }
-        let flux_code = match self.kind {
-            img::DiskKind::D35(l) => l.flux_code[0],
-            img::DiskKind::D525(l) => l.flux_code[0],
-            img::DiskKind::D8(l) => l.flux_code[0],
-            _ => img::FluxCode::None
+        let (flux_code,data_rate) = match self.kind {
+            img::DiskKind::D35(l) => (l.flux_code[0],Some(l.data_rate[0])),
+            img::DiskKind::D525(l) => (l.flux_code[0],Some(l.data_rate[0])),
+            img::DiskKind::D8(l) => (l.flux_code[0],Some(l.data_rate[0])),
+            _ => (img::FluxCode::None,None)
         };
         return Ok(Some(img::TrackSolution {
             cylinder,
             head,
             flux_code,
             nib_code: img::NibbleCode::None,
+            data_rate,
+            bit_timing: None,
             chss_map
         }));
     }
@@ -3,12 +3,16 @@
 //! DSK images are a simple sequential dump of the already-decoded sector data.
 //! If the sector sequence is ordered as in ProDOS, we have a PO variant.
 //! N.b. the ordering cannot be verified until we get up to the file system layer.
+//!
+//! Some tools leave bytes after the last whole block, or cut the last block short.  Such images are
+//! accepted with a warning, the trailing bytes are not part of the usable size, and a short block is padded.
+//! When saved, the image keeps its original length, unless the padding is repaired, see `crate::SaveOptions::repair_padding`.
 
 use crate::img;
 use crate::fs::Block;
 
 use a2kit_macro::DiskStructError;
-use log::{trace,debug,error};
+use log::{trace,debug,warn,error};
 use super::BlockLayout;
 use crate::{STDRESULT,DYNERR};
 
@@ -17,7 +21,7 @@ const MAX_BLOCKS: usize = 65535;
 const MIN_BLOCKS: usize = 280;
 
 pub fn file_extensions() -> Vec<String> {
-    vec!["po".to_string(),"dsk".to_string()]
+    vec!["po".to_string(),"dsk".to_string(),"hdv".to_string()]
 }
 
 fn select_kind(blocks: u16) -> img::DiskKind {
@@ -31,7 +35,14 @@ fn select_kind(blocks: u16) -> img::DiskKind {
 pub struct PO {
     kind: img::DiskKind,
     blocks: u16,
-    data: Vec<u8>
+    data: Vec<u8>,
+    /// bytes after the last whole block
+    trailing: Vec<u8>,
+    /// length of the image as it was read
+    raw_len: usize,
+    /// save whole blocks rather than the original length
+    repair_padding: bool,
+    journal: img::journal::Journal
 }
 
 impl PO {
@@ -43,7 +54,11 @@ impl PO {
         Self {
             kind: select_kind(blocks),
             blocks,
-            data
+            raw_len: data.len(),
+            repair_padding: false,
+            data,
+            trailing: Vec::new(),
+            journal: img::journal::Journal::default()
         }
     }
 }
@@ -61,16 +76,29 @@ impl img::DiskImage for PO {
     fn read_block(&mut self,addr: Block) -> Result<Vec<u8>,DYNERR> {
         trace!("read {}",addr);
         match addr {
-            Block::PO(block) => Ok(self.data[block*BLOCK_SIZE..(block+1)*BLOCK_SIZE].to_vec()),
+            Block::PO(block) => Ok(self.data[super::flat_range(self.data.len(),block*BLOCK_SIZE,BLOCK_SIZE)?].to_vec()),
+            _ => Err(Box::new(img::Error::ImageTypeMismatch)),
+        }
+    }
+    fn read_block_into(&mut self,addr: Block,buf: &mut [u8]) -> STDRESULT {
+        trace!("read {}",addr);
+        match addr {
+            Block::PO(block) => {
+                let n = usize::min(buf.len(),BLOCK_SIZE);
+                buf[0..n].copy_from_slice(&self.data[super::flat_range(self.data.len(),block*BLOCK_SIZE,n)?]);
+                Ok(())
+            },
             _ => Err(Box::new(img::Error::ImageTypeMismatch)),
         }
     }
     fn write_block(&mut self, addr: Block, dat: &[u8]) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Block(addr));
         trace!("write {}",addr);
         match addr {
             Block::PO(block) => {
                 let padded = super::quantize_block(dat, BLOCK_SIZE);
-                self.data[block*BLOCK_SIZE..(block+1)*BLOCK_SIZE].copy_from_slice(&padded);
+                let range = super::flat_range(self.data.len(),block*BLOCK_SIZE,BLOCK_SIZE)?;
+                self.data[range].copy_from_slice(&padded);
                 Ok(())
             },
             _ => Err(Box::new(img::Error::ImageTypeMismatch)),
@@ -86,14 +114,31 @@ impl img::DiskImage for PO {
     }
     fn from_bytes(data: &[u8]) -> Result<Self,DiskStructError> {
         // reject anything that can be neither a DOS 3.3 nor a ProDOS volume
-        if data.len()%BLOCK_SIZE > 0 || data.len()/BLOCK_SIZE > MAX_BLOCKS || data.len()/BLOCK_SIZE < MIN_BLOCKS {
-            return Err(DiskStructError::UnexpectedSize);
-        }
-        let blocks = (data.len()/BLOCK_SIZE) as u16;
+        let whole = data.len()/BLOCK_SIZE;
+        let extra = data.len()%BLOCK_SIZE;
+        let (blocks,trailing) = match (whole,extra) {
+            (w,_) if w > MAX_BLOCKS => return Err(DiskStructError::UnexpectedSize),
+            (w,0) if w >= MIN_BLOCKS => (w,0),
+            (w,e) if w >= MIN_BLOCKS => {
+                warn!("ignoring {} bytes after the last whole block",e);
+                (w,e)
+            },
+            (w,e) if e > 0 && w+1 == MIN_BLOCKS => {
+                warn!("last block is short by {} bytes, padding",BLOCK_SIZE-e);
+                (w+1,0)
+            },
+            _ => return Err(DiskStructError::UnexpectedSize)
+        };
+        let mut usable = data[0..data.len()-trailing].to_vec();
+        usable.resize(blocks*BLOCK_SIZE,0);
         Ok(Self {
-            kind: select_kind(blocks),
-            blocks,
-            data: data.to_vec()
+            kind: select_kind(blocks as u16),
+            blocks: blocks as u16,
+            data: usable,
+            trailing: data[data.len()-trailing..].to_vec(),
+            raw_len: data.len(),
+            repair_padding: false,
+            journal: img::journal::Journal::default()
         })
     }
     fn what_am_i(&self) -> img::DiskImageType {
@@ -109,7 +154,20 @@ impl img::DiskImage for PO {
         self.kind = kind;
     }
     fn to_bytes(&mut self) -> Vec<u8> {
-        return self.data.clone();
+        if self.repair_padding || self.raw_len == self.data.len() {
+            return self.data.clone();
+        }
+        if self.raw_len < self.data.len() && self.data[self.raw_len..].iter().any(|b| *b != 0) {
+            warn!("data in the padded part of the last block is dropped, repair the padding to keep it");
+        }
+        let usable = usize::min(self.raw_len,self.data.len());
+        [&self.data[0..usable],&self.trailing].concat()
+    }
+    fn set_repair_padding(&mut self,yes: bool) {
+        self.repair_padding = yes;
+    }
+    fn journal(&mut self) -> Option<&mut img::journal::Journal> {
+        Some(&mut self.journal)
     }
     fn get_track_buf(&mut self,_cyl: usize,_head: usize) -> Result<Vec<u8>,DYNERR> {
         error!("PO images have no track bits");
@@ -5,8 +5,11 @@
 //! for very early disks, the BPB is always discoverable in the boot sector.
 //! For CP/M, only specific vendors are supported, due to the fact that the DPB
 //! usually has to be supplied for each individual case.
+//!
+//! This module is library-safe, see `crate::img`.
+
+#![deny(clippy::panic,clippy::unwrap_used,clippy::expect_used)]
 
-use chrono;
 use num_traits::FromPrimitive;
 use num_derive::FromPrimitive;
 use log::{warn,info,trace,debug,error};
@@ -51,6 +54,28 @@ pub enum SectorData {
     ErrorCompressedDeleted = 8
 }
 
+/// Status of an expanded sector record given its data type
+fn sector_status(code: u8) -> img::SectorStatus {
+    match SectorData::from_u8(code) {
+        Some(SectorData::NormalDeleted) => img::SectorStatus::Deleted,
+        Some(SectorData::Error) => img::SectorStatus::CrcError,
+        Some(SectorData::ErrorDeleted) => img::SectorStatus::CrcErrorDeleted,
+        Some(SectorData::None) => img::SectorStatus::NoData,
+        _ => img::SectorStatus::Normal
+    }
+}
+
+/// Data type of an expanded sector record given its status
+fn sector_code(status: img::SectorStatus) -> u8 {
+    match status {
+        img::SectorStatus::Normal => SectorData::Normal as u8,
+        img::SectorStatus::Deleted => SectorData::NormalDeleted as u8,
+        img::SectorStatus::CrcError => SectorData::Error as u8,
+        img::SectorStatus::CrcErrorDeleted => SectorData::ErrorDeleted as u8,
+        img::SectorStatus::NoData => SectorData::None as u8
+    }
+}
+
 pub fn is_slice_uniform(slice: &[u8]) -> bool {
     if slice.len()<1 {
         return true;
@@ -100,10 +125,15 @@ pub struct Imd {
     header: [u8;29],
     comment: String,
     terminator: u8,
-    tracks: Vec<Track>
+    tracks: Vec<Track>,
+    journal: img::journal::Journal,
+    bad_sectors: img::BadSectorPolicy
 }
 
 impl Track {
+    /// # Panics
+    /// If the layout uses a data rate or flux encoding IMD does not have
+    #[allow(clippy::panic)]
     fn create(track_num: usize, layout: &super::TrackLayout) -> Self {
         let zone = layout.zone(track_num);
         let mode = match (layout.flux_code[zone],layout.data_rate[zone]) {
@@ -181,7 +211,8 @@ impl Track {
             Some(SectorData::ErrorCompressed) => 2,
             Some(SectorData::ErrorCompressedDeleted) => 2,
             Some(SectorData::ErrorDeleted) => 1 + sec_size,
-            _ => panic!("unexpected sector data type")
+            // `update_from_bytes` rejects unknown codes, so this is not expected
+            None => 1
         }
     }
     /// compress sectors with uniform data
@@ -299,6 +330,14 @@ impl DiskStruct for Track {
         self.head = bytes[2];
         self.sectors = bytes[3];
         self.sector_shift = bytes[4];
+        if self.sector_shift==0xff {
+            warn!("inhomogeneous sector sizes are not supported");
+            return Err(DiskStructError::IllegalValue);
+        }
+        if self.sector_shift > 6 {
+            debug!("sector size code {} is out of range",self.sector_shift);
+            return Err(DiskStructError::IllegalValue);
+        }
         debug!("Cylinder {}, Head {}: {} sectors x {} bytes",self.cylinder,self.head & HEAD_MASK,self.sectors,SECTOR_SIZE_BASE << self.sector_shift);
         let mut ptr: usize = 5;
         check(bytes,ptr+self.sectors as usize)?;
@@ -323,6 +362,11 @@ impl DiskStruct for Track {
         }
         self.track_buf = Vec::new();
         for _lsec in 0..self.sectors {
+            check(bytes,ptr+1)?;
+            if SectorData::from_u8(bytes[ptr]).is_none() {
+                debug!("unexpected sector data type {}",bytes[ptr]);
+                return Err(DiskStructError::IllegalValue);
+            }
             let sec_size = self.get_sec_buf_size(bytes[ptr]);
             check(bytes,ptr+sec_size)?;
             self.track_buf.append(&mut bytes[ptr..ptr+sec_size].to_vec());
@@ -338,8 +382,16 @@ impl DiskStruct for Track {
 }
 
 impl Imd {
+    /// # Panics
+    /// If the kind of disk cannot be put in an IMD
     pub fn create(kind: img::DiskKind) -> Self {
-        let now = chrono::Local::now().naive_local();
+        Self::create_at(kind,crate::now(false))
+    }
+    /// Same as `create`, with `now` as the time in the header.
+    /// # Panics
+    /// If the kind of disk cannot be put in an IMD
+    #[allow(clippy::panic,clippy::expect_used)]
+    pub fn create_at(kind: img::DiskKind,now: chrono::NaiveDateTime) -> Self {
         let header = "IMD 1.19: ".to_string() + &now.format("%d-%m-%Y %H:%M:%S").to_string();
         let creator_str = "a2kit v".to_string() + env!("CARGO_PKG_VERSION");
         debug!("header {}",header);
@@ -362,7 +414,9 @@ impl Imd {
             header: header.as_bytes().try_into().expect("header did not fit"),
             comment: creator_str,
             terminator: 0x1a,
-            tracks
+            tracks,
+            journal: img::journal::Journal::default(),
+            bad_sectors: img::BadSectorPolicy::default()
         }
     }
     fn get_track_mut(&mut self,cyl: usize,head: usize) -> Result<&mut Track,img::Error> {
@@ -418,7 +472,10 @@ impl img::DiskImage for Imd {
         self.heads
     }
     fn track_2_ch(&self,track: usize) -> [usize;2] {
-        [self.tracks[track].cylinder as usize,(self.tracks[track].head & HEAD_MASK) as usize]
+        match self.tracks.get(track) {
+            Some(trk) => [trk.cylinder as usize,(trk.head & HEAD_MASK) as usize],
+            None => [track/self.heads,track%self.heads]
+        }
     }
     fn ch_2_track(&self,ch: [usize;2]) -> usize {
         for i in 0..self.tracks.len() {
@@ -426,7 +483,8 @@ impl img::DiskImage for Imd {
                 return i
             }
         }
-        panic!("cylinder {}, head {} does not exist",ch[0],ch[1]);
+        debug!("cylinder {}, head {} does not exist",ch[0],ch[1]);
+        self.tracks.len()
     }
     fn byte_capacity(&self) -> usize {
         let mut ans = 0;
@@ -489,6 +547,7 @@ impl img::DiskImage for Imd {
         }
     }
     fn write_block(&mut self, addr: Block, dat: &[u8]) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Block(addr));
         trace!("writing {}",addr);
         match addr {
             Block::CPM((_block,_bsh,off)) => {
@@ -531,6 +590,7 @@ impl img::DiskImage for Imd {
     }
     fn read_sector(&mut self,cyl: usize,head: usize,sec: usize) -> Result<Vec<u8>,DYNERR> {
         trace!("seeking sector {} (R)",sec);
+        let policy = self.bad_sectors.clone();
         let trk = self.get_track_mut(cyl,head)?;
         let psec_size = SECTOR_SIZE_BASE << trk.sector_shift;
         // advance to the requested sector
@@ -540,8 +600,13 @@ impl img::DiskImage for Imd {
             if sec==curr {
                 trace!("reading sector {}",sec);
                 return match SectorData::from_u8(trk.track_buf[buf_idx]) {
-                    Some(SectorData::Normal) | Some(SectorData::NormalDeleted) => Ok(trk.track_buf[buf_idx+1..buf_idx+1+psec_size].to_vec()),
-                    Some(SectorData::Error) | Some(SectorData::ErrorDeleted) => Ok(trk.track_buf[buf_idx+1..buf_idx+1+psec_size].to_vec()),
+                    Some(SectorData::Normal) => Ok(trk.track_buf[buf_idx+1..buf_idx+1+psec_size].to_vec()),
+                    Some(SectorData::NormalDeleted) => {
+                        warn!("cyl {} head {} sector {} has deleted data",cyl,head,sec);
+                        Ok(trk.track_buf[buf_idx+1..buf_idx+1+psec_size].to_vec())
+                    },
+                    Some(SectorData::Error) | Some(SectorData::ErrorDeleted) => img::bad_sector_data(&policy,cyl,head,sec,Some(&trk.track_buf[buf_idx+1..buf_idx+1+psec_size]),psec_size),
+                    Some(SectorData::None) => img::bad_sector_data(&policy,cyl,head,sec,None,psec_size),
                     _ => {
                         debug!("cyl {} head {} sector {}: data type {} not expected",cyl,head,sec,trk.track_buf[buf_idx]);
                         Err(Box::new(img::Error::SectorAccess))
@@ -555,6 +620,7 @@ impl img::DiskImage for Imd {
         Err(Box::new(img::Error::SectorAccess))
     }
     fn write_sector(&mut self,cyl: usize,head: usize,sec: usize,dat: &[u8]) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Sector([cyl,head,sec]));
         trace!("seeking sector {} (W)",sec);
         let trk = self.get_track_mut(cyl,head)?;
         let psec_size = SECTOR_SIZE_BASE << trk.sector_shift;
@@ -581,11 +647,54 @@ impl img::DiskImage for Imd {
         error!("sector {} not found",sec);
         Err(Box::new(img::Error::SectorAccess))
     }
+    fn error_map(&mut self) -> Vec<img::FlaggedSector> {
+        let mut ans = Vec::new();
+        for trk in &self.tracks {
+            let mut idx = 0;
+            for sec in &trk.sector_map {
+                let status = sector_status(trk.track_buf[idx]);
+                if status != img::SectorStatus::Normal {
+                    ans.push(img::FlaggedSector { cyl: trk.cylinder as usize, head: (trk.head & HEAD_MASK) as usize, sec: *sec as usize, status });
+                }
+                idx += trk.get_sec_buf_size(trk.track_buf[idx]);
+            }
+        }
+        ans
+    }
+    fn set_sector_status(&mut self,cyl: usize,head: usize,sec: usize,status: img::SectorStatus) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Status([cyl,head,sec]));
+        img::journal::record(self,img::journal::Unit::Sector([cyl,head,sec]));
+        let trk = self.get_track_mut(cyl,head)?;
+        let psec_size = SECTOR_SIZE_BASE << trk.sector_shift;
+        let mut idx = 0;
+        for i in 0..trk.sector_map.len() {
+            let rec_size = trk.get_sec_buf_size(trk.track_buf[idx]);
+            if trk.sector_map[i] as usize==sec {
+                let mut rec = match (status,rec_size) {
+                    (img::SectorStatus::NoData,_) => vec![0],
+                    (_,1) => vec![0;psec_size+1],
+                    _ => trk.track_buf[idx..idx+rec_size].to_vec()
+                };
+                rec[0] = sector_code(status);
+                trk.track_buf.splice(idx..idx+rec_size,rec);
+                trk.head_pos = 0;
+                trk.buf_offset = 0;
+                return Ok(());
+            }
+            idx += rec_size;
+        }
+        error!("sector {} not found",sec);
+        Err(Box::new(img::Error::SectorAccess))
+    }
+    fn set_bad_sector_policy(&mut self,policy: img::BadSectorPolicy) {
+        self.bad_sectors = policy;
+    }
     fn from_bytes(data: &[u8]) -> Result<Self,DiskStructError> {
         if data.len()<29 {
             return Err(DiskStructError::UnexpectedSize);
         }
-        let header = data[0..29].to_vec();
+        let mut header = [0;29];
+        header.copy_from_slice(&data[0..29]);
         match header[0..6] {
             [73,77,68,32,48,46] => info!("identified IMD v0.x header"),
             [73,77,68,32,49,46] => info!("identified IMD v1.x header"),
@@ -606,20 +715,22 @@ impl img::DiskImage for Imd {
             let mut ans = Self {
                 kind: img::DiskKind::Unknown,
                 heads: 1, // updated below
-                header: header.try_into().expect("unexpected header mismatch"),
+                header,
                 comment,
                 terminator: 0x1a,
-                tracks: Vec::new()
+                tracks: Vec::new(),
+                journal: img::journal::Journal::default(),
+                bad_sectors: img::BadSectorPolicy::default()
             };
             ptr += 1;
             while ptr<data.len() {
                 let compressed = Track::from_bytes_adv(&data[ptr..],&mut ptr)?;
-                if compressed.sector_shift==0xff {
-                    warn!("inhomogeneous sector sizes are not supported");
-                    return Err(DiskStructError::IllegalValue);
-                }
                 ans.tracks.push(compressed.expand());
             }
+            if ans.tracks.is_empty() {
+                debug!("IMD has no tracks");
+                return Err(DiskStructError::OutOfData);
+            }
             // TODO: this works for now, but we should have the IMD object set up a pattern
             // that can be explicitly matched against the disk kind.
             ans.kind = match (ans.byte_capacity(),ans.tracks[0].sectors) {
@@ -678,6 +789,9 @@ impl img::DiskImage for Imd {
         }
         return ans;
     }
+    fn journal(&mut self) -> Option<&mut img::journal::Journal> {
+        Some(&mut self.journal)
+    }
     fn get_track_buf(&mut self,_cyl: usize,_head: usize) -> Result<Vec<u8>,DYNERR> {
         error!("IMD images have no track bits");
         return Err(Box::new(img::Error::ImageTypeMismatch));
@@ -693,6 +807,12 @@ impl img::DiskImage for Imd {
             Some(Mode::Mfm250Kbps) | Some(Mode::Mfm300Kbps) | Some(Mode::Mfm500Kbps) => img::FluxCode::MFM,
             None => img::FluxCode::None
         };
+        let data_rate = match Mode::from_u8(trk_obj.mode) {
+            Some(Mode::Fm250Kbps) | Some(Mode::Mfm250Kbps) => Some(img::DataRate::R250Kbps),
+            Some(Mode::Fm300Kbps) | Some(Mode::Mfm300Kbps) => Some(img::DataRate::R300Kbps),
+            Some(Mode::Fm500Kbps) | Some(Mode::Mfm500Kbps) => Some(img::DataRate::R500Kbps),
+            None => None
+        };
         let phys_head = (trk_obj.head & HEAD_MASK) as usize;
         let mut chss_map: Vec<[usize;4]> = Vec::new();
         for i in 0..trk_obj.sectors as usize {
@@ -705,6 +825,8 @@ impl img::DiskImage for Imd {
             head: phys_head,
             flux_code,
             nib_code: img::NibbleCode::None,
+            data_rate,
+            bit_timing: None,
             chss_map
         }))
     }
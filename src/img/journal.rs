@@ -0,0 +1,158 @@
+//! ## Journal of Changes
+//!
+//! While a `Snapshot` of an image is held, the image keeps the prior contents of whatever it is
+//! about to change, be it a block, a sector, or for images that store track bits, a whole track.
+//! Only the first change to each unit after the newest snapshot is kept.  Rolling back writes
+//! the prior contents back, newest first.  Once every snapshot has been dropped the journal is
+//! emptied and nothing more is kept, so an image that is never snapshotted pays nothing.
+//! Image metadata is not journaled.
+
+use std::collections::HashSet;
+use std::sync::{Arc,Weak};
+use log::error;
+use crate::fs::Block;
+use super::{DiskImage,Error,SectorStatus};
+use crate::STDRESULT;
+
+/// Part of an image that is journaled as a whole
+#[derive(Clone,Copy,PartialEq,Eq,Hash,Debug)]
+pub enum Unit {
+    Block(Block),
+    /// `[cylinder,head,sector]`
+    Sector([usize;3]),
+    /// `[cylinder,head]`, the track buffer as in `DiskImage::get_track_buf`
+    Track([usize;2]),
+    /// tag bytes of `[cylinder,head,sector]`
+    Tags([usize;3]),
+    /// status of `[cylinder,head,sector]`
+    Status([usize;3])
+}
+
+#[derive(Clone)]
+enum Prior {
+    Data(Vec<u8>),
+    Status(SectorStatus),
+    /// the unit could not be read, so it cannot be restored
+    Unknown
+}
+
+/// Prior contents of changed units, kept by images that support `DiskImage::snapshot`
+#[derive(Clone,Default)]
+pub struct Journal {
+    /// open snapshots, each with the length of `entries` when it was taken
+    marks: Vec<(Weak<()>,usize)>,
+    entries: Vec<(Unit,Prior)>,
+    /// units recorded since the newest snapshot
+    touched: HashSet<Unit>,
+    replaying: bool
+}
+
+impl Journal {
+    /// Forget snapshots that were dropped, along with entries that only they needed
+    fn prune(&mut self) {
+        self.marks.retain(|(w,_)| w.strong_count() > 0);
+        match self.marks.first() {
+            None => {
+                self.entries.clear();
+                self.touched.clear();
+            },
+            Some((_,pos)) if *pos > 0 => {
+                let pos = *pos;
+                self.entries.drain(0..pos);
+                for mark in self.marks.iter_mut() {
+                    mark.1 -= pos;
+                }
+            },
+            _ => {}
+        }
+    }
+    /// Start recording changes, the journal keeps them as long as the returned token is alive
+    pub fn mark(&mut self) -> Arc<()> {
+        self.prune();
+        let token = Arc::new(());
+        self.marks.push((Arc::downgrade(&token),self.entries.len()));
+        self.touched.clear();
+        token
+    }
+    /// Number of units with prior contents in the journal
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    fn wants(&mut self,unit: &Unit) -> bool {
+        if self.replaying {
+            return false;
+        }
+        self.prune();
+        !self.marks.is_empty() && !self.touched.contains(unit)
+    }
+}
+
+/// Keep the prior contents of `unit` if a snapshot is held.  Images call this before changing the unit.
+pub fn record<T: DiskImage + ?Sized>(img: &mut T,unit: Unit) {
+    if !img.journal().is_some_and(|journal| journal.wants(&unit)) {
+        return;
+    }
+    let prior = match unit {
+        Unit::Block(addr) => img.read_block(addr).map(Prior::Data),
+        Unit::Sector([c,h,s]) => img.read_sector(c,h,s).map(Prior::Data),
+        Unit::Track([c,h]) => img.get_track_buf(c,h).map(Prior::Data),
+        Unit::Tags([c,h,s]) => img.read_tags(c,h,s).map(Prior::Data),
+        Unit::Status([c,h,s]) => Ok(Prior::Status(img.error_map().iter()
+            .find(|f| f.cyl==c && f.head==h && f.sec==s)
+            .map(|f| f.status).unwrap_or(SectorStatus::Normal)))
+    }.unwrap_or(Prior::Unknown);
+    if let Some(journal) = img.journal() {
+        journal.touched.insert(unit);
+        journal.entries.push((unit,prior));
+    }
+}
+
+/// Write back the prior contents of everything changed since the snapshot holding `token` was taken.
+/// The snapshot stays open, snapshots taken after it are closed.
+pub fn rollback<T: DiskImage + ?Sized>(img: &mut T,token: &Arc<()>) -> STDRESULT {
+    let journal = match img.journal() {
+        Some(j) => j,
+        None => {
+            error!("{} images do not keep a journal",img.what_am_i());
+            return Err(Box::new(Error::ImageTypeMismatch));
+        }
+    };
+    journal.prune();
+    let idx = match journal.marks.iter().position(|(w,_)| w.upgrade().is_some_and(|t| Arc::ptr_eq(&t,token))) {
+        Some(i) => i,
+        None => {
+            error!("snapshot was not taken from this image");
+            return Err(Box::new(Error::ImageTypeMismatch));
+        }
+    };
+    let pos = journal.marks[idx].1;
+    journal.marks.truncate(idx+1);
+    journal.touched.clear();
+    journal.replaying = true;
+    let undo = journal.entries.split_off(pos);
+    let mut lost = 0;
+    for (unit,prior) in undo.into_iter().rev() {
+        let res = match (unit,prior) {
+            (Unit::Block(addr),Prior::Data(dat)) => img.write_block(addr,&dat),
+            (Unit::Sector([c,h,s]),Prior::Data(dat)) => img.write_sector(c,h,s,&dat),
+            (Unit::Track([c,h]),Prior::Data(dat)) => img.set_track_buf(c,h,&dat),
+            (Unit::Tags([c,h,s]),Prior::Data(dat)) => img.write_tags(c,h,s,&dat),
+            (Unit::Status([c,h,s]),Prior::Status(status)) => img.set_sector_status(c,h,s,status),
+            _ => Err(Box::new(Error::SectorAccess) as crate::DYNERR)
+        };
+        if res.is_err() {
+            lost += 1;
+        }
+    }
+    if let Some(journal) = img.journal() {
+        journal.replaying = false;
+    }
+    if lost > 0 {
+        error!("{} changes could not be undone",lost);
+        return Err(Box::new(Error::SectorAccess));
+    }
+    Ok(())
+}
@@ -31,6 +31,12 @@
 //! own language.  Each disk image implementation has to provide `read_block` and `write_block`.
 //! These functions have to be able to take a `Block` and transform it into whatever disk
 //! addressing the image uses.  The tables in `bios::skew` are accessible to any image.
+//!
+//! Whatever bytes an image is made from should produce an error rather than a panic.
+//! Modules that are held to this are marked library-safe, and deny `panic!`, `unwrap`, and `expect`
+//! via clippy.  So far these are `imd` and `td0`, the rest can still panic on malformed input.
+//! Creating an image of a kind the format cannot hold is still allowed to panic, the `create`
+//! functions say so.
 
 pub mod disk35;
 pub mod disk525;
@@ -41,16 +47,22 @@ pub mod dsk_img;
 pub mod dot2mg;
 pub mod nib;
 pub mod woz;
+pub mod cache;
+pub mod journal;
 pub mod woz1;
 pub mod woz2;
 pub mod imd;
 pub mod td0;
 pub mod names;
 pub mod meta;
+pub mod archive;
+pub mod provenance;
+pub mod volumes;
+pub mod convert;
 
 use std::str::FromStr;
 use std::fmt;
-use log::{info,error};
+use log::{debug,info,warn,error};
 use crate::fs;
 use crate::{STDRESULT,DYNERR};
 
@@ -72,7 +84,9 @@ pub enum Error {
     #[error("unable to access sector")]
     SectorAccess,
     #[error("metadata mismatch")]
-    MetadataMismatch
+    MetadataMismatch,
+    #[error("sector is marked bad")]
+    BadSector
 }
 
 /// Errors pertaining to nibble encoding
@@ -127,9 +141,19 @@ pub struct TrackSolution {
     head: usize,
     flux_code: FluxCode,
     nib_code: NibbleCode,
+    data_rate: Option<DataRate>,
+    /// bit cell in units of 125 ns, as in WOZ images, only for images with track bits
+    bit_timing: Option<u8>,
     chss_map: Vec<[usize;4]>
 }
 
+impl TrackSolution {
+    /// `[cylinder,head,sector,size]` of each sector, in physical order
+    pub fn chss_map(&self) -> &[[usize;4]] {
+        &self.chss_map
+    }
+}
+
 #[derive(PartialEq,Eq,Clone,Copy)]
 pub struct TrackLayout {
     cylinders: [usize;5],
@@ -262,6 +286,17 @@ impl fmt::Display for FluxCode {
     }
 }
 
+impl fmt::Display for DataRate {
+    fn fmt(&self,f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DataRate::R250Kbps => write!(f,"250kbps"),
+            DataRate::R300Kbps => write!(f,"300kbps"),
+            DataRate::R500Kbps => write!(f,"500kbps"),
+            DataRate::R1000Kbps => write!(f,"1000kbps")
+        }
+    }
+}
+
 /// Given a command line argument return a likely disk kind the user may want
 impl FromStr for DiskKind {
     type Err = Error;
@@ -281,6 +316,10 @@ impl FromStr for DiskKind {
             "5.25in-osb-dd" => Ok(names::OSBORNE1_DD_KIND),
             "5.25in-kayii" => Ok(names::KAYPROII_KIND),
             "5.25in-kay4" => Ok(names::KAYPRO4_KIND),
+            "kaypro2" => Ok(names::KAYPROII_KIND),
+            "kaypro4" => Ok(names::KAYPRO4_KIND),
+            "osborne1" => Ok(names::OSBORNE1_SD_KIND),
+            "osborne1-dd" => Ok(names::OSBORNE1_DD_KIND),
             "5.25in" => Ok(names::A2_DOS33_KIND), // mkdsk will change it if DOS 3.2 requested
             "3.5in" => Ok(names::A2_800_KIND),
             "3.5in-ss" => Ok(names::A2_400_KIND),
@@ -295,6 +334,24 @@ impl FromStr for DiskKind {
     }
 }
 
+impl DiskImageType {
+    /// File extensions this image type goes by, some are shared with other types
+    pub fn file_extensions(&self) -> Vec<String> {
+        match self {
+            Self::D13 => dsk_d13::file_extensions(),
+            Self::DO => dsk_do::file_extensions(),
+            Self::PO => dsk_po::file_extensions(),
+            Self::IMG => dsk_img::file_extensions(),
+            Self::WOZ1 => woz1::file_extensions(),
+            Self::WOZ2 => woz2::file_extensions(),
+            Self::IMD => imd::file_extensions(),
+            Self::DOT2MG => dot2mg::file_extensions(),
+            Self::NIB => nib::file_extensions(),
+            Self::TD0 => td0::file_extensions()
+        }
+    }
+}
+
 impl FromStr for DiskImageType {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self,Self::Err> {
@@ -334,7 +391,7 @@ impl fmt::Display for DiskImageType {
 
 /// Lightweight trait object for reading and writing track bits.
 /// The track buffer is borrowed.
-pub trait TrackBits {
+pub trait TrackBits: Send {
     /// get id of the track, usually sequence indexed from 0
     fn id(&self) -> usize;
     /// Bits actually on the track
@@ -359,6 +416,105 @@ pub trait TrackBits {
     fn chss_map(&mut self,bits: &[u8]) -> Result<Vec<[usize;4]>,NibbleError>;
 }
 
+/// Condition of a physical sector as recorded by images such as IMD and TD0
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum SectorStatus {
+    Normal,
+    /// data was written with a deleted data address mark
+    Deleted,
+    /// data was read with a CRC error
+    CrcError,
+    CrcErrorDeleted,
+    /// the sector was found, but there is no data
+    NoData
+}
+
+impl SectorStatus {
+    /// True if the data cannot be trusted, deleted data is not bad
+    pub fn is_bad(&self) -> bool {
+        matches!(self,Self::CrcError | Self::CrcErrorDeleted | Self::NoData)
+    }
+}
+
+impl fmt::Display for SectorStatus {
+    fn fmt(&self,f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normal => write!(f,"normal"),
+            Self::Deleted => write!(f,"deleted"),
+            Self::CrcError => write!(f,"crc_error"),
+            Self::CrcErrorDeleted => write!(f,"crc_error_deleted"),
+            Self::NoData => write!(f,"no_data")
+        }
+    }
+}
+
+/// A physical sector whose status is other than normal, as listed by `DiskImage::error_map`
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct FlaggedSector {
+    pub cyl: usize,
+    pub head: usize,
+    pub sec: usize,
+    pub status: SectorStatus
+}
+
+/// What reading a bad sector produces, images that record bad sectors keep one of these,
+/// see `DiskImage::set_bad_sector_policy`
+#[derive(Clone,Debug,Default,PartialEq)]
+pub enum BadSectorPolicy {
+    /// return the data as recorded, zeros if there is none (default)
+    #[default]
+    Keep,
+    /// return `Error::BadSector`
+    Fail,
+    /// return the pattern, repeated to fill the sector
+    Fill(Vec<u8>)
+}
+
+/// Apply the bad sector policy to a sector of `size` bytes, `recorded` is the data the image has, if any.
+/// Images call this from `read_sector` when the status is bad.
+pub fn bad_sector_data(policy: &BadSectorPolicy,cyl: usize,head: usize,sec: usize,recorded: Option<&[u8]>,size: usize) -> Result<Vec<u8>,DYNERR> {
+    match policy {
+        BadSectorPolicy::Fail => {
+            error!("cyl {} head {} sector {} is marked bad",cyl,head,sec);
+            Err(Box::new(Error::BadSector))
+        },
+        BadSectorPolicy::Keep => {
+            warn!("cyl {} head {} sector {} is marked bad, keeping recorded data",cyl,head,sec);
+            Ok(quantize_block(recorded.unwrap_or(&[]),size))
+        },
+        BadSectorPolicy::Fill(pattern) => {
+            warn!("cyl {} head {} sector {} is marked bad, filling",cyl,head,sec);
+            Ok(pattern.iter().cycle().take(size).copied().collect())
+        }
+    }
+}
+
+/// State of a disk image saved in memory by `DiskImage::snapshot`.  The image journals its
+/// changes while the snapshot is held, see `img::journal`.
+pub struct Snapshot {
+    typ: DiskImageType,
+    kind: DiskKind,
+    token: std::sync::Arc<()>
+}
+
+impl Snapshot {
+    pub fn image_type(&self) -> DiskImageType {
+        self.typ
+    }
+}
+
+/// Range of a sector or block within the buffer of a flat image.  A pointer read from a damaged
+/// file system can land anywhere, so this is checked rather than left to panic.
+pub fn flat_range(data_len: usize,offset: usize,len: usize) -> Result<std::ops::Range<usize>,Error> {
+    match offset.checked_add(len) {
+        Some(end) if end <= data_len => Ok(offset..end),
+        _ => {
+            debug!("offset {} is outside the image",offset);
+            Err(Error::SectorAccess)
+        }
+    }
+}
+
 /// The main trait for working with any kind of disk image.
 /// The corresponding trait object serves as storage for `DiskFS`.
 /// Reading can mutate the object because the image may be keeping
@@ -382,12 +538,71 @@ pub trait DiskImage {
     fn to_bytes(&mut self) -> Vec<u8>;
     /// Read a block from the image; can affect disk state
     fn read_block(&mut self,addr: fs::Block) -> Result<Vec<u8>,DYNERR>;
+    /// Read a block into `buf`, copying as much of the block as fits.
+    /// Images that keep the data in memory override this to avoid building a new buffer.
+    fn read_block_into(&mut self,addr: fs::Block,buf: &mut [u8]) -> STDRESULT {
+        let dat = self.read_block(addr)?;
+        let n = usize::min(buf.len(),dat.len());
+        buf[0..n].copy_from_slice(&dat[0..n]);
+        Ok(())
+    }
     /// Write a block to the image
     fn write_block(&mut self, addr: fs::Block, dat: &[u8]) -> STDRESULT;
     /// Read a physical sector from the image; can affect disk state
     fn read_sector(&mut self,cyl: usize,head: usize,sec: usize) -> Result<Vec<u8>,DYNERR>;
     /// Write a physical sector to the image
     fn write_sector(&mut self,cyl: usize,head: usize,sec: usize,dat: &[u8]) -> STDRESULT;
+    /// Read the tag bytes of a physical sector, which only Apple 3.5 inch sectors have.
+    /// Default is an error, since most images have no place for them.
+    fn read_tags(&mut self,_cyl: usize,_head: usize,_sec: usize) -> Result<Vec<u8>,DYNERR> {
+        Err(Box::new(Error::ImageTypeMismatch))
+    }
+    /// Write the tag bytes of a physical sector, leaving the sector data alone
+    fn write_tags(&mut self,_cyl: usize,_head: usize,_sec: usize,_tags: &[u8]) -> STDRESULT {
+        Err(Box::new(Error::ImageTypeMismatch))
+    }
+    /// Sectors whose status is other than normal, in track order.
+    /// Default is an empty map, since most images have no place to record the status.
+    fn error_map(&mut self) -> Vec<FlaggedSector> {
+        Vec::new()
+    }
+    /// Record the status of a physical sector.  The data is kept, unless the status is `NoData`.
+    fn set_sector_status(&mut self,_cyl: usize,_head: usize,_sec: usize,_status: SectorStatus) -> STDRESULT {
+        Err(Box::new(Error::ImageTypeMismatch))
+    }
+    /// Set what reading a bad sector produces.  The default is to keep the recorded data with a warning,
+    /// deleted data is always returned with a warning.  Images that cannot record bad sectors ignore this.
+    fn set_bad_sector_policy(&mut self,_policy: BadSectorPolicy) {}
+    /// Fix the times the image records when it is changed, see `crate::now`.
+    /// Images that record no times ignore this.
+    fn set_deterministic(&mut self,_yes: bool) {}
+    /// When the image is saved, drop bytes after the last whole block and pad a short last block,
+    /// rather than keeping the original length.  Only ProDOS ordered images use this.
+    fn set_repair_padding(&mut self,_yes: bool) {}
+    /// The journal of changes, images that support `snapshot` keep one (default is `None`)
+    fn journal(&mut self) -> Option<&mut journal::Journal> {
+        None
+    }
+    /// Save the state of the image, so that `rollback` can return to it.  While the snapshot
+    /// is held the image keeps the prior contents of whatever it changes (default method).
+    fn snapshot(&mut self) -> Snapshot {
+        let token = match self.journal() {
+            Some(j) => j.mark(),
+            None => std::sync::Arc::new(())
+        };
+        Snapshot { typ: self.what_am_i(), kind: self.kind(), token }
+    }
+    /// Return to the state saved by `snapshot`, the snapshot can be used again.
+    /// Snapshots taken after this one can no longer be rolled back to (default method).
+    fn rollback(&mut self,snap: &Snapshot) -> STDRESULT {
+        if snap.typ != self.what_am_i() {
+            error!("snapshot is of a {} image, not {}",snap.typ,self.what_am_i());
+            return Err(Box::new(Error::ImageTypeMismatch));
+        }
+        journal::rollback(self,&snap.token)?;
+        self.change_kind(snap.kind);
+        Ok(())
+    }
     /// Get the track buffer exactly in the form the image stores it; for user inspection
     fn get_track_buf(&mut self,cyl: usize,head: usize) -> Result<Vec<u8>,DYNERR>;
     /// Set the track buffer using another track buffer, the sizes must match
@@ -397,6 +612,14 @@ pub trait DiskImage {
     /// The expense of this operation can vary widely depending on the image type.
     /// No solution is not an error, i.e., we can return Ok(None).
     fn get_track_solution(&mut self,track: usize) -> Result<Option<TrackSolution>,DYNERR>;
+    /// Decode the given tracks ahead of time, in parallel where the image supports it, so that
+    /// later reads of their sectors are fast.  Default does nothing, which is right for images
+    /// that store sectors rather than track bits.
+    fn decode_tracks(&mut self,_tracks: &[usize]) {}
+    /// Hits and misses of the sector cache, if the image keeps one, see `img::cache`
+    fn cache_stats(&self) -> Option<cache::CacheStats> {
+        None
+    }
     /// Get the track bytes as aligned nibbles; for user inspection
     fn get_track_nibbles(&mut self,cyl: usize,head: usize) -> Result<Vec<u8>,DYNERR>;
     /// Write the track to a string suitable for display, input should be pre-aligned nibbles, e.g. from `get_track_nibbles`
@@ -444,6 +667,17 @@ pub trait DiskImage {
                         NibbleCode::None => json::JsonValue::Null,
                         n => json::JsonValue::String(n.to_string())
                     };
+                    trk_obj["data_rate"] = match sol.data_rate {
+                        Some(r) => json::JsonValue::String(r.to_string()),
+                        None => json::JsonValue::Null
+                    };
+                    trk_obj["bit_cell_ns"] = match sol.bit_timing {
+                        Some(t) => json::JsonValue::Number((t as usize * 125).into()),
+                        None => json::JsonValue::Null
+                    };
+                    trk_obj["sector_count"] = json::JsonValue::Number(sol.chss_map.len().into());
+                    trk_obj["sector_ids"] = sol.chss_map.iter().map(|x| x[2]).collect::<Vec<usize>>().into();
+                    trk_obj["sector_sizes"] = sol.chss_map.iter().map(|x| x[3]).collect::<Vec<usize>>().into();
                     trk_obj["chs_map"] = json::JsonValue::new_array();
                     for chss in sol.chss_map {
                         let mut chss_json = json::JsonValue::new_array();
@@ -464,12 +698,57 @@ pub trait DiskImage {
         } else {
             root["tracks"] = trk_ary;
         }
+        let flagged = self.error_map();
+        if !flagged.is_empty() {
+            let mut err_ary = json::JsonValue::new_array();
+            for f in flagged {
+                err_ary.push(json::object! {
+                    "cylinder": f.cyl,
+                    "head": f.head,
+                    "sector": f.sec,
+                    "status": f.status.to_string()
+                })?;
+            }
+            root["sector_errors"] = err_ary;
+        }
         if let Some(spaces) = indent {
             Ok(json::stringify_pretty(root,spaces))
         } else {
             Ok(json::stringify(root))
         }
     }
+    /// Write the track solutions as a table for reading, one line per track, sector IDs in physical order
+    fn geometry_table(&mut self) -> Result<String,DYNERR> {
+        let mut ans = format!("{}\n\n",self.kind());
+        ans += &format!("{:>5} {:>4} {:>4}  {:<5}{:<7}{:<9}{:>5} {:>4} {:>5}  {}\n","TRACK","CYL","HEAD","FLUX","NIBBLE","RATE","CELL","SECS","SIZE","IDS");
+        for trk in 0..self.track_count() {
+            let sol = match self.get_track_solution(trk)? {
+                Some(sol) => sol,
+                None => {
+                    ans += &format!("{:>5}  unsolved\n",trk);
+                    continue;
+                }
+            };
+            let rate = sol.data_rate.map(|r| r.to_string()).unwrap_or("-".to_string());
+            let cell = sol.bit_timing.map(|t| (t as usize * 125).to_string()).unwrap_or("-".to_string());
+            let size = match sol.chss_map.first() {
+                Some(first) if sol.chss_map.iter().all(|x| x[3]==first[3]) => first[3].to_string(),
+                Some(_) => "mixed".to_string(),
+                None => "-".to_string()
+            };
+            let ids: Vec<String> = sol.chss_map.iter().map(|x| x[2].to_string()).collect();
+            ans += &format!("{:>5} {:>4} {:>4}  {:<5}{:<7}{:<9}{:>5} {:>4} {:>5}  {}\n",
+                trk,sol.cylinder,sol.head,sol.flux_code.to_string(),sol.nib_code.to_string(),rate,cell,sol.chss_map.len(),size,ids.join(" "));
+        }
+        let flagged = self.error_map();
+        if !flagged.is_empty() {
+            ans += "\n";
+        }
+        for f in flagged {
+            ans += &format!("cyl {} head {} sector {} is {}\n",f.cyl,f.head,f.sec,f.status);
+        }
+        Ok(ans)
+    }
 }
 
 /// Test a buffer for a size match to DOS-oriented track and sector counts.
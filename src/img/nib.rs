@@ -27,7 +27,9 @@ pub struct Nib {
     tracks: usize,
     trk_cap: usize,
     data: Vec<u8>,
-    head_coords: HeadCoords
+    head_coords: HeadCoords,
+    cache: super::cache::SectorCache,
+    journal: img::journal::Journal
 }
 
 impl Nib {
@@ -51,15 +53,18 @@ impl Nib {
             tracks: 35,
             trk_cap: TRACK_BYTE_CAPACITY_NIB,
             data,
-            head_coords: HeadCoords { track: usize::MAX, bit_ptr: usize::MAX }
+            head_coords: HeadCoords { track: usize::MAX, bit_ptr: usize::MAX },
+            cache: super::cache::SectorCache::default(),
+            journal: img::journal::Journal::default()
         }
     }
     /// Get a reference to the track bits
     fn get_trk_bits_ref(&self,track: u8) -> &[u8] {
         &self.data[track as usize * self.trk_cap..(track as usize+1) * self.trk_cap]
     }
-    /// Get a mutable reference to the track bits
+    /// Get a mutable reference to the track bits, anything decoded from the track is forgotten
     fn get_trk_bits_mut(&mut self,track: u8) -> &mut [u8] {
+        self.cache.invalidate_track(track as usize);
         &mut self.data[track as usize * self.trk_cap..(track+1) as usize * self.trk_cap]
     }
     /// Create a lightweight trait object to read/write the bits.  The nibble format will be
@@ -98,17 +103,32 @@ impl img::woz::WozUnifier for Nib {
         self.tracks as usize
     }
     fn read_sector(&mut self,track: u8,sector: u8) -> Result<Vec<u8>,img::NibbleError> {
-        let mut reader = self.new_rw_obj(track);
-        let ans = reader.read_sector(self.get_trk_bits_ref(track),track,sector)?;
-        self.head_coords.bit_ptr = reader.get_bit_ptr();
+        let (ans,bit_ptr) = super::woz::read_cached(self,track,sector)?;
+        self.head_coords.bit_ptr = bit_ptr;
         Ok(ans)
     }
     fn write_sector(&mut self,dat: &[u8],track: u8,sector: u8) -> Result<(),img::NibbleError> {
+        img::journal::record(self,img::journal::Unit::Track(super::woz::track_to_cyl_head(self,track)));
         let mut writer = self.new_rw_obj(track);
         writer.write_sector(self.get_trk_bits_mut(track),dat,track,sector)?;
         self.head_coords.bit_ptr = writer.get_bit_ptr();
         Ok(())
     }
+    fn track_obj(&mut self,track: u8) -> Result<Box<dyn super::TrackBits>,img::NibbleError> {
+        match (track as usize) < self.tracks {
+            true => Ok(self.new_rw_obj(track)),
+            false => Err(img::NibbleError::BadTrack)
+        }
+    }
+    fn track_bits(&self,track: u8) -> Result<&[u8],img::NibbleError> {
+        match (track as usize) < self.tracks {
+            true => Ok(self.get_trk_bits_ref(track)),
+            false => Err(img::NibbleError::BadTrack)
+        }
+    }
+    fn sector_cache(&mut self) -> &mut super::cache::SectorCache {
+        &mut self.cache
+    }
 }
 
 impl img::DiskImage for Nib {
@@ -157,7 +177,9 @@ impl img::DiskImage for Nib {
                     tracks: 35,
                     trk_cap: TRACK_BYTE_CAPACITY_NIB,
                     data: buf.to_vec(),
-                    head_coords: HeadCoords { track: usize::MAX, bit_ptr: usize::MAX }
+                    head_coords: HeadCoords { track: usize::MAX, bit_ptr: usize::MAX },
+                    cache: super::cache::SectorCache::default(),
+                    journal: img::journal::Journal::default()
                 };
                 if let Ok(Some(_sol)) = disk.get_track_solution(0) {
                     debug!("setting disk kind to {}",disk.kind);
@@ -173,7 +195,9 @@ impl img::DiskImage for Nib {
                     tracks: 35,
                     trk_cap: TRACK_BYTE_CAPACITY_NB2,
                     data: buf.to_vec(),
-                    head_coords: HeadCoords { track: usize::MAX, bit_ptr: usize::MAX }
+                    head_coords: HeadCoords { track: usize::MAX, bit_ptr: usize::MAX },
+                    cache: super::cache::SectorCache::default(),
+                    journal: img::journal::Journal::default()
                 };
                 if let Ok(Some(_sol)) = disk.get_track_solution(0) {
                     debug!("setting disk kind to {}",disk.kind);
@@ -192,11 +216,15 @@ impl img::DiskImage for Nib {
     fn to_bytes(&mut self) -> Vec<u8> {
         self.data.clone()
     }
+    fn journal(&mut self) -> Option<&mut img::journal::Journal> {
+        Some(&mut self.journal)
+    }
     fn get_track_buf(&mut self,cyl: usize,head: usize) -> Result<Vec<u8>,DYNERR> {
         let track_num = super::woz::cyl_head_to_track(self, cyl, head)?;
         Ok(self.get_trk_bits_ref(track_num as u8).to_vec())
     }
     fn set_track_buf(&mut self,cyl: usize,head: usize,dat: &[u8]) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Track([cyl,head]));
         let track_num = super::woz::cyl_head_to_track(self, cyl, head)?;
         let bits = self.get_trk_bits_mut(track_num as u8);
         if bits.len()!=dat.len() {
@@ -216,6 +244,8 @@ impl img::DiskImage for Nib {
                 head,
                 flux_code: img::FluxCode::GCR,
                 nib_code: img::NibbleCode::N53,
+                data_rate: Some(img::DataRate::R250Kbps),
+                bit_timing: Some(32),
                 chss_map
             }));
         }
@@ -227,11 +257,19 @@ impl img::DiskImage for Nib {
                 head,
                 flux_code: img::FluxCode::GCR,
                 nib_code: img::NibbleCode::N62,
+                data_rate: Some(img::DataRate::R250Kbps),
+                bit_timing: Some(32),
                 chss_map
             }));
         }
         return Err(Box::new(img::Error::UnknownDiskKind));
     }
+    fn decode_tracks(&mut self,tracks: &[usize]) {
+        super::woz::decode_tracks(self,tracks)
+    }
+    fn cache_stats(&self) -> Option<img::cache::CacheStats> {
+        Some(self.cache.stats())
+    }
     fn get_track_nibbles(&mut self,cyl: usize,head: usize) -> Result<Vec<u8>,DYNERR> {
         let track_num = super::woz::cyl_head_to_track(self, cyl, head)?;
         let mut reader = self.new_rw_obj(track_num as u8);
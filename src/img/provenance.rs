@@ -0,0 +1,153 @@
+//! ## Provenance sidecar
+//!
+//! When `SaveOptions::provenance` is set, every save of an image appends an entry to a sidecar JSON file
+//! that sits beside the image, named by adding `.provenance.json` to the image path.
+//! The sidecar keeps the hash of the image as it was first found, and for each save the operation,
+//! i.e. the subcommand and the arguments it was given, the a2kit version, the time, and the hashes before and after.  Hashes are SHA-256 of the file
+//! as stored, i.e., including any gzip or zip wrapper.  Example:
+//! ```json
+//! {
+//!     "a2kit_version": "3.0.0",
+//!     "source": { "path": "disk.woz", "sha256": "9f86d0..." },
+//!     "sha256": "60303a...",
+//!     "operations": [
+//!         {
+//!             "time": "2024-05-01T12:00:00",
+//!             "a2kit_version": "3.0.0",
+//!             "operation": { "name": "put", "args": { "dimg": "disk.woz", "file": "hello", "type": "atxt" } },
+//!             "before": "9f86d0...",
+//!             "after": "60303a..."
+//!         }
+//!     ]
+//! }
+//! ```
+//! If the image did not exist before the first save, the source hash is null.
+//! Verification checks that the hashes form an unbroken chain ending with the image as it is now.
+
+use log::error;
+use sha2::{Sha256,Digest};
+use crate::{STDRESULT,DYNERR};
+
+/// SHA-256 of `dat` as a lower case hex string
+pub fn sha256(dat: &[u8]) -> String {
+    hex::encode(Sha256::digest(dat))
+}
+
+/// What was done to an image, as it is recorded in the sidecar
+#[derive(Clone,Debug,PartialEq)]
+pub struct Operation {
+    /// name of the subcommand, e.g., `put`
+    pub name: String,
+    /// arguments by their long names, with the values as given, flags have the value `true`
+    pub args: Vec<(String,Vec<String>)>
+}
+
+impl Operation {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), args: Vec::new() }
+    }
+    /// The operation `name` with the arguments that were given on the command line, `sub` being the matches of the subcommand
+    pub fn from_matches(name: &str,sub: &clap::ArgMatches) -> Self {
+        let mut ans = Self::new(name);
+        for id in sub.ids() {
+            let id = id.as_str();
+            if id == "provenance" || sub.value_source(id) != Some(clap::parser::ValueSource::CommandLine) {
+                continue;
+            }
+            if let Ok(Some(vals)) = sub.try_get_raw(id) {
+                ans.args.push((id.to_string(),vals.map(|v| v.to_string_lossy().to_string()).collect()));
+            }
+        }
+        ans
+    }
+    fn to_json(&self) -> json::JsonValue {
+        let mut args = json::JsonValue::new_object();
+        for (key,vals) in &self.args {
+            args[key] = match vals.len() {
+                1 => vals[0].as_str().into(),
+                _ => vals.clone().into()
+            };
+        }
+        json::object! {
+            "name": self.name.as_str(),
+            "args": args
+        }
+    }
+}
+
+/// Path of the sidecar for the image at `img_path`, a zip member selection is dropped
+pub fn sidecar_path(img_path: &str) -> String {
+    let (path,_) = super::archive::split_member(img_path);
+    [path,".provenance.json"].concat()
+}
+
+/// Read the sidecar for the image at `img_path`, if there is one
+pub fn load(img_path: &str) -> Result<Option<json::JsonValue>,DYNERR> {
+    match std::fs::read_to_string(sidecar_path(img_path)) {
+        Ok(s) => Ok(Some(json::parse(&s)?)),
+        Err(_) => Ok(None)
+    }
+}
+
+/// Append an operation to the sidecar, creating it if necessary.
+/// `before` is the image file as it was before the save, if it existed, and `after` is the file as saved.
+/// The time of the operation is fixed if `deterministic`, see `crate::now`.
+pub fn record(img_path: &str,op: &Operation,before: Option<&[u8]>,after: &[u8],deterministic: bool) -> STDRESULT {
+    let (path,_) = super::archive::split_member(img_path);
+    let before_hash = before.map(sha256);
+    let after_hash = sha256(after);
+    let mut sidecar = match load(img_path)? {
+        Some(obj) => obj,
+        None => {
+            let file_name = std::path::Path::new(path).file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            json::object! {
+                "a2kit_version": env!("CARGO_PKG_VERSION"),
+                "source": { "path": file_name, "sha256": before_hash.clone() },
+                "sha256": json::Null,
+                "operations": []
+            }
+        }
+    };
+    sidecar["a2kit_version"] = env!("CARGO_PKG_VERSION").into();
+    sidecar["sha256"] = after_hash.as_str().into();
+    sidecar["operations"].push(json::object! {
+        "time": crate::now(deterministic).format("%Y-%m-%dT%H:%M:%S").to_string(),
+        "a2kit_version": env!("CARGO_PKG_VERSION"),
+        "operation": op.to_json(),
+        "before": before_hash,
+        "after": after_hash
+    })?;
+    std::fs::write(sidecar_path(img_path),json::stringify_pretty(sidecar,4))?;
+    Ok(())
+}
+
+/// Check the sidecar against the image at `img_path`, returning a list of problems, empty if all is well.
+pub fn verify(img_path: &str) -> Result<Vec<String>,DYNERR> {
+    let (path,_) = super::archive::split_member(img_path);
+    let sidecar = match std::fs::read_to_string(sidecar_path(img_path)) {
+        Ok(s) => json::parse(&s)?,
+        Err(e) => {
+            error!("no provenance found for {}",path);
+            return Err(Box::new(e));
+        }
+    };
+    let mut ans = Vec::new();
+    let mut prev = sidecar["source"]["sha256"].as_str().map(|s| s.to_string());
+    for (i,op) in sidecar["operations"].members().enumerate() {
+        let before = op["before"].as_str().map(|s| s.to_string());
+        if before != prev {
+            ans.push(format!("operation {} does not start from the image left by the one before",i+1));
+        }
+        prev = op["after"].as_str().map(|s| s.to_string());
+    }
+    let recorded = sidecar["sha256"].as_str().map(|s| s.to_string());
+    if recorded != prev {
+        ans.push("final hash does not match the last operation".to_string());
+    }
+    match std::fs::read(path) {
+        Ok(dat) if Some(sha256(&dat)) == recorded => {},
+        Ok(_) => ans.push("image was changed after the last recorded operation".to_string()),
+        Err(_) => ans.push("image could not be read".to_string())
+    }
+    Ok(ans)
+}
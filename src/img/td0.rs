@@ -3,8 +3,12 @@
 //! The assumptions herein are largely based on Dave Dunfield's notes found in the
 //! ImageDisk package.  This uses the `retrocompressor` crate to handle advanced TD0 compression.
 //! As of this writing the creators of the TD0 format have never revealed its details.
+//!
+//! This module is library-safe, see `crate::img`.
+
+#![deny(clippy::panic,clippy::unwrap_used,clippy::expect_used)]
 
-use chrono::Timelike;
+use chrono::{Datelike,Timelike};
 use num_traits::FromPrimitive;
 use num_derive::FromPrimitive;
 use log::{warn,info,trace,debug,error};
@@ -98,10 +102,10 @@ const STEPPING_MASK: u8 = 0x03;
 const COMMENT_MASK: u8 = 0x80;
 
 // const FLAG_DUP_SEC: u8 = 0x01;
-// const FLAG_CRC_ERR: u8 = 0x02;
-// const FLAG_DEL_DAT: u8 = 0x04;
+const FLAG_CRC_ERR: u8 = 0x02;
+const FLAG_DEL_DAT: u8 = 0x04;
 // const FLAG_SKIPPED: u8 = 0x10;
-// const FLAG_NO_DAT: u8 = 0x20;
+const FLAG_NO_DAT: u8 = 0x20;
 // const FLAG_NO_ID: u8 = 0x40;
 
 pub fn file_extensions() -> Vec<String> {
@@ -193,19 +197,19 @@ pub struct Td0 {
     comment_header: Option<CommentHeader>,
     comment_data: Option<String>, // when flattening, newlines should be replaced by nulls
     tracks: Vec<Track>,
-    end: u8 // 0xff
+    end: u8, // 0xff
+    journal: img::journal::Journal,
+    bad_sectors: img::BadSectorPolicy,
+    /// fix the time of a comment that is added
+    deterministic: bool
 }
 
 impl CommentHeader {
-    fn pack_timestamp(maybe_time: Option<chrono::NaiveDateTime>) -> [u8;6] {
-        let now = match maybe_time {
-            Some(time) => time,
-            _ => chrono::Local::now().naive_local()
-        };
-        let mut year = u32::from_str_radix(&now.format("%Y").to_string(),10).expect("date error");
-        let month = u8::from_str_radix(&now.format("%m").to_string(),10).expect("date error");
-        let day = u8::from_str_radix(&now.format("%d").to_string(),10).expect("date error");
-        if year - 1900 > u8::MAX as u32 {
+    fn pack_timestamp(now: chrono::NaiveDateTime) -> [u8;6] {
+        let mut year = now.year().max(0) as u32;
+        let month = now.month() as u8;
+        let day = now.day() as u8;
+        if year > 1900 + u8::MAX as u32 {
             warn!("timestamp is pegged at {} years after reference date",u8::MAX);
             year = 1900 + u8::MAX as u32;
         }
@@ -232,6 +236,9 @@ impl CommentHeader {
 
 impl Sector {
     /// Create the sector structure
+    /// # Panics
+    /// If `byte_count` is not a power of 2 from 128 to 8192
+    #[allow(clippy::panic)]
     fn create(cylinder: u8,head: u8,id: u8,byte_count: usize) -> Self {
         match byte_count {
             128 | 256 | 512 | 1024 | 2048 | 4096 | 8192 => {},
@@ -288,6 +295,34 @@ impl Sector {
         }
         Ok(())
     }
+    fn status(&self) -> img::SectorStatus {
+        match (self.header.flags & NO_DATA_MASK,self.header.flags & FLAG_CRC_ERR,self.header.flags & FLAG_DEL_DAT) {
+            (x,_,_) if x > 0 => img::SectorStatus::NoData,
+            (_,0,0) => img::SectorStatus::Normal,
+            (_,0,_) => img::SectorStatus::Deleted,
+            (_,_,0) => img::SectorStatus::CrcError,
+            _ => img::SectorStatus::CrcErrorDeleted
+        }
+    }
+    /// Change the flags to match the status, the data is kept unless the status is `NoData`
+    fn set_status(&mut self,status: img::SectorStatus) -> STDRESULT {
+        if status == img::SectorStatus::NoData {
+            self.header.flags = (self.header.flags & !(FLAG_CRC_ERR | FLAG_DEL_DAT)) | FLAG_NO_DAT;
+            self.data = Vec::new();
+            return Ok(());
+        }
+        if self.header.flags & NO_DATA_MASK > 0 {
+            self.pack(&vec![0;SECTOR_SIZE_BASE << self.header.sector_shift])?;
+        }
+        self.header.flags &= !(FLAG_CRC_ERR | FLAG_DEL_DAT);
+        self.header.flags |= match status {
+            img::SectorStatus::Deleted => FLAG_DEL_DAT,
+            img::SectorStatus::CrcError => FLAG_CRC_ERR,
+            img::SectorStatus::CrcErrorDeleted => FLAG_CRC_ERR | FLAG_DEL_DAT,
+            _ => 0
+        };
+        Ok(())
+    }
     /// Unpack sector data as raw bytes.
     fn unpack(&self) -> Result<Vec<u8>,DYNERR> {
         trace!("unpacking sector {}",self.header.id);
@@ -420,21 +455,20 @@ impl DiskStruct for Sector {
     fn to_bytes(&self) -> Vec<u8> {
         let header = match self.unpack() {
             Ok(unpacked) => {
-                let mut header = SectorHeader::from_bytes(&self.header.to_bytes()).expect("header mismatch");
-                header.crc = (crc16(0,&unpacked) & 0xff) as u8;
+                let mut header = self.header.to_bytes();
+                header[5] = (crc16(0,&unpacked) & 0xff) as u8;
                 header
             },
-            _ => {
-                SectorHeader::from_bytes(&self.header.to_bytes()).expect("header mismatch")
-            }
+            _ => self.header.to_bytes()
         };
         [
-            header.to_bytes(),
+            header,
             self.data.clone()
         ].concat()
     }
     fn update_from_bytes(&mut self,_bytes: &[u8]) -> Result<(),DiskStructError> {
-        panic!("unreachable was reached"); // do not use
+        // sectors and tracks are parsed as part of `Td0::from_bytes`
+        Err(DiskStructError::IllegalValue)
     }
     fn from_bytes(bytes: &[u8]) -> Result<Self,DiskStructError> where Self: Sized {
         let mut ans = Sector::new();
@@ -469,7 +503,8 @@ impl DiskStruct for Track {
         ans
     }
     fn update_from_bytes(&mut self,_bytes: &[u8]) -> Result<(),DiskStructError> {
-        panic!("unreachable was reached"); // do not use
+        // sectors and tracks are parsed as part of `Td0::from_bytes`
+        Err(DiskStructError::IllegalValue)
     }
     fn from_bytes(bytes: &[u8]) -> Result<Self,DiskStructError> where Self: Sized {
         let mut ans = Track::new();
@@ -482,7 +517,16 @@ impl Td0 {
     /// Creates a "normal" compression TD0.
     /// If we want advanced compression we can transform the flattened image
     /// with retrocompressor::td0::compress at some later point.
+    /// # Panics
+    /// If the kind of disk cannot be put in a TD0
     pub fn create(kind: img::DiskKind) -> Self {
+        Self::create_at(kind,crate::now(false))
+    }
+    /// Same as `create`, with `time` as the time of the comment.
+    /// # Panics
+    /// If the kind of disk cannot be put in a TD0
+    #[allow(clippy::panic)]
+    pub fn create_at(kind: img::DiskKind,time: chrono::NaiveDateTime) -> Self {
         let comment_string = "created by a2kit v".to_string() + env!("CARGO_PKG_VERSION");
         let layout = match kind {
             img::DiskKind::D3(layout) => layout,
@@ -507,6 +551,7 @@ impl Td0 {
             }
         };
         let drive_type = match kind {
+            img::DiskKind::D525(super::names::IBM_DSHD) => 2,
             img::DiskKind::D3(_) => 3,
             img::DiskKind::D35(_) => 4,
             img::DiskKind::D525(_) => 1,
@@ -535,11 +580,14 @@ impl Td0 {
             comment_header: Some(CommentHeader {
                 crc: [0,0],
                 data_length: u16::to_le_bytes(comment_string.len() as u16),
-                timestamp: CommentHeader::pack_timestamp(None)
+                timestamp: CommentHeader::pack_timestamp(time)
             }),
             comment_data: Some(comment_string),
             tracks,
-            end: 0xff
+            end: 0xff,
+            journal: img::journal::Journal::default(),
+            bad_sectors: img::BadSectorPolicy::default(),
+            deterministic: false
         }
     }
     fn get_track_mut(&mut self,cyl: usize,head: usize) -> Result<&mut Track,img::Error> {
@@ -610,7 +658,10 @@ impl img::DiskImage for Td0 {
         self.heads
     }
     fn track_2_ch(&self,track: usize) -> [usize;2] {
-        [self.tracks[track].header.cylinder as usize,(self.tracks[track].header.head & HEAD_MASK) as usize]
+        match self.tracks.get(track) {
+            Some(trk) => [trk.header.cylinder as usize,(trk.header.head & HEAD_MASK) as usize],
+            None => [track/self.heads,track%self.heads]
+        }
     }
     fn ch_2_track(&self,ch: [usize;2]) -> usize {
         for i in 0..self.tracks.len() {
@@ -618,7 +669,8 @@ impl img::DiskImage for Td0 {
                 return i
             }
         }
-        panic!("cylinder {}, head {} does not exist",ch[0],ch[1]);
+        debug!("cylinder {}, head {} does not exist",ch[0],ch[1]);
+        self.tracks.len()
     }
     fn byte_capacity(&self) -> usize {
         let mut ans = 0;
@@ -674,6 +726,7 @@ impl img::DiskImage for Td0 {
         }
     }
     fn write_block(&mut self, addr: Block, dat: &[u8]) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Block(addr));
         trace!("writing {}",addr);
         match addr {
             Block::CPM((_block,_bsh,off)) => {
@@ -717,6 +770,7 @@ impl img::DiskImage for Td0 {
     }
     fn read_sector(&mut self,cyl: usize,head: usize,sec: usize) -> Result<Vec<u8>,DYNERR> {
         trace!("seeking sector {} (R)",sec);
+        let policy = self.bad_sectors.clone();
         let trk = self.get_track_mut(cyl,head)?;
         // advance to the requested sector
         for _i in 0..trk.sectors.len() {
@@ -724,12 +778,18 @@ impl img::DiskImage for Td0 {
             let curr = &trk.sectors[sec_idx];
             if sec==curr.header.id as usize {
                 trace!("reading sector {}",sec);
-                return match curr.header.flags & NO_DATA_MASK {
-                    0 => Ok(curr.unpack()?),
-                    _ => {
+                let sec_size = SECTOR_SIZE_BASE << curr.header.sector_shift;
+                return match curr.status() {
+                    img::SectorStatus::Normal => Ok(curr.unpack()?),
+                    img::SectorStatus::Deleted => {
+                        warn!("cyl {} head {} sector {} has deleted data",cyl,head,sec);
+                        Ok(curr.unpack()?)
+                    },
+                    img::SectorStatus::NoData => {
                         debug!("cyl {} head {} sector {}: no data available",cyl,head,sec);
-                        Err(Box::new(img::Error::SectorAccess))
-                    }
+                        img::bad_sector_data(&policy,cyl,head,sec,None,sec_size)
+                    },
+                    _ => img::bad_sector_data(&policy,cyl,head,sec,Some(&curr.unpack()?),sec_size)
                 };
             }
             trace!("skip sector {}",curr.header.id);
@@ -738,6 +798,7 @@ impl img::DiskImage for Td0 {
         Err(Box::new(img::Error::SectorAccess))
     }
     fn write_sector(&mut self,cyl: usize,head: usize,sec: usize,dat: &[u8]) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Sector([cyl,head,sec]));
         trace!("seeking sector {} (W)",sec);
         let trk = self.get_track_mut(cyl,head)?;
         // advance to the requested sector
@@ -754,6 +815,41 @@ impl img::DiskImage for Td0 {
         error!("sector {} not found",sec);
         Err(Box::new(img::Error::SectorAccess))
     }
+    fn error_map(&mut self) -> Vec<img::FlaggedSector> {
+        let mut ans = Vec::new();
+        for trk in &self.tracks {
+            for sec in &trk.sectors {
+                let status = sec.status();
+                if status != img::SectorStatus::Normal {
+                    ans.push(img::FlaggedSector {
+                        cyl: trk.header.cylinder as usize,
+                        head: (trk.header.head & HEAD_MASK) as usize,
+                        sec: sec.header.id as usize,
+                        status
+                    });
+                }
+            }
+        }
+        ans
+    }
+    fn set_sector_status(&mut self,cyl: usize,head: usize,sec: usize,status: img::SectorStatus) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Status([cyl,head,sec]));
+        img::journal::record(self,img::journal::Unit::Sector([cyl,head,sec]));
+        let trk = self.get_track_mut(cyl,head)?;
+        match trk.sectors.iter_mut().find(|s| s.header.id as usize==sec) {
+            Some(curr) => curr.set_status(status),
+            None => {
+                error!("sector {} not found",sec);
+                Err(Box::new(img::Error::SectorAccess))
+            }
+        }
+    }
+    fn set_bad_sector_policy(&mut self,policy: img::BadSectorPolicy) {
+        self.bad_sectors = policy;
+    }
+    fn set_deterministic(&mut self,yes: bool) {
+        self.deterministic = yes;
+    }
     fn from_bytes(compressed: &[u8]) -> Result<Self,DiskStructError> {
         let mut ptr: usize = 0;
         let mut header_slice = optional_get_slice!(compressed,ptr,12,"image header").to_vec();
@@ -780,7 +876,7 @@ impl img::DiskImage for Td0 {
             b"TD" => {
                 compressed.to_vec()
             },
-            _ => panic!("unreachable was reached")
+            _ => return Err(DiskStructError::UnexpectedValue)
         };
         let has_comment = test_header.stepping & COMMENT_MASK > 0;
         ptr = 0;
@@ -793,22 +889,35 @@ impl img::DiskImage for Td0 {
             comment_header: None,
             comment_data: None,
             tracks: Vec::new(),
-            end: 0xff
+            end: 0xff,
+            journal: img::journal::Journal::default(),
+            bad_sectors: img::BadSectorPolicy::default(),
+            deterministic: false
         };
         if has_comment {
-            ans.comment_header = Some(CommentHeader::from_bytes(&optional_get_slice!(expanded,ptr,10,"comment header").to_vec()).expect("unreachable"));
-            let comment_len = u16::from_le_bytes(ans.comment_header.as_ref().unwrap().data_length) as usize;
-            ans.comment_data = Some(String::from_utf8_lossy(&optional_get_slice!(expanded,ptr,comment_len,"comment data").to_vec()).to_string());
-            debug!("comment data `{}`",ans.comment_data.as_ref().unwrap());
+            let comment_header = CommentHeader::from_bytes(optional_get_slice!(expanded,ptr,10,"comment header"))?;
+            let comment_len = u16::from_le_bytes(comment_header.data_length) as usize;
+            let comment_data = String::from_utf8_lossy(optional_get_slice!(expanded,ptr,comment_len,"comment data")).to_string();
+            debug!("comment data `{}`",comment_data);
             // CRC of comment
-            if u16::from_le_bytes(ans.comment_header.as_ref().unwrap().crc)!=crc16(0,&expanded[14..22+comment_len]) {
+            if u16::from_le_bytes(comment_header.crc)!=crc16(0,&expanded[14..22+comment_len]) {
                 warn!("comment area CRC mismatch");
                 return Err(DiskStructError::IllegalValue);
             }
+            ans.comment_header = Some(comment_header);
+            ans.comment_data = Some(comment_data);
         }
         // don't use Track::from_bytes because it may panic
-        while expanded[ptr]!=0xff {
-            let header = TrackHeader::from_bytes(&optional_get_slice!(expanded,ptr,4,"track header").to_vec()).expect("unreachable");
+        loop {
+            match expanded.get(ptr) {
+                Some(0xff) => break,
+                Some(_) => {},
+                None => {
+                    debug!("TD0 ended without the end of image marker");
+                    return Err(DiskStructError::OutOfData);
+                }
+            }
+            let header = TrackHeader::from_bytes(optional_get_slice!(expanded,ptr,4,"track header"))?;
             // CRC of track header
             // We will not stop for bad track CRC, but do warn
             let expected_track_crc = crc16(0,&header.to_bytes()[0..3]);
@@ -823,7 +932,11 @@ impl img::DiskImage for Td0 {
             trace!("found cyl {} head {} with {} sectors",trk.header.cylinder,trk.header.head & HEAD_MASK,trk.header.sectors);
             for i in 0..trk.header.sectors {
                 let mut sec = Sector::new();
-                sec.header = SectorHeader::from_bytes(&optional_get_slice!(expanded,ptr,6,"sector header").to_vec()).expect("unreachable");
+                sec.header = SectorHeader::from_bytes(optional_get_slice!(expanded,ptr,6,"sector header"))?;
+                if sec.header.sector_shift > 6 {
+                    debug!("sector size code {} is out of range",sec.header.sector_shift);
+                    return Err(DiskStructError::IllegalValue);
+                }
                 trace!("get sector {}, size {}",sec.header.id,128 << sec.header.sector_shift);
                 if sec.header.flags & NO_DATA_MASK == 0 {
                     let size_bytes = optional_get_slice!(expanded,ptr,2,"sector data header").to_vec();
@@ -852,6 +965,10 @@ impl img::DiskImage for Td0 {
             }
             ans.tracks.push(trk);
         }
+        if ans.tracks.is_empty() {
+            debug!("TD0 has no tracks");
+            return Err(DiskStructError::OutOfData);
+        }
         debug!("disk capacity {}",ans.byte_capacity());
         // TODO: this works for now, but we should have the TD0 object set up a pattern
         // that can be explicitly matched against the disk kind.
@@ -909,7 +1026,8 @@ impl img::DiskImage for Td0 {
         // that the decoder will not give up before the end of disk marker.  The following
         // is nothing special, just 7 randomly chosen bytes.
         ans.append(&mut vec![0x27,0x09,0xe1,0xc5,0x89,0x05,0x76]);
-        // apply the advanced compression
+        // apply the advanced compression, this works in memory and does not fail
+        #[allow(clippy::expect_used)]
         retrocompressor::td0::compress_slice(&ans).expect("advanced compression failed")
     }
     fn what_am_i(&self) -> img::DiskImageType {
@@ -924,6 +1042,9 @@ impl img::DiskImage for Td0 {
     fn change_kind(&mut self,kind: img::DiskKind) {
         self.kind = kind;
     }
+    fn journal(&mut self) -> Option<&mut img::journal::Journal> {
+        Some(&mut self.journal)
+    }
     fn get_track_buf(&mut self,_cyl: usize,_head: usize) -> Result<Vec<u8>,DYNERR> {
         error!("TD0 images have no track bits");
         return Err(Box::new(img::Error::ImageTypeMismatch));
@@ -947,6 +1068,13 @@ impl img::DiskImage for Td0 {
             head: (trk_obj.header.head & HEAD_MASK) as usize,
             flux_code,
             nib_code: img::NibbleCode::None,
+            data_rate: match self.header.data_rate & RATE_MASK {
+                0 => Some(img::DataRate::R250Kbps),
+                1 => Some(img::DataRate::R300Kbps),
+                2 => Some(img::DataRate::R500Kbps),
+                _ => None
+            },
+            bit_timing: None,
             chss_map
         }))
     }
@@ -1038,7 +1166,7 @@ impl img::DiskImage for Td0 {
                     self.comment_header = Some(CommentHeader {
                         crc: [0,0], // computed in to_bytes
                         data_length: [0,0], // computed in to_bytes
-                        timestamp: CommentHeader::pack_timestamp(None)
+                        timestamp: CommentHeader::pack_timestamp(crate::now(self.deterministic))
                     });
                 }
                 return Ok(());
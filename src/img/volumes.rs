@@ -0,0 +1,75 @@
+//! ## Multi-volume hard disk images
+//!
+//! Mass storage cards such as the CFFA3000 and MicroDrive/Turbo divide a drive into a sequence of
+//! 32 MB partitions, each holding one ProDOS volume.  Images of such drives, either bare (PO, HDV)
+//! or with a 2MG header, are the partitions laid end to end.  A volume is selected by its
+//! position, counting from 1.  The selected volume is treated as a PO image, and when it is saved
+//! it is put back in place, leaving the rest of the image, including any 2MG header, untouched.
+//!
+//! A ProDOS volume cannot exceed 65535 blocks, so the last block of each partition is not used.
+
+use log::error;
+use crate::{STDRESULT,DYNERR};
+
+const BLOCK_SIZE: usize = 512;
+/// blocks in one partition
+pub const PARTITION_BLOCKS: usize = 65536;
+/// blocks in the largest ProDOS volume
+pub const VOLUME_BLOCKS: usize = 65535;
+/// largest image file that will be buffered when a volume is selected
+pub const MAX_FILE_SIZE: usize = 1 << 30;
+
+/// Offset and length of the disk data, skipping the 2MG header if there is one
+fn data_span(dat: &[u8]) -> (usize,usize) {
+    if dat.len() >= 64 && dat[0..4] == *b"2IMG" {
+        let offset = u32::from_le_bytes([dat[24],dat[25],dat[26],dat[27]]) as usize;
+        let len = u32::from_le_bytes([dat[28],dat[29],dat[30],dat[31]]) as usize;
+        if offset <= dat.len() && len > 0 && offset + len <= dat.len() {
+            return (offset,len);
+        }
+        return (usize::min(offset,dat.len()),dat.len() - usize::min(offset,dat.len()));
+    }
+    (0,dat.len())
+}
+
+/// Number of partitions in the image, a short final partition counts
+pub fn count(dat: &[u8]) -> usize {
+    let (_,len) = data_span(dat);
+    len.div_ceil(PARTITION_BLOCKS * BLOCK_SIZE)
+}
+
+/// True if the image, going by `name`, is a hard disk image with several volumes
+pub fn is_multi_volume(dat: &[u8],name: &str) -> bool {
+    let name = name.to_lowercase();
+    count(dat) > 1 && ["po","2mg","2img","hdv"].iter().any(|x| name.ends_with(x))
+}
+
+/// Byte range of volume `vol` (counting from 1) within the image
+fn volume_range(dat: &[u8],vol: usize) -> Result<std::ops::Range<usize>,DYNERR> {
+    let (offset,len) = data_span(dat);
+    let n = count(dat);
+    if vol < 1 || vol > n {
+        error!("volume {} is not within 1..{}",vol,n);
+        return Err(Box::new(crate::commands::CommandError::OutOfRange));
+    }
+    let beg = offset + (vol - 1) * PARTITION_BLOCKS * BLOCK_SIZE;
+    let avail = offset + len - beg;
+    let end = beg + usize::min(avail - avail % BLOCK_SIZE,VOLUME_BLOCKS * BLOCK_SIZE);
+    Ok(beg..end)
+}
+
+/// Copy volume `vol` (counting from 1) out of the image, the result can be used as a PO image
+pub fn extract(dat: &[u8],vol: usize) -> Result<Vec<u8>,DYNERR> {
+    Ok(dat[volume_range(dat,vol)?].to_vec())
+}
+
+/// Put volume `vol` (counting from 1) back into the image, the size must not change
+pub fn splice(dat: &mut [u8],vol: usize,vol_dat: &[u8]) -> STDRESULT {
+    let rng = volume_range(dat,vol)?;
+    if rng.len() != vol_dat.len() {
+        error!("volume {} has {} bytes, but {} were given",vol,rng.len(),vol_dat.len());
+        return Err(Box::new(super::Error::ImageSizeMismatch));
+    }
+    dat[rng].copy_from_slice(vol_dat);
+    Ok(())
+}
@@ -6,8 +6,12 @@
 
 use log::{debug,trace};
 use std::fmt::Write;
+use std::collections::HashMap;
+use rayon::prelude::*;
 use crate::fs::Block;
 use crate::bios::skew;
+use crate::img;
+use crate::img::disk525;
 
 use crate::{STDRESULT,DYNERR};
 const RCH: &str = "unreachable was reached";
@@ -17,8 +21,38 @@ pub const TMAP_ID: u32 = 0x50414d54;
 pub const TRKS_ID: u32 = 0x534b5254;
 pub const WRIT_ID: u32 = 0x54495257;
 pub const META_ID: u32 = 0x4154454D;
+pub const FLUX_ID: u32 = 0x58554c46;
 pub const ALLOWED_TRACKS_525: [usize;1] = [35];
 
+/// Options for newly formatted tracks.  Emulators and flux tools that model the drive speed
+/// can be sensitive to these, e.g., a 5.25 inch track of 50000 bits rather than 51200.
+#[derive(Clone,Copy,Default)]
+pub struct TrackTiming {
+    /// optimal bit timing in units of 125 ns, only WOZ2 records this
+    pub bit_timing: Option<u8>,
+    /// bits in each 5.25 inch track
+    pub track_bits: Option<usize>
+}
+
+/// Format a 5.25 inch track with exactly `track_bits` bits, see `disk525::format_to_length`.
+pub fn format_525_track(vol: u8,track: u8,kind: img::DiskKind,buf_len: usize,track_bits: usize) -> Result<(Vec<u8>,Box<dyn img::TrackBits>),DYNERR> {
+    let (adr_fmt,dat_fmt,sync_bits) = match kind {
+        img::names::A2_DOS32_KIND => (disk525::SectorAddressFormat::create_std13(),disk525::SectorDataFormat::create_std13(),9),
+        img::names::A2_DOS33_KIND => (disk525::SectorAddressFormat::create_std16(),disk525::SectorDataFormat::create_std16(),10),
+        _ => {
+            log::error!("track length can only be set for 5.25 inch disks");
+            return Err(Box::new(img::Error::ImageTypeMismatch));
+        }
+    };
+    match disk525::format_to_length(vol,track,buf_len,adr_fmt,dat_fmt,sync_bits,track_bits) {
+        Some(ans) => Ok(ans),
+        None => {
+            log::error!("sectors cannot be fit into a track of {} bits",track_bits);
+            Err(Box::new(img::Error::ImageSizeMismatch))
+        }
+    }
+}
+
 pub struct HeadCoords {
     pub track: usize,
     pub bit_ptr: usize
@@ -33,6 +67,104 @@ pub trait WozUnifier {
     fn write_sector(&mut self,dat: &[u8],track: u8,sector: u8) -> Result<(),super::NibbleError>;
     /// Wrapper for track object function that works with a cached object
     fn read_sector(&mut self,track: u8,sector: u8) -> Result<Vec<u8>,super::NibbleError>;
+    /// Track object that can decode the given track
+    fn track_obj(&mut self,track: u8) -> Result<Box<dyn super::TrackBits>,super::NibbleError>;
+    /// Bits of the given track
+    fn track_bits(&self,track: u8) -> Result<&[u8],super::NibbleError>;
+    fn sector_cache(&mut self) -> &mut super::cache::SectorCache;
+}
+
+/// Sectors decoded from one track, each with the bit pointer just past it
+#[derive(Default)]
+struct DecodedTrack {
+    sectors: HashMap<u8,(Vec<u8>,usize)>,
+    /// sector IDs that appear more than once, which copy is found depends on the head position
+    dups: Vec<u8>
+}
+
+/// Decode every sector of a track.  Sectors that cannot be read or whose ID appears twice are left out.
+fn decode_track(obj: &mut Box<dyn super::TrackBits>,bits: &[u8],track: u8) -> DecodedTrack {
+    let mut ans = DecodedTrack::default();
+    let chss_map = match obj.chss_map(bits) {
+        Ok(m) => m,
+        Err(_) => return ans
+    };
+    for [_,_,sec,_] in &chss_map {
+        if chss_map.iter().filter(|x| x[2]==*sec).count() > 1 && !ans.dups.contains(&(*sec as u8)) {
+            trace!("track {} has duplicate sector {}, not caching it",track,sec);
+            ans.dups.push(*sec as u8);
+        }
+    }
+    for [_,_,sec,_] in chss_map {
+        if ans.dups.contains(&(sec as u8)) {
+            continue;
+        }
+        if let Ok(dat) = obj.read_sector(bits,track,sec as u8) {
+            ans.sectors.insert(sec as u8,(dat,obj.get_bit_ptr()));
+        }
+    }
+    ans
+}
+
+fn keep_track<T: WozUnifier>(woz: &mut T,track: u8,decoded: DecodedTrack) {
+    let kind = woz.kind();
+    let cache = woz.sector_cache();
+    cache.mark_track(kind,track as usize);
+    for sec in decoded.dups {
+        cache.mark_volatile(kind,track as usize,sec as usize);
+    }
+    for (sec,(dat,bit_ptr)) in decoded.sectors {
+        cache.insert(kind,track as usize,sec as usize,dat,bit_ptr);
+    }
+}
+
+/// Read a sector through the cache, returning the data and the bit pointer just past the sector.
+/// The first read of a track decodes and caches all its sectors, after that a miss reads only the
+/// sector asked for.  Errors come from reading the sector directly.
+pub fn read_cached<T: WozUnifier>(woz: &mut T,track: u8,sector: u8) -> Result<(Vec<u8>,usize),super::NibbleError> {
+    let kind = woz.kind();
+    if let Some(ans) = woz.sector_cache().get(kind,track as usize,sector as usize) {
+        return Ok(ans);
+    }
+    if !woz.sector_cache().has_track(kind,track as usize) {
+        let mut obj = woz.track_obj(track)?;
+        let decoded = decode_track(&mut obj,woz.track_bits(track)?,track);
+        let maybe_ans = decoded.sectors.get(&sector).cloned();
+        keep_track(woz,track,decoded);
+        if let Some(ans) = maybe_ans {
+            return Ok(ans);
+        }
+    }
+    let mut reader = woz.track_obj(track)?;
+    let dat = reader.read_sector(woz.track_bits(track)?,track,sector)?;
+    let bit_ptr = reader.get_bit_ptr();
+    woz.sector_cache().insert(kind,track as usize,sector as usize,dat.clone(),bit_ptr);
+    Ok((dat,bit_ptr))
+}
+
+/// Decode the given tracks in parallel and keep the sectors in the cache.
+/// Tracks that are already decoded or cannot be found are skipped.
+pub fn decode_tracks<T: WozUnifier + Sync>(woz: &mut T,tracks: &[usize]) {
+    let kind = woz.kind();
+    let mut jobs = Vec::new();
+    for track in tracks {
+        let track = *track as u8;
+        if woz.sector_cache().has_track(kind,track as usize) {
+            continue;
+        }
+        if let Ok(obj) = woz.track_obj(track) {
+            jobs.push((track,obj));
+        }
+    }
+    let shared: &T = woz;
+    let decoded: Vec<(u8,DecodedTrack)> = jobs.into_par_iter().filter_map(|(track,mut obj)| {
+        let bits = shared.track_bits(track).ok()?;
+        Some((track,decode_track(&mut obj,bits,track)))
+    }).collect();
+    debug!("decoded {} tracks",decoded.len());
+    for (track,sectors) in decoded {
+        keep_track(woz,track,sectors);
+    }
 }
 
 const CRC32_TAB: [u32;256] = [
@@ -118,7 +250,7 @@ pub fn get_next_chunk(ptr: usize,buf: &[u8]) -> (usize,u32,Option<Vec<u8>>) {
 		debug!("found chunk id {:08X}/{}, at offset {}, next offset {}",id,String::from_utf8_lossy(&u32::to_le_bytes(id)),ptr,next);
 	}
 	match id {
-		INFO_ID | TMAP_ID | TRKS_ID | WRIT_ID | META_ID => {
+		INFO_ID | TMAP_ID | TRKS_ID | WRIT_ID | META_ID | FLUX_ID => {
 			// found something
 			return (next,id,Some(buf[ptr..end].to_vec()));
 		}
@@ -187,17 +319,25 @@ pub fn read_block<T: WozUnifier>(woz: &mut T,addr: Block) -> Result<Vec<u8>,DYNE
 	}
 }
 
+/// Tag bytes already in a 3.5 inch sector, or zeros if the sector cannot be read
+fn existing_tags<T: WozUnifier>(woz: &mut T,track: u8,sector: u8) -> Vec<u8> {
+	match woz.read_sector(track,sector) {
+		Ok(v) if v.len()==524 => v[0..12].to_vec(),
+		_ => vec![0;12]
+	}
+}
+
 /// Write the given buffer to the file system allocation unit given by `addr`.
 /// Blocks are not allowed to cross track boundaries.
 /// This relies on the disk kind being correct to invoke the correct nibbles.
-/// For 3.5 inch disks, tag bytes should not be included.
+/// For 3.5 inch disks, tag bytes should not be included, the existing tag bytes are kept.
 pub fn write_block<T: WozUnifier>(woz: &mut T,addr:Block,dat: &[u8]) -> STDRESULT {
 	trace!("writing {}",addr);
 	let (ts_list,sec_len) = get_ts_list(addr,&woz.kind())?;
 	let track = ts_list[0][0];
 	let padded = match sec_len {
 		524 => {
-			let mut tagged: Vec<u8> = vec![0;12];
+			let mut tagged = existing_tags(woz,ts_list[0][0] as u8,ts_list[0][1] as u8);
 			tagged.append(&mut dat.to_vec());
 			super::quantize_block(&tagged, ts_list.len()*sec_len)
 		},
@@ -234,6 +374,14 @@ pub fn cyl_head_to_track<T: WozUnifier>(woz: &T,cyl: usize,head: usize) -> Resul
 	Ok(track)
 }
 
+/// Inverse of `cyl_head_to_track`
+pub fn track_to_cyl_head<T: WozUnifier>(woz: &T,track: u8) -> [usize;2] {
+	match woz.kind() {
+		super::names::A2_800_KIND => [track as usize/2,track as usize%2],
+		_ => [track as usize,0]
+	}
+}
+
 /// Read the physical track and sector.
 /// This relies on the disk kind being correct to invoke the correct nibbles.
 /// For 3.5 inch disks, the returned data has the tag bytes stripped.
@@ -249,12 +397,12 @@ pub fn read_sector<T: WozUnifier>(woz: &mut T,cyl: usize,head: usize,sector: usi
 
 /// Write the physical track and sector.
 /// This relies on the disk kind being correct to invoke the correct nibbles.
-/// For 3.5 inch disks, tag bytes should not be included.
+/// For 3.5 inch disks, tag bytes should not be included, the existing tag bytes are kept.
 pub fn write_sector<T: WozUnifier>(woz: &mut T,cyl: usize,head: usize,sector: usize,dat: &[u8]) -> STDRESULT {
 	let track = cyl_head_to_track(woz, cyl, head)?;
 	let padded = match woz.kind() {
 		super::names::A2_400_KIND | super::names::A2_800_KIND => {
-			let mut tagged: Vec<u8> = vec![0;12];
+			let mut tagged = existing_tags(woz,track as u8,sector as u8);
 			tagged.append(&mut dat.to_vec());
 			super::quantize_block(&tagged, 524)
 		},
@@ -265,6 +413,30 @@ pub fn write_sector<T: WozUnifier>(woz: &mut T,cyl: usize,head: usize,sector: us
 	return Ok(());
 }
 
+/// Read the 12 tag bytes that precede the data in a 3.5 inch sector.
+pub fn read_tags<T: WozUnifier>(woz: &mut T,cyl: usize,head: usize,sector: usize) -> Result<Vec<u8>,DYNERR> {
+	let track = cyl_head_to_track(woz,cyl,head)?;
+	let ans = woz.read_sector(track as u8,sector as u8)?;
+	if ans.len()!=524 {
+		debug!("sector {} on track {} has no tag bytes",sector,track);
+		return Err(Box::new(super::Error::ImageTypeMismatch));
+	}
+	Ok(ans[0..12].to_vec())
+}
+
+/// Write the 12 tag bytes of a 3.5 inch sector, keeping the data.
+pub fn write_tags<T: WozUnifier>(woz: &mut T,cyl: usize,head: usize,sector: usize,tags: &[u8]) -> STDRESULT {
+	let track = cyl_head_to_track(woz,cyl,head)?;
+	let mut full = woz.read_sector(track as u8,sector as u8)?;
+	if full.len()!=524 {
+		debug!("sector {} on track {} has no tag bytes",sector,track);
+		return Err(Box::new(super::Error::ImageTypeMismatch));
+	}
+	full[0..12].copy_from_slice(&super::quantize_block(tags,12));
+	woz.write_sector(&full,track as u8,sector as u8)?;
+	Ok(())
+}
+
 /// Display aligned track nibbles to stdout in columns of hex, track mnemonics
 pub fn display_track<T: WozUnifier>(woz: &T,start_addr: u16,trk: &[u8]) -> String {
 	let mut ans = String::new();
@@ -78,7 +78,9 @@ pub struct Woz1 {
     tmap: TMap,
     trks: Trks,
     meta: Option<Vec<u8>>,
-    head_coords: HeadCoords
+    head_coords: HeadCoords,
+    cache: super::cache::SectorCache,
+    journal: img::journal::Journal
 }
 
 impl Header {
@@ -122,6 +124,7 @@ impl Info {
             stringify!(write_protected) => hex_str=="00" || hex_str=="01",
             stringify!(synchronized) => hex_str=="00" || hex_str=="01",
             stringify!(cleaned) => hex_str=="00" || hex_str=="01",
+            stringify!(creator) => hex_str.len() <= self.creator.len(),
             _ => true
         }
     }
@@ -243,7 +246,9 @@ impl Woz1 {
             tmap: TMap::new(),
             trks: Trks::new(),
             meta: None,
-            head_coords: HeadCoords { track: usize::MAX, bit_ptr: usize::MAX }
+            head_coords: HeadCoords { track: usize::MAX, bit_ptr: usize::MAX },
+            cache: super::cache::SectorCache::default(),
+            journal: img::journal::Journal::default()
         }
     }
     /// Create the image of a specific kind of disk (panics if unsupported disk kind).
@@ -259,26 +264,38 @@ impl Woz1 {
             tmap: TMap::create(kind),
             trks: Trks::create(vol,kind),
             meta: None,
-            head_coords: HeadCoords { track: usize::MAX, bit_ptr: usize::MAX }
+            head_coords: HeadCoords { track: usize::MAX, bit_ptr: usize::MAX },
+            cache: super::cache::SectorCache::default(),
+            journal: img::journal::Journal::default()
         }
     }
-    /// Get index to the `Trk` structure, searching main track and nearby quarter-tracks.
-    fn get_trk_idx(&self,track: u8) -> Result<usize,img::NibbleError> {
-        let key_idx = track as usize*4;
-        if self.tmap.map[key_idx]!=0xff {
-            return Ok(self.tmap.map[key_idx] as usize);
+    /// Create the image with tracks of a given length, see `woz::TrackTiming`.
+    /// WOZ1 cannot record the bit timing, so asking for it is an error.
+    pub fn create_with_timing(vol: u8,kind: img::DiskKind,timing: &super::woz::TrackTiming) -> Result<Self,DYNERR> {
+        if timing.bit_timing.is_some() {
+            error!("WOZ1 cannot record bit timing, use WOZ2");
+            return Err(Box::new(img::Error::ImageTypeMismatch));
         }
-        if key_idx!=0 {
-            if self.tmap.map[key_idx-1]!=0xff {
-                return Ok(self.tmap.map[key_idx-1] as usize);
+        let mut ans = Self::create(vol,kind);
+        if let Some(track_bits) = timing.track_bits {
+            for (track,trk) in ans.trks.tracks.iter_mut().enumerate() {
+                let (bits,obj) = super::woz::format_525_track(vol,track as u8,kind,TRACK_BYTE_CAPACITY,track_bits)?;
+                trk.bits.copy_from_slice(&bits);
+                trk.bit_count = u16::to_le_bytes(obj.bit_count() as u16);
             }
         }
-        if key_idx!=self.tmap.map.len() {
-            if self.tmap.map[key_idx+1]!=0xff {
-                return Ok(self.tmap.map[key_idx+1] as usize);
-            }
+        Ok(ans)
+    }
+    /// Get index to the `Trk` structure, searching main track and nearby quarter-tracks.
+    /// Map entries that point outside the track list are treated as empty.
+    fn get_trk_idx(&self,track: u8) -> Result<usize,img::NibbleError> {
+        let entry = |key_idx: usize| self.tmap.map.get(key_idx).map(|x| *x as usize).filter(|x| *x<self.trks.tracks.len());
+        let key_idx = track as usize*4;
+        let nearby = [Some(key_idx),key_idx.checked_sub(1),Some(key_idx+1)];
+        match nearby.iter().flatten().find_map(|k| entry(*k)) {
+            Some(idx) => Ok(idx),
+            None => Err(img::NibbleError::BadTrack)
         }
-        Err(img::NibbleError::BadTrack)
     }
     /// Find track and get a reference
     fn get_trk_ref(&self,track: u8) -> Result<&Trk,img::NibbleError> {
@@ -288,8 +305,9 @@ impl Woz1 {
     fn get_trk_bits_ref(&self,track: u8) -> Result<&[u8],img::NibbleError> {
         return Ok(&self.trks.tracks[self.get_trk_idx(track)?].bits);
     }
-    /// Get a mutable reference to the track bits
+    /// Get a mutable reference to the track bits, anything decoded from the track is forgotten
     fn get_trk_bits_mut(&mut self,track: u8) -> Result<&mut [u8],img::NibbleError> {
+        self.cache.invalidate_track(track as usize);
         let idx = self.get_trk_idx(track)?;
         return Ok(&mut self.trks.tracks[idx].bits);
     }
@@ -300,8 +318,12 @@ impl Woz1 {
             debug!("goto track {} of {}",track,self.kind);
             self.head_coords.track = track as usize;
         }
-        let bit_count_le = self.get_trk_ref(track)?.bit_count;
-        let bit_count = u16::from_le_bytes(bit_count_le) as usize;
+        let trk = self.get_trk_ref(track)?;
+        let bit_count = u16::from_le_bytes(trk.bit_count) as usize;
+        if bit_count > trk.bits.len()*8 {
+            debug!("track {} has more bits than the track buffer",track);
+            return Err(img::NibbleError::BadTrack);
+        }
         let mut ans: Box<dyn super::TrackBits> = match self.kind {
             super::names::A2_DOS32_KIND => Box::new(disk525::TrackBits::create(
                 track as usize,
@@ -313,7 +335,7 @@ impl Woz1 {
                 bit_count,
                 disk525::SectorAddressFormat::create_std16(),
                 disk525::SectorDataFormat::create_std16())),
-            _ => panic!("incompatible disk")
+            _ => return Err(img::NibbleError::BadTrack)
         };
         if self.head_coords.bit_ptr < bit_count {
             ans.set_bit_ptr(self.head_coords.bit_ptr);
@@ -330,17 +352,26 @@ impl img::woz::WozUnifier for Woz1 {
         self.trks.num_tracks()
     }
     fn read_sector(&mut self,track: u8,sector: u8) -> Result<Vec<u8>,img::NibbleError> {
-        let mut reader = self.new_rw_obj(track)?;
-        let ans = reader.read_sector(self.get_trk_bits_ref(track)?,track,sector)?;
-        self.head_coords.bit_ptr = reader.get_bit_ptr();
+        let (ans,bit_ptr) = super::woz::read_cached(self,track,sector)?;
+        self.head_coords.bit_ptr = bit_ptr;
         Ok(ans)
     }
     fn write_sector(&mut self,dat: &[u8],track: u8,sector: u8) -> Result<(),img::NibbleError> {
+        img::journal::record(self,img::journal::Unit::Track(super::woz::track_to_cyl_head(self,track)));
         let mut writer = self.new_rw_obj(track)?;
         writer.write_sector(self.get_trk_bits_mut(track)?,dat,track,sector)?;
         self.head_coords.bit_ptr = writer.get_bit_ptr();
         Ok(())
     }
+    fn track_obj(&mut self,track: u8) -> Result<Box<dyn super::TrackBits>,img::NibbleError> {
+        self.new_rw_obj(track)
+    }
+    fn track_bits(&self,track: u8) -> Result<&[u8],img::NibbleError> {
+        self.get_trk_bits_ref(track)
+    }
+    fn sector_cache(&mut self) -> &mut super::cache::SectorCache {
+        &mut self.cache
+    }
 }
 
 impl img::DiskImage for Woz1 {
@@ -436,11 +467,15 @@ impl img::DiskImage for Woz1 {
         ans[11] = crc[3];
         return ans;
     }
+    fn journal(&mut self) -> Option<&mut img::journal::Journal> {
+        Some(&mut self.journal)
+    }
     fn get_track_buf(&mut self,cyl: usize,head: usize) -> Result<Vec<u8>,DYNERR> {
         let track_num = super::woz::cyl_head_to_track(self, cyl, head)?;
         Ok(self.get_trk_bits_ref(track_num as u8)?.to_vec())
     }
     fn set_track_buf(&mut self,cyl: usize,head: usize,dat: &[u8]) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Track([cyl,head]));
         let track_num = super::woz::cyl_head_to_track(self, cyl, head)?;
         let bits = self.get_trk_bits_mut(track_num as u8)?;
         if bits.len()!=dat.len() {
@@ -460,6 +495,8 @@ impl img::DiskImage for Woz1 {
                 head,
                 flux_code: img::FluxCode::GCR,
                 nib_code: img::NibbleCode::N53,
+                data_rate: Some(img::DataRate::R250Kbps),
+                bit_timing: Some(32),
                 chss_map
             }));
         }
@@ -471,11 +508,19 @@ impl img::DiskImage for Woz1 {
                 head,
                 flux_code: img::FluxCode::GCR,
                 nib_code: img::NibbleCode::N62,
+                data_rate: Some(img::DataRate::R250Kbps),
+                bit_timing: Some(32),
                 chss_map
             }));
         }
         return Err(Box::new(img::Error::UnknownDiskKind));
     }
+    fn decode_tracks(&mut self,tracks: &[usize]) {
+        super::woz::decode_tracks(self,tracks)
+    }
+    fn cache_stats(&self) -> Option<img::cache::CacheStats> {
+        Some(self.cache.stats())
+    }
     fn get_track_nibbles(&mut self,cyl: usize,head: usize) -> Result<Vec<u8>,DYNERR> {
         let track_num = super::woz::cyl_head_to_track(self, cyl, head)?;
         let mut reader = self.new_rw_obj(track_num as u8)?;
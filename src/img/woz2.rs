@@ -2,9 +2,15 @@
 //! 
 //! This uses the nibble machinery in `disk35` and `disk525` to handle the bit streams.
 //! The `DiskStruct` trait is used to flatten and unflatten the wrapper structures.
+//!
+//! Version 2.1 adds the FLUX chunk, which maps tracks to flux timing data instead of bits.
+//! If a track only has flux data, it is resampled to a bitstream so the file system can use it.
+//! When the image is saved, a resampled track that was never written is dropped, leaving the
+//! flux track as it was.  If it was written, the bitstream replaces the flux track.
 
 use log::{debug,info,warn,error};
 use std::collections::HashMap;
+use rayon::prelude::*;
 use regex;
 // a2kit_macro automatically derives `new`, `to_bytes`, `from_bytes`, and `length` from a DiskStruct.
 // This spares us having to manually write code to copy bytes in and out for every new structure.
@@ -14,7 +20,7 @@ use a2kit_macro_derive::DiskStruct;
 use crate::img::{disk35,disk525};
 use crate::img;
 use crate::img::meta;
-use crate::img::woz::{INFO_ID,TMAP_ID,TRKS_ID,META_ID,WRIT_ID,HeadCoords};
+use crate::img::woz::{INFO_ID,TMAP_ID,TRKS_ID,META_ID,WRIT_ID,FLUX_ID,HeadCoords};
 use crate::{STDRESULT,DYNERR,getByte,getByteEx,getHexEx,putByte,putHex,putStringBuf};
 
 const MAX_TRACK_BLOCKS_525: u16 = 13;
@@ -32,7 +38,7 @@ macro_rules! metaOptions {
 /// The regex will not forbid redundant repetitions.
 /// The regex will not match to an empty string.
 /// Do not confuse the `|` appearing in the regex with the one in the metadata value.
-const STD_META_OPTIONS: [(&str,&str);5] = [
+const STD_META_OPTIONS: [(&str,&str);6] = [
     (
         "language",
         metaOptions!(
@@ -45,7 +51,8 @@ const STD_META_OPTIONS: [(&str,&str);5] = [
     ("requires_ram",r"^(16K|24K|32K|48K|64K|128K|256K|512K|768K|1M|1\.25M|1\.5M\+|Unknown)$"),
     ("requires_rom",r"^(Any|Integer|Applesoft|IIgs ROM0|IIgs ROM0\+1|IIgs ROM1|IIgs ROM1\+3|IIgs ROM3)$"),
     ("requires_machine",metaOptions!("2",r"2\+","2e","2c",r"2e\+","2gs",r"2c\+","3",r"3\+")),
-    ("side",r"^Disk [0-9]+, Side [A-B]$")
+    ("side",r"^Disk [0-9]+, Side [A-B]$"),
+    ("image_date",r"^[0-9]{4}-[0-9]{2}-[0-9]{2}T[0-9]{2}:[0-9]{2}:[0-9]{2}(\.[0-9]+)?(Z|[+-][0-9]{2}:[0-9]{2})$")
 ];
 
 const STD_META_KEYS: [&str;16] = [
@@ -59,7 +66,7 @@ const RO_META_ITEMS: [&str;5] = [
     "disk_sides",
     "largest_track",
     "flux_block",
-    "largest_flux_block"
+    "largest_flux_track"
 ];
 
 const COMPATIBLE_HARDWARE_OPT: [&str;9] = [
@@ -86,7 +93,7 @@ pub struct Header {
     crc32: [u8;4]
 }
 
-#[derive(DiskStruct)]
+#[derive(DiskStruct,Clone)]
 pub struct Info {
     id: [u8;4],
     size: [u8;4],
@@ -107,7 +114,7 @@ pub struct Info {
     pad: [u8;10]
 }
 
-#[derive(DiskStruct)]
+#[derive(DiskStruct,Clone)]
 pub struct TMap {
     id: [u8;4],
     size: [u8;4],
@@ -134,6 +141,16 @@ pub struct Meta {
     recs: Vec<(String,String)>
 }
 
+/// Bitstream track made by resampling a flux track
+struct Resampled {
+    /// index of the flux track in the TRKS array
+    flux_idx: usize,
+    /// index of the bitstream track in the TRKS array
+    trk_idx: usize,
+    /// the bits as first resampled, to tell if the track was written
+    original: Vec<u8>
+}
+
 pub struct Woz2 {
     kind: img::DiskKind,
     /// Track bit offsets are given with respect to start of file.
@@ -145,7 +162,12 @@ pub struct Woz2 {
     trks: Trks,
     meta: Option<Meta>,
     writ: Option<Vec<u8>>,
-    head_coords: HeadCoords
+    /// the FLUX chunk has the same layout as TMAP
+    flux: Option<TMap>,
+    resampled: Vec<Resampled>,
+    head_coords: HeadCoords,
+    cache: super::cache::SectorCache,
+    journal: img::journal::Journal
 }
 
 impl Header {
@@ -225,6 +247,14 @@ impl Info {
             stringify!(cleaned) => hex_str=="00" || hex_str=="01",
             stringify!(disk_sides) => hex_str=="01" || hex_str=="02",
             stringify!(boot_sector_format) => hex_str=="00" || hex_str=="01" || hex_str=="02" || hex_str=="03",
+            stringify!(creator) => hex_str.len() <= self.creator.len(),
+            // allow some latitude around the nominal 4 or 2 microseconds
+            stringify!(optimal_bit_timing) => match (u8::from_str_radix(hex_str,16),self.disk_type) {
+                (Ok(val),1) => hex_str.len()==2 && (24..=40).contains(&val),
+                (Ok(val),2) => hex_str.len()==2 && (8..=24).contains(&val),
+                _ => false
+            },
+            stringify!(required_ram) => hex_str.len()==4 && hex::decode(hex_str).is_ok(),
             stringify!(compatible_hardware) => {
                 if hex_str.len()!=4 {
                     return false;
@@ -344,6 +374,65 @@ impl Trks {
     }
 }
 
+/// Convert flux timing to a bitstream.  Each byte is a count of 125 ns ticks until the next
+/// transition, with 255 meaning the count continues in the next byte.  Each transition becomes a 1
+/// preceded by as many 0 as fit in the interval.  Returns the bits padded to whole blocks, and the bit count.
+fn flux_to_bits(flux: &[u8],bit_timing: u8) -> (Vec<u8>,usize) {
+    let timing = usize::max(1,bit_timing as usize);
+    let mut ans = Vec::new();
+    let mut bit_count = 0;
+    let mut ticks = 0;
+    for b in flux {
+        ticks += *b as usize;
+        if *b == 255 {
+            continue;
+        }
+        bit_count += usize::max(1,(ticks + timing/2) / timing);
+        while ans.len()*8 < bit_count {
+            ans.push(0);
+        }
+        ans[(bit_count-1)/8] |= 0x80 >> ((bit_count-1)%8);
+        ticks = 0;
+    }
+    ans.resize(ans.len().div_ceil(512)*512,0);
+    (ans,bit_count)
+}
+
+impl Trks {
+    fn is_free(&self,idx: usize) -> bool {
+        let trk = &self.tracks[idx];
+        trk.starting_block==[0,0] && trk.block_count==[0,0] && trk.bit_count==[0,0,0,0]
+    }
+    /// Range of the track's bits within `bits`, where `offset` is the file offset of `bits`
+    fn bits_range(&self,idx: usize,len: usize,offset: usize) -> Result<std::ops::Range<usize>,DiskStructError> {
+        let trk = self.tracks.get(idx).ok_or(DiskStructError::OutOfData)?;
+        let begin = (u16::from_le_bytes(trk.starting_block) as usize*512).checked_sub(offset).ok_or(DiskStructError::IllegalValue)?;
+        match begin.checked_add(len) {
+            Some(end) if end <= self.bits.len() => Ok(begin..end),
+            _ => Err(DiskStructError::OutOfData)
+        }
+    }
+    /// Copy keeping only the tracks in `keep`, with the data packed in order starting at `first_block`
+    fn compact(&self,keep: &[bool],first_block: usize,offset: usize) -> Result<Trks,DiskStructError> {
+        let mut ans = Trks::new();
+        ans.id = self.id;
+        ans.tracks = vec![Trk::new();160];
+        let mut order: Vec<usize> = (0..160).filter(|i| keep[*i]).collect();
+        order.sort_by_key(|i| u16::from_le_bytes(self.tracks[*i].starting_block));
+        let mut block = first_block;
+        for i in order {
+            let trk = self.tracks[i];
+            let count = u16::from_le_bytes(trk.block_count) as usize;
+            ans.bits.extend_from_slice(&self.bits[self.bits_range(i,count*512,offset)?]);
+            ans.tracks[i] = trk;
+            ans.tracks[i].starting_block = u16::to_le_bytes(u16::try_from(block).or(Err(DiskStructError::IllegalValue))?);
+            block += count;
+        }
+        ans.size = u32::to_le_bytes(1280 + ans.bits.len() as u32);
+        Ok(ans)
+    }
+}
+
 impl DiskStruct for Trks {
     fn new() -> Self where Self: Sized {
         Self {
@@ -522,7 +611,11 @@ impl Woz2 {
             trks: Trks::new(),
             meta: None,
             writ: None,
-            head_coords: HeadCoords { track: usize::MAX, bit_ptr: usize::MAX }
+            flux: None,
+            resampled: Vec::new(),
+            head_coords: HeadCoords { track: usize::MAX, bit_ptr: usize::MAX },
+            cache: super::cache::SectorCache::default(),
+            journal: img::journal::Journal::default()
         }
     }
     pub fn create(vol: u8,kind: img::DiskKind) -> Self {
@@ -538,38 +631,79 @@ impl Woz2 {
             trks: Trks::create(vol,kind),
             meta: None,
             writ: None,
-            head_coords: HeadCoords { track: usize::MAX, bit_ptr: usize::MAX }
+            flux: None,
+            resampled: Vec::new(),
+            head_coords: HeadCoords { track: usize::MAX, bit_ptr: usize::MAX },
+            cache: super::cache::SectorCache::default(),
+            journal: img::journal::Journal::default()
+        }
+    }
+    /// Create the image with the given bit timing and track length, see `woz::TrackTiming`.
+    /// The track length can only be set for 5.25 inch disks, 3.5 inch tracks have a fixed length in each zone.
+    pub fn create_with_timing(vol: u8,kind: img::DiskKind,timing: &super::woz::TrackTiming) -> Result<Self,DYNERR> {
+        let mut ans = Self::create(vol,kind);
+        if let Some(bit_timing) = timing.bit_timing {
+            if !ans.info.verify_value(stringify!(optimal_bit_timing),&hex::encode([bit_timing])) {
+                error!("bit timing {} is out of range for this kind of disk",bit_timing);
+                return Err(Box::new(img::Error::MetadataMismatch));
+            }
+            ans.info.optimal_bit_timing = bit_timing;
+        }
+        if let Some(track_bits) = timing.track_bits {
+            for track in 0..img::DiskImage::track_count(&ans) {
+                let idx = ans.get_trk_idx(track as u8)?;
+                let buf = ans.get_trk_bits_mut(track as u8)?;
+                let (bits,obj) = super::woz::format_525_track(vol,track as u8,kind,buf.len(),track_bits)?;
+                buf.copy_from_slice(&bits);
+                ans.trks.tracks[idx].bit_count = u32::to_le_bytes(obj.bit_count() as u32);
+            }
         }
+        Ok(ans)
+    }
+    /// Create from a NIB image, rebuilding the sync bytes of every track with `disk525::reconstruct_sync`.
+    /// Tracks are fit to the length of a newly formatted track, so the result behaves like a real disk
+    /// in emulators that are sensitive to timing.
+    pub fn from_nib(nib: &mut super::nib::Nib) -> Result<Self,DYNERR> {
+        let kind = img::DiskImage::kind(nib);
+        let (adr_fmt,dat_fmt) = match kind {
+            img::names::A2_DOS32_KIND => (disk525::SectorAddressFormat::create_std13(),disk525::SectorDataFormat::create_std13()),
+            img::names::A2_DOS33_KIND => (disk525::SectorAddressFormat::create_std16(),disk525::SectorDataFormat::create_std16()),
+            _ => {
+                error!("cannot convert NIB of kind {}",kind);
+                return Err(Box::new(img::Error::UnknownDiskKind));
+            }
+        };
+        let mut ans = Self::create(254,kind);
+        for track in 0..img::DiskImage::track_count(nib) {
+            let nibs = img::DiskImage::get_track_buf(nib,track,0)?;
+            let idx = ans.get_trk_idx(track as u8)?;
+            let max_bits = u32::from_le_bytes(ans.trks.tracks[idx].bit_count) as usize;
+            let buf = ans.get_trk_bits_mut(track as u8)?;
+            let (bits,obj) = disk525::reconstruct_sync(&nibs,track as u8,buf.len(),max_bits,adr_fmt,dat_fmt);
+            if bits.len()!=buf.len() {
+                error!("track {} does not fit in the WOZ track buffer",track);
+                return Err(Box::new(img::Error::ImageSizeMismatch));
+            }
+            buf.copy_from_slice(&bits);
+            ans.trks.tracks[idx].bit_count = u32::to_le_bytes(obj.bit_count() as u32);
+        }
+        Ok(ans)
     }
     /// Get index to the `Trk` structure, searching main track and nearby quarter-tracks.
+    /// Map entries that point outside the track list are treated as empty.
     fn get_trk_idx(&self,track: u8) -> Result<usize,img::NibbleError> {
+        let entry = |key_idx: usize| self.tmap.map.get(key_idx).map(|x| *x as usize).filter(|x| *x<160);
         match self.kind {
-            img::names::A2_400_KIND => {
-                let key_idx = track as usize;
-                if self.tmap.map[key_idx]<80 {
-                    return Ok(self.tmap.map[key_idx] as usize);
-                }
-            },
-            img::names::A2_800_KIND => {
-                let key_idx = track as usize;
-                if self.tmap.map[key_idx]<160 {
-                    return Ok(self.tmap.map[key_idx] as usize);
+            img::names::A2_400_KIND | img::names::A2_800_KIND => {
+                if let Some(idx) = entry(track as usize) {
+                    return Ok(idx);
                 }
             },
             _ => {
                 let key_idx = track as usize*4;
-                if self.tmap.map[key_idx]!=0xff {
-                    return Ok(self.tmap.map[key_idx] as usize);
-                }
-                if key_idx!=0 {
-                    if self.tmap.map[key_idx-1]!=0xff {
-                        return Ok(self.tmap.map[key_idx-1] as usize);
-                    }
-                }
-                if key_idx!=self.tmap.map.len() {
-                    if self.tmap.map[key_idx+1]!=0xff {
-                        return Ok(self.tmap.map[key_idx+1] as usize);
-                    }
+                let nearby = [Some(key_idx),key_idx.checked_sub(1),Some(key_idx+1)];
+                if let Some(idx) = nearby.iter().flatten().find_map(|k| entry(*k)) {
+                    return Ok(idx);
                 }
             }
         }
@@ -579,19 +713,116 @@ impl Woz2 {
     fn get_trk_ref(&self,track: u8) -> Result<&Trk,img::NibbleError> {
         return Ok(&self.trks.tracks[self.get_trk_idx(track)?]);
     }
-    /// Get a reference to the track bits
-    fn get_trk_bits_ref(&self,track: u8) -> Result<&[u8],img::NibbleError> {
+    /// Range of the track bits within the TRKS data, checking that the track record is consistent
+    fn get_trk_range(&self,track: u8) -> Result<std::ops::Range<usize>,img::NibbleError> {
         let trk = self.get_trk_ref(track)?;
-        let begin = u16::from_le_bytes(trk.starting_block) as usize*512 - self.track_bits_offset;
+        let begin = match (u16::from_le_bytes(trk.starting_block) as usize*512).checked_sub(self.track_bits_offset) {
+            Some(b) => b,
+            None => return Err(img::NibbleError::BadTrack)
+        };
         let end = begin + u16::from_le_bytes(trk.block_count) as usize*512;
-        Ok(&self.trks.bits[begin..end])
+        if end > self.trks.bits.len() || u32::from_le_bytes(trk.bit_count) as usize > (end-begin)*8 {
+            debug!("track {} record is inconsistent with the track data",track);
+            return Err(img::NibbleError::BadTrack);
+        }
+        Ok(begin..end)
+    }
+    /// Get a reference to the track bits
+    fn get_trk_bits_ref(&self,track: u8) -> Result<&[u8],img::NibbleError> {
+        let range = self.get_trk_range(track)?;
+        Ok(&self.trks.bits[range])
     }
-    /// Get a mutable reference to the track bits
+    /// Get a mutable reference to the track bits, anything decoded from the track is forgotten
     fn get_trk_bits_mut(&mut self,track: u8) -> Result<&mut [u8],img::NibbleError> {
-        let trk = self.get_trk_ref(track)?;
-        let begin = u16::from_le_bytes(trk.starting_block) as usize*512 - self.track_bits_offset;
-        let end = begin + u16::from_le_bytes(trk.block_count) as usize*512;
-        Ok(&mut self.trks.bits[begin..end])
+        self.cache.invalidate_track(track as usize);
+        let range = self.get_trk_range(track)?;
+        Ok(&mut self.trks.bits[range])
+    }
+    /// Resample flux tracks that have no bitstream, appending the bitstreams to the TRKS data
+    fn resample_flux(&mut self) {
+        let flux_map = match &self.flux {
+            Some(flux) => flux.map,
+            None => return
+        };
+        // flux tracks are independent, so the expensive part is done in parallel up front
+        let mut flux_tracks: Vec<usize> = Vec::new();
+        for (flux_idx,trk_idx) in flux_map.iter().zip(self.tmap.map.iter()) {
+            let flux_idx = *flux_idx as usize;
+            if flux_idx < 160 && *trk_idx == 0xff && !flux_tracks.contains(&flux_idx) {
+                flux_tracks.push(flux_idx);
+            }
+        }
+        let mut resampled: HashMap<usize,(Vec<u8>,usize)> = flux_tracks.par_iter().filter_map(|flux_idx| {
+            let trk = self.trks.tracks[*flux_idx];
+            let begin = (u16::from_le_bytes(trk.starting_block) as usize*512).saturating_sub(self.track_bits_offset);
+            let end = begin + u32::from_le_bytes(trk.bit_count) as usize;
+            let flux_bytes = self.trks.bits.get(begin..end)?;
+            Some((*flux_idx,flux_to_bits(flux_bytes, self.info.optimal_bit_timing)))
+        }).collect();
+        let mut made: HashMap<usize,usize> = HashMap::new();
+        for key in 0..160 {
+            let flux_idx = flux_map[key] as usize;
+            if flux_idx >= 160 || self.tmap.map[key] != 0xff {
+                continue;
+            }
+            let trk_idx = match made.get(&flux_idx) {
+                Some(idx) => *idx,
+                None => {
+                    let free = (0..160).find(|i| self.trks.is_free(*i) && !self.tmap.map.contains(&(*i as u8)) && !flux_map.contains(&(*i as u8)));
+                    let (free,(bits,bit_count)) = match (free,resampled.remove(&flux_idx)) {
+                        (Some(i),Some(r)) => (i,r),
+                        _ => {
+                            warn!("could not resample flux track {}",flux_idx);
+                            continue;
+                        }
+                    };
+                    debug!("resampled flux track {} to {} bits",flux_idx,bit_count);
+                    let mut new_trk = Trk::new();
+                    new_trk.starting_block = u16::to_le_bytes(((self.track_bits_offset + self.trks.bits.len())/512) as u16);
+                    new_trk.block_count = u16::to_le_bytes((bits.len()/512) as u16);
+                    new_trk.bit_count = u32::to_le_bytes(bit_count as u32);
+                    self.trks.tracks[free] = new_trk;
+                    self.trks.bits.extend_from_slice(&bits);
+                    self.resampled.push(Resampled { flux_idx, trk_idx: free, original: bits });
+                    made.insert(flux_idx,free);
+                    free
+                }
+            };
+            self.tmap.map[key] = trk_idx as u8;
+        }
+    }
+    /// Track map, flux map, and tracks as they should be saved.  Resampled tracks that were never
+    /// written are dropped, otherwise they replace the flux track they came from.
+    fn output_chunks(&self) -> Result<(TMap,Option<TMap>,Trks),DiskStructError> {
+        let mut tmap = self.tmap.clone();
+        let mut flux = self.flux.clone();
+        for r in &self.resampled {
+            let rng = self.trks.bits_range(r.trk_idx,r.original.len(),self.track_bits_offset)?;
+            let written = self.trks.bits[rng] != r.original[..];
+            for key in 0..160 {
+                if tmap.map[key] != r.trk_idx as u8 {
+                    continue;
+                }
+                match (written,flux.as_mut()) {
+                    (true,Some(f)) => if f.map[key] == r.flux_idx as u8 {
+                        f.map[key] = 0xff;
+                    },
+                    _ => tmap.map[key] = 0xff
+                }
+            }
+        }
+        if let Some(f) = &flux {
+            if f.map.iter().all(|x| *x==0xff) {
+                flux = None;
+            }
+        }
+        let mut keep = [false;160];
+        for idx in tmap.map.iter().chain(flux.iter().flat_map(|f| f.map.iter())) {
+            if (*idx as usize) < 160 {
+                keep[*idx as usize] = true;
+            }
+        }
+        Ok((tmap,flux,self.trks.compact(&keep, self.track_bits_offset/512, self.track_bits_offset)?))
     }
     /// Create a lightweight trait object to read/write the bits.  The nibble format will be
     /// determined by the image's underlying `DiskKind`.
@@ -600,6 +831,7 @@ impl Woz2 {
             debug!("goto track {} of {}",track,self.kind);
             self.head_coords.track = track as usize;
         }
+        self.get_trk_range(track)?;
         let bit_count_le = self.get_trk_ref(track)?.bit_count;
         let bit_count = u32::from_le_bytes(bit_count_le) as usize;
         let mut ans: Box<dyn super::TrackBits> = match self.kind {
@@ -621,7 +853,7 @@ impl Woz2 {
                 track as usize,
                 bit_count,
                 2)),
-            _ => panic!("incompatible disk")
+            _ => return Err(img::NibbleError::BadTrack)
         };
         if self.head_coords.bit_ptr < bit_count {
             ans.set_bit_ptr(self.head_coords.bit_ptr);
@@ -638,17 +870,26 @@ impl img::woz::WozUnifier for Woz2 {
         self.trks.num_tracks()
     }
     fn read_sector(&mut self,track: u8,sector: u8) -> Result<Vec<u8>,img::NibbleError> {
-        let mut reader = self.new_rw_obj(track)?;
-        let ans = reader.read_sector(self.get_trk_bits_ref(track)?,track,sector)?;
-        self.head_coords.bit_ptr = reader.get_bit_ptr();
+        let (ans,bit_ptr) = super::woz::read_cached(self,track,sector)?;
+        self.head_coords.bit_ptr = bit_ptr;
         Ok(ans)
     }
     fn write_sector(&mut self,dat: &[u8],track: u8,sector: u8) -> Result<(),img::NibbleError> {
+        img::journal::record(self,img::journal::Unit::Track(super::woz::track_to_cyl_head(self,track)));
         let mut writer = self.new_rw_obj(track)?;
         writer.write_sector(self.get_trk_bits_mut(track)?,dat,track,sector)?;
         self.head_coords.bit_ptr = writer.get_bit_ptr();
         Ok(())
     }
+    fn track_obj(&mut self,track: u8) -> Result<Box<dyn super::TrackBits>,img::NibbleError> {
+        self.new_rw_obj(track)
+    }
+    fn track_bits(&self,track: u8) -> Result<&[u8],img::NibbleError> {
+        self.get_trk_bits_ref(track)
+    }
+    fn sector_cache(&mut self) -> &mut super::cache::SectorCache {
+        &mut self.cache
+    }
 }
 
 impl img::DiskImage for Woz2 {
@@ -727,6 +968,7 @@ impl img::DiskImage for Woz2 {
                     ans.meta = Some(new_meta);
                 },
                 (WRIT_ID,Some(chunk)) => ans.writ = Some(chunk),
+                (FLUX_ID,Some(chunk)) => ans.flux = Some(TMap::from_bytes(&chunk)?),
                 _ => if id!=0 {
                     info!("unprocessed chunk with id {:08X}/{}",id,String::from_utf8_lossy(&u32::to_le_bytes(id)))
                 }
@@ -734,7 +976,15 @@ impl img::DiskImage for Woz2 {
             ptr = next;
         }
         if ans.info.vers>=3 && ans.info.flux_block!=[0,0] && ans.info.largest_flux_track!=[0,0] {
-            error!("WOZ uses flux data (not supported)");
+            if ans.flux.is_none() {
+                error!("WOZ refers to flux data, but there is no FLUX chunk");
+                return Err(DiskStructError::IllegalValue);
+            }
+            info!("WOZ uses flux data");
+            ans.resample_flux();
+        }
+        if !matches!((ans.info.disk_type,ans.info.disk_sides),(1,1) | (2,1) | (2,2)) {
+            debug!("WOZ v2 disk type {} with {} sides is not supported",ans.info.disk_type,ans.info.disk_sides);
             return Err(DiskStructError::IllegalValue);
         }
         if u32::from_le_bytes(ans.info.id)>0 && u32::from_le_bytes(ans.tmap.id)>0 && u32::from_le_bytes(ans.trks.id)>0 {
@@ -763,9 +1013,43 @@ impl img::DiskImage for Woz2 {
         }
         let mut ans: Vec<u8> = Vec::new();
         ans.append(&mut self.header.to_bytes());
-        ans.append(&mut self.info.to_bytes());
-        ans.append(&mut self.tmap.to_bytes());
-        ans.append(&mut self.trks.to_bytes());
+        let chunks = match self.resampled.is_empty() {
+            true => None,
+            false => match self.output_chunks() {
+                Ok(chunks) => Some(chunks),
+                Err(e) => {
+                    error!("could not reconcile resampled flux tracks ({}), saving them as flux",e);
+                    None
+                }
+            }
+        };
+        if let Some((tmap,flux,trks)) = chunks {
+            let largest = |map: &TMap| map.map.iter().filter(|i| **i < 160)
+                .map(|i| u16::from_le_bytes(trks.tracks[*i as usize].block_count)).max().unwrap_or(0);
+            let mut info = self.info.clone();
+            info.largest_track = u16::to_le_bytes(largest(&tmap));
+            (info.flux_block,info.largest_flux_track) = match &flux {
+                Some(f) => (u16::to_le_bytes(((self.track_bits_offset + trks.bits.len())/512) as u16),u16::to_le_bytes(largest(f))),
+                None => ([0,0],[0,0])
+            };
+            ans.append(&mut info.to_bytes());
+            ans.append(&mut tmap.to_bytes());
+            ans.append(&mut trks.to_bytes());
+            if let Some(f) = flux {
+                ans.append(&mut f.to_bytes());
+            }
+        } else {
+            // nothing to reconcile, but the FLUX chunk is always written right after TRKS
+            if self.flux.is_some() {
+                self.info.flux_block = u16::to_le_bytes(((self.track_bits_offset + self.trks.bits.len())/512) as u16);
+            }
+            ans.append(&mut self.info.to_bytes());
+            ans.append(&mut self.tmap.to_bytes());
+            ans.append(&mut self.trks.to_bytes());
+            if let Some(flux) = &self.flux {
+                ans.append(&mut flux.to_bytes());
+            }
+        }
         if let Some(meta) = &self.meta {
             ans.append(&mut meta.to_bytes());
         }
@@ -779,11 +1063,21 @@ impl img::DiskImage for Woz2 {
         ans[11] = crc[3];
         return ans;
     }
+    fn read_tags(&mut self,cyl: usize,head: usize,sec: usize) -> Result<Vec<u8>,DYNERR> {
+        super::woz::read_tags(self,cyl,head,sec)
+    }
+    fn write_tags(&mut self,cyl: usize,head: usize,sec: usize,tags: &[u8]) -> STDRESULT {
+        super::woz::write_tags(self,cyl,head,sec,tags)
+    }
+    fn journal(&mut self) -> Option<&mut img::journal::Journal> {
+        Some(&mut self.journal)
+    }
     fn get_track_buf(&mut self,cyl: usize,head: usize) -> Result<Vec<u8>,DYNERR> {
         let track_num = super::woz::cyl_head_to_track(self, cyl, head)?;
         Ok(self.get_trk_bits_ref(track_num as u8)?.to_vec())
     }
     fn set_track_buf(&mut self,cyl: usize,head: usize,dat: &[u8]) -> STDRESULT {
+        img::journal::record(self,img::journal::Unit::Track([cyl,head]));
         let track_num = super::woz::cyl_head_to_track(self, cyl, head)?;
         let bits = self.get_trk_bits_mut(track_num as u8)?;
         if bits.len()!=dat.len() {
@@ -808,6 +1102,8 @@ impl img::DiskImage for Woz2 {
                     head,
                     flux_code: img::FluxCode::GCR,
                     nib_code: img::NibbleCode::N62,
+                    data_rate: Some(img::DataRate::R500Kbps),
+                    bit_timing: Some(self.info.optimal_bit_timing),
                     chss_map
                 }));
             }
@@ -821,6 +1117,8 @@ impl img::DiskImage for Woz2 {
                     head,
                     flux_code: img::FluxCode::GCR,
                     nib_code: img::NibbleCode::N53,
+                    data_rate: Some(img::DataRate::R250Kbps),
+                    bit_timing: Some(self.info.optimal_bit_timing),
                     chss_map
                 }));
             }
@@ -832,6 +1130,8 @@ impl img::DiskImage for Woz2 {
                     head,
                     flux_code: img::FluxCode::GCR,
                     nib_code: img::NibbleCode::N62,
+                    data_rate: Some(img::DataRate::R250Kbps),
+                    bit_timing: Some(self.info.optimal_bit_timing),
                     chss_map
                 }));
             }
@@ -839,6 +1139,12 @@ impl img::DiskImage for Woz2 {
         }
         return Err(Box::new(img::Error::UnknownImageType));
     }
+    fn decode_tracks(&mut self,tracks: &[usize]) {
+        super::woz::decode_tracks(self,tracks)
+    }
+    fn cache_stats(&self) -> Option<img::cache::CacheStats> {
+        Some(self.cache.stats())
+    }
     fn get_track_nibbles(&mut self,cyl: usize,head: usize) -> Result<Vec<u8>,DYNERR> {
         let track_num = super::woz::cyl_head_to_track(self, cyl, head)?;
         let mut reader = self.new_rw_obj(track_num as u8)?;
@@ -0,0 +1,177 @@
+//! # Applesoft bundles
+//!
+//! A bundle is a single binary file that, when `BRUN`, installs a tokenized Applesoft program
+//! along with any binary assets, and then runs the program.  The file consists of a loader stub,
+//! a segment table, and the payload.  Each segment is copied to its destination, the Applesoft
+//! pointers are set as if the program had been `LOAD`ed, and control passes to `RUN`.
+//!
+//! The program is always installed at $0801, it is relinked as needed.  The bundle itself must not
+//! overlap any destination, since the stub runs from where DOS or ProDOS put it.
+
+use crate::lang::Error;
+use crate::DYNERR;
+
+/// Where Applesoft programs begin
+pub const PROGRAM_START: u16 = 0x0801;
+/// The stub must be somewhere below I/O space
+const MEMORY_TOP: usize = 0xc000;
+/// Applesoft `RUN` entry
+const RUN: u16 = 0xd566;
+/// length of the loader stub, the segment table follows immediately
+const STUB_LEN: usize = 0x6e;
+/// src, dest, and length, each 2 bytes
+const SEGMENT_LEN: usize = 6;
+
+/// Binary data that should be copied to `addr` before the program runs
+pub struct Asset {
+    pub addr: u16,
+    pub dat: Vec<u8>
+}
+
+/// Rewrite the line links of a tokenized program so it can be loaded at `start`.
+/// Anything after the end of program marker is dropped.
+pub fn relink(prog: &[u8],start: u16) -> Result<Vec<u8>,DYNERR> {
+    let mut ans = prog.to_vec();
+    let mut pos = 0;
+    loop {
+        if pos + 1 >= ans.len() {
+            log::error!("program is missing the end marker");
+            return Err(Box::new(Error::Detokenization));
+        }
+        if ans[pos]==0 && ans[pos+1]==0 {
+            ans.truncate(pos + 2);
+            return Ok(ans);
+        }
+        let eol = match ans.iter().skip(pos + 4).position(|b| *b==0) {
+            Some(offset) => pos + 4 + offset,
+            None => {
+                log::error!("program line at offset {} is not terminated",pos);
+                return Err(Box::new(Error::Detokenization));
+            }
+        };
+        let link = u16::to_le_bytes(start + eol as u16 + 1);
+        ans[pos..pos+2].copy_from_slice(&link);
+        pos = eol + 1;
+    }
+}
+
+/// Loader stub for a bundle at `addr`, the program will end at `prog_end`.
+/// Uses zero page $EB-$EC and $FA-$FD, which Applesoft leaves free.
+fn stub(addr: u16,prog_end: u16) -> Vec<u8> {
+    let [tl,th] = u16::to_le_bytes(addr + STUB_LEN as u16);
+    let [t1l,t1h] = u16::to_le_bytes(addr + STUB_LEN as u16 + 1);
+    let [t2l,t2h] = u16::to_le_bytes(addr + STUB_LEN as u16 + 2);
+    let [t3l,t3h] = u16::to_le_bytes(addr + STUB_LEN as u16 + 3);
+    let [t4l,t4h] = u16::to_le_bytes(addr + STUB_LEN as u16 + 4);
+    let [t5l,t5h] = u16::to_le_bytes(addr + STUB_LEN as u16 + 5);
+    let [nl,nh] = u16::to_le_bytes(addr + 0x02);
+    let [ll,lh] = u16::to_le_bytes(addr + 0x26);
+    let [el,eh] = u16::to_le_bytes(prog_end);
+    let [pl,ph] = u16::to_le_bytes(PROGRAM_START);
+    let [rl,rh] = u16::to_le_bytes(RUN);
+    let ans = vec![
+        0xa2,0x00,      // 00      LDX #0
+        0xbd,tl,th,     // 02 NEXT LDA TBL,X
+        0x85,0xfa,      // 05      STA $FA
+        0xbd,t1l,t1h,   // 07      LDA TBL+1,X
+        0xf0,0x43,      // 0A      BEQ DONE
+        0x85,0xfb,      // 0C      STA $FB
+        0xbd,t2l,t2h,   // 0E      LDA TBL+2,X
+        0x85,0xfc,      // 11      STA $FC
+        0xbd,t3l,t3h,   // 13      LDA TBL+3,X
+        0x85,0xfd,      // 16      STA $FD
+        0xbd,t4l,t4h,   // 18      LDA TBL+4,X
+        0x85,0xeb,      // 1B      STA $EB
+        0xbd,t5l,t5h,   // 1D      LDA TBL+5,X
+        0x85,0xec,      // 20      STA $EC
+        0x8a,           // 22      TXA
+        0x48,           // 23      PHA
+        0xa0,0x00,      // 24      LDY #0
+        0xa5,0xeb,      // 26 LOOP LDA $EB
+        0x05,0xec,      // 28      ORA $EC
+        0xf0,0x1b,      // 2A      BEQ SEGEND
+        0xb1,0xfa,      // 2C      LDA ($FA),Y
+        0x91,0xfc,      // 2E      STA ($FC),Y
+        0xe6,0xfa,      // 30      INC $FA
+        0xd0,0x02,      // 32      BNE L1
+        0xe6,0xfb,      // 34      INC $FB
+        0xe6,0xfc,      // 36 L1   INC $FC
+        0xd0,0x02,      // 38      BNE L2
+        0xe6,0xfd,      // 3A      INC $FD
+        0xa5,0xeb,      // 3C L2   LDA $EB
+        0xd0,0x02,      // 3E      BNE L3
+        0xc6,0xec,      // 40      DEC $EC
+        0xc6,0xeb,      // 42 L3   DEC $EB
+        0x4c,ll,lh,     // 44      JMP LOOP
+        0x68,           // 47 SEGEND PLA
+        0x18,           // 48      CLC
+        0x69,SEGMENT_LEN as u8, // 49 ADC #6
+        0xaa,           // 4B      TAX
+        0x4c,nl,nh,     // 4C      JMP NEXT
+        0xa9,pl,        // 4F DONE LDA #<PROGRAM_START
+        0x85,0x67,      // 51      STA TXTTAB
+        0xa9,ph,        // 53      LDA #>PROGRAM_START
+        0x85,0x68,      // 55      STA TXTTAB+1
+        0xa9,el,        // 57      LDA #<PROGRAM_END
+        0x85,0x69,      // 59      STA VARTAB
+        0x85,0x6b,      // 5B      STA ARYTAB
+        0x85,0x6d,      // 5D      STA STREND
+        0x85,0xaf,      // 5F      STA PRGEND
+        0xa9,eh,        // 61      LDA #>PROGRAM_END
+        0x85,0x6a,      // 63      STA VARTAB+1
+        0x85,0x6c,      // 65      STA ARYTAB+1
+        0x85,0x6e,      // 67      STA STREND+1
+        0x85,0xb0,      // 69      STA PRGEND+1
+        0x4c,rl,rh      // 6B      JMP RUN
+    ];
+    assert_eq!(ans.len(),STUB_LEN);
+    ans
+}
+
+/// Build a bundle that loads at `addr`.  The program is tokenized Applesoft, with any start address.
+/// The result is the content of a binary file, its load address is `addr` and its length is the length of the result.
+pub fn bundle(prog: &[u8],assets: &[Asset],addr: u16) -> Result<Vec<u8>,DYNERR> {
+    // the program segment includes the 0 that must precede the program
+    let mut prog_seg = vec![0];
+    prog_seg.append(&mut relink(prog,PROGRAM_START)?);
+    let prog_end = PROGRAM_START - 1 + prog_seg.len() as u16;
+    let mut segments: Vec<(u16,&[u8])> = vec![(PROGRAM_START - 1,&prog_seg)];
+    for asset in assets {
+        segments.push((asset.addr,&asset.dat));
+    }
+    let table_len = segments.len() * SEGMENT_LEN + 2;
+    let payload_len: usize = segments.iter().map(|(_,dat)| dat.len()).sum();
+    let total = STUB_LEN + table_len + payload_len;
+    let beg = addr as usize;
+    if beg + total > MEMORY_TOP {
+        log::error!("bundle of {} bytes at {} would run past ${:04X}",total,addr,MEMORY_TOP);
+        return Err(Box::new(Error::OutOfRange));
+    }
+    // check for collisions among the bundle and the destinations
+    let mut ranges: Vec<[usize;2]> = vec![[beg,beg + total]];
+    for (dest,dat) in &segments {
+        let rng = [*dest as usize,*dest as usize + dat.len()];
+        if rng[0] < 0x200 || rng[1] > MEMORY_TOP {
+            log::error!("segment at ${:04X} is outside the usable memory",rng[0]);
+            return Err(Box::new(Error::OutOfRange));
+        }
+        if let Some(other) = ranges.iter().find(|r| rng[0] < r[1] && r[0] < rng[1]) {
+            log::error!("segment ${:04X}-${:04X} overlaps ${:04X}-${:04X}",rng[0],rng[1]-1,other[0],other[1]-1);
+            return Err(Box::new(Error::OutOfRange));
+        }
+        ranges.push(rng);
+    }
+    let mut ans = stub(addr,prog_end);
+    let mut src = beg + STUB_LEN + table_len;
+    for (dest,dat) in &segments {
+        ans.extend_from_slice(&u16::to_le_bytes(src as u16));
+        ans.extend_from_slice(&u16::to_le_bytes(*dest));
+        ans.extend_from_slice(&u16::to_le_bytes(dat.len() as u16));
+        src += dat.len();
+    }
+    ans.extend_from_slice(&[0,0]);
+    for (_,dat) in &segments {
+        ans.extend_from_slice(dat);
+    }
+    Ok(ans)
+}
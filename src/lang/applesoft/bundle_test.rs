@@ -0,0 +1,47 @@
+use super::bundle::{bundle,relink,Asset};
+use super::tokenizer::Tokenizer;
+
+const PROG: &str = "10 PRINT \"HELLO\"\n20 GOTO 10\n";
+
+#[test]
+fn relink_program() {
+	let mut tokenizer = Tokenizer::new();
+	let at_0801 = tokenizer.tokenize(PROG,0x801).expect("tokenize failed");
+	let at_4000 = tokenizer.tokenize(PROG,0x4000).expect("tokenize failed");
+	assert_ne!(at_0801,at_4000);
+	assert_eq!(relink(&at_4000,0x801).expect("relink failed"),at_0801);
+}
+
+#[test]
+fn layout() {
+	let mut tokenizer = Tokenizer::new();
+	let prog = tokenizer.tokenize(PROG,0x801).expect("tokenize failed");
+	let assets = vec![Asset { addr: 0x300, dat: vec![0x60;16] }];
+	let dat = bundle(&prog,&assets,0x4000).expect("bundle failed");
+	let table = &dat[0x6e..0x6e+14];
+	// program goes to $0800 with the leading zero
+	let src = 0x4000 + 0x6e + 14;
+	assert_eq!(table[0..6],[(src & 0xff) as u8,(src >> 8) as u8,0x00,0x08,prog.len() as u8 + 1,0]);
+	let src = src + prog.len() + 1;
+	assert_eq!(table[6..12],[(src & 0xff) as u8,(src >> 8) as u8,0x00,0x03,16,0]);
+	assert_eq!(table[12..14],[0,0]);
+	assert_eq!(dat.len(),0x6e + 14 + prog.len() + 1 + 16);
+	assert_eq!(dat[0x6e+14],0);
+	assert_eq!(dat[dat.len()-1],0x60);
+}
+
+#[test]
+fn collisions() {
+	let mut tokenizer = Tokenizer::new();
+	let prog = tokenizer.tokenize(PROG,0x801).expect("tokenize failed");
+	// bundle over the program
+	assert!(bundle(&prog,&[],0x800).is_err());
+	// asset over the bundle
+	let assets = vec![Asset { addr: 0x4010, dat: vec![0;16] }];
+	assert!(bundle(&prog,&assets,0x4000).is_err());
+	// assets over each other
+	let assets = vec![Asset { addr: 0x2000, dat: vec![0;16] },Asset { addr: 0x200f, dat: vec![0;16] }];
+	assert!(bundle(&prog,&assets,0x4000).is_err());
+	// past the top of memory
+	assert!(bundle(&prog,&[],0xbff0).is_err());
+}
@@ -271,3 +271,18 @@ mod ampersand {
 		super::test_detokenizer(tokens, expected);
 	}
 }
+
+mod dialect {
+	use crate::lang::applesoft::{tokenizer::Tokenizer,settings::Settings,dialect::Dialect};
+	#[test]
+	fn extension_token() {
+		let expected = "10  &  HCOPY 1,2\n";
+		let tokens = hex::decode("0B080A00AFEB312C32000000").expect("hex error");
+		let mut config = Settings::new();
+		config.dialect = Dialect::from_json(r#"{"tokens": {"HCOPY": 235}}"#).expect("bad dialect");
+		let mut tokenizer = Tokenizer::new();
+		assert!(tokenizer.detokenize(&tokens).is_err());
+		tokenizer.set_config(config);
+		assert_eq!(tokenizer.detokenize(&tokens).expect("detokenization error"),expected);
+	}
+}
@@ -26,6 +26,8 @@ use super::name_range;
 struct FlowState {
     ip_branch_stack: Vec<lsp::Range>,
     curr_line_num: Option<u16>,
+    /// line numbers in program order, with whether control can pass to the next line
+    line_order: Vec<(i64,bool)>,
     row: isize,
     col: isize,
     line: String
@@ -36,6 +38,7 @@ impl FlowState {
         Self {
             ip_branch_stack: Vec::new(),
             curr_line_num: None,
+            line_order: Vec::new(),
             row: 0,
             col: 0,
             line: String::new()
@@ -75,6 +78,29 @@ impl FlowState {
         }
         None
     }
+    /// record whether control can pass from the current line to the next one, it cannot if the last
+    /// statement unconditionally branches or stops, and there is no IF that could skip it.
+    fn eval_line_end(&mut self,tree: &tree_sitter::Tree) {
+        let num = match self.curr_line_num {
+            Some(n) => n as i64,
+            None => return
+        };
+        let mut falls_through = true;
+        let root = tree.root_node();
+        if let Some(line) = root.named_child(0) {
+            let mut curs = line.walk();
+            let statements: Vec<tree_sitter::Node> = line.named_children(&mut curs).filter(|n| n.kind()=="statement").collect();
+            let conditional = statements.iter().any(|s| s.named_child(0).is_some_and(|t| t.kind()=="tok_if"));
+            if let Some(last) = statements.last() {
+                if let Some(tok) = last.named_child(0) {
+                    if !conditional && ["tok_goto","tok_end","tok_stop","tok_return","tok_run","tok_resume"].contains(&tok.kind()) {
+                        falls_through = false;
+                    }
+                }
+            }
+        }
+        self.line_order.push((num,falls_through));
+    }
     /// use print node to pop the interprogram branch in case we have a RUN command.
     fn eval_ip_run(&mut self,print_node: &tree_sitter::Node) -> Option<lsp::Diagnostic> {
         if self.ip_branch_stack.len() == 0 {
@@ -146,7 +172,12 @@ impl Analysis for Analyzer {
                 self.line = String::from(line) + "\n";
                 self.flow.new_line(&self.line, self.row);
                 match parser.parse(&self.line,None) {
-                    Some(tree) => self.walk(&tree)?,
+                    Some(tree) => {
+                        self.walk(&tree)?;
+                        if pass == 2 {
+                            self.flow.eval_line_end(&tree);
+                        }
+                    },
                     None => return Err(Box::new(crate::lang::Error::ParsingError))
                 };
                 if self.flow.ip_branch_stack.len() > 0 {
@@ -156,6 +187,9 @@ impl Analysis for Analyzer {
                 self.row += 1;
             }    
         }
+        if let Some(severity) = self.config.flag.unreachable_code {
+            self.check_unreachable(severity);
+        }
         Ok(())
     }
     fn update_config(&mut self,json_str: &str) -> STDRESULT {
@@ -225,6 +259,22 @@ impl Analyzer {
     pub fn get_symbols(&self) -> Symbols {
         self.symbols.clone()
     }
+    /// Flag lines that cannot be reached, i.e., they are never referenced, and control
+    /// cannot pass from the line before.  Any reference counts, even from unreachable code.
+    fn check_unreachable(&mut self,severity: lsp::DiagnosticSeverity) {
+        let mut reachable = true;
+        for (num,falls_through) in &self.flow.line_order {
+            if let Some(line) = self.symbols.lines.get(num) {
+                if !line.gotos.is_empty() || !line.gosubs.is_empty() {
+                    reachable = true;
+                }
+                if !reachable {
+                    self.diagnostics.push(basic_diag(line.primary, "line is unreachable", severity));
+                }
+            }
+            reachable = reachable && *falls_through;
+        }
+    }
     fn create(&self,rng: tree_sitter::Range,mess: &str,severity: lsp::DiagnosticSeverity) -> lsp::Diagnostic {
         basic_diag(lsp_range(rng,self.row,self.col),mess,severity)
     }
@@ -605,6 +655,14 @@ impl Analyzer {
                 self.diagnostics.push(diag);
             }
         }
+        else if curs.node().kind() == "tok_amp" && !self.config.dialect.is_empty() {
+            if let Some(name) = curs.node().next_named_sibling() {
+                let txt = node_text(&name,&self.line).to_uppercase().replace(" ","");
+                if name.kind()=="name_amp" && self.config.dialect.match_ampersand(&txt).is_none() {
+                    self.push(name.range(),"ampersand command is not in the dialect",lsp::DiagnosticSeverity::WARNING);
+                }
+            }
+        }
         else if curs.node().kind() == "tok_call" {
             if let Some(addr) = curs.node().next_named_sibling() {
 				self.value_range(addr,-32767.,65535.,true);
@@ -78,7 +78,19 @@ fn line_numbers() {
         "Line does not exist",
         "Line does not exist",
         "Line does not exist",
-        "Line does not exist"
+        "Line does not exist",
+        "line is unreachable",
+        "line is unreachable",
+        "line is unreachable"
+    ]);
+}
+
+#[test]
+fn unreachable() {
+    test_diagnostics("test-unreachable.abas", &[
+        "line is unreachable",
+        "line is unreachable",
+        "line is unreachable"
     ]);
 }
 
@@ -96,3 +108,15 @@ fn data() {
         "Odd quote parity in literal on multi-statement line invites trouble"
     ]);
 }
+
+#[test]
+fn dialect() {
+    use crate::lang::server::Analysis;
+    let mut analyzer = diagnostics::Analyzer::new();
+    analyzer.update_config(r#"{"dialect": {"tokens": {"HCOPY": 235}, "ampersand": ["CLS"]}}"#).expect("bad config");
+    let doc = crate::lang::Document::from_string("10 & HCOPY 1,2: & CLS: & BOGUS\n".to_string(),0);
+    analyzer.analyze(&doc).expect("could not analyze");
+    let diag_set = analyzer.get_diags(&doc);
+    assert_eq!(diag_set.len(),1);
+    assert_eq!(diag_set[0].message,"ampersand command is not in the dialect");
+}
@@ -0,0 +1,118 @@
+//! Supplemental command tables for Applesoft extensions
+//!
+//! Extensions hook the ampersand vector, and often claim the token codes Applesoft leaves unused,
+//! 235 through 255.  A dialect lists these so that the detokenizer can print them, the tokenizer
+//! can produce them, and the analyzer can check ampersand commands.  Dialects are JSON, e.g.,
+//! ```json
+//! {
+//!     "name": "My Extension",
+//!     "tokens": { "HCOPY": 235, "BEEP": 236 },
+//!     "ampersand": [ "CLS", "SCROLL" ]
+//! }
+//! ```
+//! Keywords in `tokens` are stored as the token code when they follow `&`, commands in `ampersand`
+//! are stored as text.  Keywords that contain an Applesoft token, such as `SORT`, cannot be recognized,
+//! since the parser splits them the way Applesoft would.
+//! The same object can be given as the `dialect` key in the language server settings.
+
+use std::collections::HashMap;
+use log::error;
+use crate::DYNERR;
+
+/// first token code that Applesoft leaves unused
+pub const FIRST_FREE_TOKEN: u8 = 235;
+
+#[derive(Clone,Default)]
+pub struct Dialect {
+    pub name: String,
+    /// map from keyword to token code
+    pub tokens: HashMap<String,u8>,
+    /// commands that are stored as text following `&`
+    pub ampersand: Vec<String>
+}
+
+impl Dialect {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Create from a JSON string, see module description for the format
+    pub fn from_json(json: &str) -> Result<Self,DYNERR> {
+        match serde_json::from_str::<serde_json::Value>(json) {
+            Ok(val) => Self::from_value(&val),
+            Err(e) => {
+                error!("dialect is not valid JSON");
+                Err(Box::new(e))
+            }
+        }
+    }
+    /// Create from a serde `Value`, keywords are put in upper case
+    pub fn from_value(val: &serde_json::Value) -> Result<Self,DYNERR> {
+        let mut ans = Self::new();
+        let obj = match val.as_object() {
+            Some(obj) => obj,
+            None => {
+                error!("dialect should be a JSON object");
+                return Err(Box::new(super::super::Error::Syntax));
+            }
+        };
+        if let Some(name) = obj.get("name").and_then(|x| x.as_str()) {
+            ans.name = name.to_string();
+        }
+        if let Some(tokens) = obj.get("tokens").and_then(|x| x.as_object()) {
+            for (keyword,code) in tokens {
+                let code = match code.as_u64() {
+                    Some(c) if c >= FIRST_FREE_TOKEN as u64 && c <= 255 => c as u8,
+                    _ => {
+                        error!("token code for {} should be from {} to 255",keyword,FIRST_FREE_TOKEN);
+                        return Err(Box::new(super::super::Error::OutOfRange));
+                    }
+                };
+                if ans.tokens.values().any(|c| *c==code) {
+                    error!("token code {} is used twice",code);
+                    return Err(Box::new(super::super::Error::Syntax));
+                }
+                ans.tokens.insert(Self::check_keyword(keyword)?,code);
+            }
+        }
+        if let Some(cmds) = obj.get("ampersand").and_then(|x| x.as_array()) {
+            for cmd in cmds {
+                match cmd.as_str() {
+                    Some(s) => ans.ampersand.push(Self::check_keyword(s)?),
+                    None => {
+                        error!("ampersand commands should be strings");
+                        return Err(Box::new(super::super::Error::Syntax));
+                    }
+                }
+            }
+        }
+        Ok(ans)
+    }
+    fn check_keyword(keyword: &str) -> Result<String,DYNERR> {
+        let ans = keyword.to_uppercase();
+        if ans.is_empty() || !ans.chars().all(|c| c.is_ascii_alphanumeric() || c=='$' || c=='.') {
+            error!("invalid dialect keyword {}",keyword);
+            return Err(Box::new(super::super::Error::Syntax));
+        }
+        Ok(ans)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty() && self.ampersand.is_empty()
+    }
+    /// keyword for a token code, if this dialect defines it
+    pub fn keyword(&self,code: u8) -> Option<&str> {
+        self.tokens.iter().find(|(_,c)| **c==code).map(|(k,_)| k.as_str())
+    }
+    /// Find the longest keyword that starts `cleanupper`, which should be upper case with spaces removed.
+    /// Returns the token code (if the keyword is tokenized) and the length of the keyword.
+    pub fn match_ampersand(&self,cleanupper: &str) -> Option<(Option<u8>,usize)> {
+        let mut ans: Option<(Option<u8>,usize)> = None;
+        let tokenized = self.tokens.iter().map(|(k,c)| (k,Some(*c)));
+        let text = self.ampersand.iter().map(|k| (k,None));
+        for (keyword,code) in tokenized.chain(text) {
+            if cleanupper.starts_with(keyword.as_str()) && ans.is_none_or(|(_,len)| keyword.len() > len) {
+                ans = Some((code,keyword.len()));
+            }
+        }
+        ans
+    }
+}
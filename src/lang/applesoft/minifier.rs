@@ -1,18 +1,49 @@
 //! Module containing the Applesoft minifier
 
+use std::collections::{HashMap,HashSet};
 use json;
-use log::error;
+use log::{error,warn};
 use tree_sitter;
 use tree_sitter_applesoft;
 use crate::lang;
 use crate::lang::{Navigate,Navigation};
 use super::minify_guards;
+use super::tokenizer;
 use crate::DYNERR;
 
 /// minify using safe transformations only
 pub const FLAG_SAFE: u64 = 1;
 /// minify variables in ampersand expressions
 pub const FLAG_AMP_VARS: u64 = 2;
+/// combine lines that are never the target of a branch
+pub const FLAG_COMBINE_LINES: u64 = 4;
+/// rename variables to the shortest available names, most frequent gets shortest
+pub const FLAG_RENAME_VARS: u64 = 8;
+
+/// longest line the combiner will produce, this keeps lines editable on the Apple
+const MAX_COMBINED_LINE: usize = 239;
+/// two character tokens that can never be variable names
+const TOK_2CHAR: [&str;7] = ["AT","FN","GR","IF","ON","OR","TO"];
+
+/// Gathers program-wide information needed by the aggressive transformations.
+/// Scanning is done on the original source, one line at a time.
+struct ProgramScan {
+	line: String,
+	/// significant variable keys mapped to occurrence counts
+	var_counts: HashMap<String,usize>,
+	/// line numbers that are referenced anywhere in the program
+	refs: HashSet<usize>,
+	/// primary line number of the current line
+	primary: Option<usize>,
+	/// current line cannot have anything appended
+	terminal: bool,
+	/// statement text of the current line, i.e., everything after the line number
+	body: String,
+	/// the program has ampersand statements
+	has_amp: bool,
+	/// the program has statements that operate on ranges of lines
+	has_line_ranges: bool
+}
 
 /// Handles minification of Applesoft BASIC
 pub struct Minifier
@@ -21,7 +52,99 @@ pub struct Minifier
 	minified_line: String,
     minified_program: String,
 	var_guards: json::JsonValue,
-	flags: u64
+	flags: u64,
+	/// maps significant variable keys to new names, empty unless renaming
+	renames: HashMap<String,String>,
+	/// lines combined during the last minification
+	combined_lines: usize
+}
+
+impl Navigate for ProgramScan
+{
+	fn visit(&mut self,curs:&tree_sitter::TreeCursor) -> Result<Navigation,DYNERR>
+	{
+		let kind = curs.node().kind();
+		if kind=="linenum" {
+			let num = lang::node_integer::<usize>(&curs.node(),&self.line);
+			match curs.node().parent() {
+				Some(parent) if parent.kind()=="line" => {
+					self.primary = num;
+					self.body = self.line[curs.node().end_byte()..].trim_end().to_string();
+				},
+				_ => if let Some(n) = num {
+					self.refs.insert(n);
+				}
+			}
+			return Ok(Navigation::GotoSibling);
+		}
+		if kind.starts_with("name_") && kind!="name_amp" {
+			let key = significant_key(&curs.node(),&self.line);
+			*self.var_counts.entry(key).or_insert(0) += 1;
+			return Ok(Navigation::GotoSibling);
+		}
+		match kind {
+			"tok_amp" => {
+				self.has_amp = true;
+				self.terminal = true;
+			},
+			"tok_del" | "tok_list" => self.has_line_ranges = true,
+			"tok_if" | "tok_rem" | "tok_data" | "tok_onerr" => self.terminal = true,
+			"str" => {
+				let txt = lang::node_text(&curs.node(),&self.line);
+				if txt.len()<2 || !txt.ends_with("\"") {
+					self.terminal = true;
+				}
+			},
+			_ => {}
+		}
+		if curs.node().has_error() {
+			self.terminal = true;
+		}
+		Ok(Navigation::GotoChild)
+	}
+}
+
+impl ProgramScan {
+	fn new() -> Self {
+		Self {
+			line: String::new(),
+			var_counts: HashMap::new(),
+			refs: HashSet::new(),
+			primary: None,
+			terminal: false,
+			body: String::new(),
+			has_amp: false,
+			has_line_ranges: false
+		}
+	}
+}
+
+/// Form the key that Applesoft would use to identify a variable, i.e., only the first two
+/// characters are significant, and each type and dimensionality has its own namespace.
+/// Functions are prefixed with `FN`, arrays are suffixed with `()`.
+fn significant_key(node: &tree_sitter::Node,line: &str) -> String {
+	let txt = lang::node_text(node,line).replace(" ","").to_uppercase();
+	let (base,suffix) = match txt.strip_suffix(|c: char| c=='$' || c=='%') {
+		Some(b) => (b.to_string(),txt[b.len()..].to_string()),
+		None => (txt.clone(),String::new())
+	};
+	let short: String = base.chars().take(2).collect();
+	if node.kind()=="name_fn" {
+		return ["FN",&short].concat();
+	}
+	let mut is_array = false;
+	if let Some(sib) = node.next_named_sibling() {
+		is_array = sib.kind()=="subscript";
+	}
+	if let Some(parent) = node.parent() {
+		if let Some(prev) = parent.prev_named_sibling() {
+			is_array |= prev.kind()=="tok_recall" || prev.kind()=="tok_store";
+		}
+	}
+	match is_array {
+		true => [short,suffix,"()".to_string()].concat(),
+		false => [short,suffix].concat()
+	}
 }
 
 impl Navigate for Minifier
@@ -30,6 +153,24 @@ impl Navigate for Minifier
     {
 		let node_str: String = lang::node_text(&curs.node(),&self.line);
 
+		// Rename variables using the program-wide map
+		if curs.node().kind().starts_with("name_") && !curs.node().kind().ends_with("amp") && !self.renames.is_empty() {
+			let key = significant_key(&curs.node(),&self.line);
+			if let Some(new_name) = self.renames.get(&key) {
+				let txt = node_str.replace(" ","");
+				let suffix = match txt.chars().last() {
+					Some(c) if c=='$' || c=='%' => c.to_string(),
+					_ => String::new()
+				};
+				match txt.starts_with(|c: char| c.is_lowercase()) {
+					true => self.minified_line += &new_name.to_lowercase(),
+					false => self.minified_line += new_name
+				};
+				self.minified_line += &suffix;
+				return Ok(Navigation::GotoSibling);
+			}
+		}
+
 		// Shorten variable names
 		if curs.node().kind().starts_with("name_") && !curs.node().kind().ends_with("amp") {
 			let txt = node_str.replace(" ","");
@@ -139,7 +280,9 @@ impl Minifier
 			minified_line: String::new(),
 			minified_program: String::new(),
 			var_guards: json::parse(minify_guards::VAR_GUARDS_JSON).expect("json error"),
-			flags: FLAG_SAFE
+			flags: FLAG_SAFE,
+			renames: HashMap::new(),
+			combined_lines: 0
 		}
     }
 	/// figure out if the short name needs to be guarded against forming a hidden token
@@ -162,6 +305,10 @@ impl Minifier
 	pub fn set_flags(&mut self,flags: u64) {
 		self.flags = flags;
 	}
+	/// get the minification flags
+	pub fn get_flags(&self) -> u64 {
+		self.flags
+	}
 	/// set minification level, 0 means no transformation, higher levels will
 	/// set increasing numbers of flags, the flags are returned
 	pub fn set_level(&mut self,level: usize) -> u64 {
@@ -172,8 +319,141 @@ impl Minifier
 		if level>2 {
 			self.flags |= FLAG_AMP_VARS;
 		}
+		if level>3 {
+			self.flags |= FLAG_COMBINE_LINES | FLAG_RENAME_VARS;
+		}
 		self.flags
 	}
+	/// Number of lines that were absorbed into preceding lines during the last minification
+	pub fn combined_lines(&self) -> usize {
+		self.combined_lines
+	}
+	/// Tokenize the original and minified programs at the usual address and return [original,minified] sizes.
+	/// This is the savings that matters when the program is loaded.
+	pub fn byte_savings(original: &str,minified: &str) -> Result<[usize;2],DYNERR> {
+		let mut tokenizer = tokenizer::Tokenizer::new();
+		let before = tokenizer.tokenize(original,2049)?.len();
+		let after = tokenizer.tokenize(minified,2049)?.len();
+		Ok([before,after])
+	}
+	/// Build the variable renaming map.  Each namespace gets its own sequence of names, the most
+	/// frequently used variables get the single character names.  Names that would need guarding
+	/// or that are tokens are never produced.
+	fn build_renames(&mut self,var_counts: &HashMap<String,usize>) {
+		let mut candidates: Vec<String> = Vec::new();
+		for c1 in 'A'..='Z' {
+			candidates.push(c1.to_string());
+		}
+		for c1 in 'A'..='Z' {
+			for c2 in ('A'..='Z').chain('0'..='9') {
+				candidates.push([c1,c2].iter().collect());
+			}
+		}
+		candidates.retain(|c| !TOK_2CHAR.contains(&c.as_str()) && !self.var_guards.has_key(&c.to_lowercase()));
+		let mut namespaces: HashMap<String,Vec<(&String,&usize)>> = HashMap::new();
+		for (key,count) in var_counts {
+			let ns = key.trim_start_matches(|c: char| c.is_ascii_alphanumeric()).to_string();
+			let ns = match key.starts_with("FN") { true => "FN".to_string(), false => ns };
+			namespaces.entry(ns).or_default().push((key,count));
+		}
+		self.renames = HashMap::new();
+		for (_ns,mut vars) in namespaces {
+			// sort by count descending, then by key for reproducible results
+			vars.sort_by(|a,b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+			for (i,(key,_count)) in vars.iter().enumerate() {
+				self.renames.insert(key.to_string(),candidates[i].clone());
+			}
+		}
+	}
+	/// minify a single line, the line should not have a line terminator
+	fn minify_line(&mut self,parser: &mut tree_sitter::Parser,line: &str) -> Result<String,DYNERR> {
+		// renaming happens on the first pass only, later passes would rename the new names
+		let mut renames = HashMap::new();
+		self.minified_line = String::from(line) + "\n";
+		for rep in 0..10 {
+			self.line = self.minified_line.clone();
+			self.minified_line = String::new();
+			let tree = parser.parse(&self.line,None).expect("Error parsing file");
+			self.walk(&tree)?;
+			self.minified_line.push('\n');
+			if rep==0 {
+				renames = std::mem::take(&mut self.renames);
+			}
+			if self.minified_line==self.line {
+				break;
+			}
+		}
+		self.renames = renames;
+		Ok(self.minified_line.clone())
+	}
+	/// Minify with the aggressive transformations.  The program is scanned as a whole first,
+	/// then lines are combined at the source level, then each combined line is minified.
+	fn minify_aggressive(&mut self,program: &str,parser: &mut tree_sitter::Parser) -> Result<String,DYNERR> {
+		let mut scan = ProgramScan::new();
+		// (primary,terminal,original line,body)
+		let mut lines: Vec<(Option<usize>,bool,String,String)> = Vec::new();
+		for line in program.lines() {
+			if line.trim().is_empty() {
+				continue;
+			}
+			scan.line = String::from(line) + "\n";
+			scan.primary = None;
+			scan.terminal = false;
+			scan.body = String::new();
+			let tree = parser.parse(&scan.line,None).expect("Error parsing file");
+			scan.walk(&tree)?;
+			lines.push((scan.primary,scan.terminal,line.to_string(),scan.body.clone()));
+		}
+		if scan.has_amp {
+			warn!("ampersand statements found, skipping renaming and line combination");
+			return self.minify_lines(program,parser);
+		}
+		if self.flags & FLAG_RENAME_VARS > 0 {
+			self.build_renames(&scan.var_counts);
+		}
+		let combine = self.flags & FLAG_COMBINE_LINES > 0 && !scan.has_line_ranges;
+		if self.flags & FLAG_COMBINE_LINES > 0 && scan.has_line_ranges {
+			warn!("program operates on line ranges, skipping line combination");
+		}
+		self.minified_program = String::new();
+		let mut curr: Option<(String,String,bool)> = None; // (source,minified,terminal)
+		for (primary,terminal,line,body) in lines {
+			if let Some((src,min,term)) = curr.take() {
+				let target = match primary {
+					Some(num) => scan.refs.contains(&num),
+					None => true
+				};
+				if combine && !term && !target {
+					let candidate = [src.as_str(),":",&body].concat();
+					let min_candidate = self.minify_line(parser,&candidate)?;
+					if min_candidate.trim_end().len() <= MAX_COMBINED_LINE {
+						self.combined_lines += 1;
+						curr = Some((candidate,min_candidate,terminal));
+						continue;
+					}
+				}
+				self.minified_program += &min;
+			}
+			let min = self.minify_line(parser,&line)?;
+			curr = Some((line,min,terminal));
+		}
+		if let Some((_src,min,_term)) = curr {
+			self.minified_program += &min;
+		}
+		Ok(self.minified_program.clone())
+	}
+	/// minify line by line using only local transformations
+	fn minify_lines(&mut self,program: &str,parser: &mut tree_sitter::Parser) -> Result<String,DYNERR> {
+		self.minified_program = String::new();
+		for line in program.lines() {
+			if line.trim().is_empty() {
+				continue;
+			}
+			let min = self.minify_line(parser,line)?;
+			self.minified_program += &min;
+		}
+		Ok(self.minified_program.clone())
+	}
 	/// try to reduce the size of a program using simple transformations
 	pub fn minify(&mut self,program: &str) -> Result<String,DYNERR> {
 		if self.flags==0 {
@@ -183,26 +463,15 @@ impl Minifier
 			error!("incompatible flags");
 			return Err(Box::new(crate::commands::CommandError::InvalidCommand));
 		}
-		self.minified_program = String::new();
+		self.renames = HashMap::new();
+		self.combined_lines = 0;
 		let mut parser = tree_sitter::Parser::new();
 		parser.set_language(&tree_sitter_applesoft::language()).expect("error loading applesoft grammar");
-		for line in program.lines() {
-			if line.trim().len()==0 {
-				continue;
-			}
-			self.minified_line = String::from(line) + "\n";
-			for _rep in 0..10 {
-				self.line = self.minified_line.clone();
-				self.minified_line = String::new();
-				let tree = parser.parse(&self.line,None).expect("Error parsing file");
-				self.walk(&tree)?;
-				self.minified_line.push('\n');
-				if self.minified_line==self.line {
-					break;
-				}
-			}
-			self.minified_program += &self.minified_line;
-		}
-		Ok(self.minified_program.clone())
+		let ans = match self.flags & (FLAG_COMBINE_LINES | FLAG_RENAME_VARS) {
+			0 => self.minify_lines(program,&mut parser),
+			_ => self.minify_aggressive(program,&mut parser)
+		};
+		self.renames = HashMap::new();
+		ans
 	}
 }
@@ -213,3 +213,42 @@ mod minify_separators {
 		super::test_minify(test_code, expected, 1);
 	}
 }
+
+mod minify_aggressive {
+    #[test]
+	fn rename_by_frequency() {
+		let test_code = "10 COUNT = COUNT + 1: PRINT COUNT, SUM\n20 SUM$ = \"X\"";
+		let expected = "10B=B+1:PRINTB,C:B$=\"X";
+		super::test_minify(test_code, expected, 4);
+	}
+    #[test]
+	fn significant_characters() {
+		let test_code = "10 LIMIT = 5: PRINT LIMBO";
+		let expected = "10B=5:PRINTB";
+		super::test_minify(test_code, expected, 4);
+	}
+    #[test]
+	fn arrays_and_functions() {
+		let test_code = "10 DIM CELLS(10): DEF FN SQUARE(CELL) = CELL * CELL\n20 PRINT FN SQUARE(CELLS(1))";
+		let expected = "10DIMB(10):DEFFNB(B)=B*B:PRINTFNB(B(1))";
+		super::test_minify(test_code, expected, 4);
+	}
+    #[test]
+	fn branch_targets_kept() {
+		let test_code = "10 HOME\n20 PRINT \"A\"\n30 GOTO 20";
+		let expected = "10HOME\n20PRINT\"A\":GOTO20";
+		super::test_minify(test_code, expected, 4);
+	}
+    #[test]
+	fn conditionals_and_comments() {
+		let test_code = "10 IF X THEN PRINT \"X\"\n20 REM HELLO\n30 PRINT \"Y\n40 END";
+		let expected = "10IFBTHENPRINT\"X\n20REM\n30PRINT\"Y\n40END";
+		super::test_minify(test_code, expected, 4);
+	}
+    #[test]
+	fn ampersand_prevents_renaming() {
+		let test_code = "10 & PRINT HELLO\n20 PRINT HELLO";
+		let expected = "10&PRINTHE\n20PRINTHE";
+		super::test_minify(test_code, expected, 4);
+	}
+}
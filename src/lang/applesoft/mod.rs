@@ -4,7 +4,7 @@
 //! The Applesoft parser is provided by `tree_sitter_applesoft`.
 //! The server compiles to a separate executable, its entry point is in `src/bin/server-applesoft/main.rs`.
 
-mod token_maps;
+pub(crate) mod token_maps;
 mod minify_guards;
 #[cfg(test)]
 mod tokenize_test;
@@ -16,6 +16,10 @@ mod minify_test;
 mod renumber_test;
 #[cfg(test)]
 mod diagnostics_test;
+#[cfg(test)]
+mod statistics_test;
+#[cfg(test)]
+mod bundle_test;
 pub mod diagnostics;
 pub mod checkpoint;
 pub mod tokenizer;
@@ -25,6 +29,9 @@ pub mod hovers;
 pub mod settings;
 pub mod completions;
 pub mod semantic_tokens;
+pub mod statistics;
+pub mod dialect;
+pub mod bundle;
 
 use std::fmt::Write;
 use std::collections::{HashMap,HashSet};
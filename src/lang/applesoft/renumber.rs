@@ -5,6 +5,7 @@ use tree_sitter_applesoft;
 use lsp_types::{Range,Position,TextEdit};
 use crate::lang;
 use crate::lang::Navigate;
+use crate::lang::labels;
 #[allow(deprecated)]
 use crate::lang::linenum::{LabelInformation,Renumber,LineNumberTool};
 use std::collections::BTreeMap;
@@ -137,7 +138,11 @@ impl Renumberer {
     /// Renumber all lines with number >= beg && number < end, as [start,start+step,...].
     /// References are updated globally.
     /// This function assumes the existing numbering is valid.
+    /// If the source has labels they stay attached to the same lines.
     pub fn renumber(&mut self,source: &str, beg: usize, end: usize, first: usize, step: usize) -> Result<String,DYNERR> {
+        if labels::has_labels(source) {
+            return labels::renumber(self,source,beg,end,first,step,|r,s| r.renumber(s,beg,end,first,step));
+        }
         let all_primaries = match self.gather_defs(source,0) {
             Ok(result) => result,
             Err(_) => return Err(Box::new(lang::Error::LineNumber))
@@ -181,4 +186,82 @@ impl Renumberer {
             }
         }
 	}
+    /// Number for a new line following `row`, midway between the numbered lines around it.
+    /// After the last line the number is 10 more.  If there is no number to spare an error is returned.
+    pub fn insert_line_number(&mut self,all_txt: &str,row: u32) -> Result<usize,String> {
+        let all_primaries = match self.gather_defs(all_txt,0) {
+            Ok(result) => result,
+            Err(_) => return Err("unable to gather primaries".to_string())
+        };
+        let mut prev: Option<(u32,usize)> = None;
+        let mut next: Option<(u32,usize)> = None;
+        for (num,label) in &all_primaries {
+            let r = label[0].rng.start.line;
+            if r <= row && prev.is_none_or(|(pr,_)| r > pr) {
+                prev = Some((r,*num));
+            }
+            if r > row && next.is_none_or(|(nr,_)| r < nr) {
+                next = Some((r,*num));
+            }
+        }
+        match (prev.map(|p| p.1),next.map(|n| n.1)) {
+            (Some(a),Some(b)) if b > a + 1 => Ok((a + b)/2),
+            (Some(a),None) if a < 63999 => Ok(usize::min(a + 10,63999)),
+            (None,Some(b)) if b > 0 => Ok(b/2),
+            (None,None) => Ok(10),
+            (Some(a),Some(b)) => Err(format!("no line number between {} and {}, renumber first",a,b)),
+            _ => Err("no line number available, renumber first".to_string())
+        }
+    }
+    /// Edit that inserts a numbered empty line after `pos`, also returns the new number
+    pub fn insert_line_edit(&mut self,all_txt: &str,pos: Position) -> Result<(usize,TextEdit),String> {
+        let line_sep = match all_txt.lines().count() == all_txt.split("\r\n").count() {
+            true => "\r\n",
+            false => "\n"
+        };
+        let lines = all_txt.lines().collect::<Vec<&str>>();
+        if lines.is_empty() {
+            return Ok((10,TextEdit::new(Range::new(Position::new(0,0),Position::new(0,0)),"10 ".to_string())));
+        }
+        let row = u32::min(pos.line,lines.len() as u32 - 1);
+        let num = self.insert_line_number(all_txt,row)?;
+        let edit = match (row as usize + 1) < lines.len() || all_txt.ends_with('\n') {
+            true => {
+                let start = Position::new(row + 1,0);
+                TextEdit::new(Range::new(start,start),format!("{} {}",num,line_sep))
+            },
+            false => {
+                let end = Position::new(row,lines[row as usize].encode_utf16().count() as u32);
+                TextEdit::new(Range::new(end,end),format!("{}{} ",line_sep,num))
+            }
+        };
+        Ok((num,edit))
+    }
+    /// Renumber the lines in `sel` starting from the first number in the selection, using the
+    /// largest step of 10, 5, 2, or 1 that fits before the next line.  References are updated.
+    pub fn selection_edits(&mut self,all_txt: &str,sel: Range) -> Result<Vec<TextEdit>,String> {
+        let lines = all_txt.lines().collect::<Vec<&str>>();
+        let mut sel_txt = String::new();
+        for l in sel.start.line..=u32::min(sel.end.line,lines.len() as u32) {
+            if let Some(line) = lines.get(l as usize) {
+                sel_txt += line;
+                sel_txt += "\n";
+            }
+        }
+        let first = match self.gather_defs(&sel_txt,sel.start.line as isize) {
+            Ok(result) => match result.first_key_value() {
+                Some((num,_)) => *num,
+                None => return Err("no primaries to change".to_string())
+            },
+            Err(_) => return Err("unable to gather primaries".to_string())
+        };
+        let mut last_err = String::new();
+        for step in [10,5,2,1] {
+            match self.build_edits(all_txt,Some(sel),&first.to_string(),&step.to_string(),true,false,0,63999) {
+                Ok(edits) => return Ok(edits),
+                Err(s) => last_err = s
+            }
+        }
+        Err(last_err)
+    }
 }
\ No newline at end of file
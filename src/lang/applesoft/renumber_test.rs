@@ -102,4 +102,61 @@ mod valid_moves {
 		let expected = "10 HOME\n40 END\n1000 INPUT X\n1002 PRINT X";
 		super::test_move(test_code, expected,20,40,1000,2,false);
 	}
-}
\ No newline at end of file
+}
+mod labels {
+    #[test]
+	fn labels_follow_lines() {
+		let test_code = "@start:\n10 HOME\n@loop:\n20 INPUT X\n30 IF X THEN @loop\n40 GOTO 10";
+		let expected = "@start:\n100 HOME\n@loop:\n110 INPUT X\n120 IF X THEN @loop\n130 GOTO @start\n";
+		super::test_renumber(test_code, expected,0,usize::MAX,100,10,false);
+	}
+    #[test]
+	fn segment_with_labels() {
+		let test_code = "10 HOME\n@loop:\n20 INPUT X\n30 GOTO @loop\n40 END";
+		let expected = "10 HOME\n@loop:\n27 INPUT X\n29 GOTO @loop\n40 END\n";
+		super::test_renumber(test_code, expected,20,40,27,2,false);
+	}
+    #[test]
+	fn undefined_label() {
+		let test_code = "10 HOME\n20 GOTO @nowhere";
+		let expected = "";
+		super::test_renumber(test_code, expected,0,usize::MAX,100,10,true);
+	}
+}
+mod insert_line {
+    use super::Renumberer;
+    use lsp_types::{Position,Range};
+    #[test]
+	fn midpoint() {
+		let test_code = "10 HOME\n20 PRINT X\n30 END\n";
+		let mut renumberer = Renumberer::new();
+		let (num,edit) = renumberer.insert_line_edit(test_code, Position::new(0,3)).expect("insert failed");
+		assert_eq!(num,15);
+		let result = crate::lang::apply_edits(test_code, &vec![edit], 0).expect("apply failed");
+		assert_eq!(result,"10 HOME\n15 \n20 PRINT X\n30 END\n");
+	}
+    #[test]
+	fn after_last() {
+		let test_code = "10 HOME\n20 PRINT X";
+		let mut renumberer = Renumberer::new();
+		let (num,edit) = renumberer.insert_line_edit(test_code, Position::new(1,0)).expect("insert failed");
+		assert_eq!(num,30);
+		let result = crate::lang::apply_edits(test_code, &vec![edit], 0).expect("apply failed");
+		assert_eq!(result,"10 HOME\n20 PRINT X\n30 ");
+	}
+    #[test]
+	fn no_room() {
+		let test_code = "10 HOME\n11 PRINT X\n";
+		let mut renumberer = Renumberer::new();
+		assert!(renumberer.insert_line_edit(test_code, Position::new(0,0)).is_err());
+	}
+    #[test]
+	fn renumber_selection() {
+		let test_code = "10 HOME\n11 PRINT X\n12 GOTO 11\n30 END\n";
+		let mut renumberer = Renumberer::new();
+		let sel = Range::new(Position::new(0,0),Position::new(2,5));
+		let edits = renumberer.selection_edits(test_code, sel).expect("renumber failed");
+		let result = crate::lang::apply_edits(test_code, &edits, 0).expect("apply failed");
+		assert_eq!(result,"10 HOME\n15 PRINT X\n20 GOTO 15\n30 END\n");
+	}
+}
@@ -16,7 +16,8 @@ pub struct Flag {
     pub undeclared_arrays: Option<DiagnosticSeverity>,
     pub undefined_variables: Option<DiagnosticSeverity>,
     pub bad_references: Option<DiagnosticSeverity>,
-    pub extended_call: Option<DiagnosticSeverity>
+    pub extended_call: Option<DiagnosticSeverity>,
+    pub unreachable_code: Option<DiagnosticSeverity>
 }
 #[derive(Clone)]
 pub struct Hovers {
@@ -39,7 +40,9 @@ pub struct Settings {
     pub flag: Flag,
     pub hovers: Hovers,
     pub completions: Completions,
-    pub detokenizer: Detokenizer
+    pub detokenizer: Detokenizer,
+    /// supplemental commands from extensions
+    pub dialect: super::dialect::Dialect
 }
 
 impl Settings {
@@ -52,7 +55,8 @@ impl Settings {
                 undeclared_arrays: Some(DiagnosticSeverity::WARNING),
                 undefined_variables: Some(DiagnosticSeverity::WARNING),
                 bad_references: Some(DiagnosticSeverity::ERROR),
-                extended_call: Some(DiagnosticSeverity::ERROR)
+                extended_call: Some(DiagnosticSeverity::ERROR),
+                unreachable_code: Some(DiagnosticSeverity::WARNING)
             },
             hovers : Hovers {
                 special_addresses: true,
@@ -66,7 +70,8 @@ impl Settings {
                 escapes: vec![10,13],
                 max_lines: 5000,
                 max_line_length: 255
-            }
+            },
+            dialect: super::dialect::Dialect::new()
         }
     }
 }
@@ -85,6 +90,7 @@ pub fn parse(json: &str) -> Result<Settings,DYNERR> {
                         update_json_severity(val,"undefinedVariables",&mut ans.flag.undefined_variables);
                         update_json_severity(val,"badReferences",&mut ans.flag.bad_references);
                         update_json_severity(val,"extendedCall",&mut ans.flag.extended_call);
+                        update_json_severity(val,"unreachableCode",&mut ans.flag.unreachable_code);
                     },
                     "hovers" => {
                         update_json_bool(val,"specialAddresses",&mut ans.hovers.special_addresses);
@@ -99,6 +105,9 @@ pub fn parse(json: &str) -> Result<Settings,DYNERR> {
                         update_json_i64(val,"maxLines",&mut ans.detokenizer.max_lines);
                         update_json_vec(val,"escapes",&mut ans.detokenizer.escapes);
                     },
+                    "dialect" => {
+                        ans.dialect = super::dialect::Dialect::from_value(val)?;
+                    },
                     _ => {}
                 }
             }
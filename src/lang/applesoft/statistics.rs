@@ -0,0 +1,244 @@
+//! Size statistics for tokenized Applesoft programs.
+//!
+//! Reports the bytes used by each line, the largest lines, and where the program and its variables
+//! fall relative to HIMEM.  Lines whose listing would not fit in the input buffer are flagged, since
+//! they cannot be re-entered after detokenizing.
+//!
+//! The variable table is estimated from the names found in the program.  Simple variables take 7 bytes.
+//! Arrays take a header plus 5, 2, or 3 bytes per element for real, integer, or string elements,
+//! using the `DIM` bounds if they are literal numbers, otherwise the default bound of 10.
+//! String contents are not counted.
+
+use std::collections::HashMap;
+use super::tokenizer::Tokenizer;
+use crate::lang;
+use crate::DYNERR;
+
+/// characters that fit in the input buffer, including the line number
+pub const INPUT_BUFFER_LIMIT: usize = 239;
+
+const DATA_TOK: u8 = 131;
+const DIM_TOK: u8 = 134;
+const REM_TOK: u8 = 178;
+const FN_TOK: u8 = 194;
+const QUOTE: u8 = 34;
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct LineStats {
+	pub num: u16,
+	/// address of the line's link
+	pub addr: usize,
+	/// tokenized size including link, line number, and terminator
+	pub bytes: usize,
+	/// length of the detokenized line
+	pub listed_len: usize
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Statistics {
+	pub lines: Vec<LineStats>,
+	/// first byte of the program
+	pub start: usize,
+	/// one past the end of program marker, i.e., LOMEM
+	pub end: usize,
+	pub simple_vars: usize,
+	pub arrays: usize,
+	/// estimated size of simple variables and arrays
+	pub var_bytes: usize,
+	pub himem: usize
+}
+
+/// Array element counts from the literal bounds following DIM, keyed like variables
+fn dim_bounds(line: &[u8], map: &mut HashMap<String,usize>) {
+	let mut i = 0;
+	while i < line.len() && line[i] != DIM_TOK {
+		i += 1;
+	}
+	while i < line.len() && line[i] != b':' {
+		let (name,next) = var_name(line, i + 1);
+		i = next;
+		if name.is_empty() || i >= line.len() || line[i] != b'(' {
+			continue;
+		}
+		let mut count = 1;
+		let mut bound = String::new();
+		i += 1;
+		while i < line.len() && line[i] != b')' {
+			match line[i] {
+				b',' => {
+					count *= bound.parse::<usize>().unwrap_or(10) + 1;
+					bound.clear();
+				},
+				c => bound.push(c as char)
+			}
+			i += 1;
+		}
+		count *= bound.parse::<usize>().unwrap_or(10) + 1;
+		map.insert(name, count);
+		i += 1;
+	}
+}
+
+/// Variable key starting at `i`, i.e. two significant characters and a type suffix, along with the index after the name.
+/// If there is no name at `i` the key is empty.
+fn var_name(line: &[u8], mut i: usize) -> (String,usize) {
+	let mut key = String::new();
+	if i >= line.len() || !line[i].is_ascii_uppercase() {
+		return (key,i + 1);
+	}
+	while i < line.len() && line[i].is_ascii_alphanumeric() {
+		if key.len() < 2 {
+			key.push(line[i] as char);
+		}
+		i += 1;
+	}
+	if i < line.len() && (line[i] == b'$' || line[i] == b'%') {
+		key.push(line[i] as char);
+		i += 1;
+	}
+	(key,i)
+}
+
+/// Collect variable keys in the body of a line, mapped to whether the variable is an array
+fn scan_vars(line: &[u8], vars: &mut HashMap<String,bool>) {
+	let mut i = 0;
+	while i < line.len() {
+		match line[i] {
+			QUOTE => {
+				i += 1;
+				while i < line.len() && line[i] != QUOTE {
+					i += 1;
+				}
+				i += 1;
+			},
+			REM_TOK => return,
+			DATA_TOK => {
+				while i < line.len() && line[i] != b':' {
+					i += 1;
+				}
+			},
+			c if c.is_ascii_uppercase() && (i == 0 || !line[i-1].is_ascii_alphanumeric()) => {
+				let (key,next) = var_name(line, i);
+				// function names have their own table
+				if i == 0 || line[i-1] != FN_TOK {
+					let is_array = next < line.len() && line[next] == b'(';
+					vars.insert(key, is_array);
+				}
+				i = next;
+			},
+			_ => i += 1
+		}
+	}
+}
+
+fn element_size(key: &str) -> usize {
+	match key.chars().last() {
+		Some('%') => 2,
+		Some('$') => 3,
+		_ => 5
+	}
+}
+
+/// Analyze the tokenized program `tok`, which is loaded at `start`
+pub fn analyze(tok: &[u8], start: usize, himem: usize) -> Result<Statistics,DYNERR> {
+	let tokenizer = Tokenizer::new();
+	let mut lines = Vec::new();
+	let mut vars = HashMap::new();
+	let mut bounds = HashMap::new();
+	let mut ptr = 0;
+	while ptr + 1 < tok.len() && (tok[ptr] != 0 || tok[ptr+1] != 0) {
+		let mut eol = ptr + 4;
+		while eol < tok.len() && tok[eol] != 0 {
+			eol += 1;
+		}
+		if eol >= tok.len() {
+			log::error!("program ended before end of program marker");
+			return Err(Box::new(lang::Error::Detokenization));
+		}
+		let body = &tok[ptr+4..eol];
+		scan_vars(body, &mut vars);
+		dim_bounds(body, &mut bounds);
+		let mut one_line = tok[ptr..eol+1].to_vec();
+		one_line.extend_from_slice(&[0,0]);
+		let listed = tokenizer.detokenize(&one_line)?;
+		lines.push(LineStats {
+			num: u16::from_le_bytes([tok[ptr+2],tok[ptr+3]]),
+			addr: start + ptr,
+			bytes: eol + 1 - ptr,
+			listed_len: listed.trim_end().len()
+		});
+		ptr = eol + 1;
+	}
+	let mut simple_vars = 0;
+	let mut arrays = 0;
+	let mut var_bytes = 0;
+	for (key,is_array) in &vars {
+		match is_array {
+			true => {
+				let count = *bounds.get(key).unwrap_or(&11);
+				arrays += 1;
+				var_bytes += 7 + count * element_size(key);
+			}
+			false => {
+				simple_vars += 1;
+				var_bytes += 7;
+			}
+		}
+	}
+	Ok(Statistics {
+		lines,
+		start,
+		end: start + usize::min(ptr + 2, tok.len()),
+		simple_vars,
+		arrays,
+		var_bytes,
+		himem
+	})
+}
+
+impl Statistics {
+	/// Lines whose listing exceeds the input buffer
+	pub fn long_lines(&self) -> Vec<&LineStats> {
+		self.lines.iter().filter(|l| l.listed_len > INPUT_BUFFER_LIMIT).collect()
+	}
+	/// Bytes left between the estimated end of variables and HIMEM, negative if HIMEM is exceeded
+	pub fn free(&self) -> i64 {
+		self.himem as i64 - (self.end + self.var_bytes) as i64
+	}
+	/// Report as a JSON string, listing the `top` largest lines
+	pub fn to_json(&self, top: usize, indent: Option<u16>) -> String {
+		let line_obj = |l: &LineStats| json::object! {
+			"line": l.num,
+			"addr": l.addr,
+			"bytes": l.bytes,
+			"listed_len": l.listed_len
+		};
+		let mut largest: Vec<&LineStats> = self.lines.iter().collect();
+		largest.sort_by(|a,b| b.bytes.cmp(&a.bytes).then(a.num.cmp(&b.num)));
+		let ans = json::object! {
+			"program": {
+				"start": self.start,
+				"end": self.end,
+				"bytes": self.end - self.start,
+				"lines": self.lines.len()
+			},
+			"variables": {
+				"simple": self.simple_vars,
+				"arrays": self.arrays,
+				"estimated_bytes": self.var_bytes
+			},
+			"memory": {
+				"himem": self.himem,
+				"free": self.free(),
+				"exceeded": self.free() < 0
+			},
+			"largest_lines": largest.iter().take(top).map(|l| line_obj(l)).collect::<Vec<json::JsonValue>>(),
+			"long_lines": self.long_lines().iter().map(|l| line_obj(l)).collect::<Vec<json::JsonValue>>(),
+			"per_line": self.lines.iter().map(line_obj).collect::<Vec<json::JsonValue>>()
+		};
+		match indent {
+			Some(spaces) => json::stringify_pretty(ans, spaces),
+			None => json::stringify(ans)
+		}
+	}
+}
@@ -0,0 +1,54 @@
+use super::tokenizer::Tokenizer;
+use super::statistics::{analyze,INPUT_BUFFER_LIMIT};
+
+fn tokens(prog: &str) -> Vec<u8> {
+	let mut tokenizer = Tokenizer::new();
+	tokenizer.tokenize(prog,2049).expect("tokenize failed")
+}
+
+#[test]
+fn line_sizes() {
+	let tok = tokens("10 HOME\n20 PRINT \"HELLO\"\n");
+	let stats = analyze(&tok,2049,38400).expect("analyze failed");
+	assert_eq!(stats.lines.len(),2);
+	assert_eq!(stats.lines[0].num,10);
+	assert_eq!(stats.lines[0].addr,2049);
+	assert_eq!(stats.lines[0].bytes,6);
+	assert_eq!(stats.lines[1].addr,2055);
+	assert_eq!(stats.lines[1].bytes,13);
+	assert_eq!(stats.end,2049 + tok.len());
+	assert!(stats.long_lines().is_empty());
+}
+
+#[test]
+fn variables() {
+	let tok = tokens("10 DIM A(20),B$(3,4)\n20 X = 1:Y% = 2:NAME$ = \"Q\":NA$ = \"R\"\n30 DEF FN F(Z) = Z*2:C(1) = 0\n40 REM JUNK = 1\n50 DATA Q,R\n");
+	let stats = analyze(&tok,2049,38400).expect("analyze failed");
+	// X, Y%, NA$, Z
+	assert_eq!(stats.simple_vars,4);
+	// A, B$, C
+	assert_eq!(stats.arrays,3);
+	assert_eq!(stats.var_bytes,4*7 + 7 + 21*5 + 7 + 20*3 + 7 + 11*5);
+}
+
+#[test]
+fn memory() {
+	let tok = tokens("10 DIM A(2000)\n");
+	let stats = analyze(&tok,2049,0x2000).expect("analyze failed");
+	assert!(stats.free() < 0);
+	let stats = analyze(&tok,2049,38400).expect("analyze failed");
+	assert!(stats.free() > 0);
+}
+
+#[test]
+fn long_line() {
+	let prog = String::from("10 PRINT \"") + &"X".repeat(INPUT_BUFFER_LIMIT) + "\"\n20 END\n";
+	let stats = analyze(&tokens(&prog),2049,38400).expect("analyze failed");
+	let long = stats.long_lines();
+	assert_eq!(long.len(),1);
+	assert_eq!(long[0].num,10);
+	let report = json::parse(&stats.to_json(1,None)).expect("bad json");
+	assert_eq!(report["largest_lines"].len(),1);
+	assert_eq!(report["largest_lines"][0]["line"],10);
+	assert_eq!(report["long_lines"][0]["line"],10);
+}
@@ -339,3 +339,31 @@ mod ampersand {
 		super::test_tokenizer(test_code, expected);
 	}
 }
+
+mod dialect {
+	use crate::lang::applesoft::{tokenizer::Tokenizer,settings::Settings,dialect::Dialect};
+	fn test_tokenizer_dialect(test_code: &str, expected: &str) {
+		let mut config = Settings::new();
+		config.dialect = Dialect::from_json(r#"{"tokens": {"HCOPY": 235}, "ampersand": ["CLS"]}"#).expect("bad dialect");
+		let mut tokenizer = Tokenizer::new();
+		tokenizer.set_config(config);
+		let actual = tokenizer.tokenize(test_code,2049).expect("tokenizer failed");
+		assert_eq!(hex::encode_upper(actual),expected);
+	}
+	#[test]
+	fn extension_token() {
+		let test_code = "10 & hcopy 1,2";
+		let expected = "0B080A00AFEB312C32000000";
+		test_tokenizer_dialect(test_code, expected);
+	}
+	#[test]
+	fn extension_text() {
+		let test_code = "10 & CLS";
+		let expected = "0A080A00AF434C53000000";
+		test_tokenizer_dialect(test_code, expected);
+	}
+	#[test]
+	fn bad_code() {
+		assert!(Dialect::from_json(r#"{"tokens": {"HCOPY": 200}}"#).is_err());
+	}
+}
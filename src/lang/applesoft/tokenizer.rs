@@ -6,6 +6,7 @@ use tree_sitter;
 use tree_sitter_applesoft;
 use crate::lang;
 use crate::lang::Navigate;
+use crate::lang::labels;
 use super::settings;
 use super::token_maps;
 use crate::{STDRESULT,DYNERR};
@@ -55,6 +56,15 @@ impl lang::Navigate for Tokenizer
 			self.tokenized_line.push(*tok);
 			return Ok(lang::Navigation::GotoSibling);
 		}
+		// Extension commands that are tokenized
+		if curs.node().kind()=="name_amp" {
+			let txt = String::from_utf8_lossy(&cleanupper).to_string();
+			if let Some((Some(tok),len)) = self.config.dialect.match_ampersand(&txt) {
+				self.tokenized_line.push(tok);
+				self.tokenized_line.append(&mut txt.as_bytes()[len..].to_vec());
+				return Ok(lang::Navigation::GotoSibling);
+			}
+		}
 		// Required upper case
 		if curs.node().kind().starts_with("name_") || curs.node().kind()=="real" {
 			if curs.node().kind()=="name_amp" && curs.node().child_count()>0 {
@@ -134,8 +144,16 @@ impl Tokenizer
 		self.curr_addr = next_addr;
 		Ok(())
 	}
-	/// Tokenize a program contained in a UTF8 string, result is an array of bytes
+	/// Tokenize a program contained in a UTF8 string, result is an array of bytes.
+	/// Labels are resolved to line numbers.
 	pub fn tokenize(&mut self,program: &str,start_addr: u16) -> Result<Vec<u8>,DYNERR> {
+		match labels::has_labels(program) {
+			true => self.tokenize_numbered(&labels::resolve(program)?.0,start_addr),
+			false => self.tokenize_numbered(program,start_addr)
+		}
+	}
+	/// Tokenize a program whose labels, if any, have already been resolved, see `labels::resolve`.
+	pub fn tokenize_numbered(&mut self,program: &str,start_addr: u16) -> Result<Vec<u8>,DYNERR> {
 		self.curr_addr = start_addr;
 		self.tokenized_program = Vec::new();
 		let mut parser = tree_sitter::Parser::new();
@@ -197,6 +215,9 @@ impl Tokenizer
 					if let Some(tok) = self.detok_map.get(&img[addr]) {
 						code += &(String::from(" ") + &tok.to_uppercase() + " ");
 						addr += 1;
+					} else if let Some(keyword) = self.config.dialect.keyword(img[addr]) {
+						code += &(String::from(" ") + keyword + " ");
+						addr += 1;
 					} else {
 						error!("unrecognized Applesoft token encountered");
 						return Err(Box::new(lang::Error::Detokenization));
@@ -0,0 +1,118 @@
+use lsp_types::DiagnosticSeverity;
+use super::Conversion;
+
+/// Check the program and the messages of the diagnostics, which must come in the given order
+fn check(ans: Conversion,expected: &str,messages: &[(DiagnosticSeverity,&str)]) {
+    assert_eq!(ans.program,expected);
+    let actual: Vec<(DiagnosticSeverity,&str)> = ans.diagnostics.iter()
+        .map(|d| (d.severity.expect("no severity"),d.message.as_str())).collect();
+    assert_eq!(actual,messages);
+}
+
+mod to_integer {
+    use lsp_types::DiagnosticSeverity as S;
+    fn test(code: &str,expected: &str,messages: &[(S,&str)]) {
+        let ans = super::super::applesoft_to_integer(code).expect("conversion failed");
+        super::check(ans,expected,messages);
+    }
+    #[test]
+    fn slices() {
+        test("10 A$ = \"HELLO\": PRINT LEFT$(A$,2); MID$(A$,2,3); RIGHT$(A$,2); MID$(A$,3)\n",
+            "0 DIM A$(255)\n10 A$ = \"HELLO\": PRINT A$(1,2);A$(2,4);A$(LEN(A$) - 1);A$(3)\n",&[]);
+        test("10 PRINT LEFT$(\"HELLO\",2)\n","10 PRINT LEFT$(\"HELLO\",2)\n",
+            &[(S::ERROR,"Integer BASIC can only take substrings of a string variable")]);
+    }
+    #[test]
+    fn join_strings() {
+        test("10 A$ = \"A\" + \"B\"\n","0 DIM A$(255)\n10 A$ = \"A\": A$(LEN(A$) + 1) = \"B\"\n",&[]);
+    }
+    #[test]
+    fn division() {
+        test("10 X = 5 / 2\n","10 X = 5 / 2\n",
+            &[(S::INFORMATION,"Integer BASIC division drops the remainder, results differ unless the quotient is whole")]);
+    }
+    #[test]
+    fn random() {
+        test("10 X = INT(RND(1)*6)\n","10 X = RND(6)\n",&[]);
+        test("10 X = RND(1)\n","10 X = RND(1)\n",&[(S::ERROR,"RND is real valued, use INT(RND(1)*N) to get RND(N)")]);
+    }
+    #[test]
+    fn if_then() {
+        test("10 IF X = 1 THEN PRINT \"A\"\n","10 IF X = 1 THEN PRINT \"A\"\n",&[]);
+        test("10 IF X = 1 THEN 100\n","10 IF X = 1 THEN 100\n",&[]);
+        // statements after THEN are skipped by jumping to the next line
+        test("10 IF X = 1 THEN PRINT \"A\": PRINT \"B\"\n20 END\n",
+            "10 IF NOT(X = 1) THEN 20: PRINT \"A\": PRINT \"B\"\n20 END\n",&[]);
+        test("10 IF X = 1 THEN PRINT \"A\": PRINT \"B\"\n","10 IF NOT(X = 1) THEN END: PRINT \"A\": PRINT \"B\"\n",&[]);
+        test("10 IF X = 1 THEN GOTO 100: PRINT\n","10 IF X = 1 THEN 100\n",&[(S::WARNING,"unreachable statements are dropped")]);
+    }
+    #[test]
+    fn translated_statements() {
+        test("10 HOME: INVERSE: NORMAL: CLEAR\n","10 CALL -936: POKE 50,63: POKE 50,255: CLR\n",&[]);
+        test("10 STOP\n","10 END\n",&[(S::WARNING,"STOP becomes END")]);
+    }
+    #[test]
+    fn flagged() {
+        test("10 X = 1.5\n","10 X = 1.5\n",&[(S::ERROR,"Integer BASIC has no real numbers")]);
+        test("10 X = 40000\n","10 X = 40000\n",&[(S::ERROR,"number is too large for Integer BASIC")]);
+        test("10 FOR I = 1 TO 3: NEXT\n","10 FOR I = 1 TO 3: NEXT\n",&[(S::ERROR,"Integer BASIC NEXT needs the loop variable")]);
+        test("10 X = SQR(4)\n","10 X = SQR(4)\n",&[(S::ERROR,"SQR has no Integer BASIC equivalent")]);
+        test("10 PRINT A$(1)\n","0 DIM A$(255)\n10 PRINT A$(1)\n",&[(S::ERROR,"Integer BASIC has no string arrays")]);
+        test("10 DIM A(3,3)\n","10 DIM A(3,3)\n",&[(S::ERROR,"Integer BASIC arrays have one dimension")]);
+        test("10 IF A$ < B$ THEN 20\n","0 DIM A$(255),B$(255)\n10 IF A$ < B$ THEN 20\n",
+            &[(S::ERROR,"Integer BASIC can only compare strings with = and #")]);
+        test("10 PRINT TAB(5);\"X\"\n","10 PRINT TAB(5);\"X\"\n",
+            &[(S::ERROR,"Integer BASIC cannot TAB or SPC within PRINT, use the TAB statement")]);
+        test("10 X = (1\n","10 X = (1\n",&[(S::ERROR,"line could not be parsed as Applesoft")]);
+        test("10 A$ = A$ + B$ + A$\n","0 DIM A$(255),B$(255)\n10 A$(LEN(A$) + 1) = B$: A$(LEN(A$) + 1) = A$\n",&[(S::ERROR,"string is changed before it is appended")]);
+        test("10 PRINT A$ + B$\n","10 PRINT A$ + B$\n",&[(S::ERROR,"strings can only be joined by assigning them")]);
+        test("10 HGR\n","10 HGR\n",&[(S::ERROR,"HGR has no Integer BASIC equivalent")]);
+    }
+}
+
+mod to_applesoft {
+    use lsp_types::DiagnosticSeverity as S;
+    fn test(code: &str,expected: &str,messages: &[(S,&str)]) {
+        let ans = super::super::integer_to_applesoft(code).expect("conversion failed");
+        super::check(ans,expected,messages);
+    }
+    #[test]
+    fn slices() {
+        test("10 A$ = \"HELLO\": PRINT A$(2,3); A$(3); A$(1,2)\n",
+            "10 A$ = \"HELLO\": PRINT MID$(A$,2,2);MID$(A$,3);LEFT$(A$,2)\n",&[]);
+        test("10 A$ = \"X\": A$(2) = \"Y\"\n","10 A$ = \"X\": A$ = LEFT$(A$,1) + \"Y\"\n",&[]);
+    }
+    #[test]
+    fn modulus() {
+        test("10 X = A MOD 3\n","10 X = (A - INT(A / 3) * 3)\n",
+            &[(S::INFORMATION,"MOD is computed with INT, results differ for negative operands")]);
+    }
+    #[test]
+    fn division() {
+        test("10 X = A / 3\n","10 X = INT(A / 3)\n",
+            &[(S::INFORMATION,"division is computed with INT, results differ for negative quotients")]);
+    }
+    #[test]
+    fn random() {
+        test("10 X = RND (6)\n","10 X = INT(RND(1) * 6)\n",&[]);
+    }
+    #[test]
+    fn if_then() {
+        test("10 IF X = 1 THEN 100\n","10 IF X = 1 THEN 100\n",&[]);
+        test("10 IF X = 1 THEN PRINT \"A\": PRINT \"B\"\n","10 IF X = 1 THEN PRINT \"A\": PRINT \"B\"\n",
+            &[(S::WARNING,"statements after IF-THEN only run when the condition holds in Applesoft")]);
+    }
+    #[test]
+    fn translated_statements() {
+        test("10 TAB 5: CLR: CON\n","10 HTAB 5: CLEAR: CONT\n",&[]);
+        test("10 DIM A$(10), B(5)\n","10 DIM B(5)\n",&[(S::INFORMATION,"string DIM is not needed in Applesoft")]);
+    }
+    #[test]
+    fn flagged() {
+        test("10 GOTO X * 10\n","10 GOTO X * 10\n",&[(S::ERROR,"Applesoft needs a line number here")]);
+        test("10 IF X THEN Y\n","10 IF X THEN Y\n",&[(S::ERROR,"Applesoft needs a line number here")]);
+        test("10 AUTO 10\n","10 AUTO 10\n",&[(S::ERROR,"AUTO has no Applesoft equivalent")]);
+        test("10 GOOD = 1: GOAL = 2\n","10 GOOD = 1: GOAL = 2\n",&[(S::WARNING,"GOOD and GOAL are the same variable in Applesoft")]);
+        test("10 XVALQ = 1\n","10 XVALQ = 1\n",&[(S::ERROR,"XVALQ contains the Applesoft keyword VAL")]);
+    }
+}
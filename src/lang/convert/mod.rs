@@ -0,0 +1,130 @@
+//! # BASIC dialect conversion
+//!
+//! Translates Applesoft to Integer BASIC, or the reverse, one statement at a time, using the parse
+//! trees of both dialects.  Anything that cannot be translated is kept as is and flagged with an
+//! error diagnostic.  Translations that may not behave exactly the same are flagged with a warning.
+//! The diagnostics refer to the source program, so they can be printed with `lang::eprint_diagnostic`.
+
+mod to_integer;
+mod to_applesoft;
+#[cfg(test)]
+mod convert_test;
+
+use lsp_types as lsp;
+use crate::lang;
+use crate::lang::server::basic_diag;
+use crate::DYNERR;
+
+/// Translated program along with the diagnostics
+pub struct Conversion {
+    pub program: String,
+    pub diagnostics: Vec<lsp::Diagnostic>
+}
+
+impl Conversion {
+    pub fn err_warn_info_counts(&self) -> [usize;3] {
+        let mut ans = [0,0,0];
+        for diag in &self.diagnostics {
+            match diag.severity {
+                Some(lsp::DiagnosticSeverity::ERROR) => ans[0] += 1,
+                Some(lsp::DiagnosticSeverity::WARNING) => ans[1] += 1,
+                Some(lsp::DiagnosticSeverity::INFORMATION) => ans[2] += 1,
+                _ => {}
+            }
+        }
+        ans
+    }
+}
+
+/// Translate Applesoft source to Integer BASIC source
+pub fn applesoft_to_integer(program: &str) -> Result<Conversion,DYNERR> {
+    to_integer::Converter::new()?.convert(program)
+}
+
+/// Translate Integer BASIC source to Applesoft source
+pub fn integer_to_applesoft(program: &str) -> Result<Conversion,DYNERR> {
+    to_applesoft::Converter::new()?.convert(program)
+}
+
+/// State shared by both directions while one line is being translated
+struct LineContext {
+    row: isize,
+    line: String,
+    diagnostics: Vec<lsp::Diagnostic>
+}
+
+impl LineContext {
+    fn new() -> Self {
+        Self {
+            row: 0,
+            line: String::new(),
+            diagnostics: Vec::new()
+        }
+    }
+    /// Text of a node with surrounding spaces removed
+    fn text(&self,node: &tree_sitter::Node) -> String {
+        lang::node_text(node,&self.line).trim().to_string()
+    }
+    /// Text of a keyword or operator with spaces removed and case raised
+    fn keyword(&self,node: &tree_sitter::Node) -> String {
+        self.text(node).replace(" ","").to_uppercase()
+    }
+    fn push(&mut self,node: &tree_sitter::Node,mess: &str,severity: lsp::DiagnosticSeverity) {
+        let rng = lang::lsp_range(node.range(),self.row,0);
+        self.diagnostics.push(basic_diag(rng,mess,severity));
+    }
+    fn error(&mut self,node: &tree_sitter::Node,mess: &str) {
+        self.push(node,mess,lsp::DiagnosticSeverity::ERROR);
+    }
+    fn warn(&mut self,node: &tree_sitter::Node,mess: &str) {
+        self.push(node,mess,lsp::DiagnosticSeverity::WARNING);
+    }
+    fn info(&mut self,node: &tree_sitter::Node,mess: &str) {
+        self.push(node,mess,lsp::DiagnosticSeverity::INFORMATION);
+    }
+}
+
+/// Join the tokens of a line with single spaces, except around parentheses and separators
+fn join(tokens: &[String]) -> String {
+    let mut ans = String::new();
+    let mut prev = "";
+    for tok in tokens {
+        let tight = ans.is_empty() || ans.ends_with(['(',',',';']) || [",",";",")",":"].contains(&tok.as_str())
+            || prev.len() > 1 && prev.ends_with(['=',':'])
+            || tok=="(" && ans.ends_with(|c: char| c.is_ascii_alphanumeric() || c=='$');
+        if !tight {
+            ans.push(' ');
+        }
+        ans += tok;
+        prev = tok;
+    }
+    ans
+}
+
+/// Wrap tokens in parentheses unless they are a single token
+fn group(tokens: Vec<String>) -> Vec<String> {
+    match tokens.len() {
+        1 => tokens,
+        _ => [vec!["(".to_string()],tokens,vec![")".to_string()]].concat()
+    }
+}
+
+/// Attach a sign to a single token operand, otherwise keep them apart
+fn unary(op: String,operand: Vec<String>) -> Vec<String> {
+    match (op.as_str(),operand.as_slice()) {
+        ("-" | "+",[t]) => vec![op + t],
+        _ => [vec![op],operand].concat()
+    }
+}
+
+/// If the tokens are a single integer literal return its value
+fn literal(tokens: &[String]) -> Option<i64> {
+    match tokens {
+        [t] => t.parse::<i64>().ok(),
+        _ => None
+    }
+}
+
+fn tokens(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
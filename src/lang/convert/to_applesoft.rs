@@ -0,0 +1,363 @@
+//! Integer BASIC to Applesoft
+//!
+//! String slices become `MID$`, `LEFT$`, or concatenations.  Division and `MOD` are rewritten
+//! with `INT`, which rounds down, so results differ from Integer BASIC when the quotient is negative.
+//! Variable names are checked for embedded Applesoft keywords, and for names that Applesoft would
+//! not tell apart, since only the first two characters count.
+
+use std::collections::{HashMap,HashSet};
+use tree_sitter::Node;
+use super::{LineContext,Conversion,join,group,literal,tokens,unary};
+use crate::lang::applesoft::token_maps;
+use crate::DYNERR;
+
+/// Statements that have no Applesoft equivalent
+const UNSUPPORTED: [&str;6] = ["statement_dsp_int","statement_dsp_str","statement_nodsp_int","statement_nodsp_str","com_auto","com_man"];
+
+pub struct Converter {
+    parser: tree_sitter::Parser,
+    ctx: LineContext,
+    /// alphabetic Applesoft keywords, upper case
+    keywords: Vec<String>,
+    /// significant part of each name mapped to the first full name seen
+    names: HashMap<String,String>,
+    /// pairs of names that were already flagged
+    collisions: HashSet<(String,String)>
+}
+
+/// first child as a token kind, or empty string
+fn lead(node: &Node) -> &'static str {
+    match node.child(0) {
+        Some(child) => child.kind(),
+        None => ""
+    }
+}
+
+/// Expression children, i.e., leaving out names, parentheses, and separators
+fn operands<'a>(node: &Node<'a>) -> Vec<Node<'a>> {
+    let mut ans = Vec::new();
+    let mut curs = node.walk();
+    for child in node.named_children(&mut curs) {
+        let k = child.kind();
+        if !k.starts_with("open_") && !k.starts_with("sep_") && k!="close" {
+            ans.push(child);
+        }
+    }
+    ans
+}
+
+impl Converter {
+    pub fn new() -> Result<Self,DYNERR> {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_integerbasic::language())?;
+        let keywords = token_maps::DETOK_MAP.iter()
+            .filter(|(_,s)| s.len() > 1 && s.chars().all(|c| c.is_ascii_alphabetic()))
+            .map(|(_,s)| s.to_uppercase())
+            .collect();
+        Ok(Self {
+            parser,
+            ctx: LineContext::new(),
+            keywords,
+            names: HashMap::new(),
+            collisions: HashSet::new()
+        })
+    }
+    pub fn convert(&mut self,program: &str) -> Result<Conversion,DYNERR> {
+        let mut out = Vec::new();
+        for (row,line) in program.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            self.ctx.row = row as isize;
+            self.ctx.line = line.to_string() + "\n";
+            let tree = match self.parser.parse(&self.ctx.line,None) {
+                Some(tree) => tree,
+                None => return Err(Box::new(crate::lang::Error::ParsingError))
+            };
+            let root = tree.root_node();
+            match root.named_child(0) {
+                Some(node) if node.kind()=="line" && !root.has_error() => out.push(self.line(&node)),
+                _ => {
+                    self.ctx.error(&root,"line could not be parsed as Integer BASIC");
+                    out.push(line.trim_end().to_string());
+                }
+            }
+        }
+        Ok(Conversion {
+            program: out.join("\n") + "\n",
+            diagnostics: std::mem::take(&mut self.ctx.diagnostics)
+        })
+    }
+    fn line(&mut self,line: &Node) -> String {
+        let mut ans = Vec::new();
+        let mut body = Vec::new();
+        let mut after_if = false;
+        let mut curs = line.walk();
+        for child in line.named_children(&mut curs) {
+            match child.kind() {
+                "linenum" => ans.push(self.ctx.keyword(&child)),
+                "statement" => {
+                    if after_if {
+                        self.ctx.warn(&child,"statements after IF-THEN only run when the condition holds in Applesoft");
+                        after_if = false;
+                    }
+                    if lead(&child)=="statement_if" && child.named_child(2).map(|n| n.kind())==Some("statement_then") {
+                        after_if = true;
+                    }
+                    let mut next = self.statement(&child);
+                    if next.is_empty() {
+                        continue;
+                    }
+                    if !body.is_empty() {
+                        body.push(":".to_string());
+                    }
+                    body.append(&mut next);
+                },
+                _ => {}
+            }
+        }
+        if body.is_empty() {
+            body.push("REM".to_string());
+        }
+        ans.append(&mut body);
+        join(&ans)
+    }
+    fn statement(&mut self,stmt: &Node) -> Vec<String> {
+        match lead(stmt) {
+            "statement_rem" => {
+                let beg = stmt.child(0).map(|n| n.end_byte()).unwrap_or(0);
+                vec!["REM".to_string() + self.ctx.line[beg..].trim_end()]
+            },
+            "statement_dim_str" | "statement_dim_int" => self.dim(stmt),
+            "statement_input_prompt" => self.input(stmt),
+            "statement_if" => self.if_statement(stmt),
+            "statement_goto" | "statement_gosub" => {
+                if let Some(dest) = stmt.named_child(1) {
+                    if dest.kind()!="integer" {
+                        self.ctx.error(&dest,"Applesoft needs a line number here");
+                    }
+                }
+                self.generic(stmt)
+            },
+            "statement_tab" => [tokens(&["HTAB"]),self.rest(stmt,1)].concat(),
+            "com_clr" => tokens(&["CLEAR"]),
+            "com_con" => tokens(&["CONT"]),
+            "assignment_str" => match stmt.named_child(0) {
+                Some(node) => self.assign_str(&node),
+                None => vec![]
+            },
+            k if UNSUPPORTED.contains(&k) => {
+                let mess = format!("{} has no Applesoft equivalent",self.ctx.keyword(&stmt.child(0).unwrap_or(*stmt)));
+                self.ctx.error(stmt,&mess);
+                vec![self.ctx.text(stmt)]
+            },
+            _ => self.generic(stmt)
+        }
+    }
+    /// translate all children starting with `skip`
+    fn rest(&mut self,node: &Node,skip: usize) -> Vec<String> {
+        let mut ans = Vec::new();
+        let mut curs = node.walk();
+        for child in node.children(&mut curs).skip(skip) {
+            ans.extend(self.node(&child));
+        }
+        ans
+    }
+    fn generic(&mut self,node: &Node) -> Vec<String> {
+        self.rest(node,0)
+    }
+    fn node(&mut self,node: &Node) -> Vec<String> {
+        match node.kind() {
+            "int_name" | "str_name" => vec![self.name(node)],
+            "string" => vec![self.ctx.text(node)],
+            "binary_aexpr" => self.binary(node),
+            "unary_aexpr" => match (node.child(0),node.child(1)) {
+                (Some(op),Some(arg)) if node.child_count()==2 => unary(self.ctx.keyword(&op),self.node(&arg)),
+                _ => self.generic(node)
+            },
+            "fcall" => self.fcall(node),
+            "str_slice" | "str_array" => self.slice(node),
+            "statement" => self.statement(node),
+            "assignment_str" => self.assign_str(node),
+            "op_aneq" | "op_sneq" => tokens(&["<>"]),
+            k if k.starts_with("statement_") || k.starts_with("com_") || k.starts_with("op_") || k.starts_with("fcall_") => vec![self.ctx.keyword(node)],
+            "integer" | "linenum" => vec![self.ctx.keyword(node)],
+            _ if node.child_count()==0 => vec![self.ctx.text(node)],
+            _ => self.generic(node)
+        }
+    }
+    fn name(&mut self,node: &Node) -> String {
+        let name = self.ctx.keyword(node);
+        let base = name.trim_end_matches('$');
+        let found: Vec<String> = self.keywords.iter().filter(|k| base.contains(k.as_str())).cloned().collect();
+        for kw in found {
+            let mess = format!("{} contains the Applesoft keyword {}",name,kw);
+            self.ctx.error(node,&mess);
+        }
+        let mut key: String = base.chars().take(2).collect();
+        if name.ends_with('$') {
+            key += "$";
+        }
+        match self.names.get(&key) {
+            Some(prev) if *prev!=name => {
+                let pair = (prev.clone(),name.clone());
+                if self.collisions.insert(pair) {
+                    let mess = format!("{} and {} are the same variable in Applesoft",prev,name);
+                    self.ctx.warn(node,&mess);
+                }
+            },
+            Some(_) => {},
+            None => {
+                self.names.insert(key,name.clone());
+            }
+        }
+        name
+    }
+    /// `MOD` and `/` are rewritten using `INT`, other operators are kept, except `#` becomes `<>`
+    fn binary(&mut self,node: &Node) -> Vec<String> {
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+        let mut op = None;
+        let mut curs = node.walk();
+        for child in node.children(&mut curs) {
+            if op.is_none() && child.kind().starts_with("op_") {
+                op = Some(child);
+                continue;
+            }
+            let toks = self.node(&child);
+            match op {
+                None => left.extend(toks),
+                Some(_) => right.extend(toks)
+            }
+        }
+        let op = match op {
+            Some(op) => op,
+            None => return left
+        };
+        match op.kind() {
+            "op_mod" => {
+                self.ctx.info(&op,"MOD is computed with INT, results differ for negative operands");
+                let (a,b) = (group(left),group(right));
+                [tokens(&["("]),a.clone(),tokens(&["-","INT","("]),a,tokens(&["/"]),b.clone(),tokens(&[")","*"]),b,tokens(&[")"])].concat()
+            },
+            "op_div" => {
+                self.ctx.info(&op,"division is computed with INT, results differ for negative quotients");
+                [tokens(&["INT","("]),left,tokens(&["/"]),group(right),tokens(&[")"])].concat()
+            },
+            _ => [left,self.node(&op),right].concat()
+        }
+    }
+    fn fcall(&mut self,node: &Node) -> Vec<String> {
+        match (lead(node),operands(node).get(1)) {
+            ("fcall_rnd",Some(arg)) => {
+                let n = self.node(arg);
+                [tokens(&["INT","(","RND","(","1",")","*"]),group(n),tokens(&[")"])].concat()
+            },
+            _ => self.generic(node)
+        }
+    }
+    /// `A$(i,j)` becomes `MID$(A$,i,j-i+1)` or `LEFT$(A$,j)`, and `A$(i)` becomes `MID$(A$,i)`
+    fn slice(&mut self,node: &Node) -> Vec<String> {
+        let ops = operands(node);
+        let name = match ops.first() {
+            Some(n) => self.name(n),
+            None => return self.generic(node)
+        };
+        let i = match ops.get(1) {
+            Some(n) => self.node(n),
+            None => return self.generic(node)
+        };
+        let j = match ops.get(2) {
+            Some(n) => self.node(n),
+            None => return [tokens(&["MID$","(",&name,","]),i,tokens(&[")"])].concat()
+        };
+        if literal(&i)==Some(1) {
+            return [tokens(&["LEFT$","(",&name,","]),j,tokens(&[")"])].concat();
+        }
+        let len = match (literal(&i),literal(&j)) {
+            (Some(a),Some(b)) => vec![(b-a+1).to_string()],
+            _ => [group(j),tokens(&["-"]),group(i.clone()),tokens(&["+","1"])].concat()
+        };
+        [tokens(&["MID$","(",&name,","]),i,tokens(&[","]),len,tokens(&[")"])].concat()
+    }
+    /// Assigning to `A$(i)` replaces everything from position `i` on
+    fn assign_str(&mut self,node: &Node) -> Vec<String> {
+        let ops = operands(node);
+        let (lhs,rhs) = match (ops.first(),ops.last()) {
+            (Some(l),Some(r)) if ops.len()==3 => (*l,*r),
+            _ => return self.generic(node)
+        };
+        let rhs = self.node(&rhs);
+        if lhs.kind()!="str_array" {
+            return [self.node(&lhs),tokens(&["="]),rhs].concat();
+        }
+        let sub = operands(&lhs);
+        let (name,idx) = match (sub.first(),sub.get(1)) {
+            (Some(n),Some(i)) => (self.name(n),self.node(i)),
+            _ => return self.generic(node)
+        };
+        if join(&idx).replace(' ',"")==format!("LEN({})+1",name) {
+            return [tokens(&[&name,"=",&name,"+"]),rhs].concat();
+        }
+        let keep = match literal(&idx) {
+            Some(1) => return [tokens(&[&name,"="]),rhs].concat(),
+            Some(k) => vec![(k-1).to_string()],
+            None => [group(idx),tokens(&["-","1"])].concat()
+        };
+        [tokens(&[&name,"=","LEFT$","(",&name,","]),keep,tokens(&[")","+"]),rhs].concat()
+    }
+    /// The prompt is followed by a semicolon rather than a comma
+    fn input(&mut self,stmt: &Node) -> Vec<String> {
+        let mut ans = tokens(&["INPUT"]);
+        let mut after_prompt = false;
+        let mut curs = stmt.walk();
+        for child in stmt.children(&mut curs).skip(1) {
+            match after_prompt && child.kind().starts_with("sep_") {
+                true => ans.push(";".to_string()),
+                false => ans.extend(self.node(&child))
+            }
+            after_prompt = child.kind()=="string";
+        }
+        ans
+    }
+    fn if_statement(&mut self,stmt: &Node) -> Vec<String> {
+        let (cond,then,conseq) = match (stmt.named_child(1),stmt.named_child(2),stmt.named_child(3)) {
+            (Some(c),Some(t),Some(s)) => (c,t,s),
+            _ => return self.generic(stmt)
+        };
+        if then.kind()=="statement_then_line" && conseq.kind()!="integer" {
+            self.ctx.error(&conseq,"Applesoft needs a line number here");
+        }
+        [tokens(&["IF"]),self.node(&cond),tokens(&["THEN"]),self.node(&conseq)].concat()
+    }
+    /// Strings need no dimension in Applesoft, so only integer arrays are kept
+    fn dim(&mut self,stmt: &Node) -> Vec<String> {
+        let mut items: Vec<Vec<Node>> = vec![vec![]];
+        let mut curs = stmt.walk();
+        for child in stmt.children(&mut curs).skip(1) {
+            match child.kind().starts_with("sep_dim") {
+                true => items.push(vec![]),
+                false => items.last_mut().unwrap().push(child)
+            }
+        }
+        let mut ans = Vec::new();
+        for item in items {
+            match item.first() {
+                Some(n) if n.kind()=="str_name" => {
+                    self.ctx.info(n,"string DIM is not needed in Applesoft");
+                },
+                Some(_) => {
+                    ans.push(match ans.is_empty() {
+                        true => "DIM".to_string(),
+                        false => ",".to_string()
+                    });
+                    for node in item {
+                        ans.extend(self.node(&node));
+                    }
+                },
+                None => {}
+            }
+        }
+        ans
+    }
+}
@@ -0,0 +1,535 @@
+//! Applesoft to Integer BASIC
+//!
+//! Numbers must be integers between -32767 and 32767, except that addresses given to `PEEK`, `POKE`,
+//! and `CALL` are wrapped into this range.  Variable names are shortened to the two characters
+//! Applesoft would have used.  Every string variable is dimensioned to 255 characters on a new line 0.
+//! Since Integer BASIC only makes the first statement after `THEN` conditional, an `IF` followed by
+//! more statements becomes a negated `IF` that jumps to the next line.
+
+use std::collections::{HashMap,BTreeSet};
+use tree_sitter::Node;
+use lsp_types as lsp;
+use super::{LineContext,Conversion,join,group,literal,tokens,unary};
+use crate::lang::server::basic_diag;
+use crate::DYNERR;
+
+/// Statements that are the same in both dialects, apart from details handled at the expression level
+const SAME: [&str;28] = [
+    "tok_goto","tok_gosub","tok_for","tok_next","tok_return","tok_end","tok_pop","tok_text","tok_gr",
+    "tok_call","tok_poke","tok_plot","tok_hlin","tok_vlin","tok_coloreq","tok_vtab","tok_trace","tok_notrace",
+    "tok_inn","tok_prn","tok_new","tok_run","tok_list","tok_del","tok_himem","tok_lomem","tok_load","tok_save"
+];
+
+/// Functions that are the same in both dialects
+const SAME_FCALL: [&str;7] = ["tok_abs","tok_sgn","tok_peek","tok_pdl","tok_len","tok_asc","tok_scrnp"];
+
+pub struct Converter {
+    parser: tree_sitter::Parser,
+    ctx: LineContext,
+    /// number of the next line, if any, this is where a failed `IF` goes
+    next_linenum: Option<usize>,
+    /// significant name of each numeric variable mapped to whether it was seen as real and as integer
+    numeric: HashMap<String,[bool;2]>,
+    /// string variables that need to be dimensioned
+    strings: BTreeSet<String>
+}
+
+fn leading_linenum(line: &str) -> Option<usize> {
+    let digits: String = line.trim_start().chars().take_while(|c| c.is_ascii_digit() || *c==' ').filter(|c| *c!=' ').collect();
+    digits.parse::<usize>().ok()
+}
+
+fn is_string(node: &Node) -> bool {
+    ["var_str","str","sfcall","binary_sexpr"].contains(&node.kind())
+}
+
+/// first child as a token kind, or empty string
+fn lead(node: &Node) -> &'static str {
+    match node.child(0) {
+        Some(child) => child.kind(),
+        None => ""
+    }
+}
+
+impl Converter {
+    pub fn new() -> Result<Self,DYNERR> {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_applesoft::language())?;
+        Ok(Self {
+            parser,
+            ctx: LineContext::new(),
+            next_linenum: None,
+            numeric: HashMap::new(),
+            strings: BTreeSet::new()
+        })
+    }
+    pub fn convert(&mut self,program: &str) -> Result<Conversion,DYNERR> {
+        let lines: Vec<&str> = program.lines().collect();
+        let nums: Vec<Option<usize>> = lines.iter().map(|l| leading_linenum(l)).collect();
+        let mut out = Vec::new();
+        for (row,line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            self.ctx.row = row as isize;
+            self.ctx.line = line.to_string() + "\n";
+            self.next_linenum = nums[row+1..].iter().flatten().next().copied();
+            let tree = match self.parser.parse(&self.ctx.line,None) {
+                Some(tree) => tree,
+                None => return Err(Box::new(crate::lang::Error::ParsingError))
+            };
+            let root = tree.root_node();
+            match root.named_child(0) {
+                Some(node) if node.kind()=="line" && !root.has_error() => out.push(self.line(&node)),
+                _ => {
+                    self.ctx.error(&root,"line could not be parsed as Applesoft");
+                    out.push(line.trim_end().to_string());
+                }
+            }
+        }
+        let mut diagnostics = std::mem::take(&mut self.ctx.diagnostics);
+        if !self.strings.is_empty() {
+            let dims: Vec<String> = self.strings.iter().map(|s| format!("{}(255)",s)).collect();
+            if nums.contains(&Some(0)) {
+                let mess = format!("line 0 is taken, strings have to be dimensioned by hand: {}",dims.join(","));
+                diagnostics.push(basic_diag(lsp::Range::default(),&mess,lsp::DiagnosticSeverity::ERROR));
+            } else {
+                out.insert(0,["0 DIM ",&dims.join(",")].concat());
+            }
+        }
+        Ok(Conversion {
+            program: out.join("\n") + "\n",
+            diagnostics
+        })
+    }
+    fn line(&mut self,line: &Node) -> String {
+        let mut ans = Vec::new();
+        let mut stmts = Vec::new();
+        let mut curs = line.walk();
+        for child in line.named_children(&mut curs) {
+            match child.kind() {
+                "linenum" => ans.push(self.ctx.keyword(&child)),
+                "statement" => stmts.push(child),
+                _ => {}
+            }
+        }
+        let body = self.statements(&stmts);
+        if body.is_empty() {
+            ans.push("REM".to_string());
+        }
+        ans.extend(body);
+        join(&ans)
+    }
+    fn statements(&mut self,list: &[Node]) -> Vec<String> {
+        let mut ans = Vec::new();
+        for (i,stmt) in list.iter().enumerate() {
+            let mut next = match lead(stmt) {
+                "tok_if" => self.if_statement(stmt,&list[i+1..]),
+                _ => self.statement(stmt)
+            };
+            if next.is_empty() {
+                continue;
+            }
+            if !ans.is_empty() {
+                ans.push(":".to_string());
+            }
+            ans.append(&mut next);
+            if lead(stmt)=="tok_if" {
+                break;
+            }
+        }
+        ans
+    }
+    fn if_statement(&mut self,stmt: &Node,rest: &[Node]) -> Vec<String> {
+        let (cond,conseq) = match (stmt.named_child(1),stmt.named_child(stmt.named_child_count()-1)) {
+            (Some(c),Some(s)) => (c,s),
+            _ => {
+                self.ctx.error(stmt,"incomplete IF");
+                return vec![self.ctx.text(stmt)];
+            }
+        };
+        let cond_toks = self.node(&cond);
+        let jump = match conseq.kind() {
+            "linenum" => Some(self.ctx.keyword(&conseq)),
+            "statement" if lead(&conseq)=="tok_goto" => conseq.named_child(1).map(|n| self.ctx.keyword(&n)),
+            _ => None
+        };
+        if let Some(num) = jump {
+            if !rest.is_empty() {
+                self.ctx.warn(&rest[0],"unreachable statements are dropped");
+            }
+            return [tokens(&["IF"]),cond_toks,tokens(&["THEN",&num])].concat();
+        }
+        if rest.is_empty() && lead(&conseq)!="tok_if" {
+            let then = self.statement(&conseq);
+            if !then.contains(&":".to_string()) {
+                return [tokens(&["IF"]),cond_toks,tokens(&["THEN"]),then].concat();
+            }
+        }
+        // Integer BASIC only makes one statement conditional, so skip the rest of the line instead
+        let skip = match self.next_linenum {
+            Some(num) => num.to_string(),
+            None => "END".to_string()
+        };
+        let mut list = vec![conseq];
+        list.extend_from_slice(rest);
+        [tokens(&["IF","NOT"]),group(cond_toks),tokens(&["THEN",&skip,":"]),self.statements(&list)].concat()
+    }
+    fn statement(&mut self,stmt: &Node) -> Vec<String> {
+        match lead(stmt) {
+            "assignment" => match stmt.named_child(0) {
+                Some(node) => self.assignment(&node),
+                None => vec![]
+            },
+            "tok_let" => match stmt.named_child(1) {
+                Some(node) => self.assignment(&node),
+                None => vec![]
+            },
+            "tok_print" => self.print(stmt),
+            "tok_input" => self.input(stmt),
+            "tok_dim" => self.dim(stmt),
+            "tok_rem" => {
+                let beg = stmt.child(0).map(|n| n.end_byte()).unwrap_or(0);
+                vec!["REM".to_string() + self.ctx.line[beg..].trim_end()]
+            },
+            "tok_home" => tokens(&["CALL","-936"]),
+            "tok_normal" => tokens(&["POKE","50",",","255"]),
+            "tok_inverse" => tokens(&["POKE","50",",","63"]),
+            "tok_flash" => tokens(&["POKE","50",",","127"]),
+            "tok_clear" => tokens(&["CLR"]),
+            "tok_cont" => tokens(&["CON"]),
+            "tok_stop" => {
+                self.ctx.warn(stmt,"STOP becomes END");
+                tokens(&["END"])
+            },
+            "tok_htab" => [tokens(&["TAB"]),self.rest(stmt,1)].concat(),
+            "tok_next" if stmt.named_child_count()==1 => {
+                self.ctx.error(stmt,"Integer BASIC NEXT needs the loop variable");
+                tokens(&["NEXT"])
+            },
+            k if SAME.contains(&k) => self.generic(stmt),
+            _ => {
+                let mess = format!("{} has no Integer BASIC equivalent",self.ctx.keyword(&stmt.child(0).unwrap_or(*stmt)));
+                self.ctx.error(stmt,&mess);
+                vec![self.ctx.text(stmt)]
+            }
+        }
+    }
+    /// translate all children starting with `skip`
+    fn rest(&mut self,node: &Node,skip: usize) -> Vec<String> {
+        let mut ans = Vec::new();
+        let mut curs = node.walk();
+        for child in node.children(&mut curs).skip(skip) {
+            match child.is_named() {
+                true => ans.extend(self.node(&child)),
+                false => ans.push(self.ctx.text(&child))
+            }
+        }
+        ans
+    }
+    fn generic(&mut self,node: &Node) -> Vec<String> {
+        self.rest(node,0)
+    }
+    fn node(&mut self,node: &Node) -> Vec<String> {
+        match node.kind() {
+            "var_real" | "var_int" | "var_str" => self.var(node),
+            "int" => self.int(node),
+            "real" => {
+                self.ctx.error(node,"Integer BASIC has no real numbers");
+                vec![self.ctx.text(node)]
+            },
+            "str" => vec![self.ctx.text(node)],
+            "unary_aexpr" => match (node.child(0),node.child(1)) {
+                (Some(op),Some(arg)) if node.child_count()==2 => unary(self.ctx.keyword(&op),self.node(&arg)),
+                _ => self.generic(node)
+            },
+            "linenum" => vec![self.ctx.keyword(node)],
+            "binary_aexpr" => self.binary(node),
+            "binary_sexpr" => {
+                self.ctx.error(node,"strings can only be joined by assigning them");
+                vec![self.ctx.text(node)]
+            },
+            "fcall" => self.fcall(node),
+            "sfcall" => self.sfcall(node),
+            "statement" => self.statement(node),
+            "tok_print" => tokens(&["PRINT"]),
+            k if k.starts_with("tok_") => vec![self.ctx.keyword(node)],
+            _ => self.generic(node)
+        }
+    }
+    fn name(&mut self,var: &Node) -> String {
+        let name = match var.named_child(0) {
+            Some(n) => self.ctx.keyword(&n),
+            None => return String::new()
+        };
+        let base = name.trim_end_matches(['$','%']);
+        let short: String = base.chars().take(2).collect();
+        match var.kind() {
+            "var_str" => {
+                let ans = short + "$";
+                self.strings.insert(ans.clone());
+                ans
+            },
+            kind => {
+                let seen = self.numeric.entry(short.clone()).or_insert([false,false]);
+                let idx = match kind {
+                    "var_int" => 1,
+                    _ => 0
+                };
+                if !seen[idx] && seen[1-idx] {
+                    let mess = format!("{} and {}% are the same variable in Integer BASIC",short,short);
+                    self.ctx.warn(var,&mess);
+                }
+                seen[idx] = true;
+                short
+            }
+        }
+    }
+    fn var(&mut self,var: &Node) -> Vec<String> {
+        let mut ans = vec![self.name(var)];
+        if let Some(sub) = var.named_child(1) {
+            if var.kind()=="var_str" {
+                self.ctx.error(var,"Integer BASIC has no string arrays");
+            }
+            if sub.named_child_count() > 1 {
+                self.ctx.error(&sub,"Integer BASIC arrays have one dimension");
+            }
+            ans.extend(self.generic(&sub));
+        }
+        ans
+    }
+    fn int(&mut self,node: &Node) -> Vec<String> {
+        let txt = self.ctx.keyword(node);
+        let val = match txt.parse::<i64>() {
+            Ok(v) => v,
+            Err(_) => return vec![txt]
+        };
+        if val <= 32767 {
+            return vec![txt];
+        }
+        let mut is_addr = false;
+        let mut maybe_parent = node.parent();
+        while let Some(parent) = maybe_parent {
+            if parent.kind()=="fcall" && lead(&parent)=="tok_peek" || parent.kind()=="statement" && ["tok_poke","tok_call"].contains(&lead(&parent)) {
+                is_addr = true;
+                break;
+            }
+            maybe_parent = parent.parent();
+        }
+        if is_addr && val < 65536 {
+            return vec![(val - 65536).to_string()];
+        }
+        self.ctx.error(node,"number is too large for Integer BASIC");
+        vec![txt]
+    }
+    fn binary(&mut self,node: &Node) -> Vec<String> {
+        let mut ans = Vec::new();
+        let mut op = String::new();
+        let mut has_string = false;
+        let mut curs = node.walk();
+        for child in node.children(&mut curs) {
+            if child.kind().starts_with("tok_") {
+                op += &self.ctx.keyword(&child);
+                continue;
+            }
+            if op=="/" {
+                self.ctx.info(node,"Integer BASIC division drops the remainder, results differ unless the quotient is whole");
+            }
+            if !op.is_empty() {
+                ans.push(match op.as_str() {
+                    "<>" | "><" => "#".to_string(),
+                    "=<" => "<=".to_string(),
+                    "=>" => ">=".to_string(),
+                    _ => op.clone()
+                });
+                op.clear();
+            }
+            has_string |= is_string(&child);
+            match child.is_named() {
+                true => ans.extend(self.node(&child)),
+                false => ans.push(self.ctx.text(&child))
+            }
+        }
+        if has_string && ans.iter().any(|t| ["<",">","<=",">="].contains(&t.as_str())) {
+            self.ctx.error(node,"Integer BASIC can only compare strings with = and #");
+        }
+        ans
+    }
+    /// If `node` is `RND(1)*N` or `N*RND(1)` return the tokens of N
+    fn rnd_pattern(&mut self,node: &Node) -> Option<Vec<String>> {
+        if node.kind()!="binary_aexpr" || node.named_child_count()!=3 {
+            return None;
+        }
+        let (a,op,b) = (node.named_child(0)?,node.named_child(1)?,node.named_child(2)?);
+        if op.kind()!="tok_times" {
+            return None;
+        }
+        let is_rnd = |n: &Node| n.kind()=="fcall" && lead(n)=="tok_rnd";
+        match (is_rnd(&a),is_rnd(&b)) {
+            (true,false) => Some(self.node(&b)),
+            (false,true) => Some(self.node(&a)),
+            _ => None
+        }
+    }
+    fn fcall(&mut self,node: &Node) -> Vec<String> {
+        match lead(node) {
+            "tok_int" => {
+                let arg = match node.named_child(1) {
+                    Some(a) => a,
+                    None => return self.generic(node)
+                };
+                if let Some(n) = self.rnd_pattern(&arg) {
+                    return [tokens(&["RND","("]),n,tokens(&[")"])].concat();
+                }
+                [tokens(&["("]),self.node(&arg),tokens(&[")"])].concat()
+            },
+            "tok_rnd" => {
+                self.ctx.error(node,"RND is real valued, use INT(RND(1)*N) to get RND(N)");
+                vec![self.ctx.text(node)]
+            },
+            k if SAME_FCALL.contains(&k) => self.generic(node),
+            _ => {
+                let mess = format!("{} has no Integer BASIC equivalent",self.ctx.keyword(&node.child(0).unwrap_or(*node)));
+                self.ctx.error(node,&mess);
+                vec![self.ctx.text(node)]
+            }
+        }
+    }
+    fn sfcall(&mut self,node: &Node) -> Vec<String> {
+        let kind = lead(node);
+        if !["tok_left","tok_right","tok_mid"].contains(&kind) {
+            let mess = format!("{} has no Integer BASIC equivalent",self.ctx.keyword(&node.child(0).unwrap_or(*node)));
+            self.ctx.error(node,&mess);
+            return vec![self.ctx.text(node)];
+        }
+        let mut args = Vec::new();
+        let mut curs = node.walk();
+        for child in node.named_children(&mut curs).skip(1) {
+            args.push(child);
+        }
+        let name = match args.first() {
+            Some(var) if var.kind()=="var_str" && var.named_child_count()==1 => self.name(var),
+            _ => {
+                self.ctx.error(node,"Integer BASIC can only take substrings of a string variable");
+                return vec![self.ctx.text(node)];
+            }
+        };
+        let a1 = match args.get(1) {
+            Some(n) => self.node(n),
+            None => return vec![self.ctx.text(node)]
+        };
+        let a2 = args.get(2).map(|n| self.node(n));
+        match (kind,a2) {
+            ("tok_left",_) => [tokens(&[&name,"(","1",","]),a1,tokens(&[")"])].concat(),
+            ("tok_right",_) => match literal(&a1) {
+                Some(1) => tokens(&[&name,"(","LEN","(",&name,")",")"]),
+                Some(k) => tokens(&[&name,"(","LEN","(",&name,")","-",&(k-1).to_string(),")"]),
+                None => [tokens(&[&name,"(","LEN","(",&name,")","-"]),group(a1),tokens(&["+","1",")"])].concat()
+            },
+            (_,None) => [tokens(&[&name,"("]),a1,tokens(&[")"])].concat(),
+            (_,Some(a2)) => {
+                let end = match (literal(&a1),literal(&a2)) {
+                    (Some(i),Some(n)) => vec![(i+n-1).to_string()],
+                    _ => [group(a1.clone()),tokens(&["+"]),group(a2),tokens(&["-","1"])].concat()
+                };
+                [tokens(&[&name,"("]),a1,tokens(&[","]),end,tokens(&[")"])].concat()
+            }
+        }
+    }
+    /// Integer BASIC cannot join strings in an expression, but it can append by assigning to
+    /// the position after the end, i.e., `A$(LEN(A$)+1)="X"`.
+    fn assignment(&mut self,node: &Node) -> Vec<String> {
+        let (lhs,rhs) = match (node.named_child(0),node.named_child(node.named_child_count()-1)) {
+            (Some(l),Some(r)) => (l,r),
+            _ => return self.generic(node)
+        };
+        if lhs.kind()!="var_str" || rhs.kind()!="binary_sexpr" {
+            return [self.node(&lhs),tokens(&["="]),self.node(&rhs)].concat();
+        }
+        let target = self.name(&lhs);
+        let mut terms = Vec::new();
+        let mut curr = rhs;
+        while curr.kind()=="binary_sexpr" && curr.named_child_count()==3 {
+            if let Some(b) = curr.named_child(2) {
+                terms.insert(0,b);
+            }
+            curr = match curr.named_child(0) {
+                Some(a) => a,
+                None => break
+            };
+        }
+        terms.insert(0,curr);
+        let mut ans = Vec::new();
+        for (i,term) in terms.iter().enumerate() {
+            let toks = self.node(term);
+            if i==0 && toks==vec![target.clone()] {
+                continue;
+            }
+            if i > 0 && toks.contains(&target) {
+                self.ctx.error(term,"string is changed before it is appended");
+            }
+            if !ans.is_empty() {
+                ans.push(":".to_string());
+            }
+            match i {
+                0 => ans.extend(tokens(&[&target,"="])),
+                _ => ans.extend(tokens(&[&target,"(","LEN","(",&target,")","+","1",")","="]))
+            }
+            ans.extend(toks);
+        }
+        ans
+    }
+    /// Items that follow each other without a separator get a semicolon
+    fn print(&mut self,stmt: &Node) -> Vec<String> {
+        let mut ans = tokens(&["PRINT"]);
+        let mut prev_item = false;
+        let mut curs = stmt.walk();
+        for child in stmt.children(&mut curs).skip(1) {
+            if !child.is_named() {
+                ans.push(self.ctx.text(&child));
+                prev_item = false;
+                continue;
+            }
+            if prev_item {
+                ans.push(";".to_string());
+            }
+            if child.kind()=="sfcall" && ["tok_tabp","tok_spcp"].contains(&lead(&child)) {
+                self.ctx.error(&child,"Integer BASIC cannot TAB or SPC within PRINT, use the TAB statement");
+                ans.push(self.ctx.text(&child));
+            } else {
+                ans.extend(self.node(&child));
+            }
+            prev_item = true;
+        }
+        ans
+    }
+    /// The prompt is followed by a comma rather than a semicolon
+    fn input(&mut self,stmt: &Node) -> Vec<String> {
+        let mut ans = tokens(&["INPUT"]);
+        let mut curs = stmt.walk();
+        let mut after_prompt = false;
+        for child in stmt.children(&mut curs).skip(1) {
+            match (child.is_named(),after_prompt) {
+                (false,true) => ans.push(",".to_string()),
+                (false,false) => ans.push(self.ctx.text(&child)),
+                (true,_) => ans.extend(self.node(&child))
+            }
+            after_prompt = child.kind()=="str";
+        }
+        ans
+    }
+    fn dim(&mut self,stmt: &Node) -> Vec<String> {
+        let mut ans = tokens(&["DIM"]);
+        let mut curs = stmt.walk();
+        for item in stmt.named_children(&mut curs).skip(1) {
+            if ans.len() > 1 {
+                ans.push(",".to_string());
+            }
+            match item.named_child(0) {
+                Some(var) => ans.extend(self.var(&var)),
+                None => ans.push(self.ctx.text(&item))
+            }
+        }
+        ans
+    }
+}
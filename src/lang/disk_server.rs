@@ -78,25 +78,27 @@ impl DiskServer {
                 }
                 return Ok(SelectionResult::Directory(filtered_cat));
             }
-            return match disk.get(path) {
-                Ok(fimg) => Ok(SelectionResult::FileData(SimpleFileImage {
-                    file_system: fimg.file_system.clone(),
-                    fs_type: fimg.fs_type.clone(),
-                    load_addr: fimg.get_load_address(),
-                    data: match fimg.unpack() {
-                        Ok(result) => match result {
-                            crate::fs::UnpackedData::Binary(dat) => dat,
-                            crate::fs::UnpackedData::Text(txt) => txt.as_bytes().to_vec(),
-                            _ => return Err(Box::new(CommandError::UnsupportedFormat))
-                        },
-                        Err(e) => return Err(e)
-                    }
-                })),
-                Err(e) => Err(e)
-            }
+            return Ok(SelectionResult::FileData(self.get(path)?));
         }
         return Err(Box::new(CommandError::InvalidCommand));
     }
+    /// Get a file from the mounted image as a "simplified file image"
+    pub fn get(&mut self,path: &str) -> Result<SimpleFileImage,DYNERR> {
+        if let Some(disk) = self.disk.as_mut() {
+            let fimg = disk.get(path)?;
+            return Ok(SimpleFileImage {
+                file_system: fimg.file_system.clone(),
+                fs_type: fimg.fs_type.clone(),
+                load_addr: fimg.get_load_address(),
+                data: match fimg.unpack()? {
+                    crate::fs::UnpackedData::Binary(dat) => dat,
+                    crate::fs::UnpackedData::Text(txt) => txt.as_bytes().to_vec(),
+                    _ => return Err(Box::new(CommandError::UnsupportedFormat))
+                }
+            });
+        }
+        Err(Box::new(CommandError::InvalidCommand))
+    }
     /// Extract path and white list from args and return an enumeration representing the outcome.
     /// If the selection is a directory, return its listing, if a file return a "simplified file image".
     /// Directory listings are generated using the `catalog_to_vec` trait method, so that the white list
@@ -0,0 +1,111 @@
+//! # Syntax highlighting
+//!
+//! Turns a listing into highlighted text using the semantic tokens that are sent to
+//! language clients, so the colors agree with what an editor shows.  ANSI output is meant
+//! for terminals, HTML output is a `pre` element with a `span` for each token, classed by
+//! token type, e.g. `<span class="a2kit-keyword">PRINT</span>`.  Styling the classes is left
+//! to the page.
+
+use lsp_types as lsp;
+use std::str::FromStr;
+use super::server::{Tokens,TOKEN_TYPES};
+use crate::DYNERR;
+
+/// Output format for highlighted listings
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Style {
+    Ansi,
+    Html
+}
+
+impl FromStr for Style {
+    type Err = crate::commands::CommandError;
+    fn from_str(s: &str) -> Result<Self,Self::Err> {
+        match s {
+            "ansi" => Ok(Self::Ansi),
+            "html" => Ok(Self::Html),
+            _ => Err(crate::commands::CommandError::InvalidCommand)
+        }
+    }
+}
+
+/// SGR parameters for a token type, types that are not colored give `None`
+fn ansi_code(typ: &str) -> Option<&'static str> {
+    match typ {
+        "comment" => Some("32"),
+        "string" => Some("33"),
+        "keyword" => Some("35"),
+        "number" => Some("36"),
+        "function" | "method" => Some("34"),
+        "macro" | "label" => Some("1"),
+        "regexp" => Some("31"),
+        _ => None
+    }
+}
+
+fn escape_html(txt: &str) -> String {
+    txt.replace('&',"&amp;").replace('<',"&lt;").replace('>',"&gt;")
+}
+
+fn plain(txt: &str,style: Style) -> String {
+    match style {
+        Style::Ansi => txt.to_string(),
+        Style::Html => escape_html(txt)
+    }
+}
+
+fn styled(txt: &str,typ: &str,style: Style) -> String {
+    match (style,ansi_code(typ)) {
+        (Style::Ansi,Some(code)) => format!("\x1b[{}m{}\x1b[0m",code,txt),
+        (Style::Ansi,None) => txt.to_string(),
+        (Style::Html,_) => format!("<span class=\"a2kit-{}\">{}</span>",typ,escape_html(txt))
+    }
+}
+
+/// Highlight `txt` using `tokens`, which must have been produced from the same `txt`.
+/// Tokens that do not fall on the text are ignored.
+pub fn highlight(txt: &str,tokens: &lsp::SemanticTokens,style: Style) -> String {
+    // decode the relative positions into (line,start,end,type) for each line
+    let lines: Vec<&str> = txt.lines().collect();
+    let mut spans: Vec<Vec<(usize,usize,&str)>> = vec![Vec::new();lines.len()];
+    let (mut row,mut col) = (0,0);
+    for tok in &tokens.data {
+        if tok.delta_line > 0 {
+            row += tok.delta_line as usize;
+            col = 0;
+        }
+        col += tok.delta_start as usize;
+        if let (Some(list),Some(typ)) = (spans.get_mut(row),TOKEN_TYPES.get(tok.token_type as usize)) {
+            list.push((col,col + tok.length as usize,typ));
+        }
+    }
+    let mut ans = String::new();
+    if style == Style::Html {
+        ans += "<pre class=\"a2kit\">";
+    }
+    for (line,list) in lines.iter().zip(spans) {
+        let mut ptr = 0;
+        for (beg,end,typ) in list {
+            match (line.get(ptr..beg),line.get(beg..end)) {
+                (Some(before),Some(tok)) => {
+                    ans += &plain(before,style);
+                    ans += &styled(tok,typ,style);
+                    ptr = end;
+                },
+                _ => log::debug!("skipping token at {}..{}",beg,end)
+            }
+        }
+        ans += &plain(&line[ptr..],style);
+        ans += "\n";
+    }
+    if style == Style::Html {
+        ans += "</pre>\n";
+    }
+    ans
+}
+
+/// Highlight `txt` using the semantic tokens from `provider`
+pub fn highlight_with(provider: &mut dyn Tokens,txt: &str,style: Style) -> Result<String,DYNERR> {
+    let tokens = provider.get(txt)?;
+    Ok(highlight(txt,&tokens,style))
+}
@@ -5,6 +5,7 @@ use tree_sitter_integerbasic;
 use lsp_types::{Range,Position,TextEdit};
 use crate::lang;
 use crate::lang::Navigate;
+use crate::lang::labels;
 #[allow(deprecated)]
 use crate::lang::linenum::{LabelInformation,Renumber,LineNumberTool};
 use std::collections::BTreeMap;
@@ -145,7 +146,11 @@ impl Renumberer {
     /// Renumber all lines with number >= beg && number < end, as [start,start+step,...].
     /// References are updated globally.
     /// This function assumes the existing numbering is valid.
+    /// If the source has labels they stay attached to the same lines.
     pub fn renumber(&mut self,source: &str, beg: usize, end: usize, first: usize, step: usize) -> Result<String,DYNERR> {
+        if labels::has_labels(source) {
+            return labels::renumber(self,source,beg,end,first,step,|r,s| r.renumber(s,beg,end,first,step));
+        }
         let all_primaries = match self.gather_defs(source,0) {
             Ok(result) => result,
             Err(_) => return Err(Box::new(lang::Error::LineNumber))
@@ -6,6 +6,7 @@ use tree_sitter;
 use tree_sitter_integerbasic;
 use crate::lang;
 use crate::lang::{Navigate,Navigation};
+use crate::lang::labels;
 use super::token_maps;
 use log::{warn,error};
 use crate::{STDRESULT,DYNERR};
@@ -138,8 +139,16 @@ impl Tokenizer
 		self.tokenized_line.push(1);
 		Ok(())
 	}
-	/// Tokenize a program contained in a UTF8 string, result is an array of bytes
+	/// Tokenize a program contained in a UTF8 string, result is an array of bytes.
+	/// Labels are resolved to line numbers.
 	pub fn tokenize(&mut self,program: String) -> Result<Vec<u8>,DYNERR> {
+		match labels::has_labels(&program) {
+			true => self.tokenize_numbered(labels::resolve(&program)?.0),
+			false => self.tokenize_numbered(program)
+		}
+	}
+	/// Tokenize a program whose labels, if any, have already been resolved, see `labels::resolve`.
+	pub fn tokenize_numbered(&mut self,program: String) -> Result<Vec<u8>,DYNERR> {
 		self.tokenized_program = Vec::new();
 		let mut parser = tree_sitter::Parser::new();
 		parser.set_language(&tree_sitter_integerbasic::language()).expect("error loading integer grammar");
@@ -0,0 +1,243 @@
+//! # Module for handling line labels
+//!
+//! BASIC source text can use symbolic labels in place of line numbers.
+//! A label is defined on a line by itself, e.g., `@loop:`, and attaches to the next numbered line.
+//! A label is referenced anywhere a line number could appear, e.g., `GOTO @loop`.
+//! Lines keep their numbers, labels are only aliases, so labeled and unlabeled
+//! lines can be mixed freely.
+//!
+//! The functions here convert between labeled and numbered source.
+//! The numbered source is what the parsers, tokenizers, and renumberers operate on.
+//! Any dialect that implements `linenum::Renumber` can use these.
+
+use std::collections::{BTreeMap,HashMap};
+use std::sync::LazyLock;
+use lsp_types::{TextEdit,Range,Position};
+use regex::Regex;
+use log::error;
+use crate::lang;
+use crate::lang::linenum::Renumber;
+use crate::DYNERR;
+
+static LABEL_DEF: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*@([A-Za-z_][A-Za-z0-9_]*):\s*$").expect(super::RCH));
+static LINE_NUM: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*([0-9][0-9 ]*)").expect(super::RCH));
+
+/// Extract the label name if the line is a label definition
+fn label_def(line: &str) -> Option<String> {
+    LABEL_DEF.captures(line).map(|caps| caps[1].to_string())
+}
+
+/// Line separator to use when rebuilding `source`, CRLF if any line of the source ends that way
+fn line_sep(source: &str) -> &'static str {
+    match source.contains("\r\n") {
+        true => "\r\n",
+        false => "\n"
+    }
+}
+
+/// Find label references in a line of numbered source, skipping strings and comments.
+/// Returns a vector of (byte offset,label name), not including the `@` sigil.
+fn label_refs(line: &str) -> Vec<(usize,String)> {
+    let mut ans = Vec::new();
+    let bytes = line.as_bytes();
+    let upper = bytes.to_ascii_uppercase();
+    let mut quoted = false;
+    let mut data = false;
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let c = bytes[idx];
+        let rest = &upper[idx..];
+        if c == b'"' {
+            quoted = !quoted;
+        } else if !quoted && data && c == b':' {
+            data = false;
+        } else if !quoted && !data && rest.starts_with(b"REM") {
+            break;
+        } else if !quoted && !data && rest.starts_with(b"DATA") {
+            data = true;
+        } else if !quoted && !data && c == b'@' {
+            let name: String = line[idx+1..].chars().take_while(|c| c.is_ascii_alphanumeric() || *c=='_').collect();
+            if !name.is_empty() {
+                let len = name.len();
+                ans.push((idx,name));
+                idx += len;
+            }
+        }
+        idx += 1;
+    }
+    ans
+}
+
+/// Determine if the source has any labels, either defined or referenced.
+pub fn has_labels(source: &str) -> bool {
+    if !source.contains('@') {
+        return false;
+    }
+    for line in source.lines() {
+        if label_def(line).is_some() || !label_refs(line).is_empty() {
+            return true;
+        }
+    }
+    false
+}
+
+/// Resolve labels to line numbers.  Returns the numbered source, and a map from labels to line numbers.
+/// Label definitions are removed, and label references are replaced with line numbers.
+/// Undefined, duplicated, or dangling labels produce an error.
+pub fn resolve(source: &str) -> Result<(String,HashMap<String,usize>),DYNERR> {
+    let mut map: HashMap<String,usize> = HashMap::new();
+    let mut pending: Vec<String> = Vec::new();
+    for line in source.lines() {
+        if let Some(label) = label_def(line) {
+            if map.contains_key(&label) || pending.contains(&label) {
+                error!("label @{} is defined more than once",label);
+                return Err(Box::new(lang::Error::LineNumber));
+            }
+            pending.push(label);
+            continue;
+        }
+        if let Some(caps) = LINE_NUM.captures(line) {
+            if let Ok(num) = caps[1].replace(" ","").parse::<usize>() {
+                for label in pending.drain(..) {
+                    map.insert(label,num);
+                }
+            }
+        }
+    }
+    if let Some(label) = pending.first() {
+        error!("label @{} is not followed by a numbered line",label);
+        return Err(Box::new(lang::Error::LineNumber));
+    }
+    let line_sep = line_sep(source);
+    let mut ans = String::new();
+    for line in source.lines() {
+        if label_def(line).is_some() {
+            continue;
+        }
+        let mut new_line = line.to_string();
+        for (offset,label) in label_refs(line).iter().rev() {
+            match map.get(label) {
+                Some(num) => new_line.replace_range(*offset..offset+1+label.len(),&num.to_string()),
+                None => {
+                    error!("label @{} is not defined",label);
+                    return Err(Box::new(lang::Error::LineNumber));
+                }
+            }
+        }
+        ans += &new_line;
+        ans += line_sep;
+    }
+    Ok((ans,map))
+}
+
+/// Apply labels to numbered source.  The map is from line numbers to labels, several labels can
+/// be attached to one line.  Each reference to a labeled line is replaced by the first label.
+/// The `tool` is any dialect's renumberer, which is used to find the line numbers.
+pub fn apply<R: Renumber>(tool: &mut R, source: &str, labels: &BTreeMap<usize,Vec<String>>) -> Result<String,DYNERR> {
+    let line_sep = line_sep(source);
+    let mut edits: Vec<TextEdit> = Vec::new();
+    for (num,info) in tool.gather_defs(source,0)? {
+        if let Some(names) = labels.get(&num) {
+            let start = Position::new(info[0].rng.start.line,0);
+            for name in names {
+                edits.push(TextEdit::new(Range::new(start,start),["@",name,":",line_sep].concat()));
+            }
+        }
+    }
+    for (num,info) in tool.gather_refs(source,0)? {
+        if let Some(names) = labels.get(&num) {
+            for item in info {
+                let mut txt = " ".repeat(item.leading_space);
+                txt += "@";
+                txt += &names[0];
+                txt += &" ".repeat(item.trailing_space);
+                edits.push(TextEdit::new(item.rng,txt));
+            }
+        }
+    }
+    lang::apply_edits(source,&edits,0)
+}
+
+/// Create labels of the form `@L<num>` for every line that is referenced,
+/// and apply them to the numbered source.
+pub fn label_targets<R: Renumber>(tool: &mut R, source: &str) -> Result<String,DYNERR> {
+    let primaries = tool.gather_defs(source,0)?;
+    let mut labels = BTreeMap::new();
+    for num in tool.gather_refs(source,0)?.keys() {
+        if primaries.contains_key(num) {
+            labels.insert(*num,vec![format!("L{}",num)]);
+        }
+    }
+    apply(tool,source,&labels)
+}
+
+/// Work out the mapping that `renumber` will perform, following the same selection rules,
+/// i.e., lines between the first row numbered >= `beg` and the last row numbered < `end`
+/// are numbered starting from `first` in steps of `step`.
+pub fn renumber_mapping<R: Renumber>(tool: &mut R, source: &str, beg: usize, end: usize, first: usize, step: usize) -> Result<BTreeMap<usize,usize>,DYNERR> {
+    let primaries = tool.gather_defs(source,0)?;
+    let [mut l0,mut ln] = [u32::MAX,0];
+    for (num,info) in &primaries {
+        if *num >= beg && l0 > info[0].rng.start.line {
+            l0 = info[0].rng.start.line;
+        }
+        if *num < end && ln < info[0].rng.start.line {
+            ln = info[0].rng.start.line;
+        }
+    }
+    if l0 > ln {
+        // renumber treats this as selecting everything
+        [l0,ln] = [0,u32::MAX];
+    }
+    let mut ans = BTreeMap::new();
+    let mut next = first;
+    for (num,info) in &primaries {
+        if info[0].rng.start.line >= l0 && info[0].rng.start.line <= ln {
+            ans.insert(*num,next);
+            next += step;
+        }
+    }
+    Ok(ans)
+}
+
+/// Renumber labeled source, the labels are kept attached to the same lines.
+/// The `renumber` closure should renumber numbered source with the given parameters.
+/// References to labeled lines will be expressed using the label.
+pub fn renumber<R,F>(tool: &mut R, source: &str, beg: usize, end: usize, first: usize, step: usize, renumber: F) -> Result<String,DYNERR>
+where R: Renumber, F: FnOnce(&mut R,&str) -> Result<String,DYNERR> {
+    let (numbered,label_map) = resolve(source)?;
+    let mapping = renumber_mapping(tool,&numbered,beg,end,first,step)?;
+    let renumbered = renumber(tool,&numbered)?;
+    let mut labels: BTreeMap<usize,Vec<String>> = BTreeMap::new();
+    // sort so that several labels on one line come out in a reproducible order
+    let mut sorted: Vec<(&String,&usize)> = label_map.iter().collect();
+    sorted.sort();
+    for (label,old) in sorted {
+        let new = match mapping.get(old) {
+            Some(n) => *n,
+            None => *old
+        };
+        labels.entry(new).or_default().push(label.to_string());
+    }
+    apply(tool,&renumbered,&labels)
+}
+
+#[test]
+fn test_label_refs() {
+    let refs = label_refs("10 GOTO @loop: PRINT \"@str\": REM @comment");
+    assert_eq!(refs,vec![(8,"loop".to_string())]);
+    let refs = label_refs("10 ON X GOSUB @a,@b2: DATA @x");
+    assert_eq!(refs,vec![(14,"a".to_string()),(17,"b2".to_string())]);
+}
+
+#[test]
+fn test_resolve() {
+    let (numbered,map) = resolve("@start:\n10 HOME\n@loop:\n20 PRINT \"X\";\n30 GOTO @loop\n").expect("resolve failed");
+    assert_eq!(numbered,"10 HOME\n20 PRINT \"X\";\n30 GOTO 20\n");
+    assert_eq!(map.get("start"),Some(&10));
+    assert_eq!(map.get("loop"),Some(&20));
+    assert!(resolve("10 GOTO @nowhere\n").is_err());
+    assert!(resolve("10 HOME\n@dangling:\n").is_err());
+    let (numbered,_) = resolve("@a:\r\n10 HOME\r\n20 GOTO @a").expect("resolve failed");
+    assert_eq!(numbered,"10 HOME\r\n20 GOTO 10\r\n");
+}
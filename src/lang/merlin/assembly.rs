@@ -1,7 +1,8 @@
 //! # Assembler for any Merlin version.
 //! 
-//! Currently we provide only "spot assembly," which is useful for disassembly workflows that
-//! involve conversion of code to data and vice-versa.
+//! There are two modes of operation, "spot assembly" and "full assembly."
+//! Spot assembly is useful for disassembly workflows that involve conversion of code to data and vice-versa.
+//! Full assembly processes a whole source file in two passes, see `assemble`.
 //! 
 //! The spot assembler is designed to work under conditions where the program counter and/or
 //! symbol information are not necessarily known, i.e., it will proceed as far as it can
@@ -32,6 +33,18 @@
 //! 
 //! If symbol values are available, the spot-assembler will use them.  It does not calculate symbol values, but it does
 //! check them against the program counter.
+//! 
+//! ## Full Assembly
+//! 
+//! The full assembler calculates symbol values in a first pass and generates code in a second pass.
+//! In addition to what the spot assembler handles, it handles macros (MAC, EOM, PMC, and implicit calls), including
+//! nested calls and the variables `]0` through `]8`, loops (LUP, --^), conditionals (DO, IF, ELSE, FIN),
+//! assignments (EQU, VAR), dummy sections (DUM, DEND), and CHK, ERR, END.
+//! Macros that were defined in a USE file can be expanded if they are in the shared symbols.
+//! Includes (PUT) are not handled.
+//!
+//! If the source begins with REL, the output is a relocatable module, and EXT, EXD, and ENT
+//! are handled.  Relocatable modules can be combined using `rel::link`.
 
 use std::sync::Arc;
 use super::settings::Settings;
@@ -43,6 +56,9 @@ use crate::lang::merlin::{Operation,ProcessorType};
 use crate::lang::{node_radix, node_text, Navigation, Navigate};
 use crate::{STDRESULT,DYNERR};
 
+mod expand;
+pub mod rel;
+
 const IGNORED_PSOPS: [&str;17] = ["ast", "cas", "cyc", "dsk", "exp", "kbd", "lst", "lstdo", "obj", "pag", "pau", "sav", "skp", "tr", "ttl", "typ", "xc"];
 
 /// closely parallels Merlin 8/16 error messages
@@ -101,11 +117,44 @@ pub enum Error {
     #[error("label value could not be resolved")]
     UnresolvedValue,
     #[error("unsupported (pseudo) operations or arguments")]
-    CannotAssemble
+    CannotAssemble,
+    #[error("forced error")]
+    Forced,
+    #[error("unbalanced conditional or loop")]
+    Unbalanced
+}
+
+/// How an operand has to be treated when a relocatable module is linked
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum Reloc {
+    Abs,
+    /// relocatable, with the low byte of the value
+    Rel(u8),
+    /// external reference number, and whether it is on the direct page
+    Ext(u8,bool)
+}
+
+/// Size and address mode of an instruction, see `Assembler::spot_measure`
+#[derive(Clone)]
+pub struct Measurement {
+    pub row: isize,
+    pub bytes: usize,
+    /// lower case mnemonic of the operation
+    pub mnemonic: String,
+    pub mode: super::AddressMode,
+    /// state of the M and X bits when the instruction was assembled
+    pub m8bit: bool,
+    pub x8bit: bool
 }
 
 /// Evaluate any expression starting on an arg node (assuming it wraps an expression), or an expression node.
 pub fn eval_expr(start_node: &tree_sitter::Node, source: &str, pc: Option<usize>, symbols: &Symbols, scope: Option<&Symbol>) -> Result<i64,DYNERR> {
+    eval_expr_or(start_node, source, pc, symbols, scope, None, 0)
+}
+
+/// Same as `eval_expr`, except that if `unresolved` is not `None` it is used as the value of any label that cannot be resolved.
+/// The `shift` is added to the program counter and to relocatable labels.
+fn eval_expr_or(start_node: &tree_sitter::Node, source: &str, pc: Option<usize>, symbols: &Symbols, scope: Option<&Symbol>, unresolved: Option<i64>, shift: i64) -> Result<i64,DYNERR> {
     let node = match start_node.kind().starts_with("arg_") {
         true => match start_node.named_child(0) {
             Some(child) => child,
@@ -130,7 +179,7 @@ pub fn eval_expr(start_node: &tree_sitter::Node, source: &str, pc: Option<usize>
         },
         "current_addr" => {
             match pc {
-                Some(pc) => Ok(pc as i64),
+                Some(pc) => Ok(pc as i64 + shift),
                 None => Err(Box::new(Error::UnresolvedProgramCounter))
             }
         },
@@ -139,13 +188,13 @@ pub fn eval_expr(start_node: &tree_sitter::Node, source: &str, pc: Option<usize>
             if let Some(scope) = scope {
                 if let Some(child) = scope.children.get(&txt) {
                     if let Some(val) = child.value {
-                        return Ok(val);
+                        return Ok(val + rel_shift(child, shift));
                     }
                 }
             }
             if let Some(sym) = symbols.globals.get(&txt) {
                 if let Some(val) = sym.value {
-                    return Ok(val);
+                    return Ok(val + rel_shift(sym, shift));
                 }
             }
             if let Some(sym) = symbols.vars.get(&txt) {
@@ -153,13 +202,16 @@ pub fn eval_expr(start_node: &tree_sitter::Node, source: &str, pc: Option<usize>
                     return Ok(val);
                 }
             }
-            Err(Box::new(Error::UnresolvedValue))
+            match unresolved {
+                Some(val) => Ok(val),
+                None => Err(Box::new(Error::UnresolvedValue))
+            }
         },
         "unary_aexpr" => {
             if node.child_count() != 2 {
                 Err(Box::new(Error::Syntax))
             } else {
-                let raw = eval_expr(&node.named_child(0).unwrap(), source, pc, symbols, scope)?;
+                let raw = eval_expr_or(&node.named_child(0).unwrap(), source, pc, symbols, scope, unresolved, shift)?;
                 if node.named_child(1).unwrap().kind() == "eop_minus" {
                     Ok(-raw)
                 } else {
@@ -171,8 +223,8 @@ pub fn eval_expr(start_node: &tree_sitter::Node, source: &str, pc: Option<usize>
             if node.child_count() != 3 {
                 Err(Box::new(Error::Syntax))
             } else {
-                let val1 = eval_expr(&node.named_child(0).unwrap(), source, pc, symbols, scope)?;
-                let val2 = eval_expr(&node.named_child(2).unwrap(), source, pc, symbols, scope)?;
+                let val1 = eval_expr_or(&node.named_child(0).unwrap(), source, pc, symbols, scope, unresolved, shift)?;
+                let val2 = eval_expr_or(&node.named_child(2).unwrap(), source, pc, symbols, scope, unresolved, shift)?;
                 match node.named_child(1).unwrap().kind() {
                     "eop_plus" => Ok(val1 + val2),
                     "eop_minus" => Ok(val1 - val2),
@@ -191,7 +243,7 @@ pub fn eval_expr(start_node: &tree_sitter::Node, source: &str, pc: Option<usize>
         },
         "braced_aexpr" => {
             if let Some(child) = node.named_child(0) {
-                eval_expr(&child, source, pc, symbols, scope)
+                eval_expr_or(&child, source, pc, symbols, scope, unresolved, shift)
             } else {
                 Err(Box::new(Error::Syntax))
             }
@@ -200,6 +252,33 @@ pub fn eval_expr(start_node: &tree_sitter::Node, source: &str, pc: Option<usize>
     }
 }
 
+fn rel_shift(sym: &Symbol, shift: i64) -> i64 {
+    match sym.flags & super::symbol_flags::REL {
+        0 => 0,
+        _ => shift
+    }
+}
+
+/// Reference numbers of the external labels in an expression
+fn externals(node: &tree_sitter::Node, source: &str, symbols: &Symbols, module: &rel::RelModule) -> Vec<(u8,bool)> {
+    let mut ans = Vec::new();
+    if node.kind() == "label_ref" {
+        let txt = node_text(node, source);
+        if let Some(num) = module.ext_number(&txt) {
+            let direct = module.esd.iter().any(|e| e.name == txt && e.flags & rel::esd_flags::DIRECT > 0);
+            if symbols.globals.get(&txt).is_some_and(|sym| sym.flags & super::symbol_flags::EXT > 0) {
+                ans.push((num,direct));
+            }
+        }
+        return ans;
+    }
+    let mut curs = node.walk();
+    for child in node.named_children(&mut curs) {
+        ans.append(&mut externals(&child, source, symbols, module));
+    }
+    ans
+}
+
 /// Evaluate IF argument which could parse as (arg_if (if_char (...))) or (arg_if (if_mx (...))).
 /// If it is the MX variety, the expression will need to be reparsed with MX inserted.
 /// N.b. it is not correct precedence-wise to multiply MX by the already-parsed expression.
@@ -231,7 +310,23 @@ pub struct Assembler
     line: String,
     m8bit: bool,
     x8bit: bool,
-    pc: Option<usize>
+    pc: Option<usize>,
+    /// 0 for spot assembly, otherwise the pass of the full assembly
+    pass: usize,
+    /// scope used to resolve local and macro local labels during full assembly
+    scope: Option<Symbol>,
+    /// set if a forward reference was assumed during the first pass
+    fwd_ref: std::cell::Cell<bool>,
+    /// force absolute addressing because the first pass assumed it
+    force_abs: bool,
+    /// relocatable module started by REL, the code is added at the end
+    rel: Option<rel::RelModule>,
+    /// relocation of the most recently evaluated expression
+    reloc: std::cell::Cell<Reloc>,
+    /// the most recently selected instruction, with `row` and `bytes` not yet known
+    measurement: Option<Measurement>,
+    /// SWEET16 region of spot assembly, full assembly uses the region of its parser
+    sweet16: super::handbook::sweet16::Region
 }
 
 impl Assembler {
@@ -250,7 +345,15 @@ impl Assembler {
             line: String::new(),
             m8bit: true,
             x8bit: true,
-            pc: None
+            pc: None,
+            pass: 0,
+            scope: None,
+            fwd_ref: std::cell::Cell::new(false),
+            force_abs: false,
+            rel: None,
+            reloc: std::cell::Cell::new(Reloc::Abs),
+            measurement: None,
+            sweet16: super::handbook::sweet16::Region::default()
         }
     }
     pub fn set_config(&mut self,config: Settings) {
@@ -339,6 +442,12 @@ impl Assembler {
         let mut curs = arg.walk();
         for data in arg.named_children(&mut curs) {
             let val = self.eval_data(&data, byte_count, big_endian)?;
+            let shift = match data.named_child_count() {
+                2 => Self::prefix_shift(&node_text(&data.named_child(0).unwrap(),&self.line)),
+                _ => 0
+            };
+            let offset = self.code.len();
+            self.push_relocation(offset, byte_count, shift, big_endian)?;
             self.push_data(val,byte_count,big_endian);
         }
         Ok(())
@@ -363,8 +472,8 @@ impl Assembler {
         // Modify `beg..end` based on operand suffix, forcing absolute or long addressing.
         // This must precede the prefix handling.
         let suffix = match op_node.named_child(0) {
-            Some(s) => node_text(&s,&self.line),
-            None => "".to_string()
+            Some(s) if op_node.kind().starts_with("op_") => node_text(&s,&self.line),
+            _ => "".to_string()
         };
         if end == 1 {
             if self.symbols.assembler==MerlinVersion::Merlin8 {
@@ -382,6 +491,11 @@ impl Assembler {
                 end = 3;
             }
         }
+        let reloc = self.reloc.get();
+        let fwd_ref = self.pass == 1 && self.fwd_ref.get();
+        if end == 1 && (self.force_abs || fwd_ref || matches!(reloc,Reloc::Rel(_)) || matches!(reloc,Reloc::Ext(_,false))) {
+            end = 2;
+        }
 
         // Modify `beg..end` based on prefix modifiers and special cases.
         let is16bit = !self.x8bit && op.x_sensitive || !self.m8bit && op.m_sensitive;
@@ -431,6 +545,7 @@ impl Assembler {
             Some(m) => m,
             None => return Err(Box::new(Error::BadAddressMode))
         };
+        self.note_mode(op_node, &mode);
 
         // We can now write the opcode
         self.code.push(mode.code as u8);
@@ -443,6 +558,9 @@ impl Assembler {
                 false => (0,2)
             };
             abs_addr += (end-1) * 0x10000 * val_bytes[2] as usize;
+            if matches!(reloc,Reloc::Ext(_,_)) {
+                return Err(Box::new(Error::IllegalRelAddr));
+            }
             if let Some(pc) = self.pc {
                 let rel = match OperationHandbook::abs_to_rel(pc, abs_addr, end-beg) {
                     Some(x) => x,
@@ -452,6 +570,9 @@ impl Assembler {
             } else {
                 return Err(Box::new(Error::UnresolvedProgramCounter));
             }
+        } else {
+            let offset = self.code.len();
+            self.push_relocation(offset, end-beg, beg, false)?;
         }
 
         // We can now write the operand bytes
@@ -464,9 +585,65 @@ impl Assembler {
         }
         Ok(())
 	}
+	/// During the first pass of full assembly, unresolved labels are assumed to be forward references,
+	/// whose value is provisionally the program counter.
+	/// In a relocatable module the relocation of the expression is also worked out.
 	fn eval_expr(&self, node: &tree_sitter::Node, source: &str) -> Result<i64,DYNERR> {
-        eval_expr(node, source, self.pc, &self.symbols, None)
+        self.reloc.set(Reloc::Abs);
+        let val = match eval_expr(node, source, self.pc, &self.symbols, self.scope.as_ref()) {
+            Err(e) if self.pass==1 && matches!(e.downcast_ref::<Error>(),Some(Error::UnresolvedValue)) => {
+                self.fwd_ref.set(true);
+                return eval_expr_or(node, source, self.pc, &self.symbols, self.scope.as_ref(), Some(self.pc.unwrap_or(0x8000) as i64), 0);
+            },
+            result => result?
+        };
+        if let Some(module) = self.rel.as_ref() {
+            self.reloc.set(self.relocation(node, source, val, module)?);
+        }
+        Ok(val)
 	}
+    /// Work out the relocation by shifting the module and seeing how the value changes.
+    /// Only a relocatable label plus or minus a constant, or an external label plus or minus a constant, can be linked.
+    fn relocation(&self, node: &tree_sitter::Node, source: &str, val: i64, module: &rel::RelModule) -> Result<Reloc,DYNERR> {
+        const SHIFT: i64 = 0x1000;
+        let ext = externals(node, source, &self.symbols, module);
+        let shifted = eval_expr_or(node, source, self.pc, &self.symbols, self.scope.as_ref(), None, SHIFT)?;
+        let ans = match (ext.len(),shifted - val) {
+            (0,0) => Reloc::Abs,
+            (0,SHIFT) => Reloc::Rel(val as u8),
+            (1,0) => Reloc::Ext(ext[0].0,ext[0].1),
+            (0|1,_) => return Err(Box::new(Error::IllegalRelAddr)),
+            _ => return Err(Box::new(Error::TwoExt))
+        };
+        Ok(ans)
+    }
+    /// Add an RLD entry for a field about to be written at `offset`, if the most recent expression needs one.
+    /// The field is `size` bytes of the value starting at byte `shift`.
+    fn push_relocation(&mut self, offset: usize, size: usize, shift: usize, big_endian: bool) -> STDRESULT {
+        use rel::rld_flags;
+        let reloc = self.reloc.get();
+        let module = match self.rel.as_mut() {
+            Some(m) if self.pass == 2 && reloc != Reloc::Abs => m,
+            _ => return Ok(())
+        };
+        let mut flags = match (size,shift,big_endian) {
+            (2,0,false) => rld_flags::TWO_BYTE,
+            (2,0,true) => rld_flags::TWO_BYTE | rld_flags::REVERSED,
+            (1,0,_) => 0,
+            (1,1,_) => rld_flags::HIGH_BYTE,
+            _ => return Err(Box::new(Error::IllegalRelAddr))
+        } | rld_flags::NOT_END;
+        let aux = match reloc {
+            Reloc::Ext(num,_) => {
+                flags |= rld_flags::EXTERNAL;
+                num
+            },
+            Reloc::Rel(low) if flags & rld_flags::HIGH_BYTE > 0 => low,
+            _ => 0
+        };
+        module.rld.push(rel::RldEntry { flags, offset: u16::try_from(offset)?, aux });
+        Ok(())
+    }
     /// Used to evaluate the several string-like pseudo-ops.  Within the dstring segments,
     /// the bytes are subjected to 4 transformations in order: clos, signed, dci, reverse.
     /// Hex segments are handled according to Merlin 16 rules.  The `node` should be the `arg_` node.
@@ -521,6 +698,116 @@ impl Assembler {
         self.code.append(&mut ans);
         Ok(())
     }
+    /// Assemble an operation given its node, which is normally `op_*`, but could be `macro_ref` for an undocumented operation.
+    /// Returns `None` if there is no operand and the operation has no implied mode.
+    fn visit_operation(&mut self, op: Operation, op_node: &tree_sitter::Node) -> Result<Option<Navigation>,DYNERR> {
+        match op_node.next_named_sibling() {
+            Some(nxt) => {
+                if !nxt.kind().starts_with("arg_") {
+                    return Err(Box::new(Error::Syntax));
+                }
+                if let Some(mode_node) = nxt.named_child(0) {
+                    if mode_node.kind() == "xyc" {
+                        self.note_mode(op_node, &op.modes[0]);
+                        self.code.push(op.modes[0].code as u8);
+                        self.push_data_list(&mode_node, 1, false)?;
+                        if self.code.len() > 2 {
+                            let dst = self.code.pop().unwrap();
+                            let src = self.code.pop().unwrap();
+                            self.code.push(dst);
+                            self.code.push(src);
+                            if let Some(pc) = self.pc.as_mut() {
+                                *pc += 3;
+                            }
+                            return Ok(Some(Navigation::Exit));
+                        }
+                        return Err(Box::new(Error::Syntax));
+                    }
+                    let (prefix,expr) = match (mode_node.named_child(0),mode_node.named_child(1)) {
+                        (Some(n1),None) => (None,n1),
+                        (Some(n1),Some(n2)) if n2.kind() == "mode" => (None,n1),
+                        (Some(n1),Some(n2)) => (Some(n1),n2),
+                        _ => return Err(Box::new(Error::Syntax))
+                    };
+                    let prefix_txt = match prefix {
+                        Some(n) => node_text(&n,&self.line),
+                        None => "".to_string()
+                    };
+                    match self.eval_expr(&expr,&self.line) {
+                        Ok(val) => {
+                            self.push_instruction(&op, op_node,&mode_node, val as u32, &prefix_txt)?;
+                            return Ok(Some(Navigation::Exit));
+                        },
+                        Err(e) => return Err(e)
+                    }
+                } else {
+                    return Err(Box::new(Error::Syntax));
+                }
+            },
+            None => {
+                for mode in &op.modes {
+                    if ["accum","impl","s"].contains(&mode.mnemonic.as_str()) {
+                        self.note_mode(op_node, mode);
+                        self.code.push(mode.code as u8);
+                        if let Some(pc) = self.pc.as_mut() {
+                            *pc += 1;
+                        }
+                        return Ok(Some(Navigation::Exit));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+    /// Remember the address mode that was selected for an operation, for the sake of `spot_measure`
+    fn note_mode(&mut self, op_node: &tree_sitter::Node, mode: &super::AddressMode) {
+        let mnemonic = match op_node.kind().strip_prefix("op_") {
+            Some(m) => m.to_string(),
+            None => node_text(op_node, &self.line).to_lowercase()
+        };
+        self.measurement = Some(Measurement {
+            row: self.row,
+            bytes: 0,
+            mnemonic,
+            mode: mode.clone(),
+            m8bit: self.m8bit,
+            x8bit: self.x8bit
+        });
+    }
+    /// If `node` is a macro call, or macro reference, that names an enabled undocumented operation, get the operation.
+    fn undocumented_op(&self, node: &tree_sitter::Node) -> Option<Operation> {
+        if !self.symbols.undocumented_enabled() {
+            return None;
+        }
+        let mut curs = node.walk();
+        let macro_ref = match node.kind() {
+            "macro_ref" => *node,
+            "macro_call" => node.named_children(&mut curs).find(|n| n.kind() == "macro_ref")?,
+            _ => return None
+        };
+        self.op_handbook.get_undocumented(&node_text(&macro_ref, &self.line))
+    }
+    /// If the line was SWEET16, replace the first byte of its carrier, which starts at `offset`, see `handbook::sweet16`.
+    fn patch_sweet16(&mut self, code: Option<u8>, offset: usize) {
+        if let Some(code) = code {
+            if offset < self.code.len() {
+                self.code[offset] = code;
+            }
+        }
+    }
+    /// Adjust a line for spot assembly, translating it if it is SWEET16, returns the SWEET16 opcode if there is one
+    fn spot_adjust(&mut self, line: &str) -> Option<u8> {
+        match self.symbols.sweet16_line(line, &mut self.sweet16) {
+            Some((carrier,code)) => {
+                self.line = carrier + "\n";
+                Some(code)
+            },
+            None => {
+                self.line = self.symbols.adjust_line(self.row, line, "\n");
+                None
+            }
+        }
+    }
     /// Assign values to labels with textual form `_XXXX...`, where X is a hex digit.
     /// The text is assumed to give the value, as is the case after disassembly.
     /// The returned symbols are generally turned into a new Arc pointer that is fed into the assembler.
@@ -543,8 +830,10 @@ impl Assembler {
         self.pc = pc;
         self.code = Vec::new();
 		self.row = 0;
+        self.sweet16 = super::handbook::sweet16::Region::default();
 		for line in txt.lines() {
             if self.row < beg {
+                self.symbols.sweet16_line(line, &mut self.sweet16);
                 self.row += 1;
                 continue;
             }
@@ -552,23 +841,67 @@ impl Assembler {
                 break;
             }
 			self.col = 0;
-			self.line = self.symbols.adjust_line(self.row, line, "\n");
+			let sweet16 = self.spot_adjust(line);
 			if self.line.starts_with(super::CALL_TOK) {
 				// ASSUMPTION is col will be a byte offset and LSP position encoding is utf-16
 				self.col = -2*(super::CALL_TOK.len_utf16() as isize);
 			}
 			if let Some(tree) = self.parser.parse(&self.line,None) {
+				let code_len = self.code.len();
 				self.walk(&tree)?;
+				self.patch_sweet16(sweet16, code_len);
 			}
 			self.row += 1;
 		}
         Ok(self.code.clone())
     }
+    /// Measure the instructions in rows `beg..end`, e.g., for inlay hints.
+    /// This works like the spot assembler, except that the program counter is forgotten on each line,
+    /// labels that cannot be resolved are assumed to be absolute addresses, and lines that cannot be
+    /// assembled are skipped.  The lines before `beg` are processed in order to track the MX state.
+    pub fn spot_measure(&mut self, txt: &str, beg: isize, end: isize) -> Vec<Measurement> {
+        let mut ans = Vec::new();
+        self.pass = 1;
+        self.row = 0;
+        self.sweet16 = super::handbook::sweet16::Region::default();
+        for line in txt.lines() {
+            if self.row >= end {
+                break;
+            }
+            self.pc = None;
+            self.code = Vec::new();
+            self.measurement = None;
+            self.col = 0;
+            self.spot_adjust(line);
+            if self.line.starts_with(super::CALL_TOK) {
+                self.col = -2*(super::CALL_TOK.len_utf16() as isize);
+            }
+            if let Some(tree) = self.parser.parse(&self.line,None) {
+                let result = self.walk(&tree);
+                if let (true,Some(mut measurement)) = (self.row >= beg,self.measurement.take()) {
+                    // a branch fails once the mode is known, since there is no program counter
+                    measurement.bytes = match (result,measurement.mode.mnemonic.as_str()) {
+                        (_,"rel") => 2,
+                        (_,"rell") => 3,
+                        (Ok(()),_) => self.code.len(),
+                        _ => 0
+                    };
+                    if measurement.bytes > 0 {
+                        ans.push(measurement);
+                    }
+                }
+            }
+            self.row += 1;
+        }
+        self.pass = 0;
+        self.fwd_ref.set(false);
+        ans
+    }
 }
 
 impl Navigate for Assembler {
     fn visit(&mut self,curs: &tree_sitter::TreeCursor) -> Result<Navigation,DYNERR> {
-		if curs.node().kind() == "macro_call" {
+		if curs.node().kind() == "macro_call" && self.undocumented_op(&curs.node()).is_none() {
             log::error!("macro calls are not supported");
 			return Err(Box::new(Error::CannotAssemble));
 		}
@@ -603,63 +936,21 @@ impl Navigate for Assembler {
 		if curs.node().kind().starts_with("op_") {
             let txt = node_text(&curs.node(), &self.line);
             if let Some(op) = self.op_handbook.get(&txt) {
-                match curs.node().next_named_sibling() {
-                    Some(nxt) => {
-                        if !nxt.kind().starts_with("arg_") {
-                            return Err(Box::new(Error::Syntax));
-                        }
-                        if let Some(mode_node) = nxt.named_child(0) {
-                            if mode_node.kind() == "xyc" {
-                                self.code.push(op.modes[0].code as u8);
-                                self.push_data_list(&mode_node, 1, false)?;
-                                if self.code.len() > 2 {
-                                    let dst = self.code.pop().unwrap();
-                                    let src = self.code.pop().unwrap();
-                                    self.code.push(dst);
-                                    self.code.push(src);
-                                    if let Some(pc) = self.pc.as_mut() {
-                                        *pc += 3;
-                                    }
-                                    return Ok(Navigation::Exit);
-                                }
-                                return Err(Box::new(Error::Syntax));
-                            }
-                            let (prefix,expr) = match (mode_node.named_child(0),mode_node.named_child(1)) {
-                                (Some(n1),None) => (None,n1),
-                                (Some(n1),Some(n2)) if n2.kind() == "mode" => (None,n1),
-                                (Some(n1),Some(n2)) => (Some(n1),n2),
-                                _ => return Err(Box::new(Error::Syntax))
-                            };
-                            let prefix_txt = match prefix {
-                                Some(n) => node_text(&n,&self.line),
-                                None => "".to_string()
-                            };
-                            match self.eval_expr(&expr,&self.line) {
-                                Ok(val) => {
-                                    self.push_instruction(&op, &curs.node(),&mode_node, val as u32, &prefix_txt)?;
-                                    return Ok(Navigation::Exit);
-                                },
-                                Err(e) => return Err(e)
-                            }
-                        } else {
-                            return Err(Box::new(Error::Syntax));
-                        }
-                    },
-                    None => {
-                        for mode in op.modes {
-                            if ["accum","impl","s"].contains(&mode.mnemonic.as_str()) {
-                                self.code.push(mode.code as u8);
-                                if let Some(pc) = self.pc.as_mut() {
-                                    *pc += 1;
-                                }
-                                return Ok(Navigation::Exit);
-                            }
-                        }
-                    }
+                if let Some(nav) = self.visit_operation(op, &curs.node())? {
+                    return Ok(nav);
                 }
             }
 		}
 
+		if curs.node().kind() == "macro_ref" {
+            if let Some(op) = self.undocumented_op(&curs.node()) {
+                return match self.visit_operation(op, &curs.node())? {
+                    Some(nav) => Ok(nav),
+                    None => Err(Box::new(Error::BadAddressMode))
+                };
+            }
+		}
+
 		if curs.node().kind().starts_with("psop_") {
             if IGNORED_PSOPS.contains(&&curs.node().kind()[5..]) {
                 return Ok(Navigation::Exit);
@@ -686,7 +977,7 @@ impl Navigate for Assembler {
                             return Err(Box::new(Error::Syntax));
                         },
                         "arg_org" => {
-                            if self.code.len() > 0 {
+                            if !self.code.is_empty() && self.pass == 0 || self.rel.is_some() {
                                 return Err(Box::new(Error::BadOrg));
                             }
                             if let Some(child) = arg.named_child(0) {
@@ -798,6 +1089,9 @@ impl Navigate for Assembler {
                                     }
                                 } else if child.kind() == "data" {
                                     let val = self.eval_data(&child, 1, false)?;
+                                    if maybe_reps.is_none() && self.fwd_ref.get() {
+                                        return Err(Box::new(Error::ForwardRef));
+                                    }
                                     if let Some(reps) = maybe_reps {
                                         for _i in 0..reps {
                                             self.push_data(val,1,false);
@@ -0,0 +1,785 @@
+//! # Full assembly driver
+//!
+//! This handles the pseudo-operations that control the stream of source lines, i.e., macros, loops, and conditionals,
+//! along with those that assign values to labels.  Everything else is handed to the spot assembler one line at a time.
+//!
+//! Labels are given values during the first pass.  A label that is referenced before it is defined is provisionally
+//! given the value of the program counter, and the operand is assumed to be absolute, as Merlin does.
+//! The second pass generates the code, and checks that every label still agrees with the program counter.
+//!
+//! If the source begins with REL the origin is 0, and labels that take the value of the program counter
+//! are flagged as relocatable.  The relocation dictionary is built during the second pass, see `rel`.
+
+use std::collections::{HashMap,HashSet};
+use std::sync::Arc;
+use lsp_types as lsp;
+use super::{Assembler,Error,Reloc,rel};
+use crate::lang::merlin::{Symbol,MerlinParser,symbol_flags};
+use crate::lang::merlin::diagnostics::macros::substitute_vars;
+use crate::lang::{node_text,Navigate};
+use crate::{STDRESULT,DYNERR};
+
+/// Merlin allows macro calls to be nested this deep
+const MAX_NESTING: usize = 15;
+/// Merlin will not repeat a loop more than this many times
+const MAX_LUP: i64 = 0x8000;
+/// Merlin starts here if there is no ORG
+const DEFAULT_ORG: usize = 0x8000;
+
+/// Conditional assembly frame created by DO or IF
+struct Cond {
+    /// were we assembling when the frame was created
+    parent: bool,
+    /// are we assembling in this frame
+    asm: bool
+}
+
+/// Loop that is being collected, lines are tagged with their source row
+struct Lup {
+    count: i64,
+    lines: Vec<(isize,String)>
+}
+
+/// Full assembly state, most of it is reset at the start of each pass.
+pub struct State {
+    parser: MerlinParser,
+    /// map from macro names to macro bodies, defined so far in this pass
+    macros: HashMap<String,String>,
+    /// macros whose definitions are being collected, there can be more than one if definitions are nested
+    defining: Vec<String>,
+    lup: Option<Lup>,
+    conds: Vec<Cond>,
+    /// macro local values for every expansion, indexed by the order of expansion, kept between passes
+    expansions: Vec<Symbol>,
+    expansion_count: usize,
+    /// stack of (macro name, expansion index, line within macro)
+    call_stack: Vec<(String,usize,usize)>,
+    /// global labels defined so far in this pass
+    defined: HashSet<String>,
+    /// program counter saved by DUM
+    dummy: Option<usize>,
+    /// count of lines sent to the spot assembler, used to key forward references, kept between passes
+    seq: usize,
+    /// lines that needed a forward reference during the first pass
+    fwd_lines: HashSet<usize>,
+    /// description of where the first error occurred
+    err_loc: Option<String>,
+    /// range of the first error within the source, for a macro call this is the call
+    err_rng: Option<lsp::Range>,
+    end: bool
+}
+
+impl State {
+    fn new() -> Self {
+        Self {
+            parser: MerlinParser::new(),
+            macros: HashMap::new(),
+            defining: Vec::new(),
+            lup: None,
+            conds: Vec::new(),
+            expansions: Vec::new(),
+            expansion_count: 0,
+            call_stack: Vec::new(),
+            defined: HashSet::new(),
+            dummy: None,
+            seq: 0,
+            fwd_lines: HashSet::new(),
+            err_loc: None,
+            err_rng: None,
+            end: false
+        }
+    }
+    fn reset_for_pass(&mut self) {
+        self.parser.restart();
+        self.macros = HashMap::new();
+        self.defining = Vec::new();
+        self.lup = None;
+        self.conds = Vec::new();
+        self.expansion_count = 0;
+        self.call_stack = Vec::new();
+        self.defined = HashSet::new();
+        self.dummy = None;
+        self.seq = 0;
+        self.end = false;
+    }
+    fn asm(&self) -> bool {
+        match self.conds.last() {
+            Some(cond) => cond.parent && cond.asm,
+            None => true
+        }
+    }
+    fn location(&self, row: isize) -> String {
+        let mut ans = format!("line {}",row + 1);
+        if let Some((name,_,offset)) = self.call_stack.last() {
+            ans += &format!(", macro {} line {}",name,offset + 1);
+        }
+        if self.call_stack.len() > 1 {
+            ans += &format!(", nested {} deep",self.call_stack.len());
+        }
+        ans
+    }
+}
+
+/// Get the (label, operation, argument) nodes of a statement.
+/// The operation can be `op_*`, `psop_*`, or `macro_ref`.
+fn parts<'a>(stmt: &tree_sitter::Node<'a>) -> (Option<tree_sitter::Node<'a>>,Option<tree_sitter::Node<'a>>,Option<tree_sitter::Node<'a>>) {
+    let mut label = None;
+    let mut op = None;
+    let mut arg = None;
+    let mut curs = stmt.walk();
+    for child in stmt.named_children(&mut curs) {
+        let kind = child.kind();
+        if kind == "label_def" || kind == "macro_def" {
+            label = Some(child);
+        } else if op.is_none() && (kind.starts_with("op_") || kind.starts_with("psop_") || kind == "macro_ref") {
+            op = Some(child);
+        } else if op.is_some() && arg.is_none() && kind.starts_with("arg_") {
+            arg = Some(child);
+        }
+    }
+    (label,op,arg)
+}
+
+/// Remove the MAC and EOM lines from code gathered during analysis, but keep a label on the EOM line.
+fn macro_body(defining_code: &str) -> String {
+    let mut lines = defining_code.lines().collect::<Vec<&str>>();
+    if !lines.is_empty() {
+        lines.remove(0);
+    }
+    if let Some(last) = lines.pop() {
+        let fields = last.split_whitespace().collect::<Vec<&str>>();
+        let (c1,c2) = match last.starts_with(char::is_whitespace) {
+            true => ("",fields.first().copied().unwrap_or("")),
+            false => (fields.first().copied().unwrap_or(""),fields.get(1).copied().unwrap_or(""))
+        };
+        if c2 == "<<<" || c2.to_uppercase() == "EOM" {
+            if !c1.is_empty() {
+                lines.push(c1);
+            }
+        } else {
+            lines.push(last);
+        }
+    }
+    lines.iter().map(|l| l.to_string() + "\n").collect::<String>()
+}
+
+/// Replace `@` in the label column with a letter that depends on the loop iteration, A for the first.
+fn iterated_label(line: &str, iteration: i64) -> String {
+    if line.starts_with(char::is_whitespace) || line.starts_with('*') || line.starts_with(';') {
+        return line.to_string();
+    }
+    let c1_end = line.find(char::is_whitespace).unwrap_or(line.len());
+    if !line[0..c1_end].contains('@') {
+        return line.to_string();
+    }
+    let letter = match char::from_u32(0x41 + iteration as u32) {
+        Some(c) => c.to_string(),
+        None => "@".to_string()
+    };
+    [&line[0..c1_end].replace("@",&letter),&line[c1_end..]].concat()
+}
+
+impl Assembler {
+    /// Assemble a complete source file in two passes.  If `pc` is `None` the program counter starts at $8000
+    /// unless there is an ORG.  Symbol information from the analyzer can be used by calling `use_shared_symbols`
+    /// first, which allows for macros defined in USE files.  Labels defined in the source are recalculated.
+    /// Errors are logged with the line where they occurred.  If the source begins with REL the result is a REL file.
+    pub fn assemble(&mut self, txt: &str, pc: Option<usize>) -> Result<Vec<u8>,DYNERR> {
+        let mut state = State::new();
+        self.assemble_with_state(&mut state, txt, pc)
+    }
+    /// Assemble a complete source file as in `assemble`, and turn the first error into a diagnostic.
+    /// The range covers the operation and operand of the line where assembly stopped, or the macro call
+    /// that led there.  Returns `None` if assembly succeeds, or if the source uses something the
+    /// assembler does not support, since that is not an error in the source.
+    pub fn diagnose(&mut self, txt: &str, pc: Option<usize>) -> Option<lsp::Diagnostic> {
+        let mut state = State::new();
+        let err = self.assemble_with_state(&mut state, txt, pc).err()?;
+        if let Some(Error::CannotAssemble) = err.downcast_ref::<Error>() {
+            return None;
+        }
+        let mut mess = format!("assembler: {}",err);
+        if let Some(loc) = state.err_loc.as_ref().filter(|loc| loc.contains("macro")) {
+            if let Some((_,detail)) = loc.split_once(", ") {
+                mess += &format!(" ({})",detail);
+            }
+        }
+        let rng = state.err_rng.unwrap_or_default();
+        Some(crate::lang::server::basic_diag(rng, &mess, lsp::DiagnosticSeverity::ERROR))
+    }
+    fn assemble_with_state(&mut self, state: &mut State, txt: &str, pc: Option<usize>) -> Result<Vec<u8>,DYNERR> {
+        let mx = self.get_mx();
+        let syms = Arc::make_mut(&mut self.symbols);
+        for sym in syms.globals.values_mut() {
+            if !sym.defs.is_empty() && sym.flags & symbol_flags::EXT == 0 {
+                sym.value = None;
+            }
+            sym.flags &= !symbol_flags::REL;
+        }
+        let mut result = Ok(Vec::new());
+        for pass in 1..3 {
+            self.pass = pass;
+            result = self.assemble_pass(state, txt, pc.unwrap_or(DEFAULT_ORG), mx);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.pass = 0;
+        self.scope = None;
+        self.force_abs = false;
+        self.rel = None;
+        if let Err(e) = &result {
+            log::error!("{} in {}",e,state.err_loc.as_ref().unwrap_or(&format!("line {}",self.row + 1)));
+        }
+        result
+    }
+    fn assemble_pass(&mut self, state: &mut State, txt: &str, pc: usize, mx: [bool;2]) -> Result<Vec<u8>,DYNERR> {
+        state.reset_for_pass();
+        self.pc = Some(pc);
+        self.code = Vec::new();
+        self.scope = None;
+        self.rel = None;
+        self.set_mx(mx[0], mx[1]);
+        for sym in Arc::make_mut(&mut self.symbols).vars.values_mut() {
+            sym.value = None;
+        }
+        self.row = 0;
+        for line in txt.lines() {
+            self.process_line(state, line, 0)?;
+            if state.end {
+                break;
+            }
+            self.row += 1;
+        }
+        if !state.defining.is_empty() || state.lup.is_some() {
+            state.err_loc = Some("end of source".to_string());
+            return Err(Box::new(Error::Unbalanced));
+        }
+        self.exit_global_scope();
+        match self.rel.take() {
+            Some(module) => self.finish_module(module),
+            None => Ok(self.code.clone())
+        }
+    }
+    /// Set the values of the entries and put the REL file together
+    fn finish_module(&mut self, mut module: rel::RelModule) -> Result<Vec<u8>,DYNERR> {
+        for entry in module.esd.iter_mut().filter(|e| e.flags & rel::esd_flags::ENT > 0) {
+            let sym = match self.symbols.globals.get(&entry.name) {
+                Some(sym) if sym.value.is_some() => sym,
+                _ => {
+                    log::error!("entry {} is not defined",entry.name);
+                    return Err(Box::new(Error::UndefinedLabel));
+                }
+            };
+            if sym.flags & symbol_flags::REL == 0 {
+                log::error!("entry {} is not relocatable",entry.name);
+                return Err(Box::new(Error::BadExternal));
+            }
+            entry.value = u16::try_from(sym.value.unwrap())?;
+        }
+        module.code = self.code.clone();
+        Ok(module.to_bytes())
+    }
+    /// Process a line, recording the location of the first error.
+    fn process_line(&mut self, state: &mut State, line: &str, depth: usize) -> STDRESULT {
+        let result = self.process_line_inner(state, line, depth);
+        if result.is_err() && state.err_loc.is_none() {
+            state.err_loc = Some(state.location(self.row));
+        }
+        if result.is_err() && depth == 0 && state.err_rng.is_none() {
+            state.err_rng = Some(self.error_range(state, line));
+        }
+        result
+    }
+    /// Range from the operation through the operand of a source line, or the whole line if there is no operation
+    fn error_range(&self, state: &mut State, line: &str) -> lsp::Range {
+        let row = self.row as u32;
+        let whole = lsp::Range::new(lsp::Position::new(row,0),lsp::Position::new(row,line.encode_utf16().count() as u32));
+        let tree = match state.parser.parse(line, &self.symbols) {
+            Ok(tree) => tree,
+            Err(_) => return whole
+        };
+        let (_,op,arg) = match tree.root_node().named_child(0) {
+            Some(stmt) => parts(&stmt),
+            None => return whole
+        };
+        match op {
+            Some(op) => {
+                let end = arg.unwrap_or(op);
+                let rng = tree_sitter::Range {
+                    start_byte: op.start_byte(),
+                    end_byte: end.end_byte(),
+                    start_point: op.start_position(),
+                    end_point: end.end_position()
+                };
+                crate::lang::lsp_range(rng, self.row, state.parser.col_offset())
+            },
+            None => whole
+        }
+    }
+    fn process_line_inner(&mut self, state: &mut State, line: &str, depth: usize) -> STDRESULT {
+        let tree = state.parser.parse(line, &self.symbols)?;
+        self.line = state.parser.line().to_string();
+        let stmt = match tree.root_node().named_child(0) {
+            Some(node) => node,
+            None => return Ok(())
+        };
+        let (label,op,arg) = parts(&stmt);
+        let op_kind = match op {
+            Some(node) => node.kind(),
+            None => ""
+        };
+
+        // Collect macro definitions, these are processed unconditionally.
+        // A nested definition starts another macro which ends at the same EOM.
+        if !state.defining.is_empty() {
+            let body_line = match (op_kind,label) {
+                ("psop_mac",_) => return self.begin_macro(state, label),
+                // a label on the EOM line is kept
+                ("psop_eom",Some(label)) => Some(node_text(&label, &self.line)),
+                ("psop_eom",None) => None,
+                _ => Some(line.to_string())
+            };
+            if let Some(body_line) = body_line {
+                for name in &state.defining {
+                    if let Some(body) = state.macros.get_mut(name) {
+                        *body += &body_line;
+                        *body += "\n";
+                    }
+                }
+            }
+            if op_kind == "psop_eom" {
+                state.defining = Vec::new();
+            }
+            return Ok(());
+        }
+
+        // Collect the body of a loop, it is expanded at the end.
+        if let Some(lup) = state.lup.as_mut() {
+            match op_kind {
+                "psop_lup" => return Err(Box::new(Error::Nesting)),
+                "psop_end_lup" => {
+                    let lup = state.lup.take().unwrap();
+                    return self.expand_lup(state, lup, depth);
+                },
+                _ => lup.lines.push((self.row,line.to_string()))
+            }
+            return Ok(());
+        }
+
+        if op_kind == "psop_mac" {
+            // within an expansion this can only be a nested definition, which is skipped
+            if state.call_stack.is_empty() {
+                self.begin_macro(state, label)?;
+            }
+            return Ok(());
+        }
+        if op_kind == "psop_eom" && !state.call_stack.is_empty() {
+            return Ok(());
+        }
+
+        // Conditionals
+        match (op_kind,arg) {
+            ("psop_do",Some(arg)) => {
+                let parent = state.asm();
+                let asm = match parent {
+                    true => self.eval_cond(&arg)? != 0,
+                    false => false
+                };
+                state.conds.push(Cond { parent, asm });
+                return Ok(());
+            },
+            ("psop_if",Some(arg)) => {
+                let parent = state.asm();
+                let asm = match parent {
+                    true => self.eval_if(state, &arg)? != 0,
+                    false => false
+                };
+                state.conds.push(Cond { parent, asm });
+                return Ok(());
+            },
+            ("psop_else",_) => {
+                match state.conds.last_mut() {
+                    Some(cond) => cond.asm = !cond.asm,
+                    None => return Err(Box::new(Error::Unbalanced))
+                }
+                return Ok(());
+            },
+            ("psop_fin",_) => {
+                match state.conds.pop() {
+                    Some(_) => return Ok(()),
+                    None => return Err(Box::new(Error::Unbalanced))
+                }
+            },
+            _ => {}
+        }
+        if !state.asm() {
+            return Ok(());
+        }
+
+        if op_kind == "psop_end" {
+            state.end = true;
+            return Ok(());
+        }
+
+        // Assignments
+        if op_kind == "psop_equ" {
+            let (label,arg) = match (label,arg) {
+                (Some(l),Some(a)) => (l,a),
+                (None,_) => return Err(Box::new(Error::MissingLabel)),
+                _ => return Err(Box::new(Error::Syntax))
+            };
+            let val = self.eval_expr(&arg, &self.line)?;
+            return self.define_label(state, &label, val, false);
+        }
+        if op_kind == "psop_var" {
+            if let Some(arg) = arg {
+                let mut curs = arg.walk();
+                for (i,child) in arg.named_children(&mut curs).enumerate() {
+                    let val = self.eval_expr(&child, &self.line)?;
+                    self.define_var(&format!("]{}",i+1), val);
+                }
+            }
+            return Ok(());
+        }
+
+        // Modules
+        match op_kind {
+            "psop_rel" => {
+                if !self.code.is_empty() || !state.defined.is_empty() || self.rel.is_some() {
+                    return Err(Box::new(Error::BadRel));
+                }
+                self.rel = Some(rel::RelModule::new());
+                self.pc = Some(0);
+                return Ok(());
+            },
+            "psop_ext" | "psop_exd" => {
+                let mut names = Vec::new();
+                if let Some(label) = label {
+                    names.push(node_text(&label, &self.line));
+                }
+                if let Some(arg) = arg {
+                    let mut curs = arg.walk();
+                    names.append(&mut arg.named_children(&mut curs).map(|n| node_text(&n, &self.line)).collect());
+                }
+                if names.is_empty() {
+                    return Err(Box::new(Error::MissingLabel));
+                }
+                for name in names {
+                    self.define_external(state, &name, op_kind == "psop_exd")?;
+                }
+                return Ok(());
+            },
+            "psop_ent" => {
+                if self.rel.is_none() {
+                    return Err(Box::new(Error::BadExternal));
+                }
+                if let Some(label) = label {
+                    self.define_label(state, &label, self.pc.unwrap_or(0) as i64, true)?;
+                }
+                let mut names = Vec::new();
+                if let Some(label) = label {
+                    names.push(node_text(&label, &self.line));
+                }
+                if let Some(arg) = arg {
+                    let mut curs = arg.walk();
+                    names.append(&mut arg.named_children(&mut curs).map(|n| node_text(&n, &self.line)).collect());
+                }
+                if let Some(module) = self.rel.as_mut() {
+                    for name in names {
+                        module.add_ent(&name);
+                    }
+                }
+                return Ok(());
+            },
+            _ => {}
+        }
+
+        // Every other label takes the value of the program counter
+        if let Some(label) = label {
+            if label.kind() == "label_def" {
+                self.define_label(state, &label, self.pc.unwrap_or(0) as i64, true)?;
+            }
+        }
+
+        // an undocumented operation is a macro call as far as the parser is concerned, but a macro takes precedence
+        let undocumented = match (stmt.kind(),op) {
+            ("macro_call",Some(mac_ref)) => {
+                let name = node_text(&mac_ref, &self.line);
+                !state.macros.contains_key(&name) && !self.symbols.macros.contains_key(&name) && self.undocumented_op(&stmt).is_some()
+            },
+            _ => false
+        };
+        if stmt.kind() == "macro_call" && !undocumented || op_kind == "psop_pmc" {
+            return self.expand_macro(state, &stmt, depth);
+        }
+
+        match (op_kind,arg) {
+            ("psop_lup",Some(arg)) => {
+                let count = self.eval_cond(&arg)?;
+                state.lup = Some(Lup { count, lines: Vec::new() });
+                return Ok(());
+            },
+            ("psop_dum",Some(arg)) => {
+                if state.dummy.is_some() {
+                    return Err(Box::new(Error::Nesting));
+                }
+                let val = self.eval_cond(&arg)?;
+                state.dummy = self.pc;
+                self.pc = Some(usize::try_from(val)?);
+                return Ok(());
+            },
+            ("psop_dend",_) => {
+                match state.dummy.take() {
+                    Some(pc) => self.pc = Some(pc),
+                    None => return Err(Box::new(Error::Unbalanced))
+                }
+                return Ok(());
+            },
+            ("psop_err",Some(arg)) => {
+                return self.check_err(&arg);
+            },
+            ("psop_chk",_) => {
+                let sum = self.code.iter().fold(0u8, |acc,x| acc ^ x);
+                self.push_data(sum as i64, 1, false);
+                return Ok(());
+            },
+            ("psop_use",_) => {
+                // macros from the USE file are expected to be in the shared symbols
+                return Ok(());
+            },
+            ("psop_put",_) => {
+                log::error!("PUT is not supported, combine the sources first");
+                return Err(Box::new(Error::CannotAssemble));
+            },
+            _ => {}
+        }
+
+        // Hand the line to the spot assembler
+        let code_len = self.code.len();
+        self.fwd_ref.set(false);
+        self.force_abs = self.pass == 2 && state.fwd_lines.contains(&state.seq);
+        self.walk(&tree)?;
+        self.patch_sweet16(state.parser.sweet16_code(), code_len);
+        if self.pass == 1 && self.fwd_ref.get() {
+            state.fwd_lines.insert(state.seq);
+        }
+        if state.dummy.is_some() {
+            self.code.truncate(code_len);
+        }
+        state.seq += 1;
+        Ok(())
+    }
+    /// Evaluate an expression that is not allowed to have forward references.
+    fn eval_cond(&self, node: &tree_sitter::Node) -> Result<i64,DYNERR> {
+        self.fwd_ref.set(false);
+        let val = self.eval_expr(node, &self.line)?;
+        if self.fwd_ref.get() {
+            return Err(Box::new(Error::ForwardRef));
+        }
+        Ok(val)
+    }
+    /// Evaluate the argument of IF, in the MX form the expression is reparsed with the value of MX inserted.
+    fn eval_if(&self, state: &mut State, arg: &tree_sitter::Node) -> Result<i64,DYNERR> {
+        let txt = node_text(arg, &self.line);
+        if !txt.starts_with("MX") {
+            return super::eval_if(arg, &self.line);
+        }
+        let mx = (self.m8bit as i64) * 2 + self.x8bit as i64;
+        let line = format!(" DO {}{}\n",mx,&txt[2..]);
+        let tree = state.parser.parse(&line, &self.symbols)?;
+        let src = state.parser.line().to_string();
+        match parts(&tree.root_node().named_child(0).unwrap_or(tree.root_node())) {
+            (_,_,Some(arg)) => super::eval_expr(&arg, &src, self.pc, &self.symbols, self.scope.as_ref()),
+            _ => Err(Box::new(Error::Syntax))
+        }
+    }
+    fn check_err(&self, arg: &tree_sitter::Node) -> STDRESULT {
+        if self.pass == 1 {
+            return Ok(());
+        }
+        match arg.named_child(0) {
+            Some(expr) if expr.kind() != "ptr_check" && !node_text(arg, &self.line).starts_with('\\') => {
+                if self.eval_expr(&expr, &self.line)? != 0 {
+                    return Err(Box::new(Error::Forced));
+                }
+            },
+            _ => log::warn!("ERR with a memory or REL check is ignored")
+        }
+        Ok(())
+    }
+    fn begin_macro(&mut self, state: &mut State, label: Option<tree_sitter::Node>) -> STDRESULT {
+        let name = match label {
+            Some(node) if node.kind() == "macro_def" => node_text(&node, &self.line),
+            _ => return Err(Box::new(Error::MissingLabel))
+        };
+        let syms = Arc::make_mut(&mut self.symbols);
+        if !syms.mac_defined(&name) {
+            let mut sym = Symbol::new(&name);
+            sym.flags = symbol_flags::MAC;
+            let uri = lsp::Url::parse("file:///").expect(super::super::RCH);
+            sym.defs.push(lsp::Location::new(uri, lsp::Range::default()));
+            syms.macros.insert(name.clone(), sym);
+        }
+        state.macros.insert(name.clone(), String::new());
+        state.defining.push(name);
+        Ok(())
+    }
+    fn expand_macro(&mut self, state: &mut State, stmt: &tree_sitter::Node, depth: usize) -> STDRESULT {
+        if depth >= MAX_NESTING {
+            return Err(Box::new(Error::Nesting));
+        }
+        let mac_ref = match stmt.kind() {
+            "macro_call" => parts(stmt).1,
+            _ => match parts(stmt).2 {
+                Some(arg) => arg.named_child(0),
+                None => None
+            }
+        };
+        let mac_ref = match mac_ref {
+            Some(node) if node.kind() == "macro_ref" => node,
+            _ => return Err(Box::new(Error::Syntax))
+        };
+        let name = node_text(&mac_ref, &self.line);
+        let body = match state.macros.get(&name) {
+            Some(body) => body.clone(),
+            None => match self.symbols.macros.get(&name) {
+                Some(Symbol { defining_code: Some(code), .. }) => macro_body(code),
+                _ => return Err(Box::new(Error::UndefinedMacro))
+            }
+        };
+        let mut nodes = Vec::new();
+        if let Some(args) = mac_ref.next_named_sibling() {
+            if args.kind() == "arg_macro" {
+                let mut curs = args.walk();
+                nodes = args.named_children(&mut curs).collect::<Vec<tree_sitter::Node>>();
+            }
+        }
+        if nodes.len() > 8 {
+            return Err(Box::new(Error::BadArgs));
+        }
+        let (expanded,_) = substitute_vars(&body, &nodes, &self.line)?;
+        let idx = state.expansion_count;
+        state.expansion_count += 1;
+        if state.expansions.len() <= idx {
+            state.expansions.push(Symbol::new(&name));
+        }
+        let outer_scope = self.scope.replace(state.expansions[idx].clone());
+        state.call_stack.push((name,idx,0));
+        let mut result = Ok(());
+        for (offset,line) in expanded.lines().enumerate() {
+            if let Some(frame) = state.call_stack.last_mut() {
+                frame.2 = offset;
+            }
+            result = self.process_line(state, line, depth + 1);
+            if result.is_err() || state.end {
+                break;
+            }
+        }
+        if let Some(scope) = self.scope.take() {
+            state.expansions[idx] = scope;
+        }
+        self.scope = outer_scope;
+        state.call_stack.pop();
+        result
+    }
+    fn expand_lup(&mut self, state: &mut State, lup: Lup, depth: usize) -> STDRESULT {
+        if lup.count > MAX_LUP {
+            return Err(Box::new(Error::OutOfRange));
+        }
+        let lup_row = self.row;
+        for i in 0..lup.count {
+            for (row,line) in &lup.lines {
+                self.row = *row;
+                self.process_line(state, &iterated_label(line, i), depth)?;
+                if state.end {
+                    return Ok(());
+                }
+            }
+        }
+        self.row = lup_row;
+        Ok(())
+    }
+    fn define_var(&mut self, name: &str, val: i64) {
+        let syms = Arc::make_mut(&mut self.symbols);
+        let sym = syms.vars.entry(name.to_string()).or_insert(Symbol::new(name));
+        sym.value = Some(val);
+    }
+    /// Save the local labels of the current global scope
+    fn exit_global_scope(&mut self) {
+        if let Some(scope) = self.scope.take() {
+            if let Some(sym) = Arc::make_mut(&mut self.symbols).globals.get_mut(&scope.name) {
+                sym.children = scope.children;
+            }
+        }
+    }
+    fn define_external(&mut self, state: &mut State, name: &str, direct: bool) -> STDRESULT {
+        let module = match self.rel.as_mut() {
+            Some(m) => m,
+            None => return Err(Box::new(Error::BadExternal))
+        };
+        module.add_ext(name, direct)?;
+        if !state.defined.insert(name.to_string()) {
+            return Err(Box::new(Error::DuplicateSymbol));
+        }
+        let syms = Arc::make_mut(&mut self.symbols);
+        let sym = syms.globals.entry(name.to_string()).or_insert(Symbol::new(name));
+        sym.flags |= symbol_flags::EXT;
+        sym.value = Some(0);
+        Ok(())
+    }
+    /// Give a label its value.  If `is_pc` the second pass checks the value against the first.
+    /// In a relocatable module the label is flagged if it is relative to the start of the module.
+    fn define_label(&mut self, state: &mut State, label: &tree_sitter::Node, val: i64, is_pc: bool) -> STDRESULT {
+        let txt = node_text(label, &self.line);
+        let relocatable = self.rel.is_some() && match is_pc {
+            true => state.dummy.is_none(),
+            false => matches!(self.reloc.get(),Reloc::Rel(_))
+        };
+        let set_flags = |sym: &mut Symbol| match relocatable {
+            true => sym.flags |= symbol_flags::REL,
+            false => sym.flags &= !symbol_flags::REL
+        };
+        let kind = match label.named_child(0) {
+            Some(child) => child.kind(),
+            None => return Err(Box::new(Error::Syntax))
+        };
+        if kind == "var_label" {
+            self.define_var(&txt, val);
+            return Ok(());
+        }
+        if kind == "local_label" || !state.call_stack.is_empty() {
+            // local or macro local
+            let scope = match self.scope.as_mut() {
+                Some(scope) => scope,
+                None => return Err(Box::new(Error::MissingLabel))
+            };
+            let child = scope.children.entry(txt.clone()).or_insert(Symbol::new(&txt));
+            if let Some(old) = child.value {
+                if self.pass == 2 && is_pc && old != val {
+                    log::error!("label {} was {} but is now {}",txt,old,val);
+                    return Err(Box::new(Error::Misalignment));
+                }
+            }
+            child.value = Some(val);
+            set_flags(child);
+            return Ok(());
+        }
+        if !state.defined.insert(txt.clone()) {
+            return Err(Box::new(Error::DuplicateSymbol));
+        }
+        self.exit_global_scope();
+        let syms = Arc::make_mut(&mut self.symbols);
+        let sym = syms.globals.entry(txt.clone()).or_insert(Symbol::new(&txt));
+        if let Some(old) = sym.value {
+            if self.pass == 2 && is_pc && old != val {
+                log::error!("label {} was {} but is now {}",txt,old,val);
+                return Err(Box::new(Error::Misalignment));
+            }
+        }
+        sym.value = Some(val);
+        set_flags(sym);
+        self.scope = Some(sym.clone());
+        Ok(())
+    }
+}
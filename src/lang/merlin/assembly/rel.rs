@@ -0,0 +1,249 @@
+//! # Relocatable modules and linker
+//!
+//! A Merlin REL file is the object code assembled at origin 0, followed by the relocation dictionary (RLD),
+//! followed by the external symbol dictionary (ESD).
+//!
+//! * Each RLD entry is 4 bytes: flags, offset of the field within the code (LE), and an auxiliary byte.
+//!   The auxiliary byte is the low byte of the value for a high byte field, or the external reference number
+//!   for an external field.  The RLD ends with a zero flag byte.
+//! * Each ESD entry is the symbol name, with the high bit set on all but the last character, then flags,
+//!   then 2 bytes.  The 2 bytes are the value (LE) of an entry, or the reference number and 0 for an external.
+//!   The ESD ends with a zero byte.
+//!
+//! The linker places modules one after another, resolves externals against entries, and patches every field
+//! listed in the RLD.  The high byte of an external address is patched assuming the low byte of the field is zero.
+
+use std::collections::HashMap;
+use super::Error;
+use crate::DYNERR;
+
+pub mod rld_flags {
+    /// the field is 2 bytes, otherwise 1 byte
+    pub const TWO_BYTE: u8 = 0x80;
+    /// a 1 byte field is the high byte of the value, otherwise the low byte
+    pub const HIGH_BYTE: u8 = 0x40;
+    /// a 2 byte field is big endian
+    pub const REVERSED: u8 = 0x20;
+    /// the field refers to an external symbol
+    pub const EXTERNAL: u8 = 0x10;
+    /// always set so that the flags are never zero
+    pub const NOT_END: u8 = 0x08;
+}
+
+pub mod esd_flags {
+    /// symbol is defined in this module and exported
+    pub const ENT: u8 = 0x08;
+    /// symbol is defined in another module
+    pub const EXT: u8 = 0x10;
+    /// external symbol is on the direct page (EXD)
+    pub const DIRECT: u8 = 0x20;
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct RldEntry {
+    pub flags: u8,
+    pub offset: u16,
+    pub aux: u8
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct EsdEntry {
+    pub name: String,
+    pub flags: u8,
+    /// value of an entry, or the reference number of an external
+    pub value: u16
+}
+
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct RelModule {
+    pub code: Vec<u8>,
+    pub rld: Vec<RldEntry>,
+    pub esd: Vec<EsdEntry>
+}
+
+impl RldEntry {
+    fn size(&self) -> usize {
+        match self.flags & rld_flags::TWO_BYTE {
+            0 => 1,
+            _ => 2
+        }
+    }
+}
+
+impl RelModule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Reference number of an external symbol, if it exists
+    pub fn ext_number(&self, name: &str) -> Option<u8> {
+        self.esd.iter().find(|e| e.flags & esd_flags::EXT > 0 && e.name == name).map(|e| e.value as u8)
+    }
+    /// Add an external symbol and return its reference number
+    pub fn add_ext(&mut self, name: &str, direct: bool) -> Result<u8,DYNERR> {
+        if let Some(num) = self.ext_number(name) {
+            return Ok(num);
+        }
+        let count = self.esd.iter().filter(|e| e.flags & esd_flags::EXT > 0).count();
+        if count >= 0xff {
+            return Err(Box::new(Error::Externals));
+        }
+        let flags = match direct {
+            true => esd_flags::EXT | esd_flags::DIRECT,
+            false => esd_flags::EXT
+        };
+        self.esd.push(EsdEntry { name: name.to_string(), flags, value: count as u16 + 1 });
+        Ok(count as u8 + 1)
+    }
+    /// Add an entry symbol, the value can be set later
+    pub fn add_ent(&mut self, name: &str) {
+        if !self.esd.iter().any(|e| e.flags & esd_flags::ENT > 0 && e.name == name) {
+            self.esd.push(EsdEntry { name: name.to_string(), flags: esd_flags::ENT, value: 0 });
+        }
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ans = self.code.clone();
+        for entry in &self.rld {
+            ans.push(entry.flags);
+            ans.append(&mut u16::to_le_bytes(entry.offset).to_vec());
+            ans.push(entry.aux);
+        }
+        ans.push(0);
+        for entry in &self.esd {
+            let mut name = entry.name.as_bytes().iter().map(|c| c | 0x80).collect::<Vec<u8>>();
+            if let Some(last) = name.last_mut() {
+                *last &= 0x7f;
+            }
+            ans.append(&mut name);
+            ans.push(entry.flags);
+            ans.append(&mut u16::to_le_bytes(entry.value).to_vec());
+        }
+        ans.push(0);
+        ans
+    }
+    /// Try to parse the dictionaries assuming the code has length `code_len`
+    fn parse(dat: &[u8], code_len: usize) -> Option<Self> {
+        let mut ans = Self::new();
+        let mut ptr = code_len;
+        loop {
+            let flags = *dat.get(ptr)?;
+            if flags == 0 {
+                ptr += 1;
+                break;
+            }
+            if flags & rld_flags::NOT_END == 0 || flags & 0x07 != 0 || ptr + 4 > dat.len() {
+                return None;
+            }
+            let entry = RldEntry { flags, offset: u16::from_le_bytes([dat[ptr+1],dat[ptr+2]]), aux: dat[ptr+3] };
+            if entry.offset as usize + entry.size() > code_len {
+                return None;
+            }
+            ans.rld.push(entry);
+            ptr += 4;
+        }
+        loop {
+            let c = *dat.get(ptr)?;
+            if c == 0 {
+                if ptr + 1 != dat.len() {
+                    return None;
+                }
+                break;
+            }
+            let mut name = String::new();
+            loop {
+                let c = *dat.get(ptr)?;
+                ptr += 1;
+                if !(c & 0x7f).is_ascii_graphic() {
+                    return None;
+                }
+                name.push((c & 0x7f) as char);
+                if c < 0x80 {
+                    break;
+                }
+            }
+            let flags = *dat.get(ptr)?;
+            if ![esd_flags::ENT,esd_flags::EXT,esd_flags::EXT | esd_flags::DIRECT].contains(&flags) || ptr + 3 > dat.len() {
+                return None;
+            }
+            ans.esd.push(EsdEntry { name, flags, value: u16::from_le_bytes([dat[ptr+1],dat[ptr+2]]) });
+            ptr += 3;
+        }
+        ans.code = dat[0..code_len].to_vec();
+        Some(ans)
+    }
+    /// Parse a REL file.  The length of the code is kept outside the file (in ProDOS it is the aux type).
+    /// If `code_len` is `None` the shortest code length that gives valid dictionaries is used.
+    pub fn from_bytes(dat: &[u8], code_len: Option<usize>) -> Result<Self,DYNERR> {
+        let candidates = match code_len {
+            Some(len) => len..len+1,
+            None => 0..dat.len()
+        };
+        for len in candidates {
+            if let Some(ans) = Self::parse(dat, len) {
+                return Ok(ans);
+            }
+        }
+        log::error!("could not find the relocation dictionary");
+        Err(Box::new(Error::FileTypeMismatch))
+    }
+}
+
+/// Link the modules, placing them one after another starting at `org`, and return the combined code.
+/// Every external has to be resolved by an entry in some module.
+pub fn link(modules: &[RelModule], org: usize) -> Result<Vec<u8>,DYNERR> {
+    let mut bases = Vec::new();
+    let mut entries: HashMap<String,usize> = HashMap::new();
+    let mut base = org;
+    for module in modules {
+        bases.push(base);
+        for entry in module.esd.iter().filter(|e| e.flags & esd_flags::ENT > 0) {
+            if entries.insert(entry.name.clone(), base + entry.value as usize).is_some() {
+                log::error!("entry {} is in more than one module",entry.name);
+                return Err(Box::new(Error::DuplicateSymbol));
+            }
+        }
+        base += module.code.len();
+    }
+    if base > 0x10000 {
+        return Err(Box::new(Error::OutOfRange));
+    }
+    let mut ans = Vec::new();
+    for (module,base) in modules.iter().zip(bases) {
+        let mut code = module.code.clone();
+        for rld in &module.rld {
+            let off = rld.offset as usize;
+            let reversed = rld.flags & rld_flags::REVERSED > 0;
+            let old = match (rld.size(),rld.flags & rld_flags::HIGH_BYTE > 0,reversed) {
+                (2,_,false) => u16::from_le_bytes([code[off],code[off+1]]) as usize,
+                (2,_,true) => u16::from_be_bytes([code[off],code[off+1]]) as usize,
+                (_,true,_) if rld.flags & rld_flags::EXTERNAL > 0 => code[off] as usize * 0x100,
+                (_,true,_) => code[off] as usize * 0x100 + rld.aux as usize,
+                _ => code[off] as usize
+            };
+            let addend = match rld.flags & rld_flags::EXTERNAL {
+                0 => base,
+                _ => {
+                    let name = match module.esd.iter().find(|e| e.flags & esd_flags::EXT > 0 && e.value == rld.aux as u16) {
+                        Some(e) => &e.name,
+                        None => return Err(Box::new(Error::FileTypeMismatch))
+                    };
+                    match entries.get(name) {
+                        Some(addr) => *addr,
+                        None => {
+                            log::error!("external {} is not an entry in any module",name);
+                            return Err(Box::new(Error::UnresolvedExt));
+                        }
+                    }
+                }
+            };
+            let new = old + addend;
+            match (rld.size(),rld.flags & rld_flags::HIGH_BYTE > 0,reversed) {
+                (2,_,false) => code[off..off+2].copy_from_slice(&u16::to_le_bytes(new as u16)),
+                (2,_,true) => code[off..off+2].copy_from_slice(&u16::to_be_bytes(new as u16)),
+                (_,true,_) => code[off] = (new >> 8) as u8,
+                _ => code[off] = new as u8
+            }
+        }
+        ans.append(&mut code);
+    }
+    Ok(ans)
+}
@@ -4,6 +4,7 @@ use std::str::Lines;
 use std::sync::Arc;
 use std::collections::{HashSet,HashMap};
 use super::{MerlinVersion, ProcessorType, Symbols};
+use super::imports::DiskSymbols;
 use a2_memory_map::{MemoryMap,AddressInfo};
 
 use super::handbook::operations::OperationHandbook;
@@ -128,6 +129,7 @@ impl AddressCompletionProvider {
 
 struct CodeCompletionProvider {
 	symbols: Arc<Symbols>,
+	disk_symbols: Arc<DiskSymbols>,
 	op_book: OperationHandbook,
 	psop_book: PseudoOperationHandbook,
 	addr_mode_map: HashMap<String,String>,
@@ -152,6 +154,7 @@ impl CodeCompletionProvider {
 		}
 		Self {
 			symbols: Arc::new(Symbols::new()),
+			disk_symbols: Arc::new(DiskSymbols::new()),
 			op_book: OperationHandbook::new(),
 			psop_book: PseudoOperationHandbook::new(),
 			addr_mode_map,
@@ -173,6 +176,9 @@ impl CodeCompletionProvider {
     }
     pub fn use_shared_symbols(&mut self,sym: Arc<Symbols>) {
         self.symbols = sym;
+    }
+    pub fn use_disk_symbols(&mut self,sym: Arc<DiskSymbols>) {
+        self.disk_symbols = sym;
     }
 	fn modify(&self,s: &str,padreq: usize) -> String {
 		if self.config.flag.case_sensitive.is_none() && self.config.completions.lower_case {
@@ -220,6 +226,18 @@ impl CodeCompletionProvider {
 			}
 		}
 	}
+	fn add_external(&self,ans: &mut Vec<lsp::CompletionItem>,label_set: &HashSet<String>) {
+		for raw in self.disk_symbols.names() {
+			if !label_set.contains(raw) {
+				ans.push(lsp::CompletionItem {
+					label: raw.to_string(),
+					kind: Some(lsp::CompletionItemKind::CONSTANT),
+					detail: Some("external".to_string()),
+					..Default::default()
+				});
+			}
+		}
+	}
 	fn add_snippet(&self, ans: &mut Vec<lsp::CompletionItem>, raw_lab: &str, raw_snip: &str, tab: bool)	{
 		let lab = match tab {
 			true => raw_lab.replace("\n",&format!("\n{}"," ".repeat(self.config.columns.c1 as usize))),
@@ -312,6 +330,7 @@ impl CodeCompletionProvider {
 		let mut simple = Vec::new();
 		let mut label = HashSet::new();
 		let mut psop_args = 0;
+		let mut external = false;
 		if line.starts_with("*") {
 			return ans;
 		}
@@ -364,6 +383,7 @@ impl CodeCompletionProvider {
 			for glob in self.symbols.globals.keys() {
 				label.insert(glob.to_string());
 			}
+			external = true;
 		}
 		if self.c3_arg_regex.is_match(line_prefix) {
 			// suggest pseudo-op arguments based on what is in column 2
@@ -381,6 +401,7 @@ impl CodeCompletionProvider {
 				for glob in self.symbols.globals.keys() {
 					label.insert(glob.to_string());
 				}
+				external = true;
 			}
 		}
 		if self.iaddr_regex.is_match(line_prefix) {
@@ -396,6 +417,9 @@ impl CodeCompletionProvider {
 			}
 		}
 		self.add_simple(&mut ans,&simple);
+		if external {
+			self.add_external(&mut ans,&label);
+		}
 		self.add_label(&mut ans,&label);
 		return ans;
 	}
@@ -423,6 +447,10 @@ impl CompletionProvider {
     pub fn use_shared_symbols(&mut self,sym: Arc<Symbols>) {
 		self.code_tool.use_shared_symbols(sym);
     }
+    /// Labels imported from disk images are suggested wherever globals are
+    pub fn use_disk_symbols(&mut self,sym: Arc<DiskSymbols>) {
+		self.code_tool.use_disk_symbols(sym);
+    }
 } 
 
 impl Completions for CompletionProvider {
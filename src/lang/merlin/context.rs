@@ -28,7 +28,7 @@ use std::collections::HashMap;
 use lsp_types as lsp;
 use tree_sitter::TreeCursor;
 use crate::lang::merlin::{Symbol,Symbols,Workspace,MerlinVersion,symbol_flags,ProcessorType,SourceType};
-use crate::lang::merlin::settings::Settings;
+use crate::lang::merlin::settings::{Settings,Extensions};
 use crate::lang::merlin::handbook::operations::OperationHandbook;
 use crate::lang::merlin::handbook::pseudo_ops::PseudoOperationHandbook;
 use crate::lang::{Document,node_text,lsp_range};
@@ -528,6 +528,9 @@ impl Context {
     pub fn merlin_version(&self) -> MerlinVersion {
         self.config.version.clone()
     }
+    pub fn extensions(&self) -> Extensions {
+        self.config.extensions.clone()
+    }
     pub fn case_sensitivity(&self) -> Option<lsp::DiagnosticSeverity> {
         self.config.flag.case_sensitive
     }
@@ -97,6 +97,11 @@ fn register_child(txt: &str, loc: lsp::Location, node: &tree_sitter::Node, ctx:
     }
 }
 
+/// An undocumented operation parses as a macro call, but it is not one if it is enabled and there is no such macro.
+fn is_undocumented_op(txt: &str, ctx: &Context, symbols: &Symbols) -> bool {
+    symbols.undocumented_enabled() && !symbols.mac_defined(txt) && ctx.op_handbook().get_undocumented(txt).is_some()
+}
+
 fn eval_fold_expr(node: &tree_sitter::Node,pc: Option<usize>,symbols: &Symbols,ctx: &Context,in_macro_def: bool,diagnostics: Option<&mut Vec<lsp::Diagnostic>>) -> i64 {
     let range = lsp_range(node.range(),ctx.row(),ctx.col());
     let cannot_eval_mess = match in_macro_def {
@@ -342,7 +347,7 @@ pub fn visit_gather(curs: &TreeCursor, ctx: &mut Context, ws: &Workspace, symbol
         }
         return Ok(Navigation::GotoSibling);
     } else if child.is_some() && node.kind() == "macro_ref" && asm {
-        if child.unwrap().kind()=="global_label" {
+        if child.unwrap().kind()=="global_label" && !is_undocumented_op(&txt,ctx,symbols) {
             let fwd = match symbols.mac_defined(&txt) {
                 true => Vec::new(),
                 false => vec![LabelType::Macro]
@@ -575,7 +580,9 @@ pub fn visit_verify(curs: &TreeCursor, ctx: &mut Context, ws: &Workspace, symbol
         }
         return Ok(Navigation::GotoSibling)
     } else if node.kind()=="macro_ref" {
-        if !symbols.mac_defined(&txt) && symbols.global_declared_or_defined(&txt) {
+        if is_undocumented_op(&txt,ctx,symbols) {
+            return Ok(Navigation::GotoSibling);
+        } else if !symbols.mac_defined(&txt) && symbols.global_declared_or_defined(&txt) {
             push(rng,"expected macro, this is a label",lsp::DiagnosticSeverity::ERROR);
         } else if !symbols.mac_defined(&txt) {
             push(rng,"macro is undefined",lsp::DiagnosticSeverity::ERROR);
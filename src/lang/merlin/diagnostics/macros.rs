@@ -9,8 +9,8 @@ use crate::DYNERR;
 
 struct Substitutor {
     line: String,
-    delta: usize,
-    build: String,
+    /// (start byte, end byte, index into search)
+    edits: Vec<(usize,usize,usize)>,
     search: Vec<String>,
     replace: Vec<String>,
     types: Vec<String>,
@@ -21,39 +21,35 @@ impl Substitutor {
     fn new(line: &str,search: Vec<String>,replace: Vec<String>,types: Vec<String>) -> Self {
         Self {
             line: line.to_owned(),
-            delta: 0,
-            build: String::new(),
+            edits: Vec::new(),
             search,
             replace,
             types,
             matched_args: HashSet::new()
         }
     }
+    /// Splice the replacements into the original line, so that text which is not part of any node is kept.
     fn result(&self) -> (String,HashSet<usize>) {
-        (self.build.clone(),self.matched_args.clone())
+        let mut build = self.line.trim_end_matches(['\n','\r']).to_string();
+        for (beg,end,i) in self.edits.iter().rev() {
+            if *end <= build.len() {
+                build.replace_range(*beg..*end,&self.replace[*i]);
+            }
+        }
+        (build,self.matched_args.clone())
     }
 }
 
 impl Navigate for Substitutor {
     fn visit(&mut self,curs: &tree_sitter::TreeCursor) -> Result<Navigation,DYNERR> {
         let txt = node_text(&curs.node(),&self.line);
-        let curr_len = curs.node().start_position().column + self.delta;
-        if curr_len > self.build.len() {
-            self.build += &" ".repeat(curr_len - self.build.len());
-        }
         for i in 0..self.search.len() {
             if curs.node().kind() == self.types[i] &&  txt == self.search[i] {
                 self.matched_args.insert(i);
-                self.build += &self.replace[i];
-                self.delta += self.replace[i].len() - self.search[i].len();
+                self.edits.push((curs.node().start_byte(),curs.node().end_byte(),i));
                 return Ok(Navigation::GotoSibling);
             }
         }
-        // append terminal nodes
-        if curs.node().named_child_count() == 0 {
-            self.build += &txt;
-            return Ok(Navigation::GotoSibling);
-        }
         Ok(Navigation::GotoChild)
     }
 }
@@ -76,7 +72,7 @@ fn substitute(parser: &mut tree_sitter::Parser, line: &str, search: &Vec<String>
 /// * nodes: list of macro argument nodes
 /// * call_source: text of the line where the macro is called
 /// returns (expanded macro, set of variables that were actually used)
-fn substitute_vars(txt: &str, nodes: &Vec<tree_sitter::Node>, call_source: &str) -> Result<(String,HashSet<usize>),DYNERR> {
+pub(crate) fn substitute_vars(txt: &str, nodes: &[tree_sitter::Node], call_source: &str) -> Result<(String,HashSet<usize>),DYNERR> {
     let mut parser = tree_sitter::Parser::new();
     parser.set_language(&tree_sitter_merlin6502::language())?;
     let mut ans = String::new();
@@ -96,6 +92,10 @@ fn substitute_vars(txt: &str, nodes: &Vec<tree_sitter::Node>, call_source: &str)
         replace.push(format!("]{}",i + 1));
         types.push("var_mac".to_string());
     }
+    // the argument count comes last so it does not disturb the indexing
+    search.push("]0".to_string());
+    replace.push(nodes.len().to_string());
+    types.push("var_cnt".to_string());
     for line in txt.lines() {
         let terminated = line.to_string() + "\n";
         let (ln,partial) = substitute(&mut parser, &terminated, &search, &replace, &types)?;
@@ -123,6 +123,7 @@ impl Analyzer {
         self.folding = Vec::new();
     }
     fn reset_for_pass(&mut self) {
+        self.parser.restart();
         self.ctx.reset_for_pass();
         self.asm = asm::Asm::new(&self.ctx);
     }
@@ -264,6 +265,7 @@ impl Analysis for Analyzer {
         self.symbols.display_doc_uri = doc.uri.to_string();
         self.symbols.assembler = self.ctx.merlin_version();
         self.symbols.processor = self.ctx.curr_proc();
+        self.symbols.extensions = self.ctx.extensions();
         info!("Use master {}",master.uri.to_string());
         for pass in 1..4 {
             log::debug!("ANALYSIS PASS {}",pass);
@@ -3,9 +3,12 @@
 //! This is not intended to be entirely automatic, rather it is meant to be
 //! part of a language server, wherein live human intervention is possible.
 //! However, it can also be used from the command line for simple disassemblies.
+//! 
+//! The output can be written in the syntax of other assemblers, see `Syntax`.
+//! Well known addresses can be annotated using a `symbols::SymbolDatabase`.
 
 use std::sync::Arc;
-use std::collections::{HashSet,HashMap};
+use std::collections::{HashSet,HashMap,BTreeMap};
 use hex::ToHex;
 use crate::lang;
 use crate::lang::merlin::{ProcessorType,settings::Settings,MachineOperation,Symbols};
@@ -13,6 +16,12 @@ use crate::lang::merlin::handbook::operations::OperationHandbook;
 use super::formatter;
 use crate::DYNERR;
 
+mod foreign;
+mod calls;
+mod sweet16;
+pub mod symbols;
+pub mod paths;
+
 pub enum DasmRange {
     All,
     LastBloadDos33,
@@ -20,6 +29,30 @@ pub enum DasmRange {
     Range([usize;2])
 }
 
+/// Assembler syntax of the disassembly.  Merlin is the native syntax, the others are produced
+/// by translating the Merlin lines.  Anything that cannot be expressed in the target syntax is written as data.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Syntax {
+    Merlin,
+    /// S-C Macro Assembler
+    Scasm,
+    Ca65,
+    Acme
+}
+
+impl std::str::FromStr for Syntax {
+    type Err = lang::Error;
+    fn from_str(s: &str) -> Result<Self,Self::Err> {
+        match s {
+            "merlin" => Ok(Self::Merlin),
+            "scasm" => Ok(Self::Scasm),
+            "ca65" => Ok(Self::Ca65),
+            "acme" => Ok(Self::Acme),
+            _ => Err(lang::Error::Syntax)
+        }
+    }
+}
+
 struct Operand {
     txt: String,
     num: Vec<u32>
@@ -58,7 +91,12 @@ struct DasmLine {
     prefix: String,
     suffix: String,
     operand: Option<Operand>,
-    references: Vec<usize>
+    references: Vec<usize>,
+    comment: Option<String>,
+    /// object bytes represented by this line (for a loop, the bytes of one iteration)
+    bytes: Vec<u8>,
+    /// SWEET16 or undocumented operation, which other assemblers get as data
+    extended: bool
 }
 
 impl DasmLine {
@@ -69,31 +107,43 @@ impl DasmLine {
             prefix: String::new(),
             suffix: String::new(),
             operand: None,
-            references: Vec::new()
+            references: Vec::new(),
+            comment: None,
+            bytes: Vec::new(),
+            extended: false
         }
     }
-    fn basic(addr: usize, ins: String, op: Operand) -> Self {
+    fn basic(addr: usize, ins: String, op: Operand, bytes: Vec<u8>) -> Self {
         Self {
             address: addr,
             instruction: ins,
             prefix: String::new(),
             suffix: String::new(),
             operand: Some(op),
-            references: Vec::new()
+            references: Vec::new(),
+            comment: None,
+            bytes,
+            extended: false
         }
     }
 }
 
 pub struct Disassembler {
     config: Settings,
+    syntax: Syntax,
+    proc: ProcessorType,
     pc: Option<usize>,
     m8bit: bool,
     x8bit: bool,
     symbols: Arc<Symbols>,
+    rom_symbols: Option<symbols::SymbolDatabase>,
     dasm_map: HashMap<u8,MachineOperation>,
+    undocumented_map: HashMap<u8,MachineOperation>,
     dasm_lines: Vec<DasmLine>,
+    call_blocks: HashMap<usize,calls::CallBlock>,
     std_patt: regex::Regex,
-    mov_patt: regex::Regex
+    mov_patt: regex::Regex,
+    hex_patt: regex::Regex
 }
 
 fn u32_from_operand(slice: &[u8]) -> u32 {
@@ -113,6 +163,16 @@ fn hex_from_val(prefix: &str, val: u32, bytes: usize) -> String {
     [prefix.to_string(),ans].concat()
 }
 
+/// Hex value of an equate, using the fewest bytes so the assembler can choose direct page addressing
+fn equate_value(addr: usize) -> String {
+    let bytes = match addr {
+        a if a > 0xffff => 3,
+        a if a > 0xff => 2,
+        _ => 1
+    };
+    hex_from_val("$",addr as u32,bytes)
+}
+
 fn is_alphanum(c: u8, off: u8) -> bool {
     c > 0x40 + off && c <= 0x5a + off || c > 0x60 + off && c <= 0x7a + off || c >= 0x30 + off && c <= 0x39 + off
 }
@@ -145,14 +205,20 @@ impl Disassembler {
         let book = OperationHandbook::new();
         Self {
             config: Settings::new(),
+            syntax: Syntax::Merlin,
+            proc: ProcessorType::_6502,
             pc: None,
             m8bit: true,
             x8bit: true,
             symbols: Arc::new(Symbols::new()),
+            rom_symbols: None,
             dasm_map: book.create_dasm_map(),
+            undocumented_map: book.create_undocumented_dasm_map(),
             dasm_lines: Vec::new(),
+            call_blocks: HashMap::new(),
             std_patt: regex::Regex::new(r"[0-9]").expect(super::RCH),
-            mov_patt: regex::Regex::new(r"[0-9][0-9]").expect(super::RCH)
+            mov_patt: regex::Regex::new(r"[0-9][0-9]").expect(super::RCH),
+            hex_patt: regex::Regex::new(r"\$[0-9A-Fa-f]+").expect(super::RCH)
         }
     }
     pub fn set_config(&mut self,config: Settings) {
@@ -167,6 +233,16 @@ impl Disassembler {
     pub fn set_mx(&mut self, m8bit: bool, x8bit: bool) {
         self.m8bit = m8bit;
         self.x8bit = x8bit;
+    }
+    pub fn set_syntax(&mut self, syntax: Syntax) {
+        self.syntax = syntax;
+    }
+    /// Annotate well known addresses with names and comments, internal labels take precedence
+    pub fn use_symbol_database(&mut self, db: symbols::SymbolDatabase) {
+        self.rom_symbols = match db.is_empty() {
+            true => None,
+            false => Some(db)
+        };
     }
 	fn modify(&self,s: &str) -> String {
 		if let Some(sev) = self.config.flag.case_sensitive {
@@ -176,17 +252,23 @@ impl Disassembler {
 		}
 		return s.to_uppercase();
 	}
-    fn push_data_psop(&mut self, addr: usize, ins: String, dat: String) {
-        self.dasm_lines.push(DasmLine::basic(addr, ins, Operand::txt(dat)));
+    fn push_data_psop(&mut self, addr: usize, ins: String, dat: String, bytes: &[u8]) {
+        self.dasm_lines.push(DasmLine::basic(addr, ins, Operand::txt(dat), bytes.to_vec()));
     }
     fn push_data_pattern(&mut self, addr: usize, img: &[u8], length: usize, reps: usize) {
+        if self.syntax != Syntax::Merlin {
+            // other syntaxes get the whole run
+            let v: Vec<u8> = img[addr..addr+length*reps].to_vec();
+            self.push_data_psop(addr, self.modify("HEX"), v.encode_hex_upper(), &v);
+            return;
+        }
 		if reps > 1 {
-			self.push_data_psop(addr, self.modify("LUP"), reps.to_string());
+			self.push_data_psop(addr, self.modify("LUP"), reps.to_string(), &[]);
 		}
         let v: Vec<u8> = img[addr..addr+length].to_vec();
-		self.push_data_psop(addr, self.modify("HEX"),v.encode_hex_upper());
+		self.push_data_psop(addr, self.modify("HEX"),v.encode_hex_upper(), &v);
 		if reps > 1 {
-			self.push_data_psop(addr, "--^".to_string(), "".to_string());
+			self.push_data_psop(addr, "--^".to_string(), "".to_string(), &[]);
 		}
     }
     /// * `neg` indicates the string that was found is negative ASCII
@@ -199,16 +281,19 @@ impl Disassembler {
 			delim = match neg { true=> "&", false => "/"};
 		}
         let off = match neg { true => 0, false => 128 };
+        let mut bytes = s.bytes().map(|b| match neg { true => b | 0x80, false => b }).collect::<Vec<u8>>();
 		if lookahead.is_some() && lookahead.unwrap() == 0 {
-            self.dasm_lines.push(DasmLine::basic(addr,self.modify("ASC"),Operand::txt([delim,&s,delim,",00"].concat())));
+            bytes.push(0);
+            self.dasm_lines.push(DasmLine::basic(addr,self.modify("ASC"),Operand::txt([delim,&s,delim,",00"].concat()),bytes));
 			return 1;
 		}
 		if lookahead.is_some() && probably_string(lookahead.unwrap(), off) {
             let term = String::from_utf8(vec![lookahead.unwrap() - off]).expect(super::RCH);
-			self.dasm_lines.push(DasmLine::basic(addr,self.modify("DCI"),Operand::txt([delim,&s,&term,delim].concat())));
+            bytes.push(term.as_bytes()[0] + off);
+			self.dasm_lines.push(DasmLine::basic(addr,self.modify("DCI"),Operand::txt([delim,&s,&term,delim].concat()),bytes));
 			return 1;
 		}
-        self.dasm_lines.push(DasmLine::basic(addr, self.modify("ASC"), Operand::txt([delim, &s, delim].concat())));
+        self.dasm_lines.push(DasmLine::basic(addr, self.modify("ASC"), Operand::txt([delim, &s, delim].concat()), bytes));
 		return 0;
     }
 	fn try_data_run(&mut self, img: &[u8], mut ptr: usize, end: usize) -> usize {
@@ -266,7 +351,7 @@ impl Disassembler {
 			pat4.0 -= pat4.0 % 4;
 		}
 		if uniform.0 > 0 && uniform.0 >= pat2.0 && uniform.0 >= pat4.0 && uniform.0 >= pos_str.0 && uniform.0 >= neg_str.0 {
-			self.push_data_psop(ptr0, "DS".to_string(), [uniform.0.to_string(), ",$".to_string(), vec![img[ptr0]].encode_hex_upper()].concat());
+			self.push_data_psop(ptr0, "DS".to_string(), [uniform.0.to_string(), ",$".to_string(), vec![img[ptr0]].encode_hex_upper()].concat(), &img[ptr0..ptr0+uniform.0]);
 			return uniform.0;
 		}
 		if pat2.0 > 0 && pat2.0 >= pat4.0 && pat2.0 >= pos_str.0 && pat2.0 >= neg_str.0 {
@@ -301,7 +386,12 @@ impl Disassembler {
     /// Check if we have a valid instruction and enough bytes left to accommodate it.
     /// This will return a `MachineOperation` that is modified for the current MX status.
     fn is_instruction(&self,val: u8,addr: usize,end: usize,proc: &ProcessorType) -> Option<(MachineOperation,usize)> {
-        if let Some(op) = self.dasm_map.get(&val) {
+        let maybe_op = match self.dasm_map.get(&val) {
+            Some(op) if op.processors.contains(proc) => Some(op),
+            _ if self.config.extensions.undocumented => self.undocumented_map.get(&val),
+            _ => None
+        };
+        if let Some(op) = maybe_op {
             if op.processors.contains(proc) {
                 if val!=0 || self.config.disassembly.brk {
                     let mut new_op = op.clone();
@@ -333,7 +423,9 @@ impl Disassembler {
     fn push_instruction(&mut self, img: &[u8], mut addr: usize, op: MachineOperation, operand_bytes: usize) -> Result<usize,DYNERR> {
         let mut new_line = DasmLine::new();
         new_line.address = addr;
+        new_line.bytes = img[addr..addr+1+operand_bytes].to_vec();
         new_line.instruction = self.modify(&op.mnemonic);
+        new_line.extended = self.undocumented_map.get(&img[addr]).is_some_and(|u| u.mnemonic == op.mnemonic);
         addr += 1;
         if self.mov_patt.is_match(&op.operand_snippet) {
             new_line.operand = Some(Operand::mov(img[addr+1],img[addr]));
@@ -375,7 +467,30 @@ impl Disassembler {
         self.dasm_lines.push(new_line);
         Ok(addr)
     }
+    /// Operand text with the address replaced by an internal label (`prefix` followed by hex digits),
+    /// or by an expression from the symbol database.  In the latter case the comment is also returned,
+    /// and the base address of the symbol is added to `equates`.
+    fn annotate_operand(&self, operand: &Operand, labels: &HashSet<usize>, prefix: &str, pc_bytes: usize, equates: &mut BTreeMap<usize,String>) -> (String,Option<String>) {
+        if operand.num.len() != 1 || operand.txt.starts_with("#") {
+            return (operand.txt.clone(),None);
+        }
+        let addr = operand.num[0] as usize;
+        if labels.contains(&addr) {
+            let label = [prefix.to_string(),hex_from_val("",addr as u32,pc_bytes)].concat();
+            return (self.hex_patt.replace(&operand.txt,regex::NoExpand(&label)).to_string(),None);
+        }
+        if let Some(db) = &self.rom_symbols {
+            if let (Some((base,entry)),Some(expr)) = (db.lookup(addr),db.expression(addr)) {
+                equates.insert(base,entry.label.clone());
+                return (self.hex_patt.replace(&operand.txt,regex::NoExpand(&expr)).to_string(),entry.brief.clone());
+            }
+        }
+        (operand.txt.clone(),None)
+    }
     fn format_lines(&self,labeling: &str) -> String {
+        if self.syntax != Syntax::Merlin {
+            return self.format_foreign(labeling);
+        }
         let mut last_addr = usize::MAX;
         let widths = [self.config.columns.c1 as usize,self.config.columns.c2 as usize,self.config.columns.c3 as usize];
         let pc_bytes = match self.dasm_lines.iter().map(|x| x.address > 0xffff).collect::<Vec<bool>>().contains(&true) {
@@ -383,22 +498,8 @@ impl Disassembler {
             false => 2
         };
         let mut code = String::new();
-        let mut labels = HashSet::new();
-        // gather references
-        let mut references = HashSet::new();
-        for line in &self.dasm_lines {
-            for r in &line.references {
-                references.insert(*r);
-            }
-        }
-		// determine labels
-		for i in 0..self.dasm_lines.len()	{
-			if labeling.contains("all") {
-				labels.insert(self.dasm_lines[i].address);
-            } else if labeling.contains("some") && (i==0 || references.contains(&self.dasm_lines[i].address)) {
-				labels.insert(self.dasm_lines[i].address);
-            }
-		}
+        let mut equates = BTreeMap::new();
+        let labels = self.labeled_addresses(labeling);
         // loop over lines
 		for i in 0..self.dasm_lines.len() {
 			let mut line = String::new();
@@ -411,25 +512,52 @@ impl Disassembler {
             line += &self.dasm_lines[i].instruction;
             line += &self.dasm_lines[i].suffix;
             if let Some(operand) = &self.dasm_lines[i].operand {
+                let (txt,brief) = self.annotate_operand(operand, &labels, "_", pc_bytes, &mut equates);
                 line.push(super::COLUMN_SEPARATOR);
                 line += &self.dasm_lines[i].prefix;
-                if operand.num.len() == 1 && labels.contains(&(operand.num[0] as usize)) && !operand.txt.starts_with("#") {
-                    line += "_";
-                    line += &hex_from_val("",operand.num[0] as u32,pc_bytes);
-                } else {
-                    line += &operand.txt;
+                line += &txt;
+                if let Some(comment) = self.dasm_lines[i].comment.clone().or(brief) {
+                    line.push(super::COLUMN_SEPARATOR);
+                    line += "; ";
+                    line += &comment;
                 }
             }
 			line = formatter::format_tokens(&line, &formatter::ColumnStyle::Variable, widths);
 			code += &line;
             code += "\n";
 		}
-        code
+        let mut header = String::new();
+        for (addr,label) in equates {
+            let line = [label,super::COLUMN_SEPARATOR.to_string(),self.modify("EQU"),super::COLUMN_SEPARATOR.to_string(),equate_value(addr)].concat();
+            header += &formatter::format_tokens(&line, &formatter::ColumnStyle::Variable, widths);
+            header += "\n";
+        }
+        header + &code
+    }
+    /// Addresses that should be labeled according to `labeling`, which may contain "all", "some", or "none".
+    fn labeled_addresses(&self,labeling: &str) -> HashSet<usize> {
+        let mut labels = HashSet::new();
+        // gather references
+        let mut references = HashSet::new();
+        for line in &self.dasm_lines {
+            for r in &line.references {
+                references.insert(*r);
+            }
+        }
+		// determine labels
+		for i in 0..self.dasm_lines.len()	{
+			if labeling.contains("all") || labeling.contains("some") && (i==0 || references.contains(&self.dasm_lines[i].address)) {
+				labels.insert(self.dasm_lines[i].address);
+            }
+		}
+        labels
     }
     /// Disassemble a range of bytes within `img`, which can be thought of as a RAM image.
     /// If the data source is a file, the data should be copied to `img` at the appropriate offset.
     /// In particular, the starting address will be taken as `range[0]`.
     /// Data sections are triggered by any failure to match an instruction.
+    /// Inline data following MLI calls, and RWTS parameter blocks, are decoded as such.
+    /// If SWEET16 is enabled in the settings, the code following `JSR $F689` is decoded as SWEET16.
 	pub fn disassemble(&mut self, img: &[u8], range: DasmRange, proc: ProcessorType, labeling: &str) -> Result<String,DYNERR> {
         let addr_range = match range {
             DasmRange::All => [0,img.len()],
@@ -439,15 +567,22 @@ impl Disassembler {
         };
 		let mut addr = addr_range[0];
 
+        self.proc = proc.clone();
 		self.dasm_lines = Vec::new();
+        self.find_call_blocks(img, addr_range[0], addr_range[1]);
 		while addr < addr_range[1] {
-            if let Some((op,operand_bytes)) = self.is_instruction(img[addr],addr,addr_range[1],&proc) {
+            let block_bytes = self.try_call_block(img, addr, addr_range[1]);
+            if block_bytes > 0 {
+                addr += block_bytes;
+            } else if let Some((op,operand_bytes)) = self.is_instruction(img[addr],addr,addr_range[1],&proc) {
                 addr = self.push_instruction(img, addr, op, operand_bytes)?;
+                addr += self.try_mli_call(img, addr, addr_range[1]);
+                addr += self.try_sweet16(img, addr, addr_range[1]);
 			} else {
 				let data_bytes = self.try_data_run(img, addr, addr_range[1]);
 				addr += data_bytes;
 				if data_bytes == 0 {
-					self.push_data_psop(addr, self.modify("DFB"), hex_from_val("$",img[addr] as u32,1));
+					self.push_data_psop(addr, self.modify("DFB"), hex_from_val("$",img[addr] as u32,1), &img[addr..addr+1]);
 					addr += 1;
 				}
 			}
@@ -470,7 +605,7 @@ impl Disassembler {
 			let data_bytes = self.try_data_run(&img, addr, img.len());
 			addr += data_bytes;
             if data_bytes == 0 {
-                self.push_data_psop(addr, self.modify("DFB"), hex_from_val("$",img[addr] as u32,1));
+                self.push_data_psop(addr, self.modify("DFB"), hex_from_val("$",img[addr] as u32,1), &img[addr..addr+1]);
                 addr += 1;
             }
 		}
@@ -494,7 +629,7 @@ impl Disassembler {
                     continue
                 }
 			}
-            self.push_data_psop(addr, self.modify("DFB"), hex_from_val("$",img[addr] as u32,1));
+            self.push_data_psop(addr, self.modify("DFB"), hex_from_val("$",img[addr] as u32,1), &img[addr..addr+1]);
             addr += 1;
 		}
         self.format_lines(match self.pc { Some(_) => "some", None => "none"})
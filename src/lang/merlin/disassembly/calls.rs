@@ -0,0 +1,161 @@
+//! Recognition of operating system call conventions that put data where code is expected.
+//!
+//! * ProDOS MLI calls are `JSR $BF00` followed by the call number and a pointer to the parameter list.
+//! * DOS 3.3 RWTS calls load the address of an IOB (input/output block) into A (high) and Y (low) and call `$03D9` or `$B7B5`.
+//!   The IOB, and the DCT (device characteristics table) it points to, are decoded field by field.
+//!
+//! If these are not recognized, the disassembly desynchronizes as the data is taken for instructions.
+
+use super::{Disassembler,DasmLine,Operand,hex_from_val};
+
+const MLI_CALLS: [(u8,&str);26] = [
+    (0x40,"ALLOC_INTERRUPT"),
+    (0x41,"DEALLOC_INTERRUPT"),
+    (0x65,"QUIT"),
+    (0x80,"READ_BLOCK"),
+    (0x81,"WRITE_BLOCK"),
+    (0x82,"GET_TIME"),
+    (0xc0,"CREATE"),
+    (0xc1,"DESTROY"),
+    (0xc2,"RENAME"),
+    (0xc3,"SET_FILE_INFO"),
+    (0xc4,"GET_FILE_INFO"),
+    (0xc5,"ON_LINE"),
+    (0xc6,"SET_PREFIX"),
+    (0xc7,"GET_PREFIX"),
+    (0xc8,"OPEN"),
+    (0xc9,"NEWLINE"),
+    (0xca,"READ"),
+    (0xcb,"WRITE"),
+    (0xcc,"CLOSE"),
+    (0xcd,"FLUSH"),
+    (0xce,"SET_MARK"),
+    (0xcf,"GET_MARK"),
+    (0xd0,"SET_EOF"),
+    (0xd1,"GET_EOF"),
+    (0xd2,"SET_BUF"),
+    (0xd3,"GET_BUF")
+];
+
+pub(super) const MLI_ENTRY: [u8;3] = [0x20,0x00,0xbf];
+const RWTS_ENTRIES: [[u8;3];2] = [[0x20,0xd9,0x03],[0x20,0xb5,0xb7]];
+
+/// IOB fields as (bytes,comment), pointers are 2 bytes
+const IOB_FIELDS: [(usize,&str);14] = [
+    (1,"table type"),
+    (1,"slot times 16"),
+    (1,"drive"),
+    (1,"volume expected"),
+    (1,"track"),
+    (1,"sector"),
+    (2,"DCT pointer"),
+    (2,"buffer pointer"),
+    (2,"unused"),
+    (1,"command"),
+    (1,"return code"),
+    (1,"volume found"),
+    (1,"previous slot times 16"),
+    (1,"previous drive")
+];
+const IOB_LEN: usize = 17;
+
+const DCT_FIELDS: [(usize,&str);3] = [
+    (1,"device type"),
+    (1,"phases per track"),
+    (2,"motor on time")
+];
+const DCT_LEN: usize = 4;
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum CallBlock {
+    Iob,
+    Dct
+}
+
+fn rwts_command(code: u8) -> Option<&'static str> {
+    match code {
+        0 => Some("SEEK"),
+        1 => Some("READ"),
+        2 => Some("WRITE"),
+        4 => Some("FORMAT"),
+        _ => None
+    }
+}
+
+impl Disassembler {
+    /// Scan `img[beg..end]` for RWTS calls, and record the IOB and DCT addresses that are within the range
+    pub(super) fn find_call_blocks(&mut self, img: &[u8], beg: usize, end: usize) {
+        self.call_blocks.clear();
+        let mut iobs = Vec::new();
+        for i in beg..end.saturating_sub(6) {
+            let setup = &img[i..i+4];
+            let (lo,hi) = match (setup[0],setup[2]) {
+                (0xa0,0xa9) => (setup[1],setup[3]),
+                (0xa9,0xa0) => (setup[3],setup[1]),
+                _ => continue
+            };
+            if RWTS_ENTRIES.iter().any(|e| e[..] == img[i+4..i+7]) {
+                let iob = u16::from_le_bytes([lo,hi]) as usize;
+                if iob >= beg && iob + IOB_LEN <= end {
+                    iobs.push(iob);
+                }
+            }
+        }
+        for iob in iobs {
+            self.call_blocks.insert(iob,CallBlock::Iob);
+            let dct = u16::from_le_bytes([img[iob+6],img[iob+7]]) as usize;
+            if dct >= beg && dct + DCT_LEN <= end && !self.call_blocks.contains_key(&dct) {
+                self.call_blocks.insert(dct,CallBlock::Dct);
+            }
+        }
+    }
+    fn push_field(&mut self, img: &[u8], addr: usize, bytes: usize, comment: String) {
+        let mut line = match bytes {
+            2 => {
+                let val = u16::from_le_bytes([img[addr],img[addr+1]]) as u32;
+                let mut line = DasmLine::basic(addr, self.modify("DA"), Operand::abs_addr(val,2,"2"), img[addr..addr+2].to_vec());
+                line.references.push(val as usize);
+                line
+            },
+            _ => DasmLine::basic(addr, self.modify("DFB"), Operand::txt(hex_from_val("$",img[addr] as u32,1)), img[addr..addr+1].to_vec())
+        };
+        line.comment = Some(comment);
+        self.dasm_lines.push(line);
+    }
+    /// If an IOB or DCT starts at `addr`, push its fields and return the number of bytes, otherwise return 0
+    pub(super) fn try_call_block(&mut self, img: &[u8], mut addr: usize, end: usize) -> usize {
+        let fields = match self.call_blocks.get(&addr) {
+            Some(CallBlock::Iob) if addr + IOB_LEN <= end => IOB_FIELDS.to_vec(),
+            Some(CallBlock::Dct) if addr + DCT_LEN <= end => DCT_FIELDS.to_vec(),
+            _ => return 0
+        };
+        let addr0 = addr;
+        for (bytes,comment) in fields {
+            let comment = match (comment,rwts_command(img[addr])) {
+                ("command",Some(cmd)) => ["command (",cmd,")"].concat(),
+                _ => comment.to_string()
+            };
+            self.push_field(img, addr, bytes, comment);
+            addr += bytes;
+        }
+        addr - addr0
+    }
+    /// If the instruction just before `addr` is an MLI call, push the call number and parameter list pointer,
+    /// and return the number of bytes, otherwise return 0
+    pub(super) fn try_mli_call(&mut self, img: &[u8], addr: usize, end: usize) -> usize {
+        let is_mli = match self.dasm_lines.last() {
+            Some(line) => line.bytes == MLI_ENTRY,
+            None => false
+        };
+        if !is_mli || addr + 3 > end {
+            return 0;
+        }
+        let name = match MLI_CALLS.iter().find(|(code,_)| *code == img[addr]) {
+            Some((_,name)) => name.to_string(),
+            None => "unknown MLI call".to_string()
+        };
+        self.push_field(img, addr, 1, name);
+        self.push_field(img, addr + 1, 2, "parameter list".to_string());
+        3
+    }
+}
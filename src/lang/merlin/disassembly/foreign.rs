@@ -0,0 +1,274 @@
+//! Translation of disassembled lines into the syntax of other assemblers.
+//!
+//! The lines are first produced as Merlin lines, which are then translated one at a time.
+//! Labels take the form `LXXXX` since not every assembler accepts a leading underscore.
+//! Strings are kept as strings where the target has a way to express them, otherwise they become bytes.
+//! Instructions that need a forced address size are kept as instructions if the target has a way
+//! to force the size, otherwise they become bytes.  SWEET16 and undocumented operations always become bytes.
+
+use std::collections::BTreeMap;
+use hex::ToHex;
+use super::{Disassembler,DasmLine,Syntax,hex_from_val,equate_value};
+use crate::lang::merlin::ProcessorType;
+
+/// bytes per line when data is written out
+const BYTES_PER_LINE: usize = 16;
+
+impl Syntax {
+    fn label_def(&self, name: &str) -> String {
+        match self {
+            Syntax::Ca65 => [name,":"].concat(),
+            _ => name.to_string()
+        }
+    }
+    fn bytes(&self, bytes: &[u8]) -> (String,String) {
+        let list = bytes.iter().map(|b| hex_from_val("$",*b as u32,1)).collect::<Vec<String>>().join(",");
+        match self {
+            Syntax::Scasm => (".HS".to_string(),bytes.encode_hex_upper()),
+            Syntax::Ca65 => (".byte".to_string(),list),
+            _ => ("!byte".to_string(),list)
+        }
+    }
+    fn equate(&self, name: &str, val: &str) -> (String,String,String) {
+        match self {
+            Syntax::Scasm => (name.to_string(),".EQ".to_string(),val.to_string()),
+            _ => (name.to_string(),"=".to_string(),val.to_string())
+        }
+    }
+    fn word(&self) -> String {
+        match self {
+            Syntax::Scasm => ".DA".to_string(),
+            Syntax::Ca65 => ".word".to_string(),
+            _ => "!word".to_string()
+        }
+    }
+    fn fill(&self, count: usize, val: u8) -> Option<(String,String)> {
+        let operand = [count.to_string(),",".to_string(),hex_from_val("$",val as u32,1)].concat();
+        match self {
+            Syntax::Ca65 => Some((".res".to_string(),operand)),
+            Syntax::Acme => Some(("!fill".to_string(),operand)),
+            _ => None
+        }
+    }
+    /// String with the high bit of every character as given by `neg`, and the last character inverted if `dci`.
+    /// The `zero` flag adds a terminating zero.
+    fn string(&self, txt: &str, neg: bool, dci: bool, zero: bool) -> Option<(String,String)> {
+        let delim = match txt.contains('"') {
+            true => "/",
+            false => "\""
+        };
+        match self {
+            Syntax::Scasm if !(zero || txt.contains('"') && txt.contains('/')) => {
+                let psop = match dci { true => ".AT", false => ".AS" };
+                let sign = match neg { true => "-", false => "" };
+                Some((psop.to_string(),[sign,delim,txt,delim].concat()))
+            },
+            Syntax::Ca65 if !neg && !dci && !txt.contains('"') && !txt.contains('\\') => {
+                let term = match zero { true => ",$00", false => "" };
+                Some((".byte".to_string(),["\"",txt,"\"",term].concat()))
+            },
+            Syntax::Acme if !neg && !dci && !txt.contains('"') && !txt.contains('\\') => {
+                let term = match zero { true => ",0", false => "" };
+                Some(("!text".to_string(),["\"",txt,"\"",term].concat()))
+            },
+            _ => None
+        }
+    }
+    fn header(&self, proc: &ProcessorType, org: usize, m8bit: bool, x8bit: bool) -> Vec<(String,String)> {
+        let mut ans = Vec::new();
+        let native = *proc == ProcessorType::_65802 || *proc == ProcessorType::_65c816;
+        match self {
+            Syntax::Scasm => {
+                match proc {
+                    ProcessorType::_6502 => {},
+                    ProcessorType::_65c02 => ans.push((".OP".to_string(),"65C02".to_string())),
+                    _ => ans.push((".OP".to_string(),"65802".to_string()))
+                }
+                ans.push((".OR".to_string(),hex_from_val("$",org as u32,2)));
+            },
+            Syntax::Ca65 => {
+                let cpu = match proc {
+                    ProcessorType::_6502 => "\"6502\"",
+                    ProcessorType::_65c02 => "\"65C02\"",
+                    _ => "\"65816\""
+                };
+                ans.push((".setcpu".to_string(),cpu.to_string()));
+                ans.push((".org".to_string(),hex_from_val("$",org as u32,2)));
+                if native && !m8bit {
+                    ans.push((".a16".to_string(),String::new()));
+                }
+                if native && !x8bit {
+                    ans.push((".i16".to_string(),String::new()));
+                }
+            },
+            Syntax::Acme => {
+                let cpu = match proc {
+                    ProcessorType::_6502 => "6502",
+                    ProcessorType::_65c02 => "65c02",
+                    _ => "65816"
+                };
+                ans.push(("!cpu".to_string(),cpu.to_string()));
+                ans.push((["* = ",&hex_from_val("$",org as u32,2)].concat(),String::new()));
+                if native && !m8bit {
+                    ans.push(("!al".to_string(),String::new()));
+                }
+                if native && !x8bit {
+                    ans.push(("!rl".to_string(),String::new()));
+                }
+            },
+            Syntax::Merlin => {}
+        }
+        ans
+    }
+}
+
+impl Disassembler {
+    fn foreign_label(&self, addr: usize, pc_bytes: usize) -> String {
+        ["L".to_string(),hex_from_val("",addr as u32,pc_bytes)].concat()
+    }
+    fn foreign_line(&self, label: &str, ins: &str, operand: &str) -> String {
+        let widths = [self.config.columns.c1 as usize,self.config.columns.c2 as usize];
+        let mut ans = label.to_string();
+        if ans.len() >= widths[0] {
+            ans += " ";
+        } else {
+            ans += &" ".repeat(widths[0] - ans.len());
+        }
+        ans += ins;
+        if !operand.is_empty() {
+            ans += &" ".repeat(1 + widths[1].saturating_sub(ins.len() + 1));
+            ans += operand;
+        }
+        ans
+    }
+    /// Add a comment to a line, the comment starts after the third column if possible
+    fn foreign_comment(&self, line: &str, comment: &str) -> String {
+        let start = (self.config.columns.c1 + self.config.columns.c2 + self.config.columns.c3) as usize;
+        let mut ans = line.to_string();
+        ans += &" ".repeat(1 + start.saturating_sub(line.len() + 1));
+        ans += "; ";
+        ans += comment;
+        ans
+    }
+    /// Translate one line of data, returns (instruction,operand) pairs, or `None` if this is not a data line
+    fn foreign_data(&self, line: &DasmLine) -> Option<Vec<(String,String)>> {
+        let syn = self.syntax;
+        let mut ans = Vec::new();
+        let as_bytes = |bytes: &[u8]| bytes.chunks(BYTES_PER_LINE).map(|c| syn.bytes(c)).collect::<Vec<(String,String)>>();
+        match line.instruction.to_uppercase().as_str() {
+            "HEX" | "DFB" => ans.append(&mut as_bytes(&line.bytes)),
+            "DS" => match syn.fill(line.bytes.len(),line.bytes[0]) {
+                Some(pair) => ans.push(pair),
+                None => ans.append(&mut as_bytes(&line.bytes))
+            },
+            "ASC" | "DCI" => {
+                let dci = line.instruction.to_uppercase() == "DCI";
+                let zero = !dci && line.bytes.last() == Some(&0);
+                let chars = match zero {
+                    true => &line.bytes[0..line.bytes.len()-1],
+                    false => &line.bytes[..]
+                };
+                let txt = String::from_utf8(chars.iter().map(|c| c & 0x7f).collect()).unwrap_or_default();
+                let neg = chars.first().is_some_and(|c| c & 0x80 > 0);
+                match syn.string(&txt, neg, dci, zero) {
+                    Some(pair) => ans.push(pair),
+                    None if syn == Syntax::Scasm && zero => {
+                        // S-C strings cannot have the terminator in the same line
+                        match syn.string(&txt, neg, dci, false) {
+                            Some(pair) => ans.push(pair),
+                            None => ans.append(&mut as_bytes(chars))
+                        }
+                        ans.push(syn.bytes(&[0]));
+                    },
+                    None => ans.append(&mut as_bytes(&line.bytes))
+                }
+            },
+            _ => return None
+        }
+        Some(ans)
+    }
+    /// Translate an instruction, returns an (instruction,operand) pair
+    fn foreign_instruction(&self, line: &DasmLine, operand: &str) -> (String,String) {
+        let force_abs = line.suffix == ":";
+        let force_long = line.suffix.to_uppercase() == "L" || line.prefix == ">";
+        let mut ins = line.instruction.clone();
+        let mut operand = operand.to_string();
+        if ins.to_uppercase() == "DA" {
+            return (self.syntax.word(),operand);
+        }
+        match self.syntax {
+            Syntax::Scasm if force_abs || force_long => return self.syntax.bytes(&line.bytes),
+            Syntax::Ca65 if force_abs => operand = ["a:",&operand].concat(),
+            Syntax::Ca65 if force_long => operand = ["f:",&operand].concat(),
+            Syntax::Acme if force_abs => ins += "+2",
+            Syntax::Acme if force_long => ins += "+3",
+            _ => {}
+        }
+        (ins,operand)
+    }
+    /// Format the lines using a syntax other than Merlin
+    pub(super) fn format_foreign(&self, labeling: &str) -> String {
+        let pc_bytes = match self.dasm_lines.iter().any(|x| x.address > 0xffff) {
+            true => 3,
+            false => 2
+        };
+        let labels = self.labeled_addresses(labeling);
+        let mut code = String::new();
+        if let Some(first) = self.dasm_lines.first() {
+            for (ins,operand) in self.syntax.header(&self.proc, first.address, self.m8bit, self.x8bit) {
+                code += &self.foreign_line("", &ins, &operand);
+                code += "\n";
+            }
+        }
+        let mut equates = BTreeMap::new();
+        let mut body = String::new();
+        let mut last_addr = usize::MAX;
+        for line in &self.dasm_lines {
+            let mut label = match labels.contains(&line.address) && line.address != last_addr {
+                true => self.syntax.label_def(&self.foreign_label(line.address, pc_bytes)),
+                false => String::new()
+            };
+            last_addr = line.address;
+            let mut comment = line.comment.clone();
+            let pairs = match self.foreign_data(line) {
+                Some(pairs) => pairs,
+                None if line.extended => {
+                    // keep the Merlin form as a comment
+                    let operand = match &line.operand {
+                        Some(op) => [" ",&op.txt].concat(),
+                        None => String::new()
+                    };
+                    comment = comment.or(Some([line.instruction.clone(),operand].concat()));
+                    vec![self.syntax.bytes(&line.bytes)]
+                },
+                None => {
+                    let operand = match &line.operand {
+                        Some(op) => {
+                            let (txt,brief) = self.annotate_operand(op, &labels, "L", pc_bytes, &mut equates);
+                            comment = comment.or(brief);
+                            txt
+                        },
+                        None => String::new()
+                    };
+                    vec![self.foreign_instruction(line, &operand)]
+                }
+            };
+            for (ins,operand) in pairs {
+                let formatted = self.foreign_line(&label, &ins, &operand);
+                body += &match comment.take() {
+                    Some(c) => self.foreign_comment(&formatted, &c),
+                    None => formatted
+                };
+                body += "\n";
+                label = String::new();
+            }
+        }
+        for (addr,name) in equates {
+            let (label,ins,operand) = self.syntax.equate(&name, &equate_value(addr));
+            code += &self.foreign_line(&label, &ins, &operand);
+            code += "\n";
+        }
+        code += &body;
+        code
+    }
+}
@@ -0,0 +1,124 @@
+//! Following the execution paths of a binary from its entry points.
+//!
+//! This is a sanity check for assembled binaries rather than a disassembly.  Every path is followed
+//! until it returns, jumps away, or enters SWEET16.  Branches and subroutine calls that land inside
+//! the binary are followed as well.  Jumps and calls outside the binary are assumed to go to the
+//! ROM or operating system.
+
+use std::collections::HashSet;
+use super::{Disassembler,calls::MLI_ENTRY,u32_from_operand};
+use crate::lang::merlin::ProcessorType;
+use crate::lang::merlin::handbook::operations::OperationHandbook;
+use crate::lang::merlin::handbook::sweet16;
+
+/// Something wrong on a path that starts at `entry`
+#[derive(Clone,PartialEq,Debug)]
+pub enum PathFault {
+    /// the byte at `addr` is not an instruction for the processor
+    Illegal { entry: usize, addr: usize, opcode: u8 },
+    /// the path reaches `addr`, which is outside the binary, without returning or jumping
+    OutOfBounds { entry: usize, addr: usize }
+}
+
+impl std::fmt::Display for PathFault {
+    fn fmt(&self,f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Illegal { entry, addr, opcode } => write!(f,"entry ${:04X}: illegal opcode ${:02X} at ${:04X}",entry,opcode,addr),
+            Self::OutOfBounds { entry, addr } => write!(f,"entry ${:04X}: path leaves the binary at ${:04X}",entry,addr)
+        }
+    }
+}
+
+/// Result of following the paths from all the entry points
+pub struct PathReport {
+    /// number of distinct instructions reached
+    pub instructions: usize,
+    pub faults: Vec<PathFault>
+}
+
+impl Disassembler {
+    /// Follow every path from `entries` through the binary occupying `range` of `img`.
+    /// A path ends at `RTS`, `RTI`, `RTL`, `BRK` (if enabled), `STP`, an unconditional jump,
+    /// or the entry into SWEET16.  The inline parameters of MLI calls are skipped.
+    pub fn trace_entry_points(&mut self, img: &[u8], range: [usize;2], entries: &[usize], proc: ProcessorType) -> PathReport {
+        let [beg,end] = range;
+        let in_range = |addr: usize| addr >= beg && addr < end;
+        self.proc = proc.clone();
+        let mut visited = HashSet::new();
+        let mut faults = Vec::new();
+        let mut instructions = 0;
+        for entry in entries {
+            let mut pending = vec![*entry];
+            while let Some(mut addr) = pending.pop() {
+                loop {
+                    if !in_range(addr) {
+                        faults.push(PathFault::OutOfBounds { entry: *entry, addr });
+                        break;
+                    }
+                    if !visited.insert(addr) {
+                        break;
+                    }
+                    let (op,operand_bytes) = match self.is_instruction(img[addr],addr,end,&proc) {
+                        Some(x) => x,
+                        None => {
+                            match self.is_instruction(img[addr],addr,usize::MAX,&proc) {
+                                Some(_) => faults.push(PathFault::OutOfBounds { entry: *entry, addr: end }),
+                                None => faults.push(PathFault::Illegal { entry: *entry, addr, opcode: img[addr] })
+                            }
+                            break;
+                        }
+                    };
+                    instructions += 1;
+                    let next = addr + 1 + operand_bytes;
+                    let val = u32_from_operand(&img[addr+1..next]) as usize;
+                    let immediate_or_indirect = op.operand_snippet.starts_with(['#','(','[']);
+                    let target = match (op.relative,immediate_or_indirect,operand_bytes) {
+                        (true,_,_) => OperationHandbook::rel_to_abs(addr,val,operand_bytes),
+                        (false,false,2..) => Some(val),
+                        _ => None
+                    };
+                    match op.mnemonic.to_uppercase().as_str() {
+                        "RTS" | "RTI" | "RTL" | "BRK" | "STP" => break,
+                        "JMP" | "JML" => {
+                            if let Some(dest) = target.filter(|t| in_range(*t)) {
+                                pending.push(dest);
+                            }
+                            break;
+                        },
+                        "JSR" | "JSL" => {
+                            if let Some(dest) = target.filter(|t| in_range(*t)) {
+                                pending.push(dest);
+                            }
+                            if target==Some(sweet16::ENTRY as usize) {
+                                break;
+                            }
+                            addr = match img[addr..next]==MLI_ENTRY {
+                                true => next + 3,
+                                false => next
+                            };
+                        },
+                        "BRA" | "BRL" => {
+                            match target {
+                                Some(dest) => pending.push(dest),
+                                None => faults.push(PathFault::OutOfBounds { entry: *entry, addr })
+                            }
+                            break;
+                        },
+                        _ if op.relative => {
+                            match target {
+                                Some(dest) => pending.push(dest),
+                                None => faults.push(PathFault::OutOfBounds { entry: *entry, addr })
+                            }
+                            addr = next;
+                        },
+                        _ => addr = next
+                    }
+                }
+            }
+        }
+        PathReport {
+            instructions,
+            faults
+        }
+    }
+}
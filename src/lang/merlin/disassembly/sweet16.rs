@@ -0,0 +1,66 @@
+//! Decoding of SWEET16 code, which follows `JSR $F689` and runs until `RTN`.
+//!
+//! The operations are written as in the original listing, e.g., `SET R1,$0800` or `LD @R1`,
+//! which the assembler accepts when SWEET16 is enabled, see `handbook::sweet16`.
+
+use super::{Disassembler,DasmLine,Operand,hex_from_val};
+use crate::lang::merlin::handbook::operations::OperationHandbook;
+use crate::lang::merlin::handbook::sweet16::{self,Form};
+
+impl Disassembler {
+    /// If SWEET16 is enabled and the instruction just before `addr` enters SWEET16, push the SWEET16 operations
+    /// up to and including `RTN`, and return the number of bytes, otherwise return 0.
+    /// Decoding stops early at an undefined opcode or a branch that is out of bounds.
+    pub(super) fn try_sweet16(&mut self, img: &[u8], mut addr: usize, end: usize) -> usize {
+        let [lo,hi] = u16::to_le_bytes(sweet16::ENTRY);
+        let entered = match self.dasm_lines.last() {
+            Some(line) => line.bytes == [0x20,lo,hi],
+            None => false
+        };
+        if !self.config.extensions.sweet16 || !entered {
+            return 0;
+        }
+        let addr0 = addr;
+        while addr < end {
+            let (mnemonic,form,reg) = match sweet16::decode(img[addr]) {
+                Some(x) => x,
+                None => break
+            };
+            let operand_bytes = match form {
+                Form::Constant => 2,
+                Form::Branch => 1,
+                _ => 0
+            };
+            if addr + 1 + operand_bytes > end {
+                break;
+            }
+            let mut line = DasmLine::new();
+            line.address = addr;
+            line.instruction = self.modify(mnemonic);
+            line.bytes = img[addr..addr+1+operand_bytes].to_vec();
+            line.extended = true;
+            line.operand = match form {
+                Form::Implied => None,
+                Form::Register => Some(Operand::txt(format!("R{}",reg))),
+                Form::Indirect => Some(Operand::txt(format!("@R{}",reg))),
+                Form::Constant => {
+                    let val = u16::from_le_bytes([img[addr+1],img[addr+2]]) as u32;
+                    Some(Operand::txt(format!("R{},{}",reg,hex_from_val("$",val,2))))
+                },
+                Form::Branch => match OperationHandbook::rel_to_abs(addr, img[addr+1] as usize, 1) {
+                    Some(dest) => {
+                        line.references.push(dest);
+                        Some(Operand::rel_addr(dest as u32,1,"1"))
+                    },
+                    None => break
+                }
+            };
+            self.dasm_lines.push(line);
+            addr += 1 + operand_bytes;
+            if mnemonic == "rtn" {
+                break;
+            }
+        }
+        addr - addr0
+    }
+}
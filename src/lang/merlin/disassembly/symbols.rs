@@ -0,0 +1,127 @@
+//! Database of well known addresses, used to annotate disassembly.
+//!
+//! The database is organized in sets that can be selected independently:
+//! * `appleii` - Monitor, Applesoft, and soft switches, taken from the `a2_memory_map` crate
+//! * `prodos` - MLI entry point and the system global page
+//! * `dos33` - DOS vectors in page 3, and the RWTS parameter block
+//! * `iigs` - toolbox and GS/OS entry points, and the IIgs registers
+//!
+//! An entry can cover several bytes, e.g. a vector, in which case references to later bytes
+//! are expressed as an offset from the label.
+
+use std::collections::HashMap;
+use a2_memory_map as a2memory;
+use crate::lang;
+use crate::DYNERR;
+
+const JSON_STR: &str = include_str!("symbols.json");
+
+pub const SETS: [&str;4] = ["appleii","prodos","dos33","iigs"];
+
+#[derive(Clone)]
+pub struct Entry {
+    pub label: String,
+    pub brief: Option<String>,
+    pub bytes: usize
+}
+
+#[derive(Clone,Default)]
+pub struct SymbolDatabase {
+    map: HashMap<usize,Entry>
+}
+
+/// Merlin labels have to start with a letter, which also suits the other assemblers
+fn is_valid_label(label: &str) -> bool {
+    let mut chars = label.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false
+    }
+}
+
+impl SymbolDatabase {
+    /// Create an empty database
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Create a database from a comma separated list of sets, such as `appleii,prodos`
+    pub fn from_sets(sets: &str) -> Result<Self,DYNERR> {
+        let mut ans = Self::new();
+        for set in sets.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            match set {
+                "appleii" => ans.add_memory_map(),
+                s if SETS.contains(&s) => ans.add_json_set(s),
+                s => {
+                    log::error!("unknown symbol set {}, choices are {}",s,SETS.join(","));
+                    return Err(Box::new(lang::Error::Syntax));
+                }
+            }
+        }
+        Ok(ans)
+    }
+    fn add_memory_map(&mut self) {
+        let main_map = a2memory::MemoryMap::new();
+        for addr in main_map.get_all().keys() {
+            // first context wins if the address is overloaded
+            let info = match main_map.get_one_and_split(*addr as i64) {
+                Some(list) if !list.is_empty() => list[0].clone(),
+                _ => continue
+            };
+            let label = match &info.label {
+                Some(l) if is_valid_label(l) => l.to_string(),
+                _ => continue
+            };
+            let bytes = match info.typ.as_str() {
+                "word" => 2,
+                "vector" => 3,
+                "float" => 5,
+                "unpacked float" => 6,
+                _ => 1
+            };
+            let brief = info.brief.map(|s| s.replace('`',""));
+            self.map.insert(*addr as usize, Entry { label, brief, bytes });
+        }
+    }
+    /// This can panic if something is wrong with the JSON, but it practically never changes.
+    fn add_json_set(&mut self, set: &str) {
+        let root = serde_json::from_str::<serde_json::Value>(JSON_STR).expect(super::super::RCH);
+        if let Some(obj) = root[set].as_object() {
+            for (key,val) in obj {
+                let addr = usize::from_str_radix(&key[2..],16).expect("bad address in symbol database");
+                let label = val["label"].as_str().expect("missing label in symbol database").to_string();
+                let brief = val["brief"].as_str().map(|s| s.to_string());
+                let bytes = val["bytes"].as_u64().unwrap_or(1) as usize;
+                self.map.insert(addr, Entry { label, brief, bytes });
+            }
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+    /// Find the entry covering `addr`, returns the base address along with the entry
+    pub fn lookup(&self, addr: usize) -> Option<(usize,&Entry)> {
+        if let Some(entry) = self.map.get(&addr) {
+            return Some((addr,entry));
+        }
+        for offset in 1..32 {
+            if offset > addr {
+                break;
+            }
+            if let Some(entry) = self.map.get(&(addr - offset)) {
+                if entry.bytes > offset {
+                    return Some((addr - offset,entry));
+                }
+            }
+        }
+        None
+    }
+    /// Expression referring to `addr`, such as `CSW+1`
+    pub fn expression(&self, addr: usize) -> Option<String> {
+        match self.lookup(addr) {
+            Some((base,entry)) if base == addr => Some(entry.label.clone()),
+            Some((base,entry)) => Some(format!("{}+{}",entry.label,addr - base)),
+            None => None
+        }
+    }
+}
+
@@ -22,6 +22,14 @@ pub enum ColumnStyle {
     Tabs
 }
 
+/// Case convention for operations and pseudo-operations.
+/// Macro calls are not affected, since macro names are case sensitive.
+#[derive(Clone,Copy,PartialEq)]
+pub enum OpCase {
+    Upper,
+    Lower
+}
+
 /// Format line of code using given style.
 /// This relies on being able to split the line on COLUMN_SEPARATOR, as is the
 /// case after detokenization or disassembly. 
@@ -76,6 +84,12 @@ pub fn format_for_paste(program: String, tokenizer: &mut super::tokenizer::Token
     tokenizer.detokenize(&img)
 }
 
+/// Format an entire program using the style, widths, and case convention that are set in the tokenizer
+pub fn format_program(program: String, tokenizer: &mut super::tokenizer::Tokenizer) -> Result<String,DYNERR> {
+    let img = tokenizer.tokenize(program)?;
+    tokenizer.detokenize(&img)
+}
+
 pub fn format_range(program: String, sel: lsp::Range, tokenizer: &mut super::tokenizer::Tokenizer) -> Result<Vec<lsp::TextEdit>,DYNERR> {
     let mut formatted_range = String::new();
     let line_count = program.lines().count() as u32;
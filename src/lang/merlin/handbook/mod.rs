@@ -4,4 +4,5 @@
 //! and assembler pseudo-operations.
 
 pub mod operations;
-pub mod pseudo_ops;
\ No newline at end of file
+pub mod pseudo_ops;
+pub mod sweet16;
\ No newline at end of file
@@ -3,6 +3,8 @@ use super::super::{Operation,MachineOperation,ProcessorType,AddressMode};
 use crate::lang::{update_json_i64, update_json_string, update_json_vec_str};
 
 const JSON_STR: &str = include_str!("opcodes.json");
+/// Undocumented NMOS 6502 operations, only the ones that behave consistently
+const UNDOCUMENTED_JSON_STR: &str = include_str!("undocumented.json");
 
 /// Map from a machine addressing mode to the reduced modes used by the parser.
 const UNPARSING_MAP: [(&'static str,&'static str);26] =  [
@@ -181,9 +183,9 @@ fn setup_modifiers(mnemonic: &str, op: &mut Operation) {
 
 /// This can panic if something is wrong with the JSON.
 /// But the JSON practically never changes.
-fn build_operations() -> HashMap<String,Operation> {
+fn build_operations(json_str: &str) -> HashMap<String,Operation> {
     let mut ans = HashMap::new();
-    let root_val = serde_json::from_str::<serde_json::Value>(JSON_STR).unwrap();
+    let root_val = serde_json::from_str::<serde_json::Value>(json_str).unwrap();
     if let Some(root) = root_val.as_object() {
         for (mnemonic,info) in root {
             let m = M_STATUS.contains(&mnemonic.as_str());
@@ -210,7 +212,9 @@ pub struct OperationHandbook {
     ops_65802: HashSet<String>,
     ops_65c816: HashSet<String>,
     alternates: HashMap<String,String>,
-    ops: HashMap<String,Operation>
+    ops: HashMap<String,Operation>,
+    /// undocumented operations keyed by mnemonic and by each alternate
+    undocumented: HashMap<String,Operation>
 }
 
 impl AddressMode {
@@ -281,7 +285,14 @@ impl Operation {
 
 impl OperationHandbook {
     pub fn new() -> Self {
-        let ops = build_operations();
+        let ops = build_operations(JSON_STR);
+        let mut undocumented = HashMap::new();
+        for (key,op) in build_operations(UNDOCUMENTED_JSON_STR) {
+            for alt in &op.alt {
+                undocumented.insert(alt.to_owned(),op.clone());
+            }
+            undocumented.insert(key,op);
+        }
         let mut ops_6502 = HashSet::new();
         let mut ops_65c02 = HashSet::new();
         let mut ops_65802 = HashSet::new();
@@ -312,7 +323,8 @@ impl OperationHandbook {
             ops_65802,
             ops_65c816,
             alternates,
-            ops
+            ops,
+            undocumented
         }
     }
     fn normalize(mnemonic: &str) -> String {
@@ -350,6 +362,10 @@ impl OperationHandbook {
             None => self.ops.get(&norm).cloned()
         }
     }
+    /// Find an undocumented operation by its mnemonic or any of its alternates, case insensitive.
+    pub fn get_undocumented(&self,mnemonic_or_alt: &str) -> Option<Operation> {
+        self.undocumented.get(&mnemonic_or_alt.to_lowercase()).cloned()
+    }
     pub fn completion(&self,proc: &ProcessorType) -> Vec<String> {
         match proc {
             ProcessorType::_6502 => self.ops_6502.iter().map(|s| s.to_string()).collect(),
@@ -375,9 +391,21 @@ impl OperationHandbook {
     /// On creation, `MachineOperation::operand_snippet` assumes MX=11.
     /// The disassembler will mutate it as necessary.
     pub fn create_dasm_map(&self) -> HashMap<u8,MachineOperation> {
+        Self::dasm_map(&self.ops)
+    }
+    /// Map opcode to undocumented machine operation for disassembly purposes.
+    /// These are kept apart since the same opcodes are documented operations on later processors.
+    pub fn create_undocumented_dasm_map(&self) -> HashMap<u8,MachineOperation> {
+        let primary = self.undocumented.iter()
+            .filter(|(key,op)| !op.alt.contains(key))
+            .map(|(key,op)| (key.to_owned(),op.clone()))
+            .collect::<HashMap<String,Operation>>();
+        Self::dasm_map(&primary)
+    }
+    fn dasm_map(ops: &HashMap<String,Operation>) -> HashMap<u8,MachineOperation> {
         let mut ans = HashMap::new();
         let dasm_map: HashMap<&str,&str> = HashMap::from(DASM_MAP);
-        for (key,book_op) in &self.ops {
+        for (key,book_op) in ops {
             for mode in &book_op.modes {
                 let op = MachineOperation {
                     mnemonic: key.to_string(),
@@ -0,0 +1,173 @@
+//! SWEET16 handbook
+//!
+//! SWEET16 is the 16 bit interpreter in the Apple II monitor ROM.  It is entered with `JSR $F689`,
+//! and interprets the bytes that follow until `RTN`.  Registers are written `R0` through `R15`,
+//! and indirect operands are written `@R1`, as in the original listing.
+//!
+//! The Merlin grammar has no SWEET16 operations, so the assembler translates each SWEET16 line
+//! into a carrier line that the grammar accepts, and that produces code of the same length.
+//! After the carrier is assembled, its first byte is replaced by the SWEET16 opcode.
+//! * implied and register operations become `DFB`
+//! * `SET` becomes `JMP`, so the constant is always two bytes
+//! * branches become `BNE`, so the offset is computed and checked as usual
+//!
+//! Lines are only translated within a SWEET16 region, which starts after `JSR $F689` and ends after `RTN`,
+//! the same as in disassembly.  A mnemonic that is also a defined macro is always a macro call.
+
+/// `JSR` to this address starts SWEET16
+pub const ENTRY: u16 = 0xf689;
+
+/// Operand forms of SWEET16 operations
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Form {
+    /// no operand
+    Implied,
+    /// register, e.g. `LD R1`
+    Register,
+    /// indirect register, e.g. `LD @R1`
+    Indirect,
+    /// register and 16 bit constant, e.g. `SET R1,$1234`
+    Constant,
+    /// relative branch
+    Branch
+}
+
+/// (mnemonic,opcode,form), for forms with a register, the register goes in the low nibble
+const OPERATIONS: [(&str,u8,Form);28] = [
+    ("rtn",0x00,Form::Implied),
+    ("br",0x01,Form::Branch),
+    ("bnc",0x02,Form::Branch),
+    ("bc",0x03,Form::Branch),
+    ("bp",0x04,Form::Branch),
+    ("bm",0x05,Form::Branch),
+    ("bz",0x06,Form::Branch),
+    ("bnz",0x07,Form::Branch),
+    ("bm1",0x08,Form::Branch),
+    ("bnm1",0x09,Form::Branch),
+    ("bk",0x0a,Form::Implied),
+    ("rs",0x0b,Form::Implied),
+    ("bs",0x0c,Form::Branch),
+    ("set",0x10,Form::Constant),
+    ("ld",0x20,Form::Register),
+    ("st",0x30,Form::Register),
+    ("ld",0x40,Form::Indirect),
+    ("st",0x50,Form::Indirect),
+    ("ldd",0x60,Form::Indirect),
+    ("std",0x70,Form::Indirect),
+    ("pop",0x80,Form::Indirect),
+    ("stp",0x90,Form::Indirect),
+    ("add",0xa0,Form::Register),
+    ("sub",0xb0,Form::Register),
+    ("popd",0xc0,Form::Indirect),
+    ("cpr",0xd0,Form::Register),
+    ("inr",0xe0,Form::Register),
+    ("dcr",0xf0,Form::Register)
+];
+
+/// Get the mnemonic, form, and register of an opcode, or `None` if the opcode is undefined
+pub fn decode(code: u8) -> Option<(&'static str,Form,u8)> {
+    let (key,reg) = match code >> 4 {
+        0 => (code,0),
+        _ => (code & 0xf0,code & 0x0f)
+    };
+    OPERATIONS.iter().find(|(_,c,_)| *c == key).map(|(m,_,f)| (*m,*f,reg))
+}
+
+/// Parse a register such as `R5`, `5`, or `$5`
+fn register(txt: &str) -> Option<u8> {
+    let num = txt.strip_prefix(['R','r']).unwrap_or(txt);
+    let val = match num.strip_prefix('$') {
+        Some(hex) => u8::from_str_radix(hex,16).ok()?,
+        None => num.parse::<u8>().ok()?
+    };
+    match val < 16 {
+        true => Some(val),
+        false => None
+    }
+}
+
+/// Split off the first whitespace delimited field
+fn split_field(txt: &str) -> (&str,&str) {
+    match txt.find(char::is_whitespace) {
+        Some(i) => (&txt[..i],txt[i..].trim_start()),
+        None => (txt,"")
+    }
+}
+
+/// If `line` is a SWEET16 operation, return the carrier line and the opcode.
+/// Anything else, including a SWEET16 mnemonic with a malformed operand, gives `None`.
+pub fn translate(line: &str) -> Option<(String,u8)> {
+    let line = line.trim_end_matches(['\n','\r']);
+    if line.starts_with('*') || line.starts_with(';') {
+        return None;
+    }
+    let (label,rest) = split_field(line);
+    let (mnemonic,rest) = split_field(rest);
+    let (operand,comment) = match rest.starts_with(';') {
+        true => ("",rest),
+        false => split_field(rest)
+    };
+    let mnemonic = mnemonic.to_lowercase();
+    let candidates = OPERATIONS.iter().filter(|(m,_,_)| *m == mnemonic).collect::<Vec<_>>();
+    let has_register_form = candidates.iter().any(|(_,_,f)| *f == Form::Register);
+    let indirect = operand.strip_prefix('@');
+    for (_,code,form) in candidates {
+        let (carrier_op,carrier_operand,code) = match (form,indirect) {
+            (Form::Implied,_) if operand.is_empty() => ("DFB".to_string(),format!("${:02X}",code),*code),
+            (Form::Register,None) => {
+                let code = code | register(operand)?;
+                ("DFB".to_string(),format!("${:02X}",code),code)
+            },
+            (Form::Indirect,Some(reg)) => {
+                let code = code | register(reg)?;
+                ("DFB".to_string(),format!("${:02X}",code),code)
+            },
+            (Form::Indirect,None) if !has_register_form => {
+                let code = code | register(operand)?;
+                ("DFB".to_string(),format!("${:02X}",code),code)
+            },
+            (Form::Constant,None) => {
+                let (reg,expr) = operand.split_once(',')?;
+                ("JMP".to_string(),expr.to_string(),code | register(reg)?)
+            },
+            (Form::Branch,None) if !operand.is_empty() => ("BNE".to_string(),operand.to_string(),*code),
+            _ => continue
+        };
+        let carrier = [label," ",&carrier_op," ",&carrier_operand," ",comment].concat();
+        return Some((carrier.trim_end().to_string(),code));
+    }
+    None
+}
+
+/// Tracks whether a sequence of source lines is within a SWEET16 region, see the module notes
+#[derive(Clone,Copy,Default,Debug)]
+pub struct Region {
+    active: bool
+}
+
+impl Region {
+    /// If `line` is a SWEET16 operation within the region, return the carrier line and the opcode as in `translate`.
+    /// Either way the region moves past the line.  `is_macro` tells whether a mnemonic is a defined macro,
+    /// and `value` gives the value of a label, if it is known.
+    pub fn next(&mut self,line: &str,is_macro: impl Fn(&str) -> bool,value: impl Fn(&str) -> Option<i64>) -> Option<(String,u8)> {
+        let (_,rest) = split_field(line.trim_end_matches(['\n','\r']));
+        let (mnemonic,rest) = split_field(rest);
+        if !self.active {
+            let operand = split_field(rest).0;
+            let target = match operand.strip_prefix('$') {
+                Some(hex) => i64::from_str_radix(hex,16).ok(),
+                None => operand.parse::<i64>().ok().or_else(|| value(operand))
+            };
+            self.active = mnemonic.eq_ignore_ascii_case("jsr") && target == Some(ENTRY as i64);
+            return None;
+        }
+        if line.starts_with('*') || line.starts_with(';') || is_macro(mnemonic) {
+            return None;
+        }
+        let ans = translate(line);
+        if let Some((_,0x00)) = ans {
+            self.active = false;
+        }
+        ans
+    }
+}
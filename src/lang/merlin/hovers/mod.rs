@@ -2,6 +2,7 @@ use lsp_types as lsp;
 use std::sync::Arc;
 use super::settings::Settings;
 use super::Symbols;
+use super::imports::DiskSymbols;
 
 use crate::lang::{Navigation,Navigate,lsp_range,range_contains_pos,node_text};
 use crate::lang::server::{Hovers,path_in_workspace};
@@ -18,6 +19,7 @@ pub struct HoverProvider {
     pos: lsp::Position,
     rng: lsp::Range,
     symbols: Arc<Symbols>,
+    disk_symbols: Arc<DiskSymbols>,
     addresses: hovers_addresses::AddressHovers,
     statements: hovers_statements::StatementHovers
 }
@@ -43,6 +45,7 @@ impl HoverProvider {
             pos: zero.clone(),
             rng: lsp::Range::new(zero.clone(),zero),
             symbols: Arc::new(Symbols::new()),
+            disk_symbols: Arc::new(DiskSymbols::new()),
             addresses: hovers_addresses::AddressHovers::new(),
             statements: hovers_statements::StatementHovers::new()
         }
@@ -53,6 +56,9 @@ impl HoverProvider {
     pub fn use_shared_symbols(&mut self,sym: Arc<Symbols>) {
         self.symbols = sym;
     }
+    pub fn use_disk_symbols(&mut self,sym: Arc<DiskSymbols>) {
+        self.disk_symbols = sym;
+    }
     pub fn set_workspace_folder(&mut self,uri: Vec<lsp::Url>) {
         self.ws_folder = uri;
     }
@@ -183,6 +189,13 @@ add `>` to right justify in 5 column field, e.g. `#'>`";
                         }
                     }
                 }
+                let defined = self.symbols.globals.get(&txt).is_some_and(|sym| !sym.defs.is_empty());
+                if let (false,Some(ext)) = (defined,self.disk_symbols.get(&txt)) {
+                    if let (Some(num),false) = (ext.value,ext.relative) {
+                        new_section(&mut self.markup.value,&self.addresses.get(num as i64).unwrap_or_default());
+                    }
+                    new_section(&mut self.markup.value,&ext.hover());
+                }
                 for (name,sym) in &self.symbols.macros {
                     if sym.children.contains_key(name) {
                         self.markup.value += "scoped to macro ";
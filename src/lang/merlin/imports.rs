@@ -0,0 +1,120 @@
+//! # Symbols imported from disk images
+//!
+//! Original source disk sets often keep shared labels outside the source being edited.
+//! This gathers such labels so the language server can offer them in completions and hovers.
+//! Two kinds of files are understood:
+//! * relocatable modules (LNK/REL), whose entry symbols are taken from the external symbol dictionary
+//! * symbol files, i.e., Merlin source whose lines assign labels with `EQU` or `=`
+//!
+//! Imported symbols are only a convenience for the editor, the analyzer and assembler do not see them.
+
+use std::collections::HashMap;
+use super::assembly::rel::{RelModule,esd_flags};
+
+/// A label found in a file on a disk image
+#[derive(Clone,Debug,PartialEq)]
+pub struct DiskSymbol {
+    pub name: String,
+    /// absolute value from a symbol file, or offset of an entry within its module
+    pub value: Option<u16>,
+    /// true if `value` is an offset into a relocatable module
+    pub relative: bool,
+    /// path of the file on the disk image
+    pub source: String
+}
+
+/// Collection of labels imported from disk images, keyed by label name
+#[derive(Clone,Default)]
+pub struct DiskSymbols {
+    map: HashMap<String,DiskSymbol>
+}
+
+/// Parse a Merlin number with optional `$` or `%` prefix
+fn parse_value(txt: &str) -> Option<u16> {
+    let ans = if let Some(hex) = txt.strip_prefix('$') {
+        u32::from_str_radix(hex,16).ok()?
+    } else if let Some(bin) = txt.strip_prefix('%') {
+        u32::from_str_radix(&bin.replace('_',""),2).ok()?
+    } else {
+        txt.parse::<u32>().ok()?
+    };
+    u16::try_from(ans).ok()
+}
+
+impl DiskSymbols {
+    pub fn new() -> Self {
+        Self {
+            map: HashMap::new()
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+    pub fn get(&self,name: &str) -> Option<&DiskSymbol> {
+        self.map.get(name)
+    }
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.map.keys()
+    }
+    fn insert(&mut self,name: &str,value: Option<u16>,relative: bool,source: &str) {
+        self.map.insert(name.to_string(),DiskSymbol {
+            name: name.to_string(),
+            value,
+            relative,
+            source: source.to_string()
+        });
+    }
+    /// Add the entries of a relocatable module, returns the number added, or None if `dat` is not a module
+    pub fn add_rel(&mut self,dat: &[u8],source: &str) -> Option<usize> {
+        let module = RelModule::from_bytes(dat,None).ok()?;
+        let mut count = 0;
+        for entry in module.esd.iter().filter(|e| e.flags & esd_flags::ENT > 0) {
+            self.insert(&entry.name,Some(entry.value),true,source);
+            count += 1;
+        }
+        Some(count)
+    }
+    /// Add the labels assigned by `EQU` or `=` in Merlin source, returns the number added.
+    /// High bits are ignored and either CR or LF can end a line, so that raw file data can be used.
+    /// Labels whose value is an expression are added without a value.
+    pub fn add_source(&mut self,dat: &[u8],source: &str) -> usize {
+        let txt = dat.iter().map(|c| (c & 0x7f) as char).collect::<String>();
+        let mut count = 0;
+        for line in txt.split(['\r','\n']) {
+            if line.starts_with([' ','\t','*',';']) {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            if let (Some(label),Some(op),Some(arg)) = (fields.next(),fields.next(),fields.next()) {
+                if !["EQU","="].contains(&op.to_uppercase().as_str()) || label.starts_with([':',']']) {
+                    continue;
+                }
+                self.insert(label,parse_value(arg),false,source);
+                count += 1;
+            }
+        }
+        count
+    }
+    /// Add the symbols in a file, trying it as a relocatable module first, returns the number added
+    pub fn add_file(&mut self,dat: &[u8],source: &str) -> usize {
+        match self.add_rel(dat,source) {
+            Some(count) => count,
+            None => self.add_source(dat,source)
+        }
+    }
+}
+
+impl DiskSymbol {
+    /// Markdown for hovers
+    pub fn hover(&self) -> String {
+        let val = match (self.value,self.relative) {
+            (Some(v),true) => format!("entry at offset ${:04X} of its module\n\n",v),
+            (Some(v),false) => format!("value ${:04X}\n\n",v),
+            _ => String::new()
+        };
+        format!("external symbol, {}imported from disk image file `{}`",val,self.source)
+    }
+}
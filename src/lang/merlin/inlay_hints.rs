@@ -0,0 +1,144 @@
+//! Inlay hints showing the size and cycle count of each instruction.
+//!
+//! The instructions are measured by the spot assembler, see `Assembler::spot_measure`.
+//! Cycle counts are the base counts from the handbook, adjusted for the processor target and,
+//! on the 65816, for 16 bit data.  A trailing `+` means more cycles can be needed at run time,
+//! e.g. when a branch is taken or an indexed read crosses a page boundary.
+
+use lsp_types as lsp;
+use std::sync::Arc;
+use super::settings::Settings;
+use super::assembly::{Assembler,Measurement};
+use super::{ProcessorType,Symbols};
+
+/// operations that modify memory, these pay twice for 16 bit data, and do not depend on page crossing (except 65C02 shifts)
+const READ_MODIFY_WRITE: [&str;8] = ["asl","dec","inc","lsr","rol","ror","trb","tsb"];
+/// operations whose data width follows the M bit
+const M_WIDTH: [&str;20] = ["adc","and","asl","bit","cmp","dec","eor","inc","lda","lsr","ora","pha","pla","rol","ror","sbc","sta","stz","trb","tsb"];
+/// operations whose data width follows the X bit
+const X_WIDTH: [&str;10] = ["cpx","cpy","ldx","ldy","phx","phy","plx","ply","stx","sty"];
+/// stores pay the page crossing cycle unconditionally, so it is already in the base count
+const STORES: [&str;4] = ["sta","stx","sty","stz"];
+
+pub struct InlayHintProvider {
+    config: Settings,
+    assembler: Assembler,
+    symbols: Arc<Symbols>
+}
+
+/// Does the instruction work on 16 bit data, this is only meaningful for the 65816
+fn wide(measurement: &Measurement) -> bool {
+    let mnemonic = measurement.mnemonic.as_str();
+    measurement.mode.mnemonic != "accum" && (
+        !measurement.m8bit && M_WIDTH.contains(&mnemonic) ||
+        !measurement.x8bit && X_WIDTH.contains(&mnemonic)
+    )
+}
+
+/// Cycle count of a measured instruction, and whether more cycles may be needed
+pub fn cycles(measurement: &Measurement, proc: &ProcessorType) -> (i64,bool) {
+    let mnemonic = measurement.mnemonic.as_str();
+    let mode = measurement.mode.mnemonic.as_str();
+    let rmw = READ_MODIFY_WRITE.contains(&mnemonic) && mode != "accum";
+    let shift = ["asl","lsr","rol","ror"].contains(&mnemonic);
+    let mut ans = measurement.mode.cycles;
+    // the handbook counts are mostly those of the 65C02
+    if rmw && mode == "abs,x" && (!shift || *proc != ProcessorType::_65c02) {
+        ans += 1;
+    }
+    match proc {
+        ProcessorType::_6502 | ProcessorType::_65802 | ProcessorType::_65c816 if mnemonic == "jmp" && mode == "(abs)" => ans -= 1,
+        _ => {}
+    }
+    if wide(measurement) && [ProcessorType::_65802,ProcessorType::_65c816].contains(proc) {
+        ans += match rmw {
+            true => 2,
+            false => 1
+        };
+    }
+    let variable = match mode {
+        "rel" | "xyc" => true,
+        "abs,x" if shift && *proc == ProcessorType::_65c02 => true,
+        "abs,x" | "abs,y" | "(zp),y" => !rmw && !STORES.contains(&mnemonic),
+        _ => false
+    };
+    (ans,variable)
+}
+
+impl InlayHintProvider {
+    pub fn new() -> Self {
+        Self {
+            config: Settings::new(),
+            assembler: Assembler::new(),
+            symbols: Arc::new(Symbols::new())
+        }
+    }
+    pub fn set_config(&mut self,config: Settings) {
+        self.assembler.set_config(config.clone());
+        self.config = config;
+    }
+    pub fn use_shared_symbols(&mut self,sym: Arc<Symbols>) {
+        self.assembler.use_shared_symbols(Arc::clone(&sym));
+        self.symbols = sym;
+    }
+    /// Get hints for the instructions in `rng`, each hint is placed at the end of its line
+    pub fn get(&mut self,txt: &str,rng: &lsp::Range) -> Vec<lsp::InlayHint> {
+        let mut ans = Vec::new();
+        if !self.config.inlay_hints.bytes && !self.config.inlay_hints.cycles {
+            return ans;
+        }
+        self.assembler.set_mx(true,true);
+        let lines = txt.lines().collect::<Vec<&str>>();
+        for measurement in self.assembler.spot_measure(txt, rng.start.line as isize, rng.end.line as isize + 1) {
+            let line = match lines.get(measurement.row as usize) {
+                Some(l) => l.trim_end(),
+                None => continue
+            };
+            let mut label = Vec::new();
+            let mut tooltip = Vec::new();
+            if self.config.inlay_hints.bytes {
+                label.push(match measurement.bytes {
+                    1 => "1 byte".to_string(),
+                    n => format!("{} bytes",n)
+                });
+            }
+            if self.config.inlay_hints.cycles {
+                let (count,variable) = cycles(&measurement,&self.symbols.processor);
+                label.push(match variable {
+                    true => format!("{}+ cycles",count),
+                    false => format!("{} cycles",count)
+                });
+                if variable {
+                    tooltip.push(match measurement.mode.mnemonic.as_str() {
+                        "rel" => "add a cycle if the branch is taken, and on the 6502 or 65C02 another if it crosses a page",
+                        "xyc" => "cycles for each byte moved",
+                        _ => "add a cycle if the index crosses a page"
+                    });
+                }
+            }
+            if wide(&measurement) && [ProcessorType::_65802,ProcessorType::_65c816].contains(&self.symbols.processor) && self.config.inlay_hints.cycles {
+                tooltip.push("16 bit data");
+            }
+            ans.push(lsp::InlayHint {
+                position: lsp::Position::new(measurement.row as u32,line.encode_utf16().count() as u32),
+                label: lsp::InlayHintLabel::String(label.join(", ")),
+                kind: None,
+                text_edits: None,
+                tooltip: match tooltip.len() {
+                    0 => None,
+                    _ => Some(lsp::InlayHintTooltip::String(tooltip.join("; ")))
+                },
+                padding_left: Some(true),
+                padding_right: None,
+                data: None
+            });
+        }
+        ans
+    }
+}
+
+impl Default for InlayHintProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -7,7 +7,7 @@
 //! 
 //! The analyzer performs functions that begin to resemble assembly, such as resolving
 //! file relationships and identifying symbols.  There is a spot assembler that is used to aid in
-//! disassembly. As of this writing, however, full assembly is not supported.
+//! disassembly, and a full assembler that handles macros, loops, and conditionals, but not yet includes or modules.
 //! 
 //! We test against Merlin 8 and Merlin 32.
 //! Test coverage is 100% in terms of instructions and addressing modes available on
@@ -40,6 +40,8 @@ pub mod settings;
 pub mod checkpoint;
 pub mod hovers;
 pub mod completions;
+pub mod inlay_hints;
+pub mod imports;
 pub mod tokenizer;
 pub mod formatter;
 pub mod assembly;
@@ -72,6 +74,8 @@ pub mod symbol_flags {
     pub const MLC: u64 = 0x40;
     /// label is a variable
     pub const VAR: u64 = 0x80;
+    /// label value is an offset into a relocatable module (set during assembly)
+    pub const REL: u64 = 0x100;
 }
 
 /// Stackable source types, e.g., we may have a nesting such as (Master (Put (MacroRef)) (MacroRef))
@@ -202,7 +206,8 @@ pub struct Symbols {
     vars: HashMap<String,Symbol>,
     macros: HashMap<String,Symbol>,
     /// lines in the display document that need the parser hint
-    alt_parser_lines: HashSet<isize>
+    alt_parser_lines: HashSet<isize>,
+    extensions: settings::Extensions
 }
 
 #[derive(Clone)]
@@ -235,7 +240,9 @@ pub struct MerlinParser {
     psop_book: handbook::pseudo_ops::PseudoOperationHandbook,
     col: isize,
     adj_line: String,
-    c2_regex: regex::Regex
+    c2_regex: regex::Regex,
+    sweet16: handbook::sweet16::Region,
+    sweet16_code: Option<u8>
 }
 
 impl fmt::Display for SourceType {
@@ -372,7 +379,8 @@ impl Symbols {
             globals: HashMap::new(),
             vars: HashMap::new(),
             macros: HashMap::new(),
-            alt_parser_lines: HashSet::new()
+            alt_parser_lines: HashSet::new(),
+            extensions: settings::Settings::new().extensions
         }
     }
     /// return strings to be displayed in the client's toolbar,
@@ -485,6 +493,18 @@ impl Symbols {
             false
         }
     }
+    /// If SWEET16 is enabled and `line` is a SWEET16 operation within `region`, get the carrier line and the opcode,
+    /// see `handbook::sweet16`.  The region moves past the line.
+    pub fn sweet16_line(&self,line: &str,region: &mut handbook::sweet16::Region) -> Option<(String,u8)> {
+        match self.extensions.sweet16 {
+            true => region.next(line,|mnemonic| self.mac_defined(mnemonic),|label| self.globals.get(label).and_then(|sym| sym.value)),
+            false => None
+        }
+    }
+    /// Are undocumented operations enabled, they only exist for the 6502
+    pub fn undocumented_enabled(&self) -> bool {
+        self.extensions.undocumented && self.processor == ProcessorType::_6502
+    }
     /// should only be used if symbols have been updated
     pub fn adjust_line(&self,row: isize,line: &str,term: &str) -> String {
         let prefix = match self.alt_parser_lines.contains(&row) {
@@ -511,9 +531,20 @@ impl MerlinParser {
             psop_book: handbook::pseudo_ops::PseudoOperationHandbook::new(),
             col: 0,
             adj_line: String::new(),
-            c2_regex: regex::Regex::new(r"\s+\S+").expect(RCH)
+            c2_regex: regex::Regex::new(r"\s+\S+").expect(RCH),
+            sweet16: handbook::sweet16::Region::default(),
+            sweet16_code: None
         }
     }
+    /// Start a new sequence of lines, forgetting state carried from line to line, such as a SWEET16 region
+    pub fn restart(&mut self) {
+        self.sweet16 = handbook::sweet16::Region::default();
+        self.sweet16_code = None;
+    }
+    /// SWEET16 opcode of the most recently parsed line, if it was translated, see `handbook::sweet16`
+    pub fn sweet16_code(&self) -> Option<u8> {
+        self.sweet16_code
+    }
     /// This adjusts the parsing precedence rules to emulate Merlin's behavior.
     /// The underlying parser requires a hint to do this because it depends on the document
     /// symbols, the Merlin version, and the processor target.
@@ -521,6 +552,7 @@ impl MerlinParser {
     /// to an operation or psuedo-operation mnemonic.
     fn adjust_line(&mut self, old_line: &str, symbols: &Symbols) -> String {
         self.col = 0;
+        self.sweet16_code = None;
         if old_line.starts_with(CALL_TOK) {
             self.col = -(CALL_TOK.len_utf8() as isize); // LSP position encoding assumption
             return old_line.to_string();
@@ -528,6 +560,10 @@ impl MerlinParser {
         if old_line.starts_with("*") || old_line.starts_with(";") {
             return old_line.to_string();
         }
+        if let Some((carrier,code)) = symbols.sweet16_line(old_line,&mut self.sweet16) {
+            self.sweet16_code = Some(code);
+            return carrier;
+        }
         let prefix = match self.c2_regex.find(old_line) {
             Some(sep_c2) => {
                 let c2 = sep_c2.as_str().trim();
@@ -34,6 +34,7 @@ impl SemanticTokensProvider {
 impl Tokens for SemanticTokensProvider {
 	fn get(&mut self, txt: &str) -> Result<lsp::SemanticTokens,DYNERR> {
 		self.builder.reset();
+		self.parser.restart();
 		self.row = 0;
 		self.curr_macro = None;
 		for line in txt.lines() {
@@ -29,6 +29,14 @@ pub struct Hovers {
     pub mnemonics: bool,
     pub pseudo: bool
 }
+/// Hints shown after each instruction, both default to false
+#[derive(Clone)]
+pub struct InlayHints {
+    /// size of the instruction in bytes
+    pub bytes: bool,
+    /// cycle count of the instruction
+    pub cycles: bool
+}
 #[derive(Clone)]
 pub struct Completions {
     pub lower_case: bool,
@@ -37,12 +45,22 @@ pub struct Completions {
 }
 #[derive(Clone)]
 pub struct Diagnostics {
-    pub live: bool
+    pub live: bool,
+    /// run the full assembler when a document is saved, and report its first error
+    pub assemble_on_save: bool
 }
 #[derive(Clone)]
 pub struct Disassembly {
     pub brk: bool
 }
+/// Instructions beyond what Merlin provides, both default to false
+#[derive(Clone)]
+pub struct Extensions {
+    /// SWEET16 operations, also decoded after `JSR $F689` during disassembly
+    pub sweet16: bool,
+    /// undocumented NMOS 6502 operations, only for the 6502 target
+    pub undocumented: bool
+}
 #[derive(Clone)]
 pub struct Settings {
     pub version: super::MerlinVersion,
@@ -50,9 +68,11 @@ pub struct Settings {
     pub columns: Columns,
     pub linker: Linker,
     pub hovers: Hovers,
+    pub inlay_hints: InlayHints,
     pub completions: Completions,
     pub disassembly: Disassembly,
-    pub diagnostics: Diagnostics
+    pub diagnostics: Diagnostics,
+    pub extensions: Extensions
 }
 
 impl Settings {
@@ -76,6 +96,10 @@ impl Settings {
                 mnemonics: true,
                 pseudo: true
             },
+            inlay_hints : InlayHints {
+                bytes: false,
+                cycles: false
+            },
             completions : Completions {
                 lower_case: false,
                 ibas: false,
@@ -85,7 +109,12 @@ impl Settings {
                 brk: false,
             },
             diagnostics: Diagnostics {
-                live: true
+                live: true,
+                assemble_on_save: false
+            },
+            extensions: Extensions {
+                sweet16: false,
+                undocumented: false
             }
         }
     }
@@ -123,6 +152,10 @@ pub fn parse(json: &str) -> Result<Settings,DYNERR> {
                         update_json_bool(val,"mnemonics",&mut ans.hovers.mnemonics);
                         update_json_bool(val,"pseudo",&mut ans.hovers.pseudo);
                     },
+                    "inlayHints" => {
+                        update_json_bool(val,"bytes",&mut ans.inlay_hints.bytes);
+                        update_json_bool(val,"cycles",&mut ans.inlay_hints.cycles);
+                    },
                     "completions" => {
                         update_json_bool(val,"lowerCase",&mut ans.completions.lower_case);
                         update_json_bool(val,"ibas",&mut ans.completions.ibas);
@@ -133,6 +166,11 @@ pub fn parse(json: &str) -> Result<Settings,DYNERR> {
                     },
                     "diagnostics" => {
                         update_json_bool(val, "live", &mut ans.diagnostics.live);
+                        update_json_bool(val, "assembleOnSave", &mut ans.diagnostics.assemble_on_save);
+                    },
+                    "extensions" => {
+                        update_json_bool(val, "sweet16", &mut ans.extensions.sweet16);
+                        update_json_bool(val, "undocumented", &mut ans.extensions.undocumented);
                     },
                     _ => {}
                 }
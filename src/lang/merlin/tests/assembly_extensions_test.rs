@@ -0,0 +1,148 @@
+//! Test of SWEET16 and undocumented 6502 operations
+
+use std::sync::Arc;
+use super::super::assembly::Assembler;
+use super::super::{ProcessorType,Symbol,Symbols};
+
+fn extended_symbols(sweet16: bool, undocumented: bool, proc: ProcessorType) -> Arc<Symbols> {
+    let mut symbols = Symbols::new();
+    symbols.processor = proc;
+    symbols.extensions.sweet16 = sweet16;
+    symbols.extensions.undocumented = undocumented;
+    Arc::new(symbols)
+}
+
+fn test_spot(hex: &str, code: &str, symbols: Arc<Symbols>) {
+    let line_count = code.lines().count();
+    let img = hex::decode(hex.replace(" ","")).expect("hex error");
+    let mut assembler = Assembler::new();
+    assembler.use_shared_symbols(symbols);
+    let actual = assembler
+        .spot_assemble(code.to_string(), 0, line_count as isize, Some(0x800))
+        .expect("asm error");
+    assert_eq!(actual, img);
+}
+
+fn test_full(hex: &str, code: &str, symbols: Arc<Symbols>) {
+    let mut assembler = Assembler::new();
+    assembler.use_shared_symbols(symbols);
+    let actual = assembler.assemble(code, None).expect("asm error");
+    assert_eq!(hex::encode_upper(actual), hex.replace(" ",""));
+}
+
+mod undocumented {
+    use super::super::super::ProcessorType;
+    #[test]
+    fn modes() {
+        let mut test_code = String::new();
+        test_code += "         LAX   $10\n";
+        test_code += "         LAX   $10,Y\n";
+        test_code += "         LAX   ($10,X)\n";
+        test_code += "         LAX   ($10),Y\n";
+        test_code += "         LAX   $1234\n";
+        test_code += "         LAX   $1234,Y\n";
+        test_code += "         SAX   $10\n";
+        test_code += "         DCP   $1234,X\n";
+        test_code += "         ISC   ($10),Y\n";
+        test_code += "         ANC   #$0F\n";
+        test_code += "         SBX   #$01\n";
+        let hex = "A710 B710 A310 B310 AF3412 BF3412 8710 DF3412 F310 0B0F CB01";
+        super::test_spot(hex, &test_code, super::extended_symbols(false, true, ProcessorType::_6502));
+    }
+    #[test]
+    fn alternates() {
+        let test_code = "         ISB   $10\n         DCM   $10\n         ASO   $10\n         LSE   $10\n";
+        super::test_spot("E710 C710 0710 4710", test_code, super::extended_symbols(false, true, ProcessorType::_6502));
+    }
+    #[test]
+    fn disabled() {
+        let mut assembler = super::Assembler::new();
+        assembler.use_shared_symbols(super::extended_symbols(false, true, ProcessorType::_65c02));
+        assert!(assembler.spot_assemble("         LAX   $10\n".to_string(), 0, 1, Some(0x800)).is_err());
+        assembler.use_shared_symbols(super::extended_symbols(false, false, ProcessorType::_6502));
+        assert!(assembler.spot_assemble("         LAX   $10\n".to_string(), 0, 1, Some(0x800)).is_err());
+    }
+    #[test]
+    fn macro_precedence() {
+        let mut test_code = String::new();
+        test_code += "LAX      MAC\n";
+        test_code += "         LDA   ]1\n";
+        test_code += "         TAX\n";
+        test_code += "         EOM\n";
+        test_code += "         LAX   $10\n";
+        test_code += "         SLO   $10\n";
+        super::test_full("A510 AA 0710", &test_code, super::extended_symbols(false, true, ProcessorType::_6502));
+    }
+}
+
+mod sweet16 {
+    use super::super::super::ProcessorType;
+    #[test]
+    fn registers() {
+        let mut test_code = String::new();
+        test_code += "         JSR   $F689\n";
+        test_code += "         SET   R1,$1234\n";
+        test_code += "         LD    R1\n";
+        test_code += "         ST    @R2\n";
+        test_code += "         LDD   @R3\n";
+        test_code += "         STP   R4 ; indirect is implied\n";
+        test_code += "         ADD   R15\n";
+        test_code += "         INR   $A\n";
+        test_code += "         RTN\n";
+        let hex = "2089F6 113412 21 52 63 94 AF EA 00";
+        super::test_spot(hex, &test_code, super::extended_symbols(true, false, ProcessorType::_6502));
+    }
+    #[test]
+    fn branches() {
+        let mut test_code = String::new();
+        test_code += "         ORG   $800\n";
+        test_code += "         JSR   $F689\n";
+        test_code += "LOOP     DCR   R1\n";
+        test_code += "         BNZ   LOOP\n";
+        test_code += "         BS    SUB\n";
+        test_code += "         BR    DONE\n";
+        test_code += "SUB      SET   R2,DATA\n";
+        test_code += "         RS\n";
+        test_code += "DONE     RTN\n";
+        test_code += "DATA     HEX   00\n";
+        let hex = "2089F6 F1 07FD 0C02 0104 120F08 0B 00 00";
+        super::test_full(hex, &test_code, super::extended_symbols(true, false, ProcessorType::_6502));
+    }
+    #[test]
+    fn bad_register() {
+        let mut assembler = super::Assembler::new();
+        assembler.use_shared_symbols(super::extended_symbols(true, false, ProcessorType::_6502));
+        assert!(assembler.spot_assemble("         JSR   $F689\n         LD    R16\n".to_string(), 0, 2, Some(0x800)).is_err());
+    }
+    #[test]
+    fn outside_region() {
+        // before `JSR $F689` and after `RTN` the mnemonics are macro calls, which spot assembly rejects
+        let mut assembler = super::Assembler::new();
+        assembler.use_shared_symbols(super::extended_symbols(true, false, ProcessorType::_6502));
+        assert!(assembler.spot_assemble("         LD    R1\n".to_string(), 0, 1, Some(0x800)).is_err());
+        let test_code = "         JSR   $F689\n         RTN\n         LD    R1\n";
+        assert!(assembler.spot_assemble(test_code.to_string(), 0, 3, Some(0x800)).is_err());
+        // the region is followed even when assembly starts after the entry
+        let test_code = "SW16     EQU   $F689\n         JSR   SW16\n         LD    R1\n";
+        let mut symbols = super::Symbols::new();
+        symbols.extensions.sweet16 = true;
+        let mut sym = super::Symbol::new("SW16");
+        sym.value = Some(0xf689);
+        symbols.globals.insert("SW16".to_string(), sym);
+        let mut assembler = super::Assembler::new();
+        assembler.use_shared_symbols(std::sync::Arc::new(symbols));
+        assert_eq!(assembler.spot_assemble(test_code.to_string(), 2, 3, Some(0x800)).expect("asm error"), vec![0x21]);
+    }
+    #[test]
+    fn macro_first() {
+        let mut symbols = super::Symbols::new();
+        symbols.extensions.sweet16 = true;
+        let mut sym = super::Symbol::new("LD");
+        sym.defs.push(lsp_types::Location::new(lsp_types::Url::parse("file:///test.S").expect("bad url"), lsp_types::Range::default()));
+        symbols.macros.insert("LD".to_string(), sym);
+        let mut region = super::super::super::handbook::sweet16::Region::default();
+        assert!(symbols.sweet16_line("         JSR   $F689\n", &mut region).is_none());
+        assert!(symbols.sweet16_line("         LD    R1\n", &mut region).is_none());
+        assert_eq!(symbols.sweet16_line("         ST    R1\n", &mut region).map(|(_,code)| code), Some(0x31));
+    }
+}
@@ -0,0 +1,245 @@
+//! Test of full assembly, including macros, loops, and conditionals.
+
+use crate::lang::merlin::MerlinVersion;
+use crate::lang::merlin::settings::Settings;
+use super::super::assembly::Assembler;
+
+fn test_assembler(test_code: &str, expected: &str, vers: MerlinVersion) {
+    let mut config = Settings::new();
+    let mut assembler = Assembler::new();
+    config.version = vers;
+    assembler.set_config(config);
+    let bytes = assembler.assemble(test_code, None).expect("assembler failed");
+    let actual = hex::encode_upper(bytes);
+    assert_eq!(actual,expected.replace(" ",""));
+}
+
+fn test_error(test_code: &str, vers: MerlinVersion) {
+    let mut config = Settings::new();
+    let mut assembler = Assembler::new();
+    config.version = vers;
+    assembler.set_config(config);
+    assert!(assembler.assemble(test_code, None).is_err());
+}
+
+mod labels {
+    use super::MerlinVersion;
+    #[test]
+    fn forward_and_backward() {
+        let mut test_code = String::new();
+        test_code += "         ORG   $300\n";
+        test_code += "START    LDX   #0\n";
+        test_code += ":LOOP    INX\n";
+        test_code += "         BNE   :LOOP\n";
+        test_code += "         JMP   DONE\n";
+        test_code += "DONE     JMP   START\n";
+        let expected = "A2 00 E8 D0 FD 4C 08 03 4C 00 03";
+        super::test_assembler(&test_code, expected, MerlinVersion::Merlin8);
+    }
+    #[test]
+    fn forward_zero_page() {
+        let mut test_code = String::new();
+        test_code += "         ORG   $300\n";
+        test_code += "         LDA   PTR\n";
+        test_code += "         LDA   PTR\n";
+        test_code += "PTR      EQU   $06\n";
+        test_code += "         LDA   PTR\n";
+        let expected = "AD 06 00 AD 06 00 A5 06";
+        super::test_assembler(&test_code, expected, MerlinVersion::Merlin8);
+    }
+    #[test]
+    fn dummy_section() {
+        let mut test_code = String::new();
+        test_code += "         ORG   $300\n";
+        test_code += "         DUM   $06\n";
+        test_code += "PTR      DS    2\n";
+        test_code += "CNT      DS    1\n";
+        test_code += "         DEND\n";
+        test_code += "         LDA   CNT\n";
+        test_code += "         STA   PTR+1\n";
+        let expected = "A5 08 85 07";
+        super::test_assembler(&test_code, expected, MerlinVersion::Merlin8);
+    }
+    #[test]
+    fn duplicate() {
+        let mut test_code = String::new();
+        test_code += "LAB      NOP\n";
+        test_code += "LAB      NOP\n";
+        super::test_error(&test_code, MerlinVersion::Merlin8);
+    }
+}
+
+mod macros {
+    use super::MerlinVersion;
+    #[test]
+    fn arguments() {
+        let mut test_code = String::new();
+        test_code += "MOV      MAC\n";
+        test_code += "         LDA   ]1\n";
+        test_code += "         STA   ]2\n";
+        test_code += "         <<<\n";
+        test_code += "         ORG   $300\n";
+        test_code += "         PMC   MOV,#1;$06\n";
+        test_code += "         >>>   MOV,$06;$07\n";
+        test_code += "         MOV   #$FF;$0800\n";
+        let expected = "A9 01 85 06 A5 06 85 07 A9 FF 8D 00 08";
+        super::test_assembler(&test_code, expected, MerlinVersion::Merlin8);
+    }
+    #[test]
+    fn nested_calls() {
+        let mut test_code = String::new();
+        test_code += "INCW     MAC\n";
+        test_code += "         INC   ]1\n";
+        test_code += "         BNE   SKIP\n";
+        test_code += "         INC   ]1+1\n";
+        test_code += "SKIP     <<<\n";
+        test_code += "INC4     MAC\n";
+        test_code += "         INCW  ]1\n";
+        test_code += "         INCW  ]1+2\n";
+        test_code += "         EOM\n";
+        test_code += "         ORG   $300\n";
+        test_code += "         INC4  $06\n";
+        let expected = "E6 06 D0 02 E6 07 E6 08 D0 02 E6 09";
+        super::test_assembler(&test_code, expected, MerlinVersion::Merlin8);
+    }
+    #[test]
+    fn argument_count() {
+        let mut test_code = String::new();
+        test_code += "COUNT    MAC\n";
+        test_code += "         DFB   ]0\n";
+        test_code += "         <<<\n";
+        test_code += "         COUNT 1;2;3\n";
+        test_code += "         COUNT 1\n";
+        let expected = "03 01";
+        super::test_assembler(&test_code, expected, MerlinVersion::Merlin16Plus);
+    }
+    #[test]
+    fn conditional_argument() {
+        let mut test_code = String::new();
+        test_code += "LOAD     MAC\n";
+        test_code += "         IF    #=]1\n";
+        test_code += "         LDA   ]1\n";
+        test_code += "         ELSE\n";
+        test_code += "         LDA   (]1),Y\n";
+        test_code += "         FIN\n";
+        test_code += "         <<<\n";
+        test_code += "         LOAD  #$01\n";
+        test_code += "         LOAD  $06\n";
+        let expected = "A9 01 B1 06";
+        super::test_assembler(&test_code, expected, MerlinVersion::Merlin8);
+    }
+    #[test]
+    fn too_deep() {
+        let mut test_code = String::new();
+        test_code += "REC      MAC\n";
+        test_code += "         NOP\n";
+        test_code += "         REC\n";
+        test_code += "         <<<\n";
+        test_code += "         PMC   REC\n";
+        super::test_error(&test_code, MerlinVersion::Merlin8);
+    }
+    #[test]
+    fn undefined() {
+        let test_code = "         PMC   NOTHING\n";
+        super::test_error(test_code, MerlinVersion::Merlin8);
+    }
+}
+
+mod loops {
+    use super::MerlinVersion;
+    #[test]
+    fn variables() {
+        let mut test_code = String::new();
+        test_code += "]I       =     0\n";
+        test_code += "         LUP   4\n";
+        test_code += "]I       =     ]I+1\n";
+        test_code += "         DFB   ]I\n";
+        test_code += "         --^\n";
+        let expected = "01 02 03 04";
+        super::test_assembler(&test_code, expected, MerlinVersion::Merlin8);
+    }
+    #[test]
+    fn iterated_labels() {
+        let mut test_code = String::new();
+        test_code += "         ORG   $300\n";
+        test_code += "         LUP   3\n";
+        test_code += "IT@      NOP\n";
+        test_code += "         --^\n";
+        test_code += "         DA    ITA,ITC\n";
+        let expected = "EA EA EA 00 03 02 03";
+        super::test_assembler(&test_code, expected, MerlinVersion::Merlin8);
+    }
+}
+
+mod conditionals {
+    use super::MerlinVersion;
+    #[test]
+    fn do_else() {
+        let mut test_code = String::new();
+        test_code += "DEBUG    =     0\n";
+        test_code += "         DO    DEBUG\n";
+        test_code += "         BRK\n";
+        test_code += "         ELSE\n";
+        test_code += "         NOP\n";
+        test_code += "         DO    DEBUG+1\n";
+        test_code += "         RTS\n";
+        test_code += "         FIN\n";
+        test_code += "         FIN\n";
+        let expected = "EA 60";
+        super::test_assembler(&test_code, expected, MerlinVersion::Merlin8);
+    }
+    #[test]
+    fn forward_reference() {
+        let mut test_code = String::new();
+        test_code += "         DO    LATER\n";
+        test_code += "         NOP\n";
+        test_code += "         FIN\n";
+        test_code += "LATER    =     1\n";
+        super::test_error(&test_code, MerlinVersion::Merlin8);
+    }
+    #[test]
+    fn end_and_err() {
+        let mut test_code = String::new();
+        test_code += "         ORG   $300\n";
+        test_code += "         NOP\n";
+        test_code += "         ERR   *-$301\n";
+        test_code += "         CHK\n";
+        test_code += "         END\n";
+        test_code += "         NOP\n";
+        let expected = "EA EA";
+        super::test_assembler(&test_code, expected, MerlinVersion::Merlin8);
+        let test_code = "         ERR   1\n";
+        super::test_error(test_code, MerlinVersion::Merlin8);
+    }
+}
+
+mod diagnose {
+    use super::{Assembler,MerlinVersion,Settings};
+    fn diagnose(test_code: &str) -> Option<lsp_types::Diagnostic> {
+        let mut config = Settings::new();
+        let mut assembler = Assembler::new();
+        config.version = MerlinVersion::Merlin8;
+        assembler.set_config(config);
+        assembler.diagnose(test_code, None)
+    }
+    #[test]
+    fn clean() {
+        let mut test_code = String::new();
+        test_code += "         ORG   $300\n";
+        test_code += "         LDA   #0\n";
+        test_code += "         RTS\n";
+        assert!(diagnose(&test_code).is_none());
+    }
+    #[test]
+    fn first_error() {
+        let mut test_code = String::new();
+        test_code += "         ORG   $300\n";
+        test_code += "         NOP\n";
+        test_code += "         ERR   1\n";
+        test_code += "         ERR   1\n";
+        let diag = diagnose(&test_code).expect("no diagnostic");
+        assert_eq!(diag.range.start.line,2);
+        assert_eq!(diag.range.start.character,9);
+        assert!(diag.message.starts_with("assembler:"));
+    }
+}
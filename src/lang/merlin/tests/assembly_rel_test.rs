@@ -0,0 +1,109 @@
+//! Test of relocatable modules and the linker.
+
+use crate::lang::merlin::MerlinVersion;
+use crate::lang::merlin::settings::Settings;
+use super::super::assembly::Assembler;
+use super::super::assembly::rel::{RelModule,link};
+
+fn assemble(test_code: &str) -> Result<Vec<u8>,crate::DYNERR> {
+    let mut config = Settings::new();
+    let mut assembler = Assembler::new();
+    config.version = MerlinVersion::Merlin16;
+    assembler.set_config(config);
+    assembler.assemble(test_code, None)
+}
+
+fn module_a() -> String {
+    let mut test_code = String::new();
+    test_code += "         REL\n";
+    test_code += "PRINT    EXT\n";
+    test_code += "START    ENT\n";
+    test_code += "         LDA   #<MSG\n";
+    test_code += "         LDY   #>MSG\n";
+    test_code += "         JSR   PRINT\n";
+    test_code += "         RTS\n";
+    test_code += "MSG      ASC   \"HI\"\n";
+    test_code += "         HEX   00\n";
+    test_code
+}
+
+fn module_b() -> String {
+    let mut test_code = String::new();
+    test_code += "         REL\n";
+    test_code += "PRINT    ENT\n";
+    test_code += "         STA   PTR\n";
+    test_code += "         RTS\n";
+    test_code += "PTR      DS    2\n";
+    test_code
+}
+
+mod output {
+    #[test]
+    fn dictionaries() {
+        let bytes = super::assemble(&super::module_a()).expect("assembler failed");
+        let code = "A9 08 A0 00 20 00 00 60 C8 C9 00";
+        let rld = "08 01 00 00 48 03 00 08 98 05 00 01 00";
+        let esd = "D0 D2 C9 CE 54 10 01 00 D3 D4 C1 D2 54 08 00 00 00";
+        assert_eq!(hex::encode_upper(bytes),[code,rld,esd].concat().replace(" ",""));
+    }
+    #[test]
+    fn forward_reference() {
+        let bytes = super::assemble(&super::module_b()).expect("assembler failed");
+        let expected = "8D 04 00 60 00 00 88 01 00 00 00 D0 D2 C9 CE 54 08 00 00 00";
+        assert_eq!(hex::encode_upper(bytes),expected.replace(" ",""));
+    }
+    #[test]
+    fn absolute_labels() {
+        let mut test_code = String::new();
+        test_code += "         REL\n";
+        test_code += "COUT     EQU   $FDED\n";
+        test_code += "         DUM   0\n";
+        test_code += "FIELD    DS    1\n";
+        test_code += "         DEND\n";
+        test_code += "         LDA   FIELD\n";
+        test_code += "         JMP   COUT\n";
+        let bytes = super::assemble(&test_code).expect("assembler failed");
+        assert_eq!(hex::encode_upper(bytes),"A5004CEDFD0000");
+    }
+    #[test]
+    fn errors() {
+        assert!(super::assemble("X1 EXT\n LDA X1\n").is_err());
+        assert!(super::assemble(" REL\nX1 EXT\nX2 EXT\n LDA X1+X2\n").is_err());
+        assert!(super::assemble(" REL\nX1 EXT\n BNE X1\n").is_err());
+        assert!(super::assemble(" REL\nL1 NOP\n LDA L1*2\n").is_err());
+        assert!(super::assemble(" NOP\n REL\n").is_err());
+        assert!(super::assemble(" REL\n ENT NOWHERE\n RTS\n").is_err());
+    }
+}
+
+mod linker {
+    use super::{RelModule,link};
+    #[test]
+    fn round_trip() {
+        let bytes = super::assemble(&super::module_a()).expect("assembler failed");
+        let module = RelModule::from_bytes(&bytes, None).expect("could not parse");
+        assert_eq!(module.code.len(),11);
+        assert_eq!(module.rld.len(),3);
+        assert_eq!(module.esd.len(),2);
+        assert_eq!(module.to_bytes(),bytes);
+    }
+    #[test]
+    fn two_modules() {
+        let a = RelModule::from_bytes(&super::assemble(&super::module_a()).expect("assembler failed"), None).expect("could not parse");
+        let b = RelModule::from_bytes(&super::assemble(&super::module_b()).expect("assembler failed"), None).expect("could not parse");
+        let linked = link(&[a,b], 0x300).expect("link failed");
+        let expected = "A9 08 A0 03 20 0B 03 60 C8 C9 00 8D 0F 03 60 00 00";
+        assert_eq!(hex::encode_upper(linked),expected.replace(" ",""));
+    }
+    #[test]
+    fn unresolved() {
+        let a = RelModule::from_bytes(&super::assemble(&super::module_a()).expect("assembler failed"), None).expect("could not parse");
+        assert!(link(&[a], 0x300).is_err());
+    }
+    #[test]
+    fn duplicate_entry() {
+        let b1 = RelModule::from_bytes(&super::assemble(&super::module_b()).expect("assembler failed"), None).expect("could not parse");
+        let b2 = b1.clone();
+        assert!(link(&[b1,b2], 0x300).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+//! Test of disassembly through MLI calls and RWTS parameter blocks.
+
+use super::super::disassembly::{DasmRange,Disassembler,Syntax};
+use super::super::ProcessorType;
+
+fn test_disassembler(hex: &str, expected: &str, syntax: Syntax) {
+    let mut img = vec![0;0x300];
+    img.append(&mut hex::decode(hex).expect("hex error"));
+    let mut disassembler = Disassembler::new();
+    disassembler.set_syntax(syntax);
+    let actual = disassembler.disassemble(
+        &img,
+        DasmRange::Range([0x300,img.len()]),
+        ProcessorType::_6502,
+        "some").expect("dasm error");
+    assert_eq!(actual,expected);
+}
+
+#[test]
+fn mli_calls() {
+    // the second call would be lost if the inline bytes of the first were taken as instructions
+    let code = "2000bfc50d032000bfcc0d0360000000";
+    let mut expected = String::new();
+    expected += "_0300    JSR   $BF00\n";
+    expected += "         DFB   $C5        ; ON_LINE\n";
+    expected += "         DA    _030D      ; parameter list\n";
+    expected += "         JSR   $BF00\n";
+    expected += "         DFB   $CC        ; CLOSE\n";
+    expected += "         DA    _030D      ; parameter list\n";
+    expected += "         RTS\n";
+    expected += "_030D    DS    3,$00\n";
+    test_disassembler(code, &expected, Syntax::Merlin);
+}
+
+#[test]
+fn rwts_block() {
+    let code = "a9 03 a0 0a 20 d9 03 b0 01 60 01 60 01 00 11 00 1b 03 00 20 00 00 02 00 00 60 01 00 01 d8 ef".replace(' ',"");
+    let mut expected = String::new();
+    expected += "_0300    LDA   #$03\n";
+    expected += "         LDY   #$0A\n";
+    expected += "         JSR   $03D9\n";
+    expected += "         BCS   _030A\n";
+    expected += "         RTS\n";
+    expected += "_030A    DFB   $01        ; table type\n";
+    expected += "         DFB   $60        ; slot times 16\n";
+    expected += "         DFB   $01        ; drive\n";
+    expected += "         DFB   $00        ; volume expected\n";
+    expected += "         DFB   $11        ; track\n";
+    expected += "         DFB   $00        ; sector\n";
+    expected += "         DA    _031B      ; DCT pointer\n";
+    expected += "         DA    $2000      ; buffer pointer\n";
+    expected += "         DA    $0000      ; unused\n";
+    expected += "         DFB   $02        ; command (WRITE)\n";
+    expected += "         DFB   $00        ; return code\n";
+    expected += "         DFB   $00        ; volume found\n";
+    expected += "         DFB   $60        ; previous slot times 16\n";
+    expected += "         DFB   $01        ; previous drive\n";
+    expected += "_031B    DFB   $00        ; device type\n";
+    expected += "         DFB   $01        ; phases per track\n";
+    expected += "         DA    $EFD8      ; motor on time\n";
+    test_disassembler(&code, &expected, Syntax::Merlin);
+}
+
+#[test]
+fn mli_acme() {
+    let code = "2000bfc8070360000000";
+    let mut expected = String::new();
+    expected += "         !cpu  6502\n";
+    expected += "         * = $0300\n";
+    expected += "L0300    JSR   $BF00\n";
+    expected += "         !byte $C8        ; OPEN\n";
+    expected += "         !word L0307      ; parameter list\n";
+    expected += "         RTS\n";
+    expected += "L0307    !fill 3,$00\n";
+    test_disassembler(code, &expected, Syntax::Acme);
+}
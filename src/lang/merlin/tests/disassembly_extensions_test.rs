@@ -0,0 +1,73 @@
+//! Test of SWEET16 and undocumented 6502 operations
+
+use super::super::disassembly::{DasmRange,Disassembler,Syntax};
+use super::super::ProcessorType;
+use super::super::settings::Settings;
+
+fn test_disassembler(hex: &str, expected: &str, sweet16: bool, undocumented: bool, proc: ProcessorType, syntax: Syntax) {
+    let img = hex::decode(hex.replace(" ","")).expect("hex error");
+    let mut disassembler = Disassembler::new();
+    let mut config = Settings::new();
+    config.extensions.sweet16 = sweet16;
+    config.extensions.undocumented = undocumented;
+    disassembler.set_config(config);
+    disassembler.set_syntax(syntax);
+    let actual = disassembler.disassemble(
+        &img,
+        DasmRange::All,
+        proc,
+        "some").expect("dasm error");
+    assert_eq!(actual,expected);
+}
+
+#[test]
+fn undocumented() {
+    let hex = "A780 B780 A380 B380 AF3412 BF3412 0B0F 60";
+    let mut expected = String::new();
+    expected += "_0000    LAX   $80\n";
+    expected += "         LAX   $80,Y\n";
+    expected += "         LAX   ($80,X)\n";
+    expected += "         LAX   ($80),Y\n";
+    expected += "         LAX   $1234\n";
+    expected += "         LAX   $1234,Y\n";
+    expected += "         ANC   #$0F\n";
+    expected += "         RTS\n";
+    test_disassembler(hex, &expected, false, true, ProcessorType::_6502, Syntax::Merlin);
+}
+
+#[test]
+fn undocumented_not_65c02() {
+    let hex = "0B0F";
+    let mut expected = String::new();
+    expected += "_0000    DFB   $0B\n";
+    expected += "         DFB   $0F\n";
+    test_disassembler(hex, &expected, false, true, ProcessorType::_65c02, Syntax::Merlin);
+}
+
+#[test]
+fn sweet16() {
+    let hex = "2089F6 110008 41 07FD 0C01 00 0B 60";
+    let mut expected = String::new();
+    expected += "_0000    JSR   $F689\n";
+    expected += "         SET   R1,$0800\n";
+    expected += "_0006    LD    @R1\n";
+    expected += "         BNZ   _0006\n";
+    expected += "         BS    _000C\n";
+    expected += "         RTN\n";
+    expected += "_000C    DFB   $0B\n";
+    expected += "         RTS\n";
+    test_disassembler(hex, &expected, true, false, ProcessorType::_6502, Syntax::Merlin);
+}
+
+#[test]
+fn sweet16_foreign() {
+    let hex = "2089F6 2F 00 60";
+    let mut expected = String::new();
+    expected += "         !cpu  6502\n";
+    expected += "         * = $0000\n";
+    expected += "L0000    JSR   $F689\n";
+    expected += "         !byte $2F        ; LD R15\n";
+    expected += "         !byte $00        ; RTN\n";
+    expected += "         RTS\n";
+    test_disassembler(hex, &expected, true, false, ProcessorType::_6502, Syntax::Acme);
+}
@@ -0,0 +1,61 @@
+//! Test of disassembly annotated with the symbol database.
+
+use super::super::disassembly::{DasmRange,Disassembler,Syntax};
+use super::super::disassembly::symbols::SymbolDatabase;
+use super::super::ProcessorType;
+
+fn test_disassembler(hex: &str, expected: &str, syntax: Syntax, sets: &str) {
+    let mut img = vec![0;0x300];
+    img.append(&mut hex::decode(hex).expect("hex error"));
+    let mut disassembler = Disassembler::new();
+    disassembler.set_syntax(syntax);
+    disassembler.use_symbol_database(SymbolDatabase::from_sets(sets).expect("could not load symbols"));
+    let actual = disassembler.disassemble(
+        &img,
+        DasmRange::Range([0x300,img.len()]),
+        ProcessorType::_6502,
+        "some").expect("dasm error");
+    assert_eq!(actual,expected);
+}
+
+/// call COUT, read the high byte of CSW, jump to DOS, indexed load of an internal label, call MLI
+const CODE: &str = "20edfda5374cd003bd00032000bf";
+
+#[test]
+fn lookup() {
+    let db = SymbolDatabase::from_sets("appleii,prodos").expect("could not load symbols");
+    assert_eq!(db.expression(0xfded),Some("COUT".to_string()));
+    assert_eq!(db.expression(0x37),Some("CSW+1".to_string()));
+    assert_eq!(db.expression(0xbf00),Some("MLI".to_string()));
+    assert_eq!(db.expression(0x3d0),None);
+    assert!(SymbolDatabase::from_sets("cpm").is_err());
+}
+
+#[test]
+fn merlin() {
+    let mut expected = String::new();
+    expected += "CSW      EQU   $36\n";
+    expected += "DOSWARM  EQU   $03D0\n";
+    expected += "MLI      EQU   $BF00\n";
+    expected += "COUT     EQU   $FDED\n";
+    expected += "_0300    JSR   COUT       ; Print character in A\n";
+    expected += "         LDA   CSW+1\n";
+    expected += "         JMP   DOSWARM    ; DOS warm start\n";
+    expected += "         LDA   _0300,X\n";
+    expected += "         JSR   MLI        ; ProDOS machine language interface\n";
+    test_disassembler(CODE, &expected, Syntax::Merlin, "appleii,prodos,dos33");
+}
+
+#[test]
+fn scasm() {
+    let mut expected = String::new();
+    expected += "         .OR   $0300\n";
+    expected += "CSW      .EQ   $36\n";
+    expected += "COUT     .EQ   $FDED\n";
+    expected += "L0300    JSR   COUT       ; Print character in A\n";
+    expected += "         LDA   CSW+1\n";
+    expected += "         JMP   $03D0\n";
+    expected += "         LDA   L0300,X\n";
+    expected += "         JSR   $BF00\n";
+    test_disassembler(CODE, &expected, Syntax::Scasm, "appleii");
+}
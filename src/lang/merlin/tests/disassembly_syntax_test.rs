@@ -0,0 +1,69 @@
+//! Test of disassembly into the syntax of other assemblers.
+
+use super::super::disassembly::{DasmRange,Disassembler,Syntax};
+use super::super::ProcessorType;
+
+fn test_disassembler(hex: &str, expected: &str, syntax: Syntax) {
+    let mut img = vec![0;0x300];
+    img.append(&mut hex::decode(hex).expect("hex error"));
+    let mut disassembler = Disassembler::new();
+    disassembler.set_syntax(syntax);
+    let actual = disassembler.disassemble(
+        &img,
+        DasmRange::Range([0x300,img.len()]),
+        ProcessorType::_6502,
+        "some").expect("dasm error");
+    assert_eq!(actual,expected);
+}
+
+/// loop with forced absolute addressing, followed by terminated strings and a fill
+const CODE: &str = "a210ad1200cad0fa604b4f00cbcf00000000";
+
+#[test]
+fn ca65() {
+    let mut expected = String::new();
+    expected += "         .setcpu \"6502\"\n";
+    expected += "         .org  $0300\n";
+    expected += "L0300:   LDX   #$10\n";
+    expected += "L0302:   LDA   a:$0012\n";
+    expected += "         DEX\n";
+    expected += "         BNE   L0302\n";
+    expected += "         RTS\n";
+    expected += "         .byte \"KO\",$00\n";
+    expected += "         .byte $CB,$CF,$00\n";
+    expected += "         .res  3,$00\n";
+    test_disassembler(CODE, &expected, Syntax::Ca65);
+}
+
+#[test]
+fn acme() {
+    let mut expected = String::new();
+    expected += "         !cpu  6502\n";
+    expected += "         * = $0300\n";
+    expected += "L0300    LDX   #$10\n";
+    expected += "L0302    LDA+2 $0012\n";
+    expected += "         DEX\n";
+    expected += "         BNE   L0302\n";
+    expected += "         RTS\n";
+    expected += "         !text \"KO\",0\n";
+    expected += "         !byte $CB,$CF,$00\n";
+    expected += "         !fill 3,$00\n";
+    test_disassembler(CODE, &expected, Syntax::Acme);
+}
+
+#[test]
+fn scasm() {
+    let mut expected = String::new();
+    expected += "         .OR   $0300\n";
+    expected += "L0300    LDX   #$10\n";
+    expected += "L0302    .HS   AD1200\n";
+    expected += "         DEX\n";
+    expected += "         BNE   L0302\n";
+    expected += "         RTS\n";
+    expected += "         .AS   \"KO\"\n";
+    expected += "         .HS   00\n";
+    expected += "         .AS   -\"KO\"\n";
+    expected += "         .HS   00\n";
+    expected += "         .HS   000000\n";
+    test_disassembler(CODE, &expected, Syntax::Scasm);
+}
@@ -0,0 +1,24 @@
+//! Test of the formatter's column layout and case conversion.
+
+use crate::lang::merlin::formatter::{self,OpCase,ColumnStyle};
+use crate::lang::merlin::tokenizer::Tokenizer;
+
+#[test]
+fn upper_case_ops() {
+    let test_code = "lab lda #$00 ;rem\n sta $C000\n MyMac 1\n";
+    let expected = "lab      LDA   #$00       ;rem\n         STA   $C000\n         MyMac 1\n";
+    let mut tokenizer = Tokenizer::new();
+    tokenizer.set_op_case(Some(OpCase::Upper));
+    assert_eq!(formatter::format_program(test_code.to_string(),&mut tokenizer).expect("format failed"),expected);
+}
+
+#[test]
+fn widths() {
+    let test_code = "lab LDA #$00 ;rem\n";
+    let expected = "lab   lda  #$00 ;rem\n";
+    let mut tokenizer = Tokenizer::new();
+    tokenizer.set_style(ColumnStyle::Variable);
+    tokenizer.set_widths([6,5,5]);
+    tokenizer.set_op_case(Some(OpCase::Lower));
+    assert_eq!(formatter::format_program(test_code.to_string(),&mut tokenizer).expect("format failed"),expected);
+}
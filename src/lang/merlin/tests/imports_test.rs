@@ -0,0 +1,50 @@
+//! Test of symbols imported from disk image files.
+
+use crate::lang::merlin::MerlinVersion;
+use crate::lang::merlin::settings::Settings;
+use super::super::assembly::Assembler;
+use super::super::imports::DiskSymbols;
+
+#[test]
+fn rel_entries() {
+    let mut config = Settings::new();
+    let mut assembler = Assembler::new();
+    config.version = MerlinVersion::Merlin16;
+    assembler.set_config(config);
+    let mut test_code = String::new();
+    test_code += "         REL\n";
+    test_code += "PRINT    EXT\n";
+    test_code += "START    ENT\n";
+    test_code += "         JSR   PRINT\n";
+    test_code += "DONE     ENT\n";
+    test_code += "         RTS\n";
+    let dat = assembler.assemble(&test_code, None).expect("assembler failed");
+    let mut symbols = DiskSymbols::new();
+    assert_eq!(symbols.add_file(&dat, "/LIB/MOD.L"), 2);
+    let done = symbols.get("DONE").expect("missing entry");
+    assert_eq!(done.value, Some(3));
+    assert!(done.relative);
+    assert_eq!(done.source, "/LIB/MOD.L");
+    assert!(symbols.get("PRINT").is_none());
+}
+
+#[test]
+fn equates() {
+    let mut test_code = String::new();
+    test_code += "* system symbols\n";
+    test_code += "COUT     EQU   $FDED\n";
+    test_code += "HOME     =     64600\n";
+    test_code += "MASK     equ   %1111_0000\n";
+    test_code += "NEXT     EQU   HOME+1\n";
+    test_code += "]VAR     EQU   1\n";
+    test_code += "         LDA   #0\n";
+    // raw Merlin file data has high bits set and CR line endings
+    let dat = test_code.replace("\n","\r").as_bytes().iter().map(|c| c | 0x80).collect::<Vec<u8>>();
+    let mut symbols = DiskSymbols::new();
+    assert_eq!(symbols.add_file(&dat, "T.SYMBOLS"), 4);
+    assert_eq!(symbols.get("COUT").unwrap().value, Some(0xfded));
+    assert_eq!(symbols.get("HOME").unwrap().value, Some(0xfc58));
+    assert_eq!(symbols.get("MASK").unwrap().value, Some(0xf0));
+    assert_eq!(symbols.get("NEXT").unwrap().value, None);
+    assert!(!symbols.get("COUT").unwrap().relative);
+}
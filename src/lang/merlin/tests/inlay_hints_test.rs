@@ -0,0 +1,85 @@
+//! Test of inlay hints giving instruction sizes and cycle counts.
+
+use std::sync::Arc;
+use lsp_types as lsp;
+use crate::lang::merlin::{ProcessorType,Symbols};
+use crate::lang::merlin::settings::Settings;
+use super::super::inlay_hints::InlayHintProvider;
+
+fn test_hints(test_code: &str, proc: ProcessorType, rows: [u32;2], expected: &[(u32,&str)]) {
+    let mut config = Settings::new();
+    config.inlay_hints.bytes = true;
+    config.inlay_hints.cycles = true;
+    let mut symbols = Symbols::new();
+    symbols.processor = proc;
+    let mut provider = InlayHintProvider::new();
+    provider.set_config(config);
+    provider.use_shared_symbols(Arc::new(symbols));
+    let rng = lsp::Range::new(lsp::Position::new(rows[0],0),lsp::Position::new(rows[1],0));
+    let actual = provider.get(test_code, &rng).iter().map(|hint| match &hint.label {
+        lsp::InlayHintLabel::String(s) => (hint.position.line,s.to_string()),
+        _ => panic!("unexpected label")
+    }).collect::<Vec<(u32,String)>>();
+    let expected = expected.iter().map(|(row,s)| (*row,s.to_string())).collect::<Vec<(u32,String)>>();
+    assert_eq!(actual,expected);
+}
+
+#[test]
+fn nmos_6502() {
+    let mut test_code = String::new();
+    test_code += "         ORG   $300\n";
+    test_code += "START    LDA   $1234,X\n";
+    test_code += "* store it\n";
+    test_code += "         STA   $06\n";
+    test_code += "         ROR   $1234,X\n";
+    test_code += "         BNE   START\n";
+    test_code += "         JMP   ($3F0)\n";
+    test_code += "         JSR   PRINT\n";
+    test_code += "         ASL\n";
+    let expected = [
+        (1,"3 bytes, 4+ cycles"),
+        (3,"2 bytes, 3 cycles"),
+        (4,"3 bytes, 7 cycles"),
+        (5,"2 bytes, 2+ cycles"),
+        (6,"3 bytes, 5 cycles"),
+        (7,"3 bytes, 6 cycles"),
+        (8,"1 byte, 2 cycles")
+    ];
+    test_hints(&test_code, ProcessorType::_6502, [0,8], &expected);
+    test_hints(&test_code, ProcessorType::_6502, [3,4], &expected[1..3]);
+}
+
+#[test]
+fn cmos_65c02() {
+    let mut test_code = String::new();
+    test_code += "         ROR   $1234,X\n";
+    test_code += "         JMP   ($3F0)\n";
+    test_code += "         STZ   $06\n";
+    let expected = [
+        (0,"3 bytes, 6+ cycles"),
+        (1,"3 bytes, 6 cycles"),
+        (2,"2 bytes, 3 cycles")
+    ];
+    test_hints(&test_code, ProcessorType::_65c02, [0,2], &expected);
+}
+
+#[test]
+fn wide_65816() {
+    let mut test_code = String::new();
+    test_code += "         LDA   #$12\n";
+    test_code += "         MX    %00\n";
+    test_code += "         LDA   #$1234\n";
+    test_code += "         INC   $06\n";
+    test_code += "         LDA   $06,S\n";
+    test_code += "         MX    %11\n";
+    test_code += "         LDA   $06,S\n";
+    let expected = [
+        (0,"2 bytes, 2 cycles"),
+        (2,"3 bytes, 3 cycles"),
+        (3,"2 bytes, 7 cycles"),
+        (4,"2 bytes, 5 cycles"),
+        (6,"2 bytes, 4 cycles")
+    ];
+    test_hints(&test_code, ProcessorType::_65c816, [0,6], &expected);
+    test_hints(&test_code, ProcessorType::_65c816, [6,6], &expected[4..]);
+}
@@ -7,4 +7,14 @@ mod assembly_strings_test;
 mod assembly_data_test;
 mod assembly_6502_test;
 mod assembly_65c02_test;
-mod assembly_65816_test;
\ No newline at end of file
+mod assembly_65816_test;
+mod assembly_full_test;
+mod assembly_rel_test;
+mod disassembly_syntax_test;
+mod disassembly_symbols_test;
+mod disassembly_calls_test;
+mod assembly_extensions_test;
+mod disassembly_extensions_test;
+mod imports_test;
+mod inlay_hints_test;
+mod formatter_test;
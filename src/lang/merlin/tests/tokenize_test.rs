@@ -128,4 +128,4 @@ mod expressions {
 		let expected = "A0CCD5D0A0D6C1CCB1ABFBFBD6C1CCB2ABB1FDAAB1B5AFA4C5FD8D";
 		super::test_tokenizer(test_code, expected);
 	}
-}
\ No newline at end of file
+}
@@ -20,6 +20,7 @@ pub struct Tokenizer
 	columns: usize,
 	widths: [usize;3],
 	style: super::formatter::ColumnStyle,
+	op_case: Option<super::formatter::OpCase>,
 	line_sep: String,
 	symbols: Arc<super::Symbols>
 }
@@ -77,6 +78,13 @@ impl lang::Navigate for Tokenizer
 				if curs.node().kind()=="comment" {
 					txt = txt.replace("\t"," ");
 				}
+				if curs.node().kind().starts_with("op_") || curs.node().kind().starts_with("psop_") {
+					txt = match self.op_case {
+						Some(super::formatter::OpCase::Upper) => txt.to_uppercase(),
+						Some(super::formatter::OpCase::Lower) => txt.to_lowercase(),
+						None => txt
+					};
+				}
 				trace!("visit: {}",txt);
 				self.tokenized_line.append(&mut txt.as_bytes().to_vec());
 				return Ok(Navigation::GotoSibling);	
@@ -98,6 +106,7 @@ impl Tokenizer
             tokenized_program: Vec::<u8>::new(),
 			columns: 0,
 			style: super::formatter::ColumnStyle::Variable,
+			op_case: None,
 			widths: [9,6,11],
 			line_sep: "\n".to_string(),
 			symbols: Arc::new(super::Symbols::new())
@@ -114,6 +123,14 @@ impl Tokenizer
 	pub fn set_style(&mut self,style: super::formatter::ColumnStyle) {
 		self.style = style;
 	}
+	/// Column widths to use during detokenization with the variable style
+	pub fn set_widths(&mut self,widths: [usize;3]) {
+		self.widths = widths;
+	}
+	/// Change the case of operations and pseudo-operations during tokenization, `None` leaves them as written
+	pub fn set_op_case(&mut self,case: Option<super::formatter::OpCase>) {
+		self.op_case = case;
+	}
 	pub fn use_shared_symbols(&mut self,sym: Arc<super::Symbols>) {
         self.symbols = sym;
     }
@@ -144,6 +161,7 @@ impl Tokenizer
 			None => "\n".to_string()
 		};
 		self.tokenized_program = Vec::new();
+		self.parser.restart();
 		for line in program.lines() {
 			if line.trim().len()==0 {
 				self.tokenized_program.push(0x8d);
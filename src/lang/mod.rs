@@ -16,8 +16,11 @@ pub mod applesoft;
 pub mod integer;
 pub mod merlin;
 pub mod linenum;
+pub mod labels;
 pub mod server;
 pub mod disk_server;
+pub mod highlight;
+pub mod convert;
 
 use tree_sitter;
 use lsp_types as lsp;
@@ -86,12 +86,30 @@
 //! * 5.25 inch IBM formats (160K through 1200K)
 //! * 5.25 inch CP/M formats (Osborne 100K/200K, Kaypro 200K/400K)
 //! * 8 inch CP/M formats (IBM 250K, Nabu 1M, TRS-80 600K)
+//!
+//! ## Conversions
+//!
+//! The `testing` module converts a disk image to another type and verifies the result
+//! block by block, see `testing::verify_convert`.
+//!
+//! ## Compressed Images
+//!
+//! Image files ending in `.gz` or `.zip` are decompressed when they are opened and compressed again
+//! when they are saved.  A zip archive with several images needs the member to be named,
+//! either as in `collection.zip:disk1.dsk`, or see `create_fs_from_archive` and `save_img_to_archive`.
+//!
+//! ## Cassettes
+//!
+//! The `cassette` module converts tokenized BASIC and binary data to and from Apple II cassette audio.
 
 pub mod fs;
 pub mod lang;
 pub mod bios;
 pub mod img;
 pub mod commands;
+pub mod testing;
+pub mod cassette;
+pub mod omf;
 
 use img::DiskImage;
 use fs::DiskFS;
@@ -106,37 +124,359 @@ type STDRESULT = Result<(),Box<dyn std::error::Error>>;
 
 const KNOWN_FILE_EXTENSIONS: &str = "2mg,2img,dsk,d13,do,nib,po,woz,imd,td0,img,ima";
 const MAX_FILE_SIZE: u64 = 1 << 26;
+const MAX_DEVICE_SIZE: u64 = 1 << 32;
+
+/// The local time, or if `deterministic`, a fixed time so that repeated runs give identical outputs.
+/// Use this for anything stored in an image.  The fixed time is taken from the `SOURCE_DATE_EPOCH`
+/// environment variable if it is set, otherwise it is 1980-01-01 00:00.
+pub fn now(deterministic: bool) -> chrono::NaiveDateTime {
+    if !deterministic {
+        return chrono::Local::now().naive_local();
+    }
+    let fallback = chrono::NaiveDate::from_ymd_opt(1980,1,1).and_then(|d| d.and_hms_opt(0,0,0)).unwrap_or_default();
+    match std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|s| s.parse::<i64>().ok()) {
+        Some(secs) => match chrono::DateTime::from_timestamp(secs,0) {
+            Some(t) => t.naive_utc(),
+            None => fallback
+        },
+        None => fallback
+    }
+}
+
+/// Options that change how an image is saved, see `save_img_with`.
+/// The default saves the image as it is.
+#[derive(Clone,Debug,Default)]
+pub struct SaveOptions {
+    /// Rebuild the record of free blocks from the directory tree before saving.
+    /// Blocks that are marked used but belong to no file are freed, and blocks that belong to a file
+    /// but are marked free are allocated.  Only ProDOS and DOS 3.x support this, other file systems
+    /// are saved as they are, with a warning.
+    pub fix_bitmap: bool,
+    /// Record the save as this operation in the provenance sidecar of the image, see `img::provenance`
+    pub provenance: Option<img::provenance::Operation>,
+    /// Volume of a multi-volume hard disk image that was loaded, counting from 1, see `img::volumes`.
+    /// The volume is put back in place.  If this is `None` and the image holds several volumes,
+    /// the first one is assumed, as in `create_fs_from_volume`.
+    pub volume: Option<usize>,
+    /// Save to this path rather than where the image was loaded from.  The image is converted
+    /// if the extension calls for another image type, so that e.g. a DO image can be saved as WOZ.
+    pub save_as: Option<String>,
+    /// Fix the times recorded while saving, such as the provenance time, see `now`
+    pub deterministic: bool,
+    /// Allow the image path to be a block device, such as a USB floppy drive or CF card,
+    /// which is written in place.  This is off unless explicitly requested.
+    pub allow_devices: bool,
+    /// When a ProDOS ordered image that had bytes after the last whole block, or a short last block,
+    /// is saved, drop the extra bytes and pad the last block, rather than keeping the original length.
+    pub repair_padding: bool
+}
+
+impl SaveOptions {
+    /// Options given by the global arguments, `cmd` being the matches of the whole command line,
+    /// so that provenance can name the subcommand
+    pub fn from_matches(cmd: &clap::ArgMatches) -> Self {
+        let provenance = match (cmd.try_get_one::<bool>("provenance"),cmd.subcommand()) {
+            (Ok(Some(true)),Some((name,sub))) => Some(img::provenance::Operation::from_matches(name,sub)),
+            _ => None
+        };
+        Self {
+            fix_bitmap: matches!(cmd.try_get_one::<bool>("fix-bitmap"),Ok(Some(true))),
+            provenance,
+            volume: cmd.subcommand().and_then(|(_,sub)| commands::volume_arg(sub)),
+            save_as: cmd.try_get_one::<String>("save-as").ok().flatten().cloned(),
+            deterministic: matches!(cmd.try_get_one::<bool>("deterministic"),Ok(Some(true))),
+            allow_devices: matches!(cmd.try_get_one::<bool>("yes-i-know"),Ok(Some(true))),
+            repair_padding: matches!(cmd.try_get_one::<bool>("repair-padding"),Ok(Some(true)))
+        }
+    }
+}
+
+/// Options that change how an image is loaded, and how its file system behaves, see `create_fs_with`.
+/// The default loads the image as it is.
+#[derive(Clone,Debug,Default)]
+pub struct LoadOptions {
+    /// Volume of a multi-volume hard disk image to load, counting from 1, see `img::volumes`.
+    /// If this is `None` and the image holds several volumes, the first one is loaded, with a warning.
+    pub volume: Option<usize>,
+    /// What reading a sector that is marked bad produces, for images that record bad sectors
+    pub bad_sectors: img::BadSectorPolicy,
+    /// Behaviors of the file system, see `fs::quirks`, this also applies to new disks
+    pub quirks: fs::quirks::Quirks,
+    /// Skip damaged CP/M directory entries, with a warning, rather than rejecting the disk
+    pub lenient: bool,
+    /// Code page of FAT file names and labels, this also applies to new disks
+    pub codepage: fs::fat::codepage::CodePage,
+    /// Fix the times the file system stores, and identifiers that would be generated from the clock,
+    /// such as FAT volume serial numbers, see `now`.  This also applies to new disks.
+    pub deterministic: bool,
+    /// Allow the image path to be a block device, such as a USB floppy drive or CF card,
+    /// which is read whole.  This is off unless explicitly requested.
+    pub allow_devices: bool
+}
+
+impl LoadOptions {
+    /// Options given by the global arguments, `cmd` being the matches of the subcommand,
+    /// which the global arguments are propagated to
+    pub fn from_matches(cmd: &clap::ArgMatches) -> Result<Self,DYNERR> {
+        let mut bad_sectors = img::BadSectorPolicy::Keep;
+        if let Ok(Some(hex_str)) = cmd.try_get_one::<String>("fill-bad") {
+            match hex::decode(hex_str) {
+                Ok(pattern) if !pattern.is_empty() => bad_sectors = img::BadSectorPolicy::Fill(pattern),
+                _ => {
+                    error!("fill pattern should be a nonempty hex string");
+                    return Err(Box::new(commands::CommandError::InvalidCommand));
+                }
+            }
+        }
+        if let Ok(Some(true)) = cmd.try_get_one::<bool>("fail-bad") {
+            bad_sectors = img::BadSectorPolicy::Fail;
+        }
+        let quirks = match cmd.try_get_one::<String>("quirks") {
+            Ok(Some(path)) => fs::quirks::Quirks::from_json(&std::fs::read_to_string(path)?)?,
+            _ => fs::quirks::Quirks::default()
+        };
+        let codepage = match cmd.try_get_one::<String>("codepage") {
+            Ok(Some(cp)) => fs::fat::codepage::CodePage::new(cp.parse::<u16>()?)?,
+            _ => fs::fat::codepage::CodePage::default()
+        };
+        Ok(Self {
+            volume: commands::volume_arg(cmd),
+            bad_sectors,
+            quirks,
+            lenient: matches!(cmd.try_get_one::<bool>("lenient"),Ok(Some(true))),
+            codepage,
+            deterministic: matches!(cmd.try_get_one::<bool>("deterministic"),Ok(Some(true))),
+            allow_devices: matches!(cmd.try_get_one::<bool>("yes-i-know"),Ok(Some(true)))
+        })
+    }
+}
+
+fn fix_bitmap(disk: &mut Box<dyn DiskFS>,opts: &SaveOptions) -> STDRESULT {
+    if !opts.fix_bitmap {
+        return Ok(());
+    }
+    match disk.repair_bitmap() {
+        Ok(changed) if changed > 0 => log::warn!("bitmap repair changed the state of {} blocks",changed),
+        Ok(_) => {},
+        Err(e) => match e.downcast_ref::<commands::CommandError>() {
+            Some(commands::CommandError::UnsupportedFormat) => log::warn!("this file system has no bitmap repair, saving without it"),
+            _ => return Err(e)
+        }
+    }
+    Ok(())
+}
+
+/// True if `path` is a block device, only detected on unix-like systems
+pub fn is_device(path: &str) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        match std::fs::metadata(path) {
+            Ok(meta) => meta.file_type().is_block_device(),
+            Err(_) => false
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+fn check_device_access(path: &str,allow_devices: bool) -> STDRESULT {
+    if !allow_devices {
+        error!("{} is a device, use `--yes-i-know` if you really mean to access it",path);
+        return Err(Box::new(commands::CommandError::InvalidCommand));
+    }
+    Ok(())
+}
+
+/// Write image data to a file, a device is written in place if `allow_devices`
+fn write_file(path: &str,dat: &[u8],allow_devices: bool) -> STDRESULT {
+    if is_device(path) {
+        check_device_access(path,allow_devices)?;
+        let mut f = std::fs::OpenOptions::new().write(true).open(path)?;
+        std::io::Write::write_all(&mut f,dat)?;
+        f.sync_all()?;
+        return Ok(());
+    }
+    std::fs::write(path,dat)?;
+    Ok(())
+}
+
+/// The image type that saving to `img_path` calls for, if it differs from `current`.
+/// The extension of the image inside any gzip or zip wrapper decides.  If the extension is
+/// one the current type goes by, such as `dsk` for a DO image, there is no conversion.
+fn save_type(img_path: &str,current: img::DiskImageType) -> Option<img::DiskImageType> {
+    use img::DiskImageType as T;
+    let (path,inner) = img::archive::split_member(img_path);
+    let name = match (inner,img::archive::wrapper(path)) {
+        (Some(m),_) => m,
+        (None,Some(img::archive::Wrapper::Gzip)) => &path[0..path.len()-3],
+        (None,Some(img::archive::Wrapper::Zip)) => return None,
+        (None,None) => path
+    };
+    let ext = std::path::Path::new(name).extension()?.to_string_lossy().to_lowercase();
+    if current.file_extensions().contains(&ext) {
+        return None;
+    }
+    // when an extension is shared the first type listed is preferred
+    [T::WOZ2,T::WOZ1,T::DO,T::PO,T::D13,T::IMG,T::DOT2MG,T::NIB,T::IMD,T::TD0].into_iter()
+        .find(|typ| typ.file_extensions().contains(&ext))
+}
+
+/// The bytes to save at `img_path`, converted to the image type its extension calls for.
+/// It is an error if the new type cannot hold the disk, e.g., a 3.5 inch disk cannot be a WOZ1 image.
+fn img_bytes_for(disk: &mut Box<dyn DiskFS>,img_path: &str,deterministic: bool) -> Result<Vec<u8>,DYNERR> {
+    let current = disk.get_img().what_am_i();
+    match save_type(img_path,current) {
+        Some(typ) => {
+            info!("converting {} image to {}",current,typ);
+            match img::convert::convert_with(disk,typ,deterministic) {
+                Ok(mut converted) => Ok(converted.to_bytes()),
+                Err(e) => {
+                    error!("{} image cannot be saved as {}",current,typ);
+                    Err(e)
+                }
+            }
+        },
+        None => Ok(disk.get_img().to_bytes())
+    }
+}
 
-/// Save the image file (make changes permanent)
+/// Save the image file (make changes permanent).
+/// If the path ends in `.gz` or `.zip` the image is compressed again.
+/// The image is saved as it is, to convert it see `SaveOptions::save_as`.
 pub fn save_img(disk: &mut Box<dyn DiskFS>,img_path: &str) -> STDRESULT {
-    std::fs::write(img_path,disk.get_img().to_bytes())?;
+    save_img_with(disk,img_path,&SaveOptions::default())
+}
+
+/// Save the image file as in `save_img`, with the given options
+pub fn save_img_with(disk: &mut Box<dyn DiskFS>,img_path: &str,opts: &SaveOptions) -> STDRESULT {
+    fix_bitmap(disk,opts)?;
+    disk.get_img().set_repair_padding(opts.repair_padding);
+    let dest = opts.save_as.as_deref().unwrap_or(img_path);
+    // a volume goes back into its image as it is
+    if let Some(whole) = splice_volume(dest,None,&disk.get_img().to_bytes(),opts)? {
+        return write_whole_img_file(dest,&whole,opts);
+    }
+    let dat = match opts.save_as {
+        Some(_) => img_bytes_for(disk,dest,opts.deterministic)?,
+        None => disk.get_img().to_bytes()
+    };
+    write_whole_img_file(dest,&dat,opts)
+}
+
+/// Save an image that need not hold a file system, such as after sectors were written directly.
+/// This is `save_img_with` without conversion, which needs a file system, so the extension of a
+/// `save_as` destination has to suit the image type.
+pub fn save_raw_img_with(img: &mut Box<dyn DiskImage>,img_path: &str,opts: &SaveOptions) -> STDRESULT {
+    let dest = match &opts.save_as {
+        Some(path) => match save_type(path,img.what_am_i()) {
+            Some(typ) => {
+                error!("{} image cannot be converted to {} without a file system",img.what_am_i(),typ);
+                return Err(Box::new(img::Error::ImageTypeMismatch));
+            },
+            None => path
+        },
+        None => img_path
+    };
+    img.set_repair_padding(opts.repair_padding);
+    write_img_file(dest,&img.to_bytes(),opts)
+}
+
+/// Write the image data `dat` to the file at `img_path`, wrapping it as in `img::archive::write`,
+/// and record the save in the provenance sidecar if `opts` asks for it.
+/// If `dat` is a volume of a multi-volume image, see `SaveOptions::volume`, it is put back in place.
+pub fn write_img_file(img_path: &str,dat: &[u8],opts: &SaveOptions) -> STDRESULT {
+    match splice_volume(img_path,None,dat,opts)? {
+        Some(whole) => write_whole_img_file(img_path,&whole,opts),
+        None => write_whole_img_file(img_path,dat,opts)
+    }
+}
+
+/// If `dat` is a volume of the multi-volume image at `img_path`, return the whole image with the volume put back.
+/// Without a selection only a full partition can be the first volume, so other images are not read again.
+fn splice_volume(img_path: &str,member: Option<&str>,dat: &[u8],opts: &SaveOptions) -> Result<Option<Vec<u8>>,DYNERR> {
+    let vol = match opts.volume {
+        Some(vol) => vol,
+        None if dat.len() == img::volumes::VOLUME_BLOCKS * 512 => 1,
+        None => return Ok(None)
+    };
+    let path = img::archive::split_member(img_path).0;
+    let whole_len = match std::fs::metadata(path) {
+        Ok(meta) => meta.len() as usize,
+        Err(_) => return Ok(None)
+    };
+    // an uncompressed file with room for only one partition and a 2MG header is not worth reading
+    if img::archive::wrapper(path).is_none() && whole_len <= img::volumes::PARTITION_BLOCKS * 512 + 64 {
+        return Ok(None);
+    }
+    let (mut whole,name) = buffer_whole_img_file(img_path,member,opts.allow_devices)?;
+    if !img::volumes::is_multi_volume(&whole,&name) {
+        return Ok(None);
+    }
+    img::volumes::splice(&mut whole,vol,dat)?;
+    Ok(Some(whole))
+}
+
+fn write_whole_img_file(img_path: &str,dat: &[u8],opts: &SaveOptions) -> STDRESULT {
+    let (path,_) = img::archive::split_member(img_path);
+    let before = match opts.provenance {
+        Some(_) => std::fs::read(path).ok(),
+        None => None
+    };
+    img::archive::write(img_path,dat,None,opts)?;
+    if let Some(op) = &opts.provenance {
+        img::provenance::record(img_path,op,before.as_deref(),&std::fs::read(path)?,opts.deterministic)?;
+    }
     Ok(())
 }
 
+/// Save the image file as the member `member` of the zip archive at `img_path`.
+/// Other members of the archive are kept.  The image is saved as it is, as in `save_img`.
+pub fn save_img_to_archive(disk: &mut Box<dyn DiskFS>,img_path: &str,member: &str) -> STDRESULT {
+    let dat = disk.get_img().to_bytes();
+    match splice_volume(img_path,Some(member),&dat,&SaveOptions::default())? {
+        Some(whole) => img::archive::write(img_path,&whole,Some(member),&SaveOptions::default()),
+        None => img::archive::write(img_path,&dat,Some(member),&SaveOptions::default())
+    }
+}
+
 /// Return the file system on a disk image, if all goes well we have `Ok(Some(fs))`.
 /// If the file system cannot be identified we have `Ok(None)`.
 /// If the file system is identified, but broken, we have `Err(_)`.
 /// If `Ok(Some(_))`, the file system takes ownership of the disk image.
-fn try_img(mut img: Box<dyn DiskImage>) -> Result<Option<Box<dyn DiskFS>>,DYNERR> {
+fn try_img(mut img: Box<dyn DiskImage>,opts: &LoadOptions) -> Result<Option<Box<dyn DiskFS>>,DYNERR> {
+    img.set_bad_sector_policy(opts.bad_sectors.clone());
+    img.set_deterministic(opts.deterministic);
     if fs::dos3x::Disk::test_img(&mut img) {
         info!("identified DOS 3.x file system");
-        return Ok(Some(Box::new(fs::dos3x::Disk::from_img(img)?)));
+        return Ok(Some(Box::new(fs::dos3x::Disk::from_img(img)?.with_quirks(opts.quirks.clone()))));
     }
     if fs::prodos::Disk::test_img(&mut img) {
         info!("identified ProDOS file system");
-        return Ok(Some(Box::new(fs::prodos::Disk::from_img(img)?)));
+        return Ok(Some(Box::new(fs::prodos::Disk::from_img(img)?.with_quirks(opts.quirks.clone()).with_deterministic(opts.deterministic))));
     }
     if fs::pascal::Disk::test_img(&mut img) {
         info!("identified Pascal file system");
-        return Ok(Some(Box::new(fs::pascal::Disk::from_img(img)?)));
+        return Ok(Some(Box::new(fs::pascal::Disk::from_img(img)?.with_deterministic(opts.deterministic))));
+    }
+    if fs::rdos::Disk::test_img(&mut img) {
+        info!("identified RDOS file system");
+        return Ok(Some(Box::new(fs::rdos::Disk::from_img(img)?)));
     }
     if fs::fat::Disk::test_img(&mut img) {
         info!("identified FAT file system");
-        return Ok(Some(Box::new(fs::fat::Disk::from_img(img,None)?)));
+        return Ok(Some(Box::new(fs::fat::Disk::from_img(img,None)?.with_codepage(opts.codepage).with_deterministic(opts.deterministic))));
     }
     if fs::fat::Disk::test_img_dos1x(&mut img) {
         info!("identified MS-DOS 1.x file system");
-        return Ok(Some(Box::new(fs::fat::Disk::from_img_dos1x(img)?)));
+        return Ok(Some(Box::new(fs::fat::Disk::from_img_dos1x(img)?.with_codepage(opts.codepage).with_deterministic(opts.deterministic))));
+    }
+    if fs::fat::Disk::test_img_fat_id(&mut img) {
+        info!("identified FAT file system from the FAT ID");
+        return Ok(Some(Box::new(fs::fat::Disk::from_img_fat_id(img)?.with_codepage(opts.codepage).with_deterministic(opts.deterministic))));
     }
     // For CP/M we have to try all these DPB heuristically
     let dpb_list = vec![
@@ -152,9 +492,9 @@ fn try_img(mut img: Box<dyn DiskImage>) -> Result<Option<Box<dyn DiskFS>>,DYNERR
 
     ];
     for dpb in &dpb_list {
-        if fs::cpm::Disk::test_img(&mut img,dpb,[3,1,0]) {
+        if fs::cpm::Disk::test_img(&mut img,dpb,[3,1,0],opts.lenient) {
             info!("identified CP/M file system on {}",dpb);
-            return Ok(Some(Box::new(fs::cpm::Disk::from_img(img,dpb.clone(),[3,1,0])?)));
+            return Ok(Some(Box::new(fs::cpm::Disk::from_img(img,dpb.clone(),[3,1,0])?.with_lenient(opts.lenient).with_deterministic(opts.deterministic))));
         }
     }
    return Ok(None);
@@ -163,6 +503,11 @@ fn try_img(mut img: Box<dyn DiskImage>) -> Result<Option<Box<dyn DiskFS>>,DYNERR
 /// Given a bytestream return a DiskFS, or Err if the bytestream cannot be interpreted.
 /// Optional `maybe_ext` restricts the image types that will be tried based on file extension.
 pub fn create_fs_from_bytestream(disk_img_data: &Vec<u8>,maybe_ext: Option<&str>) -> Result<Box<dyn DiskFS>,DYNERR> {
+    create_fs_from_bytestream_with(disk_img_data,maybe_ext,&LoadOptions::default())
+}
+
+/// Given a bytestream return a DiskFS as in `create_fs_from_bytestream`, with the given options
+pub fn create_fs_from_bytestream_with(disk_img_data: &[u8],maybe_ext: Option<&str>,opts: &LoadOptions) -> Result<Box<dyn DiskFS>,DYNERR> {
     let ext = match maybe_ext {
         Some(x) => x.to_string().to_lowercase(),
         None => "".to_string()
@@ -174,7 +519,7 @@ pub fn create_fs_from_bytestream(disk_img_data: &Vec<u8>,maybe_ext: Option<&str>
     if img::imd::file_extensions().contains(&ext) || ext=="" {
         if let Ok(img) = img::imd::Imd::from_bytes(disk_img_data) {
             info!("identified IMD image");
-            if let Some(disk) = try_img(Box::new(img))? {
+            if let Some(disk) = try_img(Box::new(img),opts)? {
                 return Ok(disk);
             }
         }
@@ -182,7 +527,7 @@ pub fn create_fs_from_bytestream(disk_img_data: &Vec<u8>,maybe_ext: Option<&str>
     if img::woz1::file_extensions().contains(&ext) || ext=="" {
         if let Ok(img) = img::woz1::Woz1::from_bytes(disk_img_data) {
             info!("identified woz1 image");
-            if let Some(disk) = try_img(Box::new(img))? {
+            if let Some(disk) = try_img(Box::new(img),opts)? {
                 return Ok(disk);
             }
         }
@@ -190,7 +535,7 @@ pub fn create_fs_from_bytestream(disk_img_data: &Vec<u8>,maybe_ext: Option<&str>
     if img::woz2::file_extensions().contains(&ext) || ext=="" {
         if let Ok(img) = img::woz2::Woz2::from_bytes(disk_img_data) {
             info!("identified woz2 image");
-            if let Some(disk) = try_img(Box::new(img))? {
+            if let Some(disk) = try_img(Box::new(img),opts)? {
                 return Ok(disk);
             }
         }
@@ -198,7 +543,7 @@ pub fn create_fs_from_bytestream(disk_img_data: &Vec<u8>,maybe_ext: Option<&str>
     if img::dot2mg::file_extensions().contains(&ext) || ext=="" {
         if let Ok(img) = img::dot2mg::Dot2mg::from_bytes(disk_img_data) {
             info!("identified 2mg image");
-            if let Some(disk) = try_img(Box::new(img))? {
+            if let Some(disk) = try_img(Box::new(img),opts)? {
                 return Ok(disk);
             }
         }
@@ -206,7 +551,7 @@ pub fn create_fs_from_bytestream(disk_img_data: &Vec<u8>,maybe_ext: Option<&str>
     if img::td0::file_extensions().contains(&ext) || ext=="" {
         if let Ok(img) = img::td0::Td0::from_bytes(disk_img_data) {
             info!("identified td0 image");
-            if let Some(disk) = try_img(Box::new(img))? {
+            if let Some(disk) = try_img(Box::new(img),opts)? {
                 return Ok(disk);
             }
         }
@@ -214,7 +559,7 @@ pub fn create_fs_from_bytestream(disk_img_data: &Vec<u8>,maybe_ext: Option<&str>
     if img::nib::file_extensions().contains(&ext) || ext=="" {
         if let Ok(img) = img::nib::Nib::from_bytes(disk_img_data) {
             info!("Possible nib/nb2 image");
-            if let Some(disk) = try_img(Box::new(img))? {
+            if let Some(disk) = try_img(Box::new(img),opts)? {
                 return Ok(disk);
             }
         }
@@ -222,7 +567,7 @@ pub fn create_fs_from_bytestream(disk_img_data: &Vec<u8>,maybe_ext: Option<&str>
     if img::dsk_d13::file_extensions().contains(&ext) || ext=="" {
         if let Ok(img) = img::dsk_d13::D13::from_bytes(disk_img_data) {
             info!("Possible D13 image");
-            if let Some(disk) = try_img(Box::new(img))? {
+            if let Some(disk) = try_img(Box::new(img),opts)? {
                 return Ok(disk);
             }
         }
@@ -230,7 +575,7 @@ pub fn create_fs_from_bytestream(disk_img_data: &Vec<u8>,maybe_ext: Option<&str>
     if img::dsk_do::file_extensions().contains(&ext) || ext=="" {
         if let Ok(img) = img::dsk_do::DO::from_bytes(disk_img_data) {
             info!("Possible DO image");
-            if let Some(disk) = try_img(Box::new(img))? {
+            if let Some(disk) = try_img(Box::new(img),opts)? {
                 return Ok(disk);
             }
         }
@@ -238,7 +583,7 @@ pub fn create_fs_from_bytestream(disk_img_data: &Vec<u8>,maybe_ext: Option<&str>
     if img::dsk_po::file_extensions().contains(&ext) || ext=="" {
         if let Ok(img) = img::dsk_po::PO::from_bytes(disk_img_data) {
             info!("Possible PO image");
-            if let Some(disk) = try_img(Box::new(img))? {
+            if let Some(disk) = try_img(Box::new(img),opts)? {
                 return Ok(disk);
             }
         }
@@ -246,7 +591,7 @@ pub fn create_fs_from_bytestream(disk_img_data: &Vec<u8>,maybe_ext: Option<&str>
     if img::dsk_img::file_extensions().contains(&ext) || ext=="" {
         if let Ok(img) = img::dsk_img::Img::from_bytes(disk_img_data) {
             info!("Possible IMG image");
-            if let Some(disk) = try_img(Box::new(img))? {
+            if let Some(disk) = try_img(Box::new(img),opts)? {
                 return Ok(disk);
             }
         }
@@ -259,6 +604,18 @@ pub fn create_fs_from_bytestream(disk_img_data: &Vec<u8>,maybe_ext: Option<&str>
 /// Optional `maybe_ext` restricts the image types that will be tried based on file extension.
 /// N.b. the ordering for DSK types cannot always be determined without the file system.
 pub fn create_img_from_bytestream(disk_img_data: &Vec<u8>,maybe_ext: Option<&str>) -> Result<Box<dyn DiskImage>,DYNERR> {
+    create_img_from_bytestream_with(disk_img_data,maybe_ext,&LoadOptions::default())
+}
+
+/// Given a bytestream return a disk image as in `create_img_from_bytestream`, with the given options
+pub fn create_img_from_bytestream_with(disk_img_data: &[u8],maybe_ext: Option<&str>,opts: &LoadOptions) -> Result<Box<dyn DiskImage>,DYNERR> {
+    let mut img = match_img(disk_img_data,maybe_ext,opts)?;
+    img.set_bad_sector_policy(opts.bad_sectors.clone());
+    img.set_deterministic(opts.deterministic);
+    Ok(img)
+}
+
+fn match_img(disk_img_data: &[u8],maybe_ext: Option<&str>,opts: &LoadOptions) -> Result<Box<dyn DiskImage>,DYNERR> {
     let ext = match maybe_ext {
         Some(x) => x.to_string().to_lowercase(),
         None => "".to_string()
@@ -317,7 +674,7 @@ pub fn create_img_from_bytestream(disk_img_data: &Vec<u8>,maybe_ext: Option<&str
             if ext=="do" {
                 return Ok(Box::new(img));
             }
-            if let Ok(Some(_)) = try_img(Box::new(img)) {
+            if let Ok(Some(_)) = try_img(Box::new(img),opts) {
                 if let Ok(copy) = img::dsk_do::DO::from_bytes(disk_img_data) {
                     return Ok(Box::new(copy));
                 }
@@ -341,8 +698,11 @@ pub fn create_img_from_bytestream(disk_img_data: &Vec<u8>,maybe_ext: Option<&str
     return Err(Box::new(img::Error::ImageTypeMismatch));
 }
 
-/// buffer a file if its EOF < `max`, otherwise return an error
-fn buffer_file(path: &str,max: u64) -> Result<Vec<u8>,DYNERR> {
+/// buffer a file if its EOF < `max`, otherwise return an error, a device is read only if `allow_devices`
+fn buffer_file(path: &str,max: u64,allow_devices: bool) -> Result<Vec<u8>,DYNERR> {
+    if is_device(path) {
+        return buffer_device(path,allow_devices);
+    }
     match std::fs::OpenOptions::new().read(true).open(path) {
         Ok(mut f) => match f.metadata()?.len() <= max {
             true => {
@@ -356,88 +716,183 @@ fn buffer_file(path: &str,max: u64) -> Result<Vec<u8>,DYNERR> {
     }
 }
 
-/// Calls `create_img_from_bytestream` getting the bytes from stdin.
-/// All image types will be tried heuristically.
-pub fn create_img_from_stdin() -> Result<Box<dyn DiskImage>,DYNERR> {
+/// Read a whole block device, the size is found by seeking to the end since the metadata has no length
+fn buffer_device(path: &str,allow_devices: bool) -> Result<Vec<u8>,DYNERR> {
+    check_device_access(path,allow_devices)?;
+    let mut f = std::fs::OpenOptions::new().read(true).open(path)?;
+    let size = std::io::Seek::seek(&mut f,std::io::SeekFrom::End(0))?;
+    info!("device {} has {} bytes",path,size);
+    if size > MAX_DEVICE_SIZE {
+        error!("device is larger than {} bytes",MAX_DEVICE_SIZE);
+        return Err(Box::new(img::Error::ImageSizeMismatch));
+    }
+    std::io::Seek::seek(&mut f,std::io::SeekFrom::Start(0))?;
+    let mut buf = vec![0;size as usize];
+    f.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Buffer an image file, decompressing it if it is wrapped in gzip or zip.
+/// Returns the image data and the image file extension, if it is known.
+/// If `vol` is given, or the image holds several volumes, the selected or first volume is returned as a PO image.
+/// The image path can be a device only if `allow_devices`.
+fn buffer_img_file(img_path: &str,member: Option<&str>,vol: Option<usize>,allow_devices: bool) -> Result<(Vec<u8>,Option<String>),DYNERR> {
+    let (dat,name) = match (vol,buffer_file(img::archive::split_member(img_path).0,MAX_FILE_SIZE,allow_devices)) {
+        (None,Ok(dat)) => img::archive::read(img_path,dat,member,MAX_FILE_SIZE as usize)?,
+        (None,Err(e)) if !matches!(e.downcast_ref::<img::Error>(),Some(img::Error::ImageSizeMismatch)) => return Err(e),
+        _ => buffer_whole_img_file(img_path,member,allow_devices)?
+    };
+    match vol {
+        Some(vol) => return Ok((img::volumes::extract(&dat,vol)?,Some("po".to_string()))),
+        None if img::volumes::is_multi_volume(&dat,&name) => {
+            warn!("image holds {} volumes, using the first, select another with `--volume`",img::volumes::count(&dat));
+            return Ok((img::volumes::extract(&dat,1)?,Some("po".to_string())));
+        },
+        None if dat.len() > MAX_FILE_SIZE as usize => {
+            error!("image is too large");
+            return Err(Box::new(img::Error::ImageSizeMismatch));
+        },
+        None => {}
+    }
+    let mut maybe_ext = name.split('.').next_back().map(|s| s.to_string());
+    if let Some(ext) = &maybe_ext {
+        if !KNOWN_FILE_EXTENSIONS.contains(&ext.to_lowercase()) {
+            maybe_ext = None;
+        }
+    }
+    Ok((dat,maybe_ext))
+}
+
+/// Buffer an image file that may hold several volumes, decompressing it if necessary.
+/// Returns the image data and the name the image goes by.  The image path can be a device only if `allow_devices`.
+pub fn buffer_whole_img_file(img_path: &str,member: Option<&str>,allow_devices: bool) -> Result<(Vec<u8>,String),DYNERR> {
+    let max = img::volumes::MAX_FILE_SIZE;
+    let dat = buffer_file(img::archive::split_member(img_path).0,max as u64,allow_devices)?;
+    img::archive::read(img_path,dat,member,max)
+}
+
+/// Read a disk image piped to stdin, it is an error if stdin is a terminal
+fn buffer_stdin() -> Result<Vec<u8>,DYNERR> {
     let mut disk_img_data = Vec::new();
     if atty::is(atty::Stream::Stdin) {
         error!("pipe a disk image or use `-d` option");
         return Err(Box::new(commands::CommandError::InvalidCommand));
     }
-    match std::io::stdin().read_to_end(&mut disk_img_data) {
-        Ok(_n) => create_img_from_bytestream(&disk_img_data,None),
-        Err(e) => Err(Box::new(e))
-    }
+    std::io::stdin().read_to_end(&mut disk_img_data)?;
+    Ok(disk_img_data)
+}
+
+/// Calls `create_img_from_bytestream` getting the bytes from stdin.
+/// All image types will be tried heuristically.
+pub fn create_img_from_stdin() -> Result<Box<dyn DiskImage>,DYNERR> {
+    create_img_from_bytestream(&buffer_stdin()?,None)
 }
 
 /// Calls `create_img_from_bytestream` getting the bytes from a file.
 /// The pathname must already be in the right format for the file system.
 /// File extension will be used to restrict image types that are tried,
 /// unless the extension is unknown, in which case all will be tried.
+/// Files ending in `.gz` or `.zip` are decompressed first, see `img::archive`.
+/// If the image holds several volumes the first is used, see `create_img_from_volume`.
 pub fn create_img_from_file(img_path: &str) -> Result<Box<dyn DiskImage>,DYNERR> {
-    match buffer_file(img_path,MAX_FILE_SIZE) {
-        Ok(disk_img_data) => {
-            let mut maybe_ext = img_path.split('.').last();
-            if let Some(ext) = maybe_ext {
-                if !KNOWN_FILE_EXTENSIONS.contains(&ext.to_lowercase()) {
-                    maybe_ext = None;
-                }
-            }
-            create_img_from_bytestream(&disk_img_data,maybe_ext)
-        },
-        Err(e) => Err(e)
-    }
+    create_img_from_volume(img_path,None)
+}
+
+/// Calls `create_img_from_bytestream` getting the bytes of volume `vol` of a multi-volume hard disk image,
+/// counting from 1, see `img::volumes`.  If `vol` is `None` this is `create_img_from_file`, except that
+/// an image with several volumes gives its first volume, with a warning.
+pub fn create_img_from_volume(img_path: &str,vol: Option<usize>) -> Result<Box<dyn DiskImage>,DYNERR> {
+    create_img_with(img_path,&LoadOptions { volume: vol, ..LoadOptions::default() })
+}
+
+/// Calls `create_img_from_bytestream_with` getting the bytes from a file, as in `create_img_from_volume`
+/// with the volume and other options taken from `opts`.
+pub fn create_img_with(img_path: &str,opts: &LoadOptions) -> Result<Box<dyn DiskImage>,DYNERR> {
+    let (disk_img_data,maybe_ext) = buffer_img_file(img_path,None,opts.volume,opts.allow_devices)?;
+    create_img_from_bytestream_with(&disk_img_data,maybe_ext.as_deref(),opts)
+}
+
+/// Calls `create_img_from_bytestream` getting the bytes from the member `member` of a zip archive.
+pub fn create_img_from_archive(img_path: &str,member: &str) -> Result<Box<dyn DiskImage>,DYNERR> {
+    let (disk_img_data,maybe_ext) = buffer_img_file(img_path,Some(member),None,false)?;
+    create_img_from_bytestream(&disk_img_data,maybe_ext.as_deref())
 }
 
 pub fn create_img_from_file_or_stdin(maybe_img_path: Option<&String>) -> Result<Box<dyn DiskImage>,DYNERR> {
+    create_img_from_volume_or_stdin(maybe_img_path,None)
+}
+
+pub fn create_img_from_volume_or_stdin(maybe_img_path: Option<&String>,vol: Option<usize>) -> Result<Box<dyn DiskImage>,DYNERR> {
+    create_img_with_or_stdin(maybe_img_path,&LoadOptions { volume: vol, ..LoadOptions::default() })
+}
+
+pub fn create_img_with_or_stdin(maybe_img_path: Option<&String>,opts: &LoadOptions) -> Result<Box<dyn DiskImage>,DYNERR> {
     match maybe_img_path {
-        Some(img_path) => create_img_from_file(img_path),
-        None => create_img_from_stdin()
+        Some(img_path) => create_img_with(img_path,opts),
+        None => create_img_from_bytestream_with(&buffer_stdin()?,None,opts)
     }
 }
 
 /// Calls `create_fs_from_bytestream` getting the bytes from stdin.
 /// All image types and file systems will be tried heuristically.
 pub fn create_fs_from_stdin() -> Result<Box<dyn DiskFS>,DYNERR> {
-    let mut disk_img_data = Vec::new();
-    if atty::is(atty::Stream::Stdin) {
-        error!("pipe a disk image or use `-d` option");
-        return Err(Box::new(commands::CommandError::InvalidCommand));
-    }
-    match std::io::stdin().read_to_end(&mut disk_img_data) {
-        Ok(_n) => create_fs_from_bytestream(&disk_img_data,None),
-        Err(e) => Err(Box::new(e))
-    }
+    create_fs_from_bytestream(&buffer_stdin()?,None)
 }
 
 /// Calls `create_fs_from_bytestream` getting the bytes from a file.
 /// The pathname must already be in the right format for the file system.
 /// File extension will be used to restrict image types that are tried,
 /// unless the extension is unknown, in which case all will be tried.
+/// Files ending in `.gz` or `.zip` are decompressed first, see `img::archive`.
+/// If the image holds several volumes the first is used, see `create_fs_from_volume`.
 pub fn create_fs_from_file(img_path: &str) -> Result<Box<dyn DiskFS>,DYNERR> {
-    match buffer_file(img_path,MAX_FILE_SIZE) {
-        Ok(disk_img_data) => {
-            let mut maybe_ext = img_path.split('.').last();
-            if let Some(ext) = maybe_ext {
-                if !KNOWN_FILE_EXTENSIONS.contains(&ext.to_lowercase()) {
-                    maybe_ext = None;
-                }
-            }
-            create_fs_from_bytestream(&disk_img_data,maybe_ext)
-        },
-        Err(e) => Err(e)
-    }
+    create_fs_from_volume(img_path,None)
+}
+
+/// Calls `create_fs_from_bytestream` getting the bytes of volume `vol` of a multi-volume hard disk image,
+/// counting from 1, see `img::volumes`.  If `vol` is `None` this is `create_fs_from_file`, except that
+/// an image with several volumes gives its first volume, with a warning.
+pub fn create_fs_from_volume(img_path: &str,vol: Option<usize>) -> Result<Box<dyn DiskFS>,DYNERR> {
+    create_fs_with(img_path,&LoadOptions { volume: vol, ..LoadOptions::default() })
+}
+
+/// Calls `create_fs_from_bytestream_with` getting the bytes from a file, as in `create_fs_from_volume`
+/// with the volume and other options taken from `opts`.
+pub fn create_fs_with(img_path: &str,opts: &LoadOptions) -> Result<Box<dyn DiskFS>,DYNERR> {
+    let (disk_img_data,maybe_ext) = buffer_img_file(img_path,None,opts.volume,opts.allow_devices)?;
+    create_fs_from_bytestream_with(&disk_img_data,maybe_ext.as_deref(),opts)
+}
+
+/// Calls `create_fs_from_bytestream` getting the bytes from the member `member` of a zip archive.
+pub fn create_fs_from_archive(img_path: &str,member: &str) -> Result<Box<dyn DiskFS>,DYNERR> {
+    create_fs_from_archive_with(img_path,member,&LoadOptions::default())
+}
+
+/// Calls `create_fs_from_bytestream_with` getting the bytes from the member `member` of a zip archive.
+/// The volume in `opts` is not used.
+pub fn create_fs_from_archive_with(img_path: &str,member: &str,opts: &LoadOptions) -> Result<Box<dyn DiskFS>,DYNERR> {
+    let (disk_img_data,maybe_ext) = buffer_img_file(img_path,Some(member),None,opts.allow_devices)?;
+    create_fs_from_bytestream_with(&disk_img_data,maybe_ext.as_deref(),opts)
 }
 
 pub fn create_fs_from_file_or_stdin(maybe_img_path: Option<&String>) -> Result<Box<dyn DiskFS>,DYNERR> {
+    create_fs_from_volume_or_stdin(maybe_img_path,None)
+}
+
+pub fn create_fs_from_volume_or_stdin(maybe_img_path: Option<&String>,vol: Option<usize>) -> Result<Box<dyn DiskFS>,DYNERR> {
+    create_fs_with_or_stdin(maybe_img_path,&LoadOptions { volume: vol, ..LoadOptions::default() })
+}
+
+pub fn create_fs_with_or_stdin(maybe_img_path: Option<&String>,opts: &LoadOptions) -> Result<Box<dyn DiskFS>,DYNERR> {
     match maybe_img_path {
-        Some(img_path) => create_fs_from_file(img_path),
-        None => create_fs_from_stdin()
+        Some(img_path) => create_fs_with(img_path,opts),
+        None => create_fs_from_bytestream_with(&buffer_stdin()?,None,opts)
     }
 }
 
-/// Display binary to stdout in columns of hex, +ascii, and -ascii
-pub fn display_block(start_addr: usize,block: &Vec<u8>) {
+/// Format binary as lines with columns of hex, +ascii, and -ascii
+pub fn block_lines(start_addr: usize,block: &[u8]) -> Vec<String> {
+    let mut ans = Vec::new();
     let mut slice_start = 0;
     loop {
         let row_label = start_addr + slice_start;
@@ -455,23 +910,32 @@ pub fn display_block(start_addr: usize,block: &Vec<u8>) {
             x if x>=160 && x<255 => x - 128,
             _ => 46
         }).collect();
-        print!("{:04X} : ",row_label);
+        let mut line = format!("{:04X} : ",row_label);
         for byte in slice {
-            print!("{:02X} ",byte);
+            line += &format!("{:02X} ",byte);
         }
         for _blank in slice_end..slice_start+16 {
-            print!("   ");
+            line += "   ";
         }
-        print!("|+| {} ",String::from_utf8_lossy(&txt));
+        line += &format!("|+| {} ",String::from_utf8_lossy(&txt));
         for _blank in slice_end..slice_start+16 {
-            print!(" ");
+            line += " ";
         }
-        println!("|-| {}",String::from_utf8_lossy(&neg_txt));
+        line += &format!("|-| {}",String::from_utf8_lossy(&neg_txt));
+        ans.push(line);
         slice_start += 16;
         if slice_end==block.len() {
             break;
         }
     }
+    ans
+}
+
+/// Display binary to stdout in columns of hex, +ascii, and -ascii
+pub fn display_block(start_addr: usize,block: &Vec<u8>) {
+    for line in block_lines(start_addr,block) {
+        println!("{}",line);
+    }
 }
 
 /// This takes any bytes and makes an ascii friendly string
@@ -31,11 +31,17 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
     let main_cmd = cli::build_cli();
     let main_cmd_copy = main_cmd.clone();
     let matches = main_cmd.get_matches();
+    let save_opts = a2kit::SaveOptions::from_matches(&matches);
+    let load_opts = match matches.subcommand() {
+        Some((_,sub)) => a2kit::LoadOptions::from_matches(sub)?,
+        None => a2kit::LoadOptions::default()
+    };
+    let cache_stats = matches.get_flag("cache-stats");
     
     // Create a disk image
 
     if let Some(cmd) = matches.subcommand_matches("mkdsk") {
-        return commands::mkdsk::mkdsk(cmd);
+        return commands::mkdsk::mkdsk(cmd,&save_opts);
     }
 
     // Catalog a disk image
@@ -45,31 +51,82 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
             Some(path) => path,
             _ => "/"
         };
-        let mut disk = a2kit::create_fs_from_file_or_stdin(cmd.get_one::<String>("dimg"))?;
-        return if cmd.get_flag("generic") {
-            let rows = disk.catalog_to_vec(&path_in_img)?;
-            for row in rows {
-                println!("{}",row);
-            }
-            Ok(())
+        let mut disk = a2kit::create_fs_with_or_stdin(cmd.get_one::<String>("dimg"),&load_opts)?;
+        let maybe_out = cmd.get_one::<String>("output");
+        if maybe_out.is_some() {
+            colored::control::set_override(false);
+        }
+        let order = match cmd.get_one::<String>("sort") {
+            Some(key) => a2kit::fs::CatalogOrder::from_str(key)?,
+            None => a2kit::fs::CatalogOrder::Directory
+        };
+        let mut filter = a2kit::fs::CatalogFilter::default();
+        if let Some(key_value) = cmd.get_one::<String>("filter") {
+            filter.set(key_value)?;
+        }
+        if let Some(date) = cmd.get_one::<String>("since") {
+            filter.set_since(date)?;
+        }
+        filter.limit = cmd.get_one::<usize>("limit").copied();
+        let listing = if cmd.get_flag("plain") {
+            let items = a2kit::fs::filter_catalog(disk.catalog_items(path_in_img)?,&filter,order);
+            a2kit::fs::render::plain(&items)
+        } else if cmd.get_flag("generic") {
+            let rows = disk.catalog_filtered(path_in_img,&filter,order)?;
+            rows.iter().map(|row| row.to_string() + "\n").collect()
         } else {
-            disk.catalog_to_stdout(&path_in_img)
+            disk.catalog_to_string_with(path_in_img,&filter,order)?
+        };
+        if cache_stats {
+            report_cache_stats(disk.get_img().as_ref());
         }
+        return commands::write_output(maybe_out,listing.as_bytes());
     }
     
     // Output the directory tree as a JSON string
 
     if let Some(cmd) = matches.subcommand_matches("tree") {
-        let mut disk = a2kit::create_fs_from_file_or_stdin(cmd.get_one::<String>("dimg"))?;
-        println!("{}",disk.tree(cmd.get_flag("meta"), cmd.get_one::<u16>("indent").copied())?);
+        let mut disk = a2kit::create_fs_with_or_stdin(cmd.get_one::<String>("dimg"),&load_opts)?;
+        let opt = a2kit::fs::TreeOptions {
+            meta: cmd.get_flag("meta"),
+            indent: cmd.get_one::<u16>("indent").copied(),
+            depth: cmd.get_one::<u64>("depth").map(|d| *d as usize),
+            du: cmd.get_flag("du"),
+            paths: cmd.get_flag("paths")
+        };
+        let tree = disk.tree_with(opt)? + "\n";
+        if cache_stats {
+            report_cache_stats(disk.get_img().as_ref());
+        }
+        return commands::write_output(cmd.get_one::<String>("output"),tree.as_bytes());
+    }
+
+    // Output the block structure of a file
+
+    if let Some(cmd) = matches.subcommand_matches("inspect") {
+        let mut disk = a2kit::create_fs_with_or_stdin(cmd.get_one::<String>("dimg"),&load_opts)?;
+        println!("{}",disk.inspect(cmd.get_one::<String>("file").unwrap(),cmd.get_one::<u16>("indent").copied())?);
+        if cache_stats {
+            report_cache_stats(disk.get_img().as_ref());
+        }
         return Ok(());
     }
 
     // Output the matches to the glob pattern
 
     if let Some(cmd) = matches.subcommand_matches("glob") {
-        let mut disk = a2kit::create_fs_from_file_or_stdin(cmd.get_one::<String>("dimg"))?;
-        let v = disk.glob(cmd.get_one::<String>("file").unwrap(),false)?;
+        let opt = a2kit::fs::GlobOptions { case_sensitive: cmd.get_flag("case"), fold: cmd.get_flag("fold") };
+        let v = match cmd.get_one::<String>("workspace") {
+            Some(map_path) => a2kit::fs::pascal::workspace::Workspace::from_file(map_path)?.glob_with(cmd.get_one::<String>("file").unwrap(),opt)?,
+            None => {
+                let mut disk = a2kit::create_fs_with_or_stdin(cmd.get_one::<String>("dimg"),&load_opts)?;
+                let v = disk.glob_with(cmd.get_one::<String>("file").unwrap(),opt)?;
+                if cache_stats {
+                    report_cache_stats(disk.get_img().as_ref());
+                }
+                v
+            }
+        };
         let mut obj = json::array![];
         for m in v {
             obj.push(m)?;
@@ -85,8 +142,14 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
     // Output the FS stats as a JSON string
 
     if let Some(cmd) = matches.subcommand_matches("stat") {
-        let mut disk = a2kit::create_fs_from_file_or_stdin(cmd.get_one::<String>("dimg"))?;
-        let stats = disk.stat()?;
+        let mut disk = a2kit::create_fs_with_or_stdin(cmd.get_one::<String>("dimg"),&load_opts)?;
+        let mut stats = disk.stat()?;
+        if cmd.get_one::<String>("level").unwrap() == "deep" {
+            stats.raw = json::stringify(disk.stat_deep()?);
+        }
+        if cache_stats {
+            report_cache_stats(disk.get_img().as_ref());
+        }
         println!("{}",stats.to_json(cmd.get_one::<u16>("indent").copied()));
         return Ok(());
     }
@@ -94,14 +157,150 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
     // Output the disk geometry as a JSON string
 
     if let Some(cmd) = matches.subcommand_matches("geometry") {
-        let mut disk = a2kit::create_img_from_file_or_stdin(cmd.get_one::<String>("dimg"))?;
-        println!("{}",disk.export_geometry(cmd.get_one::<u16>("indent").copied())?);
+        let mut disk = a2kit::create_img_with_or_stdin(cmd.get_one::<String>("dimg"),&load_opts)?;
+        match cmd.get_flag("table") {
+            true => print!("{}",disk.geometry_table()?),
+            false => println!("{}",disk.export_geometry(cmd.get_one::<u16>("indent").copied())?)
+        }
+        if cache_stats {
+            report_cache_stats(disk.as_ref());
+        }
         return Ok(());
     }
 
+    // List volumes of a hard disk image
+
+    if let Some(cmd) = matches.subcommand_matches("volumes") {
+        let (dat,_) = a2kit::buffer_whole_img_file(cmd.get_one::<String>("dimg").expect(RCH),None,load_opts.allow_devices)?;
+        let mut list = json::JsonValue::new_array();
+        for vol in 1..=a2kit::img::volumes::count(&dat) {
+            let vol_dat = a2kit::img::volumes::extract(&dat,vol)?;
+            let mut obj = json::object! {
+                "volume": vol,
+                "blocks": vol_dat.len() / 512
+            };
+            match a2kit::create_fs_from_bytestream_with(&vol_dat,Some("po"),&load_opts) {
+                Ok(mut disk) => {
+                    let stat = disk.stat()?;
+                    obj["file_system"] = stat.fs_name.into();
+                    obj["label"] = stat.label.into();
+                    obj["free_blocks"] = stat.free_blocks.into();
+                },
+                Err(_) => obj["file_system"] = json::Null
+            }
+            list.push(obj)?;
+        }
+        match cmd.get_one::<u16>("indent") {
+            Some(spaces) => println!("{}",json::stringify_pretty(list,*spaces)),
+            None => println!("{}",json::stringify(list))
+        }
+        return Ok(());
+    }
+
+    // Provenance sidecar
+
+    if let Some(cmd) = matches.subcommand_matches("provenance") {
+        let path_to_img = cmd.get_one::<String>("dimg").expect(RCH);
+        let problems = a2kit::img::provenance::verify(path_to_img)?;
+        if let Some(sidecar) = a2kit::img::provenance::load(path_to_img)? {
+            match cmd.get_one::<u16>("indent") {
+                Some(spaces) => println!("{}",json::stringify_pretty(sidecar,*spaces)),
+                None => println!("{}",json::stringify(sidecar))
+            }
+        }
+        for problem in &problems {
+            log::error!("{}",problem);
+        }
+        if !problems.is_empty() {
+            return Err(Box::new(a2kit::img::Error::MetadataMismatch));
+        }
+        return Ok(());
+    }
+
+    // Bundle Applesoft program and assets
+
+    if let Some(cmd) = matches.subcommand_matches("bundle") {
+        return commands::bundle::bundle(cmd,&save_opts);
+    }
+
+    // Track entropy
+
+    if let Some(cmd) = matches.subcommand_matches("entropy") {
+        return commands::entropy::entropy(cmd);
+    }
+
+    // Verify conversion
+    if let Some(cmd) = matches.subcommand_matches("verify-convert") {
+        let path_to_img = cmd.get_one::<String>("dimg").expect(RCH);
+        let typ = a2kit::img::DiskImageType::from_str(cmd.get_one::<String>("type").expect(RCH))?;
+        let ans = a2kit::testing::verify_convert(path_to_img, typ)?;
+        println!("{}",ans.to_json(cmd.get_one::<u16>("indent").copied()));
+        if !ans.is_lossless() {
+            log::error!("conversion is not lossless");
+            return Err(Box::new(a2kit::testing::Error::NotEquivalent));
+        }
+        if let Some(out) = cmd.get_one::<String>("output") {
+            std::fs::write(out,&ans.converted)?;
+        }
+        return Ok(());
+    }
+
+    // Normalize a directory of images
+
+    if let Some(cmd) = matches.subcommand_matches("normalize") {
+        return commands::normalize::normalize(cmd);
+    }
+
+    // Interactive browser
+
+    #[cfg(feature = "browse")]
+    if let Some(cmd) = matches.subcommand_matches("browse") {
+        return commands::browse::browse(cmd,&save_opts);
+    }
+
+    // Compare file systems
+
+    if let Some(cmd) = matches.subcommand_matches("fsdiff") {
+        return commands::fsdiff::fsdiff(cmd);
+    }
+
+    // Dump and restore ranges of sectors or blocks
+
+    if let Some(cmd) = matches.subcommand_matches("dump") {
+        return commands::dump::dump(cmd);
+    }
+    if let Some(cmd) = matches.subcommand_matches("restore") {
+        return commands::dump::restore(cmd,&save_opts);
+    }
+
+    // Wipe free space, deleted entries, or sectors
+
+    if let Some(cmd) = matches.subcommand_matches("wipe") {
+        return commands::wipe::wipe(cmd,&save_opts);
+    }
+
+    if let Some(cmd) = matches.subcommand_matches("banner") {
+        return commands::put::banner(cmd,&save_opts);
+    }
+
+    // Carve files from a damaged image
+
+    if let Some(cmd) = matches.subcommand_matches("carve") {
+        return commands::carve::carve(cmd);
+    }
+
+    // Migrate DOS 3.3 to ProDOS
+
+    if let Some(cmd) = matches.subcommand_matches("migrate") {
+        return commands::migrate::migrate(cmd,&save_opts);
+    }
+
     // Verify
 
     if let Some(cmd) = matches.subcommand_matches("verify") {
+        if cmd.get_one::<String>("type").expect(RCH)=="bin" {
+            return commands::verify_bin::verify_bin(cmd);
+        }
         let mut analyzer: Box<dyn Analysis> = match ItemType::from_str(cmd.get_one::<String>("type").expect(RCH)) {
             Ok(ItemType::ApplesoftText) => Box::new(lang::applesoft::diagnostics::Analyzer::new()),
             Ok(ItemType::IntegerText) => Box::new(lang::integer::diagnostics::Analyzer::new()),
@@ -142,6 +341,12 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
         }
     }
 
+    // Lint a Merlin project
+
+    if let Some(cmd) = matches.subcommand_matches("lint") {
+        return commands::lint::lint(cmd);
+    }
+
     // Minify
 
     if let Some(cmd) = matches.subcommand_matches("minify") {
@@ -174,6 +379,10 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
                     minifier.set_flags(u64::from_str_radix(cmd.get_one::<String>("flags").unwrap(),10)?);
                 }
                 let object = minifier.minify(&program)?;
+                if minifier.get_flags() & (applesoft::minifier::FLAG_COMBINE_LINES | applesoft::minifier::FLAG_RENAME_VARS) > 0 {
+                    let [before,after] = applesoft::minifier::Minifier::byte_savings(&program,&object)?;
+                    eprintln!("combined {} lines, {} bytes reduced to {} bytes",minifier.combined_lines(),before,after);
+                }
                 println!("{}",&object);
                 Ok(())
             },
@@ -206,10 +415,14 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
             log::error!("renumber did not receive any data from previous node");
             return Err(Box::new(CommandError::InvalidCommand));
         }
+        let numbered = match lang::labels::has_labels(&program) {
+            true => lang::labels::resolve(&program)?.0,
+            false => program.clone()
+        };
         return match typ
         {
             Ok(ItemType::ApplesoftText) => {
-                lang::verify_str(tree_sitter_applesoft::language(),&program)?;
+                lang::verify_str(tree_sitter_applesoft::language(),&numbered)?;
                 let mut renumberer = applesoft::renumber::Renumberer::new();
                 renumberer.set_flags(match reorder {true => 1, false => 0});
                 let new_prog = renumberer.renumber(&program,beg,end,first,step)?;
@@ -217,7 +430,7 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
                 Ok(())
             },
             Ok(ItemType::IntegerText) => {
-                lang::verify_str(tree_sitter_integerbasic::language(), &program)?;
+                lang::verify_str(tree_sitter_integerbasic::language(), &numbered)?;
                 let mut renumberer = integer::renumber::Renumberer::new();
                 renumberer.set_flags(match reorder {true => 1, false => 0});
                 let new_prog = renumberer.renumber(&program,beg,end,first,step)?;
@@ -229,6 +442,12 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
         };
     }
     
+    // Convert between BASIC dialects
+
+    if let Some(cmd) = matches.subcommand_matches("convert-lang") {
+        return commands::convert_lang::convert_lang(cmd);
+    }
+
     // Tokenize BASIC or Encode Merlin
 
     if let Some(cmd) = matches.subcommand_matches("tokenize") {
@@ -250,6 +469,9 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
             log::error!("tokenize did not receive any data from previous node");
             return Err(Box::new(CommandError::InvalidCommand));
         }
+        if !matches!(typ,Ok(ItemType::MerlinText)) && lang::labels::has_labels(&program) {
+            program = lang::labels::resolve(&program)?.0;
+        }
         return match typ
         {
             Ok(ItemType::ApplesoftText) => {
@@ -260,7 +482,12 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
                 }
                 if let Ok(addr) = u16::from_str_radix(addr_opt.expect(RCH),10) {
                     let mut tokenizer = applesoft::tokenizer::Tokenizer::new();
-                    let object = tokenizer.tokenize(&program,addr)?;
+                    if let Some(path) = cmd.get_one::<String>("dialect") {
+                        let mut config = applesoft::settings::Settings::new();
+                        config.dialect = applesoft::dialect::Dialect::from_json(&std::fs::read_to_string(path)?)?;
+                        tokenizer.set_config(config);
+                    }
+                    let object = tokenizer.tokenize_numbered(&program,addr)?;
                     if atty::is(atty::Stream::Stdout) {
                         a2kit::display_block(addr as usize,&object);
                     } else {
@@ -277,7 +504,7 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
                     return Err(Box::new(CommandError::InvalidCommand));
                 }
                 let mut tokenizer = integer::tokenizer::Tokenizer::new();
-                let object = tokenizer.tokenize(String::from(&program))?;
+                let object = tokenizer.tokenize_numbered(String::from(&program))?;
                 if atty::is(atty::Stream::Stdout) {
                     a2kit::display_block(0,&object);
                 } else {
@@ -304,6 +531,60 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
         };
     }
 
+    // Format Merlin source
+
+    if let Some(cmd) = matches.subcommand_matches("format") {
+        if atty::is(atty::Stream::Stdin) {
+            log::error!("line entry is not supported for `format`, please pipe something in");
+            return Err(Box::new(CommandError::InvalidCommand));
+        }
+        let typ = ItemType::from_str(cmd.get_one::<String>("type").expect(RCH));
+        let widths: Vec<usize> = match cmd.get_one::<String>("columns").expect(RCH).split(',').map(|w| usize::from_str(w.trim())).collect() {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("column widths should be three numbers separated by commas");
+                return Err(Box::new(e));
+            }
+        };
+        if widths.len()!=3 {
+            log::error!("column widths should be three numbers separated by commas");
+            return Err(Box::new(CommandError::InvalidCommand));
+        }
+        let mut program = String::new();
+        match std::io::stdin().read_to_string(&mut program) {
+            Ok(_) => {},
+            Err(e) => {
+                log::error!("the file to format could not be interpreted as a string");
+                return Err(Box::new(e));
+            }
+        }
+        if program.len()==0 {
+            log::error!("format did not receive any data from previous node");
+            return Err(Box::new(CommandError::InvalidCommand));
+        }
+        return match typ
+        {
+            Ok(ItemType::MerlinText) => {
+                lang::verify_str(tree_sitter_merlin6502::language(),&program)?;
+                let mut tokenizer = merlin::tokenizer::Tokenizer::new();
+                tokenizer.set_style(match cmd.get_one::<String>("style").expect(RCH).as_str() {
+                    "tabs" => merlin::formatter::ColumnStyle::Tabs,
+                    "paste" => merlin::formatter::ColumnStyle::Pasteable,
+                    _ => merlin::formatter::ColumnStyle::Variable
+                });
+                tokenizer.set_widths([widths[0],widths[1],widths[2]]);
+                tokenizer.set_op_case(match cmd.get_one::<String>("case").map(|s| s.as_str()) {
+                    Some("upper") => Some(merlin::formatter::OpCase::Upper),
+                    Some("lower") => Some(merlin::formatter::OpCase::Lower),
+                    _ => None
+                });
+                print!("{}",merlin::formatter::format_program(program,&mut tokenizer)?);
+                Ok(())
+            },
+            _ => Err(Box::new(CommandError::UnsupportedItemType))
+        };
+    }
+
     // Detokenize BASIC or decode Merlin
 
     if let Some(cmd) = matches.subcommand_matches("detokenize") {
@@ -318,34 +599,95 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
             log::error!("detokenize did not receive any data from previous node");
             return Err(Box::new(CommandError::InvalidCommand));
         }
-        return match typ
+        let (program,mut highlighter): (String,Box<dyn lang::server::Tokens>) = match typ
         {
             Ok(ItemType::ApplesoftTokens) => {
-                let tokenizer = applesoft::tokenizer::Tokenizer::new();
-                let program = tokenizer.detokenize(&tok)?;
-                for line in program.lines() {
-                    println!("{}",line);
+                let mut tokenizer = applesoft::tokenizer::Tokenizer::new();
+                if let Some(path) = cmd.get_one::<String>("dialect") {
+                    let mut config = applesoft::settings::Settings::new();
+                    config.dialect = applesoft::dialect::Dialect::from_json(&std::fs::read_to_string(path)?)?;
+                    tokenizer.set_config(config);
                 }
-                Ok(())
+                let mut program = tokenizer.detokenize(&tok)?;
+                if cmd.get_flag("labels") {
+                    program = lang::labels::label_targets(&mut applesoft::renumber::Renumberer::new(),&program)?;
+                }
+                (program,Box::new(applesoft::semantic_tokens::SemanticTokensProvider::new()))
             },
             Ok(ItemType::IntegerTokens) => {
                 let tokenizer = integer::tokenizer::Tokenizer::new();
-                let program = tokenizer.detokenize(&tok)?;
-                for line in program.lines() {
-                    println!("{}",line);
+                let mut program = tokenizer.detokenize(&tok)?;
+                if cmd.get_flag("labels") {
+                    program = lang::labels::label_targets(&mut integer::renumber::Renumberer::new(),&program)?;
                 }
-                Ok(())
+                (program,Box::new(integer::semantic_tokens::SemanticTokensProvider::new()))
             },
             Ok(ItemType::MerlinTokens) => {
                 let tokenizer = merlin::tokenizer::Tokenizer::new();
-                let program = tokenizer.detokenize(&tok)?;
-                for line in program.lines() {
-                    println!("{}",line);
-                }
-                Ok(())
+                (tokenizer.detokenize(&tok)?,Box::new(merlin::semantic_tokens::SemanticTokensProvider::new()))
             },
-            _ => Err(Box::new(CommandError::UnsupportedItemType))
+            _ => return Err(Box::new(CommandError::UnsupportedItemType))
         };
+        if let Some(style) = cmd.get_one::<String>("highlight") {
+            let style = lang::highlight::Style::from_str(style)?;
+            print!("{}",lang::highlight::highlight_with(highlighter.as_mut(),&program,style)?);
+            return Ok(());
+        }
+        for line in program.lines() {
+            println!("{}",line);
+        }
+        return Ok(());
+    }
+
+    // Size statistics for tokenized programs
+
+    if let Some(cmd) = matches.subcommand_matches("analyze") {
+        if atty::is(atty::Stream::Stdin) {
+            log::error!("line entry is not supported for `analyze`, please pipe something in");
+            return Err(Box::new(CommandError::InvalidCommand));
+        }
+        let mut tok: Vec<u8> = Vec::new();
+        std::io::stdin().read_to_end(&mut tok).expect("could not read input stream");
+        let addr = *cmd.get_one::<u16>("addr").expect(RCH) as usize;
+        let himem = *cmd.get_one::<u16>("himem").expect(RCH) as usize;
+        let stats = applesoft::statistics::analyze(&tok,addr,himem)?;
+        println!("{}",stats.to_json(*cmd.get_one::<usize>("top").expect(RCH),cmd.get_one::<u16>("indent").copied()));
+        return Ok(());
+    }
+
+    // Cassette audio
+
+    if let Some(cmd) = matches.subcommand_matches("to-wav") {
+        if atty::is(atty::Stream::Stdin) || atty::is(atty::Stream::Stdout) {
+            log::error!("`to-wav` needs piped input and output");
+            return Err(Box::new(CommandError::InvalidCommand));
+        }
+        let typ = ItemType::from_str(cmd.get_one::<String>("type").expect(RCH))?;
+        let mut dat: Vec<u8> = Vec::new();
+        std::io::stdin().read_to_end(&mut dat).expect("could not read input stream");
+        let mut encoder = a2kit::cassette::Encoder::new();
+        encoder.leader = *cmd.get_one::<f64>("leader").expect(RCH);
+        encoder.sample_rate = *cmd.get_one::<u32>("rate").expect(RCH);
+        let wav = encoder.to_wav(typ,&dat)?;
+        std::io::stdout().write_all(&wav).expect("could not write output stream");
+        return Ok(());
+    }
+
+    if let Some(cmd) = matches.subcommand_matches("from-wav") {
+        if atty::is(atty::Stream::Stdin) {
+            log::error!("line entry is not supported for `from-wav`, please pipe something in");
+            return Err(Box::new(CommandError::InvalidCommand));
+        }
+        let typ = ItemType::from_str(cmd.get_one::<String>("type").expect(RCH))?;
+        let mut wav: Vec<u8> = Vec::new();
+        std::io::stdin().read_to_end(&mut wav).expect("could not read input stream");
+        let dat = a2kit::cassette::from_wav(typ,&wav)?;
+        if atty::is(atty::Stream::Stdout) {
+            a2kit::display_block(0,&dat);
+        } else {
+            std::io::stdout().write_all(&dat).expect("could not write output stream");
+        }
+        return Ok(());
     }
 
     // Assemble source code
@@ -359,6 +701,29 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
             "m32" => merlin::MerlinVersion::Merlin32,
             _ => panic!("{}",RCH)
         };
+        config.extensions.sweet16 = cmd.get_flag("sweet16");
+        config.extensions.undocumented = cmd.get_flag("undocumented");
+        if let Some(paths) = cmd.get_many::<String>("link") {
+            let org = match u16::from_str(cmd.get_one::<String>("org").expect(RCH)) {
+                Ok(x) => x as usize,
+                Err(_) => {
+                    log::error!("origin did not parse as decimal unsigned 16 bit integer");
+                    return Err(Box::new(CommandError::OutOfRange))
+                }
+            };
+            let mut modules = Vec::new();
+            for path in paths {
+                let dat = std::fs::read(path)?;
+                modules.push(merlin::assembly::rel::RelModule::from_bytes(&dat,None)?);
+            }
+            let object = merlin::assembly::rel::link(&modules,org)?;
+            if atty::is(atty::Stream::Stdout) {
+                a2kit::display_block(org,&object);
+            } else {
+                std::io::stdout().write_all(&object).expect("could not write output stream");
+            }
+            return Ok(());
+        }
         let mut analyzer = lang::merlin::diagnostics::Analyzer::new();
         analyzer.set_config(config.clone());
         // if cmd.value_source("config").unwrap()==ValueSource::CommandLine {
@@ -386,7 +751,10 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
             } else {
                 asm.use_shared_symbols(std::sync::Arc::new(symbols));
             }
-            let object = asm.spot_assemble(doc.text.clone(), 0, doc.text.len() as isize, None)?;
+            let object = match cmd.get_flag("literals") {
+                true => asm.spot_assemble(doc.text.clone(), 0, doc.text.len() as isize, None)?,
+                false => asm.assemble(&doc.text, None)?
+            };
             if atty::is(atty::Stream::Stdout) {
                 a2kit::display_block(0,&object);
             } else {
@@ -399,6 +767,12 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
         }
     }
 
+    // Inspect GS/OS load file
+
+    if let Some(cmd) = matches.subcommand_matches("omf") {
+        return commands::omf::omf(cmd);
+    }
+
     // Disassemble binary to Merlin source
 
     if let Some(cmd) = matches.subcommand_matches("dasm") {
@@ -434,8 +808,16 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
             log::error!("dasm did not receive any data from previous node");
             return Err(Box::new(CommandError::InvalidCommand));
         }
+        let mut config = merlin::settings::Settings::new();
+        config.extensions.sweet16 = cmd.get_flag("sweet16");
+        config.extensions.undocumented = cmd.get_flag("undocumented");
         let mut dasm = merlin::disassembly::Disassembler::new();
+        dasm.set_config(config);
         dasm.set_mx(m8bit,x8bit);
+        dasm.set_syntax(merlin::disassembly::Syntax::from_str(cmd.get_one::<String>("syntax").expect(RCH)).expect(RCH));
+        if let Some(sets) = cmd.get_one::<String>("symbols") {
+            dasm.use_symbol_database(merlin::disassembly::symbols::SymbolDatabase::from_sets(sets)?);
+        }
         let rng =  merlin::disassembly::DasmRange::Range([org as usize,tok.len()]);
         let program = dasm.disassemble(&tok, rng, proc, "some")?;
         for line in program.lines() {
@@ -444,13 +826,59 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
         return Ok(());
     }
 
+    // Serve disk images to an Apple II
+    if let Some(cmd) = matches.subcommand_matches("serve") {
+        let mut paths = vec![cmd.get_one::<String>("dimg").expect(RCH).to_string()];
+        if let Some(path) = cmd.get_one::<String>("drive2") {
+            paths.push(path.to_string());
+        }
+        let mut server = commands::serve::BlockServer::new_with(&paths,&load_opts)?;
+        server.set_save_options(save_opts);
+        return match cmd.get_one::<String>("serial") {
+            Some(device) => commands::serve::serve_serial(&mut server, device),
+            None => commands::serve::serve_tcp(&mut server, cmd.get_one::<String>("tcp").expect(RCH))
+        };
+    }
+
+    // Serve disk images over HTTP
+    if let Some(cmd) = matches.subcommand_matches("serve-http") {
+        let root = std::path::Path::new(cmd.get_one::<String>("root").expect(RCH));
+        let mut server = commands::http::HttpServer::new(root)?;
+        server.set_load_options(load_opts);
+        server.set_save_options(save_opts);
+        let listener = std::net::TcpListener::bind(cmd.get_one::<String>("addr").expect(RCH))?;
+        eprintln!("serving {} on http://{}",root.display(),listener.local_addr()?);
+        return server.serve(listener);
+    }
+
+    // Long running JSON-RPC mode
+    if let Some(_cmd) = matches.subcommand_matches("rpc") {
+        return commands::rpc::run(&load_opts,&save_opts);
+    }
+
+    // Build a disk image from a manifest
+    if let Some(cmd) = matches.subcommand_matches("build") {
+        let path = std::path::Path::new(cmd.get_one::<String>("manifest").expect(RCH));
+        let mut builder = commands::build::Builder::new(commands::build::Manifest::from_file(path)?);
+        builder.set_load_options(load_opts);
+        builder.set_save_options(save_opts);
+        return builder.build();
+    }
+
+    // Build and rebuild a disk image from a manifest
+    if let Some(cmd) = matches.subcommand_matches("watch") {
+        let path = std::path::Path::new(cmd.get_one::<String>("manifest").expect(RCH));
+        let interval = std::time::Duration::from_millis(*cmd.get_one::<u64>("interval").expect(RCH));
+        return commands::build::watch(path, interval, &load_opts, &save_opts);
+    }
+
     // Create directory inside disk image
     if let Some(cmd) = matches.subcommand_matches("mkdir") {
         let path_to_img = cmd.get_one::<String>("dimg").expect(RCH);
         let path_in_img = cmd.get_one::<String>("file").expect(RCH);
-        let mut disk = a2kit::create_fs_from_file(&path_to_img)?;
+        let mut disk = a2kit::create_fs_with(&path_to_img,&load_opts)?;
         disk.create(&path_in_img)?;
-        return a2kit::save_img(&mut disk,&path_to_img);
+        return a2kit::save_img_with(&mut disk,&path_to_img,&save_opts);
     }
 
     // Update password for a file
@@ -461,45 +889,65 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
         let read = cmd.get_flag("read");
         let write = cmd.get_flag("write");
         let delete = cmd.get_flag("delete");
-        let mut disk = a2kit::create_fs_from_file(&path_to_img)?;
+        let mut disk = a2kit::create_fs_with(&path_to_img,&load_opts)?;
         disk.protect(path_in_img,password,read,write,delete)?;
-        return a2kit::save_img(&mut disk,path_to_img);
+        return a2kit::save_img_with(&mut disk,path_to_img,&save_opts);
     }
 
     // Remove password from a file
     if let Some(cmd) = matches.subcommand_matches("unprotect") {
         let path_to_img = cmd.get_one::<String>("dimg").expect(RCH);
         let path_in_img = cmd.get_one::<String>("file").expect(RCH);
-        let mut disk = a2kit::create_fs_from_file(&path_to_img)?;
+        let mut disk = a2kit::create_fs_with(&path_to_img,&load_opts)?;
         disk.unprotect(path_in_img)?;
-        return a2kit::save_img(&mut disk,path_to_img);
+        return a2kit::save_img_with(&mut disk,path_to_img,&save_opts);
     }
     
     // Delete a file or directory
     if let Some(cmd) = matches.subcommand_matches("delete") {
         let path_to_img = cmd.get_one::<String>("dimg").expect(RCH);
         let path_in_img = cmd.get_one::<String>("file").expect(RCH);
-        let mut disk = a2kit::create_fs_from_file(&path_to_img)?;
+        let mut disk = a2kit::create_fs_with(&path_to_img,&load_opts)?;
         disk.delete(&path_in_img)?;
-        return a2kit::save_img(&mut disk,&path_to_img);
+        return a2kit::save_img_with(&mut disk,&path_to_img,&save_opts);
+    }
+
+    // Append to a file
+    if let Some(cmd) = matches.subcommand_matches("append") {
+        return commands::put::append(cmd,&save_opts);
+    }
+
+    // Patch bytes within a file
+    if let Some(cmd) = matches.subcommand_matches("patch") {
+        return commands::put::patch(cmd,&save_opts);
+    }
+
+    // Truncate a file
+    if let Some(cmd) = matches.subcommand_matches("truncate") {
+        let path_to_img = cmd.get_one::<String>("dimg").expect(RCH);
+        let path_in_img = cmd.get_one::<String>("file").expect(RCH);
+        let len = *cmd.get_one::<usize>("len").expect(RCH);
+        let mut disk = a2kit::create_fs_with(&path_to_img,&load_opts)?;
+        disk.truncate(&path_in_img,len)?;
+        return a2kit::save_img_with(&mut disk,&path_to_img,&save_opts);
     }
 
     // Lock a file or directory
     if let Some(cmd) = matches.subcommand_matches("lock") {
         let path_to_img = cmd.get_one::<String>("dimg").expect(RCH);
         let path_in_img = cmd.get_one::<String>("file").expect(RCH);
-        let mut disk = a2kit::create_fs_from_file(&path_to_img)?;
+        let mut disk = a2kit::create_fs_with(&path_to_img,&load_opts)?;
         disk.lock(&path_in_img)?;
-        return a2kit::save_img(&mut disk,&path_to_img);
+        return a2kit::save_img_with(&mut disk,&path_to_img,&save_opts);
     }
 
     // Unlock a file or directory
     if let Some(cmd) = matches.subcommand_matches("unlock") {
         let path_to_img = cmd.get_one::<String>("dimg").expect(RCH);
         let path_in_img = cmd.get_one::<String>("file").expect(RCH);
-        let mut disk = a2kit::create_fs_from_file(&path_to_img)?;
+        let mut disk = a2kit::create_fs_with(&path_to_img,&load_opts)?;
         disk.unlock(&path_in_img)?;
-        return a2kit::save_img(&mut disk,&path_to_img);
+        return a2kit::save_img_with(&mut disk,&path_to_img,&save_opts);
     }
 
     // Rename a file or directory
@@ -507,9 +955,14 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
         let path_to_img = cmd.get_one::<String>("dimg").expect(RCH);
         let name = cmd.get_one::<String>("name").expect(RCH);
         let path_in_img = cmd.get_one::<String>("file").expect(RCH);
-        let mut disk = a2kit::create_fs_from_file(&path_to_img)?;
-        disk.rename(&path_in_img,&name)?;
-        return a2kit::save_img(&mut disk,&path_to_img);
+        let mut disk = a2kit::create_fs_with(&path_to_img,&load_opts)?;
+        if path_in_img.contains(['*','?']) {
+            let count = disk.rename_wildcard(path_in_img,name)?;
+            log::info!("renamed {} files",count);
+        } else {
+            disk.rename(path_in_img,name)?;
+        }
+        return a2kit::save_img_with(&mut disk,&path_to_img,&save_opts);
     }
 
     // Retype a file
@@ -518,14 +971,76 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
         let path_in_img = cmd.get_one::<String>("file").expect(RCH);
         let typ = cmd.get_one::<String>("type").expect(RCH);
         let aux = cmd.get_one::<String>("aux").expect(RCH);
-        let mut disk = a2kit::create_fs_from_file(&path_to_img)?;
-        disk.retype(&path_in_img,&typ,&aux)?;
-        return a2kit::save_img(&mut disk,&path_to_img);
+        let aux = match aux.strip_prefix("0x").or(aux.strip_prefix('$')) {
+            Some(hex) => u32::from_str_radix(hex,16)?.to_string(),
+            None => aux.to_string()
+        };
+        let mut disk = a2kit::create_fs_with(&path_to_img,&load_opts)?;
+        let paths = match path_in_img.contains(['*','?','[']) {
+            true => disk.glob(path_in_img,false)?,
+            false => vec![path_in_img.to_string()]
+        };
+        if paths.is_empty() {
+            log::warn!("no files match {}",path_in_img);
+        }
+        if cmd.get_flag("dry-run") {
+            for path in paths {
+                println!("{} -> {} {}",path,typ,aux);
+            }
+            return Ok(());
+        }
+        for path in &paths {
+            disk.retype(path,&typ,&aux)?;
+        }
+        log::info!("retyped {} files",paths.len());
+        return a2kit::save_img_with(&mut disk,&path_to_img,&save_opts);
+    }
+
+    // Relabel a volume
+    if let Some(cmd) = matches.subcommand_matches("relabel") {
+        let path_to_img = cmd.get_one::<String>("dimg").expect(RCH);
+        let mut disk = a2kit::create_fs_with(&path_to_img,&load_opts)?;
+        if let Some(label) = cmd.get_one::<String>("label") {
+            disk.relabel(label)?;
+        }
+        if let Some(serial) = cmd.get_one::<String>("serial") {
+            disk.set_serial(serial)?;
+        }
+        return a2kit::save_img_with(&mut disk,&path_to_img,&save_opts);
+    }
+
+    // Reclaim DOS tracks
+    if let Some(cmd) = matches.subcommand_matches("reclaim-dos") {
+        let path_to_img = cmd.get_one::<String>("dimg").expect(RCH);
+        let mut disk = a2kit::create_fs_with(&path_to_img,&load_opts)?;
+        let count = disk.reclaim_dos()?;
+        eprintln!("freed {} sectors",count);
+        return a2kit::save_img_with(&mut disk,&path_to_img,&save_opts);
+    }
+
+    // Check the bitmap against the directory tree
+    if let Some(cmd) = matches.subcommand_matches("fsck") {
+        let path_to_img = cmd.get_one::<String>("dimg").expect(RCH);
+        let mut disk = a2kit::create_fs_with(&path_to_img,&load_opts)?;
+        let diffs = disk.check_bitmap()?;
+        for (block,is_used) in &diffs {
+            match is_used {
+                true => println!("{} is used but marked free",block),
+                false => println!("{} is marked used but not used",block)
+            }
+        }
+        println!("{} differences",diffs.len());
+        if cmd.get_flag("repair") {
+            let count = disk.repair_bitmap()?;
+            println!("repaired {} differences, {} remain",count,disk.check_bitmap()?.len());
+            return a2kit::save_img_with(&mut disk,&path_to_img,&save_opts);
+        }
+        return Ok(());
     }
 
     // Put file inside disk image, or save to local
     if let Some(cmd) = matches.subcommand_matches("put") {
-        return commands::put::put(cmd);
+        return commands::put::put(cmd,&save_opts);
     }
 
     // Get file from local or from inside a disk image
@@ -535,7 +1050,7 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
     
     // Put JSON list of file images inside a disk image
     if let Some(cmd) = matches.subcommand_matches("mput") {
-        return commands::put::mput(cmd);
+        return commands::put::mput(cmd,&save_opts);
     }
 
     // Get JSON list of file images from inside a disk image
@@ -563,3 +1078,10 @@ fn main() -> Result<(),Box<dyn std::error::Error>>
 
 }
 
+/// Print the sector cache statistics of an image that has a cache
+fn report_cache_stats(img: &dyn a2kit::img::DiskImage) {
+    if let Some(stats) = img.cache_stats() {
+        eprintln!("sector cache: {} hits, {} misses, {} evictions, {} invalidations",
+            stats.hits,stats.misses,stats.evictions,stats.invalidations);
+    }
+}
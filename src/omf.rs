@@ -0,0 +1,508 @@
+//! ### GS/OS load files
+//!
+//! Apple IIgs executables (S16, TOOL, CDA, and the like) are in the Object Module Format (OMF).
+//! A load file is a sequence of segments, each of which is a header followed by a body of records.
+//! The body lays down the segment's memory image with `LCONST` and `DS` records, and then lists the
+//! fields that need to be patched when the segment is loaded, using `RELOC`, `INTERSEG`, their compressed
+//! forms `cRELOC` and `cINTERSEG`, and `SUPER` records, which pack many patches of one kind together.
+//!
+//! * OMF versions 1 and 2 are supported, version 1 kinds are converted to the version 2 form
+//! * records that only appear in object files are rejected
+//! * inter-segment references are resolved within the file, references to other files are left alone
+//!
+//! Relocation emulates the loader by laying the segments out in memory one after another, so that a
+//! segment can be written as a flat binary ready for the 65816 disassembler.
+//! Segments and layouts are confined to the 16 MB address space of the 65816.
+
+use std::collections::HashMap;
+use crate::DYNERR;
+
+/// size of the 65816 address space, no segment can be longer
+const MAX_LENGTH: usize = 1 << 24;
+
+#[derive(thiserror::Error,Debug)]
+pub enum Error {
+    #[error("unexpected end of data")]
+    EndOfData,
+    #[error("not a supported load file")]
+    FileTypeMismatch,
+    #[error("out of range")]
+    Range
+}
+
+/// offset of the fields in the segment header
+mod hdr {
+    pub const BYTECNT: usize = 0x00;
+    pub const RESSPC: usize = 0x04;
+    pub const LENGTH: usize = 0x08;
+    pub const KIND_V1: usize = 0x0c;
+    pub const LABLEN: usize = 0x0d;
+    pub const NUMLEN: usize = 0x0e;
+    pub const VERSION: usize = 0x0f;
+    pub const BANKSIZE: usize = 0x10;
+    pub const KIND: usize = 0x14;
+    pub const ORG: usize = 0x18;
+    pub const ALIGN: usize = 0x1c;
+    pub const NUMSEX: usize = 0x20;
+    pub const SEGNUM: usize = 0x22;
+    pub const ENTRY: usize = 0x24;
+    pub const DISPNAME: usize = 0x28;
+    pub const DISPDATA: usize = 0x2a;
+    /// smallest header that has all the fields above
+    pub const MIN_LEN: usize = 0x2c;
+    /// length of the load name
+    pub const LOADNAME_LEN: usize = 10;
+}
+
+/// segment kinds, the low 5 bits are the type and the high byte holds the attributes
+pub mod kind {
+    pub const CODE: u16 = 0x00;
+    pub const DATA: u16 = 0x01;
+    pub const JUMP_TABLE: u16 = 0x02;
+    pub const PATHNAME: u16 = 0x04;
+    pub const LIBRARY_DICTIONARY: u16 = 0x08;
+    pub const INIT: u16 = 0x10;
+    pub const DIRECT_PAGE: u16 = 0x12;
+    pub const TYPE_MASK: u16 = 0x1f;
+    pub const BANK_RELATIVE: u16 = 0x0100;
+    pub const SKIP: u16 = 0x0200;
+    pub const RELOAD: u16 = 0x0400;
+    pub const ABSOLUTE_BANK: u16 = 0x0800;
+    pub const NO_SPECIAL_MEMORY: u16 = 0x1000;
+    pub const POSITION_INDEPENDENT: u16 = 0x2000;
+    pub const PRIVATE: u16 = 0x4000;
+    pub const DYNAMIC: u16 = 0x8000;
+}
+
+/// record opcodes that can appear in a load file, 0x01 through 0xdf are `CONST`
+mod op {
+    pub const END: u8 = 0x00;
+    pub const CONST_MAX: u8 = 0xdf;
+    pub const RELOC: u8 = 0xe2;
+    pub const INTERSEG: u8 = 0xe3;
+    pub const DS: u8 = 0xf1;
+    pub const LCONST: u8 = 0xf2;
+    pub const CRELOC: u8 = 0xf5;
+    pub const CINTERSEG: u8 = 0xf6;
+    pub const SUPER: u8 = 0xf7;
+}
+
+const ATTRIBUTES: [(u16,&str);8] = [
+    (kind::BANK_RELATIVE,"bank-relative"),
+    (kind::SKIP,"skip"),
+    (kind::RELOAD,"reload"),
+    (kind::ABSOLUTE_BANK,"absolute-bank"),
+    (kind::NO_SPECIAL_MEMORY,"no-special-memory"),
+    (kind::POSITION_INDEPENDENT,"position-independent"),
+    (kind::PRIVATE,"private"),
+    (kind::DYNAMIC,"dynamic")
+];
+
+/// A field that is patched with an address when the segment is loaded
+#[derive(Clone,Debug,PartialEq)]
+pub struct Reloc {
+    /// number of bytes to patch, 1 to 4
+    pub size: u8,
+    /// the address is shifted left by this amount before patching, negative shifts right
+    pub shift: i8,
+    /// offset of the field within the segment
+    pub offset: u32,
+    /// offset of the referenced address within the target segment
+    pub value: u32,
+    /// file and segment number of the target, `None` if the target is this segment
+    pub target: Option<(u16,u16)>
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Segment {
+    pub version: u8,
+    pub segnum: u16,
+    /// version 2 form, even if the file is version 1
+    pub kind: u16,
+    pub name: String,
+    pub load_name: String,
+    /// memory needed, including the reserved space
+    pub length: u32,
+    /// zeros that follow the body in memory
+    pub reserved: u32,
+    /// absolute load address, or 0 if the segment can go anywhere
+    pub org: u32,
+    pub align: u32,
+    pub banksize: u32,
+    pub entry: u32,
+    /// memory image at offset 0, before relocation
+    pub data: Vec<u8>,
+    pub relocs: Vec<Reloc>
+}
+
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct LoadFile {
+    pub segments: Vec<Segment>
+}
+
+/// Little endian number of `len` bytes at `ptr`, erring if it runs off the end.
+fn num(dat: &[u8],ptr: usize,len: usize) -> Result<u32,DYNERR> {
+    if ptr + len > dat.len() || len > 4 {
+        log::error!("OMF record at {} runs past the end of the segment",ptr);
+        return Err(Box::new(Error::EndOfData));
+    }
+    Ok(dat[ptr..ptr+len].iter().rev().fold(0,|acc,b| (acc << 8) | *b as u32))
+}
+
+/// Name at `ptr`, fixed length if `lablen` is nonzero, otherwise length prefixed.
+/// Returns the name and the number of bytes it occupied.
+fn name(dat: &[u8],ptr: usize,lablen: usize) -> Result<(String,usize),DYNERR> {
+    let (beg,len) = match lablen {
+        0 => (ptr + 1,num(dat,ptr,1)? as usize),
+        l => (ptr,l)
+    };
+    if beg + len > dat.len() {
+        log::error!("OMF name at {} runs past the end of the segment",ptr);
+        return Err(Box::new(Error::EndOfData));
+    }
+    let s = dat[beg..beg+len].iter().map(|b| (*b & 0x7f) as char).collect::<String>();
+    Ok((s.trim_end().to_string(),beg + len - ptr))
+}
+
+/// Expand the patch offsets in a `SUPER` record body, which is organized by 256 byte pages.
+/// A count byte with the high bit set skips pages, otherwise count + 1 offsets within the page follow.
+fn super_offsets(dat: &[u8]) -> Result<Vec<u32>,DYNERR> {
+    let mut ans = Vec::new();
+    let mut page = 0;
+    let mut ptr = 0;
+    while ptr < dat.len() {
+        let count = dat[ptr];
+        ptr += 1;
+        if count & 0x80 > 0 {
+            page += (count & 0x7f) as u32;
+            continue;
+        }
+        for _i in 0..=count {
+            ans.push(page * 256 + num(dat,ptr,1)?);
+            ptr += 1;
+        }
+        page += 1;
+    }
+    Ok(ans)
+}
+
+impl Segment {
+    /// Parse the segment at the start of `dat`, returning it along with its length in the file.
+    pub fn from_bytes(dat: &[u8]) -> Result<(Self,usize),DYNERR> {
+        if dat.len() < hdr::MIN_LEN {
+            log::error!("OMF segment header is truncated");
+            return Err(Box::new(Error::EndOfData));
+        }
+        let version = dat[hdr::VERSION];
+        let (bytecnt,kind) = match version {
+            1 => {
+                let k = dat[hdr::KIND_V1] as u16;
+                (num(dat,hdr::BYTECNT,4)? as usize * 512,(k & kind::TYPE_MASK) | ((k & 0xe0) << 8))
+            },
+            2 => (num(dat,hdr::BYTECNT,4)? as usize,num(dat,hdr::KIND,2)? as u16),
+            v => {
+                log::error!("OMF version {} is not supported",v);
+                return Err(Box::new(Error::FileTypeMismatch));
+            }
+        };
+        let numlen = dat[hdr::NUMLEN] as usize;
+        if numlen != 4 || dat[hdr::NUMSEX] != 0 {
+            log::error!("OMF numbers should be 4 bytes, little endian");
+            return Err(Box::new(Error::FileTypeMismatch));
+        }
+        if bytecnt < hdr::MIN_LEN || bytecnt > dat.len() {
+            log::error!("OMF segment length {} is out of range",bytecnt);
+            return Err(Box::new(Error::Range));
+        }
+        let dat = &dat[0..bytecnt];
+        let lablen = dat[hdr::LABLEN] as usize;
+        let dispname = num(dat,hdr::DISPNAME,2)? as usize;
+        let dispdata = num(dat,hdr::DISPDATA,2)? as usize;
+        let (load_name,_) = name(dat,dispname,hdr::LOADNAME_LEN)?;
+        let (seg_name,_) = name(dat,dispname + hdr::LOADNAME_LEN,lablen)?;
+        let mut ans = Self {
+            version,
+            segnum: num(dat,hdr::SEGNUM,2)? as u16,
+            kind,
+            name: seg_name,
+            load_name,
+            length: num(dat,hdr::LENGTH,4)?,
+            reserved: num(dat,hdr::RESSPC,4)?,
+            org: num(dat,hdr::ORG,4)?,
+            align: num(dat,hdr::ALIGN,4)?,
+            banksize: num(dat,hdr::BANKSIZE,4)?,
+            entry: num(dat,hdr::ENTRY,4)?,
+            data: Vec::new(),
+            relocs: Vec::new()
+        };
+        if ans.length as usize > MAX_LENGTH {
+            log::error!("segment {} claims {} bytes, more than 16 MB",ans.segnum,ans.length);
+            return Err(Box::new(Error::Range));
+        }
+        ans.parse_body(dat,dispdata)?;
+        if ans.data.len() > ans.length as usize {
+            log::warn!("segment {} has {} bytes, header says {}",ans.segnum,ans.data.len(),ans.length);
+        }
+        ans.data.resize(usize::max(ans.data.len(),ans.length as usize),0);
+        Ok((ans,bytecnt))
+    }
+    fn parse_body(&mut self,dat: &[u8],mut ptr: usize) -> Result<(),DYNERR> {
+        let mut supers: Vec<(u8,Vec<u32>)> = Vec::new();
+        loop {
+            let opcode = num(dat,ptr,1)? as u8;
+            ptr += 1;
+            match opcode {
+                op::END => break,
+                1..=op::CONST_MAX => {
+                    let len = opcode as usize;
+                    num(dat,ptr + len - 1,1)?;
+                    self.data.extend_from_slice(&dat[ptr..ptr+len]);
+                    ptr += len;
+                },
+                op::LCONST => {
+                    let len = num(dat,ptr,4)? as usize;
+                    ptr += 4;
+                    if ptr + len > dat.len() {
+                        log::error!("LCONST at {} runs past the end of the segment",ptr);
+                        return Err(Box::new(Error::EndOfData));
+                    }
+                    self.data.extend_from_slice(&dat[ptr..ptr+len]);
+                    ptr += len;
+                },
+                op::DS => {
+                    let len = num(dat,ptr,4)? as usize;
+                    ptr += 4;
+                    if self.data.len() + len > MAX_LENGTH {
+                        log::error!("DS at {} makes the segment longer than 16 MB",ptr);
+                        return Err(Box::new(Error::Range));
+                    }
+                    self.data.resize(self.data.len() + len,0);
+                },
+                op::RELOC => {
+                    self.relocs.push(Reloc {
+                        size: num(dat,ptr,1)? as u8,
+                        shift: num(dat,ptr+1,1)? as u8 as i8,
+                        offset: num(dat,ptr+2,4)?,
+                        value: num(dat,ptr+6,4)?,
+                        target: None
+                    });
+                    ptr += 10;
+                },
+                op::INTERSEG => {
+                    self.relocs.push(Reloc {
+                        size: num(dat,ptr,1)? as u8,
+                        shift: num(dat,ptr+1,1)? as u8 as i8,
+                        offset: num(dat,ptr+2,4)?,
+                        value: num(dat,ptr+10,4)?,
+                        target: Some((num(dat,ptr+6,2)? as u16,num(dat,ptr+8,2)? as u16))
+                    });
+                    ptr += 14;
+                },
+                op::CRELOC => {
+                    self.relocs.push(Reloc {
+                        size: num(dat,ptr,1)? as u8,
+                        shift: num(dat,ptr+1,1)? as u8 as i8,
+                        offset: num(dat,ptr+2,2)?,
+                        value: num(dat,ptr+4,2)?,
+                        target: None
+                    });
+                    ptr += 6;
+                },
+                op::CINTERSEG => {
+                    self.relocs.push(Reloc {
+                        size: num(dat,ptr,1)? as u8,
+                        shift: num(dat,ptr+1,1)? as u8 as i8,
+                        offset: num(dat,ptr+2,2)?,
+                        value: num(dat,ptr+5,2)?,
+                        target: Some((1,num(dat,ptr+4,1)? as u16))
+                    });
+                    ptr += 7;
+                },
+                op::SUPER => {
+                    let len = num(dat,ptr,4)? as usize;
+                    if len < 1 || ptr + 4 + len > dat.len() {
+                        log::error!("SUPER at {} runs past the end of the segment",ptr);
+                        return Err(Box::new(Error::EndOfData));
+                    }
+                    supers.push((dat[ptr+4],super_offsets(&dat[ptr+5..ptr+4+len])?));
+                    ptr += 4 + len;
+                },
+                _ => {
+                    log::error!("OMF record ${:02X} is not allowed in a load file",opcode);
+                    return Err(Box::new(Error::FileTypeMismatch));
+                }
+            }
+        }
+        // SUPER patches keep part of the reference in the data, so expand them once the data is in place
+        for (typ,offsets) in supers {
+            for offset in offsets {
+                let lo = num(&self.data,offset as usize,2)?;
+                let reloc = match typ {
+                    0 => Reloc { size: 2, shift: 0, offset, value: lo, target: None },
+                    1 => Reloc { size: 3, shift: 0, offset, value: num(&self.data,offset as usize,3)?, target: None },
+                    2..=13 => {
+                        let seg = num(&self.data,offset as usize + 2,1)? as u16;
+                        Reloc { size: 3, shift: 0, offset, value: lo, target: Some((typ as u16 - 1,seg)) }
+                    },
+                    14..=25 => Reloc { size: 2, shift: 0, offset, value: lo, target: Some((1,typ as u16 - 13)) },
+                    26..=37 => Reloc { size: 2, shift: -16, offset, value: lo, target: Some((1,typ as u16 - 25)) },
+                    _ => {
+                        log::error!("SUPER record type {} is unknown",typ);
+                        return Err(Box::new(Error::FileTypeMismatch));
+                    }
+                };
+                self.relocs.push(reloc);
+            }
+        }
+        Ok(())
+    }
+    /// Name of the segment type, e.g. `code` or `data`
+    pub fn type_name(&self) -> &'static str {
+        match self.kind & kind::TYPE_MASK {
+            kind::CODE => "code",
+            kind::DATA => "data",
+            kind::JUMP_TABLE => "jump-table",
+            kind::PATHNAME => "pathname",
+            kind::LIBRARY_DICTIONARY => "library-dictionary",
+            kind::INIT => "init",
+            kind::DIRECT_PAGE => "direct-page",
+            _ => "unknown"
+        }
+    }
+    /// Names of the attributes that are set in the kind
+    pub fn attributes(&self) -> Vec<&'static str> {
+        ATTRIBUTES.iter().filter(|(mask,_)| self.kind & mask > 0).map(|(_,s)| *s).collect()
+    }
+}
+
+impl LoadFile {
+    /// Parse a load file, there must be at least one segment
+    pub fn from_bytes(dat: &[u8]) -> Result<Self,DYNERR> {
+        let mut segments = Vec::new();
+        let mut ptr = 0;
+        // some linkers pad the file out to a block boundary
+        while ptr < dat.len() && dat[ptr..].iter().any(|b| *b != 0) {
+            let (seg,len) = Segment::from_bytes(&dat[ptr..])?;
+            segments.push(seg);
+            ptr += len;
+        }
+        if segments.is_empty() {
+            log::error!("OMF file has no segments");
+            return Err(Box::new(Error::EndOfData));
+        }
+        Ok(Self { segments })
+    }
+    pub fn segment(&self,segnum: u16) -> Option<&Segment> {
+        self.segments.iter().find(|s| s.segnum == segnum)
+    }
+    /// Assign a load address to every segment, starting with segment `first` at `org`.
+    /// The others follow in file order, except that a segment with a fixed origin goes there.
+    /// Alignment is respected, and a segment is moved to the next bank if it would cross one.
+    /// It is an error if the segments do not fit below 16 MB.
+    pub fn layout(&self,first: u16,org: u32) -> Result<HashMap<u16,u32>,DYNERR> {
+        let mut ans = HashMap::new();
+        let mut addr = org;
+        let round_up = |addr: u32,unit: u32| addr.div_ceil(unit).checked_mul(unit);
+        let order = self.segments.iter().filter(|s| s.segnum == first)
+            .chain(self.segments.iter().filter(|s| s.segnum != first));
+        for seg in order {
+            if seg.segnum != first {
+                if seg.org != 0 {
+                    ans.insert(seg.segnum,seg.org);
+                    continue;
+                }
+                if seg.align > 1 {
+                    addr = round_up(addr,seg.align).ok_or(Error::Range)?;
+                }
+                if seg.banksize > 0 && (addr % seg.banksize).checked_add(seg.length).ok_or(Error::Range)? > seg.banksize {
+                    addr = round_up(addr,seg.banksize).ok_or(Error::Range)?;
+                }
+            }
+            ans.insert(seg.segnum,addr);
+            addr = match addr.checked_add(seg.length) {
+                Some(end) if end as usize <= MAX_LENGTH => end,
+                _ => {
+                    log::error!("segment {} does not fit below 16 MB when loaded at {}",seg.segnum,addr);
+                    return Err(Box::new(Error::Range));
+                }
+            };
+        }
+        Ok(ans)
+    }
+    /// Memory image of segment `segnum` with every field patched, given the load addresses in `addrs`.
+    /// References to other files are left as they are, with a warning.
+    pub fn relocate(&self,segnum: u16,addrs: &HashMap<u16,u32>) -> Result<Vec<u8>,DYNERR> {
+        let seg = match self.segment(segnum) {
+            Some(s) => s,
+            None => {
+                log::error!("segment {} is not in the file",segnum);
+                return Err(Box::new(Error::Range));
+            }
+        };
+        let mut ans = seg.data.clone();
+        let mut foreign = 0;
+        for reloc in &seg.relocs {
+            let base = match reloc.target {
+                None => addrs.get(&segnum),
+                Some((1,target)) => addrs.get(&target),
+                Some(_) => {
+                    foreign += 1;
+                    continue;
+                }
+            };
+            let addr = match base {
+                Some(base) => base.wrapping_add(reloc.value),
+                None => {
+                    log::error!("segment {} refers to segment {:?}, which has no address",segnum,reloc.target);
+                    return Err(Box::new(Error::Range));
+                }
+            };
+            let patch = match reloc.shift {
+                s if s < 0 => addr.checked_shr(s.unsigned_abs() as u32).unwrap_or(0),
+                s => addr.checked_shl(s as u32).unwrap_or(0)
+            };
+            let beg = reloc.offset as usize;
+            let size = reloc.size as usize;
+            if size > 4 || beg + size > ans.len() {
+                log::error!("patch at {} is outside segment {}",beg,segnum);
+                return Err(Box::new(Error::Range));
+            }
+            ans[beg..beg+size].copy_from_slice(&u32::to_le_bytes(patch)[0..size]);
+        }
+        if foreign > 0 {
+            log::warn!("{} references to other files were not patched",foreign);
+        }
+        Ok(ans)
+    }
+    /// Segment listing as a JSON string
+    pub fn to_json(&self,indent: Option<u16>) -> String {
+        let mut list = json::JsonValue::new_array();
+        for seg in &self.segments {
+            let mut attributes = json::JsonValue::new_array();
+            for attr in seg.attributes() {
+                attributes.push(attr).expect("could not build JSON");
+            }
+            list.push(json::object! {
+                "segment": seg.segnum,
+                "name": seg.name.clone(),
+                "load_name": seg.load_name.clone(),
+                "version": seg.version,
+                "type": seg.type_name(),
+                "attributes": attributes,
+                "length": seg.length,
+                "reserved": seg.reserved,
+                "org": seg.org,
+                "align": seg.align,
+                "bank_size": seg.banksize,
+                "entry": seg.entry,
+                "relocations": seg.relocs.iter().filter(|r| r.target.is_none()).count(),
+                "interseg": seg.relocs.iter().filter(|r| r.target.is_some()).count()
+            }).expect("could not build JSON");
+        }
+        let ans = json::object! { "segments": list };
+        match indent {
+            Some(spaces) => json::stringify_pretty(ans, spaces),
+            None => json::stringify(ans)
+        }
+    }
+}
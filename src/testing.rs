@@ -0,0 +1,157 @@
+//! # Testing aids
+//!
+//! Functions to verify that converting a disk image to another type is lossless.
+//! Disks are compared block by block, using the blocks the file system covers, so that
+//! images of different types can be compared directly.  Offsets that are known to be
+//! ignorable, such as certain timestamps, are zeroed before comparison.
+//!
+//! The conversion itself is `img::convert::convert`.
+//!
+//! Example:
+//! ```rs
+//! let ans = a2kit::testing::verify_convert("disk.woz",a2kit::img::DiskImageType::DO)?;
+//! if ans.is_lossless() {
+//!     std::fs::write("disk.do",&ans.converted)?;
+//! }
+//! ```
+
+use std::collections::HashMap;
+use crate::fs::{Block,DiskFS};
+use crate::img::{DiskImage,DiskImageType};
+use crate::img::convert::convert;
+use crate::DYNERR;
+
+#[derive(thiserror::Error,Debug)]
+pub enum Error {
+    #[error("disks are not equivalent")]
+    NotEquivalent
+}
+
+/// A byte that differs between two disks
+#[derive(Debug,Clone,PartialEq)]
+pub struct Mismatch {
+    pub block: Block,
+    pub offset: usize,
+    pub expected: u8,
+    pub actual: u8
+}
+
+/// Outcome of comparing two disks
+#[derive(Debug,Clone,Default)]
+pub struct Report {
+    /// number of blocks compared
+    pub blocks: usize,
+    /// number of offsets that were ignored
+    pub ignored: usize,
+    pub mismatches: Vec<Mismatch>
+}
+
+/// Outcome of a round trip conversion
+pub struct Verification {
+    /// the converted image, ready to be saved
+    pub converted: Vec<u8>,
+    /// comparison of the original with the converted image
+    pub forward: Report,
+    /// comparison of the original with the image converted back to the original type
+    pub round_trip: Report
+}
+
+impl Report {
+    fn to_json_value(&self) -> json::JsonValue {
+        let mut mismatches = json::JsonValue::new_array();
+        for m in &self.mismatches {
+            mismatches.push(json::object! {
+                "block": format!("{:?}",m.block),
+                "offset": m.offset,
+                "expected": m.expected,
+                "actual": m.actual
+            }).expect("unreachable was reached");
+        }
+        json::object! {
+            "blocks": self.blocks,
+            "ignored": self.ignored,
+            "mismatches": mismatches
+        }
+    }
+}
+
+impl Verification {
+    pub fn is_lossless(&self) -> bool {
+        self.forward.mismatches.is_empty() && self.round_trip.mismatches.is_empty()
+    }
+    /// Both comparisons as a JSON string
+    pub fn to_json(&self, indent: Option<u16>) -> String {
+        let obj = json::object! {
+            "lossless": self.is_lossless(),
+            "forward": self.forward.to_json_value(),
+            "round_trip": self.round_trip.to_json_value()
+        };
+        match indent {
+            Some(spaces) => json::stringify_pretty(obj, spaces),
+            None => json::stringify(obj)
+        }
+    }
+}
+
+/// Offsets that can be ignored when comparing the disk with another disk.
+/// This calls `DiskFS::standardize` with the reference appropriate for the file system.
+pub fn ignorable_offsets(disk: &mut Box<dyn DiskFS>) -> Result<HashMap<Block,Vec<usize>>,DYNERR> {
+    let ref_con = match disk.stat()?.fs_name.as_str() {
+        "prodos" => 2,
+        _ => 0
+    };
+    Ok(disk.standardize(ref_con))
+}
+
+/// Compare every block of `expected` with the same block of `actual`, after zeroing the offsets in `ignore`.
+pub fn compare(expected: &mut Box<dyn DiskFS>, actual: &mut Box<dyn DiskFS>, ignore: &HashMap<Block,Vec<usize>>) -> Result<Report,DYNERR> {
+    compare_blocks(expected, actual, ignore)
+}
+
+fn compare_blocks(expected: &mut Box<dyn DiskFS>, actual: &mut Box<dyn DiskFS>, ignore: &HashMap<Block,Vec<usize>>) -> Result<Report,DYNERR> {
+    let mut ans = Report::default();
+    for block in expected.all_blocks()? {
+        let mut x = expected.get_img().read_block(block)?;
+        let mut y = actual.get_img().read_block(block)?;
+        if let Some(offsets) = ignore.get(&block) {
+            for offset in offsets {
+                x[*offset] = 0;
+                y[*offset] = 0;
+            }
+            ans.ignored += offsets.len();
+        }
+        if x.len() != y.len() {
+            log::error!("block {:?} has different sizes",block);
+            return Err(Box::new(Error::NotEquivalent));
+        }
+        for offset in 0..x.len() {
+            if x[offset] != y[offset] {
+                ans.mismatches.push(Mismatch { block, offset, expected: x[offset], actual: y[offset] });
+            }
+        }
+        ans.blocks += 1;
+    }
+    Ok(ans)
+}
+
+fn reload(img: &mut Box<dyn DiskImage>) -> Result<Box<dyn DiskFS>,DYNERR> {
+    let ext = img.file_extensions().first().cloned();
+    crate::create_fs_from_bytestream(&img.to_bytes(), ext.as_deref())
+}
+
+/// Convert the disk image at `path` to type `typ` and back, comparing both results with the original.
+pub fn verify_convert(path: &str, typ: DiskImageType) -> Result<Verification,DYNERR> {
+    let mut original = crate::create_fs_from_file(path)?;
+    let original_typ = original.get_img().what_am_i();
+    let ignore = ignorable_offsets(&mut original)?;
+    let mut converted_img = convert(&mut original, typ)?;
+    let mut converted = reload(&mut converted_img)?;
+    let forward = compare(&mut original, &mut converted, &ignore)?;
+    let mut restored = reload(&mut convert(&mut converted, original_typ)?)?;
+    let round_trip = compare(&mut original, &mut restored, &ignore)?;
+    Ok(Verification {
+        converted: converted_img.to_bytes(),
+        forward,
+        round_trip
+    })
+}
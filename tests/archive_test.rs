@@ -0,0 +1,81 @@
+// test of gzip and zip wrapped disk images
+use a2kit::img::archive;
+
+type STDRESULT = Result<(),Box<dyn std::error::Error>>;
+
+#[test]
+fn gzip_round_trip() -> STDRESULT {
+    let mut dat = Vec::new();
+    for i in 0..70000usize {
+        dat.push(match i % 1000 < 600 {
+            true => 0,
+            false => ((i * 7919) % 251) as u8
+        });
+    }
+    let compressed = archive::gzip(&dat, Some("disk.do"))?;
+    assert!(compressed.len() < dat.len() / 2);
+    assert_eq!(archive::gunzip(&compressed, 1 << 20)?,dat);
+    assert!(archive::gunzip(&compressed, 1000).is_err());
+    Ok(())
+}
+
+#[test]
+fn open_gzip() -> STDRESULT {
+    let mut plain = a2kit::create_img_from_file("tests/prodos-smallfiles.do")?;
+    let mut wrapped = a2kit::create_img_from_file("tests/prodos-smallfiles.do.gz")?;
+    assert_eq!(plain.to_bytes(),wrapped.to_bytes());
+    Ok(())
+}
+
+#[test]
+fn save_gzip() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("disk.do.gz").to_string_lossy().to_string();
+    std::fs::copy("tests/prodos-smallfiles.do.gz", &path)?;
+    let mut disk = a2kit::create_fs_from_file(&path)?;
+    disk.bsave("new", &[1,2,3], Some(768), None)?;
+    a2kit::save_img(&mut disk, &path)?;
+    let raw = std::fs::read(&path)?;
+    assert_eq!(raw[0..2],[0x1f,0x8b]);
+    let mut disk = a2kit::create_fs_from_file(&path)?;
+    assert_eq!(disk.bload("new")?,(768,vec![1,2,3]));
+    Ok(())
+}
+
+#[test]
+fn zip_members() -> STDRESULT {
+    assert!(a2kit::create_img_from_file("tests/collection.zip").is_err());
+    let mut plain = a2kit::create_img_from_file("tests/dos33-ren-del.do")?;
+    let mut member = a2kit::create_img_from_archive("tests/collection.zip","dos33-ren-del.do")?;
+    assert_eq!(plain.to_bytes(),member.to_bytes());
+    let names = archive::zip_names(&std::fs::read("tests/collection.zip")?)?;
+    assert_eq!(archive::image_names(&names),vec!["prodos-blank.po","dos33-ren-del.do"]);
+    Ok(())
+}
+
+#[test]
+fn save_zip_member() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("collection.zip").to_string_lossy().to_string();
+    std::fs::copy("tests/collection.zip", &path)?;
+    let mut disk = a2kit::create_fs_from_archive(&path, "prodos-blank.po")?;
+    disk.write_text("hello", "HELLO")?;
+    a2kit::save_img_to_archive(&mut disk, &path, "prodos-blank.po")?;
+    let mut disk = a2kit::create_fs_from_archive(&path, "prodos-blank.po")?;
+    assert_eq!(disk.read_text("hello")?,"HELLO\n");
+    // other members are untouched
+    let zip = std::fs::read(&path)?;
+    assert_eq!(archive::zip_names(&zip)?,vec!["prodos-blank.po","dos33-ren-del.do","readme.txt"]);
+    assert_eq!(archive::zip_get(&zip, "readme.txt", 100)?,b"two disk images\n".to_vec());
+    let mut other = a2kit::create_img_from_archive(&path, "dos33-ren-del.do")?;
+    assert_eq!(other.to_bytes(),std::fs::read("tests/dos33-ren-del.do")?);
+    Ok(())
+}
+
+#[test]
+fn member_syntax() {
+    assert_eq!(archive::split_member("dir/collection.zip:disk1.dsk"),("dir/collection.zip",Some("disk1.dsk")));
+    assert_eq!(archive::split_member("C:\\collection.ZIP:disk1.dsk"),("C:\\collection.ZIP",Some("disk1.dsk")));
+    assert_eq!(archive::split_member("C:\\disk1.dsk"),("C:\\disk1.dsk",None));
+    assert_eq!(archive::split_member("collection.zip:"),("collection.zip:",None));
+}
@@ -0,0 +1,75 @@
+// test of the browser operations, without the terminal
+use a2kit::commands::browse::{Browser,Item,Side,ViewAs};
+
+type STDRESULT = Result<(),Box<dyn std::error::Error>>;
+
+fn names(items: &[Item]) -> Vec<String> {
+    items.iter().map(|x| x.name.clone()).collect()
+}
+
+#[test]
+fn copy_rename_delete() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let host = dir.path().join("host");
+    std::fs::create_dir(&host)?;
+    std::fs::write(host.join("notes.txt"),"HELLO FROM HOST\n")?;
+    std::fs::write(host.join("code.bin"),[0xa9,0x00,0x60])?;
+    let img = dir.path().join("disk.do").to_string_lossy().to_string();
+    std::fs::copy("tests/prodos-smallfiles.do",&img)?;
+    let mut browser = Browser::new(&img,&host)?;
+    assert_eq!(names(browser.image_items()),vec!["HELLO","THECHIP","THETEXT"]);
+    assert_eq!(names(browser.host_items()),vec!["..","code.bin","notes.txt"]);
+
+    browser.copy_to_image("notes.txt")?;
+    browser.copy_to_image("code.bin")?;
+    let mut disk = a2kit::create_fs_from_file(&img)?;
+    assert_eq!(disk.read_text("notes.txt")?,"HELLO FROM HOST\n");
+    assert_eq!(disk.bload("code.bin")?,(0x800,vec![0xa9,0x00,0x60]));
+
+    browser.copy_to_host("THECHIP")?;
+    assert!(host.join("THECHIP").exists());
+    assert!(browser.copy_to_host("THECHIP").is_err());
+
+    browser.rename("THETEXT","RENAMED")?;
+    browser.delete("HELLO")?;
+    assert_eq!(names(browser.image_items()),vec!["CODE.BIN","NOTES.TXT","RENAMED","THECHIP"]);
+    let mut disk = a2kit::create_fs_from_file(&img)?;
+    assert!(disk.get("HELLO").is_err());
+    assert!(disk.get("RENAMED").is_ok());
+    Ok(())
+}
+
+#[test]
+fn views() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    std::fs::write(dir.path().join("hex.bin"),[0x41,0x42])?;
+    let mut browser = Browser::new("tests/prodos-smallfiles.do",dir.path())?;
+    let lines = browser.view(Side::Image,"HELLO",ViewAs::Applesoft)?;
+    assert!(lines.len() > 1);
+    assert!(lines[0].starts_with(|c: char| c.is_ascii_digit()));
+    let lines = browser.view(Side::Image,"THECHIP",ViewAs::Hex)?;
+    assert_eq!(lines.len(),1);
+    assert!(lines[0].starts_with("0000"));
+    let lines = browser.view(Side::Host,"hex.bin",ViewAs::Text)?;
+    assert_eq!(lines,vec!["AB"]);
+    Ok(())
+}
+
+#[test]
+fn undo() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let img = dir.path().join("disk.do").to_string_lossy().to_string();
+    std::fs::copy("tests/prodos-smallfiles.do",&img)?;
+    let original = std::fs::read(&img)?;
+    let mut browser = Browser::new(&img,dir.path())?;
+    assert!(!browser.undo()?);
+    browser.rename("THETEXT","RENAMED")?;
+    browser.delete("HELLO")?;
+    assert!(browser.undo()?);
+    assert_eq!(names(browser.image_items()),vec!["HELLO","RENAMED","THECHIP"]);
+    assert!(browser.undo()?);
+    assert_eq!(names(browser.image_items()),vec!["HELLO","THECHIP","THETEXT"]);
+    assert_eq!(std::fs::read(&img)?,original);
+    assert!(!browser.undo()?);
+    Ok(())
+}
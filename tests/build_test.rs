@@ -0,0 +1,104 @@
+// test of building disk images from a manifest
+use std::path::Path;
+use a2kit::commands::build::{Builder,Manifest};
+
+const MANIFEST: &str = r#"{
+    "image": { "path": "out.dsk", "type": "do", "os": "dos33", "volume": 254 },
+    "files": [
+        { "source": "hello.bas", "path": "HELLO", "type": "atxt" },
+        { "source": "prog.S", "path": "PROG", "type": "asm" },
+        { "source": "table.bin", "path": "TABLE", "type": "bin", "addr": 768 }
+    ]
+}"#;
+
+fn setup(dir: &Path) -> Manifest {
+    std::fs::write(dir.join("build.json"),MANIFEST).expect("write failed");
+    std::fs::write(dir.join("hello.bas"),"10 PRINT \"HELLO\"\n").expect("write failed");
+    std::fs::write(dir.join("prog.S"),"         ORG   $4000\n         LDA   #$00\n         RTS\n").expect("write failed");
+    std::fs::write(dir.join("table.bin"),[1,2,3,4]).expect("write failed");
+    Manifest::from_file(&dir.join("build.json")).expect("bad manifest")
+}
+
+#[test]
+fn parse_manifest() {
+    let manifest = Manifest::from_json(MANIFEST, Path::new("base")).expect("bad manifest");
+    assert_eq!(manifest.image.path,Path::new("base").join("out.dsk"));
+    assert_eq!(manifest.image.kind,"5.25in");
+    assert_eq!(manifest.image.volume,Some("254".to_string()));
+    assert_eq!(manifest.files.len(),3);
+    assert_eq!(manifest.files[2].addr,Some(768));
+    assert_eq!(manifest.files[0].addr,None);
+}
+
+#[test]
+fn missing_image() {
+    assert!(Manifest::from_json(r#"{"files":[]}"#, Path::new("")).is_err());
+}
+
+#[test]
+fn build_and_update() {
+    let dir = tempfile::tempdir().expect("no temp dir");
+    let mut builder = Builder::new(setup(dir.path()));
+    builder.build().expect("build failed");
+    let img_path = dir.path().join("out.dsk");
+    let mut disk = a2kit::create_fs_from_file(img_path.to_str().unwrap()).expect("no image");
+    assert_eq!(disk.bload("PROG").expect("no PROG"),(0x4000,vec![0xa9,0x00,0x60]));
+    assert_eq!(disk.bload("TABLE").expect("no TABLE"),(768,vec![1,2,3,4]));
+    assert!(disk.load("HELLO").is_ok());
+    assert_eq!(builder.changed(),vec![]);
+
+    // modify one source, and make sure the time stamp moves even on coarse file systems
+    let table = dir.path().join("table.bin");
+    std::fs::write(&table,[5,6]).expect("write failed");
+    let later = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+    std::fs::File::options().write(true).open(&table).unwrap().set_modified(later).unwrap();
+    let updated = builder.update().expect("update failed");
+    assert_eq!(updated.len(),1);
+    assert_eq!(updated[0].path,"TABLE");
+    let mut disk = a2kit::create_fs_from_file(img_path.to_str().unwrap()).expect("no image");
+    assert_eq!(disk.bload("TABLE").expect("no TABLE"),(768,vec![5,6]));
+    assert_eq!(disk.bload("PROG").expect("no PROG"),(0x4000,vec![0xa9,0x00,0x60]));
+    assert_eq!(builder.update().expect("update failed"),vec![]);
+}
+
+#[test]
+fn post_steps() {
+    let dir = tempfile::tempdir().expect("no temp dir");
+    let mut manifest = setup(dir.path());
+    let json_str = MANIFEST.replace("    ]\n}",r#"    ],
+    "post": [
+        { "rename": "TABLE", "name": "DATA" },
+        { "lock": "DATA" }
+    ]
+}"#);
+    manifest.post = Manifest::from_json(&json_str, dir.path()).expect("bad manifest").post;
+    assert_eq!(manifest.post.len(),2);
+    let mut builder = Builder::new(manifest);
+    builder.build().expect("build failed");
+    let img_path = dir.path().join("out.dsk");
+    let mut disk = a2kit::create_fs_from_file(img_path.to_str().unwrap()).expect("no image");
+    assert!(disk.bload("TABLE").is_err());
+    assert_eq!(disk.bload("DATA").expect("no DATA"),(768,vec![1,2,3,4]));
+    assert!(disk.delete("DATA").is_err());
+}
+
+#[test]
+fn bad_post_step() {
+    let json_str = MANIFEST.replace("    ]\n}","    ],\n    \"post\": [ { \"protect\": \"HELLO\" } ]\n}");
+    assert!(Manifest::from_json(&json_str, Path::new("")).is_err());
+}
+
+#[test]
+fn placement() {
+    let dir = tempfile::tempdir().expect("no temp dir");
+    std::fs::write(dir.path().join("table.bin"),[7;512]).expect("write failed");
+    let json_str = r#"{
+        "image": { "path": "out.po", "type": "po", "os": "prodos", "volume": "place" },
+        "files": [ { "source": "table.bin", "path": "table", "type": "bin", "addr": 768, "at_block": 100 } ]
+    }"#;
+    let manifest = Manifest::from_json(json_str, dir.path()).expect("bad manifest");
+    assert_eq!(manifest.files[0].at_block,Some(100));
+    Builder::new(manifest).build().expect("build failed");
+    let mut disk = a2kit::create_fs_from_file(dir.path().join("out.po").to_str().unwrap()).expect("no image");
+    assert_eq!(disk.read_block("100").expect("bad block"),vec![7;512]);
+}
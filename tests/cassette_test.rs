@@ -0,0 +1,69 @@
+// test of cassette audio encoding and decoding
+use a2kit::cassette::{Encoder,decode_records,from_wav};
+use a2kit::commands::ItemType;
+use a2kit::lang::applesoft;
+
+fn short_encoder() -> Encoder {
+    let mut encoder = Encoder::new();
+    encoder.leader = 0.5;
+    encoder
+}
+
+#[test]
+fn applesoft_round_trip() {
+    let mut tokenizer = applesoft::tokenizer::Tokenizer::new();
+    let tokens = tokenizer.tokenize("10 PRINT \"HELLO\"\n20 GOTO 10\n",2049).expect("tokenize failed");
+    let wav = short_encoder().to_wav(ItemType::ApplesoftTokens,&tokens).expect("encode failed");
+    let records = decode_records(&wav).expect("decode failed");
+    assert_eq!(records.len(),2);
+    assert_eq!(records[0],vec![tokens.len() as u8,0,0]);
+    assert_eq!(records[1].len(),tokens.len() + 1);
+    assert_eq!(from_wav(ItemType::ApplesoftTokens,&wav).expect("decode failed"),tokens);
+}
+
+#[test]
+fn integer_round_trip() {
+    let tokens: Vec<u8> = vec![0x0b,0x0a,0x00,0x61,0xb2,0xb1,0x00,0x00,0x12,0x01];
+    let wav = short_encoder().to_wav(ItemType::IntegerTokens,&tokens).expect("encode failed");
+    let records = decode_records(&wav).expect("decode failed");
+    assert_eq!(records[0],vec![10,0]);
+    assert_eq!(from_wav(ItemType::IntegerTokens,&wav).expect("decode failed"),tokens);
+}
+
+#[test]
+fn binary_round_trip() {
+    let dat: Vec<u8> = (0..=255).collect();
+    for rate in [22050,44100,48000] {
+        let mut encoder = short_encoder();
+        encoder.sample_rate = rate;
+        let wav = encoder.to_wav(ItemType::Binary,&dat).expect("encode failed");
+        assert_eq!(from_wav(ItemType::Binary,&wav).expect("decode failed"),dat);
+    }
+}
+
+#[test]
+fn checksum_error() {
+    let dat = vec![0x55;16];
+    let mut wav = short_encoder().to_wav(ItemType::Binary,&dat).expect("encode failed");
+    let level = |wav: &[u8],i: usize| wav[44+2*i+1] as i8 > 0;
+    // find the first 1 bit after the leader, and split its cycle into two 0 bits
+    let leader_end = (0.5 * 44100.0) as usize;
+    let mut beg = leader_end;
+    while level(&wav,beg+1) == level(&wav,beg) || level(&wav,beg+16) != level(&wav,beg+1) {
+        beg += 1;
+    }
+    beg += 1;
+    for i in 0..44 {
+        let val: i16 = match (i / 11) % 2 {
+            0 => 16384,
+            _ => -16384
+        };
+        wav[44+2*(beg+i)..44+2*(beg+i)+2].copy_from_slice(&val.to_le_bytes());
+    }
+    assert!(from_wav(ItemType::Binary,&wav).is_err());
+}
+
+#[test]
+fn not_wav() {
+    assert!(from_wav(ItemType::Binary,&[0;64]).is_err());
+}
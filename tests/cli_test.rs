@@ -130,6 +130,590 @@ BLOCKS FREE: 225\s+BLOCKS USED: 55\s+TOTAL BLOCKS: 280"#;
     Ok(())
 }
 
+#[test]
+fn catalog_sorted() -> STDRESULT {
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("catalog").arg("--generic").arg("--sort").arg("name")
+        .arg("-d").arg(Path::new("tests").join("prodos-bigfiles.woz"))
+        .assert()
+        .success()
+        .stdout("BAS      3  HELLO\nBIN     33  SAPLING\nTXT      5  TREE1\nTXT      7  TREE2\n");
+    Ok(())
+}
+
+#[test]
+fn catalog_native_sorted() -> STDRESULT {
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("catalog").arg("--sort").arg("size")
+        .arg("-d").arg(Path::new("tests").join("dos33-bigfiles.woz"))
+        .assert()
+        .success()
+        .stdout("\nDISK VOLUME 254\n\n B 066 SAPLING\n T 019 TREE2\n T 010 TREE1\n A 004 HELLO\n\n");
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("catalog").arg("--sort").arg("name")
+        .arg("-d").arg(Path::new("tests").join("cpm-smallfiles.dsk"))
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn catalog_filtered() -> STDRESULT {
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("catalog").arg("--generic").arg("--sort").arg("size").arg("--limit").arg("2")
+        .arg("-d").arg(Path::new("tests").join("prodos-bigfiles.woz"))
+        .assert()
+        .success()
+        .stdout("BIN     33  SAPLING\nTXT      7  TREE2\n");
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("catalog").arg("--generic").arg("--filter").arg("type=txt").arg("--since").arg("2022-12-04")
+        .arg("-d").arg(Path::new("tests").join("prodos-bigfiles.woz"))
+        .assert()
+        .success()
+        .stdout("TXT      5  TREE1\nTXT      7  TREE2\n");
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("catalog").arg("--generic").arg("--since").arg("2022-12-05")
+        .arg("-d").arg(Path::new("tests").join("prodos-bigfiles.woz"))
+        .assert()
+        .success()
+        .stdout("");
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("catalog").arg("--plain").arg("--sort").arg("size").arg("--limit").arg("2")
+        .arg("-d").arg(Path::new("tests").join("prodos-bigfiles.woz"))
+        .assert()
+        .success()
+        .stdout("NAME\tTYPE\tBLOCKS\tMODIFIED\nSAPLING\tBIN\t33\t2022-12-04T10:17\nTREE2\tTXT\t7\t2022-12-04T10:17\n");
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("catalog").arg("--filter").arg("type=txt")
+        .arg("-d").arg(Path::new("tests").join("prodos-bigfiles.woz"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("TREE1").and(predicate::str::contains("TREE2"))
+            .and(predicate::str::contains("SAPLING").not()).and(predicate::str::contains("BLOCKS FREE: 225")));
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("catalog").arg("--generic").arg("--filter").arg("size=3")
+        .arg("-d").arg(Path::new("tests").join("prodos-bigfiles.woz"))
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn entropy() -> STDRESULT {
+    let output = Command::cargo_bin("a2kit")?
+        .arg("entropy").arg("-d").arg(Path::new("tests").join("prodos-bigfiles.woz"))
+        .output()?;
+    assert!(output.status.success());
+    let report = json::parse(&String::from_utf8(output.stdout)?)?;
+    assert_eq!(report["summary"]["unformatted"],0);
+    assert_eq!(report["summary"]["blank"],28);
+    assert_eq!(report["summary"]["duplicate"],2);
+    assert_eq!(report["summary"]["data"],5);
+    assert_eq!(report["tracks"][4]["class"],"duplicate");
+    assert_eq!(report["tracks"][4]["duplicate_of"],3);
+    assert_eq!(report["tracks"][3]["entropy"],8.0);
+    assert_eq!(report["tracks"][7]["class"],"blank");
+    assert_eq!(report["tracks"][7]["entropy"],0.0);
+    Ok(())
+}
+
+#[test]
+fn fsdiff() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let changed = dir.path().join("changed.do");
+    std::fs::copy(Path::new("tests").join("prodos-smallfiles.do"),&changed)?;
+    let mut disk = a2kit::create_fs_from_file(&changed.to_string_lossy())?;
+    disk.delete("thetext")?;
+    disk.write_text("thetext","HELLO FROM A2KIT\n")?;
+    disk.write_text("new","NEW\n")?;
+    disk.lock("thechip")?;
+    a2kit::save_img(&mut disk,&changed.to_string_lossy())?;
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("fsdiff").arg(Path::new("tests").join("prodos-smallfiles.do")).arg(&changed).arg("--meta").arg("-u")
+        .assert()
+        .success()
+        .stdout("added   NEW\nmeta    THECHIP\nchanged THETEXT\n--- a/THETEXT\n+++ b/THETEXT\n@@ -1 +1 @@\n-HELLO FROM EMULATOR\n+HELLO FROM A2KIT\n");
+    Ok(())
+}
+
+#[test]
+fn carve() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("carve").arg("-d").arg(Path::new("tests").join("dos33-smallfiles.dsk"))
+        .arg("-o").arg(dir.path()).arg("--min-confidence").arg("0.9")
+        .assert()
+        .success()
+        .stdout("{\"count\":1,\"files\":[{\"evidence\":\"track_sector_list\",\"location\":\"DOS track 20 sector 15\",\"content\":\"text\",\"bytes\":20,\"confidence\":1,\"file\":\"carved_000.txt\"}]}\n");
+    assert_eq!(std::fs::read_to_string(dir.path().join("carved_000.txt"))?,"HELLO FROM EMULATOR\n");
+    Ok(())
+}
+
+#[test]
+fn get_bad_sector() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let bad = dir.path().join("bad.imd");
+    let mut img = a2kit::create_img_from_file(&Path::new("tests").join("msdos-ren-del.imd").to_string_lossy())?;
+    img.set_sector_status(1,0,3,a2kit::img::SectorStatus::CrcError)?;
+    std::fs::write(&bad,img.to_bytes())?;
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("get").arg("-t").arg("sec").arg("-f").arg("1,0,3").arg("-d").arg(&bad)
+        .assert()
+        .success()
+        .stdout(img.read_sector(1,0,3)?);
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("get").arg("-t").arg("sec").arg("-f").arg("1,0,3").arg("-d").arg(&bad).arg("--fail-bad")
+        .assert()
+        .failure();
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("get").arg("-t").arg("sec").arg("-f").arg("1,0,3").arg("-d").arg(&bad).arg("--fill-bad").arg("DEAD")
+        .assert()
+        .success()
+        .stdout([0xde,0xad].repeat(256));
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("catalog").arg("-d").arg(&bad).arg("--fill-bad").arg("DEAD")
+        .assert()
+        .success();
+    Ok(())
+}
+
+#[test]
+fn geometry_details() -> STDRESULT {
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    let output = cmd.arg("geometry").arg("-d").arg(Path::new("tests").join("dos32-smallfiles.woz")).output()?;
+    let geometry = json::parse(&String::from_utf8(output.stdout)?)?;
+    assert_eq!(geometry["tracks"][0]["data_rate"],"250kbps");
+    assert_eq!(geometry["tracks"][0]["bit_cell_ns"],4000);
+    assert_eq!(geometry["tracks"][0]["sector_count"],13);
+    assert_eq!(geometry["tracks"][0]["sector_ids"][0],7);
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("geometry").arg("--table").arg("-d").arg(Path::new("tests").join("msdos-ren-del.imd"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("    1    0    1  MFM  none   250kbps      -    9   512  1 2 3 4 5 6 7 8 9\n"));
+    Ok(())
+}
+
+#[test]
+fn dump_restore() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg = dir.path().join("dos33.dsk");
+    let dump = dir.path().join("dump.bin");
+    std::fs::copy(Path::new("tests").join("dos33-smallfiles.dsk"),&dimg)?;
+    Command::cargo_bin("a2kit")?
+        .arg("dump").arg("-d").arg(&dimg).arg("-r").arg("t17s14..t18s1").arg("-o").arg(&dump)
+        .assert()
+        .success();
+    let dat = std::fs::read(&dump)?;
+    assert_eq!(dat.len(),4*256);
+    Command::cargo_bin("a2kit")?
+        .arg("dump").arg("-d").arg(&dimg).arg("-b").arg("3..5").arg("-o").arg(&dump)
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&dump)?.len(),2*256);
+    // write the sectors back after zeroing them, and the image should be unchanged
+    std::fs::write(dir.path().join("blank.bin"),vec![0;4*256])?;
+    Command::cargo_bin("a2kit")?
+        .arg("restore").arg("-d").arg(&dimg).arg("-r").arg("t17s14..t18s1").arg("-i").arg(dir.path().join("blank.bin"))
+        .assert()
+        .success();
+    assert_ne!(std::fs::read(&dimg)?,std::fs::read(Path::new("tests").join("dos33-smallfiles.dsk"))?);
+    std::fs::write(&dump,&dat)?;
+    Command::cargo_bin("a2kit")?
+        .arg("restore").arg("-d").arg(&dimg).arg("-r").arg("t17s14..t18s1").arg("-i").arg(&dump)
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&dimg)?,std::fs::read(Path::new("tests").join("dos33-smallfiles.dsk"))?);
+    Command::cargo_bin("a2kit")?
+        .arg("restore").arg("-d").arg(&dimg).arg("-r").arg("t17s14..t18s2").arg("-i").arg(&dump)
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn reclaim_dos() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg = dir.path().join("dos33.dsk");
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk").arg("-d").arg(&dimg).arg("-o").arg("dos33").arg("-v").arg("254").arg("-t").arg("do").arg("-b")
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("reclaim-dos").arg("-d").arg(&dimg)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("freed 32 sectors"));
+    Command::cargo_bin("a2kit")?
+        .arg("reclaim-dos").arg("-d").arg(&dimg)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("freed 0 sectors"));
+    // fill the disk so that files spill onto the freed tracks
+    let txt = dir.path().join("big.txt");
+    std::fs::write(&txt,"HELLO WORLD\n".repeat(2700))?;
+    for name in ["F1","F2","F3","F4"] {
+        Command::cargo_bin("a2kit")?
+            .arg("put").arg("-d").arg(&dimg).arg("-f").arg(name).arg("-t").arg("txt").arg("-i").arg(&txt)
+            .assert()
+            .success();
+    }
+    Command::cargo_bin("a2kit")?
+        .arg("reclaim-dos").arg("-d").arg(&dimg)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is used by"));
+    Ok(())
+}
+
+#[test]
+fn wipe() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg = dir.path().join("prodos.do");
+    std::fs::copy(Path::new("tests").join("prodos-smallfiles.do"),&dimg)?;
+    Command::cargo_bin("a2kit")?
+        .arg("delete").arg("-d").arg(&dimg).arg("-f").arg("hello")
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("wipe").arg("-d").arg(&dimg).arg("--free").arg("--deleted").arg("-r").arg("t34s15").arg("-p").arg("e5")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("erased 1 deleted entries"))
+        .stderr(predicate::str::contains("filled 1 sectors"));
+    let dat = std::fs::read(&dimg)?;
+    assert_eq!(dat[dat.len()-256..],[0xe5;256]);
+    // the name HELLO is gone from the volume directory
+    let output = Command::cargo_bin("a2kit")?.arg("dump").arg("-d").arg(&dimg).arg("-b").arg("2").output()?;
+    assert_eq!(output.stdout.len(),512);
+    assert!(!output.stdout.windows(5).any(|w| w==b"HELLO"));
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&dimg)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("THECHIP"))
+        .stdout(predicate::str::contains("HELLO").not());
+    Command::cargo_bin("a2kit")?
+        .arg("wipe").arg("-d").arg(&dimg).arg("-p").arg("e5")
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn relabel() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let prodos = dir.path().join("prodos.do");
+    let dos = dir.path().join("dos33.dsk");
+    std::fs::copy(Path::new("tests").join("prodos-smallfiles.do"),&prodos)?;
+    std::fs::copy(Path::new("tests").join("dos33-smallfiles.dsk"),&dos)?;
+    Command::cargo_bin("a2kit")?
+        .arg("relabel").arg("-d").arg(&prodos).arg("-l").arg("renamed")
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&prodos)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("\n/RENAMED"));
+    Command::cargo_bin("a2kit")?
+        .arg("relabel").arg("-d").arg(&dos).arg("-l").arg("100")
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&dos)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("DISK VOLUME 100"));
+    Command::cargo_bin("a2kit")?
+        .arg("relabel").arg("-d").arg(&dos).arg("-l").arg("255")
+        .assert()
+        .failure();
+    Command::cargo_bin("a2kit")?
+        .arg("relabel").arg("-d").arg(&dos).arg("--serial").arg("1234-ABCD")
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn migrate() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dest = dir.path().join("migrated.dsk");
+    Command::cargo_bin("a2kit")?
+        .arg("migrate").arg("-d").arg(Path::new("tests").join("dos33-smallfiles.dsk")).arg("-o").arg(&dest).arg("-v").arg("new.vol")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"count\":3"));
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&dest)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("/NEW.VOL"))
+        .stdout(predicate::str::contains("THECHIP"));
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&dest).arg("-f").arg("thetext").arg("-t").arg("txt")
+        .assert()
+        .success()
+        .stdout("HELLO FROM EMULATOR\n");
+    // never overwrite, and the source must be DOS
+    Command::cargo_bin("a2kit")?
+        .arg("migrate").arg("-d").arg(Path::new("tests").join("dos33-smallfiles.dsk")).arg("-o").arg(&dest)
+        .assert()
+        .failure();
+    Command::cargo_bin("a2kit")?
+        .arg("migrate").arg("-d").arg(Path::new("tests").join("prodos-smallfiles.do")).arg("-o").arg(dir.path().join("again.do"))
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn mput_if_exists() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg = dir.path().join("prodos.do");
+    let paths = dir.path().join("paths.json");
+    let list = dir.path().join("list.json");
+    std::fs::copy(Path::new("tests").join("prodos-smallfiles.do"),&dimg)?;
+    std::fs::write(&paths,"[\"hello\",\"thetext\"]")?;
+    let output = Command::cargo_bin("a2kit")?
+        .arg("mget").arg("-d").arg(&dimg)
+        .stdin(Stdio::from(File::open(&paths)?))
+        .output()?;
+    std::fs::write(&list,output.stdout)?;
+    Command::cargo_bin("a2kit")?
+        .arg("mput").arg("-d").arg(&dimg)
+        .stdin(Stdio::from(File::open(&list)?))
+        .assert()
+        .failure();
+    // putting the same files again is harmless
+    for policy in ["skip","overwrite"] {
+        Command::cargo_bin("a2kit")?
+            .arg("mput").arg("-d").arg(&dimg).arg("--if-exists").arg(policy)
+            .stdin(Stdio::from(File::open(&list)?))
+            .assert()
+            .success();
+    }
+    Command::cargo_bin("a2kit")?
+        .arg("mput").arg("-d").arg(&dimg).arg("--if-exists").arg("rename")
+        .stdin(Stdio::from(File::open(&list)?))
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&dimg)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("HELLO1"))
+        .stdout(predicate::str::contains("THETEXT1"))
+        .stdout(predicate::str::contains("THETEXT2").not());
+    Ok(())
+}
+
+#[test]
+fn put_times() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let prodos = dir.path().join("prodos.do");
+    let fat = dir.path().join("msdos.img");
+    let txt = dir.path().join("host.txt");
+    std::fs::copy(Path::new("tests").join("prodos-smallfiles.do"),&prodos)?;
+    std::fs::copy(Path::new("tests").join("msdos-ren-del.img"),&fat)?;
+    std::fs::write(&txt,"HELLO\n")?;
+    let host_time = chrono::NaiveDate::from_ymd_opt(1990,2,3).unwrap().and_hms_opt(4,5,0).unwrap()
+        .and_local_timezone(chrono::Local).unwrap();
+    File::options().write(true).open(&txt)?.set_modified(host_time.into())?;
+    Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&prodos).arg("-f").arg("new").arg("-t").arg("txt").arg("-i").arg(&txt)
+        .arg("--time").arg("1986-09-15T12:34:00-05:00")
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&prodos)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("NEW             TXT       1 15-Sep-86 12:34  15-Sep-86 12:34"));
+    // a file image keeps its times on ProDOS only if asked
+    let fimg = Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&prodos).arg("-f").arg("new").arg("-t").arg("any")
+        .output()?.stdout;
+    assert_cmd::Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&prodos).arg("-f").arg("copy").arg("-t").arg("any")
+        .write_stdin(fimg)
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&prodos)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("COPY            TXT       1 15-Sep-86").not());
+    Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&fat).arg("-f").arg("new.txt").arg("-t").arg("txt").arg("-i").arg(&txt)
+        .arg("--preserve-times")
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&fat)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("02-03-90   04:05"));
+    Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&fat).arg("-f").arg("other.txt").arg("-t").arg("txt").arg("--preserve-times")
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn put_keep_case() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let prodos = dir.path().join("prodos.do");
+    let txt = dir.path().join("host.txt");
+    std::fs::copy(Path::new("tests").join("prodos-smallfiles.do"),&prodos)?;
+    std::fs::write(&txt,"HELLO\n")?;
+    Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&prodos).arg("-f").arg("Mixed.Case").arg("-t").arg("txt").arg("-i").arg(&txt)
+        .arg("--keep-case")
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&prodos).arg("-f").arg("lower").arg("-t").arg("txt").arg("-i").arg(&txt)
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&prodos)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(" Mixed.Case ").and(predicate::str::contains(" LOWER ")));
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&prodos).arg("-f").arg("MIXED.CASE").arg("-t").arg("txt")
+        .assert()
+        .success()
+        .stdout("HELLO\n");
+    Ok(())
+}
+
+#[test]
+fn multi_volume() -> STDRESULT {
+    use a2kit::fs::DiskFS;
+    // two ProDOS volumes in 32 MB partitions behind a 2MG header
+    let mut data = Vec::new();
+    for name in ["FIRST","SECOND"] {
+        let img = a2kit::img::dsk_po::PO::create(65535);
+        let mut disk = a2kit::fs::prodos::Disk::from_img(Box::new(img))?;
+        disk.format(&name.to_string(),false,None)?;
+        data.append(&mut disk.get_img().to_bytes());
+        data.append(&mut vec![0;512]);
+    }
+    let mut header = b"2IMG2KIT".to_vec();
+    for x in [64u16,1] {
+        header.extend_from_slice(&x.to_le_bytes());
+    }
+    for x in [1u32,0,data.len() as u32/512,64,data.len() as u32] {
+        header.extend_from_slice(&x.to_le_bytes());
+    }
+    header.resize(64,0);
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("big.2mg");
+    let txt = dir.path().join("hello.txt");
+    std::fs::write(&path,[header,data].concat())?;
+    std::fs::write(&txt,"HELLO\n")?;
+    Command::cargo_bin("a2kit")?
+        .arg("volumes").arg("-d").arg(&path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"volume\":2,\"blocks\":65535,\"file_system\":\"prodos\",\"label\":\"SECOND\""));
+    Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&path).arg("--volume").arg("2").arg("-f").arg("hello").arg("-t").arg("txt").arg("-i").arg(&txt)
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&path).arg("--volume").arg("2").arg("--generic")
+        .assert()
+        .success()
+        .stdout("TXT      1  HELLO\n");
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&path).arg("--volume").arg("1").arg("--generic")
+        .assert()
+        .success()
+        .stdout("");
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&path).arg("--volume").arg("3")
+        .assert()
+        .failure();
+    // without `--volume` the first volume is used
+    Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&path).arg("-f").arg("first").arg("-t").arg("txt").arg("-i").arg(&txt)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("image holds 2 volumes"));
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&path).arg("--volume").arg("1").arg("--generic")
+        .assert()
+        .success()
+        .stdout("TXT      1  FIRST\n");
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&path).arg("--volume").arg("2").arg("--generic")
+        .assert()
+        .success()
+        .stdout("TXT      1  HELLO\n");
+    assert_eq!(std::fs::read(&path)?[0..8],*b"2IMG2KIT");
+    Ok(())
+}
+
+#[test]
+fn lint_workspace() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    std::fs::write(dir.path().join("MAIN.S")," LDA #$00\n PUT INC\n RTS\n")?;
+    std::fs::write(dir.path().join("INC.S")," STA $C000\n JMP NOWHERE\n")?;
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    let output = cmd.arg("lint")
+        .arg("-w").arg(dir.path())
+        .arg("--format").arg("json")
+        .output()?;
+    assert!(!output.status.success());
+    let report = json::parse(&String::from_utf8_lossy(&output.stdout))?;
+    assert_eq!(report["errors"],1);
+    assert_eq!(report["files"].len(),1);
+    assert_eq!(report["files"][0]["path"],"INC.S");
+    assert_eq!(report["files"][0]["diagnostics"][0]["line"],1);
+
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    let output = cmd.arg("lint")
+        .arg("-w").arg(dir.path())
+        .arg("--format").arg("sarif")
+        .output()?;
+    let report = json::parse(&String::from_utf8_lossy(&output.stdout))?;
+    assert_eq!(report["version"],"2.1.0");
+    assert_eq!(report["runs"][0]["results"][0]["level"],"error");
+    assert_eq!(report["runs"][0]["results"][0]["locations"][0]["physicalLocation"]["region"]["startLine"],2);
+    Ok(())
+}
+
+#[test]
+fn lint_applesoft() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    std::fs::write(dir.path().join("GAME.BAS"),"10 GOSUB 100\n20 END\n30 PRINT \"DEAD\"\n100 RETURN\n")?;
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    let output = cmd.arg("lint")
+        .arg("-t").arg("atxt")
+        .arg("-w").arg(dir.path())
+        .arg("--format").arg("json")
+        .output()?;
+    assert!(output.status.success());
+    let report = json::parse(&String::from_utf8_lossy(&output.stdout))?;
+    assert_eq!(report["warnings"],1);
+    assert_eq!(report["files"][0]["path"],"GAME.BAS");
+    assert_eq!(report["files"][0]["diagnostics"][0]["message"],"line is unreachable");
+
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("lint")
+        .arg("-t").arg("atxt")
+        .arg("-w").arg(dir.path())
+        .arg("--deny-warnings")
+        .assert()
+        .failure();
+    Ok(())
+}
+
 #[test]
 fn catalog_pascal() -> STDRESULT {
     let mut cmd = Command::cargo_bin("a2kit")?;
@@ -236,3 +820,1178 @@ r#"10  HOME
         
     Ok(())
 }
+
+#[test]
+fn detokenize_highlight() -> STDRESULT {
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    let expected =
+r#"<pre class="a2kit"><span class="a2kit-macro">10  </span><span class="a2kit-keyword">HOME</span> 
+<span class="a2kit-macro">20  </span><span class="a2kit-keyword">PRINT</span> <span class="a2kit-variable">A$</span>
+</pre>
+"#;
+    let toks: Vec<u8> = vec![7,8,10,0,0x97,0,15,8,0x14,0,0xba,0x41,0x24,0,0,0];
+    let mut child = cmd.arg("detokenize")
+        .arg("-t").arg("atok")
+        .arg("--highlight").arg("html")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn child process");
+        let mut stdin = child.stdin.take().expect("Failed to open stdin");
+        std::thread::spawn(move || {
+            stdin.write_all(&toks).expect("Failed to write to stdin");
+        });
+        
+        let output = child.wait_with_output().expect("Failed to read stdout");
+        assert_eq!(String::from_utf8_lossy(&output.stdout),expected);
+        
+    Ok(())
+}
+
+#[test]
+fn format_merlin() -> STDRESULT {
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    let test_prog = "START lda #$01 ;load\n\tsta $C000\n";
+    let expected = "START     LDA     #$01        ;load\n          STA     $C000\n";
+    let mut child = cmd.arg("format")
+        .arg("-t").arg("mtxt")
+        .arg("--columns").arg("10,8,12")
+        .arg("--case").arg("upper")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn child process");
+        let mut stdin = child.stdin.take().expect("Failed to open stdin");
+        std::thread::spawn(move || {
+            stdin.write_all(test_prog.as_bytes()).expect("Failed to write to stdin");
+        });
+        
+        let output = child.wait_with_output().expect("Failed to read stdout");
+        assert_eq!(String::from_utf8_lossy(&output.stdout),expected);
+        
+    Ok(())
+}
+
+#[test]
+fn verify_convert() -> STDRESULT {
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("verify-convert")
+        .arg("-d").arg(Path::new("tests").join("prodos-smallfiles.do"))
+        .arg("-t").arg("woz2")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"lossless\":true"));
+    Ok(())
+}
+
+#[test]
+fn build_manifest() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    std::fs::write(dir.path().join("hello.bas"),"10 PRINT \"HELLO\"\n")?;
+    std::fs::write(dir.path().join("build.json"),r#"{
+        "image": { "path": "out.po", "type": "po", "os": "prodos", "volume": "test" },
+        "files": [ { "source": "hello.bas", "path": "hello", "type": "atxt" } ],
+        "post": [ { "lock": "hello" } ]
+    }"#)?;
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("build").arg(dir.path().join("build.json"))
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("catalog")
+        .arg("-d").arg(dir.path().join("out.po"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("HELLO"));
+    Ok(())
+}
+
+#[test]
+fn wav_round_trip() -> STDRESULT {
+    let dat: Vec<u8> = vec![0xa9,0xc1,0x20,0xed,0xfd,0x60];
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    let wav = cmd.arg("to-wav").arg("-t").arg("bin").arg("--leader").arg("1")
+        .write_stdin(dat.clone())
+        .assert()
+        .success()
+        .get_output().stdout.clone();
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    cmd.arg("from-wav").arg("-t").arg("bin")
+        .write_stdin(wav)
+        .assert()
+        .success()
+        .stdout(dat);
+    Ok(())
+}
+
+#[test]
+fn deterministic_images() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let mut images = Vec::new();
+    for name in ["a.img","b.img","a.imd","b.imd"] {
+        let path = dir.path().join(name);
+        let typ = name.split('.').last().unwrap();
+        let mut cmd = Command::cargo_bin("a2kit")?;
+        cmd.arg("mkdsk").arg("--deterministic")
+            .arg("-d").arg(&path).arg("-t").arg(typ).arg("-o").arg("fat").arg("-k").arg("3.5in-ibm-720").arg("-v").arg("TEST")
+            .assert()
+            .success();
+        let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+        cmd.arg("--deterministic").arg("put").arg("-d").arg(&path).arg("-f").arg("hello.txt").arg("-t").arg("txt")
+            .write_stdin("HELLO\n")
+            .assert()
+            .success();
+        images.push(std::fs::read(&path)?);
+    }
+    assert_eq!(images[0],images[1]);
+    assert_eq!(images[2],images[3]);
+    Ok(())
+}
+
+#[test]
+fn zip_member_path() -> STDRESULT {
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("catalog")
+        .arg("-d").arg("tests/collection.zip:dos33-ren-del.do")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MYTREE1"));
+    let dir = tempfile::tempdir()?;
+    let archive = dir.path().join("collection.zip").to_string_lossy().to_string();
+    std::fs::copy("tests/collection.zip",&archive)?;
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("mkdsk").arg("-d").arg(archive.clone() + ":new.po").arg("-t").arg("po").arg("-o").arg("prodos").arg("-v").arg("new")
+        .assert()
+        .success();
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    cmd.arg("put").arg("-d").arg(archive.clone() + ":new.po").arg("-f").arg("hello").arg("-t").arg("txt")
+        .write_stdin("HELLO\n")
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("catalog")
+        .arg("-d").arg(archive.clone() + ":new.po")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("HELLO"));
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    let out = cmd.arg("mget").arg("-d").arg(&archive)
+        .write_stdin("[\"hello\"]")
+        .assert()
+        .success()
+        .get_output().stdout.clone();
+    let ans = json::parse(&String::from_utf8(out)?)?;
+    assert_eq!(ans["prodos-blank.po"].len(),0);
+    assert_eq!(ans["dos33-ren-del.do"].len(),1);
+    assert_eq!(ans["new.po"].len(),1);
+    Ok(())
+}
+
+#[test]
+fn normalize_dir() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    std::fs::copy("tests/prodos-smallfiles.do",dir.path().join("Small Files.do"))?;
+    std::fs::copy("tests/prodos-blank.po",dir.path().join("blank.po"))?;
+    let mut runs = Vec::new();
+    for _ in 0..2 {
+        let mut cmd = Command::cargo_bin("a2kit")?;
+        let out = cmd.arg("normalize").arg("--dir").arg(dir.path()).arg("-t").arg("po")
+            .assert()
+            .success()
+            .get_output().stdout.clone();
+        runs.push(json::parse(&String::from_utf8(out)?)?);
+    }
+    assert_eq!(runs[0]["converted"],1);
+    assert_eq!(runs[0]["skipped"],1);
+    assert_eq!(runs[1]["skipped"],3);
+    assert!(dir.path().join("small_files.po").exists());
+    Ok(())
+}
+
+#[test]
+fn normalize_conflict() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let out_dir = tempfile::tempdir()?;
+    std::fs::copy("tests/prodos-smallfiles.do",dir.path().join("Small Files.do"))?;
+    std::fs::copy("tests/prodos-blank.po",dir.path().join("small_files.po"))?;
+    std::fs::write(out_dir.path().join("blank.po"),"not an image")?;
+    std::fs::copy("tests/prodos-blank.po",dir.path().join("blank.po"))?;
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    let out = cmd.arg("normalize").arg("--dir").arg(dir.path()).arg("-o").arg(out_dir.path()).arg("-t").arg("po")
+        .assert()
+        .failure()
+        .get_output().stdout.clone();
+    let ans = json::parse(&String::from_utf8(out)?)?;
+    assert_eq!(ans["converted"],1);
+    assert_eq!(ans["skipped"],0);
+    assert_eq!(ans["conflicts"],2);
+    assert_eq!(std::fs::read(out_dir.path().join("blank.po"))?,b"not an image");
+    Ok(())
+}
+
+#[test]
+fn completions_dynamic() -> STDRESULT {
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("completions").arg("--image").arg(Path::new("tests").join("prodos-bigfiles.woz"))
+        .arg("--prefix").arg("/new.disk/T")
+        .assert()
+        .success()
+        .stdout("/new.disk/TREE1\n/new.disk/TREE2\n");
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("completions").arg("--image").arg(Path::new("tests").join("msdos-ren-del.img"))
+        .arg("--prefix").arg("DIR1/")
+        .assert()
+        .success()
+        .stdout("DIR1/ASCEND.TXT\nDIR1/DSKBLD.BAS\nDIR1/SUBDIR1/\n");
+    for shell in ["bash","fish","zsh"] {
+        let mut cmd = Command::cargo_bin("a2kit")?;
+        cmd.arg("completions").arg("-s").arg(shell).arg("--dynamic")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("a2kit completions --image"));
+    }
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("completions").arg("-s").arg("ps1").arg("--dynamic")
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn pascal_workspace() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    for (vol,fname,txt) in [("APPLE1","apple1.do","EDITOR\n"),("APPLE2","apple2.do","COMPILER\n")] {
+        let dimg_path = dir.path().join(fname);
+        Command::cargo_bin("a2kit")?
+            .arg("mkdsk").arg("-o").arg("pascal").arg("-v").arg(vol).arg("-t").arg("do")
+            .arg("-d").arg(&dimg_path)
+            .assert().success();
+        assert_cmd::Command::cargo_bin("a2kit")?
+            .arg("put").arg("-f").arg([&txt[0..4],".TEXT"].concat()).arg("-t").arg("txt")
+            .arg("-d").arg(&dimg_path)
+            .write_stdin(txt)
+            .assert().success();
+    }
+    let map_path = dir.path().join("volumes.json");
+    std::fs::write(&map_path,"{\"APPLE1\": \"apple1.do\", \"apple2\": \"apple2.do\"}")?;
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-f").arg("apple2:COMP.TEXT").arg("-t").arg("txt")
+        .arg("--workspace").arg(&map_path)
+        .assert().success()
+        .stdout("COMPILER\n");
+    Command::cargo_bin("a2kit")?
+        .arg("glob").arg("-f").arg("*.TEXT")
+        .arg("--workspace").arg(&map_path)
+        .assert().success()
+        .stdout("[\"APPLE1:EDIT.TEXT\",\"APPLE2:COMP.TEXT\"]\n");
+    Command::cargo_bin("a2kit")?
+        .arg("glob").arg("-f").arg("APPLE1:*")
+        .arg("--workspace").arg(&map_path)
+        .assert().success()
+        .stdout("[\"APPLE1:EDIT.TEXT\"]\n");
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-f").arg("APPLE3:EDIT.TEXT").arg("-t").arg("txt")
+        .arg("--workspace").arg(&map_path)
+        .assert().failure();
+    Ok(())
+}
+
+#[test]
+fn bundle_applesoft() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg_path = dir.path().join("bundle.do");
+    let asset_path = dir.path().join("asset.bin");
+    std::fs::write(&asset_path,[0xa9,0xc1,0x20,0xed,0xfd,0x60])?;
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk").arg("-o").arg("dos33").arg("-v").arg("254").arg("-t").arg("do")
+        .arg("-d").arg(&dimg_path)
+        .assert().success();
+    let tok = assert_cmd::Command::cargo_bin("a2kit")?
+        .arg("tokenize").arg("-a").arg("2049").arg("-t").arg("atxt")
+        .write_stdin("10 CALL 768\n")
+        .assert().success().get_output().stdout.clone();
+    assert_cmd::Command::cargo_bin("a2kit")?
+        .arg("bundle").arg("-a").arg("16384").arg("--asset").arg(["768:",&asset_path.to_string_lossy()].concat())
+        .arg("-f").arg("HELLO").arg("-d").arg(&dimg_path)
+        .write_stdin(tok.clone())
+        .assert().success();
+    let mut disk = a2kit::create_fs_from_file(&dimg_path.to_string_lossy())?;
+    let (addr,dat) = disk.bload("HELLO")?;
+    assert_eq!(addr,16384);
+    assert_eq!(dat.len(),0x6e + 2*6 + 2 + tok.len() + 1 + 6);
+    assert_eq!(dat[dat.len()-6..],[0xa9,0xc1,0x20,0xed,0xfd,0x60]);
+    // asset would overwrite the bundle
+    assert_cmd::Command::cargo_bin("a2kit")?
+        .arg("bundle").arg("-a").arg("768")
+        .arg("--asset").arg(["800:",&asset_path.to_string_lossy()].concat())
+        .write_stdin(tok)
+        .assert().failure();
+    Ok(())
+}
+
+#[test]
+fn macbinary_round_trip() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg = dir.path().join("prodos.do");
+    std::fs::copy(Path::new("tests").join("prodos-smallfiles.do"),&dimg)?;
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    let macbin = cmd.arg("get").arg("-d").arg(&dimg).arg("-f").arg("thechip").arg("-t").arg("macbinary")
+        .assert()
+        .success()
+        .get_output().stdout.clone();
+    assert_eq!(macbin.len(),256);
+    assert_eq!(macbin[1..9],*b"\x07THECHIP");
+    assert_eq!(macbin[65..73],*b"p\x06\x03\x00pdos");
+    assert_eq!(macbin[83..87],[0,0,0,4]);
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    cmd.arg("put").arg("-d").arg(&dimg).arg("-f").arg("chip2").arg("-t").arg("macbinary")
+        .write_stdin(macbin)
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&dimg)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"CHIP2\s+BIN\s+1 04-Dec-22 10:28  04-Dec-22 10:28\s+4\s+768")?);
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    let orig = cmd.arg("get").arg("-d").arg(&dimg).arg("-f").arg("thechip").arg("-t").arg("bin")
+        .assert().success().get_output().stdout.clone();
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    cmd.arg("get").arg("-d").arg(&dimg).arg("-f").arg("chip2").arg("-t").arg("bin")
+        .assert().success().stdout(orig);
+    Ok(())
+}
+
+#[test]
+fn macbinary_forks() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg = dir.path().join("prodos.do");
+    let mut img = std::fs::read(Path::new("tests").join("prodos-smallfiles.do"))?;
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    let mut macbin = cmd.arg("get").arg("-d").arg(Path::new("tests").join("prodos-smallfiles.do")).arg("-f").arg("thechip").arg("-t").arg("macbinary")
+        .assert()
+        .success()
+        .get_output().stdout.clone();
+    // a resource fork, with a version that skips the CRC
+    macbin[87..91].copy_from_slice(&[0,0,0,0x80]);
+    macbin[122] = 0;
+    macbin.extend_from_slice(&[0xaa;128]);
+    std::fs::write(&dimg,&img)?;
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    cmd.arg("put").arg("-d").arg(&dimg).arg("-f").arg("chip2").arg("-t").arg("macbinary")
+        .write_stdin(macbin)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("resource fork"));
+    assert_eq!(std::fs::read(&dimg)?,img);
+    // storage type 5 is an extended file
+    let ptr = img.windows(8).position(|w| w == b"\x17THECHIP").expect("entry not found");
+    img[ptr] = 0x57;
+    std::fs::write(&dimg,&img)?;
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&dimg).arg("-f").arg("thechip").arg("-t").arg("macbinary")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("resource fork"));
+    Ok(())
+}
+
+#[test]
+fn retype_glob() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg = dir.path().join("prodos.do");
+    std::fs::copy(Path::new("tests").join("prodos-smallfiles.do"),&dimg)?;
+    Command::cargo_bin("a2kit")?
+        .arg("retype").arg("-d").arg(&dimg).arg("-f").arg("THE*").arg("-t").arg("bin").arg("-a").arg("0x2000").arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("THECHIP -> bin 8192"))
+        .stdout(predicate::str::contains("THETEXT -> bin 8192"));
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&dimg)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"THETEXT\s+TXT")?);
+    Command::cargo_bin("a2kit")?
+        .arg("retype").arg("-d").arg(&dimg).arg("-f").arg("THE*").arg("-t").arg("bin").arg("-a").arg("0x2000")
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&dimg)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"THECHIP\s+BIN.*8192")?)
+        .stdout(predicate::str::is_match(r"THETEXT\s+BIN.*8192")?)
+        .stdout(predicate::str::is_match(r"HELLO\s+BAS.*2049")?);
+    Ok(())
+}
+
+#[test]
+fn mput_resume() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg = dir.path().join("prodos.do");
+    let blank = dir.path().join("blank.po");
+    let paths = dir.path().join("paths.json");
+    let list = dir.path().join("list.json");
+    let status = dir.path().join("status.json");
+    std::fs::copy(Path::new("tests").join("prodos-smallfiles.do"),&dimg)?;
+    std::fs::copy(Path::new("tests").join("prodos-blank.po"),&blank)?;
+    std::fs::write(&paths,"[\"hello\",\"missing\",\"thetext\"]")?;
+    // mget carries on past the missing file
+    let output = Command::cargo_bin("a2kit")?
+        .arg("mget").arg("-d").arg(&dimg).arg("--status").arg(&status)
+        .stdin(Stdio::from(File::open(&paths)?))
+        .output()?;
+    assert!(!output.status.success());
+    let fimgs = json::parse(&String::from_utf8(output.stdout)?)?;
+    assert_eq!(fimgs.len(),2);
+    let manifest = json::parse(&std::fs::read_to_string(&status)?)?;
+    assert_eq!(manifest[0]["status"],"ok");
+    assert_eq!(manifest[1]["status"],"failed");
+    assert_eq!(manifest[2]["status"],"ok");
+    // mput carries on past a file image for the wrong file system, and the good files are saved
+    let mut bad = fimgs.clone();
+    bad[0]["file_system"] = "a2 dos".into();
+    std::fs::write(&list,json::stringify(bad))?;
+    Command::cargo_bin("a2kit")?
+        .arg("mput").arg("-d").arg(&blank).arg("--status").arg(&status)
+        .stdin(Stdio::from(File::open(&list)?))
+        .assert()
+        .failure();
+    let manifest = json::parse(&std::fs::read_to_string(&status)?)?;
+    assert_eq!(manifest[0]["status"],"failed");
+    assert_eq!(manifest[1]["status"],"ok");
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&blank)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("THETEXT"))
+        .stdout(predicate::str::contains("HELLO").not());
+    // resume, only the failure is retried, otherwise THETEXT would fail because it exists
+    let manifest_path = dir.path().join("manifest.json");
+    std::fs::copy(&status,&manifest_path)?;
+    Command::cargo_bin("a2kit")?
+        .arg("mput").arg("-d").arg(&blank).arg("--resume").arg(&manifest_path).arg("--status").arg(&status)
+        .stdin(Stdio::from(File::open(&list)?))
+        .assert()
+        .failure();
+    let manifest = json::parse(&std::fs::read_to_string(&status)?)?;
+    assert_eq!(manifest[0]["status"],"failed");
+    assert_eq!(manifest[1]["status"],"ok");
+    // fix the file system and resume again
+    std::fs::write(&list,json::stringify(fimgs.clone()))?;
+    Command::cargo_bin("a2kit")?
+        .arg("mput").arg("-d").arg(&blank).arg("--resume").arg(&manifest_path).arg("--status").arg(&status)
+        .stdin(Stdio::from(File::open(&list)?))
+        .assert()
+        .success();
+    let manifest = json::parse(&std::fs::read_to_string(&status)?)?;
+    assert_eq!(manifest.len(),2);
+    assert!(manifest.members().all(|e| e["status"] == "ok"));
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&blank)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("THETEXT"))
+        .stdout(predicate::str::contains("HELLO"));
+    Ok(())
+}
+
+#[test]
+fn put_host_glob() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg = dir.path().join("prodos.po");
+    let src = dir.path().join("src");
+    std::fs::create_dir(&src)?;
+    std::fs::write(src.join("hello.bas"),"10 PRINT \"HI\"\n")?;
+    std::fs::write(src.join("2 bye!.bas"),"10 PRINT \"BYE\"\n")?;
+    std::fs::write(src.join("notes.txt"),"not matched\n")?;
+    std::fs::copy(Path::new("tests").join("prodos-blank.po"),&dimg)?;
+    Command::cargo_bin("a2kit")?
+        .arg("mkdir").arg("-d").arg(&dimg).arg("-f").arg("basic")
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&dimg).arg("-i").arg(src.join("*.bas")).arg("--as-dir").arg("basic/").arg("-t").arg("atxt")
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&dimg).arg("-f").arg("basic")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"HELLO\s+BAS")?)
+        .stdout(predicate::str::is_match(r"A2\.BYE\.\s+BAS")?)
+        .stdout(predicate::str::contains("NOTES").not());
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    let tok = cmd.arg("get").arg("-d").arg(&dimg).arg("-f").arg("basic/a2.bye.").arg("-t").arg("atok")
+        .assert().success().get_output().stdout.clone();
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    cmd.arg("detokenize").arg("-t").arg("atok").write_stdin(tok)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("PRINT \"BYE\""));
+    Ok(())
+}
+
+#[test]
+fn mput_invalid_names() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg = dir.path().join("prodos.do");
+    let blank = dir.path().join("blank.po");
+    let list = dir.path().join("list.json");
+    std::fs::copy(Path::new("tests").join("prodos-smallfiles.do"),&dimg)?;
+    std::fs::copy(Path::new("tests").join("prodos-blank.po"),&blank)?;
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    let output = cmd.arg("mget").arg("-d").arg(&dimg).write_stdin("[\"hello\",\"thetext\"]")
+        .assert().success().get_output().stdout.clone();
+    let mut fimgs = json::parse(&String::from_utf8(output)?)?;
+    fimgs[0]["full_path"] = "/NEW.DISK/_X".into();
+    fimgs[1]["full_path"] = "/NEW.DISK/-X".into();
+    std::fs::write(&list,json::stringify(fimgs))?;
+    Command::cargo_bin("a2kit")?
+        .arg("mput").arg("-d").arg(&blank)
+        .stdin(Stdio::from(File::open(&list)?))
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&blank)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"A\.X\s+BAS")?)
+        .stdout(predicate::str::is_match(r"A\.X1\s+TXT")?);
+    Ok(())
+}
+
+#[test]
+fn inspect_structure() -> STDRESULT {
+    let inspect = |img: &str,path: &str| -> Result<json::JsonValue,Box<dyn std::error::Error>> {
+        let output = Command::cargo_bin("a2kit")?
+            .arg("inspect").arg("-d").arg(Path::new("tests").join(img)).arg("-f").arg(path)
+            .output()?;
+        assert!(output.status.success());
+        Ok(json::parse(&String::from_utf8(output.stdout)?)?)
+    };
+    let tree = inspect("prodos-bigfiles.dsk","tree2")?;
+    assert_eq!(tree["storage_type"],"tree");
+    assert_eq!(tree["index"]["block"],tree["key_block"]);
+    assert_eq!(tree["index"]["index"][0]["data"][0],15);
+    // the second index block only points at the end of the sparse file
+    assert_eq!(tree["index"]["index"][1]["data"][0],0);
+    let sapling = inspect("prodos-bigfiles.dsk","sapling")?;
+    assert_eq!(sapling["storage_type"],"sapling");
+    assert_eq!(sapling["index"]["data"].len(),32);
+    let dos = inspect("dos33-bigfiles.do","SAPLING")?;
+    assert_eq!(dos["ts_lists"][0]["data"][0].len(),2);
+    let fat = inspect("msdos-ren-del.img","/DIR1/ASCEND.TXT")?;
+    assert_eq!(fat["clusters"].len(),14);
+    Command::cargo_bin("a2kit")?
+        .arg("inspect").arg("-d").arg(Path::new("tests").join("cpm-smallfiles.dsk")).arg("-f").arg("POLARIS.TXT")
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn stat_deep() -> STDRESULT {
+    let stat = |img: &str,level: &str| -> Result<json::JsonValue,Box<dyn std::error::Error>> {
+        let output = Command::cargo_bin("a2kit")?
+            .arg("stat").arg("-d").arg(Path::new("tests").join(img)).arg("--level").arg(level)
+            .output()?;
+        assert!(output.status.success());
+        Ok(json::parse(&String::from_utf8(output.stdout)?)?)
+    };
+    let basic = stat("dos33-bigfiles.do","basic")?;
+    assert_eq!(basic["raw"]["variant"],"standard");
+    let prodos = stat("prodos-bigfiles.dsk","deep")?;
+    assert_eq!(prodos["raw"]["volume_header"]["total_blocks"],280);
+    assert_eq!(prodos["raw"]["derived"]["free_blocks"],prodos["free_blocks"]);
+    let dos = stat("dos33-bigfiles.do","deep")?;
+    assert_eq!(dos["raw"]["vtoc"]["catalog_track"],17);
+    assert_eq!(dos["raw"]["derived"]["image"]["type"],"do");
+    let cpm = stat("cpm-smallfiles.dsk","deep")?;
+    assert_eq!(cpm["raw"]["dpb"]["drm"],47);
+    assert_eq!(cpm["raw"]["derived"]["dir_entries"],48);
+    let fat = stat("msdos-ren-del.img","deep")?;
+    assert_eq!(fat["raw"]["bpb"]["root_ent_cnt"],64);
+    assert_eq!(fat["raw"]["derived"]["fat_type"],12);
+    let pascal = stat("pascal-smallfiles.do","deep")?;
+    assert_eq!(pascal["raw"]["volume_header"]["name"],pascal["label"]);
+    Ok(())
+}
+
+#[test]
+fn get_chain() -> STDRESULT {
+    let dos_img = Path::new("tests").join("dos33-bigfiles.do");
+    let raw = Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&dos_img).arg("-f").arg("SAPLING").arg("-t").arg("raw")
+        .output()?;
+    assert!(raw.status.success());
+    let chain = Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&dos_img).arg("--chain").arg("22,14 22,13 22,12")
+        .output()?;
+    assert!(chain.status.success());
+    assert_eq!(chain.stdout,raw.stdout[0..768].to_vec());
+    let prodos_img = Path::new("tests").join("prodos-bigfiles.dsk");
+    let blocks = Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&prodos_img).arg("-f").arg("2..4").arg("-t").arg("block")
+        .output()?;
+    let chain = Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&prodos_img).arg("--chain").arg("2 3")
+        .output()?;
+    assert_eq!(chain.stdout,blocks.stdout);
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&prodos_img).arg("--chain").arg("0,2")
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn po_padding() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let txt = dir.path().join("host.txt");
+    std::fs::write(&txt,"HELLO\n")?;
+    let orig = std::fs::read(Path::new("tests").join("prodos-blank.po"))?;
+    let put = |dimg: &Path,name: &str,repair: bool| -> STDRESULT {
+        let mut cmd = Command::cargo_bin("a2kit")?;
+        cmd.arg("put").arg("-d").arg(dimg).arg("-f").arg(name).arg("-t").arg("txt").arg("-i").arg(&txt);
+        if repair {
+            cmd.arg("--repair-padding");
+        }
+        cmd.assert().success();
+        Ok(())
+    };
+    // trailing bytes are kept unless repaired
+    let padded = dir.path().join("padded.hdv");
+    std::fs::write(&padded,[orig.clone(),vec![0x1a;100]].concat())?;
+    put(&padded,"first",false)?;
+    let buf = std::fs::read(&padded)?;
+    assert_eq!(buf.len(),orig.len()+100);
+    assert_eq!(buf[orig.len()..],vec![0x1a;100]);
+    put(&padded,"second",true)?;
+    assert_eq!(std::fs::read(&padded)?.len(),orig.len());
+    // a short last block is padded
+    let short = dir.path().join("short.po");
+    std::fs::write(&short,&orig[0..orig.len()-12])?;
+    put(&short,"first",true)?;
+    assert_eq!(std::fs::read(&short)?.len(),orig.len());
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&short)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("FIRST"));
+    Ok(())
+}
+
+#[test]
+fn append_truncate() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let first = dir.path().join("first.txt");
+    let second = dir.path().join("second.txt");
+    std::fs::write(&first,"FIRST LINE\n")?;
+    std::fs::write(&second,"SECOND LINE\n")?;
+    for (dimg,path) in [("dos33-smallfiles.dsk","LOG"),("prodos-smallfiles.do","LOG")] {
+        let dimg_path = dir.path().join(dimg);
+        std::fs::copy(Path::new("tests").join(dimg),&dimg_path)?;
+        Command::cargo_bin("a2kit")?
+            .arg("put").arg("-d").arg(&dimg_path).arg("-f").arg(path).arg("-t").arg("txt").arg("-i").arg(&first)
+            .assert().success();
+        Command::cargo_bin("a2kit")?
+            .arg("append").arg("-d").arg(&dimg_path).arg("-f").arg(path).arg("-t").arg("txt").arg("-i").arg(&second)
+            .assert().success();
+        Command::cargo_bin("a2kit")?
+            .arg("get").arg("-d").arg(&dimg_path).arg("-f").arg(path).arg("-t").arg("txt")
+            .assert().success().stdout("FIRST LINE\nSECOND LINE\n");
+        Command::cargo_bin("a2kit")?
+            .arg("truncate").arg("-d").arg(&dimg_path).arg("-f").arg(path).arg("--len").arg("11")
+            .assert().success();
+        Command::cargo_bin("a2kit")?
+            .arg("get").arg("-d").arg(&dimg_path).arg("-f").arg(path).arg("-t").arg("txt")
+            .assert().success().stdout("FIRST LINE\n");
+        Command::cargo_bin("a2kit")?
+            .arg("truncate").arg("-d").arg(&dimg_path).arg("-f").arg(path).arg("--len").arg("12")
+            .assert().failure();
+    }
+    // not supported for CP/M
+    let dimg_path = dir.path().join("cpm-smallfiles.dsk");
+    std::fs::copy(Path::new("tests").join("cpm-smallfiles.dsk"),&dimg_path)?;
+    Command::cargo_bin("a2kit")?
+        .arg("append").arg("-d").arg(&dimg_path).arg("-f").arg("NOTHING.TXT").arg("-i").arg(&second)
+        .assert().failure();
+    Ok(())
+}
+
+#[test]
+fn patch_file() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg_path = dir.path().join("msdos-ren-del.img");
+    std::fs::copy(Path::new("tests").join("msdos-ren-del.img"),&dimg_path)?;
+    let bytes = dir.path().join("bytes.bin");
+    std::fs::write(&bytes,"XYZ")?;
+    let orig = Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&dimg_path).arg("-f").arg("DIR1/ASCEND.TXT").arg("-t").arg("raw")
+        .assert().success().get_output().stdout.clone();
+    Command::cargo_bin("a2kit")?
+        .arg("patch").arg("-d").arg(&dimg_path).arg("-f").arg("DIR1/ASCEND.TXT").arg("-o").arg("$200").arg("-i").arg(&bytes)
+        .assert().success();
+    let mut expected = orig.clone();
+    expected[0x200..0x203].copy_from_slice(b"XYZ");
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&dimg_path).arg("-f").arg("DIR1/ASCEND.TXT").arg("-t").arg("raw")
+        .assert().success().stdout(expected);
+    Command::cargo_bin("a2kit")?
+        .arg("patch").arg("-d").arg(&dimg_path).arg("-f").arg("DIR1/ASCEND.TXT").arg("-o").arg((orig.len()+1).to_string()).arg("-i").arg(&bytes)
+        .assert().failure();
+    Ok(())
+}
+
+#[test]
+fn sector_addressing() -> STDRESULT {
+    let get_sec = |img: &str,sec: &str,addressing: &str| -> Result<Vec<u8>,Box<dyn std::error::Error>> {
+        Ok(Command::cargo_bin("a2kit")?
+            .arg("get").arg("-d").arg(Path::new("tests").join(img)).arg("-t").arg("sec").arg("-f").arg(sec).arg("--addressing").arg(addressing)
+            .assert().success().get_output().stdout.clone())
+    };
+    // DOS logical sector 14 is physical sector 2
+    assert_eq!(get_sec("dos33-ren-del.do","17,0,14","dos")?,get_sec("dos33-ren-del.do","17,0,2","physical")?);
+    assert_ne!(get_sec("dos33-ren-del.do","17,0,14","dos")?,get_sec("dos33-ren-del.do","17,0,14","physical")?);
+    // ProDOS logical sectors 4 and 5 make block 2, which are physical 8 and 10
+    let blk = Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(Path::new("tests").join("prodos-ren-del.dsk")).arg("-t").arg("block").arg("-f").arg("2")
+        .assert().success().get_output().stdout.clone();
+    assert_eq!(get_sec("prodos-ren-del.dsk","0,0,4..6","prodos")?,blk);
+    let mut phys = get_sec("prodos-ren-del.dsk","0,0,8","physical")?;
+    phys.append(&mut get_sec("prodos-ren-del.dsk","0,0,10","physical")?);
+    assert_eq!(phys,blk);
+    // no logical order for a 13 sector disk
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(Path::new("tests").join("dos32-ren-del.woz")).arg("-t").arg("sec").arg("-f").arg("0,0,0").arg("--addressing").arg("prodos")
+        .assert().failure();
+    Ok(())
+}
+
+#[test]
+fn boot_tracks() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg_path = dir.path().join("cpm-ren-del.dsk");
+    std::fs::copy(Path::new("tests").join("cpm-ren-del.dsk"),&dimg_path)?;
+    let boot = dir.path().join("boot.bin");
+    let dat = Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(Path::new("tests").join("cpm-smallfiles.dsk")).arg("-t").arg("boot")
+        .assert().success().get_output().stdout.clone();
+    assert_eq!(dat.len(),12288);
+    std::fs::write(&boot,&dat)?;
+    Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&dimg_path).arg("-t").arg("boot").arg("-i").arg(&boot)
+        .assert().success();
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&dimg_path).arg("-t").arg("boot")
+        .assert().success().stdout(dat);
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(Path::new("tests").join("dos33-ren-del.do")).arg("-t").arg("boot")
+        .assert().failure();
+    Ok(())
+}
+
+#[test]
+fn banner() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg_path = dir.path().join("prodos-blank.po");
+    std::fs::copy(Path::new("tests").join("prodos-blank.po"),&dimg_path)?;
+    Command::cargo_bin("a2kit")?
+        .arg("banner").arg("-d").arg(&dimg_path).arg("-m").arg("CRACKED BY NOBODY")
+        .assert().success().stderr(predicate::str::contains("ProDOS block"));
+    let dimg_path = dir.path().join("dos33-ren-del.do");
+    std::fs::copy(Path::new("tests").join("dos33-ren-del.do"),&dimg_path)?;
+    let before = std::fs::read(&dimg_path)?;
+    Command::cargo_bin("a2kit")?
+        .arg("banner").arg("-d").arg(&dimg_path).arg("-m").arg("CRACKED BY NOBODY")
+        .assert().failure();
+    assert_eq!(std::fs::read(&dimg_path)?,before);
+    Ok(())
+}
+
+#[test]
+fn quirks_profile() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let profile = dir.path().join("quirks.json");
+    std::fs::write(&profile,r#"{"prodos":{"swap_index_halves":false},"fat":{"root_entries":64}}"#)?;
+    // FAT root directory
+    let fat_img = dir.path().join("new.img");
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk").arg("-d").arg(&fat_img).arg("-t").arg("img").arg("-o").arg("fat").arg("-k").arg("5.25in-ibm-dsdd9").arg("--quirks").arg(&profile)
+        .assert().success();
+    Command::cargo_bin("a2kit")?
+        .arg("stat").arg("-d").arg(&fat_img).arg("--level").arg("deep")
+        .assert().success().stdout(predicate::str::contains("\"root_ent_cnt\":64"));
+    // ProDOS index block is left alone on delete
+    let po_img = dir.path().join("new.po");
+    let dat = dir.path().join("dat.bin");
+    std::fs::write(&dat,vec![0xaa;1000])?;
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk").arg("-d").arg(&po_img).arg("-t").arg("po").arg("-o").arg("prodos").arg("-v").arg("new")
+        .assert().success();
+    Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&po_img).arg("-f").arg("dat").arg("-t").arg("bin").arg("-a").arg("768").arg("-i").arg(&dat)
+        .assert().success();
+    let index = Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&po_img).arg("-t").arg("block").arg("-f").arg("8")
+        .assert().success().get_output().stdout.clone();
+    assert_eq!(index[0..2],[7,9]);
+    Command::cargo_bin("a2kit")?
+        .arg("delete").arg("-d").arg(&po_img).arg("-f").arg("dat").arg("--quirks").arg(&profile)
+        .assert().success();
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&po_img).arg("-t").arg("block").arg("-f").arg("8")
+        .assert().success().stdout(index);
+    // DOS 3.3 takes sectors from the bottom of the track
+    std::fs::write(&profile,r#"{"dos3x":{"sector_order":"ascending"}}"#)?;
+    let do_img = dir.path().join("new.do");
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk").arg("-d").arg(&do_img).arg("-t").arg("do").arg("-o").arg("dos33").arg("-v").arg("254")
+        .assert().success();
+    Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&do_img).arg("-f").arg("DAT").arg("-t").arg("bin").arg("-a").arg("768").arg("-i").arg(&dat).arg("--quirks").arg(&profile)
+        .assert().success();
+    let sec = Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&do_img).arg("-t").arg("sec").arg("-f").arg("18,0,1").arg("--addressing").arg("dos")
+        .assert().success().get_output().stdout.clone();
+    assert_eq!(sec[0..4],[0x00,0x03,0xe8,0x03]);
+    // unknown switches are an error
+    std::fs::write(&profile,r#"{"prodos":{"swap_index":false}}"#)?;
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&po_img).arg("--quirks").arg(&profile)
+        .assert().failure();
+    Ok(())
+}
+
+#[test]
+fn cache_stats() -> STDRESULT {
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg("tests/dos33-bigfiles.woz").arg("--cache-stats")
+        .assert().success().stderr(predicate::str::contains("sector cache:").and(predicate::str::contains("0 evictions")));
+    // images that store sectors have no cache to report
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg("tests/dos33-bigfiles.do").arg("--cache-stats")
+        .assert().success().stderr(predicate::str::contains("sector cache:").not());
+    Ok(())
+}
+
+#[test]
+fn lenient_cpm() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg_path = dir.path().join("cpm-damaged.dsk");
+    let mut dat = std::fs::read(Path::new("tests").join("cpm-smallfiles.dsk"))?;
+    // give the first extent an impossible user number
+    dat[0x3000] = 0x40;
+    std::fs::write(&dimg_path,&dat)?;
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&dimg_path)
+        .assert().failure();
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&dimg_path).arg("--lenient")
+        .assert().success().stdout(predicate::str::contains("POLARIS  TXT").and(predicate::str::contains("BAK").not()));
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&dimg_path).arg("-f").arg("POLARIS.TXT").arg("-t").arg("txt").arg("--lenient")
+        .assert().success();
+    // mostly damaged directories are still rejected
+    dat[0x3020] = 0x40;
+    std::fs::write(&dimg_path,&dat)?;
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&dimg_path).arg("--lenient")
+        .assert().failure();
+    Ok(())
+}
+
+#[test]
+fn fsck_dos33() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg = dir.path().join("dos33.do");
+    let mut dat = std::fs::read(Path::new("tests").join("dos33-bigfiles.do"))?;
+    // VTOC bitmap: mark 8 free sectors of track 34 used, 8 used sectors of track 20 free,
+    // and free part of the DOS tracks, which is left alone
+    dat[0x11000 + 0x38 + 34*4] = 0x00;
+    dat[0x11000 + 0x38 + 20*4] = 0xff;
+    dat[0x11000 + 0x38 + 4] = 0xff;
+    std::fs::write(&dimg,&dat)?;
+    Command::cargo_bin("a2kit")?
+        .arg("fsck").arg("-d").arg(&dimg)
+        .assert().success()
+        .stdout(predicate::str::contains("DOS track 34 sector 15 is marked used but not used")
+            .and(predicate::str::contains("DOS track 20 sector 8 is used but marked free"))
+            .and(predicate::str::contains("16 differences")));
+    Command::cargo_bin("a2kit")?
+        .arg("fsck").arg("-d").arg(&dimg).arg("--repair")
+        .assert().success()
+        .stdout(predicate::str::contains("repaired 16 differences, 0 remain"));
+    Command::cargo_bin("a2kit")?
+        .arg("fsck").arg("-d").arg(&dimg)
+        .assert().success()
+        .stdout("0 differences\n");
+    let fixed = std::fs::read(&dimg)?;
+    let original = std::fs::read(Path::new("tests").join("dos33-bigfiles.do"))?;
+    assert_eq!(fixed[0x11000 + 0x38 + 20*4],original[0x11000 + 0x38 + 20*4]);
+    assert_eq!(fixed[0x11000 + 0x38 + 34*4],original[0x11000 + 0x38 + 34*4]);
+    // other file systems are not supported
+    Command::cargo_bin("a2kit")?
+        .arg("fsck").arg("-d").arg(Path::new("tests").join("cpm-smallfiles.dsk"))
+        .assert().failure();
+    Ok(())
+}
+
+#[test]
+fn fix_bitmap_unsupported() -> STDRESULT {
+    // file systems without bitmap repair are saved as they are
+    let dir = tempfile::tempdir()?;
+    let dimg = dir.path().join("cpm.dsk");
+    std::fs::copy(Path::new("tests").join("cpm-smallfiles.dsk"),&dimg)?;
+    assert_cmd::Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&dimg).arg("-t").arg("txt").arg("-f").arg("NEW.TXT").arg("--fix-bitmap")
+        .write_stdin("HELLO")
+        .assert().success()
+        .stderr(predicate::str::contains("no bitmap repair"));
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&dimg).arg("-t").arg("txt").arg("-f").arg("NEW.TXT")
+        .assert().success()
+        .stdout(predicate::str::contains("HELLO"));
+    Ok(())
+}
+
+#[test]
+fn save_as_convert() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let src = dir.path().join("src.do");
+    let woz = dir.path().join("out.woz");
+    let txt = dir.path().join("hello.txt");
+    std::fs::copy(Path::new("tests").join("dos33-bigfiles.do"),&src)?;
+    std::fs::write(&txt,"HELLO\n")?;
+    Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&src).arg("-f").arg("GREETING").arg("-t").arg("txt").arg("-i").arg(&txt)
+        .arg("--save-as").arg(&woz)
+        .assert().success();
+    // the source is left alone
+    assert_eq!(std::fs::read(&src)?,std::fs::read(Path::new("tests").join("dos33-bigfiles.do"))?);
+    assert_eq!(&std::fs::read(&woz)?[0..4],b"WOZ2");
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&woz).arg("-f").arg("GREETING").arg("-t").arg("txt")
+        .assert().success().stdout("HELLO\n");
+    // a 13 sector image cannot hold a ProDOS disk
+    Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(Path::new("tests").join("prodos-blank.po")).arg("-f").arg("GREETING").arg("-t").arg("txt").arg("-i").arg(&txt)
+        .arg("--save-as").arg(dir.path().join("out.d13"))
+        .assert().failure().stderr(predicate::str::contains("cannot be saved as d13"));
+    assert!(!dir.path().join("out.d13").exists());
+    Ok(())
+}
+
+#[test]
+fn output_files() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let img = Path::new("tests").join("dos33-bigfiles.do");
+    // binary data is written as is
+    let sec_path = dir.path().join("vtoc.bin");
+    let piped = Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&img).arg("-t").arg("sec").arg("-f").arg("17,0,0")
+        .assert().success().get_output().stdout.clone();
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&img).arg("-t").arg("sec").arg("-f").arg("17,0,0").arg("-o").arg(&sec_path)
+        .assert().success().stdout("");
+    assert_eq!(std::fs::read(&sec_path)?,piped);
+    let bin_path = dir.path().join("tree2.bin");
+    let piped = Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&img).arg("-t").arg("bin").arg("-f").arg("SAPLING")
+        .assert().success().get_output().stdout.clone();
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&img).arg("-t").arg("bin").arg("-f").arg("SAPLING").arg("--output").arg(&bin_path)
+        .assert().success().stdout("");
+    assert_eq!(std::fs::read(&bin_path)?,piped);
+    // listings and metadata are the same as on stdout
+    for args in [vec!["catalog"],vec!["catalog","--generic"],vec!["tree","--indent","2"],vec!["get","-t","meta"],vec!["get","-t","meta","-f","/do"]] {
+        let list_path = dir.path().join("list.txt");
+        let piped = Command::cargo_bin("a2kit")?
+            .args(&args).arg("-d").arg(&img)
+            .assert().success().get_output().stdout.clone();
+        Command::cargo_bin("a2kit")?
+            .args(&args).arg("-d").arg(&img).arg("-o").arg(&list_path)
+            .assert().success().stdout("");
+        assert_eq!(std::fs::read(&list_path)?,piped);
+    }
+    Ok(())
+}
+
+#[test]
+fn catalog_plain() -> STDRESULT {
+    for img in ["dos33-bigfiles.do","prodos-bigfiles.woz","msdos-ren-del.img","cpm-smallfiles.dsk","pascal-smallfiles.do"] {
+        let out = Command::cargo_bin("a2kit")?
+            .arg("catalog").arg("--plain").arg("-d").arg(Path::new("tests").join(img))
+            .assert().success().get_output().stdout.clone();
+        let out = String::from_utf8(out)?;
+        let mut lines = out.lines();
+        assert_eq!(lines.next(),Some("NAME\tTYPE\tBLOCKS\tMODIFIED"));
+        for line in lines {
+            let fields: Vec<&str> = line.split('\t').collect();
+            assert_eq!(fields.len(),4,"{} has row {}",img,line);
+            assert!(usize::from_str_radix(fields[2],10).is_ok());
+        }
+    }
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("--plain").arg("-d").arg(Path::new("tests").join("cpm-smallfiles.dsk"))
+        .assert().success().stdout(predicate::str::contains("POLARIS\tTXT\t1\t-"));
+    Ok(())
+}
+
+#[test]
+fn catalog_width() -> STDRESULT {
+    // a narrow terminal gets fewer columns in the wide format
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(Path::new("tests").join("msdos-ren-del.img")).arg("-f").arg("/ /w")
+        .env("COLUMNS","40")
+        .assert().success().stdout(predicate::str::is_match(r"DSKBLD\s+BAT\s+DSKBLD\s+BAS\s*\n").expect("regex error"));
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(Path::new("tests").join("msdos-ren-del.img")).arg("-f").arg("/ /w")
+        .env("COLUMNS","80")
+        .assert().success().stdout(predicate::str::is_match(r"DSKBLD\s+BAT\s+DSKBLD\s+BAS\s+DIR1\s+DIR3").expect("regex error"));
+    Ok(())
+}
+
+#[test]
+fn fat_codepage() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("intl.img");
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk").arg("-d").arg(&path).arg("-t").arg("img").arg("-o").arg("fat").arg("-k").arg("3.5in-ibm-720").arg("-v").arg("TEST")
+        .assert().success();
+    // o-slash is in CP850 but not CP437
+    assert_cmd::Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&path).arg("-f").arg("søren.txt").arg("-t").arg("txt")
+        .write_stdin("HEJ\n")
+        .assert().failure();
+    assert_cmd::Command::cargo_bin("a2kit")?
+        .arg("--codepage").arg("850").arg("put").arg("-d").arg(&path).arg("-f").arg("søren.txt").arg("-t").arg("txt")
+        .write_stdin("HEJ\n")
+        .assert().success();
+    assert_cmd::Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&path).arg("-f").arg("café.txt").arg("-t").arg("txt")
+        .write_stdin("BONJOUR\n")
+        .assert().success();
+    let img = std::fs::read(&path)?;
+    assert!(img.windows(11).any(|w| w==b"S\x9dREN   TXT"));
+    assert!(img.windows(11).any(|w| w==b"CAF\x90    TXT"));
+    Command::cargo_bin("a2kit")?
+        .arg("--codepage").arg("850").arg("catalog").arg("-d").arg(&path)
+        .assert().success().stdout(predicate::str::contains("SØREN").and(predicate::str::contains("CAFÉ")));
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&path)
+        .assert().success().stdout(predicate::str::contains("S¥REN"));
+    Command::cargo_bin("a2kit")?
+        .arg("get").arg("-d").arg(&path).arg("-f").arg("café.txt").arg("-t").arg("txt")
+        .assert().success().stdout("BONJOUR\n");
+    Ok(())
+}
+
+#[test]
+fn prodos_extended_year() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let prodos = dir.path().join("prodos.po");
+    std::fs::copy(Path::new("tests").join("prodos-blank.po"),&prodos)?;
+    assert_cmd::Command::cargo_bin("a2kit")?
+        .arg("put").arg("-d").arg(&prodos).arg("-f").arg("new").arg("-t").arg("txt")
+        .arg("--time").arg("2005-06-07T00:00:00+00:00")
+        .write_stdin("HELLO\n")
+        .assert()
+        .success();
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&prodos)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("NEW             TXT       1 07-Jun-05 00:00  07-Jun-05 00:00"));
+    // year 5 becomes 105, the way GS/OS extended the year, should decode the same
+    let mut img = std::fs::read(&prodos)?;
+    let mut count = 0;
+    for i in 0..img.len()-4 {
+        if img[i..i+4]==[0xc7,0x0a,0,0] {
+            img[i+1] = 0xd2;
+            count += 1;
+        }
+    }
+    assert_eq!(count,2);
+    std::fs::write(&prodos,&img)?;
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&prodos)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("NEW             TXT       1 07-Jun-05 00:00  07-Jun-05 00:00"));
+    Ok(())
+}
+
+#[test]
+fn verify_binary() -> STDRESULT {
+    // LDA #0 ; BEQ $0808 ; JSR $0809 ; RTS ; RTS ; JSR MLI with inline parameters ; RTS
+    let good: Vec<u8> = vec![0xa9,0x00,0xf0,0x04,0x20,0x09,0x08,0x60,0x60,0x20,0x00,0xbf,0xc8,0x00,0x00,0x60];
+    assert_cmd::Command::cargo_bin("a2kit")?
+        .arg("verify").arg("-t").arg("bin").arg("--org").arg("2048")
+        .write_stdin(good.clone())
+        .assert().success().stderr(predicate::str::contains("7 instructions").and(predicate::str::contains("Passing")));
+    // the branch lands on an illegal opcode, and the subroutine runs off the end
+    let mut bad = good.clone();
+    bad[8] = 0x02;
+    bad[15] = 0xea;
+    assert_cmd::Command::cargo_bin("a2kit")?
+        .arg("verify").arg("-t").arg("bin").arg("--org").arg("$800").arg("--entry").arg("$800,$809")
+        .write_stdin(bad.clone())
+        .assert().failure()
+        .stderr(predicate::str::contains("illegal opcode $02 at $0808").and(predicate::str::contains("path leaves the binary at $0810")));
+    // the illegal opcode cannot be reached from the second entry point
+    bad[15] = 0x60;
+    assert_cmd::Command::cargo_bin("a2kit")?
+        .arg("verify").arg("-t").arg("bin").arg("--org").arg("$800").arg("--entry").arg("$809")
+        .write_stdin(bad)
+        .assert().success();
+    Ok(())
+}
+
+#[test]
+fn convert_lang() -> STDRESULT {
+    let applesoft = "10 HOME: INPUT \"NAME\";N$\n20 IF N$ = \"\" THEN PRINT \"NONE\": GOTO 10\n30 A$ = MID$(N$,2,3) + \"X\": R = INT(RND(1)*6): POKE 49168,0\n";
+    let integer = "0 DIM A$(255),N$(255)\n10 CALL -936: INPUT \"NAME\",N$\n20 IF NOT(N$ = \"\") THEN 30: PRINT \"NONE\": GOTO 10\n30 A$ = N$(2,4): A$(LEN(A$) + 1) = \"X\": R = RND(6): POKE -16368,0\n";
+    assert_cmd::Command::cargo_bin("a2kit")?
+        .arg("convert-lang").arg("-t").arg("atxt")
+        .write_stdin(applesoft)
+        .assert().success().stdout(integer);
+    let integer = "10 DIM A$(20),B(5)\n20 A$ = \"HELLO\": A$(LEN(A$)+1) = \"!\": PRINT A$(2,3): B(1) = 7 MOD 2\n";
+    let applesoft = "10 DIM B(5)\n20 A$ = \"HELLO\": A$ = A$ + \"!\": PRINT MID$(A$,2,2): B(1) = (7 - INT(7 / 2) * 2)\n";
+    assert_cmd::Command::cargo_bin("a2kit")?
+        .arg("convert-lang").arg("-t").arg("itxt")
+        .write_stdin(integer)
+        .assert().success().stdout(applesoft);
+    // untranslatable statements are kept and flagged
+    assert_cmd::Command::cargo_bin("a2kit")?
+        .arg("convert-lang").arg("-t").arg("atxt")
+        .write_stdin("10 X = SQR(2)\n")
+        .assert().failure()
+        .stdout("10 X = SQR(2)\n")
+        .stderr(predicate::str::contains("SQR has no Integer BASIC equivalent"));
+    Ok(())
+}
+
+#[test]
+fn tree_shaping() -> STDRESULT {
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    cmd.arg("tree").arg("-d").arg(Path::new("tests").join("msdos-ren-del.img"))
+        .arg("--paths").arg("--du")
+        .assert().success()
+        .stdout(predicate::str::starts_with("32\t/DIR1/\n14\t/DIR1/ASCEND.TXT\n"))
+        .stdout(predicate::str::contains("16\t/DIR1/SUBDIR1/\n"))
+        .stdout(predicate::str::ends_with("1\t/DSKBLD.BAT\n49\t/\n"));
+    let mut cmd = Command::cargo_bin("a2kit")?;
+    let output = cmd.arg("tree").arg("-d").arg(Path::new("tests").join("msdos-ren-del.img"))
+        .arg("--depth").arg("1").arg("--du")
+        .assert().success().get_output().stdout.clone();
+    let tree = json::parse(&String::from_utf8(output)?)?;
+    assert_eq!(tree["du"]["files"],7);
+    assert_eq!(tree["files"]["DIR1"]["du"]["files"],4);
+    assert_eq!(tree["files"]["DIR1"]["pruned"],true);
+    assert_eq!(tree["files"]["DIR1"]["files"].len(),0);
+    assert!(!tree["files"]["DSKBLD.BAS"].has_key("meta"));
+    Ok(())
+}
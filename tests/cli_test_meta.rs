@@ -207,3 +207,43 @@ fn put_get_meta_woz2_filtered() -> STDRESULT {
 
     Ok(())
 }
+
+#[test]
+fn put_meta_woz2_validation() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg_path = dir.path().join("woz2.woz");
+    Command::cargo_bin("a2kit")?.arg("mkdsk")
+        .arg("-v").arg("254").arg("-t").arg("woz2").arg("-o").arg("dos33")
+        .arg("-d").arg(&dimg_path)
+        .assert()
+        .success();
+    let bad_items = [
+        "{\"woz2\":{\"info\":{\"optimal_bit_timing\":\"08\"}}}",
+        "{\"woz2\":{\"info\":{\"required_ram\":\"40\"}}}",
+        "{\"woz2\":{\"info\":{\"creator\":\"a creator that is longer than 32 bytes\"}}}",
+        "{\"woz2\":{\"meta\":{\"image_date\":\"yesterday\"}}}",
+        "{\"woz2\":{\"meta\":{\"requires_machine\":\"2e|2x\"}}}"
+    ];
+    for items in bad_items {
+        assert_cmd::Command::cargo_bin("a2kit")?.arg("put")
+            .arg("-t").arg("meta").arg("-d").arg(&dimg_path)
+            .write_stdin(items)
+            .assert()
+            .failure();
+    }
+    // read-only items are skipped
+    assert_cmd::Command::cargo_bin("a2kit")?.arg("put")
+        .arg("-t").arg("meta").arg("-d").arg(&dimg_path)
+        .write_stdin("{\"woz2\":{\"info\":{\"creator\":\"Imaging Station\",\"optimal_bit_timing\":\"1f\",\"largest_flux_track\":\"0100\"},\"meta\":{\"image_date\":\"2024-03-01T12:00:00Z\"}}}")
+        .assert()
+        .success();
+    let output = Command::cargo_bin("a2kit")?.arg("get")
+        .arg("-t").arg("meta").arg("-d").arg(&dimg_path)
+        .output()?;
+    let meta = json::parse(&String::from_utf8(output.stdout)?)?;
+    assert_eq!(meta["woz2"]["info"]["creator"],"Imaging Station");
+    assert_eq!(meta["woz2"]["info"]["optimal_bit_timing"],"1f");
+    assert!(meta["woz2"]["info"]["largest_flux_track"].is_null());
+    assert_eq!(meta["woz2"]["meta"]["image_date"],"2024-03-01T12:00:00Z");
+    Ok(())
+}
@@ -17,6 +17,39 @@ fn mk_dos33_do() -> STDRESULT {
     Ok(())
 }
 
+#[test]
+fn mk_dos33_tracks() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg_path = dir.path().join("dos33.do");
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk")
+        .arg("-v").arg("254").arg("-t").arg("do").arg("-o").arg("dos33").arg("--tracks").arg("40").arg("-b")
+        .arg("-d").arg(&dimg_path)
+        .assert()
+        .success();
+    assert_eq!(std::fs::metadata(&dimg_path)?.len(),40*16*256);
+    Command::cargo_bin("a2kit")?
+        .arg("stat").arg("-d").arg(&dimg_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"free_blocks\":576"));
+    let dimg_path = dir.path().join("dos33boot80.do");
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk")
+        .arg("-v").arg("254").arg("-t").arg("do").arg("-o").arg("dos33").arg("--tracks").arg("80").arg("-b")
+        .arg("-d").arg(&dimg_path)
+        .assert()
+        .failure();
+    let dimg_path = dir.path().join("dos33.woz");
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk")
+        .arg("-v").arg("254").arg("-t").arg("woz2").arg("-o").arg("dos33").arg("--tracks").arg("40")
+        .arg("-d").arg(&dimg_path)
+        .assert()
+        .failure();
+    Ok(())
+}
+
 #[test]
 fn mk_dos33_bad_ext() -> STDRESULT {
     let mut cmd = Command::cargo_bin("a2kit")?;
@@ -193,3 +226,227 @@ fn mk_fat_img() -> STDRESULT {
         .success();
     Ok(())
 }
+
+#[test]
+fn mk_fat_high_density() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    for (kind,typ,free) in [
+        ("5.25in-ibm-dshd","img","1210880 bytes free"),
+        ("5.25in-ibm-dshd","imd","1210880 bytes free"),
+        ("5.25in-ibm-dshd","td0","1210880 bytes free"),
+        ("3.5in-ibm-2880","img","2928640 bytes free")
+    ] {
+        let dimg_path = dir.path().join([kind,typ].join("."));
+        Command::cargo_bin("a2kit")?.arg("mkdsk")
+            .arg("-t").arg(typ).arg("-o").arg("fat")
+            .arg("-k").arg(kind)
+            .arg("-d").arg(&dimg_path)
+            .assert()
+            .success();
+        assert_cmd::Command::cargo_bin("a2kit")?.arg("put")
+            .arg("-t").arg("bin").arg("-f").arg("test.bin")
+            .arg("-d").arg(&dimg_path)
+            .write_stdin(vec![0xa5;3000])
+            .assert()
+            .success();
+        Command::cargo_bin("a2kit")?.arg("catalog")
+            .arg("-d").arg(&dimg_path)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(free));
+        Command::cargo_bin("a2kit")?.arg("get")
+            .arg("-t").arg("bin").arg("-f").arg("test.bin")
+            .arg("-d").arg(&dimg_path)
+            .assert()
+            .success()
+            .stdout(predicate::eq(vec![0xa5;3000]));
+    }
+    Ok(())
+}
+
+#[test]
+fn mk_fat_2880_needs_img() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    for typ in ["imd","td0"] {
+        Command::cargo_bin("a2kit")?.arg("mkdsk")
+            .arg("-t").arg(typ).arg("-o").arg("fat")
+            .arg("-k").arg("3.5in-ibm-2880")
+            .arg("-d").arg(dir.path().join(["fat2880",typ].join(".")))
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("data rate"));
+    }
+    Ok(())
+}
+
+#[test]
+fn mk_cpm_a2_bootable() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let sys_path = dir.path().join("cpm.sys");
+    let sys: Vec<u8> = (0..3*16*256).map(|i| ((i/256) ^ (i%256)) as u8).collect();
+    std::fs::write(&sys_path,&sys)?;
+    for (typ,fname) in [("do","cpm.do"),("woz1","cpm1.woz"),("woz2","cpm2.woz")] {
+        let dimg_path = dir.path().join(fname);
+        Command::cargo_bin("a2kit")?
+            .arg("mkdsk")
+            .arg("-t").arg(typ).arg("-o").arg("cpm2").arg("-b").arg("--system").arg(&sys_path)
+            .arg("-d").arg(&dimg_path)
+            .assert()
+            .success();
+        // reserved tracks are in DOS order regardless of image type
+        let mut img = a2kit::create_img_from_file(&dimg_path.to_string_lossy())?;
+        for track in 0..3 {
+            for sector in 0..16 {
+                let offset = (track*16 + sector)*256;
+                assert_eq!(img.read_block(a2kit::fs::Block::DO([track,sector]))?,sys[offset..offset+256].to_vec());
+            }
+        }
+        // directory starts at track 3, CP/M records are mapped through the Softcard skew
+        assert_cmd::Command::cargo_bin("a2kit")?
+            .arg("put").arg("-f").arg("HELLO.TXT").arg("-t").arg("txt").arg("-d").arg(&dimg_path)
+            .write_stdin("HELLO")
+            .assert()
+            .success();
+        let mut img = a2kit::create_img_from_file(&dimg_path.to_string_lossy())?;
+        let dir_sec = img.read_block(a2kit::fs::Block::DO([3,0]))?;
+        assert_eq!(&dir_sec[1..9],"HELLO   ".as_bytes());
+        Command::cargo_bin("a2kit")?
+            .arg("get").arg("-f").arg("HELLO.TXT").arg("-t").arg("txt").arg("-d").arg(&dimg_path)
+            .assert()
+            .success()
+            .stdout(predicate::str::starts_with("HELLO"));
+    }
+    let dimg_path = dir.path().join("noboot.do");
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk")
+        .arg("-t").arg("do").arg("-o").arg("cpm2").arg("-b")
+        .arg("-d").arg(&dimg_path)
+        .assert()
+        .failure();
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk")
+        .arg("-t").arg("do").arg("-o").arg("dos33").arg("-v").arg("254").arg("--system").arg(&sys_path)
+        .arg("-d").arg(&dimg_path)
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn mk_kaypro_osborne() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    for (kind,dsm,off) in [("kaypro2","C200","0100"),("kaypro4","C400","0100"),("osborne1","2D00","0300"),("osborne1-dd","B800","0300")] {
+        for typ in ["imd","td0"] {
+            let dimg_path = dir.path().join([kind,".",typ].concat());
+            Command::cargo_bin("a2kit")?
+                .arg("mkdsk")
+                .arg("-t").arg(typ).arg("-o").arg("cpm2").arg("-k").arg(kind)
+                .arg("-d").arg(&dimg_path)
+                .assert()
+                .success();
+            Command::cargo_bin("a2kit")?
+                .arg("stat").arg("-d").arg(&dimg_path)
+                .assert()
+                .success()
+                .stdout(predicate::str::contains(["\"dsm\":\"",dsm,"\""].concat()))
+                .stdout(predicate::str::contains(["\"off\":\"",off,"\""].concat()));
+            // directory is initialized with the deleted file mark
+            let dir_block = Command::cargo_bin("a2kit")?
+                .arg("get").arg("-t").arg("block").arg("-f").arg("0").arg("-d").arg(&dimg_path)
+                .output()?.stdout;
+            assert!(dir_block.len() >= 1024);
+            assert!(dir_block.iter().all(|b| *b==0xe5));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn mk_templates() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg_path = dir.path().join("applesoft.do");
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk").arg("-t").arg("do").arg("-o").arg("dos33").arg("--template").arg("applesoft-boot")
+        .arg("-d").arg(&dimg_path)
+        .assert()
+        .success();
+    let mut disk = a2kit::create_fs_from_file(&dimg_path.to_string_lossy())?;
+    let (_,tok) = disk.load("HELLO")?;
+    let mut tokenizer = a2kit::lang::applesoft::tokenizer::Tokenizer::new();
+    assert!(tokenizer.detokenize(&tok)?.contains("HELLO FROM A2KIT"));
+    // boot tracks are there
+    assert_ne!(disk.get_img().read_block(a2kit::fs::Block::DO([0,0]))?,vec![0;256]);
+
+    let dimg_path = dir.path().join("merlin.woz");
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk").arg("-t").arg("woz2").arg("-o").arg("dos33").arg("--template").arg("merlin-hello")
+        .arg("-d").arg(&dimg_path)
+        .assert()
+        .success();
+    let mut disk = a2kit::create_fs_from_file(&dimg_path.to_string_lossy())?;
+    let (addr,obj) = disk.bload("HELLO.OBJ")?;
+    assert_eq!(addr,0x300);
+    assert_eq!(obj[0..4],[0xa2,0x00,0xbd,0x0e]);
+    assert!(disk.read_text("T.HELLO")?.contains("COUT"));
+    let (_,tok) = disk.load("HELLO")?;
+    assert!(tokenizer.detokenize(&tok)?.contains("BRUN HELLO.OBJ"));
+
+    let dimg_path = dir.path().join("prodos.po");
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk").arg("-t").arg("po").arg("-o").arg("prodos").arg("-v").arg("NEW").arg("--template").arg("applesoft-boot")
+        .arg("-d").arg(&dimg_path)
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn mk_woz_timing() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let dimg_path = dir.path().join("timed.woz");
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk").arg("-t").arg("woz2").arg("-o").arg("dos33").arg("-v").arg("254")
+        .arg("--track-bits").arg("50000").arg("--bit-timing").arg("31")
+        .arg("-d").arg(&dimg_path)
+        .assert()
+        .success();
+    let buf = std::fs::read(&dimg_path)?;
+    // optimal bit timing in INFO, bit count of the first track in TRKS
+    assert_eq!(buf[59],31);
+    assert_eq!(u32::from_le_bytes([buf[260],buf[261],buf[262],buf[263]]),50000);
+    let mut disk = a2kit::create_fs_from_file(&dimg_path.to_string_lossy())?;
+    disk.write_text("HELLO","HELLO FROM A2KIT\n")?;
+    assert_eq!(disk.read_text("HELLO")?,"HELLO FROM A2KIT\n");
+
+    let dimg_path = dir.path().join("timed1.woz");
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk").arg("-t").arg("woz1").arg("-o").arg("prodos").arg("-v").arg("NEW")
+        .arg("--track-bits").arg("51200")
+        .arg("-d").arg(&dimg_path)
+        .assert()
+        .success();
+    let buf = std::fs::read(&dimg_path)?;
+    assert_eq!(u16::from_le_bytes([buf[256+6648],buf[256+6649]]),51200);
+    Command::cargo_bin("a2kit")?
+        .arg("catalog").arg("-d").arg(&dimg_path)
+        .assert()
+        .success();
+    // WOZ1 has no bit timing, 3.5 inch tracks have fixed lengths, and the sectors have to fit
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk").arg("-t").arg("woz1").arg("-o").arg("dos33").arg("-v").arg("254").arg("--bit-timing").arg("31")
+        .arg("-d").arg(dir.path().join("bad1.woz"))
+        .assert()
+        .failure();
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk").arg("-t").arg("woz2").arg("-o").arg("prodos").arg("-v").arg("NEW").arg("-k").arg("3.5in")
+        .arg("--track-bits").arg("51200")
+        .arg("-d").arg(dir.path().join("bad2.woz"))
+        .assert()
+        .failure();
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk").arg("-t").arg("woz2").arg("-o").arg("dos33").arg("-v").arg("254").arg("--track-bits").arg("45000")
+        .arg("-d").arg(dir.path().join("bad3.woz"))
+        .assert()
+        .failure();
+    Ok(())
+}
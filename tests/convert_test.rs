@@ -0,0 +1,111 @@
+// test of round trip image conversion
+use std::path::Path;
+use a2kit::img;
+use a2kit::img::DiskImageType;
+use a2kit::testing;
+
+fn verify(name: &str, typ: DiskImageType) {
+    let path = Path::new("tests").join(name);
+    let ans = testing::verify_convert(path.to_str().unwrap(), typ).expect("conversion failed");
+    assert!(ans.forward.blocks > 0);
+    assert_eq!(ans.forward.mismatches,vec![]);
+    assert_eq!(ans.round_trip.mismatches,vec![]);
+}
+
+#[test]
+fn dos33_do_to_woz() {
+    verify("dos33-smallfiles.dsk", DiskImageType::WOZ2);
+}
+
+#[test]
+fn dos33_woz_to_do() {
+    verify("dos33-bigfiles.woz", DiskImageType::DO);
+}
+
+#[test]
+fn prodos_woz_to_po() {
+    verify("prodos-bigfiles.woz", DiskImageType::PO);
+}
+
+#[test]
+fn pascal_do_to_nib() {
+    verify("pascal-smallfiles.do", DiskImageType::NIB);
+}
+
+#[test]
+fn dos33_nib_to_woz() {
+    let dir = tempfile::tempdir().expect("no temp dir");
+    let nib_path = dir.path().join("dos33.nib");
+    let mut disk = a2kit::create_fs_from_file(Path::new("tests").join("dos33-smallfiles.dsk").to_str().unwrap()).expect("read error");
+    let mut nib = img::convert::convert(&mut disk, DiskImageType::NIB).expect("conversion failed");
+    std::fs::write(&nib_path, nib.to_bytes()).expect("write error");
+    verify(nib_path.to_str().unwrap(), DiskImageType::WOZ2);
+    // NIB sync bytes are 8 bits, they should come back as 10 bits, and the track should fit a revolution
+    let ans = testing::verify_convert(nib_path.to_str().unwrap(), DiskImageType::WOZ2).expect("conversion failed");
+    let mut woz = a2kit::create_img_from_bytestream(&ans.converted, None).expect("could not load WOZ");
+    let track = woz.get_track_buf(17, 0).expect("no track");
+    assert!(track.windows(5).any(|w| w == [0xff,0x3f,0xcf,0xf3,0xfc]));
+    let bit_count = u32::from_le_bytes([ans.converted[256+17*8+4],ans.converted[256+17*8+5],ans.converted[256+17*8+6],ans.converted[256+17*8+7]]);
+    assert!(bit_count > 50000 && bit_count <= 51664);
+}
+
+#[test]
+fn msdos_img_to_imd() {
+    verify("msdos-ren-del.img", DiskImageType::IMD);
+}
+
+#[test]
+fn different_disks() {
+    let mut expected = a2kit::create_fs_from_file(Path::new("tests").join("dos33-smallfiles.dsk").to_str().unwrap()).expect("read error");
+    let mut actual = a2kit::create_fs_from_file(Path::new("tests").join("dos33-ren-del.do").to_str().unwrap()).expect("read error");
+    let ignore = testing::ignorable_offsets(&mut expected).expect("ignore error");
+    let report = testing::compare(&mut expected, &mut actual, &ignore).expect("compare error");
+    assert_eq!(report.blocks,560);
+    assert!(!report.mismatches.is_empty());
+}
+
+#[test]
+fn unsupported_pairing() {
+    let path = Path::new("tests").join("dos33-smallfiles.dsk");
+    assert!(testing::verify_convert(path.to_str().unwrap(), DiskImageType::PO).is_err());
+}
+
+#[test]
+fn prodos_800k_tags() {
+    let dir = tempfile::tempdir().expect("no temp dir");
+    let path = dir.path().join("prodos800.woz");
+    let path_str = path.to_str().unwrap();
+    let buf = a2kit::commands::mkdsk::create(path_str, &DiskImageType::WOZ2, a2kit::img::names::A2_800_KIND,
+        "prodos", Some(&"tagged".to_string()), false, None, &a2kit::LoadOptions::default()).expect("could not create disk");
+    std::fs::write(&path, buf).expect("write error");
+    let tags: Vec<u8> = (1..13).collect();
+    let mut disk = a2kit::create_fs_from_file(path_str).expect("read error");
+    disk.get_img().write_tags(10, 1, 3, &tags).expect("could not write tags");
+    // writing the data keeps the tags
+    disk.get_img().write_sector(10, 1, 3, &[0xa5;512]).expect("could not write sector");
+    assert_eq!(disk.get_img().read_tags(10, 1, 3).expect("could not read tags"),tags);
+    assert_eq!(disk.get_img().read_sector(10, 1, 3).expect("could not read sector"),vec![0xa5;512]);
+    // conversion keeps the tags
+    let mut converted = img::convert::convert(&mut disk, DiskImageType::WOZ2).expect("conversion failed");
+    assert_eq!(converted.read_tags(10, 1, 3).expect("could not read tags"),tags);
+    assert_eq!(converted.read_tags(10, 1, 4).expect("could not read tags"),vec![0;12]);
+    let mut po = img::convert::convert(&mut disk, DiskImageType::PO).expect("conversion failed");
+    assert!(po.read_tags(10, 1, 3).is_err());
+}
+
+#[test]
+fn save_as_only_converts() {
+    let dir = tempfile::tempdir().expect("no temp dir");
+    let original = std::fs::read(Path::new("tests").join("dos33-bigfiles.do")).expect("read error");
+    let mut disk = a2kit::create_fs_from_bytestream(&original, Some("do")).expect("could not get disk");
+    // saving without `save_as` keeps the bytes, whatever the extension
+    let kept = dir.path().join("kept.woz");
+    a2kit::save_img(&mut disk, kept.to_str().unwrap()).expect("save failed");
+    assert_eq!(std::fs::read(&kept).expect("read error"),original);
+    // `save_as` converts
+    let converted = dir.path().join("converted.woz");
+    let opts = a2kit::SaveOptions { save_as: Some(converted.to_str().unwrap().to_string()), ..Default::default() };
+    a2kit::save_img_with(&mut disk, kept.to_str().unwrap(), &opts).expect("save failed");
+    assert_eq!(&std::fs::read(&converted).expect("read error")[0..4],b"WOZ2");
+    assert_eq!(std::fs::read(&kept).expect("read error"),original);
+}
@@ -47,6 +47,18 @@ fn read_small() {
     assert_eq!(&txt,ED_TEST);
 }
 
+#[test]
+fn lenient_small() {
+    let mut img = std::fs::read(&Path::new("tests").join("cpm-smallfiles.dsk")).expect("failed to read test image file");
+    // give the first extent an impossible user number
+    img[0x3000] = 0x40;
+    assert!(a2kit::create_fs_from_bytestream(&img,None).is_err());
+    let opts = a2kit::LoadOptions { lenient: true, ..Default::default() };
+    let mut disk = a2kit::create_fs_from_bytestream_with(&img,None,&opts).expect("lenient load failed");
+    let fimg = disk.get("POLARIS.TXT").expect("error");
+    assert_eq!(&fimg.unpack_txt().expect("bad setup"),ED_TEST);
+}
+
 #[test]
 fn write_small() {
     // Formatting: FORMAT.COM, writing: ED.COM, emulator: Virtual II
@@ -171,3 +183,55 @@ fn rename_delete_dsk() {
     //disk.compare(&Path::new("tests").join("cpm-ren-del.dsk"),&ignore);
 }
 
+
+#[test]
+fn rename_wildcard() {
+    let img = dsk_do::DO::create(35, 16);
+    let mut disk = cpm::Disk::from_img(Box::new(img),DiskParameterBlock::create(&names::A2_DOS33_KIND),[2,2,3]).expect("bad setup");
+    disk.format("test",None).expect("failed to format disk");
+    for name in ["ONE.TXT","TWO.TXT","THREE.BAS"] {
+        disk.write_text(name,"HELLO").expect(RCH);
+    }
+    assert_eq!(disk.rename_wildcard("*.TXT","*.DOC").expect("rename failed"),2);
+    assert_eq!(disk.glob("*",false).expect("glob failed"),vec!["0:ONE.DOC","0:THREE.BAS","0:TWO.DOC"]);
+    // THREE.DOC would replace the existing TWO.DOC
+    assert!(disk.rename_wildcard("T*.*","THREE.DOC").is_err());
+    assert!(disk.rename_wildcard("*.COM","*.BAK").is_err());
+    assert_eq!(disk.rename_wildcard("?WO.*","*.TXT").expect("rename failed"),1);
+    assert_eq!(disk.glob("*.TXT",false).expect("glob failed"),vec!["0:TWO.TXT"]);
+}
+
+#[test]
+fn relabel() {
+    let img = dsk_do::DO::create(35, 16);
+    let mut disk = cpm::Disk::from_img(Box::new(img),DiskParameterBlock::create(&names::A2_DOS33_KIND),[2,2,0]).expect("bad setup");
+    disk.format("",None).expect("failed to format disk");
+    assert!(disk.relabel("test").is_err());
+    let img = dsk_do::DO::create(35, 16);
+    let mut disk = cpm::Disk::from_img(Box::new(img),DiskParameterBlock::create(&names::A2_DOS33_KIND),[3,1,0]).expect("bad setup");
+    disk.format("",None).expect("failed to format disk");
+    disk.write_text("ONE.TXT","HELLO").expect(RCH);
+    assert_eq!(disk.stat().expect("stat failed").label,"");
+    disk.relabel("disk.one").expect("relabel failed");
+    disk.relabel("disk.two").expect("relabel failed");
+    assert_eq!(disk.stat().expect("stat failed").label,"DISK.TWO");
+    assert_eq!(disk.glob("*",false).expect("glob failed"),vec!["0:ONE.TXT"]);
+}
+
+#[test]
+fn boot_tracks() {
+    let img = a2kit::create_img_from_file(&Path::new("tests").join("cpm-smallfiles.dsk").to_str().expect(RCH)).expect("bad image");
+    let mut src = cpm::Disk::from_img(img,DiskParameterBlock::create(&names::A2_DOS33_KIND),[2,2,3]).expect("bad setup");
+    let boot = src.read_boot().expect("read boot failed");
+    assert_eq!(boot.len(),3*32*128);
+    let img = dsk_do::DO::create(35, 16);
+    let mut disk = cpm::Disk::from_img(Box::new(img),DiskParameterBlock::create(&names::A2_DOS33_KIND),[2,2,3]).expect("bad setup");
+    disk.format("test",None).expect("failed to format disk");
+    disk.write_text("POLARIS.TXT",ED_TEST).expect(RCH);
+    assert_eq!(disk.write_boot(&boot).expect("write boot failed"),boot.len());
+    assert_eq!(disk.read_boot().expect("read boot failed"),boot);
+    // the reserved tracks are whole tracks, so the raw bytes match whatever the skew
+    assert_eq!(disk.get_img().to_bytes()[0..3*4096],src.get_img().to_bytes()[0..3*4096]);
+    assert_eq!(disk.read_text("POLARIS.TXT").expect("read failed"),ED_TEST);
+    assert!(disk.write_boot(&vec![0;boot.len()+1]).is_err());
+}
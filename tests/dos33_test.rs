@@ -236,4 +236,333 @@ fn read_big_woz1() {
     ignore_boot_tracks(&mut ignore);
     a2kit::fs::add_ignorable_offsets(&mut ignore, Block::DO([18,12]), vec![243]);
     disk.compare(&Path::new("tests").join("dos33-bigfiles.do"),&ignore);    
-}
\ No newline at end of file
+}
+#[test]
+fn read_flux_woz() {
+    // dos33-flux.woz was derived from dos33-bigfiles.woz by rewriting it as WOZ 2.1 (INFO version 3),
+    // with tracks 0 and 17 moved from TMAP/TRKS to the FLUX chunk.  Each flux track was made from the
+    // original bitstream, one transition per 1 bit, counting 32 ticks of 125 ns per bit cell.
+    // Everything else, including the other tracks and the INFO fields, is unchanged.
+    let mut flux = a2kit::create_img_from_file("tests/dos33-flux.woz").expect("could not get image");
+    let mut bits = a2kit::create_img_from_file("tests/dos33-bigfiles.woz").expect("could not get image");
+    for track in 0..35 {
+        for sector in 0..16 {
+            assert_eq!(flux.read_block(Block::DO([track,sector])).expect("bad read"),
+                bits.read_block(Block::DO([track,sector])).expect("bad read"));
+        }
+    }
+    // FLUX tracks pass through unchanged if they are not written
+    assert_eq!(flux.to_bytes(),std::fs::read("tests/dos33-flux.woz").expect("could not read"));
+}
+
+#[test]
+fn decode_tracks_woz() {
+    // decoding every track up front gives the same sectors as decoding as they are touched
+    let mut ahead = a2kit::create_img_from_file("tests/dos33-bigfiles.woz").expect("could not get image");
+    let mut lazy = a2kit::create_img_from_file("tests/dos33-bigfiles.woz").expect("could not get image");
+    ahead.decode_tracks(&(0..35).collect::<Vec<usize>>());
+    for track in 0..35 {
+        for sector in 0..16 {
+            assert_eq!(ahead.read_sector(track,0,sector).expect("bad read"),
+                lazy.read_sector(track,0,sector).expect("bad read"));
+        }
+    }
+    // writing a track drops what was decoded from it
+    ahead.write_sector(17,0,3,&[0x5a;256]).expect("bad write");
+    assert_eq!(ahead.read_sector(17,0,3).expect("bad read"),vec![0x5a;256]);
+    assert_ne!(lazy.read_sector(17,0,3).expect("bad read"),vec![0x5a;256]);
+}
+
+#[test]
+fn sector_cache() {
+    let mut img = a2kit::create_img_from_file("tests/dos33-bigfiles.woz").expect("could not get image");
+    let first = img.read_sector(17,0,15).expect("bad read");
+    assert_eq!(img.read_sector(17,0,15).expect("bad read"),first);
+    img.read_sector(17,0,14).expect("bad read");
+    let stats = img.cache_stats().expect("no cache");
+    assert_eq!((stats.hits,stats.misses),(2,1));
+    // a write drops the track, the next read decodes it again
+    img.write_sector(17,0,14,&[0;256]).expect("bad write");
+    assert_eq!(img.read_sector(17,0,15).expect("bad read"),first);
+    let stats = img.cache_stats().expect("no cache");
+    assert_eq!((stats.hits,stats.misses,stats.invalidations),(2,2,16));
+    // least recently used sectors go first
+    let mut cache = img::cache::SectorCache::new(2);
+    let kind = img::names::A2_DOS33_KIND;
+    cache.insert(kind,0,0,vec![0],10);
+    cache.insert(kind,0,1,vec![1],20);
+    cache.get(kind,0,0);
+    cache.insert(kind,0,2,vec![2],30);
+    assert_eq!(cache.get(kind,0,1),None);
+    assert_eq!(cache.get(kind,0,0),Some((vec![0],10)));
+    assert_eq!(cache.stats().evictions,1);
+    // sectors with duplicate IDs are never kept
+    cache.mark_volatile(kind,1,3);
+    cache.insert(kind,1,3,vec![3],40);
+    assert_eq!(cache.get(kind,1,3),None);
+    // a track stays decoded after its sectors are evicted, until it changes
+    cache.mark_track(kind,1);
+    cache.insert(kind,1,4,vec![4],50);
+    cache.insert(kind,1,5,vec![5],60);
+    assert!(cache.has_track(kind,1));
+    cache.invalidate_track(1);
+    assert!(!cache.has_track(kind,1));
+}
+
+#[test]
+fn malformed_woz() {
+    // damaged track records give errors rather than a panic
+    let good = std::fs::read("tests/dos33-bigfiles.woz").expect("failed to read test image file");
+    let mut dat = good.clone();
+    dat[264..266].copy_from_slice(&u16::to_le_bytes(0xffff));
+    let mut img = a2kit::create_img_from_bytestream(&dat,Some("woz")).expect("could not get image");
+    assert!(img.read_sector(1,0,0).is_err());
+    img.read_sector(0,0,0).expect("bad read");
+    let mut dat = good.clone();
+    dat[260..264].copy_from_slice(&u32::to_le_bytes(u32::MAX));
+    let mut img = a2kit::create_img_from_bytestream(&dat,Some("woz")).expect("could not get image");
+    assert!(img.read_sector(0,0,0).is_err());
+    let mut dat = good.clone();
+    dat[88..90].copy_from_slice(&[0xf0,0xf0]);
+    let mut img = a2kit::create_img_from_bytestream(&dat,Some("woz")).expect("could not get image");
+    assert!(img.read_sector(0,0,0).is_err());
+    assert!(a2kit::create_img_from_bytestream(&good[0..300].to_vec(),Some("woz")).is_err());
+}
+
+#[test]
+fn write_flux_woz() {
+    let img = a2kit::create_img_from_file("tests/dos33-flux.woz").expect("could not get image");
+    let mut disk = dos3x::Disk::from_img(img).expect("could not get disk");
+    disk.write_text("NEWTEXT","HELLO FROM FLUX").expect("could not write");
+    let buf = disk.get_img().to_bytes();
+    // track 0 is still flux, track 17 was written and became a bitstream
+    assert!(buf.windows(4).any(|w| w==b"FLUX"));
+    let flux_block = u16::from_le_bytes([buf[20+46],buf[20+47]]) as usize;
+    assert_eq!(&buf[flux_block*512..flux_block*512+4],b"FLUX");
+    assert_ne!(buf[flux_block*512+8],0xff);
+    assert_eq!(buf[flux_block*512+8+68],0xff);
+    let img = a2kit::create_img_from_bytestream(&buf,Some("woz")).expect("could not reload");
+    let mut disk = dos3x::Disk::from_img(img).expect("could not get disk");
+    assert_eq!(disk.read_text("NEWTEXT").expect("could not read"),"HELLO FROM FLUX\n");
+}
+
+#[test]
+fn carve_without_catalog() {
+    let path = Path::new("tests").join("dos33-smallfiles.dsk");
+    let mut disk = a2kit::create_fs_from_file(&path.to_string_lossy()).expect("could not get image");
+    let tokens = disk.load("hello").expect("error").1;
+    let mut img = a2kit::create_img_from_file(&path.to_string_lossy()).expect("could not get image");
+    for s in 0..16 {
+        img.write_block(Block::DO([17,s]),&vec![0;256]).expect("could not wipe catalog");
+    }
+    let files = a2kit::fs::carve::carve(&mut img).expect("carve failed");
+    assert_eq!(files.len(),3);
+    assert_eq!(files[0].location,Block::DO([18,15]));
+    assert_eq!(files[0].content,a2kit::fs::carve::Content::Applesoft);
+    assert!(tokens.starts_with(&files[0].data));
+    assert_eq!(files[1].addr,Some(0x300));
+    assert_eq!(files[2].content,a2kit::fs::carve::Content::Text);
+    assert_eq!(String::from_utf8(files[2].data.clone()).expect("bad text"),"HELLO FROM EMULATOR\n");
+    assert!(files.iter().all(|f| f.confidence > 0.7));
+}
+
+#[test]
+fn wipe_free_and_deleted() {
+    let path = Path::new("tests").join("dos33-smallfiles.dsk");
+    let mut disk = a2kit::create_fs_from_file(&path.to_string_lossy()).expect("could not get image");
+    let free = disk.stat().expect("stat failed").free_blocks;
+    disk.delete("hello").expect("could not delete");
+    assert_eq!(disk.wipe_deleted().expect("wipe failed"),1);
+    assert_eq!(disk.wipe_deleted().expect("wipe failed"),0);
+    assert_eq!(disk.wipe_free(&[0xaa]).expect("wipe failed"),free + 4);
+    assert_eq!(disk.get_img().read_block(Block::DO([18,15])).expect("bad sector"),vec![0xaa;256]);
+    assert_eq!(disk.catalog_items("").expect("catalog failed").len(),2);
+    assert_eq!(disk.bload("thechip").expect("could not load").0,768);
+    // no trace of the deleted name remains in the catalog
+    let catalog = disk.get_img().read_block(Block::DO([17,15])).expect("bad sector");
+    assert!(!catalog.windows(5).any(|w| w==[0xc8,0xc5,0xcc,0xcc,0xcf]));
+}
+
+#[test]
+fn migrate_to_prodos() {
+    let path = Path::new("tests").join("dos33-bigfiles.do");
+    let mut src = a2kit::create_fs_from_file(&path.to_string_lossy()).expect("could not get image");
+    let img = Box::new(img::dsk_po::PO::create(280));
+    let mut prodos_disk = a2kit::fs::prodos::Disk::from_img(img).expect("bad image");
+    prodos_disk.format("MIGRATED",true,None).expect("could not format");
+    let mut dst: Box<dyn DiskFS> = Box::new(prodos_disk);
+    let report = a2kit::fs::migrate::migrate(&mut src,&mut dst).expect("migrate failed");
+    assert_eq!(report.len(),4);
+    let types: Vec<(u8,usize)> = report.iter().map(|m| (m.prodos_type,m.aux)).collect();
+    assert_eq!(types,vec![(0xfc,0x801),(0x04,0),(0x04,0),(0x06,0x4000)]);
+    let (addr,tok) = dst.load("hello").expect("could not load");
+    assert_eq!(addr,0x801);
+    assert_eq!(tok,src.load("hello").expect("could not load").1);
+    assert_eq!(dst.bload("sapling").expect("could not load"),src.bload("sapling").expect("could not load"));
+    let recs = dst.read_records("tree2",Some(127)).expect("failed to read tree2");
+    assert_eq!(recs.map.get(&2000).unwrap(),"HELLO FROM TREE 2\n");
+    assert_eq!(recs.map.get(&4000).unwrap(),"HELLO FROM TREE 2\n");
+    // holes in the random access file are kept
+    assert!(dst.get("tree2").expect("could not get").is_sparse());
+}
+
+#[test]
+fn migrate_names() {
+    use a2kit::fs::migrate::prodos_name;
+    assert_eq!(prodos_name("MY PROGRAM",&[]),"MY.PROGRAM");
+    assert_eq!(prodos_name("1ST-FILE",&[]),"A1ST.FILE");
+    assert_eq!(prodos_name("A VERY LONG DOS FILE NAME",&[]),"A.VERY.LONG.DOS");
+    assert_eq!(prodos_name("A VERY LONG DOS FILE",&["A.VERY.LONG.DOS".to_string()]),"A.VERY.LONG.DO1");
+}
+
+#[test]
+fn glob_options() {
+    use a2kit::fs::GlobOptions;
+    let path = Path::new("tests").join("dos33-smallfiles.dsk");
+    let mut disk = a2kit::create_fs_from_file(&path.to_string_lossy()).expect("could not get image");
+    disk.write_text("MY PROGRAM","HELLO").expect("could not write");
+    disk.write_text("A/B","HELLO").expect("could not write");
+    assert_eq!(disk.glob("the*",false).expect("glob failed"),vec!["THECHIP","THETEXT"]);
+    let exact = GlobOptions { case_sensitive: true, fold: false };
+    assert!(disk.glob_with("the*",exact).expect("glob failed").is_empty());
+    let fold = GlobOptions { case_sensitive: false, fold: true };
+    assert!(disk.glob("my_prógram",false).expect("glob failed").is_empty());
+    assert_eq!(disk.glob_with("my_prógram",fold).expect("glob failed"),vec!["MY PROGRAM"]);
+    // wildcards do not match the separator, even on a flat file system
+    assert!(disk.glob("A*",false).expect("glob failed").is_empty());
+    assert_eq!(disk.glob("A/*",false).expect("glob failed"),vec!["A/B"]);
+}
+
+#[test]
+fn overwrite_keeps_old_on_failure() {
+    use a2kit::fs::IfExists;
+    let path = Path::new("tests").join("dos33-smallfiles.dsk");
+    let mut disk = a2kit::create_fs_from_file(&path.to_string_lossy()).expect("could not get image");
+    disk.bsave("thechip",&[1,2,3],Some(768),None).expect_err("should not write over");
+    let big = disk.new_fimg(None,true,"thechip").and_then(|mut fimg| {
+        fimg.pack_bin(&vec![0;200000],Some(768),None)?;
+        Ok(fimg)
+    }).expect("could not pack");
+    assert!(disk.put_if_exists(&big,IfExists::Overwrite).is_err());
+    assert_eq!(disk.bload("thechip").expect("could not load").0,768);
+    let mut small = disk.new_fimg(None,true,"thechip").expect("could not create");
+    small.pack_bin(&[1,2,3],Some(0x2000),None).expect("could not pack");
+    disk.put_if_exists(&small,IfExists::Overwrite).expect("overwrite failed");
+    assert_eq!(disk.bload("thechip").expect("could not load"),(0x2000,vec![1,2,3]));
+}
+
+#[test]
+fn extended_tracks() {
+    let img = Box::new(img::dsk_do::DO::create(40,16));
+    let mut disk = dos3x::Disk::from_img(img).expect("bad image");
+    disk.init33_tracks(254,false).expect("could not init");
+    assert_eq!(disk.stat().expect("stat failed").free_blocks,38*16);
+    // 80 tracks, more than the standard bitmap can hold
+    let img = Box::new(img::dsk_do::DO::create(80,16));
+    let mut disk = dos3x::Disk::from_img(img).expect("bad image");
+    disk.init33_tracks(254,false).expect("could not init");
+    assert_eq!(disk.stat().expect("stat failed").free_blocks,78*16);
+    let dat: Vec<u8> = (0..30000).map(|i| (i % 251) as u8).collect();
+    for i in 0..8 {
+        disk.bsave(&format!("f{}",i),&dat,Some(0x800),None).expect("could not save");
+    }
+    let buf = disk.get_img().to_bytes();
+    let mut disk = a2kit::create_fs_from_bytestream(&buf,Some("do")).expect("could not open");
+    assert_eq!(disk.stat().expect("stat failed").free_blocks,78*16 - 8*119);
+    for i in 0..8 {
+        assert_eq!(disk.bload(&format!("f{}",i)).expect("could not load"),(0x800,dat.clone()));
+    }
+}
+
+#[test]
+fn variants_and_moved_catalog() {
+    let path = Path::new("tests").join("dos33-boot.do");
+    let mut disk = dos3x::Disk::from_img(a2kit::create_img_from_file(&path.to_string_lossy()).expect("bad image")).expect("bad disk");
+    assert_eq!(disk.variant().expect("no variant"),dos3x::variant::Variant::Standard);
+    let path = Path::new("tests").join("dos33-smallfiles.dsk");
+    let mut dat = std::fs::read(&path).expect("could not read");
+    let name: Vec<u8> = "PRONTO-DOS".bytes().map(|b| b | 0x80).collect();
+    dat[(16+5)*256..(16+5)*256+name.len()].copy_from_slice(&name);
+    // move the first catalog sector to track 30, it still links to the rest of the catalog on track 17
+    let first: Vec<u8> = dat[(17*16+15)*256..(17*16+16)*256].to_vec();
+    dat[(30*16+15)*256..(30*16+16)*256].copy_from_slice(&first);
+    dat[17*16*256+1] = 30;
+    let mut disk = a2kit::create_fs_from_bytestream(&dat,Some("dsk")).expect("could not open");
+    assert!(disk.stat().expect("stat failed").raw.contains("ProntoDOS"));
+    let items = disk.catalog_items("").expect("catalog failed");
+    assert_eq!(items.len(),3);
+    assert_eq!(items[0].name,"HELLO");
+}
+
+#[test]
+fn append_truncate() {
+    let img = img::dsk_do::DO::create(35, 16);
+    let mut disk = dos3x::Disk::from_img(Box::new(img)).expect("bad setup");
+    disk.init33(254,true).expect("failed to INIT");
+    let free = disk.stat().expect("bad stat").free_blocks;
+    // text ends at the first null, and the track/sector list has to grow
+    disk.write_text("LOG","HELLO\n").expect("write failed");
+    let line: Vec<u8> = "MORE TEXT\r".bytes().map(|b| b | 0x80).collect();
+    let mut expected = "HELLO\n".to_string();
+    for _i in 0..3200 {
+        disk.append("LOG",&line).expect("append failed");
+        expected += "MORE TEXT\n";
+    }
+    assert_eq!(disk.read_text("LOG").expect("bad text"),expected);
+    assert_eq!(disk.truncate("LOG",6).expect("truncate failed"),6);
+    assert_eq!(disk.read_text("LOG").expect("bad text"),"HELLO\n");
+    // binary length is in the header
+    let dat: Vec<u8> = (0..600).map(|i| (i % 251) as u8).collect();
+    disk.bsave("BIN",&dat[0..300],Some(0x300),None).expect("bsave failed");
+    assert_eq!(disk.append("BIN",&dat[300..]).expect("append failed"),600);
+    assert_eq!(disk.bload("BIN").expect("bload failed"),(0x300,dat.clone()));
+    assert_eq!(disk.truncate("BIN",10).expect("truncate failed"),10);
+    assert_eq!(disk.bload("BIN").expect("bload failed"),(0x300,dat[0..10].to_vec()));
+    disk.lock("BIN").expect("lock failed");
+    assert!(disk.append("BIN",&dat).is_err());
+    disk.unlock("BIN").expect("unlock failed");
+    disk.delete("BIN").expect("delete failed");
+    disk.delete("LOG").expect("delete failed");
+    assert_eq!(disk.stat().expect("bad stat").free_blocks,free);
+}
+
+#[test]
+fn patch() {
+    let img = img::dsk_do::DO::create(35, 16);
+    let mut disk = dos3x::Disk::from_img(Box::new(img)).expect("bad setup");
+    disk.init33(254,true).expect("failed to INIT");
+    let mut dat: Vec<u8> = (0..600).map(|i| (i % 251) as u8).collect();
+    disk.bsave("SCORES",&dat,Some(0x300),None).expect("bsave failed");
+    // offset does not count the header
+    assert_eq!(disk.patch("SCORES",250,&[1,2,3,4,5,6,7,8]).expect("patch failed"),600);
+    dat[250..258].copy_from_slice(&[1,2,3,4,5,6,7,8]);
+    assert_eq!(disk.bload("SCORES").expect("bload failed"),(0x300,dat.clone()));
+    assert_eq!(disk.patch("SCORES",599,&[9,9]).expect("patch failed"),601);
+    dat[599] = 9;
+    dat.push(9);
+    assert_eq!(disk.bload("SCORES").expect("bload failed"),(0x300,dat));
+    assert!(disk.patch("SCORES",602,&[0]).is_err());
+}
+
+#[test]
+fn banner() {
+    let img = img::dsk_do::DO::create(35, 16);
+    let mut disk = dos3x::Disk::from_img(Box::new(img)).expect("bad setup");
+    disk.init33(254,true).expect("failed to INIT");
+    let before = disk.get_img().to_bytes();
+    assert!(disk.banner(b"HELLO").is_err());
+    assert_eq!(disk.get_img().to_bytes(),before);
+    // shorten the catalog so sector 1 of the catalog track is free
+    let mut cat = disk.read_block(&(17*16+2).to_string()).expect("read failed");
+    cat[1..3].copy_from_slice(&[0,0]);
+    disk.write_block(&(17*16+2).to_string(),&cat).expect("write failed");
+    let mut vtoc = disk.read_block(&(17*16).to_string()).expect("read failed");
+    vtoc[0x7d] |= 0x02;
+    disk.write_block(&(17*16).to_string(),&vtoc).expect("write failed");
+    assert!(disk.banner(&[0xc1;257]).is_err());
+    assert_eq!(disk.banner(b"HELLO").expect("banner failed"),vec![Block::DO([17,1])]);
+    let sec = disk.read_block(&(17*16+1).to_string()).expect("read failed");
+    assert_eq!(&sec[0..6],b"HELLO\x00");
+    disk.bsave("SCORES",&[1,2,3],Some(0x300),None).expect("bsave failed");
+    assert_eq!(disk.bload("SCORES").expect("bload failed"),(0x300,vec![1,2,3]));
+}
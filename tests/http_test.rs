@@ -0,0 +1,72 @@
+// test of the HTTP server
+use std::io::{Read,Write};
+use a2kit::commands::http::HttpServer;
+
+fn setup() -> (tempfile::TempDir,HttpServer) {
+    let dir = tempfile::tempdir().expect("no temp dir");
+    std::fs::create_dir(dir.path().join("sub")).expect("mkdir failed");
+    std::fs::copy("tests/prodos-blank.po",dir.path().join("blank.po")).expect("copy failed");
+    std::fs::copy("tests/dos33-smallfiles.dsk",dir.path().join("sub").join("small.dsk")).expect("copy failed");
+    std::fs::write(dir.path().join("notes.txt"),"not an image").expect("write failed");
+    let server = HttpServer::new(dir.path()).expect("no server");
+    (dir,server)
+}
+
+#[test]
+fn list_images() {
+    let (_dir,server) = setup();
+    let resp = server.handle("GET","/images",&[]);
+    assert_eq!(resp.status,200);
+    assert_eq!(String::from_utf8(resp.body).unwrap(),"[\"blank.po\",\"sub/small.dsk\"]");
+}
+
+#[test]
+fn catalog_and_stat() {
+    let (_dir,server) = setup();
+    let resp = server.handle("GET","/catalog?image=sub%2Fsmall.dsk",&[]);
+    assert_eq!(resp.status,200);
+    assert!(json::parse(&String::from_utf8(resp.body).unwrap()).is_ok());
+    let resp = server.handle("GET","/stat?image=blank.po",&[]);
+    assert_eq!(resp.status,200);
+    assert!(String::from_utf8(resp.body).unwrap().contains("prodos"));
+}
+
+#[test]
+fn put_and_get() {
+    let (_dir,server) = setup();
+    let resp = server.handle("PUT","/file?image=blank.po&path=hello&type=atxt","10 PRINT \"HELLO\"\n".as_bytes());
+    assert_eq!(resp.status,200);
+    let resp = server.handle("GET","/file?image=blank.po&path=hello&type=atxt",&[]);
+    assert_eq!(resp.status,200);
+    assert_eq!(String::from_utf8(resp.body).unwrap().trim(),"10  PRINT \"HELLO\"");
+    let resp = server.handle("PUT","/file?image=blank.po&path=code&type=bin&addr=768",&[0xa9,0x00,0x60]);
+    assert_eq!(resp.status,200);
+    let resp = server.handle("GET","/file?image=blank.po&path=code&type=bin",&[]);
+    assert_eq!(resp.body,vec![0xa9,0x00,0x60]);
+}
+
+#[test]
+fn refused() {
+    let (_dir,server) = setup();
+    assert_eq!(server.handle("GET","/stat?image=..%2Fblank.po",&[]).status,404);
+    assert_eq!(server.handle("GET","/stat?image=notes.txt",&[]).status,400);
+    assert_eq!(server.handle("GET","/nothing",&[]).status,404);
+    assert_eq!(server.handle("DELETE","/images",&[]).status,405);
+    assert_eq!(server.handle("GET","/file?image=blank.po&path=missing&type=bin",&[]).status,400);
+}
+
+#[test]
+fn tcp_request() {
+    let (_dir,server) = setup();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind failed");
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let _ = server.serve(listener);
+    });
+    let mut stream = std::net::TcpStream::connect(addr).expect("connect failed");
+    stream.write_all(b"GET /images HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp).unwrap();
+    assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(resp.ends_with("[\"blank.po\",\"sub/small.dsk\"]"));
+}
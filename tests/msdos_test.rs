@@ -73,3 +73,217 @@ fn rename_delete_imd() {
 
     disk.compare(&Path::new("tests").join("msdos-ren-del.imd"),&ignore);
 }
+
+#[test]
+fn rename_wildcard() {
+    let kind = a2kit::img::DiskKind::D525(a2kit::img::names::IBM_SSDD_8);
+    let boot_sector = a2kit::bios::bpb::BootSector::create(&kind).expect("could not create boot sector");
+    let img = a2kit::img::dsk_img::Img::create(kind);
+    let mut disk = fat::Disk::from_img(Box::new(img),Some(boot_sector)).expect("bad setup");
+    disk.format(&String::from("NEW DISK 1"),None).expect("failed to format");
+    disk.create("dir1").expect("could not create");
+    for name in ["a.txt","b.txt","c.bas","dir1/d.txt"] {
+        disk.write_text(name,"HELLO").expect("could not write");
+    }
+    assert_eq!(a2kit::fs::wildcard_substitute("LETTER.TXT","?X*.D?C"),"LXTTER.DXC");
+    assert_eq!(disk.rename_wildcard("*.txt","*.doc").expect("rename failed"),2);
+    assert_eq!(disk.glob("**",false).expect("glob failed"),vec!["/A.DOC","/B.DOC","/C.BAS","/DIR1/D.TXT"]);
+    assert_eq!(disk.glob("*",false).expect("glob failed"),vec!["/A.DOC","/B.DOC","/C.BAS"]);
+    // both names would become X.DOC, so nothing happens
+    assert!(disk.rename_wildcard("*.doc","x.*").is_err());
+    assert_eq!(disk.rename_wildcard("dir1/*.txt","*.doc").expect("rename failed"),1);
+    assert_eq!(disk.read_text("dir1/d.doc").expect("could not read"),"HELLO");
+}
+
+#[test]
+fn relabel() {
+    let kind = a2kit::img::DiskKind::D525(a2kit::img::names::IBM_SSDD_8);
+    let boot_sector = a2kit::bios::bpb::BootSector::create(&kind).expect("could not create boot sector");
+    let img = a2kit::img::dsk_img::Img::create(kind);
+    let mut disk = fat::Disk::from_img(Box::new(img),Some(boot_sector)).expect("bad setup");
+    disk.format("",None).expect("failed to format");
+    disk.write_text("a.txt","HELLO").expect("could not write");
+    assert_eq!(disk.stat().expect("stat failed").label,"NO NAME");
+    // first time creates the root directory entry, second time changes it
+    disk.relabel("first").expect("relabel failed");
+    disk.relabel("second one").expect("relabel failed");
+    assert_eq!(disk.stat().expect("stat failed").label,"SECOND ONE");
+    assert!(disk.relabel("much too long").is_err());
+    disk.set_serial("1234-abcd").expect("could not set serial");
+    assert!(disk.set_serial("1234").is_err());
+    let boot = disk.get_img().read_sector(0,0,1).expect("could not read");
+    assert_eq!(boot[0x27..0x36],*b"\xcd\xab\x34\x12SECOND ONE ");
+    assert_eq!(disk.glob("*",false).expect("glob failed"),vec!["/A.TXT"]);
+    assert_eq!(disk.read_text("a.txt").expect("could not read"),"HELLO");
+}
+
+#[test]
+fn e5_standin() {
+    let kind = a2kit::img::DiskKind::D525(a2kit::img::names::IBM_SSDD_8);
+    let boot_sector = a2kit::bios::bpb::BootSector::create(&kind).expect("could not create boot sector");
+    let img = a2kit::img::dsk_img::Img::create(kind);
+    let cp = fat::codepage::CodePage::new(850).expect("code page not supported");
+    let mut disk = fat::Disk::from_img(Box::new(img),Some(boot_sector)).expect("bad setup").with_codepage(cp);
+    disk.format("Õlabel",None).expect("failed to format");
+    // 0xe5 is Õ in CP850, only the first byte of a directory entry uses the stand-in
+    disk.write_text("Õa.txt","FIRST").expect("could not write");
+    disk.write_text("a.Õxt","EXT").expect("could not write");
+    assert_eq!(disk.stat().expect("stat failed").label,"ÕLABEL");
+    let boot = disk.get_img().read_sector(0,0,1).expect("could not read");
+    assert_eq!(boot[0x2b..0x36],*b"\xe5LABEL     ");
+    let root = disk.get_img().read_sector(0,0,4).expect("could not read");
+    assert_eq!(root[0..11],*b"\x05LABEL     ");
+    assert_eq!(root[32..43],*b"\x05A      TXT");
+    assert_eq!(root[64..75],*b"A       \xe5XT");
+    assert_eq!(disk.glob("*",false).expect("glob failed"),vec!["/A.ÕXT","/ÕA.TXT"]);
+    assert_eq!(disk.read_text("Õa.txt").expect("could not read"),"FIRST");
+    assert_eq!(disk.read_text("a.Õxt").expect("could not read"),"EXT");
+    disk.relabel("Õther").expect("relabel failed");
+    assert_eq!(disk.stat().expect("stat failed").label,"ÕTHER");
+}
+
+#[test]
+fn put_if_exists() {
+    use a2kit::fs::IfExists;
+    let kind = a2kit::img::DiskKind::D525(a2kit::img::names::IBM_SSDD_8);
+    let boot_sector = a2kit::bios::bpb::BootSector::create(&kind).expect("could not create boot sector");
+    let img = a2kit::img::dsk_img::Img::create(kind);
+    let mut disk = fat::Disk::from_img(Box::new(img),Some(boot_sector)).expect("bad setup");
+    disk.format("",None).expect("failed to format");
+    disk.write_text("abcdefgh.txt","OLD").expect("could not write");
+    let mut fimg = disk.new_fimg(None,true,"abcdefgh.txt").expect("could not create");
+    fimg.pack_txt("NEW").expect("could not pack");
+    assert!(disk.put_if_exists(&fimg,IfExists::Error).is_err());
+    assert_eq!(disk.put_if_exists(&fimg,IfExists::Skip).expect("skip failed"),None);
+    assert_eq!(disk.read_text("abcdefgh.txt").expect("could not read"),"OLD");
+    // the base name is shortened to make room for the number
+    assert_eq!(disk.put_if_exists(&fimg,IfExists::Rename).expect("rename failed"),Some("abcdefg1.txt".to_string()));
+    assert_eq!(disk.put_if_exists(&fimg,IfExists::Overwrite).expect("overwrite failed"),Some("abcdefgh.txt".to_string()));
+    assert_eq!(disk.read_text("abcdefgh.txt").expect("could not read"),"NEW");
+    assert_eq!(disk.glob("*",false).expect("glob failed"),vec!["/ABCDEFG1.TXT","/ABCDEFGH.TXT"]);
+    assert_eq!(a2kit::fs::numbered_name("/DIR/HELLO.S",12,0),"/DIR/HELLO12.S");
+    assert_eq!(a2kit::fs::numbered_name("0:ABCDEFGH.TXT",1,1),"0:ABCDEFG1.TXT");
+}
+
+#[test]
+fn bad_sectors_imd() {
+    use a2kit::img::{DiskImageType,SectorStatus,BadSectorPolicy,FlaggedSector};
+    let path = Path::new("tests").join("msdos-ren-del.imd");
+    let mut disk = a2kit::create_fs_from_file(&path.to_string_lossy()).expect("could not get image");
+    let img = disk.get_img();
+    assert!(img.error_map().is_empty());
+    let good = img.read_sector(1,0,3).expect("could not read");
+    img.set_sector_status(1,0,3,SectorStatus::CrcError).expect("could not flag");
+    img.set_sector_status(1,0,4,SectorStatus::Deleted).expect("could not flag");
+    img.set_sector_status(1,1,2,SectorStatus::NoData).expect("could not flag");
+    let flagged = vec![
+        FlaggedSector { cyl: 1, head: 0, sec: 3, status: SectorStatus::CrcError },
+        FlaggedSector { cyl: 1, head: 0, sec: 4, status: SectorStatus::Deleted },
+        FlaggedSector { cyl: 1, head: 1, sec: 2, status: SectorStatus::NoData }
+    ];
+    assert_eq!(img.error_map(),flagged);
+    img.set_bad_sector_policy(BadSectorPolicy::Fail);
+    assert!(img.read_sector(1,0,3).is_err());
+    assert!(img.read_sector(1,0,4).is_ok());
+    assert!(img.read_sector(1,1,2).is_err());
+    img.set_bad_sector_policy(BadSectorPolicy::Keep);
+    assert_eq!(img.read_sector(1,0,3).expect("could not keep"),good);
+    assert_eq!(img.read_sector(1,1,2).expect("could not keep"),vec![0;512]);
+    img.set_bad_sector_policy(BadSectorPolicy::Fill(vec![0xde,0xad]));
+    assert_eq!(img.read_sector(1,0,3).expect("could not fill")[0..4],[0xde,0xad,0xde,0xad]);
+    img.set_bad_sector_policy(BadSectorPolicy::Keep);
+    // status survives saving and converting
+    let mut reloaded = a2kit::create_img_from_bytestream(&img.to_bytes(),Some("imd")).expect("could not reload");
+    assert_eq!(reloaded.error_map(),flagged);
+    let mut td0 = a2kit::img::convert::convert(&mut disk,DiskImageType::TD0).expect("could not convert");
+    assert_eq!(td0.error_map(),flagged);
+    let mut reloaded = a2kit::create_img_from_bytestream(&td0.to_bytes(),Some("td0")).expect("could not reload");
+    assert_eq!(reloaded.error_map(),flagged);
+    assert_eq!(reloaded.read_sector(1,0,3).expect("could not keep"),good);
+    // policy can be given when loading
+    let opts = a2kit::LoadOptions { bad_sectors: BadSectorPolicy::Fail, ..Default::default() };
+    let mut strict = a2kit::create_img_from_bytestream_with(&td0.to_bytes(),Some("td0"),&opts).expect("could not reload");
+    assert!(strict.read_sector(1,0,3).is_err());
+}
+
+/// Make a 360K disk, then patch it the way MSX-DOS and other Z80 machines leave it
+fn msx_disk(patch: impl Fn(&mut Vec<u8>)) -> Vec<u8> {
+    let kind = a2kit::img::DiskKind::D525(a2kit::img::names::IBM_DSDD_9);
+    let boot_sector = a2kit::bios::bpb::BootSector::create(&kind).expect("could not create boot sector");
+    let img = a2kit::img::dsk_img::Img::create(kind);
+    let mut disk = fat::Disk::from_img(Box::new(img),Some(boot_sector)).expect("bad setup");
+    disk.format(&String::from("MSX"),None).expect("failed to format");
+    disk.write_text("HELLO.TXT","HELLO FROM MSX\n").expect("dimg error");
+    let mut dat = disk.get_img().to_bytes();
+    patch(&mut dat);
+    dat
+}
+
+#[test]
+fn msx_no_signature() {
+    let dat = msx_disk(|dat| {
+        dat[510] = 0;
+        dat[511] = 0;
+    });
+    let mut disk = a2kit::create_fs_from_bytestream(&dat,Some("dsk")).expect("could not identify");
+    assert_eq!(disk.read_text("HELLO.TXT").expect("could not read"),"HELLO FROM MSX\n");
+}
+
+#[test]
+fn msx_fat_id_only() {
+    // no boot sector at all, single sided 80 track FAT ID, which has the same layout as the 360K PC disk
+    let dat = msx_disk(|dat| {
+        dat[0..512].copy_from_slice(&[0;512]);
+        dat[512] = 0xf8;
+        dat[1024+512] = 0xf8;
+    });
+    let mut disk = a2kit::create_fs_from_bytestream(&dat,Some("dsk")).expect("could not identify");
+    assert_eq!(disk.read_text("HELLO.TXT").expect("could not read"),"HELLO FROM MSX\n");
+    disk.write_text("BYE.TXT","BYE\n").expect("could not write");
+    assert_eq!(disk.read_text("BYE.TXT").expect("could not read"),"BYE\n");
+    // unknown FAT ID is not accepted
+    let dat = msx_disk(|dat| {
+        dat[0..512].copy_from_slice(&[0;512]);
+        dat[512] = 0xe0;
+        dat[1024+512] = 0xe0;
+    });
+    assert!(a2kit::create_fs_from_bytestream(&dat,Some("dsk")).is_err());
+}
+
+#[test]
+fn append_truncate() {
+    let kind = a2kit::img::DiskKind::D525(a2kit::img::names::IBM_SSDD_8);
+    let boot_sector = a2kit::bios::bpb::BootSector::create(&kind).expect("could not create boot sector");
+    let img = a2kit::img::dsk_img::Img::create(kind);
+    let mut disk = fat::Disk::from_img(Box::new(img),Some(boot_sector)).expect("bad setup");
+    disk.format(&String::from("NEW DISK 1"),None).expect("failed to format");
+    let free = disk.stat().expect("bad stat").free_blocks;
+    let dat: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+    let mut fimg = disk.new_fimg(None,true,"LOG.TXT").expect("bad fimg");
+    fimg.pack_raw(&dat[0..100]).expect("bad pack");
+    disk.put(&fimg).expect("put failed");
+    assert_eq!(disk.append("LOG.TXT",&dat[100..]).expect("append failed"),5000);
+    assert_eq!(disk.get("LOG.TXT").expect("bad file").unpack_raw(true).expect("bad data"),dat);
+    disk.truncate("LOG.TXT",1000).expect("truncate failed");
+    assert_eq!(disk.get("LOG.TXT").expect("bad file").unpack_raw(true).expect("bad data"),dat[0..1000]);
+    assert!(disk.truncate("LOG.TXT",1001).is_err());
+    // empty file has no clusters
+    disk.truncate("LOG.TXT",0).expect("truncate failed");
+    disk.append("LOG.TXT",&dat[0..10]).expect("append failed");
+    assert_eq!(disk.get("LOG.TXT").expect("bad file").unpack_raw(true).expect("bad data"),dat[0..10]);
+    disk.delete("LOG.TXT").expect("delete failed");
+    assert_eq!(disk.stat().expect("bad stat").free_blocks,free);
+}
+
+#[test]
+fn malformed_imd() {
+    // byte 46 is the sector size code of the first track
+    let good = std::fs::read(&Path::new("tests").join("msdos-ren-del.imd")).expect("failed to read test image file");
+    for shift in [7,0xff] {
+        let mut dat = good.clone();
+        dat[46] = shift;
+        assert!(a2kit::create_img_from_bytestream(&dat,Some("imd")).is_err());
+    }
+    assert!(a2kit::create_img_from_bytestream(&good[0..50].to_vec(),Some("imd")).is_err());
+    assert!(a2kit::create_img_from_bytestream(&good[0..42].to_vec(),Some("imd")).is_err());
+}
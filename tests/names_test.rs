@@ -0,0 +1,40 @@
+// test of name conversion for each file system
+use std::path::Path;
+use a2kit::fs::names::{valid_name,is_valid,from_host};
+
+#[test]
+fn prodos_names() {
+    assert_eq!(valid_name("prodos","my program",&[]),"MY.PROGRAM");
+    assert_eq!(valid_name("prodos","1st-file",&[]),"A1ST.FILE");
+    assert_eq!(valid_name("prodos","a very long name indeed",&[]),"A.VERY.LONG.NAM");
+    let taken = vec!["A.VERY.LONG.NAM".to_string(),"a.very.long.na1".to_string()];
+    assert_eq!(valid_name("prodos","a very long name indeed",&taken),"A.VERY.LONG.NA2");
+    assert!(is_valid("prodos","hello"));
+    assert!(!is_valid("prodos","1bad"));
+}
+
+#[test]
+fn flat_names() {
+    assert_eq!(valid_name("a2 dos","hello, world",&[]),"HELLO. WORLD");
+    assert_eq!(valid_name("a2 dos","",&[]),"UNTITLED");
+    assert_eq!(valid_name("a2 pascal","my prog$.text",&[]),"MY.PROG..TEXT");
+    assert_eq!(valid_name("a2 pascal","hello",&["HELLO".to_string()]),"HELLO1");
+    assert_eq!(valid_name("a2 rdos","x".repeat(30).as_str(),&[]).len(),24);
+}
+
+#[test]
+fn eight_three_names() {
+    assert_eq!(valid_name("cpm","read me.text",&[]),"READ_ME.TEX");
+    assert_eq!(valid_name("fat","archive.tar.gz",&[]),"ARCHIVE_.GZ");
+    assert_eq!(valid_name("fat","longfilename.c",&["LONGFILE.C".to_string()]),"LONGFIL1.C");
+    assert!(is_valid("cpm","hello.com"));
+    assert!(!is_valid("cpm","hello.comx"));
+}
+
+#[test]
+fn host_names() {
+    let host = Path::new("src").join("hello world.bas");
+    assert_eq!(from_host("prodos",&host,&[]),"HELLO.WORLD");
+    assert_eq!(from_host("a2 dos",&host,&[]),"HELLO WORLD");
+    assert_eq!(from_host("fat",&host,&[]),"HELLO_WO.BAS");
+}
@@ -0,0 +1,127 @@
+// test of GS/OS load file parsing and relocation
+use a2kit::omf::{LoadFile,kind};
+
+/// Build a version 2 segment with the given body, which should end with an END record
+fn segment(segnum: u16,kind: u16,name: &str,body: &[u8],length: u32,resspc: u32) -> Vec<u8> {
+    let dispname: u16 = 0x2c;
+    let dispdata = dispname + 10 + 1 + name.len() as u16;
+    let bytecnt = dispdata as u32 + body.len() as u32;
+    let mut ans = Vec::new();
+    ans.extend_from_slice(&u32::to_le_bytes(bytecnt));
+    ans.extend_from_slice(&u32::to_le_bytes(resspc));
+    ans.extend_from_slice(&u32::to_le_bytes(length));
+    ans.extend_from_slice(&[0,0,4,2]); // undefined, LABLEN, NUMLEN, VERSION
+    ans.extend_from_slice(&u32::to_le_bytes(0x10000));
+    ans.extend_from_slice(&u16::to_le_bytes(kind));
+    ans.extend_from_slice(&[0,0]);
+    ans.extend_from_slice(&[0;8]); // ORG, ALIGN
+    ans.extend_from_slice(&[0,0]); // NUMSEX
+    ans.extend_from_slice(&u16::to_le_bytes(segnum));
+    ans.extend_from_slice(&[0;4]); // ENTRY
+    ans.extend_from_slice(&u16::to_le_bytes(dispname));
+    ans.extend_from_slice(&u16::to_le_bytes(dispdata));
+    ans.extend_from_slice(b"          ");
+    ans.push(name.len() as u8);
+    ans.extend_from_slice(name.as_bytes());
+    ans.extend_from_slice(body);
+    assert_eq!(ans.len(),bytecnt as usize);
+    ans
+}
+
+/// Code segment that refers to itself and to the data segment, followed by a data segment
+fn test_file() -> Vec<u8> {
+    let code = [
+        0xad,0x10,0x00,      // LDA $0010 - cRELOC
+        0x22,0x00,0x00,0x00, // JSL DATA - cINTERSEG
+        0xad,0x08,0x00,      // LDA $0008 - SUPER RELOC2
+        0x6b                 // RTL
+    ];
+    let mut body = vec![0xf2];
+    body.extend_from_slice(&u32::to_le_bytes(code.len() as u32));
+    body.extend_from_slice(&code);
+    body.extend_from_slice(&[0xf1,5,0,0,0]); // DS 5
+    body.extend_from_slice(&[0xf5,2,0,1,0,0x10,0]); // cRELOC
+    body.extend_from_slice(&[0xf6,3,0,4,0,2,0,0]); // cINTERSEG
+    body.extend_from_slice(&[0xf7,3,0,0,0,0,0,8]); // SUPER RELOC2
+    body.push(0);
+    let mut ans = segment(1,kind::CODE,"MAIN",&body,16,0);
+    let mut body = vec![0xf2,5,0,0,0];
+    body.extend_from_slice(b"HELLO");
+    body.push(0);
+    ans.append(&mut segment(2,kind::DATA | kind::DYNAMIC,"STRINGS",&body,8,3));
+    // pad to block boundary as some linkers do
+    ans.resize(512,0);
+    ans
+}
+
+#[test]
+fn parse_segments() {
+    let load_file = LoadFile::from_bytes(&test_file()).expect("could not parse");
+    assert_eq!(load_file.segments.len(),2);
+    let code = load_file.segment(1).expect("missing segment");
+    assert_eq!(code.name,"MAIN");
+    assert_eq!(code.type_name(),"code");
+    assert_eq!(code.data.len(),16);
+    assert_eq!(code.relocs.len(),3);
+    let data = load_file.segment(2).expect("missing segment");
+    assert_eq!(data.type_name(),"data");
+    assert_eq!(data.attributes(),vec!["dynamic"]);
+    assert_eq!(data.data,b"HELLO\0\0\0".to_vec());
+    let listing = json::parse(&load_file.to_json(None)).expect("bad JSON");
+    assert_eq!(listing["segments"][0]["relocations"],2);
+    assert_eq!(listing["segments"][0]["interseg"],1);
+    assert_eq!(listing["segments"][1]["name"],"STRINGS");
+}
+
+#[test]
+fn relocate_segment() {
+    let load_file = LoadFile::from_bytes(&test_file()).expect("could not parse");
+    let addrs = load_file.layout(1,0x2000).expect("could not lay out");
+    assert_eq!(addrs[&1],0x2000);
+    assert_eq!(addrs[&2],0x2010);
+    let actual = load_file.relocate(1,&addrs).expect("could not relocate");
+    let expected = [0xad,0x10,0x20,0x22,0x10,0x20,0x00,0xad,0x08,0x20,0x6b,0,0,0,0,0];
+    assert_eq!(actual,expected.to_vec());
+    // data segment first
+    let addrs = load_file.layout(2,0x12fff0).expect("could not lay out");
+    assert_eq!(addrs[&1],0x130000);
+    // nothing can be loaded past the end of memory
+    assert!(load_file.layout(1,0xfffff8).is_err());
+}
+
+#[test]
+fn oversized_segment() {
+    // DS of 4 GB
+    let body = [0xf1,0xff,0xff,0xff,0xff,0];
+    assert!(LoadFile::from_bytes(&segment(1,kind::CODE,"BIG",&body,16,0)).is_err());
+    // header length of 4 GB
+    let body = [0xf1,1,0,0,0,0];
+    assert!(LoadFile::from_bytes(&segment(1,kind::CODE,"BIG",&body,0xffffffff,0)).is_err());
+}
+
+#[test]
+fn not_omf() {
+    assert!(LoadFile::from_bytes(&[0x20,0x58,0xfc,0x60]).is_err());
+}
+
+#[test]
+fn omf_cli() -> Result<(),Box<dyn std::error::Error>> {
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    let output = cmd.arg("omf").write_stdin(test_file()).assert().success().get_output().stdout.clone();
+    let listing = json::parse(&String::from_utf8(output)?)?;
+    assert_eq!(listing["segments"][0]["name"],"MAIN");
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    cmd.arg("omf").arg("-s").arg("2").write_stdin(test_file())
+        .assert().success().stdout(b"HELLO\0\0\0".to_vec());
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    let output = cmd.arg("omf").arg("-s").arg("1").arg("-o").arg("768").write_stdin(test_file())
+        .assert().success().get_output().stdout.clone();
+    assert_eq!(output[0..7],[0xad,0x10,0x03,0x22,0x10,0x03,0x00]);
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    let hex_output = cmd.arg("omf").arg("-s").arg("1").arg("-o").arg("$300").write_stdin(test_file())
+        .assert().success().get_output().stdout.clone();
+    assert_eq!(hex_output,output);
+    let mut cmd = assert_cmd::Command::cargo_bin("a2kit")?;
+    cmd.arg("omf").arg("-s").arg("3").write_stdin(test_file()).assert().failure();
+    Ok(())
+}
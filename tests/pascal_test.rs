@@ -110,3 +110,34 @@ fn out_of_space() {
         }
     }
 }
+
+#[test]
+fn dates() {
+    let mut src = a2kit::create_fs_from_file("tests/pascal-smallfiles.do").expect("could not interpret image");
+    let mut fimg = src.get("test2.text").expect("could not get file");
+    let img = a2kit::img::dsk_do::DO::create(35,16);
+    let mut disk = pascal::Disk::from_img(Box::new(img)).expect("bad setup");
+    let vol_time = chrono::NaiveDate::from_ymd_opt(2024,1,2).unwrap().and_hms_opt(0,0,0);
+    disk.format(&String::from("DATED"),0,vol_time).expect("failed to format");
+    disk.write_text("hello.text",PROG1).expect("error");
+    // the date in the file image is kept
+    disk.put_at("test2.text",&mut fimg).expect("could not put file");
+    let tree = json::parse(&disk.tree(true,None).expect("no tree")).expect("bad json");
+    assert_eq!(tree["label"]["time_modified"],"2024/01/02 00:00");
+    assert_eq!(tree["files"]["TEST2.TEXT"]["meta"]["time_modified"],"1981/03/17 00:00");
+    let rows = disk.catalog_sorted("/",a2kit::fs::CatalogOrder::Date).expect("no catalog");
+    assert!(rows[0].ends_with("TEST2.TEXT"));
+    assert!(rows[1].ends_with("HELLO.TEXT"));
+}
+
+#[test]
+fn damaged_names() {
+    // a file name length out of range should not take down the catalog
+    let img = std::fs::read(&Path::new("tests").join("pascal-smallfiles.do")).expect("failed to read test image file");
+    let mut img = a2kit::create_img_from_bytestream(&img,Some("do")).expect("could not get image");
+    let mut buf = img.read_block(Block::PO(2)).expect("bad read");
+    buf[32] = 0xff;
+    img.write_block(Block::PO(2),&buf).expect("bad write");
+    let mut disk = pascal::Disk::from_img(img).expect("could not get disk");
+    disk.catalog_to_stdout("/").expect("catalog failed");
+}
@@ -276,3 +276,228 @@ fn read_big_woz1() {
     let ignore = disk.standardize(2);
     disk.compare(&Path::new("tests").join("prodos-bigfiles.dsk"),&ignore);    
 }
+
+#[test]
+fn placement() {
+    let img = a2kit::img::dsk_po::PO::create(280);
+    let mut disk = prodos::Disk::from_img(Box::new(img)).expect("bad setup");
+    disk.format(&String::from("NEW.DISK"),true,None).expect("failed to format");
+    let dat: Vec<u8> = (0..BLOCK_SIZE).map(|i| (i % 256) as u8).collect();
+    disk.allocate_from(Some(200)).expect("placement failed");
+    disk.bsave("f1",&dat,Some(0x800),None).expect("error");
+    disk.bsave("f2",&dat,Some(0x800),None).expect("error");
+    disk.allocate_from(None).expect("placement failed");
+    disk.bsave("f3",&dat,Some(0x800),None).expect("error");
+    // seedling files, so the data block is the key block
+    assert_eq!(disk.read_block("200").expect("bad block"),dat);
+    assert_eq!(disk.read_block("201").expect("bad block"),dat);
+    assert_ne!(disk.read_block("202").expect("bad block"),dat);
+    assert_eq!(disk.bload("f3").expect("bad file").1,dat);
+    assert!(disk.allocate_from(Some(280)).is_err());
+}
+
+#[test]
+fn repair_bitmap() {
+    let img = a2kit::img::dsk_po::PO::create(280);
+    let mut disk = prodos::Disk::from_img(Box::new(img)).expect("bad setup");
+    disk.format(&String::from("NEW.DISK"),true,None).expect("failed to format");
+    let dat: Vec<u8> = vec![0x55;BLOCK_SIZE*3];
+    disk.bsave("f1",&dat,Some(0x800),None).expect("error");
+    disk.create("dir").expect("error");
+    disk.bsave("dir/f2",&dat,Some(0x800),None).expect("error");
+    let free = disk.stat().expect("bad stat").free_blocks;
+    assert_eq!(disk.repair_bitmap().expect("repair failed"),0);
+    // block 7 is the first data block, free it, and claim block 270 which nothing uses
+    let mut bytes = disk.get_img().to_bytes();
+    bytes[6*BLOCK_SIZE] |= 0x01;
+    bytes[6*BLOCK_SIZE + 270/8] &= !(0x80 >> (270%8));
+    let mut disk = a2kit::create_fs_from_bytestream(&bytes,Some("po")).expect("could not interpret image");
+    assert_eq!(disk.stat().expect("bad stat").free_blocks,free);
+    assert_eq!(disk.repair_bitmap().expect("repair failed"),2);
+    assert_eq!(disk.stat().expect("bad stat").free_blocks,free);
+    assert_eq!(disk.repair_bitmap().expect("repair failed"),0);
+    assert_eq!(disk.bload("dir/f2").expect("bad file").1,dat);
+}
+
+#[test]
+fn carve_without_directory() {
+    let img = a2kit::img::dsk_po::PO::create(280);
+    let mut disk = prodos::Disk::from_img(Box::new(img)).expect("bad setup");
+    disk.format(&String::from("NEW.DISK"),true,None).expect("failed to format");
+    let dat: Vec<u8> = (0..BLOCK_SIZE*3).map(|i| (i % 251) as u8).collect();
+    disk.bsave("f1",&dat,Some(0x800),None).expect("error");
+    disk.write_text("t1",&"THE QUICK BROWN FOX JUMPS OVER THE LAZY DOG\n".repeat(40)).expect("error");
+    let mut img = a2kit::create_img_from_bytestream(&disk.get_img().to_bytes(),Some("po")).expect("could not get image");
+    for b in 2..6 {
+        img.write_block(Block::PO(b),&vec![0;BLOCK_SIZE]).expect("could not wipe directory");
+    }
+    let files = a2kit::fs::carve::carve(&mut img).expect("carve failed");
+    assert_eq!(files.len(),2);
+    assert_eq!(files[0].evidence,a2kit::fs::carve::Evidence::IndexBlock);
+    assert_eq!(files[0].data,dat);
+    assert_eq!(files[1].content,a2kit::fs::carve::Content::Text);
+    assert!(String::from_utf8(files[1].data.clone()).expect("bad text").starts_with("THE QUICK BROWN FOX"));
+}
+
+#[test]
+fn exists_files_only() {
+    let img = a2kit::img::dsk_po::PO::create(280);
+    let mut disk = prodos::Disk::from_img(Box::new(img)).expect("bad setup");
+    disk.format(&String::from("NEW.DISK"),true,None).expect("failed to format");
+    disk.create(&String::from("INNER")).expect("could not create directory");
+    disk.bsave("INNER/F1",&[1,2,3],Some(0x800),None).expect("error");
+    assert!(disk.exists("INNER/F1").expect("could not check"));
+    assert!(disk.exists("/NEW.DISK/INNER/F1").expect("could not check"));
+    assert!(!disk.exists("INNER").expect("could not check"));
+    assert!(!disk.exists("INNER/").expect("could not check"));
+    assert!(!disk.exists("INNER/F2").expect("could not check"));
+    assert!(!disk.exists("NOWHERE/F1").expect("could not check"));
+}
+
+#[test]
+fn append_truncate() {
+    let img = a2kit::img::dsk_po::PO::create(1600);
+    let mut disk = prodos::Disk::from_img(Box::new(img)).expect("bad setup");
+    disk.format(&String::from("NEW.DISK"),false,None).expect("failed to format");
+    let free = disk.stat().expect("bad stat").free_blocks;
+    let dat: Vec<u8> = (0..BLOCK_SIZE*300).map(|i| (i % 251) as u8).collect();
+    let mut fimg = disk.new_fimg(None,true,"log").expect("bad fimg");
+    fimg.pack_raw(&dat[0..100]).expect("bad pack");
+    disk.put(&fimg).expect("put failed");
+    // seedling to sapling, then sapling to tree
+    assert_eq!(disk.append("log",&dat[100..1000]).expect("append failed"),1000);
+    assert_eq!(disk.append("log",&dat[1000..]).expect("append failed"),dat.len());
+    assert_eq!(disk.get("log").expect("bad file").unpack_raw(true).expect("bad data"),dat);
+    assert_eq!(disk.repair_bitmap().expect("repair failed"),0);
+    disk.truncate("log",700).expect("truncate failed");
+    assert_eq!(disk.get("log").expect("bad file").unpack_raw(true).expect("bad data"),dat[0..700]);
+    assert_eq!(disk.repair_bitmap().expect("repair failed"),0);
+    assert!(disk.truncate("log",701).is_err());
+    disk.truncate("log",0).expect("truncate failed");
+    disk.delete("log").expect("delete failed");
+    assert_eq!(disk.stat().expect("bad stat").free_blocks,free);
+}
+
+#[test]
+fn patch() {
+    let img = a2kit::img::dsk_po::PO::create(280);
+    let mut disk = prodos::Disk::from_img(Box::new(img)).expect("bad setup");
+    disk.format(&String::from("NEW.DISK"),true,None).expect("failed to format");
+    let mut dat: Vec<u8> = (0..BLOCK_SIZE*3).map(|i| (i % 251) as u8).collect();
+    disk.bsave("scores",&dat,Some(0x800),None).expect("error");
+    let before = disk.get_img().to_bytes();
+    // spans two blocks, only those change
+    assert_eq!(disk.patch("scores",510,&[1,2,3,4]).expect("patch failed"),dat.len());
+    dat[510..514].copy_from_slice(&[1,2,3,4]);
+    assert_eq!(disk.bload("scores").expect("bad file").1,dat);
+    let after = disk.get_img().to_bytes();
+    let changed: Vec<usize> = (0..280).filter(|b| before[b*BLOCK_SIZE..(b+1)*BLOCK_SIZE] != after[b*BLOCK_SIZE..(b+1)*BLOCK_SIZE]).collect();
+    // two data blocks, plus the directory if the time stamp moved
+    assert!(changed.len()>=2 && changed.len()<=3);
+    // running past the end grows the file
+    assert_eq!(disk.patch("scores",dat.len()-1,&[5,6]).expect("patch failed"),dat.len()+1);
+    assert!(disk.patch("scores",dat.len()+2,&[7]).is_err());
+}
+
+#[test]
+fn banner() {
+    let img = a2kit::img::dsk_po::PO::create(280);
+    let mut disk = prodos::Disk::from_img(Box::new(img)).expect("bad setup");
+    disk.format(&String::from("NEW.DISK"),true,None).expect("failed to format");
+    let msg: Vec<u8> = (0..600).map(|i| b'A' + (i % 26) as u8).collect();
+    let blocks = disk.banner(&msg).expect("banner failed");
+    assert_eq!(blocks,vec![Block::PO(7),Block::PO(8)]);
+    let mut expected = msg.clone();
+    expected.resize(1024,0);
+    let mut actual = disk.read_block("7").expect("read failed");
+    actual.append(&mut disk.read_block("8").expect("read failed"));
+    assert_eq!(actual,expected);
+    // the blocks stay free
+    assert_eq!(disk.repair_bitmap().expect("repair failed"),0);
+    assert!(disk.banner(&vec![0;BLOCK_SIZE*280]).is_err());
+}
+
+#[test]
+fn borrowed_reads() {
+    // reading into a buffer gives the same bytes as reading a new block, for both orderings
+    for path in ["tests/prodos-bigfiles.dsk","tests/prodos-smallfiles.do"] {
+        let mut img = a2kit::create_img_from_file(path).expect("could not get image");
+        for block in [0,2,7,100] {
+            let expected = img.read_block(Block::PO(block)).expect("bad read");
+            let mut buf = vec![0;BLOCK_SIZE];
+            img.read_block_into(Block::PO(block),&mut buf).expect("bad read");
+            assert_eq!(buf,expected);
+            let mut short = vec![0;300];
+            img.read_block_into(Block::PO(block),&mut short).expect("bad read");
+            assert_eq!(short,expected[0..300]);
+        }
+    }
+    // file image chunks are borrowed in order, and a limited sequence stops partway into a chunk
+    let img = a2kit::img::dsk_po::PO::create(280);
+    let mut disk = prodos::Disk::from_img(Box::new(img)).expect("bad setup");
+    disk.format(&String::from("NEW.DISK"),true,None).expect("failed to format");
+    let dat: Vec<u8> = (0..BLOCK_SIZE*3).map(|i| (i % 251) as u8).collect();
+    disk.bsave("scores",&dat,Some(0x800),None).expect("error");
+    let fimg = disk.get("scores").expect("could not get file");
+    let chunks = fimg.ordered_chunks();
+    assert_eq!(chunks.iter().map(|(idx,_)| *idx).collect::<Vec<usize>>(),vec![0,1,2]);
+    assert_eq!(chunks[1].1,&dat[BLOCK_SIZE..BLOCK_SIZE*2]);
+    assert_eq!(fimg.sequence_limited(700),dat[0..700]);
+    assert_eq!(fimg.sequence_limited(5000),fimg.sequence());
+    assert_eq!(fimg.end(),3);
+    assert_eq!(json::parse(&fimg.to_json(None)).expect("bad JSON"),fimg.to_json_value());
+}
+
+#[test]
+fn bad_input_errors() {
+    // malformed file images are errors
+    let img = a2kit::img::dsk_po::PO::create(280);
+    let mut disk = prodos::Disk::from_img(Box::new(img)).expect("bad setup");
+    disk.format(&String::from("NEW.DISK"),true,None).expect("failed to format");
+    disk.bsave("scores",&[1,2,3],Some(0x800),None).expect("error");
+    let mut fimg = disk.get("scores").expect("could not get file");
+    let json_str = fimg.to_json(None);
+    assert!(a2kit::fs::FileImage::from_json(&json_str.replace("\"prodos\"","\"bogus\"")).is_err());
+    assert!(a2kit::fs::FileImage::from_json(&json_str.replace("\"2.1.0\"","\"2.1\"")).is_err());
+    fimg.file_system = "bogus".to_string();
+    assert!(fimg.unpack_raw(false).is_err());
+    assert_eq!(fimg.get_load_address(),0);
+    // an entry with a storage type we do not know is passed over
+    let img = disk.get_img();
+    let mut buf = img.read_block(Block::PO(2)).expect("bad read");
+    buf[43] = 0x50 | (buf[43] & 0x0f);
+    img.write_block(Block::PO(2),&buf).expect("bad write");
+    disk.catalog_to_stdout("/").expect("catalog failed");
+    assert!(disk.get("scores").is_err());
+}
+
+#[test]
+fn date_epochs() {
+    // ProDOS technote 28: year field 40..99 is 1940..1999, 0..39 is 2000..2039
+    let img = a2kit::img::dsk_po::PO::create(280);
+    let mut disk = prodos::Disk::from_img(Box::new(img)).expect("bad setup");
+    disk.format(&String::from("NEW.DISK"),true,None).expect("failed to format");
+    let cases = [
+        ("Y1940",1940,1940),
+        ("Y1979",1979,1979),
+        ("Y1999",1999,1999),
+        ("Y2000",2000,2000),
+        ("Y2024",2024,2024),
+        ("Y2039",2039,2039),
+        ("Y2050",2050,2039),
+        ("Y1930",1930,1940)
+    ];
+    for (name,year,_) in cases {
+        let time = chrono::NaiveDate::from_ymd_opt(year,3,4).unwrap().and_hms_opt(5,6,0).unwrap();
+        let mut fimg = disk.new_fimg(None,false,name).expect("could not create file image");
+        fimg.pack_txt("HELLO\n").expect("could not pack");
+        disk.put(&fimg).expect("could not put");
+        disk.set_time(name,time).expect("could not set time");
+    }
+    let items = disk.catalog_items("").expect("catalog failed");
+    for (name,_,expected) in cases {
+        let item = items.iter().find(|item| item.name==name).expect("file missing");
+        let time = chrono::NaiveDate::from_ymd_opt(expected,3,4).unwrap().and_hms_opt(5,6,0).unwrap();
+        assert_eq!(item.modified,Some(time),"{}",name);
+    }
+}
@@ -0,0 +1,66 @@
+// test of provenance sidecars
+use a2kit::img::provenance;
+use std::process::Command;
+use assert_cmd::prelude::*;
+
+type STDRESULT = Result<(),Box<dyn std::error::Error>>;
+
+#[test]
+fn sha256_vectors() {
+    assert_eq!(provenance::sha256(b""),"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    assert_eq!(provenance::sha256(b"abc"),"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    assert_eq!(provenance::sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+}
+
+#[test]
+fn record_and_verify() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("disk.do").to_string_lossy().to_string();
+    std::fs::copy("tests/prodos-smallfiles.do",&path)?;
+    let original = std::fs::read(&path)?;
+    let opts = a2kit::SaveOptions { provenance: Some(provenance::Operation::new("test")), ..Default::default() };
+    let mut disk = a2kit::create_fs_from_file(&path)?;
+    disk.bsave("new",&[1,2,3],Some(768),None)?;
+    a2kit::save_img_with(&mut disk,&path,&opts)?;
+    disk.delete("new")?;
+    a2kit::save_img_with(&mut disk,&path,&opts)?;
+    let sidecar = provenance::load(&path)?.expect("no sidecar");
+    assert_eq!(sidecar["source"]["sha256"],provenance::sha256(&original));
+    assert_eq!(sidecar["operations"].len(),2);
+    assert_eq!(sidecar["operations"][1]["operation"]["name"],"test");
+    assert_eq!(sidecar["sha256"],provenance::sha256(&std::fs::read(&path)?));
+    assert!(provenance::verify(&path)?.is_empty());
+    // change the image without recording it
+    std::fs::write(&path,&original)?;
+    assert_eq!(provenance::verify(&path)?,vec!["image was changed after the last recorded operation"]);
+    Ok(())
+}
+
+#[test]
+fn provenance_cli() -> STDRESULT {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("new.do");
+    Command::cargo_bin("a2kit")?
+        .arg("mkdsk").arg("-o").arg("dos33").arg("-v").arg("254").arg("-t").arg("do").arg("-d").arg(&path)
+        .arg("--provenance")
+        .assert()
+        .success();
+    let output = Command::cargo_bin("a2kit")?
+        .arg("provenance").arg("-d").arg(&path)
+        .output()?;
+    assert!(output.status.success());
+    let sidecar = json::parse(&String::from_utf8(output.stdout)?)?;
+    assert!(sidecar["source"]["sha256"].is_null());
+    let op = &sidecar["operations"][0]["operation"];
+    assert_eq!(op["name"],"mkdsk");
+    assert_eq!(op["args"]["os"],"dos33");
+    assert_eq!(op["args"]["volume"],"254");
+    assert!(op["args"]["provenance"].is_null());
+    std::fs::write(&path,"corrupted")?;
+    Command::cargo_bin("a2kit")?
+        .arg("provenance").arg("-d").arg(&path)
+        .assert()
+        .failure();
+    Ok(())
+}
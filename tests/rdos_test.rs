@@ -0,0 +1,67 @@
+// test of rdos file system module
+use a2kit::fs::DiskFS;
+
+/// Build an RDOS disk as a flat image with sectors in file system order, as in D13 and PO images
+fn build_disk(sectors: usize) -> Vec<u8> {
+    let offset = |sec: usize| sec*256;
+    let mut dat = vec![0;35*sectors*256];
+    let mut catalog: Vec<u8> = Vec::new();
+    let mut add_entry = |name: &str,typ: u8,secs: u8,addr: u16,len: u16,start: u16| {
+        let mut padded: Vec<u8> = format!("{:24}",name).bytes().map(|b| b | 0x80).collect();
+        catalog.append(&mut padded);
+        catalog.append(&mut vec![typ,secs]);
+        catalog.extend_from_slice(&u16::to_le_bytes(addr));
+        catalog.extend_from_slice(&u16::to_le_bytes(len));
+        catalog.extend_from_slice(&u16::to_le_bytes(start));
+    };
+    add_entry("RDOS 2.1 COPYRIGHT 1981",b'B' | 0x80,(2*sectors) as u8,0,0,0);
+    add_entry("HELLO",b'T' | 0x80,1,0,12,40);
+    add_entry("DELETED",b'B' | 0x80,1,0x800,3,41);
+    add_entry("GAME CODE",b'B' | 0x80,2,0x800,300,42);
+    // mark the third entry deleted
+    catalog[64] = 0x80;
+    for (i,chunk) in catalog.chunks(256).enumerate() {
+        let beg = offset(sectors + i);
+        dat[beg..beg+chunk.len()].copy_from_slice(chunk);
+    }
+    let text: Vec<u8> = "HELLO WORLD\r".bytes().map(|b| b | 0x80).collect();
+    dat[offset(40)..offset(40)+12].copy_from_slice(&text);
+    for i in 0..300 {
+        let sec = 42 + i/256;
+        dat[offset(sec) + i%256] = (i % 256) as u8;
+    }
+    dat
+}
+
+fn check_disk(disk: &mut Box<dyn DiskFS>) {
+    let stat = disk.stat().expect("stat failed");
+    assert_eq!(stat.fs_name,"a2 rdos");
+    assert_eq!(stat.label,"RDOS 2.1 COPYRIGHT 1981");
+    let items = disk.catalog_items("").expect("catalog failed");
+    let names: Vec<String> = items.iter().map(|x| x.name.clone()).collect();
+    assert_eq!(names,vec!["HELLO","GAME CODE"]);
+    assert_eq!(items[1].typ,"B");
+    assert_eq!(disk.read_text("hello").expect("could not read"),"HELLO WORLD\n");
+    let (addr,dat) = disk.bload("GAME CODE").expect("could not load");
+    assert_eq!(addr,0x800);
+    assert_eq!(dat.len(),300);
+    assert_eq!(dat[299],43);
+    assert!(disk.get("DELETED").is_err());
+    assert!(disk.delete("HELLO").is_err());
+}
+
+#[test]
+fn read_13_sector() {
+    let dat = build_disk(13);
+    let mut disk = a2kit::create_fs_from_bytestream(&dat,Some("d13")).expect("could not open");
+    check_disk(&mut disk);
+    assert_eq!(disk.stat().expect("stat failed").free_blocks,35*13 - 26 - 3);
+}
+
+#[test]
+fn read_16_sector() {
+    let dat = build_disk(16);
+    let mut disk = a2kit::create_fs_from_bytestream(&dat,Some("po")).expect("could not open");
+    check_disk(&mut disk);
+    assert_eq!(disk.stat().expect("stat failed").free_blocks,35*16 - 32 - 3);
+}
@@ -0,0 +1,79 @@
+// test of the JSON-RPC interface
+use a2kit::commands::rpc::RpcServer;
+
+fn call(server: &mut RpcServer, id: usize, method: &str, params: json::JsonValue) -> json::JsonValue {
+    let req = json::object! { "jsonrpc": "2.0", "id": id, "method": method, "params": params };
+    let resp = json::parse(&server.handle(&json::stringify(req))).expect("bad response");
+    assert_eq!(resp["id"],id);
+    resp
+}
+
+#[test]
+fn session() {
+    let dir = tempfile::tempdir().expect("no temp dir");
+    let img = dir.path().join("test.po").to_str().unwrap().to_string();
+    let mut server = RpcServer::new();
+    let resp = call(&mut server, 1, "mkdsk", json::object! { "image": img.clone(), "type": "po", "os": "prodos", "volume": "rpc" });
+    assert!(resp["error"].is_null());
+    let resp = call(&mut server, 2, "put", json::object! { "image": img.clone(), "path": "hello", "type": "atxt", "text": "10 PRINT \"HI\"" });
+    assert!(resp["error"].is_null());
+    let resp = call(&mut server, 3, "put", json::object! { "image": img.clone(), "path": "code", "type": "bin", "hex": "A90060", "addr": 768 });
+    assert!(resp["error"].is_null());
+    let resp = call(&mut server, 4, "get", json::object! { "image": img.clone(), "path": "code", "type": "bin" });
+    assert_eq!(resp["result"]["hex"],"A90060");
+    let resp = call(&mut server, 5, "catalog", json::object! { "image": img.clone() });
+    let lines: Vec<String> = resp["result"].members().map(|l| l.to_string()).collect();
+    assert!(lines.iter().any(|l| l.contains("HELLO")));
+    assert!(lines.iter().any(|l| l.contains("CODE")));
+    let resp = call(&mut server, 6, "rename", json::object! { "image": img.clone(), "path": "code", "name": "data" });
+    assert!(resp["error"].is_null());
+    // changes are on the host file
+    let mut disk = a2kit::create_fs_from_file(&img).expect("no image");
+    assert_eq!(disk.bload("data").expect("no data"),(768,vec![0xa9,0x00,0x60]));
+    let resp = call(&mut server, 7, "stat", json::object! { "image": img.clone() });
+    assert_eq!(resp["result"]["fs_name"],"prodos");
+    call(&mut server, 8, "exit", json::JsonValue::Null);
+    assert!(server.is_done());
+}
+
+#[test]
+fn errors() {
+    let mut server = RpcServer::new();
+    let resp = json::parse(&server.handle("{not json")).unwrap();
+    assert_eq!(resp["error"]["code"],-32700);
+    let resp = call(&mut server, 1, "format", json::JsonValue::Null);
+    assert_eq!(resp["error"]["code"],-32601);
+    let resp = call(&mut server, 2, "stat", json::object! {});
+    assert_eq!(resp["error"]["code"],-32602);
+    let resp = call(&mut server, 3, "stat", json::object! { "image": "no/such/image.po" });
+    assert_eq!(resp["error"]["code"],-32000);
+    // notifications get no response
+    assert_eq!(server.handle(r#"{"jsonrpc":"2.0","method":"exit"}"#),"");
+}
+
+#[test]
+fn rollback() {
+    let dir = tempfile::tempdir().expect("no temp dir");
+    let img = dir.path().join("test.po").to_str().unwrap().to_string();
+    let mut server = RpcServer::new();
+    call(&mut server, 1, "mkdsk", json::object! { "image": img.clone(), "type": "po", "os": "prodos", "volume": "rpc" });
+    let resp = call(&mut server, 2, "rollback", json::object! { "image": img.clone() });
+    assert_eq!(resp["error"]["code"],-32000);
+    call(&mut server, 3, "put", json::object! { "image": img.clone(), "path": "keep", "type": "txt", "text": "KEEP\n" });
+    let resp = call(&mut server, 4, "snapshot", json::object! { "image": img.clone() });
+    assert!(resp["error"].is_null());
+    let before = std::fs::read(&img).expect("no image");
+    // a file too big for the disk fails part way and is rolled back
+    let resp = call(&mut server, 5, "put", json::object! { "image": img.clone(), "path": "big", "type": "txt", "text": "BIG\n".repeat(40000) });
+    assert_eq!(resp["error"]["code"],-32000);
+    let resp = call(&mut server, 6, "put", json::object! { "image": img.clone(), "path": "small", "type": "txt", "text": "SMALL\n" });
+    assert!(resp["error"].is_null());
+    assert_ne!(std::fs::read(&img).expect("no image"),before);
+    let resp = call(&mut server, 7, "rollback", json::object! { "image": img.clone() });
+    assert!(resp["error"].is_null());
+    assert_eq!(std::fs::read(&img).expect("no image"),before);
+    let mut disk = a2kit::create_fs_from_file(&img).expect("no image");
+    assert_eq!(disk.read_text("keep").expect("no file"),"KEEP\n");
+    assert!(disk.get("small").is_err());
+    assert!(disk.get("big").is_err());
+}
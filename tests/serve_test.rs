@@ -0,0 +1,130 @@
+// test of the virtual drive block server
+use std::io::{Read,Write,Cursor};
+use a2kit::commands::serve::BlockServer;
+use a2kit::fs::Block;
+
+/// Stream that reads from a fixed request buffer and collects the replies
+struct Loopback {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>
+}
+
+impl Read for Loopback {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for Loopback {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.output.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn xor(dat: &[u8]) -> u8 {
+    dat.iter().fold(0,|acc,b| acc ^ b)
+}
+
+fn request(cmd: u8, block: u16) -> Vec<u8> {
+    let b = block.to_le_bytes();
+    let mut ans = vec![0xc5,cmd,b[0],b[1]];
+    ans.push(xor(&ans));
+    ans
+}
+
+fn temp_image(dir: &tempfile::TempDir) -> String {
+    let path = dir.path().join("blank.po");
+    std::fs::copy("tests/prodos-blank.po",&path).expect("copy failed");
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn read_block() {
+    let dir = tempfile::tempdir().expect("no temp dir");
+    let path = temp_image(&dir);
+    let mut server = BlockServer::new(std::slice::from_ref(&path)).expect("no server");
+    let mut stream = Loopback { input: Cursor::new(request(0x03,2)), output: Vec::new() };
+    server.run(&mut stream).expect("server failed");
+    let expected = a2kit::create_img_from_file(&path).unwrap().read_block(Block::PO(2)).unwrap();
+    let out = stream.output;
+    assert_eq!(out.len(),9 + 512 + 1);
+    assert_eq!(out[0..4],[0xc5,0x03,2,0]);
+    assert_eq!(out[8],xor(&out[0..8]));
+    assert_eq!(out[9..521],expected[..]);
+    assert_eq!(out[521],xor(&expected));
+}
+
+#[test]
+fn write_block() {
+    let dir = tempfile::tempdir().expect("no temp dir");
+    let path = temp_image(&dir);
+    let mut server = BlockServer::new(std::slice::from_ref(&path)).expect("no server");
+    let dat: Vec<u8> = (0..512).map(|i| (i % 251) as u8).collect();
+    let mut req = request(0x02,100);
+    req.extend_from_slice(&dat);
+    req.push(xor(&dat));
+    // a bad request in front is skipped, as is the junk byte
+    let mut input = request(0x03,5);
+    input[4] ^= 1;
+    input.push(0x00);
+    input.append(&mut req);
+    let mut stream = Loopback { input: Cursor::new(input), output: Vec::new() };
+    server.run(&mut stream).expect("server failed");
+    assert_eq!(stream.output,vec![0xc5,0x02,100,0,xor(&[0xc5,0x02,100,0])]);
+    let actual = a2kit::create_img_from_file(&path).unwrap().read_block(Block::PO(100)).unwrap();
+    assert_eq!(actual,dat);
+}
+
+#[test]
+fn no_second_drive() {
+    let dir = tempfile::tempdir().expect("no temp dir");
+    let path = temp_image(&dir);
+    let mut server = BlockServer::new(&[path]).expect("no server");
+    let mut stream = Loopback { input: Cursor::new(request(0x05,0)), output: Vec::new() };
+    server.run(&mut stream).expect("server failed");
+    assert!(stream.output.is_empty());
+}
+
+#[test]
+fn write_with_save_options() {
+    let dir = tempfile::tempdir().expect("no temp dir");
+    let path = temp_image(&dir);
+    let mut padded = std::fs::read(&path).unwrap();
+    let blocks_len = padded.len();
+    padded.extend_from_slice(&[0xaa;10]);
+    std::fs::write(&path,&padded).unwrap();
+    let mut server = BlockServer::new(std::slice::from_ref(&path)).expect("no server");
+    server.set_save_options(a2kit::SaveOptions { repair_padding: true, ..a2kit::SaveOptions::default() });
+    let dat = [0x11;512];
+    let mut req = request(0x02,7);
+    req.extend_from_slice(&dat);
+    req.push(xor(&dat));
+    let mut stream = Loopback { input: Cursor::new(req), output: Vec::new() };
+    server.run(&mut stream).expect("server failed");
+    assert_eq!(std::fs::read(&path).unwrap().len(),blocks_len);
+}
+
+#[test]
+fn failures_end_connection() {
+    let dir = tempfile::tempdir().expect("no temp dir");
+    let path = temp_image(&dir);
+    let mut server = BlockServer::new(std::slice::from_ref(&path)).expect("no server");
+    // write data with a bad checksum
+    let dat = [0x22;512];
+    let mut req = request(0x02,8);
+    req.extend_from_slice(&dat);
+    req.push(xor(&dat) ^ 1);
+    req.append(&mut request(0x03,2));
+    let mut stream = Loopback { input: Cursor::new(req), output: Vec::new() };
+    assert!(server.run(&mut stream).is_err());
+    assert!(stream.output.is_empty());
+    let actual = a2kit::create_img_from_file(&path).unwrap().read_block(Block::PO(8)).unwrap();
+    assert_eq!(actual,vec![0;512]);
+    // block beyond the end of the image
+    let mut stream = Loopback { input: Cursor::new(request(0x03,0xffff)), output: Vec::new() };
+    assert!(server.run(&mut stream).is_err());
+    assert!(stream.output.is_empty());
+}
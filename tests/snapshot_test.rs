@@ -0,0 +1,88 @@
+// test of in-memory snapshot and rollback
+type STDRESULT = Result<(),Box<dyn std::error::Error>>;
+
+#[test]
+fn round_trip() -> STDRESULT {
+    for (path,new) in [
+        ("tests/dos33-ren-del.do","new"),
+        ("tests/dos32-ren-del.woz","new"),
+        ("tests/prodos-bigfiles.woz","new"),
+        ("tests/prodos-ren-del.dsk","new"),
+        ("tests/pascal-smallfiles.do","new.text"),
+        ("tests/cpm-ren-del.dsk","new.txt"),
+        ("tests/msdos-ren-del.imd","new.txt"),
+        ("tests/msdos-ren-del.img","new.txt")
+    ] {
+        let mut disk = a2kit::create_fs_from_file(path)?;
+        let original = disk.get_img().to_bytes();
+        let snap = disk.snapshot();
+        disk.write_text(new,"SOMETHING NEW\n")?;
+        disk.delete(new)?;
+        disk.write_text(new,"SOMETHING ELSE\n")?;
+        assert_ne!(disk.get_img().to_bytes(),original,"{} did not change",path);
+        disk.rollback(&snap)?;
+        assert_eq!(disk.get_img().to_bytes(),original,"{} was not restored",path);
+        assert!(disk.get(new).is_err());
+        // snapshot can be used again
+        disk.write_text(new,"AGAIN\n")?;
+        disk.rollback(&snap)?;
+        assert_eq!(disk.get_img().to_bytes(),original,"{} was not restored twice",path);
+    }
+    Ok(())
+}
+
+#[test]
+fn buffers_discarded() -> STDRESULT {
+    let mut disk = a2kit::create_fs_from_file("tests/prodos-smallfiles.do")?;
+    let free = disk.stat()?.free_blocks;
+    let snap = disk.snapshot();
+    // fails part way, leaving blocks allocated in the bitmap buffer
+    assert!(disk.write_text("big",&"BIG\n".repeat(40000)).is_err());
+    assert!(disk.stat()?.free_blocks < free);
+    disk.rollback(&snap)?;
+    assert_eq!(disk.stat()?.free_blocks,free);
+    disk.write_text("small","SMALL\n")?;
+    assert_eq!(disk.read_text("small")?,"SMALL\n");
+    Ok(())
+}
+
+#[test]
+fn type_mismatch() -> STDRESULT {
+    let mut disk = a2kit::create_fs_from_file("tests/dos33-ren-del.do")?;
+    let snap = disk.snapshot();
+    let mut other = a2kit::create_fs_from_file("tests/dos33-bigfiles.woz")?;
+    assert!(other.rollback(&snap).is_err());
+    Ok(())
+}
+
+#[test]
+fn nested() -> STDRESULT {
+    let mut disk = a2kit::create_fs_from_file("tests/prodos-smallfiles.do")?;
+    let original = disk.get_img().to_bytes();
+    let outer = disk.snapshot();
+    disk.write_text("first","FIRST\n")?;
+    let first = disk.get_img().to_bytes();
+    let inner = disk.snapshot();
+    disk.write_text("second","SECOND\n")?;
+    disk.rollback(&inner)?;
+    assert_eq!(disk.get_img().to_bytes(),first);
+    disk.rollback(&outer)?;
+    assert_eq!(disk.get_img().to_bytes(),original);
+    // rolling back to the outer snapshot closes the inner one
+    assert!(disk.rollback(&inner).is_err());
+    Ok(())
+}
+
+#[test]
+fn journal_released() -> STDRESULT {
+    let mut disk = a2kit::create_fs_from_file("tests/dos33-ren-del.do")?;
+    {
+        let _snap = disk.snapshot();
+        disk.write_text("new","NEW\n")?;
+        assert!(disk.get_img().journal().is_some_and(|j| !j.is_empty()));
+    }
+    // nothing is kept once the snapshot is dropped
+    disk.write_text("other","OTHER\n")?;
+    assert!(disk.get_img().journal().is_some_and(|j| j.is_empty()));
+    Ok(())
+}